@@ -0,0 +1,77 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+#extension GL_GOOGLE_include_directive : require
+
+layout(location = 0) out vec4 out_frag_color;
+
+#include <neptune/view.glsl>
+
+layout(set = 0, binding = 1, rgba8) uniform readonly image2D color_images[];
+layout(set = 0, binding = 2) uniform texture2D textures[];
+layout(set = 0, binding = 3) uniform sampler samplers[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint view_index;
+    uint color_image_binding;
+    uint depth_texture_binding;
+    uint effect_texture_binding;
+    uint effect_depth_texture_binding;
+    uint sampler_binding;
+    uint composite;
+} push_constants;
+
+// Matches UpsampleComposite in reduced_resolution.rs
+const uint COMPOSITE_TRANSMITTANCE = 0;
+
+// Relative depth difference where a texel's weight is halved
+const float DEPTH_TOLERANCE = 0.02;
+
+// Distance along the view direction, the far plane of an infinite projection just becomes very large
+float linear_depth(float depth) {
+    vec4 view_position = views[push_constants.view_index].inverse_projection_matrix * vec4(0.0, 0.0, depth, 1.0);
+    return abs(view_position.z) / max(abs(view_position.w), 1e-6);
+}
+
+void main() {
+    uint color_index = push_constants.color_image_binding & 0xFFFF;
+    uint depth_index = push_constants.depth_texture_binding & 0xFFFF;
+    uint effect_index = push_constants.effect_texture_binding & 0xFFFF;
+    uint effect_depth_index = push_constants.effect_depth_texture_binding & 0xFFFF;
+    uint sampler_index = push_constants.sampler_binding & 0xFFFF;
+
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    ivec2 full_size = imageSize(color_images[color_index]);
+    ivec2 effect_size = textureSize(sampler2D(textures[effect_index], samplers[sampler_index]), 0);
+
+    float pixel_depth = linear_depth(texelFetch(sampler2D(textures[depth_index], samplers[sampler_index]), pixel, 0).r);
+
+    // Position of this pixel's center in effect texels, relative to the 4 nearest texel centers
+    vec2 effect_position = (vec2(pixel) + 0.5) * vec2(effect_size) / vec2(full_size) - 0.5;
+    ivec2 base_texel = ivec2(floor(effect_position));
+    vec2 bilinear = effect_position - vec2(base_texel);
+
+    vec4 effect_sum = vec4(0.0);
+    float weight_sum = 0.0;
+    for (int i = 0; i < 4; i++) {
+        ivec2 offset = ivec2(i & 1, i >> 1);
+        ivec2 texel = clamp(base_texel + offset, ivec2(0), effect_size - ivec2(1));
+
+        float texel_depth = linear_depth(texelFetch(sampler2D(textures[effect_depth_index], samplers[sampler_index]), texel, 0).r);
+        float depth_difference = abs(texel_depth - pixel_depth) / max(pixel_depth, 1e-6);
+
+        vec2 bilinear_weights = mix(1.0 - bilinear, bilinear, vec2(offset));
+        // The small bias keeps the sum valid when every texel is across an edge
+        float weight = bilinear_weights.x * bilinear_weights.y / (1.0 + depth_difference / DEPTH_TOLERANCE) + 1e-5;
+
+        effect_sum += texelFetch(sampler2D(textures[effect_index], samplers[sampler_index]), texel, 0) * weight;
+        weight_sum += weight;
+    }
+    vec4 effect = effect_sum / weight_sum;
+
+    vec4 color = imageLoad(color_images[color_index], pixel);
+    if (push_constants.composite == COMPOSITE_TRANSMITTANCE) {
+        color.rgb = color.rgb * effect.a + effect.rgb;
+    }
+    out_frag_color = color;
+}
@@ -0,0 +1,16 @@
+#version 460
+
+layout(push_constant) uniform PushConstants
+{
+    uint field; // 0 if this frame shades the pixels where x + y is even
+    float near_depth;
+} push_constants;
+
+// Fills the pixels this frame skips with near plane depth, so the scene fails the depth test there
+void main() {
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    if (uint((pixel.x + pixel.y) & 1) == push_constants.field) {
+        discard;
+    }
+    gl_FragDepth = push_constants.near_depth;
+}
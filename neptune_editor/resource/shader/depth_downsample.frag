@@ -0,0 +1,24 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout(location = 0) out float out_depth;
+
+layout(set = 0, binding = 2) uniform texture2D depth_textures[];
+layout(set = 0, binding = 3) uniform sampler samplers[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint depth_texture_binding;
+    uint sampler_binding;
+    float scale;
+} push_constants;
+
+// Point sampled instead of min/max so the reduced depth is always a real surface the upsample can compare against
+void main() {
+    uint depth_index = push_constants.depth_texture_binding & 0xFFFF;
+    uint sampler_index = push_constants.sampler_binding & 0xFFFF;
+
+    ivec2 depth_size = textureSize(sampler2D(depth_textures[depth_index], samplers[sampler_index]), 0);
+    ivec2 pixel = clamp(ivec2(gl_FragCoord.xy / push_constants.scale), ivec2(0), depth_size - ivec2(1));
+    out_depth = texelFetch(sampler2D(depth_textures[depth_index], samplers[sampler_index]), pixel, 0).r;
+}
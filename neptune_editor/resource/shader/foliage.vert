@@ -0,0 +1,97 @@
+#version 450
+#extension GL_EXT_nonuniform_qualifier : require
+#extension GL_GOOGLE_include_directive : require
+
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec3 normal;
+layout (location = 2) in vec4 tangent;
+layout (location = 3) in vec4 uv1_uv2;
+layout (location = 4) in vec4 color;
+
+// Same outputs as mesh_static.vert so mesh.frag can be used
+layout (location = 0) out mat3 tangent_space_matrix;
+layout (location = 3) out vec2 frag_uv1;
+layout (location = 4) out vec2 frag_uv2;
+layout (location = 5) out vec4 frag_color;
+layout (location = 6) out vec4 clip_position;
+layout (location = 7) out vec4 previous_clip_position;
+layout (location = 8) flat out uint frag_draw_index;
+
+#include <neptune/view.glsl>
+
+// Matches FoliageInstance in foliage.rs
+struct FoliageInstance {
+	vec4 position_scale;
+	vec4 rotation_phase; // x: sin of the yaw, y: cos of the yaw, z: wind phase
+};
+
+layout(std430, set = 0, binding = 0) readonly buffer InstanceBuffer {
+	FoliageInstance instances[];
+} instance_buffers[];
+
+// Matches FoliageWindData in foliage.rs
+layout(std140, set = 0, binding = 0) readonly buffer WindBuffer {
+	vec4 wind; // xy: direction, z: angular frequency
+	vec4 time; // x: time, y: last frame's time
+} wind_buffers[];
+
+// The same layout as mesh.frag with the foliage values after it
+layout(push_constant) uniform PushConstants
+{
+	uint view_index;
+	uint draw_buffer_index; // A single draw, the instances all share it
+	uint instance_buffer_index;
+	uint image_sampler;
+	uint albedo_texture;
+	uint lightmap_sampler;
+	uint lightmap_texture;
+	uint material_buffer_index;
+	uint probe_buffer_index;
+	uint probe_sampler;
+	uint probe_atlas;
+	uint wind_buffer_index;
+	float mesh_height;
+} push_constants;
+
+vec3 rotate_yaw(vec3 v, vec2 sin_cos) {
+	return vec3(sin_cos.y * v.x + sin_cos.x * v.z, v.y, -sin_cos.x * v.x + sin_cos.y * v.z);
+}
+
+// Leans with the wind and sways around that lean, the base of the mesh stays planted
+vec3 sway(vec3 local_position, float scale, float phase, float time) {
+	uint wind_index = push_constants.wind_buffer_index & 0xFFFF;
+	vec4 wind = wind_buffers[wind_index].wind;
+	float weight = clamp(local_position.y / push_constants.mesh_height, 0.0, 1.0);
+	weight *= weight;
+	float amount = 0.5 + 0.5 * sin(time * wind.z + phase);
+	return vec3(wind.x, 0.0, wind.y) * (amount * weight * scale);
+}
+
+void main() {
+	uint view_index = push_constants.view_index & 0xFFFF;
+	uint instance_buffer_index = push_constants.instance_buffer_index & 0xFFFF;
+	uint wind_index = push_constants.wind_buffer_index & 0xFFFF;
+
+	FoliageInstance instance = instance_buffers[instance_buffer_index].instances[gl_InstanceIndex];
+	vec3 origin = instance.position_scale.xyz;
+	float scale = instance.position_scale.w;
+	vec2 sin_cos = instance.rotation_phase.xy;
+	float phase = instance.rotation_phase.z;
+
+	vec3 world_position = origin + rotate_yaw(position * scale, sin_cos);
+	vec4 time = wind_buffers[wind_index].time;
+
+	gl_Position = views[view_index].view_projection_matrix * vec4(world_position + sway(position, scale, phase, time.x), 1.0);
+	clip_position = gl_Position;
+	previous_clip_position = views[view_index].previous_view_projection_matrix * vec4(world_position + sway(position, scale, phase, time.y), 1.0);
+
+	vec3 world_normal = normalize(rotate_yaw(normal, sin_cos));
+	vec3 world_tangent = normalize(rotate_yaw(tangent.xyz, sin_cos));
+	vec3 world_bitangent = cross(world_normal, world_tangent) * tangent.w;
+	tangent_space_matrix = mat3(world_tangent, world_bitangent, world_normal);
+
+	frag_uv1 = uv1_uv2.xy;
+	frag_uv2 = uv1_uv2.zw;
+	frag_color = color;
+	frag_draw_index = 0;
+}
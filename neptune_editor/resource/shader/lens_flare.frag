@@ -0,0 +1,20 @@
+#version 460
+
+layout(location = 0) in vec2 frag_corner;
+layout(location = 1) in vec4 frag_color;
+layout(location = 2) flat in uint frag_shape;
+
+layout(location = 0) out vec4 out_frag_color;
+
+void main() {
+    float distance = length(frag_corner);
+    float strength;
+    if (frag_shape == 0) {
+        strength = pow(max(1.0 - distance, 0.0), 3.0);
+    } else if (frag_shape == 1) {
+        strength = smoothstep(1.0, 0.8, distance) * 0.6;
+    } else {
+        strength = max(1.0 - abs(distance - 0.85) / 0.1, 0.0);
+    }
+    out_frag_color = vec4(frag_color.rgb * strength, 1.0);
+}
@@ -0,0 +1,63 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout (location = 0) out vec2 frag_corner;
+layout (location = 1) out vec4 frag_color;
+layout (location = 2) flat out uint frag_shape;
+
+// Matches LensEffectData in lens_effects.rs
+layout(std140, set = 0, binding = 0) readonly buffer LensEffectBuffer {
+    vec4 sun_screen;  // xy: sun uv, z: aspect ratio, w: fade as the sun leaves the screen
+    vec4 sun_color;   // w: flare intensity
+    vec4 shafts;      // x: intensity, y: decay, z: length
+} lens_settings[];
+
+layout(std430, set = 0, binding = 0) readonly buffer VisibilityBuffer {
+    float visibility;
+} visibility_buffers[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint lens_index;
+    uint visibility_buffer_index;
+} push_constants;
+
+const vec2 CORNERS[6] = vec2[](
+    vec2(-0.5, -0.5), vec2(0.5, -0.5), vec2(0.5, 0.5),
+    vec2(-0.5, -0.5), vec2(0.5, 0.5), vec2(-0.5, 0.5)
+);
+
+// One instance per element, placed along the line from the sun through the center of the screen.
+// 0 is at the sun and 1 is mirrored across the center
+const int ELEMENT_COUNT = 7;
+const float ELEMENT_POSITIONS[ELEMENT_COUNT] = float[](0.0, 0.3, 0.5, 0.65, 0.85, 1.0, 1.25);
+// In view heights
+const float ELEMENT_SIZES[ELEMENT_COUNT] = float[](0.5, 0.05, 0.1, 0.04, 0.15, 0.3, 0.08);
+const vec3 ELEMENT_COLORS[ELEMENT_COUNT] = vec3[](
+    vec3(0.6, 0.55, 0.5),
+    vec3(0.3, 0.5, 0.3),
+    vec3(0.4, 0.3, 0.6),
+    vec3(0.5, 0.4, 0.2),
+    vec3(0.2, 0.3, 0.5),
+    vec3(0.25, 0.2, 0.15),
+    vec3(0.4, 0.2, 0.3)
+);
+// 0: glow, 1: disk, 2: ring
+const uint ELEMENT_SHAPES[ELEMENT_COUNT] = uint[](0, 1, 1, 1, 2, 2, 1);
+
+void main() {
+    vec4 sun_screen = lens_settings[push_constants.lens_index].sun_screen;
+    vec4 sun_color = lens_settings[push_constants.lens_index].sun_color;
+    float visibility = visibility_buffers[push_constants.visibility_buffer_index].visibility;
+
+    uint element = gl_InstanceIndex;
+    vec2 sun_position = sun_screen.xy * 2.0 - 1.0;
+    vec2 center = mix(sun_position, -sun_position, ELEMENT_POSITIONS[element]);
+    vec2 corner = CORNERS[gl_VertexIndex];
+
+    frag_corner = corner * 2.0;
+    frag_color = vec4(ELEMENT_COLORS[element] * sun_color.rgb * (sun_color.w * visibility), 1.0);
+    frag_shape = ELEMENT_SHAPES[element];
+    // Ndc is two units tall
+    gl_Position = vec4(center + corner * ELEMENT_SIZES[element] * 2.0 * vec2(1.0 / sun_screen.z, 1.0), 0.0, 1.0);
+}
@@ -0,0 +1,47 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_frag_color;
+
+// Matches LensEffectData in lens_effects.rs
+layout(std140, set = 0, binding = 0) readonly buffer LensEffectBuffer {
+    vec4 sun_screen;  // xy: sun uv, z: aspect ratio, w: fade as the sun leaves the screen
+    vec4 sun_color;   // w: flare intensity
+    vec4 shafts;      // x: intensity, y: decay, z: length
+} lens_settings[];
+
+layout(set = 0, binding = 2) uniform texture2D mask_textures[];
+layout(set = 0, binding = 3) uniform sampler samplers[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint lens_index;
+    uint mask_texture_binding;
+    uint sampler_binding;
+} push_constants;
+
+const int SAMPLE_COUNT = 48;
+
+// Radial blur of the mask towards the sun, added onto the color
+void main() {
+    uint mask_index = push_constants.mask_texture_binding & 0xFFFF;
+    uint sampler_index = push_constants.sampler_binding & 0xFFFF;
+
+    vec4 sun_screen = lens_settings[push_constants.lens_index].sun_screen;
+    vec4 shafts = lens_settings[push_constants.lens_index].shafts;
+
+    vec2 uv_step = (sun_screen.xy - in_uv) * (shafts.z / float(SAMPLE_COUNT));
+    vec2 uv = in_uv;
+    float weight = 1.0;
+    float shaft = 0.0;
+    for (int i = 0; i < SAMPLE_COUNT; i++) {
+        uv += uv_step;
+        shaft += texture(sampler2D(mask_textures[mask_index], samplers[sampler_index]), uv).r * weight;
+        weight *= shafts.y;
+    }
+    shaft *= shafts.x * sun_screen.w / float(SAMPLE_COUNT);
+
+    out_frag_color = vec4(lens_settings[push_constants.lens_index].sun_color.rgb * shaft, 1.0);
+}
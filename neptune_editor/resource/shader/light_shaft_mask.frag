@@ -0,0 +1,50 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+#extension GL_GOOGLE_include_directive : require
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_frag_color;
+
+#include <neptune/view.glsl>
+
+// Matches LensEffectData in lens_effects.rs
+layout(std140, set = 0, binding = 0) readonly buffer LensEffectBuffer {
+    vec4 sun_screen;  // xy: sun uv, z: aspect ratio, w: fade as the sun leaves the screen
+    vec4 sun_color;   // w: flare intensity
+    vec4 shafts;      // x: intensity, y: decay, z: length
+} lens_settings[];
+
+layout(set = 0, binding = 2) uniform texture2D depth_textures[];
+layout(set = 0, binding = 3) uniform sampler samplers[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint camera_index;
+    uint lens_index;
+    uint depth_texture_binding;
+    uint sampler_binding;
+} push_constants;
+
+// In view heights, how far the glow around the sun reaches
+const float GLOW_RADIUS = 0.3;
+
+// Bright where the sky is visible near the sun, the shaft pass smears this towards the sun
+void main() {
+    uint depth_index = push_constants.depth_texture_binding & 0xFFFF;
+    uint sampler_index = push_constants.sampler_binding & 0xFFFF;
+
+    // The mask is smaller than the depth image, so read the depth texel under this pixel
+    ivec2 depth_size = textureSize(sampler2D(depth_textures[depth_index], samplers[sampler_index]), 0);
+    float depth = texelFetch(sampler2D(depth_textures[depth_index], samplers[sampler_index]), ivec2(in_uv * vec2(depth_size)), 0).r;
+    float far_depth = views[push_constants.camera_index].reversed_depth != 0 ? 0.0 : 1.0;
+    if (depth != far_depth) {
+        out_frag_color = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    vec4 sun_screen = lens_settings[push_constants.lens_index].sun_screen;
+    vec2 to_sun = (sun_screen.xy - in_uv) * vec2(sun_screen.z, 1.0);
+    float glow = max(1.0 - length(to_sun) / GLOW_RADIUS, 0.0);
+    out_frag_color = vec4(vec3(glow * glow), 1.0);
+}
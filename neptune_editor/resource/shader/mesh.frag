@@ -1,43 +1,167 @@
 #version 460
 #extension GL_EXT_nonuniform_qualifier : require
+#extension GL_GOOGLE_include_directive : require
 
 layout (location = 0) in mat3 tangent_space_matrix;
 layout (location = 3) in vec2 frag_uv1;
 layout (location = 4) in vec2 frag_uv2;
 layout (location = 5) in vec4 frag_color;
+layout (location = 6) in vec4 clip_position;
+layout (location = 7) in vec4 previous_clip_position;
+layout (location = 8) flat in uint frag_draw_index;
 
 layout(location = 0) out vec4 out_frag_color;
+layout(location = 1) out vec4 out_velocity;
 
-layout(set = 0, binding = 2) uniform texture2D sampled_images[];
-struct SampledImageBinding {
-    uint binding_index;
+#include <neptune/bindless.glsl>
+
+struct MaterialData {
+    vec4 base_color;
+    vec4 emissive_color;
+    vec4 metallic_roughness;
 };
-uint get_image_index(SampledImageBinding binding) {
-    return binding.binding_index & 0xFFFF;
-}
 
-layout(set = 0, binding = 3) uniform sampler samplers[];
-struct SamplerBinding {
-    uint binding_index;
+// Every material lives in one palette buffer, indexed by the material's slot
+layout(std140, set = 0, binding = 0) readonly buffer MaterialBuffer {
+    MaterialData materials[];
+} material_buffers[];
+
+#include <neptune/view.glsl>
+#include <neptune/draw.glsl>
+#include <neptune/brdf.glsl>
+
+struct ReflectionProbe {
+    vec4 position_blend; // xyz: capture position, w: blend distance
+    vec4 extents;        // xyz: half size of the box, w: 1 if reflections are box projected
+    vec4 tile;           // xy: uv offset in the atlas, z: uv size, w: 1 once captured
 };
-uint get_sampler_index(SamplerBinding binding) {
-    return binding.binding_index & 0xFFFF;
-}
 
-vec4 sample_image(SampledImageBinding image_binding, SamplerBinding sampler_binding, vec2 uv) {
-    uint image_index = get_image_index(image_binding);
-    uint sampler_index = get_sampler_index(sampler_binding);
-    return texture(sampler2D(sampled_images[image_index], samplers[sampler_index]), uv);
-}
+// Matches ProbeData in reflection_probes.rs
+layout(std140, set = 0, binding = 0) readonly buffer ReflectionProbeBuffer {
+    uint probe_count;
+    float intensity;
+    float half_texel; // Of a tile, keeps filtering from bleeding into the neighbouring tiles
+    ReflectionProbe probes[];
+} probe_buffers[];
 
 layout(push_constant) uniform PushConstants
 {
-    uint view_projection_matrix_index;
+    uint view_index;
+    uint draw_buffer_index;
     uint model_matrices_index;
     SamplerBinding image_sampler;
     SampledImageBinding albedo_texture;
+    SamplerBinding lightmap_sampler;
+    SampledImageBinding lightmap_texture;
+    uint material_buffer_index;
+    uint probe_buffer_index;
+    SamplerBinding probe_sampler;
+    SampledImageBinding probe_atlas;
 } push_constants;
 
+DrawData get_draw() {
+    return draw_buffers[push_constants.draw_buffer_index].draws[frag_draw_index];
+}
+
+MaterialData get_material() {
+    return material_buffers[push_constants.material_buffer_index].materials[get_draw().material_index];
+}
+
+// Same mapping as reflection_probe_pack.comp
+vec2 octahedral_encode(vec3 n) {
+    n /= abs(n.x) + abs(n.y) + abs(n.z);
+    vec2 f = n.xz;
+    if (n.y < 0.0) {
+        f = (1.0 - abs(f.yx)) * vec2(f.x >= 0.0 ? 1.0 : -1.0, f.y >= 0.0 ? 1.0 : -1.0);
+    }
+    return f * 0.5 + 0.5;
+}
+
+vec3 get_world_position() {
+    vec4 position = views[push_constants.view_index].inverse_view_projection_matrix * vec4(clip_position.xyz / clip_position.w, 1.0);
+    return position.xyz / position.w;
+}
+
+// Blends every probe whose box contains the point, coverage is how much of the result came from probes
+vec3 sample_reflection_probes(vec3 world_position, vec3 direction, out float coverage) {
+    uint buffer_index = push_constants.probe_buffer_index;
+    uint atlas_index = get_image_index(push_constants.probe_atlas);
+    uint sampler_index = get_sampler_index(push_constants.probe_sampler);
+    float half_texel = probe_buffers[buffer_index].half_texel;
+
+    vec3 color_sum = vec3(0.0);
+    float weight_sum = 0.0;
+    for (uint i = 0; i < probe_buffers[buffer_index].probe_count; i++) {
+        ReflectionProbe probe = probe_buffers[buffer_index].probes[i];
+        if (probe.tile.w == 0.0) {
+            continue;
+        }
+
+        vec3 local_position = world_position - probe.position_blend.xyz;
+        vec3 inside = probe.extents.xyz - abs(local_position);
+        float weight = clamp(min(min(inside.x, inside.y), inside.z) / max(probe.position_blend.w, 1e-4), 0.0, 1.0);
+        if (weight <= 0.0) {
+            continue;
+        }
+
+        vec3 sample_direction = direction;
+        if (probe.extents.w != 0.0) {
+            // Reflects what's at the side of the box the ray leaves through instead of what's infinitely far away
+            vec3 first = (probe.extents.xyz - local_position) / direction;
+            vec3 second = (-probe.extents.xyz - local_position) / direction;
+            vec3 furthest = max(first, second);
+            float distance = min(min(furthest.x, furthest.y), furthest.z);
+            sample_direction = local_position + direction * distance;
+        }
+
+        vec2 tile_uv = clamp(octahedral_encode(normalize(sample_direction)), vec2(half_texel), vec2(1.0 - half_texel));
+        vec2 uv = probe.tile.xy + tile_uv * probe.tile.z;
+        color_sum += textureLod(sampler2D(sampled_images[atlas_index], samplers[sampler_index]), uv, 0.0).rgb * weight;
+        weight_sum += weight;
+    }
+
+    coverage = min(weight_sum, 1.0);
+    if (weight_sum <= 0.0) {
+        return vec3(0.0);
+    }
+    return color_sum / weight_sum * probe_buffers[buffer_index].intensity;
+}
+
+// There are no prefiltered mips, so rough surfaces just reflect less
+vec3 apply_reflections(vec3 color, vec3 albedo, vec2 metallic_roughness) {
+    float smoothness = 1.0 - metallic_roughness.y;
+    if (probe_buffers[push_constants.probe_buffer_index].probe_count == 0 || smoothness <= 0.0) {
+        return color;
+    }
+
+    vec3 world_position = get_world_position();
+    vec3 view_direction = normalize(world_position - views[push_constants.view_index].camera_position);
+    vec3 normal = normalize(tangent_space_matrix[2]);
+    if (!gl_FrontFacing) {
+        normal = -normal;
+    }
+
+    float coverage;
+    vec3 reflection = sample_reflection_probes(world_position, reflect(view_direction, normal), coverage);
+
+    float n_dot_v = dot(normal, -view_direction);
+    vec3 fresnel = fresnel_schlick(base_reflectance(albedo, metallic_roughness.x), n_dot_v);
+    vec3 amount = fresnel * (smoothness * smoothness * coverage);
+    return color * (1.0 - amount) + reflection * amount;
+}
+
 void main() {
-    out_frag_color = frag_color * sample_image(push_constants.albedo_texture, push_constants.image_sampler, frag_uv1);
+    // Unlit geometry binds a white lightmap
+    vec3 lightmap = sample_image(push_constants.lightmap_texture, push_constants.lightmap_sampler, frag_uv2).rgb;
+    vec4 base_color = get_material().base_color;
+    vec3 emissive_color = get_material().emissive_color.rgb;
+    vec4 albedo = frag_color * base_color * sample_image(push_constants.albedo_texture, push_constants.image_sampler, frag_uv1);
+    out_frag_color = albedo * vec4(lightmap, 1.0);
+    out_frag_color.rgb = apply_reflections(out_frag_color.rgb, albedo.rgb, get_material().metallic_roughness.xy);
+    out_frag_color.rgb += emissive_color;
+
+    // Screen space motion in uv units
+    vec2 current_position = clip_position.xy / clip_position.w;
+    vec2 previous_position = previous_clip_position.xy / previous_clip_position.w;
+    out_velocity = vec4((current_position - previous_position) * 0.5, 0.0, 0.0);
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
 #version 450
 #extension GL_EXT_nonuniform_qualifier : require
+#extension GL_GOOGLE_include_directive : require
 
 layout (location = 0) in vec3 position;
 layout (location = 1) in vec3 normal;
@@ -11,33 +12,49 @@ layout (location = 0) out mat3 tangent_space_matrix;
 layout (location = 3) out vec2 frag_uv1;
 layout (location = 4) out vec2 frag_uv2;
 layout (location = 5) out vec4 frag_color;
+layout (location = 6) out vec4 clip_position;
+layout (location = 7) out vec4 previous_clip_position;
+layout (location = 8) flat out uint frag_draw_index;
 
-layout(std140, set = 0, binding = 0) readonly buffer Some{
-	mat4 view_projection_matrix;
-} Matrices[];
+#include <neptune/view.glsl>
+#include <neptune/draw.glsl>
+
+struct InstanceData {
+	mat4 model_matrix;
+	mat4 normal_matrix;
+	mat4 previous_model_matrix;
+};
 
 layout(std140, set = 0, binding = 0) readonly buffer Some1{
-	mat4 model_matrices[];
+	InstanceData instances[];
 } ModelMatrices[];
 
 layout(push_constant) uniform PushConstants
 {
-    uint view_projection_matrix_index;
+    uint view_index;
+    uint draw_buffer_index;
     uint model_matrices_index;
 } push_constants;
 
 void main() {
-    mat4 model_matrix = ModelMatrices[push_constants.model_matrices_index].model_matrices[gl_InstanceIndex];
-    mat4 mvp_matrix = Matrices[push_constants.view_projection_matrix_index].view_projection_matrix * model_matrix;
+    DrawData draw = draw_buffers[push_constants.draw_buffer_index].draws[gl_InstanceIndex];
+    InstanceData instance = ModelMatrices[push_constants.model_matrices_index].instances[draw.instance_index];
+    mat4 model_matrix = instance.model_matrix;
+    mat4 mvp_matrix = views[push_constants.view_index].view_projection_matrix * model_matrix;
     gl_Position = mvp_matrix * vec4(position, 1.0);
 
-    mat3 normal_matrix = mat3(model_matrix);
+    mat4 previous_mvp_matrix = views[push_constants.view_index].previous_view_projection_matrix * instance.previous_model_matrix;
+    clip_position = gl_Position;
+    previous_clip_position = previous_mvp_matrix * vec4(position, 1.0);
+
+    mat3 normal_matrix = mat3(instance.normal_matrix);
     vec3 world_normal = normalize(normal_matrix * normal);
-    vec3 world_tangent = normalize(normal_matrix * tangent.xyz);
+    vec3 world_tangent = normalize(mat3(model_matrix) * tangent.xyz);
     vec3 world_bitangent = cross(  world_normal, world_tangent ) * tangent.w;
     tangent_space_matrix = mat3(world_tangent, world_bitangent, world_normal);
 
     frag_uv1 = uv1_uv2.xy;
     frag_uv2 = uv1_uv2.zw;
     frag_color = color;
+    frag_draw_index = gl_InstanceIndex;
 }
\ No newline at end of file
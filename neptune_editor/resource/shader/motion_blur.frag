@@ -0,0 +1,121 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout(location = 0) out vec4 out_frag_color;
+
+layout(set = 0, binding = 1, rgba8) uniform readonly image2D color_images[];
+layout(set = 0, binding = 1, rgba16f) uniform readonly image2D velocity_images[];
+struct StorageImageBinding {
+    uint binding_index;
+};
+uint get_image_index(StorageImageBinding binding) {
+    return binding.binding_index & 0xFFFF;
+}
+
+layout(std140, set = 0, binding = 0) readonly buffer PostEffectBuffer {
+    vec4 vignette;             // x: intensity, y: radius, z: smoothness
+    vec4 chromatic_aberration; // x: pixel offset at the corners
+    vec4 film_grain;           // x: intensity, y: luminance response, z: frame seed
+    vec4 exposure;             // x: color multiplier
+} post_effects[];
+
+layout(set = 0, binding = 2) uniform texture3D lut_textures[];
+layout(set = 0, binding = 3) uniform sampler samplers[];
+
+layout(push_constant) uniform PushConstants
+{
+    StorageImageBinding color_image_binding;
+    StorageImageBinding velocity_image_binding;
+    uint lut_binding;
+    uint secondary_lut_binding;
+    uint lut_sampler_binding;
+    float lut_blend;
+    uint post_effect_index;
+    float dither_scale;
+    uvec2 view_offset;
+} push_constants;
+
+// Offsets the lookup so 0 and 1 land on the centers of the edge texels
+vec3 apply_lut(uint lut_binding, vec3 color) {
+    uint lut_index = lut_binding & 0xFFFF;
+    uint sampler_index = push_constants.lut_sampler_binding & 0xFFFF;
+    float size = float(textureSize(sampler3D(lut_textures[lut_index], samplers[sampler_index]), 0).x);
+    vec3 uvw = clamp(color, 0.0, 1.0) * ((size - 1.0) / size) + (0.5 / size);
+    return texture(sampler3D(lut_textures[lut_index], samplers[sampler_index]), uvw).rgb;
+}
+
+float hash(vec3 p) {
+    p = fract(p * 0.1031);
+    p += dot(p, p.zyx + 31.32);
+    return fract((p.x + p.y) * p.z);
+}
+
+// Red and blue are pulled in opposite directions away from the center
+vec4 load_color(uint color_index, ivec2 pixel, ivec2 image_size, ivec2 aberration_offset) {
+    vec4 color = imageLoad(color_images[color_index], pixel);
+    if (aberration_offset != ivec2(0)) {
+        color.r = imageLoad(color_images[color_index], clamp(pixel + aberration_offset, ivec2(0), image_size - ivec2(1))).r;
+        color.b = imageLoad(color_images[color_index], clamp(pixel - aberration_offset, ivec2(0), image_size - ivec2(1))).b;
+    }
+    return color;
+}
+
+// 4x4 Bayer matrix threshold in 0-1, built from the bits of the pixel coordinate
+float bayer_threshold(ivec2 pixel) {
+    uint x = uint(pixel.x) & 3u;
+    uint y = uint(pixel.y) & 3u;
+    uint xy = x ^ y;
+    uint index = ((xy & 1u) << 3u) | ((x & 1u) << 2u) | (xy & 2u) | ((x & 2u) >> 1u);
+    return (float(index) + 0.5) / 16.0;
+}
+
+const int SAMPLE_COUNT = 8;
+const float BLUR_SCALE = 0.5;
+
+void main() {
+    uint color_index = get_image_index(push_constants.color_image_binding);
+    uint velocity_index = get_image_index(push_constants.velocity_image_binding);
+
+    ivec2 image_size = imageSize(color_images[color_index]);
+    // Relative to the view, which is only part of the target in split screen
+    ivec2 pixel = ivec2(gl_FragCoord.xy) - ivec2(push_constants.view_offset);
+    // Velocity is at the render resolution, which is smaller than the output when upscaling
+    ivec2 velocity_size = imageSize(velocity_images[velocity_index]);
+    ivec2 velocity_pixel = min(ivec2(vec2(pixel) * vec2(velocity_size) / vec2(image_size)), velocity_size - ivec2(1));
+    vec2 velocity = imageLoad(velocity_images[velocity_index], velocity_pixel).xy * vec2(image_size) * BLUR_SCALE;
+
+    vec4 vignette = post_effects[push_constants.post_effect_index].vignette;
+    vec4 chromatic_aberration = post_effects[push_constants.post_effect_index].chromatic_aberration;
+    vec4 film_grain = post_effects[push_constants.post_effect_index].film_grain;
+
+    // -1 to 1 across the screen, with 1 at the corners
+    vec2 centered = ((vec2(pixel) + 0.5) / vec2(image_size)) * 2.0 - 1.0;
+    ivec2 aberration_offset = ivec2(centered * 0.70710678 * chromatic_aberration.x);
+
+    // Sample along the motion vector centered on the current pixel
+    vec4 color = vec4(0.0);
+    for (int i = 0; i < SAMPLE_COUNT; i++) {
+        float t = (float(i) / float(SAMPLE_COUNT - 1)) - 0.5;
+        ivec2 sample_pixel = clamp(pixel + ivec2(velocity * t), ivec2(0), image_size - ivec2(1));
+        color += load_color(color_index, sample_pixel, image_size, aberration_offset);
+    }
+    color /= float(SAMPLE_COUNT);
+    color.rgb *= post_effects[push_constants.post_effect_index].exposure.x;
+
+    float vignette_distance = length(centered) * 0.70710678;
+    color.rgb *= 1.0 - vignette.x * smoothstep(vignette.y, vignette.y + vignette.z, vignette_distance);
+
+    // Grain is applied before grading so the lut can tint it like the rest of the image
+    float luminance = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    float grain = hash(vec3(vec2(pixel), film_grain.z)) - 0.5;
+    color.rgb += grain * film_grain.x * mix(1.0, 1.0 - luminance, film_grain.y);
+
+    // Color grading is the last step of the post stack
+    vec3 graded = apply_lut(push_constants.lut_binding, color.rgb);
+    vec3 secondary_graded = apply_lut(push_constants.secondary_lut_binding, color.rgb);
+    vec3 final_color = mix(graded, secondary_graded, push_constants.lut_blend);
+
+    // Ordered dither of one output step so dark gradients don't band when quantized
+    final_color += (bayer_threshold(pixel) - 0.5) * push_constants.dither_scale;
+    out_frag_color = vec4(final_color, color.a);
+}
@@ -0,0 +1,44 @@
+#ifndef NEPTUNE_BINDLESS_GLSL
+#define NEPTUNE_BINDLESS_GLSL
+
+// Bindless images and samplers, the low 16 bits of a binding are its index in the array
+// Needs GL_EXT_nonuniform_qualifier
+
+// Storage buffer 0 is reserved for stale binding reports, only enabled with DeviceSettings::validate_bindings.
+// Sampled image bindings then carry their slot's generation in the high 16 bits, a mismatch means the image was freed
+layout(std430, set = 0, binding = 0) buffer BindingValidationBuffer {
+    uint enabled;
+    uint stale_read_count;
+    uint last_stale_binding;
+    uint padding;
+    uint generations[];
+} binding_validation[];
+
+layout(set = 0, binding = 2) uniform texture2D sampled_images[];
+struct SampledImageBinding {
+    uint binding_index;
+};
+uint get_image_index(SampledImageBinding binding) {
+    uint index = binding.binding_index & 0xFFFF;
+    if (binding_validation[0].enabled != 0 && binding_validation[0].generations[index] != (binding.binding_index >> 16)) {
+        atomicAdd(binding_validation[0].stale_read_count, 1);
+        binding_validation[0].last_stale_binding = binding.binding_index;
+    }
+    return index;
+}
+
+layout(set = 0, binding = 3) uniform sampler samplers[];
+struct SamplerBinding {
+    uint binding_index;
+};
+uint get_sampler_index(SamplerBinding binding) {
+    return binding.binding_index & 0xFFFF;
+}
+
+vec4 sample_image(SampledImageBinding image_binding, SamplerBinding sampler_binding, vec2 uv) {
+    uint image_index = get_image_index(image_binding);
+    uint sampler_index = get_sampler_index(sampler_binding);
+    return texture(sampler2D(sampled_images[image_index], samplers[sampler_index]), uv);
+}
+
+#endif
@@ -0,0 +1,47 @@
+#ifndef NEPTUNE_BRDF_GLSL
+#define NEPTUNE_BRDF_GLSL
+
+// Metallic roughness pbr, all directions point away from the surface
+
+const float BRDF_PI = 3.14159265;
+
+// Reflectance at normal incidence, dielectrics all use 4%
+vec3 base_reflectance(vec3 albedo, float metallic) {
+    return mix(vec3(0.04), albedo, metallic);
+}
+
+vec3 fresnel_schlick(vec3 f0, float cos_theta) {
+    return f0 + (1.0 - f0) * pow(1.0 - clamp(cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Trowbridge-Reitz, roughness is squared first like glTF expects
+float distribution_ggx(float n_dot_h, float roughness) {
+    float alpha = roughness * roughness;
+    float alpha2 = alpha * alpha;
+    float d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / max(BRDF_PI * d * d, 1e-6);
+}
+
+// Schlick-GGX for both directions with the 1 / (4 * n_dot_v * n_dot_l) term folded in
+float visibility_smith_ggx(float n_dot_v, float n_dot_l, float roughness) {
+    float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    float g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    float g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return g_v * g_l / max(4.0 * n_dot_v * n_dot_l, 1e-6);
+}
+
+// Light reflected towards view_direction per unit of light coming from light_direction, n_dot_l included
+vec3 evaluate_brdf(vec3 normal, vec3 view_direction, vec3 light_direction, vec3 albedo, float metallic, float roughness) {
+    vec3 half_vector = normalize(view_direction + light_direction);
+    float n_dot_v = max(dot(normal, view_direction), 1e-4);
+    float n_dot_l = clamp(dot(normal, light_direction), 0.0, 1.0);
+    float n_dot_h = clamp(dot(normal, half_vector), 0.0, 1.0);
+    float v_dot_h = clamp(dot(view_direction, half_vector), 0.0, 1.0);
+
+    vec3 fresnel = fresnel_schlick(base_reflectance(albedo, metallic), v_dot_h);
+    vec3 specular = fresnel * distribution_ggx(n_dot_h, roughness) * visibility_smith_ggx(n_dot_v, n_dot_l, roughness);
+    vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * albedo / BRDF_PI;
+    return (diffuse + specular) * n_dot_l;
+}
+
+#endif
@@ -0,0 +1,23 @@
+#ifndef NEPTUNE_DRAW_GLSL
+#define NEPTUNE_DRAW_GLSL
+
+// Per draw values, a draw's index is its first instance so vertex shaders read it from gl_InstanceIndex
+// Needs GL_EXT_nonuniform_qualifier
+
+const uint DRAW_FLAG_TRANSPARENT = 1;
+const uint DRAW_FLAG_LIGHTMAPPED = 2;
+const uint DRAW_FLAG_SHADER_GRAPH = 4;
+
+// Matches DrawData in draw_list.rs
+struct DrawData {
+    uint instance_index;
+    uint material_index;
+    uint flags;
+    uint padding;
+};
+
+layout(std430, set = 0, binding = 0) readonly buffer DrawBuffer {
+    DrawData draws[];
+} draw_buffers[];
+
+#endif
@@ -0,0 +1,192 @@
+#ifndef NEPTUNE_PATH_TRACE_GLSL
+#define NEPTUNE_PATH_TRACE_GLSL
+
+// Scene triangles and bvh from PathTracer::build_scene, shared by the path tracer and the lightmap baker
+// Needs GL_EXT_nonuniform_qualifier
+
+struct Triangle {
+    vec4 position0_albedo_r;
+    vec4 position1_albedo_g;
+    vec4 position2_albedo_b;
+    vec4 emissive;
+};
+layout(std430, set = 0, binding = 0) readonly buffer TriangleBuffer {
+    Triangle triangles[];
+} triangle_buffers[];
+
+struct BvhNode {
+    vec3 min;
+    uint first; // first triangle for leaves, right child for branches
+    vec3 max;
+    uint count; // 0 for branches, the left child is always the next node
+};
+layout(std430, set = 0, binding = 0) readonly buffer BvhBuffer {
+    BvhNode nodes[];
+} bvh_buffers[];
+
+struct PathTraceScene {
+    uint triangle_index;
+    uint bvh_index;
+    uint node_count;
+};
+
+struct PathTraceLighting {
+    vec3 sun_direction; // towards the sun
+    vec3 sun_color;
+    vec3 sky_color;
+    uint max_bounces;
+};
+
+const float PI = 3.14159265359;
+const float RAY_BIAS = 0.001;
+const float MAX_DISTANCE = 10000.0;
+const int STACK_SIZE = 32;
+
+uint rng_state;
+uint pcg_hash(uint value) {
+    uint state = value * 747796405u + 2891336453u;
+    uint word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+float random() {
+    rng_state = pcg_hash(rng_state);
+    return float(rng_state) / 4294967296.0;
+}
+
+float intersect_triangle(vec3 origin, vec3 direction, Triangle triangle) {
+    vec3 p0 = triangle.position0_albedo_r.xyz;
+    vec3 edge1 = triangle.position1_albedo_g.xyz - p0;
+    vec3 edge2 = triangle.position2_albedo_b.xyz - p0;
+    vec3 p = cross(direction, edge2);
+    float determinant = dot(edge1, p);
+    if (abs(determinant) < 1e-8) {
+        return -1.0;
+    }
+
+    float inverse_determinant = 1.0 / determinant;
+    vec3 t = origin - p0;
+    float u = dot(t, p) * inverse_determinant;
+    if (u < 0.0 || u > 1.0) {
+        return -1.0;
+    }
+
+    vec3 q = cross(t, edge1);
+    float v = dot(direction, q) * inverse_determinant;
+    if (v < 0.0 || u + v > 1.0) {
+        return -1.0;
+    }
+    return dot(edge2, q) * inverse_determinant;
+}
+
+bool trace(PathTraceScene scene, vec3 origin, vec3 direction, float max_distance, out float hit_distance, out uint hit_index) {
+    hit_distance = max_distance;
+    hit_index = 0;
+    if (scene.node_count == 0) {
+        return false;
+    }
+
+    // Avoid inf * 0 in the slab test
+    vec3 safe_direction = mix(direction, vec3(1e-8), lessThan(abs(direction), vec3(1e-8)));
+    vec3 inverse_direction = 1.0 / safe_direction;
+
+    bool hit = false;
+    uint stack[STACK_SIZE];
+    int stack_size = 0;
+    stack[stack_size++] = 0;
+    while (stack_size > 0) {
+        uint node_index = stack[--stack_size];
+        BvhNode node = bvh_buffers[scene.bvh_index].nodes[node_index];
+
+        vec3 t0 = (node.min - origin) * inverse_direction;
+        vec3 t1 = (node.max - origin) * inverse_direction;
+        vec3 t_min = min(t0, t1);
+        vec3 t_max = max(t0, t1);
+        float t_enter = max(max(max(t_min.x, t_min.y), t_min.z), 0.0);
+        float t_exit = min(min(t_max.x, t_max.y), t_max.z);
+        if (t_enter > t_exit || t_enter > hit_distance) {
+            continue;
+        }
+
+        if (node.count > 0) {
+            for (uint i = node.first; i < node.first + node.count; i++) {
+                float triangle_distance = intersect_triangle(origin, direction, triangle_buffers[scene.triangle_index].triangles[i]);
+                if (triangle_distance > 0.0 && triangle_distance < hit_distance) {
+                    hit_distance = triangle_distance;
+                    hit_index = i;
+                    hit = true;
+                }
+            }
+        } else if (stack_size + 2 <= STACK_SIZE) {
+            stack[stack_size++] = node.first;
+            stack[stack_size++] = node_index + 1;
+        }
+    }
+    return hit;
+}
+
+vec3 cosine_sample_hemisphere(vec3 normal) {
+    float u = random();
+    float v = random();
+    float radius = sqrt(u);
+    float phi = 2.0 * PI * v;
+
+    vec3 up = abs(normal.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+    vec3 tangent = normalize(cross(up, normal));
+    vec3 bitangent = cross(normal, tangent);
+    return normalize((tangent * (radius * cos(phi))) + (bitangent * (radius * sin(phi))) + (normal * sqrt(max(1.0 - u, 0.0))));
+}
+
+// Sun light reaching a surface, zero when it faces away or is shadowed
+vec3 direct_light(PathTraceScene scene, PathTraceLighting lighting, vec3 position, vec3 normal) {
+    float n_dot_l = dot(normal, lighting.sun_direction);
+    float shadow_distance;
+    uint shadow_index;
+    if (n_dot_l > 0.0 && !trace(scene, position + (normal * RAY_BIAS), lighting.sun_direction, MAX_DISTANCE, shadow_distance, shadow_index)) {
+        return lighting.sun_color * n_dot_l;
+    }
+    return vec3(0.0);
+}
+
+// Radiance arriving at origin from direction, following the path for up to max_bounces hits
+vec3 trace_path(PathTraceScene scene, PathTraceLighting lighting, vec3 origin, vec3 direction) {
+    vec3 radiance = vec3(0.0);
+    vec3 throughput = vec3(1.0);
+    for (uint bounce = 0; bounce < lighting.max_bounces; bounce++) {
+        float hit_distance;
+        uint hit_index;
+        if (!trace(scene, origin, direction, MAX_DISTANCE, hit_distance, hit_index)) {
+            radiance += throughput * lighting.sky_color;
+            break;
+        }
+
+        Triangle triangle = triangle_buffers[scene.triangle_index].triangles[hit_index];
+        vec3 position = origin + (direction * hit_distance);
+        vec3 normal = normalize(cross(triangle.position1_albedo_g.xyz - triangle.position0_albedo_r.xyz, triangle.position2_albedo_b.xyz - triangle.position0_albedo_r.xyz));
+        if (dot(normal, direction) > 0.0) {
+            normal = -normal;
+        }
+        vec3 albedo = vec3(triangle.position0_albedo_r.w, triangle.position1_albedo_g.w, triangle.position2_albedo_b.w);
+        radiance += throughput * triangle.emissive.rgb;
+
+        // Next event estimation towards the sun
+        radiance += throughput * albedo * direct_light(scene, lighting, position, normal);
+
+        // Cosine weighted sampling cancels the lambert term, leaving just the albedo
+        throughput *= albedo;
+
+        // Russian roulette past the first couple of bounces
+        if (bounce > 1) {
+            float survive = clamp(max(max(throughput.r, throughput.g), throughput.b), 0.05, 1.0);
+            if (random() > survive) {
+                break;
+            }
+            throughput /= survive;
+        }
+
+        origin = position + (normal * RAY_BIAS);
+        direction = cosine_sample_hemisphere(normal);
+    }
+    return radiance;
+}
+
+#endif
@@ -0,0 +1,30 @@
+#ifndef NEPTUNE_TONEMAP_GLSL
+#define NEPTUNE_TONEMAP_GLSL
+
+// Hdr to [0, 1], exposure is applied before calling these
+
+// What the sky uses, never fully saturates
+vec3 tonemap_exponential(vec3 color) {
+    return 1.0 - exp(-color);
+}
+
+vec3 tonemap_reinhard(vec3 color) {
+    return color / (1.0 + color);
+}
+
+// Narkowicz's fit of the ACES filmic curve
+vec3 tonemap_aces(vec3 color) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), 0.0, 1.0);
+}
+
+// Exposure in stops, like PostEffectSettings::exposure
+vec3 apply_exposure(vec3 color, float exposure) {
+    return color * exp2(exposure);
+}
+
+#endif
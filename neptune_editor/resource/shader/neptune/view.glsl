@@ -0,0 +1,23 @@
+#ifndef NEPTUNE_VIEW_GLSL
+#define NEPTUNE_VIEW_GLSL
+
+// Needs GL_EXT_nonuniform_qualifier
+
+// Matches ViewData in scene_renderer.rs
+layout(std140, set = 0, binding = 0) readonly buffer ViewBuffer {
+    mat4 view_projection_matrix;
+    mat4 previous_view_projection_matrix;
+    mat4 inverse_view_projection_matrix;
+    vec3 camera_position;
+    float near_clip;
+    mat4 view_matrix;
+    mat4 projection_matrix;
+    mat4 inverse_view_matrix;
+    mat4 inverse_projection_matrix;
+    vec2 viewport_size;
+    vec2 jitter;
+    float far_clip; // 0 for an infinite far plane
+    uint reversed_depth; // 1 if the near plane is at depth 1
+} views[];
+
+#endif
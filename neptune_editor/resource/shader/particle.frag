@@ -0,0 +1,14 @@
+#version 460
+
+layout (location = 0) in vec2 frag_corner;
+layout (location = 1) in vec4 frag_color;
+
+layout (location = 0) out vec4 out_frag_color;
+
+void main() {
+    float falloff = 1.0 - dot(frag_corner, frag_corner);
+    if (falloff <= 0.0) {
+        discard;
+    }
+    out_frag_color = vec4(frag_color.rgb, frag_color.a * falloff);
+}
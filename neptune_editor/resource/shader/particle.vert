@@ -0,0 +1,65 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+#extension GL_GOOGLE_include_directive : require
+
+layout (location = 0) out vec2 frag_corner;
+layout (location = 1) out vec4 frag_color;
+
+#include <neptune/view.glsl>
+
+layout(std140, set = 0, binding = 0) readonly buffer ParticleDataBuffer {
+    vec4 emitter_position; // w: delta time
+    vec4 spawn_velocity;   // w: velocity spread
+    vec4 gravity;          // w: lifetime
+    vec4 color;
+    vec4 collision;        // x: bounce, y: friction, z: thickness, w: 1 if depth collision is enabled
+    uvec4 spawn;           // x: first particle to spawn, y: spawn count, z: capacity, w: random seed
+    vec4 draw;             // x: size
+} particle_data[];
+
+// Matches Particle in particles.rs
+struct Particle {
+    vec4 position_age;
+    vec4 velocity_lifetime; // w: lifetime, 0 for dead particles
+};
+
+layout(std430, set = 0, binding = 0) readonly buffer ParticleBuffer {
+    Particle particles[];
+} particle_buffers[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint view_index;
+    uint particle_data_index;
+    uint particle_buffer_index;
+    uint alive_indices_index;
+} push_constants;
+
+layout(std430, set = 0, binding = 0) readonly buffer IndexBuffer {
+    uint values[];
+} index_buffers[];
+
+const vec2 CORNERS[6] = vec2[](
+    vec2(-0.5, -0.5), vec2(0.5, -0.5), vec2(0.5, 0.5),
+    vec2(-0.5, -0.5), vec2(0.5, 0.5), vec2(-0.5, 0.5)
+);
+
+// Camera facing quads, one instance per live particle
+void main() {
+    uint slot = index_buffers[push_constants.alive_indices_index].values[gl_InstanceIndex];
+    Particle particle = particle_buffers[push_constants.particle_buffer_index].particles[slot];
+    vec2 corner = CORNERS[gl_VertexIndex];
+    frag_corner = corner * 2.0;
+
+    mat4 inverse_view_matrix = views[push_constants.view_index].inverse_view_matrix;
+    vec3 camera_right = inverse_view_matrix[0].xyz;
+    vec3 camera_up = inverse_view_matrix[1].xyz;
+    float size = particle_data[push_constants.particle_data_index].draw.x;
+    vec3 position = particle.position_age.xyz + (camera_right * corner.x + camera_up * corner.y) * size;
+    gl_Position = views[push_constants.view_index].view_projection_matrix * vec4(position, 1.0);
+
+    // Fades out over the particle's life
+    float life = particle.position_age.w / particle.velocity_lifetime.w;
+    frag_color = particle_data[push_constants.particle_data_index].color;
+    frag_color.a *= 1.0 - life;
+}
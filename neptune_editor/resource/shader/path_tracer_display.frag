@@ -0,0 +1,17 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout(location = 0) out vec4 out_frag_color;
+
+layout(set = 0, binding = 1, rgba32f) uniform readonly image2D accumulation_images[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint accumulation_image_binding;
+} push_constants;
+
+void main() {
+    uint accumulation_index = push_constants.accumulation_image_binding & 0xFFFF;
+    vec3 color = imageLoad(accumulation_images[accumulation_index], ivec2(gl_FragCoord.xy)).rgb;
+    out_frag_color = vec4(clamp(color, 0.0, 1.0), 1.0);
+}
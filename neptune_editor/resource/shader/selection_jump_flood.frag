@@ -0,0 +1,50 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout(location = 0) out vec4 out_seed;
+
+layout(std140, set = 0, binding = 0) readonly buffer StepBuffer {
+    uvec4 step_size;
+} steps[];
+
+layout(set = 0, binding = 1, rg32f) uniform readonly image2D seed_images[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint step_index;
+    uint seed_image_binding;
+} push_constants;
+
+void main() {
+    uint seed_index = push_constants.seed_image_binding & 0xFFFF;
+    int step_size = int(steps[push_constants.step_index].step_size.x);
+
+    ivec2 image_size = imageSize(seed_images[seed_index]);
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+
+    // Keep the closest seed found by this pixel or any neighbor step_size away
+    vec2 best_seed = vec2(-1.0);
+    float best_distance = 1e30;
+    for (int y = -1; y <= 1; y++) {
+        for (int x = -1; x <= 1; x++) {
+            ivec2 sample_pixel = pixel + (ivec2(x, y) * step_size);
+            if (any(lessThan(sample_pixel, ivec2(0))) || any(greaterThanEqual(sample_pixel, image_size))) {
+                continue;
+            }
+
+            vec2 seed = imageLoad(seed_images[seed_index], sample_pixel).xy;
+            if (seed.x < 0.0) {
+                continue;
+            }
+
+            vec2 offset = seed - gl_FragCoord.xy;
+            float seed_distance = dot(offset, offset);
+            if (seed_distance < best_distance) {
+                best_distance = seed_distance;
+                best_seed = seed;
+            }
+        }
+    }
+
+    out_seed = vec4(best_seed, 0.0, 0.0);
+}
@@ -0,0 +1,8 @@
+#version 460
+
+layout(location = 0) out vec4 out_seed;
+
+void main() {
+    // Every covered pixel is its own nearest seed
+    out_seed = vec4(gl_FragCoord.xy, 0.0, 0.0);
+}
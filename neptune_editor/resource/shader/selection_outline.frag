@@ -0,0 +1,41 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout(location = 0) out vec4 out_frag_color;
+
+layout(std140, set = 0, binding = 0) readonly buffer OutlineBuffer {
+    vec4 color;
+    vec4 params; // x: width in pixels
+} outline_settings[];
+
+layout(set = 0, binding = 1, rgba8) uniform readonly image2D color_images[];
+layout(set = 0, binding = 1, rg32f) uniform readonly image2D seed_images[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint outline_index;
+    uint color_image_binding;
+    uint seed_image_binding;
+} push_constants;
+
+void main() {
+    uint color_index = push_constants.color_image_binding & 0xFFFF;
+    uint seed_index = push_constants.seed_image_binding & 0xFFFF;
+    vec4 outline_color = outline_settings[push_constants.outline_index].color;
+    float outline_width = outline_settings[push_constants.outline_index].params.x;
+
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    vec4 color = imageLoad(color_images[color_index], pixel);
+    vec2 seed = imageLoad(seed_images[seed_index], pixel).xy;
+
+    // Selected pixels are their own seed, so only pixels outside the selection get the outline
+    if (seed.x >= 0.0) {
+        float seed_distance = length(seed - gl_FragCoord.xy);
+        if (seed_distance > 0.0) {
+            float coverage = clamp(outline_width - seed_distance + 1.0, 0.0, 1.0);
+            color.rgb = mix(color.rgb, outline_color.rgb, outline_color.a * coverage);
+        }
+    }
+
+    out_frag_color = color;
+}
@@ -0,0 +1,98 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+#extension GL_GOOGLE_include_directive : require
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_frag_color;
+
+#include <neptune/view.glsl>
+
+// Matches SkyData in sky.rs, distances are in km
+layout(std140, set = 0, binding = 0) readonly buffer SkyBuffer {
+    vec4 sun_direction;  // xyz: direction towards the sun, w: exposure
+    vec4 rayleigh;       // rgb: scattering, w: scale height
+    vec4 mie;            // x: scattering, y: extinction, z: scale height, w: anisotropy
+    vec4 ozone;          // rgb: absorption, w: half width of the layer
+    vec4 planet;         // x: ground radius, y: atmosphere radius, z: viewer radius, w: ozone layer height
+    vec4 ground_albedo;
+} sky_settings[];
+
+layout(set = 0, binding = 2) uniform texture2D textures[];
+layout(set = 0, binding = 3) uniform sampler samplers[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint camera_index;
+    uint sky_index;
+    uint depth_texture_binding;
+    uint sky_view_texture_binding;
+    uint transmittance_texture_binding;
+    uint sampler_binding;
+} push_constants;
+
+const float PI = 3.14159265;
+// Drawn bigger than the real sun so it still reads at low resolutions
+const float SUN_COS_RADIUS = 0.99996;
+// Luminance of the disk per unit of sun illuminance, saturates after tonemapping anyway
+const float SUN_DISK_LUMINANCE = 20.0;
+
+vec4 sample_texture(uint binding, vec2 uv) {
+    return texture(sampler2D(textures[binding & 0xFFFF], samplers[push_constants.sampler_binding & 0xFFFF]), uv);
+}
+
+void main() {
+    uint depth_index = push_constants.depth_texture_binding & 0xFFFF;
+    uint sampler_index = push_constants.sampler_binding & 0xFFFF;
+
+    // Only fills in what the scene pass left at the far plane
+    float depth = texelFetch(sampler2D(textures[depth_index], samplers[sampler_index]), ivec2(gl_FragCoord.xy), 0).r;
+    float far_depth = views[push_constants.camera_index].reversed_depth != 0 ? 0.0 : 1.0;
+    if (depth != far_depth) {
+        discard;
+    }
+
+    vec4 sun = sky_settings[push_constants.sky_index].sun_direction;
+    vec4 planet = sky_settings[push_constants.sky_index].planet;
+
+    // Any point along the pixel's ray works, the near plane is always valid
+    vec4 near_position = views[push_constants.camera_index].inverse_view_projection_matrix * vec4(in_uv * 2.0 - 1.0, views[push_constants.camera_index].reversed_depth != 0 ? 1.0 : 0.0, 1.0);
+    vec3 view_direction = normalize(near_position.xyz / near_position.w - views[push_constants.camera_index].camera_position);
+
+    // Same mapping as sky_view.comp
+    float horizon_cos = sqrt(planet.z * planet.z - planet.x * planet.x) / planet.z;
+    float beta = acos(horizon_cos);
+    float zenith_horizon_angle = PI - beta;
+    float view_zenith_angle = acos(clamp(view_direction.y, -1.0, 1.0));
+    bool below_horizon = view_zenith_angle > zenith_horizon_angle;
+    float v;
+    if (below_horizon) {
+        v = sqrt((view_zenith_angle - zenith_horizon_angle) / beta) * 0.5 + 0.5;
+    } else {
+        v = (1.0 - sqrt(1.0 - view_zenith_angle / zenith_horizon_angle)) * 0.5;
+    }
+
+    vec2 view_flat = view_direction.xz;
+    vec2 sun_flat = sun.xz;
+    float cos_azimuth = 1.0;
+    if (dot(view_flat, view_flat) > 1e-8 && dot(sun_flat, sun_flat) > 1e-8) {
+        cos_azimuth = dot(normalize(view_flat), normalize(sun_flat));
+    }
+    float u = sqrt(clamp(-cos_azimuth * 0.5 + 0.5, 0.0, 1.0));
+
+    vec3 luminance = sample_texture(push_constants.sky_view_texture_binding, vec2(u, v)).rgb;
+
+    if (!below_horizon && dot(view_direction, sun.xyz) > SUN_COS_RADIUS) {
+        float horizon = sqrt(planet.y * planet.y - planet.x * planet.x);
+        float rho = sqrt(planet.z * planet.z - planet.x * planet.x);
+        float discriminant = planet.z * planet.z * (sun.y * sun.y - 1.0) + planet.y * planet.y;
+        float distance = max(-planet.z * sun.y + sqrt(max(discriminant, 0.0)), 0.0);
+        float min_distance = planet.y - planet.z;
+        float max_distance = rho + horizon;
+        vec2 transmittance_uv = vec2((distance - min_distance) / (max_distance - min_distance), rho / horizon);
+        luminance += sample_texture(push_constants.transmittance_texture_binding, transmittance_uv).rgb * SUN_DISK_LUMINANCE;
+    }
+
+    // The scene is lit in ldr, so the sky gets its own exposure and tonemap
+    out_frag_color = vec4(1.0 - exp(-luminance * sun.w), 1.0);
+}
@@ -0,0 +1,24 @@
+#version 450
+#extension GL_EXT_nonuniform_qualifier : require
+#extension GL_GOOGLE_include_directive : require
+
+layout (location = 0) in vec2 frag_uv;
+layout (location = 1) in vec4 frag_color;
+
+layout(location = 0) out vec4 out_frag_color;
+
+#include <neptune/bindless.glsl>
+
+layout(push_constant) uniform PushConstants
+{
+    uint camera_index;
+    uint view_index;
+    uint instance_index;
+    SampledImageBinding texture_binding;
+    SamplerBinding sampler_binding;
+} push_constants;
+
+void main() {
+    vec4 texture_color = sample_image(push_constants.texture_binding, push_constants.sampler_binding, frag_uv);
+    out_frag_color = texture_color * frag_color;
+}
@@ -0,0 +1,60 @@
+#version 450
+#extension GL_EXT_nonuniform_qualifier : require
+#extension GL_GOOGLE_include_directive : require
+
+layout (location = 0) out vec2 frag_uv;
+layout (location = 1) out vec4 frag_color;
+
+#include <neptune/view.glsl>
+
+layout(std140, set = 0, binding = 0) readonly buffer SpriteViewBuffer {
+    mat4 screen_matrix;
+} sprite_views[];
+
+struct SpriteInstance {
+    vec4 position_rotation; // xyz: position, w: rotation in radians
+    vec4 size_space;        // xy: size, z: 0 for screen space, 1 for world space
+    vec4 uv_rect;           // xy: uv min, zw: uv max
+    vec4 color;
+};
+
+layout(std140, set = 0, binding = 0) readonly buffer SpriteInstanceBuffer {
+    SpriteInstance instances[];
+} sprite_instances[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint camera_index;
+    uint view_index;
+    uint instance_index;
+    uint texture_binding;
+    uint sampler_binding;
+} push_constants;
+
+const vec2 CORNERS[6] = vec2[](
+    vec2(-0.5, -0.5), vec2(0.5, -0.5), vec2(0.5, 0.5),
+    vec2(-0.5, -0.5), vec2(0.5, 0.5), vec2(-0.5, 0.5)
+);
+
+void main() {
+    SpriteInstance sprite = sprite_instances[push_constants.instance_index].instances[gl_InstanceIndex];
+    bool world_space = sprite.size_space.z > 0.5;
+
+    // Corners go down the texture as y increases, screen space y already points down but world space y points up
+    vec2 corner = CORNERS[gl_VertexIndex];
+    vec2 local_position = corner * sprite.size_space.xy;
+    if (world_space) {
+        local_position.y = -local_position.y;
+    }
+
+    float s = sin(sprite.position_rotation.w);
+    float c = cos(sprite.position_rotation.w);
+    vec2 rotated = vec2(local_position.x * c - local_position.y * s, local_position.x * s + local_position.y * c);
+    vec3 position = sprite.position_rotation.xyz + vec3(rotated, 0.0);
+
+    mat4 matrix = world_space ? views[push_constants.camera_index].view_projection_matrix : sprite_views[push_constants.view_index].screen_matrix;
+    gl_Position = matrix * vec4(position, 1.0);
+
+    frag_uv = mix(sprite.uv_rect.xy, sprite.uv_rect.zw, corner + 0.5);
+    frag_color = sprite.color;
+}
@@ -0,0 +1,35 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout(location = 0) out vec4 out_frag_color;
+
+layout(set = 0, binding = 2) uniform texture2D ui_textures[];
+layout(set = 0, binding = 3) uniform sampler samplers[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint ui_texture_binding;
+    uint sampler_binding;
+    uint linear_output;
+    float paper_white;
+} push_constants;
+
+vec3 srgb_to_linear(vec3 color) {
+    return mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)), greaterThan(color, vec3(0.04045)));
+}
+
+void main() {
+    uint texture_index = push_constants.ui_texture_binding & 0xFFFF;
+    uint sampler_index = push_constants.sampler_binding & 0xFFFF;
+
+    // Premultiplied srgb values, blended over the target with ONE, ONE_MINUS_SRC_ALPHA
+    vec4 ui = texelFetch(sampler2D(ui_textures[texture_index], samplers[sampler_index]), ivec2(gl_FragCoord.xy), 0);
+
+    if (push_constants.linear_output != 0) {
+        // Decoding isn't linear, so it has to be done on the straight color and premultiplied again after
+        vec3 straight_color = ui.a > 0.0 ? ui.rgb / ui.a : vec3(0.0);
+        ui.rgb = srgb_to_linear(straight_color) * push_constants.paper_white * ui.a;
+    }
+
+    out_frag_color = ui;
+}
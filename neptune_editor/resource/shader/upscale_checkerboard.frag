@@ -0,0 +1,92 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout(location = 0) out vec4 out_frag_color;
+layout(location = 1) out vec4 out_history;
+
+layout(set = 0, binding = 1, rgba8) uniform readonly image2D color_images[];
+layout(set = 0, binding = 1, rgba16f) uniform readonly image2D velocity_images[];
+layout(set = 0, binding = 2) uniform texture2D history_textures[];
+layout(set = 0, binding = 3) uniform sampler samplers[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint color_image_binding;
+    uint velocity_image_binding;
+    uint history_texture_binding;
+    uint history_sampler_binding;
+    uint output_width;
+    uint output_height;
+    uint field; // 0 if this frame shaded the pixels where x + y is even
+    uint history_valid;
+} push_constants;
+
+// Steps the other way past the edges, the opposite neighbor was shaded this frame too
+ivec2 neighbor(ivec2 pixel, ivec2 offset, ivec2 size) {
+    ivec2 neighbor_pixel = pixel + offset;
+    if (any(lessThan(neighbor_pixel, ivec2(0))) || any(greaterThanEqual(neighbor_pixel, size))) {
+        neighbor_pixel = pixel - offset;
+    }
+    return clamp(neighbor_pixel, ivec2(0), size - ivec2(1));
+}
+
+void main() {
+    uint color_index = push_constants.color_image_binding & 0xFFFF;
+    uint velocity_index = push_constants.velocity_image_binding & 0xFFFF;
+    uint history_index = push_constants.history_texture_binding & 0xFFFF;
+    uint sampler_index = push_constants.history_sampler_binding & 0xFFFF;
+
+    ivec2 size = imageSize(color_images[color_index]);
+    vec2 output_size = vec2(push_constants.output_width, push_constants.output_height);
+    ivec2 pixel = min(ivec2(gl_FragCoord.xy), size - ivec2(1));
+
+    vec4 result;
+    if (uint((pixel.x + pixel.y) & 1) == push_constants.field) {
+        // Shaded this frame
+        result = imageLoad(color_images[color_index], pixel);
+    } else {
+        // Masked this frame, all four direct neighbors were shaded
+        ivec2 neighbors[4] = ivec2[4](
+            neighbor(pixel, ivec2(-1, 0), size),
+            neighbor(pixel, ivec2(1, 0), size),
+            neighbor(pixel, ivec2(0, -1), size),
+            neighbor(pixel, ivec2(0, 1), size)
+        );
+
+        vec4 color_min = vec4(1e30);
+        vec4 color_max = vec4(-1e30);
+        vec4 interpolated = vec4(0.0);
+        vec2 velocity = vec2(0.0);
+        for (int i = 0; i < 4; i++) {
+            vec4 color = imageLoad(color_images[color_index], neighbors[i]);
+            color_min = min(color_min, color);
+            color_max = max(color_max, color);
+            interpolated += color * 0.25;
+
+            // The mask leaves no velocity here, the fastest neighbor keeps moving edges from smearing
+            vec2 neighbor_velocity = imageLoad(velocity_images[velocity_index], neighbors[i]).xy;
+            if (dot(neighbor_velocity, neighbor_velocity) > dot(velocity, velocity)) {
+                velocity = neighbor_velocity;
+            }
+        }
+
+        vec2 uv = gl_FragCoord.xy / output_size;
+        vec2 history_uv = uv - velocity;
+
+        bool history_usable = push_constants.history_valid != 0
+            && all(greaterThanEqual(history_uv, vec2(0.0)))
+            && all(lessThanEqual(history_uv, vec2(1.0)));
+
+        if (history_usable) {
+            // Bounding by the neighbors rejects history that no longer matches the scene
+            vec4 history = texture(sampler2D(history_textures[history_index], samplers[sampler_index]), history_uv);
+            vec4 margin = (color_max - color_min) * 0.25 + vec4(0.02);
+            result = clamp(history, color_min - margin, color_max + margin);
+        } else {
+            result = interpolated;
+        }
+    }
+
+    out_frag_color = result;
+    out_history = result;
+}
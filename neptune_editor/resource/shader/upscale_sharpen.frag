@@ -0,0 +1,36 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout(location = 0) out vec4 out_frag_color;
+
+layout(set = 0, binding = 1, rgba8) uniform readonly image2D color_images[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint color_image_binding;
+    float sharpness;
+} push_constants;
+
+// Contrast adaptive sharpening on a 5 tap cross, the amount is limited so the result never leaves the neighborhood's range
+void main() {
+    uint color_index = push_constants.color_image_binding & 0xFFFF;
+
+    ivec2 image_size = imageSize(color_images[color_index]);
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+
+    vec4 center = imageLoad(color_images[color_index], pixel);
+    vec3 north = imageLoad(color_images[color_index], clamp(pixel + ivec2(0, -1), ivec2(0), image_size - ivec2(1))).rgb;
+    vec3 south = imageLoad(color_images[color_index], clamp(pixel + ivec2(0, 1), ivec2(0), image_size - ivec2(1))).rgb;
+    vec3 west = imageLoad(color_images[color_index], clamp(pixel + ivec2(-1, 0), ivec2(0), image_size - ivec2(1))).rgb;
+    vec3 east = imageLoad(color_images[color_index], clamp(pixel + ivec2(1, 0), ivec2(0), image_size - ivec2(1))).rgb;
+
+    vec3 neighbor_min = min(min(north, south), min(west, east));
+    vec3 neighbor_max = max(max(north, south), max(west, east));
+
+    // Less sharpening where the neighborhood is already close to black or white
+    vec3 headroom = min(neighbor_min, 1.0 - neighbor_max) / max(neighbor_max, 1e-4);
+    vec3 amount = sqrt(clamp(headroom, 0.0, 1.0)) * (-0.125 * push_constants.sharpness);
+
+    vec3 sharpened = (center.rgb + (north + south + west + east) * amount) / (1.0 + 4.0 * amount);
+    out_frag_color = vec4(clamp(sharpened, min(neighbor_min, center.rgb), max(neighbor_max, center.rgb)), center.a);
+}
@@ -0,0 +1,58 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout(location = 0) out vec4 out_frag_color;
+
+layout(set = 0, binding = 1, rgba8) uniform readonly image2D color_images[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint color_image_binding;
+    uint output_width;
+    uint output_height;
+} push_constants;
+
+float lanczos2(float x) {
+    x = abs(x);
+    if (x >= 2.0) {
+        return 0.0;
+    }
+    if (x < 1e-4) {
+        return 1.0;
+    }
+    float pi_x = 3.14159265 * x;
+    return 2.0 * sin(pi_x) * sin(pi_x * 0.5) / (pi_x * pi_x);
+}
+
+// 4x4 Lanczos upscale, clamped to the nearest 2x2 texels so the negative lobes don't ring around edges
+void main() {
+    uint color_index = push_constants.color_image_binding & 0xFFFF;
+
+    ivec2 input_size = imageSize(color_images[color_index]);
+    vec2 output_size = vec2(push_constants.output_width, push_constants.output_height);
+    vec2 input_position = gl_FragCoord.xy * vec2(input_size) / output_size - 0.5;
+    ivec2 base_texel = ivec2(floor(input_position));
+    vec2 fraction = input_position - vec2(base_texel);
+
+    vec4 color_sum = vec4(0.0);
+    float weight_sum = 0.0;
+    vec4 color_min = vec4(1.0);
+    vec4 color_max = vec4(0.0);
+    for (int y = -1; y <= 2; y++) {
+        for (int x = -1; x <= 2; x++) {
+            ivec2 texel = clamp(base_texel + ivec2(x, y), ivec2(0), input_size - ivec2(1));
+            vec4 color = imageLoad(color_images[color_index], texel);
+
+            float weight = lanczos2(float(x) - fraction.x) * lanczos2(float(y) - fraction.y);
+            color_sum += color * weight;
+            weight_sum += weight;
+
+            if (x >= 0 && x <= 1 && y >= 0 && y <= 1) {
+                color_min = min(color_min, color);
+                color_max = max(color_max, color);
+            }
+        }
+    }
+
+    out_frag_color = clamp(color_sum / weight_sum, color_min, color_max);
+}
@@ -0,0 +1,82 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout(location = 0) out vec4 out_frag_color;
+layout(location = 1) out vec4 out_history;
+
+layout(set = 0, binding = 1, rgba8) uniform readonly image2D color_images[];
+layout(set = 0, binding = 1, rgba16f) uniform readonly image2D velocity_images[];
+layout(set = 0, binding = 2) uniform texture2D history_textures[];
+layout(set = 0, binding = 3) uniform sampler samplers[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint color_image_binding;
+    uint velocity_image_binding;
+    uint history_texture_binding;
+    uint history_sampler_binding;
+    uint output_width;
+    uint output_height;
+    float jitter_x; // In render pixels
+    float jitter_y;
+    float jitter_delta_x; // Change in jitter since last frame, in uv
+    float jitter_delta_y;
+    float history_weight; // 0 when there is no valid history
+} push_constants;
+
+void main() {
+    uint color_index = push_constants.color_image_binding & 0xFFFF;
+    uint velocity_index = push_constants.velocity_image_binding & 0xFFFF;
+    uint history_index = push_constants.history_texture_binding & 0xFFFF;
+    uint sampler_index = push_constants.history_sampler_binding & 0xFFFF;
+
+    ivec2 input_size = imageSize(color_images[color_index]);
+    vec2 output_size = vec2(push_constants.output_width, push_constants.output_height);
+    vec2 uv = gl_FragCoord.xy / output_size;
+
+    // Render texel i holds the scene at i + 0.5 - jitter, so this is the texel position of the output pixel
+    vec2 jitter = vec2(push_constants.jitter_x, push_constants.jitter_y);
+    vec2 input_position = uv * vec2(input_size) + jitter - 0.5;
+    ivec2 nearest_texel = ivec2(floor(input_position + 0.5));
+
+    // Gaussian reconstruction of the current frame, the 3x3 neighborhood also bounds the history
+    vec4 color_sum = vec4(0.0);
+    float weight_sum = 0.0;
+    float max_weight = 0.0;
+    vec3 color_min = vec3(1.0);
+    vec3 color_max = vec3(0.0);
+    for (int y = -1; y <= 1; y++) {
+        for (int x = -1; x <= 1; x++) {
+            ivec2 texel = nearest_texel + ivec2(x, y);
+            vec4 color = imageLoad(color_images[color_index], clamp(texel, ivec2(0), input_size - ivec2(1)));
+
+            vec2 offset = vec2(texel) - input_position;
+            float weight = exp(-2.29 * dot(offset, offset));
+            color_sum += color * weight;
+            weight_sum += weight;
+            max_weight = max(max_weight, weight);
+
+            color_min = min(color_min, color.rgb);
+            color_max = max(color_max, color.rgb);
+        }
+    }
+    vec4 current = color_sum / weight_sum;
+
+    vec2 velocity = imageLoad(velocity_images[velocity_index], clamp(nearest_texel, ivec2(0), input_size - ivec2(1))).xy;
+    vec2 history_uv = uv - (velocity - vec2(push_constants.jitter_delta_x, push_constants.jitter_delta_y));
+
+    float history_weight = push_constants.history_weight;
+    if (any(lessThan(history_uv, vec2(0.0))) || any(greaterThan(history_uv, vec2(1.0)))) {
+        history_weight = 0.0;
+    }
+
+    vec4 history = texture(sampler2D(history_textures[history_index], samplers[sampler_index]), history_uv);
+    history.rgb = clamp(history.rgb, color_min, color_max);
+
+    // Output pixels far from any render sample lean on the history more
+    float current_weight = history_weight > 0.0 ? (1.0 - history_weight) * max_weight : 1.0;
+    vec4 result = mix(history, current, current_weight);
+
+    out_frag_color = result;
+    out_history = result;
+}
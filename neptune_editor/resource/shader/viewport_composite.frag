@@ -0,0 +1,38 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout(location = 0) out vec4 out_frag_color;
+
+layout(std140, set = 0, binding = 0) readonly buffer LayoutBuffer {
+    uvec4 rects[4]; // xy: offset, zw: size
+    vec4 border_color;
+} viewport_layouts[];
+
+layout(set = 0, binding = 2) uniform texture2D viewport_textures[];
+layout(set = 0, binding = 3) uniform sampler samplers[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint layout_index;
+    uint sampler_binding;
+    uint viewport_texture_bindings[4];
+} push_constants;
+
+void main() {
+    uint sampler_index = push_constants.sampler_binding & 0xFFFF;
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+
+    // Pixels outside every viewport are the borders between them
+    vec4 color = viewport_layouts[push_constants.layout_index].border_color;
+    for (int i = 0; i < 4; i++) {
+        ivec4 rect = ivec4(viewport_layouts[push_constants.layout_index].rects[i]);
+        ivec2 local_pixel = pixel - rect.xy;
+        if (all(greaterThanEqual(local_pixel, ivec2(0))) && all(lessThan(local_pixel, rect.zw))) {
+            // Differs between pixels of the same draw, so the index has to be marked non-uniform
+            uint texture_index = push_constants.viewport_texture_bindings[i] & 0xFFFF;
+            color = texelFetch(sampler2D(viewport_textures[nonuniformEXT(texture_index)], samplers[sampler_index]), local_pixel, 0);
+        }
+    }
+
+    out_frag_color = color;
+}
@@ -0,0 +1,125 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+#extension GL_GOOGLE_include_directive : require
+
+layout(location = 0) out vec4 out_frag_color;
+
+#include <neptune/view.glsl>
+
+layout(std140, set = 0, binding = 0) readonly buffer HelperBuffer {
+    vec4 minor_line_color;
+    vec4 major_line_color;
+    vec4 grid_params;  // x: minor spacing, y: major interval, z: fade start, w: fade end
+    vec4 gizmo_params; // x: show grid, y: show gizmo, z: gizmo size in pixels
+} helper_settings[];
+
+layout(set = 0, binding = 1, rgba8) uniform readonly image2D color_images[];
+layout(set = 0, binding = 2) uniform texture2D depth_textures[];
+layout(set = 0, binding = 3) uniform sampler samplers[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint camera_index;
+    uint helper_index;
+    uint color_image_binding;
+    uint depth_texture_binding;
+    uint depth_sampler_binding;
+} push_constants;
+
+vec3 unproject(mat4 inverse_view_projection_matrix, vec2 uv, float depth) {
+    vec4 position = inverse_view_projection_matrix * vec4(uv * 2.0 - 1.0, depth, 1.0);
+    return position.xyz / position.w;
+}
+
+// Anti-aliased coverage of the lines at every whole coordinate
+vec2 line_coverage(vec2 coord, vec2 derivative) {
+    vec2 line = abs(fract(coord - 0.5) - 0.5) / derivative;
+    return 1.0 - min(line, vec2(1.0));
+}
+
+float segment_distance(vec2 point, vec2 end) {
+    float t = clamp(dot(point, end) / max(dot(end, end), 1e-6), 0.0, 1.0);
+    return length(point - (end * t));
+}
+
+void main() {
+    uint color_index = push_constants.color_image_binding & 0xFFFF;
+    uint depth_index = push_constants.depth_texture_binding & 0xFFFF;
+    uint sampler_index = push_constants.depth_sampler_binding & 0xFFFF;
+
+    mat4 view_projection_matrix = views[push_constants.camera_index].view_projection_matrix;
+    mat4 inverse_view_projection_matrix = views[push_constants.camera_index].inverse_view_projection_matrix;
+    vec3 camera_position = views[push_constants.camera_index].camera_position;
+    bool reversed_depth = views[push_constants.camera_index].reversed_depth != 0;
+    vec4 grid_params = helper_settings[push_constants.helper_index].grid_params;
+    vec4 gizmo_params = helper_settings[push_constants.helper_index].gizmo_params;
+
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    ivec2 image_size = imageSize(color_images[color_index]);
+    vec2 uv = (vec2(pixel) + 0.5) / vec2(image_size);
+    vec4 color = imageLoad(color_images[color_index], pixel);
+    float depth = texelFetch(sampler2D(depth_textures[depth_index], samplers[sampler_index]), pixel, 0).r;
+
+    // Grid on the y = 0 plane, everything is computed outside of branches so the derivatives stay valid
+    vec3 ray_origin = unproject(inverse_view_projection_matrix, uv, reversed_depth ? 1.0 : 0.0);
+    vec3 ray_direction = normalize(unproject(inverse_view_projection_matrix, uv, 0.5) - ray_origin);
+    float plane_direction = abs(ray_direction.y) > 1e-6 ? ray_direction.y : 1e-6;
+    float t = -ray_origin.y / plane_direction;
+    vec3 hit_position = ray_origin + (ray_direction * max(t, 0.0));
+
+    vec4 hit_clip = view_projection_matrix * vec4(hit_position, 1.0);
+    float grid_depth = hit_clip.z / hit_clip.w;
+    float visible = (gizmo_params.x > 0.5 && t > 0.0 && (reversed_depth ? grid_depth > depth : grid_depth < depth)) ? 1.0 : 0.0;
+    float fade = 1.0 - smoothstep(grid_params.z, grid_params.w, length(hit_position.xz - camera_position.xz));
+
+    vec2 minor_coord = hit_position.xz / grid_params.x;
+    vec2 minor_derivative = max(fwidth(minor_coord), vec2(1e-6));
+    vec2 major_coord = minor_coord / grid_params.y;
+    vec2 major_derivative = max(fwidth(major_coord), vec2(1e-6));
+
+    vec2 minor_lines = line_coverage(minor_coord, minor_derivative);
+    vec2 major_lines = line_coverage(major_coord, major_derivative);
+    vec2 axis_lines = 1.0 - min(abs(minor_coord) / minor_derivative, vec2(1.0));
+
+    vec4 minor_line_color = helper_settings[push_constants.helper_index].minor_line_color;
+    vec4 major_line_color = helper_settings[push_constants.helper_index].major_line_color;
+    float grid_alpha = visible * fade;
+    color.rgb = mix(color.rgb, minor_line_color.rgb, minor_line_color.a * max(minor_lines.x, minor_lines.y) * grid_alpha);
+    color.rgb = mix(color.rgb, major_line_color.rgb, major_line_color.a * max(major_lines.x, major_lines.y) * grid_alpha);
+    // The x axis runs along z = 0 and the z axis along x = 0
+    color.rgb = mix(color.rgb, vec3(0.9, 0.2, 0.2), axis_lines.y * grid_alpha);
+    color.rgb = mix(color.rgb, vec3(0.2, 0.3, 0.9), axis_lines.x * grid_alpha);
+
+    // Axis gizmo in the bottom left corner
+    float gizmo_size = gizmo_params.z;
+    vec2 gizmo_center = vec2(gizmo_size * 0.5, float(image_size.y) - (gizmo_size * 0.5));
+    vec2 gizmo_position = (gl_FragCoord.xy - gizmo_center) / (gizmo_size * 0.5);
+    gizmo_position.y = -gizmo_position.y;
+    if (gizmo_params.y > 0.5 && all(lessThanEqual(abs(gizmo_position), vec2(1.0)))) {
+        mat3 view_rotation = mat3(views[push_constants.camera_index].view_matrix);
+        vec3 axis_colors[3] = vec3[3](vec3(0.9, 0.2, 0.2), vec3(0.2, 0.9, 0.2), vec3(0.2, 0.3, 0.9));
+        vec3 axis_directions[3] = vec3[3](view_rotation[0], view_rotation[1], view_rotation[2]);
+
+        // Draw back to front, view space +z points towards the camera
+        int order[3] = int[3](0, 1, 2);
+        for (int i = 0; i < 2; i++) {
+            for (int j = 0; j < 2 - i; j++) {
+                if (axis_directions[order[j]].z > axis_directions[order[j + 1]].z) {
+                    int temp = order[j];
+                    order[j] = order[j + 1];
+                    order[j + 1] = temp;
+                }
+            }
+        }
+
+        float line_width = 3.0 / (gizmo_size * 0.5);
+        for (int i = 0; i < 3; i++) {
+            int axis = order[i];
+            float line_distance = segment_distance(gizmo_position, axis_directions[axis].xy * 0.8);
+            float coverage = clamp((line_width - line_distance) / line_width * 2.0, 0.0, 1.0);
+            color.rgb = mix(color.rgb, axis_colors[axis], coverage);
+        }
+    }
+
+    out_frag_color = color;
+}
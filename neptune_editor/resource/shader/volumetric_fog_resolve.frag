@@ -0,0 +1,62 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+#extension GL_GOOGLE_include_directive : require
+
+layout(location = 0) out vec4 out_frag_color;
+
+#include <neptune/view.glsl>
+
+layout(std140, set = 0, binding = 0) readonly buffer FogBuffer {
+    vec4 scattering;
+    vec4 sun_direction;
+    vec4 sun_color;
+    vec4 ambient_color;
+    vec4 volume_params;  // x: start distance, y: max distance, z: history weight, w: frame index
+} fog_settings[];
+
+layout(set = 0, binding = 2) uniform texture2D depth_textures[];
+layout(set = 0, binding = 2) uniform texture3D volume_textures[];
+layout(set = 0, binding = 3) uniform sampler samplers[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint camera_index;
+    uint fog_index;
+    uint depth_texture_binding;
+    uint volume_texture_binding;
+    uint volume_sampler_binding;
+} push_constants;
+
+float distance_to_slice(float view_distance, vec4 volume_params) {
+    return log(view_distance / volume_params.x) / log(volume_params.y / volume_params.x);
+}
+
+void main() {
+    uint depth_index = push_constants.depth_texture_binding & 0xFFFF;
+    uint volume_index = push_constants.volume_texture_binding & 0xFFFF;
+    uint sampler_index = push_constants.volume_sampler_binding & 0xFFFF;
+
+    mat4 inverse_view_projection_matrix = views[push_constants.camera_index].inverse_view_projection_matrix;
+    vec3 camera_position = views[push_constants.camera_index].camera_position;
+    vec4 volume_params = fog_settings[push_constants.fog_index].volume_params;
+
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    vec2 uv = (vec2(pixel) + 0.5) / vec2(textureSize(sampler2D(depth_textures[depth_index], samplers[sampler_index]), 0));
+    float depth = texelFetch(sampler2D(depth_textures[depth_index], samplers[sampler_index]), pixel, 0).r;
+
+    // Nothing was drawn at the far plane, so fog the full volume
+    float pixel_distance = volume_params.y;
+    float far_depth = views[push_constants.camera_index].reversed_depth != 0 ? 0.0 : 1.0;
+    if (depth != far_depth) {
+        vec4 world_position = inverse_view_projection_matrix * vec4(uv * 2.0 - 1.0, depth, 1.0);
+        pixel_distance = clamp(distance(world_position.xyz / world_position.w, camera_position), volume_params.x, volume_params.y);
+    }
+
+    // Each slice holds the integration up to its far edge, so offset by half a slice
+    float slice_count = float(textureSize(sampler3D(volume_textures[volume_index], samplers[sampler_index]), 0).z);
+    float w = distance_to_slice(pixel_distance, volume_params) - (0.5 / slice_count);
+    vec4 fog = texture(sampler3D(volume_textures[volume_index], samplers[sampler_index]), vec3(uv, w));
+
+    // In-scattering and transmittance, composited onto the color by the bilateral upsample
+    out_frag_color = fog;
+}
@@ -0,0 +1,161 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+#extension GL_GOOGLE_include_directive : require
+
+layout(location = 0) out vec4 out_frag_color;
+
+#include <neptune/view.glsl>
+
+// Matches VoxelGiData in voxel_gi.rs
+layout(std140, set = 0, binding = 0) readonly buffer VoxelGiBuffer {
+    vec4 volume;        // xyz: min corner, w: voxel size
+    vec4 sun_direction; // w: diffuse strength
+    vec4 sun_color;     // w: specular strength
+    vec4 sky_color;     // w: specular aperture
+    uvec4 params;       // x: triangle count, y: resolution, z: level count
+} gi_buffers[];
+
+layout(set = 0, binding = 1, rgba8) uniform readonly image2D color_images[];
+layout(set = 0, binding = 2) uniform texture2D depth_textures[];
+layout(set = 0, binding = 2) uniform texture3D volume_textures[];
+layout(set = 0, binding = 3) uniform sampler samplers[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint view_index;
+    uint gi_buffer_index;
+    uint color_image_binding;
+    uint depth_texture_binding;
+    uint albedo_volume_binding;
+    uint radiance_volume_bindings[4];
+    uint volume_sampler_binding;
+} push_constants;
+
+const int LEVEL_COUNT = 4;
+const int MAX_CONE_STEPS = 64;
+
+vec4 sample_level(vec3 uvw, int level) {
+    uint volume_index = push_constants.radiance_volume_bindings[level] & 0xFFFF;
+    uint sampler_index = push_constants.volume_sampler_binding & 0xFFFF;
+    return textureLod(sampler3D(volume_textures[volume_index], samplers[sampler_index]), uvw, 0.0);
+}
+
+// Blends between the two levels around `level` like a trilinear mip lookup
+vec4 sample_radiance(vec3 uvw, float level) {
+    level = clamp(level, 0.0, float(LEVEL_COUNT - 1));
+    int lower = int(floor(level));
+    int upper = min(lower + 1, LEVEL_COUNT - 1);
+    return mix(sample_level(uvw, lower), sample_level(uvw, upper), level - float(lower));
+}
+
+// Front to back compositing of the voxels along a cone, the footprint grows with distance so coarser levels are sampled further out.
+// Whatever light gets through the whole volume comes from the sky
+vec3 cone_trace(uint gi_index, vec3 origin, vec3 direction, float aperture) {
+    vec4 volume = gi_buffers[gi_index].volume;
+    float voxel_size = volume.w;
+    float extent = voxel_size * float(gi_buffers[gi_index].params.y);
+
+    vec4 accumulated = vec4(0.0);
+    float cone_distance = voxel_size;
+    for (int i = 0; i < MAX_CONE_STEPS && accumulated.a < 0.95; i++) {
+        float diameter = max(voxel_size, 2.0 * aperture * cone_distance);
+        float level = log2(diameter / voxel_size);
+        if (level >= float(LEVEL_COUNT)) {
+            break;
+        }
+
+        vec3 uvw = (origin + direction * cone_distance - volume.xyz) / extent;
+        if (any(lessThan(uvw, vec3(0.0))) || any(greaterThan(uvw, vec3(1.0)))) {
+            break;
+        }
+
+        vec4 voxel = sample_radiance(uvw, level);
+        accumulated.rgb += (1.0 - accumulated.a) * voxel.rgb;
+        accumulated.a += (1.0 - accumulated.a) * voxel.a;
+        cone_distance += diameter * 0.5;
+    }
+
+    return accumulated.rgb + (1.0 - accumulated.a) * gi_buffers[gi_index].sky_color.rgb;
+}
+
+vec3 world_position(uint view_index, ivec2 pixel, vec2 size) {
+    uint depth_index = push_constants.depth_texture_binding & 0xFFFF;
+    uint sampler_index = push_constants.volume_sampler_binding & 0xFFFF;
+    float depth = texelFetch(sampler2D(depth_textures[depth_index], samplers[sampler_index]), pixel, 0).r;
+    vec2 uv = (vec2(pixel) + 0.5) / size;
+    vec4 position = views[view_index].inverse_view_projection_matrix * vec4(uv * 2.0 - 1.0, depth, 1.0);
+    return position.xyz / position.w;
+}
+
+// Rebuilt from the depth of the neighbors, taking the side with the smaller step avoids smearing across edges
+vec3 reconstruct_normal(uint view_index, ivec2 pixel, vec3 center, vec2 size) {
+    ivec2 max_pixel = ivec2(size) - 1;
+    vec3 right = world_position(view_index, min(pixel + ivec2(1, 0), max_pixel), size) - center;
+    vec3 left = center - world_position(view_index, max(pixel - ivec2(1, 0), ivec2(0)), size);
+    vec3 up = world_position(view_index, min(pixel + ivec2(0, 1), max_pixel), size) - center;
+    vec3 down = center - world_position(view_index, max(pixel - ivec2(0, 1), ivec2(0)), size);
+    vec3 dx = dot(right, right) < dot(left, left) ? right : left;
+    vec3 dy = dot(up, up) < dot(down, down) ? up : down;
+
+    vec3 normal = normalize(cross(dx, dy));
+    vec3 to_camera = views[view_index].camera_position - center;
+    return dot(normal, to_camera) < 0.0 ? -normal : normal;
+}
+
+void main() {
+    uint view_index = push_constants.view_index & 0xFFFF;
+    uint gi_index = push_constants.gi_buffer_index & 0xFFFF;
+    uint color_index = push_constants.color_image_binding & 0xFFFF;
+    uint depth_index = push_constants.depth_texture_binding & 0xFFFF;
+    uint albedo_index = push_constants.albedo_volume_binding & 0xFFFF;
+    uint sampler_index = push_constants.volume_sampler_binding & 0xFFFF;
+
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    vec4 color = imageLoad(color_images[color_index], pixel);
+
+    // Nothing was drawn at the far plane
+    vec2 size = vec2(textureSize(sampler2D(depth_textures[depth_index], samplers[sampler_index]), 0));
+    float depth = texelFetch(sampler2D(depth_textures[depth_index], samplers[sampler_index]), pixel, 0).r;
+    float far_depth = views[view_index].reversed_depth != 0 ? 0.0 : 1.0;
+    if (depth == far_depth) {
+        out_frag_color = color;
+        return;
+    }
+
+    vec3 position = world_position(view_index, pixel, size);
+    vec4 volume = gi_buffers[gi_index].volume;
+    float extent = volume.w * float(gi_buffers[gi_index].params.y);
+    vec3 uvw = (position - volume.xyz) / extent;
+    if (any(lessThan(uvw, vec3(0.0))) || any(greaterThan(uvw, vec3(1.0)))) {
+        out_frag_color = color;
+        return;
+    }
+
+    vec3 normal = reconstruct_normal(view_index, pixel, position, size);
+    vec3 origin = position + normal * volume.w;
+
+    // Voxels are averaged with the empty space around them, dividing by the coverage gets the surface albedo back
+    vec4 albedo = textureLod(sampler3D(volume_textures[albedo_index], samplers[sampler_index]), uvw, 0.0);
+    albedo.rgb = albedo.a > 0.0 ? albedo.rgb / albedo.a : vec3(0.0);
+
+    // One cone along the normal and five around it at 60 degrees, 60 degree wide cones cover the hemisphere
+    vec3 tangent = normalize(abs(normal.y) < 0.99 ? cross(normal, vec3(0.0, 1.0, 0.0)) : cross(normal, vec3(1.0, 0.0, 0.0)));
+    vec3 bitangent = cross(normal, tangent);
+    const float DIFFUSE_APERTURE = 0.577;
+    vec3 diffuse = cone_trace(gi_index, origin, normal, DIFFUSE_APERTURE) * 0.25;
+    for (int i = 0; i < 5; i++) {
+        float angle = float(i) * (6.2831853 / 5.0);
+        vec3 side = tangent * cos(angle) + bitangent * sin(angle);
+        vec3 direction = normalize(normal * 0.5 + side * 0.8660254);
+        diffuse += cone_trace(gi_index, origin, direction, DIFFUSE_APERTURE) * 0.15;
+    }
+    diffuse *= albedo.rgb * gi_buffers[gi_index].sun_direction.w;
+
+    vec3 view_direction = normalize(position - views[view_index].camera_position);
+    vec3 reflection_direction = reflect(view_direction, normal);
+    float fresnel = 0.04 + 0.96 * pow(1.0 - clamp(dot(-view_direction, normal), 0.0, 1.0), 5.0);
+    vec3 specular = cone_trace(gi_index, origin, reflection_direction, gi_buffers[gi_index].sky_color.w)
+        * fresnel * gi_buffers[gi_index].sun_color.w;
+
+    out_frag_color = vec4(color.rgb + diffuse + specular, color.a);
+}
@@ -0,0 +1,189 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+#extension GL_GOOGLE_include_directive : require
+
+layout(location = 0) in vec3 frag_world_position;
+layout(location = 1) flat in uint frag_surface_index;
+
+layout(location = 0) out vec4 out_frag_color;
+
+#include <neptune/view.glsl>
+
+// Matches WaterSurfaceData in water.rs
+struct WaterSurface {
+    vec4 center;        // xy: center on the xz plane, z: height, w: time
+    vec4 grid;          // xy: size, z: quads per side, w: steepness
+    vec4 waves[4];      // xy: direction, z: wavelength, w: amplitude
+    vec4 shallow_color; // w: refraction strength
+    vec4 deep_color;    // w: absorption
+    vec4 sky_color;     // w: reflection strength
+    vec4 reflection;    // x: reflection distance
+};
+
+layout(std430, set = 0, binding = 0) readonly buffer WaterBuffer {
+    WaterSurface surfaces[];
+} water_buffers[];
+
+layout(set = 0, binding = 1, rgba8) uniform readonly image2D color_images[];
+layout(set = 0, binding = 2) uniform texture2D depth_textures[];
+layout(set = 0, binding = 3) uniform sampler samplers[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint view_index;
+    uint water_buffer_index;
+    uint color_image_binding;
+    uint depth_texture_binding;
+    uint depth_sampler_binding;
+} push_constants;
+
+const float GRAVITY = 9.8;
+const float PI = 3.14159265;
+
+// Deep water dispersion, longer waves travel faster
+float wave_phase(vec4 wave, vec2 position, float time) {
+    float k = 2.0 * PI / wave.z;
+    return k * (dot(wave.xy, position) - sqrt(GRAVITY / k) * time);
+}
+
+// Crest sharpness is split between the waves so the surface never loops over itself
+float wave_steepness(WaterSurface surface, vec4 wave) {
+    float k = 2.0 * PI / wave.z;
+    return surface.grid.w / (k * wave.w * 4.0);
+}
+
+vec3 gerstner_offset(WaterSurface surface, vec2 position) {
+    vec3 offset = vec3(0.0);
+    for (int i = 0; i < 4; i++) {
+        vec4 wave = surface.waves[i];
+        if (wave.z <= 0.0 || wave.w <= 0.0) {
+            continue;
+        }
+        float phase = wave_phase(wave, position, surface.center.w);
+        offset.xz += wave.xy * (wave_steepness(surface, wave) * wave.w * cos(phase));
+        offset.y += wave.w * sin(phase);
+    }
+    return offset;
+}
+
+vec3 gerstner_normal(WaterSurface surface, vec2 position) {
+    vec3 normal = vec3(0.0, 1.0, 0.0);
+    for (int i = 0; i < 4; i++) {
+        vec4 wave = surface.waves[i];
+        if (wave.z <= 0.0 || wave.w <= 0.0) {
+            continue;
+        }
+        float phase = wave_phase(wave, position, surface.center.w);
+        float k_amplitude = 2.0 * PI / wave.z * wave.w;
+        normal.xz -= wave.xy * (k_amplitude * cos(phase));
+        normal.y -= wave_steepness(surface, wave) * k_amplitude * sin(phase);
+    }
+    return normalize(normal);
+}
+
+const int REFLECTION_STEPS = 32;
+// How far behind the depth buffer a reflection ray can be and still count as a hit
+const float REFLECTION_THICKNESS = 0.5;
+
+float load_depth(ivec2 pixel) {
+    uint depth_index = push_constants.depth_texture_binding & 0xFFFF;
+    uint sampler_index = push_constants.depth_sampler_binding & 0xFFFF;
+    return texelFetch(sampler2D(depth_textures[depth_index], samplers[sampler_index]), pixel, 0).r;
+}
+
+// Distance in front of the camera, the far plane is treated as infinitely far
+float view_depth(vec2 uv, float depth) {
+    float far_depth = views[push_constants.view_index].reversed_depth != 0 ? 0.0 : 1.0;
+    if (depth == far_depth) {
+        return 1e30;
+    }
+    vec4 view_position = views[push_constants.view_index].inverse_projection_matrix * vec4(uv * 2.0 - 1.0, depth, 1.0);
+    return -view_position.z / view_position.w;
+}
+
+// Small ripples on top of the waves, too fine to displace the grid
+vec3 ripple_normal(vec2 position, float time) {
+    vec2 ripple = vec2(
+        cos(dot(position, vec2(3.1, 1.7)) * 2.0 + time * 3.0),
+        cos(dot(position, vec2(-1.3, 2.9)) * 2.5 + time * 2.6)
+    );
+    return vec3(ripple.x, 0.0, ripple.y) * 0.05;
+}
+
+// Marches the reflected ray through the depth buffer, returns the sky color if it leaves the screen or hits nothing
+vec3 screen_space_reflection(WaterSurface surface, vec3 position, vec3 direction, vec2 image_size) {
+    uint color_index = push_constants.color_image_binding & 0xFFFF;
+    float step_length = surface.reflection.x / float(REFLECTION_STEPS);
+
+    for (int i = 1; i <= REFLECTION_STEPS; i++) {
+        vec3 ray_position = position + direction * (step_length * float(i));
+        vec4 clip_position = views[push_constants.view_index].view_projection_matrix * vec4(ray_position, 1.0);
+        if (clip_position.w <= 0.0) {
+            break;
+        }
+
+        vec2 uv = clip_position.xy / clip_position.w * 0.5 + 0.5;
+        if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0)))) {
+            break;
+        }
+
+        ivec2 pixel = min(ivec2(uv * image_size), ivec2(image_size) - ivec2(1));
+        float scene_distance = view_depth(uv, load_depth(pixel));
+        float ray_distance = -(views[push_constants.view_index].view_matrix * vec4(ray_position, 1.0)).z;
+        if (ray_distance > scene_distance && ray_distance - scene_distance < REFLECTION_THICKNESS) {
+            // Fade out near the screen edges where the ray would have left the screen a few pixels later
+            vec2 edge_distance = min(uv, 1.0 - uv);
+            float edge_fade = clamp(min(edge_distance.x, edge_distance.y) * 10.0, 0.0, 1.0);
+            return mix(surface.sky_color.rgb, imageLoad(color_images[color_index], pixel).rgb, edge_fade);
+        }
+    }
+
+    return surface.sky_color.rgb;
+}
+
+void main() {
+    WaterSurface surface = water_buffers[push_constants.water_buffer_index].surfaces[frag_surface_index];
+    uint color_index = push_constants.color_image_binding & 0xFFFF;
+
+    vec2 image_size = vec2(imageSize(color_images[color_index]));
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    vec2 uv = gl_FragCoord.xy / image_size;
+
+    // Manual depth test, the depth image is sampled below so it can't be the attachment as well
+    float scene_depth = load_depth(pixel);
+    bool reversed_depth = views[push_constants.view_index].reversed_depth != 0;
+    if (reversed_depth ? gl_FragCoord.z < scene_depth : gl_FragCoord.z > scene_depth) {
+        discard;
+    }
+
+    vec3 camera_position = views[push_constants.view_index].camera_position;
+    vec3 view_vector = normalize(camera_position - frag_world_position);
+    vec3 normal = normalize(gerstner_normal(surface, frag_world_position.xz) + ripple_normal(frag_world_position.xz, surface.center.w));
+    if (dot(normal, view_vector) < 0.0) {
+        // Seen from below
+        normal = -normal;
+    }
+
+    // Refraction fades out in shallow water so the bend doesn't tear apart objects where they meet the surface
+    float water_distance = view_depth(uv, gl_FragCoord.z);
+    float thickness = max(view_depth(uv, scene_depth) - water_distance, 0.0);
+    vec2 refracted_uv = uv + normal.xz * (surface.shallow_color.w * clamp(thickness, 0.0, 1.0));
+    ivec2 refracted_pixel = clamp(ivec2(refracted_uv * image_size), ivec2(0), ivec2(image_size) - ivec2(1));
+    float refracted_distance = view_depth((vec2(refracted_pixel) + 0.5) / image_size, load_depth(refracted_pixel));
+    if (refracted_distance < water_distance) {
+        // The bent sample landed on something in front of the water, use the straight one instead
+        refracted_pixel = pixel;
+        refracted_distance = water_distance + thickness;
+    }
+    vec3 refracted = imageLoad(color_images[color_index], refracted_pixel).rgb;
+
+    float transmittance = exp(-surface.deep_color.w * max(refracted_distance - water_distance, 0.0));
+    vec3 water_color = mix(surface.deep_color.rgb, refracted * surface.shallow_color.rgb, transmittance);
+
+    vec3 reflection_direction = reflect(-view_vector, normal);
+    vec3 reflection = screen_space_reflection(surface, frag_world_position, reflection_direction, image_size);
+
+    // Schlick's approximation with water's reflectance at normal incidence
+    float fresnel = 0.02 + 0.98 * pow(1.0 - clamp(dot(normal, view_vector), 0.0, 1.0), 5.0);
+    out_frag_color = vec4(mix(water_color, reflection, fresnel * surface.sky_color.w), 1.0);
+}
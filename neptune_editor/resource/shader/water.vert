@@ -0,0 +1,101 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+#extension GL_GOOGLE_include_directive : require
+
+layout(location = 0) out vec3 frag_world_position;
+layout(location = 1) flat out uint frag_surface_index;
+
+#include <neptune/view.glsl>
+
+// Matches WaterSurfaceData in water.rs
+struct WaterSurface {
+    vec4 center;        // xy: center on the xz plane, z: height, w: time
+    vec4 grid;          // xy: size, z: quads per side, w: steepness
+    vec4 waves[4];      // xy: direction, z: wavelength, w: amplitude
+    vec4 shallow_color; // w: refraction strength
+    vec4 deep_color;    // w: absorption
+    vec4 sky_color;     // w: reflection strength
+    vec4 reflection;    // x: reflection distance
+};
+
+layout(std430, set = 0, binding = 0) readonly buffer WaterBuffer {
+    WaterSurface surfaces[];
+} water_buffers[];
+
+layout(set = 0, binding = 1, rgba8) uniform readonly image2D color_images[];
+layout(set = 0, binding = 2) uniform texture2D depth_textures[];
+layout(set = 0, binding = 3) uniform sampler samplers[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint view_index;
+    uint water_buffer_index;
+    uint color_image_binding;
+    uint depth_texture_binding;
+    uint depth_sampler_binding;
+} push_constants;
+
+const float GRAVITY = 9.8;
+const float PI = 3.14159265;
+
+// Deep water dispersion, longer waves travel faster
+float wave_phase(vec4 wave, vec2 position, float time) {
+    float k = 2.0 * PI / wave.z;
+    return k * (dot(wave.xy, position) - sqrt(GRAVITY / k) * time);
+}
+
+// Crest sharpness is split between the waves so the surface never loops over itself
+float wave_steepness(WaterSurface surface, vec4 wave) {
+    float k = 2.0 * PI / wave.z;
+    return surface.grid.w / (k * wave.w * 4.0);
+}
+
+vec3 gerstner_offset(WaterSurface surface, vec2 position) {
+    vec3 offset = vec3(0.0);
+    for (int i = 0; i < 4; i++) {
+        vec4 wave = surface.waves[i];
+        if (wave.z <= 0.0 || wave.w <= 0.0) {
+            continue;
+        }
+        float phase = wave_phase(wave, position, surface.center.w);
+        offset.xz += wave.xy * (wave_steepness(surface, wave) * wave.w * cos(phase));
+        offset.y += wave.w * sin(phase);
+    }
+    return offset;
+}
+
+vec3 gerstner_normal(WaterSurface surface, vec2 position) {
+    vec3 normal = vec3(0.0, 1.0, 0.0);
+    for (int i = 0; i < 4; i++) {
+        vec4 wave = surface.waves[i];
+        if (wave.z <= 0.0 || wave.w <= 0.0) {
+            continue;
+        }
+        float phase = wave_phase(wave, position, surface.center.w);
+        float k_amplitude = 2.0 * PI / wave.z * wave.w;
+        normal.xz -= wave.xy * (k_amplitude * cos(phase));
+        normal.y -= wave_steepness(surface, wave) * k_amplitude * sin(phase);
+    }
+    return normalize(normal);
+}
+
+const vec2 CORNERS[6] = vec2[](
+    vec2(0.0, 0.0), vec2(0.0, 1.0), vec2(1.0, 1.0),
+    vec2(0.0, 0.0), vec2(1.0, 1.0), vec2(1.0, 0.0)
+);
+
+// Grid generated from the vertex index, one instance per surface
+void main() {
+    WaterSurface surface = water_buffers[push_constants.water_buffer_index].surfaces[gl_InstanceIndex];
+
+    uint quads = uint(surface.grid.z);
+    uint quad = uint(gl_VertexIndex) / 6;
+    vec2 grid_position = (vec2(quad % quads, quad / quads) + CORNERS[gl_VertexIndex % 6]) / float(quads);
+    vec2 position = surface.center.xy + (grid_position - 0.5) * surface.grid.xy;
+
+    vec3 world_position = vec3(position.x, surface.center.z, position.y) + gerstner_offset(surface, position);
+    gl_Position = views[push_constants.view_index].view_projection_matrix * vec4(world_position, 1.0);
+
+    frag_world_position = world_position;
+    frag_surface_index = uint(gl_InstanceIndex);
+}
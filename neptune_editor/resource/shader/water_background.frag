@@ -0,0 +1,16 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout(location = 0) out vec4 out_frag_color;
+
+layout(set = 0, binding = 1, rgba8) uniform readonly image2D color_images[];
+
+layout(push_constant) uniform PushConstants
+{
+    uint color_image_binding;
+} push_constants;
+
+// Copies the scene under the water into the water pass's target
+void main() {
+    out_frag_color = imageLoad(color_images[push_constants.color_image_binding & 0xFFFF], ivec2(gl_FragCoord.xy));
+}
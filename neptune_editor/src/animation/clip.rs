@@ -0,0 +1,123 @@
+use crate::animation::Pose;
+use glam::{Quat, Vec3};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+}
+
+#[derive(Debug, Clone)]
+pub enum ChannelValues {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub joint: usize,
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>,
+    pub values: ChannelValues,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    pub name: String,
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    pub fn new(name: &str, channels: Vec<Channel>) -> Self {
+        let duration = channels
+            .iter()
+            .filter_map(|channel| channel.times.last().copied())
+            .fold(0.0, f32::max);
+        Self {
+            name: name.to_string(),
+            duration,
+            channels,
+        }
+    }
+
+    /// Writes the animated joints into the pose, joints without channels are left untouched
+    pub fn sample(&self, time: f32, looping: bool, pose: &mut Pose) {
+        let time = if looping && self.duration > 0.0 {
+            time.rem_euclid(self.duration)
+        } else {
+            time.clamp(0.0, self.duration)
+        };
+
+        for channel in self.channels.iter() {
+            let Some(joint) = pose.joints.get_mut(channel.joint) else {
+                continue;
+            };
+            if channel.times.is_empty() {
+                continue;
+            }
+
+            match &channel.values {
+                ChannelValues::Translation(values) => {
+                    joint.position = sample_keyframes(
+                        &channel.times,
+                        values,
+                        time,
+                        channel.interpolation,
+                        |a, b, t| a.lerp(b, t),
+                    );
+                }
+                ChannelValues::Rotation(values) => {
+                    joint.rotation = sample_keyframes(
+                        &channel.times,
+                        values,
+                        time,
+                        channel.interpolation,
+                        |a, b, t| a.slerp(b, t),
+                    )
+                    .normalize();
+                }
+                ChannelValues::Scale(values) => {
+                    joint.scale = sample_keyframes(
+                        &channel.times,
+                        values,
+                        time,
+                        channel.interpolation,
+                        |a, b, t| a.lerp(b, t),
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn sample_keyframes<T: Copy>(
+    times: &[f32],
+    values: &[T],
+    time: f32,
+    interpolation: Interpolation,
+    lerp: impl Fn(T, T, f32) -> T,
+) -> T {
+    let next = times.partition_point(|key_time| *key_time <= time);
+    if next == 0 {
+        return values[0];
+    }
+    if next >= times.len() {
+        return values[values.len() - 1];
+    }
+
+    let previous = next - 1;
+    match interpolation {
+        Interpolation::Step => values[previous],
+        Interpolation::Linear => {
+            let span = times[next] - times[previous];
+            let t = if span > 0.0 {
+                (time - times[previous]) / span
+            } else {
+                0.0
+            };
+            lerp(values[previous], values[next], t)
+        }
+    }
+}
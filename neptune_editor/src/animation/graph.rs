@@ -0,0 +1,505 @@
+use crate::animation::clip::AnimationClip;
+use crate::animation::ik::IkRig;
+use crate::animation::skeleton::Skeleton;
+use crate::animation::Pose;
+use crate::mesh::procedural::ProceduralMesh;
+use crate::time::Time;
+use glam::{Mat4, Vec2, Vec3};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ParameterValue {
+    Float(f32),
+    Bool(bool),
+    /// Bool that is reset once a transition consumes it
+    Trigger(bool),
+}
+
+#[derive(Debug, Clone)]
+pub enum Condition {
+    Greater(String, f32),
+    Less(String, f32),
+    IsTrue(String),
+    IsFalse(String),
+    Trigger(String),
+}
+
+/// What a state plays, blend tree children are time synced so their footsteps line up
+#[derive(Debug, Clone)]
+pub enum Motion {
+    Clip {
+        clip: Arc<AnimationClip>,
+        looping: bool,
+    },
+    /// Blends the two children around the parameter's value, children must be sorted by threshold
+    BlendTree1D {
+        parameter: String,
+        children: Vec<(f32, Motion)>,
+    },
+    /// Inverse distance weighted blend of the children closest to the parameters
+    BlendTree2D {
+        x_parameter: String,
+        y_parameter: String,
+        children: Vec<(Vec2, Motion)>,
+    },
+}
+
+impl Motion {
+    fn duration(&self, parameters: &HashMap<String, ParameterValue>) -> f32 {
+        match self {
+            Motion::Clip { clip, .. } => clip.duration,
+            Motion::BlendTree1D { .. } | Motion::BlendTree2D { .. } => self
+                .child_weights(parameters)
+                .iter()
+                .map(|(child, weight)| child.duration(parameters) * weight)
+                .sum(),
+        }
+    }
+
+    fn is_looping(&self) -> bool {
+        match self {
+            Motion::Clip { looping, .. } => *looping,
+            Motion::BlendTree1D { children, .. } => {
+                children.iter().any(|(_, child)| child.is_looping())
+            }
+            Motion::BlendTree2D { children, .. } => {
+                children.iter().any(|(_, child)| child.is_looping())
+            }
+        }
+    }
+
+    fn child_weights(&self, parameters: &HashMap<String, ParameterValue>) -> Vec<(&Motion, f32)> {
+        match self {
+            Motion::Clip { .. } => vec![(self, 1.0)],
+            Motion::BlendTree1D {
+                parameter,
+                children,
+            } => {
+                let value = get_float(parameters, parameter);
+                let next = children.partition_point(|(threshold, _)| *threshold <= value);
+                if children.is_empty() {
+                    Vec::new()
+                } else if next == 0 {
+                    vec![(&children[0].1, 1.0)]
+                } else if next == children.len() {
+                    vec![(&children[next - 1].1, 1.0)]
+                } else {
+                    let (low, low_motion) = &children[next - 1];
+                    let (high, high_motion) = &children[next];
+                    let t = (value - low) / (high - low).max(f32::EPSILON);
+                    vec![(low_motion, 1.0 - t), (high_motion, t)]
+                }
+            }
+            Motion::BlendTree2D {
+                x_parameter,
+                y_parameter,
+                children,
+            } => {
+                let point = Vec2::new(
+                    get_float(parameters, x_parameter),
+                    get_float(parameters, y_parameter),
+                );
+
+                //An exact hit would divide by zero, it gets the whole weight instead
+                if let Some((_, motion)) = children
+                    .iter()
+                    .find(|(position, _)| position.distance_squared(point) < 1e-6)
+                {
+                    return vec![(motion, 1.0)];
+                }
+
+                let weights: Vec<f32> = children
+                    .iter()
+                    .map(|(position, _)| 1.0 / position.distance_squared(point))
+                    .collect();
+                let total: f32 = weights.iter().sum();
+                children
+                    .iter()
+                    .zip(weights)
+                    .map(|((_, motion), weight)| (motion, weight / total))
+                    .collect()
+            }
+        }
+    }
+
+    fn sample(
+        &self,
+        normalized_time: f32,
+        parameters: &HashMap<String, ParameterValue>,
+        pose: &mut Pose,
+    ) {
+        match self {
+            Motion::Clip { clip, looping } => {
+                clip.sample(normalized_time * clip.duration, *looping, pose)
+            }
+            Motion::BlendTree1D { .. } | Motion::BlendTree2D { .. } => {
+                //Running total so each child can be blended in with a single lerp
+                let mut accumulated_weight = 0.0;
+                let base_pose = pose.clone();
+                let mut child_pose = pose.clone();
+                for (child, weight) in self.child_weights(parameters) {
+                    if weight <= 0.0 {
+                        continue;
+                    }
+                    accumulated_weight += weight;
+                    child_pose.joints.clone_from(&base_pose.joints);
+                    child.sample(normalized_time, parameters, &mut child_pose);
+                    pose.blend(&child_pose, weight / accumulated_weight);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationState {
+    pub name: String,
+    pub motion: Motion,
+    pub speed: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Transition {
+    /// None transitions from any state
+    pub from: Option<usize>,
+    pub to: usize,
+    /// Cross-fade time in seconds
+    pub duration: f32,
+    /// Normalized time the source state has to reach before the transition can start
+    pub exit_time: Option<f32>,
+    /// All conditions have to pass
+    pub conditions: Vec<Condition>,
+}
+
+/// Shared description of the states, transitions and parameters, played back by `AnimationPlayer`s
+#[derive(Debug, Clone, Default)]
+pub struct AnimationGraph {
+    pub states: Vec<AnimationState>,
+    pub transitions: Vec<Transition>,
+    pub parameters: HashMap<String, ParameterValue>,
+    pub entry_state: usize,
+}
+
+impl AnimationGraph {
+    /// A looping state per clip, each with a trigger of the same name that cross-fades to it from any other state
+    pub fn from_clips(clips: &[Arc<AnimationClip>], blend_time: f32) -> Self {
+        let mut graph = Self::default();
+        for clip in clips.iter() {
+            let state = graph.add_state(
+                &clip.name,
+                Motion::Clip {
+                    clip: clip.clone(),
+                    looping: true,
+                },
+            );
+            graph.add_parameter(&clip.name, ParameterValue::Trigger(false));
+            graph.add_transition(Transition {
+                from: None,
+                to: state,
+                duration: blend_time,
+                exit_time: None,
+                conditions: vec![Condition::Trigger(clip.name.clone())],
+            });
+        }
+        graph
+    }
+
+    pub fn add_state(&mut self, name: &str, motion: Motion) -> usize {
+        self.states.push(AnimationState {
+            name: name.to_string(),
+            motion,
+            speed: 1.0,
+        });
+        self.states.len() - 1
+    }
+
+    pub fn add_transition(&mut self, transition: Transition) {
+        self.transitions.push(transition);
+    }
+
+    pub fn add_parameter(&mut self, name: &str, default: ParameterValue) {
+        self.parameters.insert(name.to_string(), default);
+    }
+
+    pub fn state_index(&self, name: &str) -> Option<usize> {
+        self.states.iter().position(|state| state.name == name)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct StatePlayback {
+    state: usize,
+    normalized_time: f32,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct ActiveTransition {
+    target: StatePlayback,
+    duration: f32,
+    elapsed: f32,
+}
+
+/// Runtime instance of an animation graph, game code drives it by setting parameters
+#[derive(Clone)]
+pub struct AnimationPlayer {
+    graph: Arc<AnimationGraph>,
+    skeleton: Arc<Skeleton>,
+    parameters: HashMap<String, ParameterValue>,
+
+    current: StatePlayback,
+    transition: Option<ActiveTransition>,
+
+    ik_rig: IkRig,
+    //Pose straight out of the graph, before IK is applied
+    sampled_pose: Pose,
+    pose: Pose,
+}
+
+impl AnimationPlayer {
+    pub fn new(graph: Arc<AnimationGraph>, skeleton: Arc<Skeleton>) -> Self {
+        let parameters = graph.parameters.clone();
+        let current = StatePlayback {
+            state: graph.entry_state,
+            normalized_time: 0.0,
+        };
+        let pose = skeleton.rest_pose();
+        Self {
+            ik_rig: IkRig::default(),
+            sampled_pose: pose.clone(),
+            graph,
+            skeleton,
+            parameters,
+            current,
+            transition: None,
+            pose,
+        }
+    }
+
+    pub fn set_float(&mut self, name: &str, value: f32) {
+        self.set_parameter(name, ParameterValue::Float(value));
+    }
+
+    pub fn set_bool(&mut self, name: &str, value: bool) {
+        self.set_parameter(name, ParameterValue::Bool(value));
+    }
+
+    pub fn set_trigger(&mut self, name: &str) {
+        self.set_parameter(name, ParameterValue::Trigger(true));
+    }
+
+    pub fn get_parameter(&self, name: &str) -> Option<ParameterValue> {
+        self.parameters.get(name).copied()
+    }
+
+    fn set_parameter(&mut self, name: &str, value: ParameterValue) {
+        match self.parameters.get_mut(name) {
+            Some(parameter)
+                if std::mem::discriminant(parameter) == std::mem::discriminant(&value) =>
+            {
+                *parameter = value
+            }
+            Some(parameter) => warn!(
+                "Animation parameter {} is {:?}, can't set it to {:?}",
+                name, parameter, value
+            ),
+            None => warn!("Animation parameter {} doesn't exist", name),
+        }
+    }
+
+    pub fn graph(&self) -> &Arc<AnimationGraph> {
+        &self.graph
+    }
+
+    pub fn current_state(&self) -> &str {
+        &self.graph.states[self.current.state].name
+    }
+
+    pub fn is_transitioning(&self) -> bool {
+        self.transition.is_some()
+    }
+
+    pub fn pose(&self) -> &Pose {
+        &self.pose
+    }
+
+    pub fn skeleton(&self) -> &Arc<Skeleton> {
+        &self.skeleton
+    }
+
+    /// Joint matrices relative to the skeleton root, these are what sockets attach to
+    pub fn joint_model_matrices(&self) -> Vec<Mat4> {
+        self.skeleton.model_matrices(&self.pose)
+    }
+
+    pub fn skinning_matrices(&self) -> Vec<Mat4> {
+        self.skeleton.skinning_matrices(&self.pose)
+    }
+
+    pub fn ik_rig(&self) -> &IkRig {
+        &self.ik_rig
+    }
+
+    /// IK targets and weights can be changed at any time, they are applied on the next update
+    pub fn ik_rig_mut(&mut self) -> &mut IkRig {
+        &mut self.ik_rig
+    }
+
+    /// Moves foot placement targets onto the ground under the last sampled pose, call before `update`
+    pub fn place_feet(
+        &mut self,
+        model_matrix: &Mat4,
+        ray_cast: impl FnMut(Vec3, Vec3, f32) -> Option<Vec3>,
+    ) {
+        self.ik_rig
+            .place_feet(&self.skeleton, &self.sampled_pose, model_matrix, ray_cast);
+    }
+
+    pub fn ik_debug_mesh(&self) -> ProceduralMesh {
+        self.ik_rig.debug_mesh(&self.skeleton, &self.pose)
+    }
+
+    /// Advances by the scaled delta, takes any transition whose conditions pass, then evaluates the pose
+    pub fn update(&mut self, time: &Time) {
+        if self.graph.states.is_empty() {
+            return;
+        }
+
+        let delta_time = time.delta();
+
+        self.current = self.advance(self.current, delta_time);
+        if let Some(transition) = &mut self.transition {
+            transition.target =
+                Self::advance_state(&self.graph, &self.parameters, transition.target, delta_time);
+            transition.elapsed += delta_time;
+            if transition.elapsed >= transition.duration {
+                self.current = transition.target;
+                self.transition = None;
+            }
+        }
+
+        //Transitions out of a state in the middle of a cross-fade are only checked once it finishes
+        if self.transition.is_none() {
+            if let Some(index) = self.find_transition() {
+                self.consume_triggers(index);
+                let transition = &self.graph.transitions[index];
+                let target = StatePlayback {
+                    state: transition.to,
+                    normalized_time: 0.0,
+                };
+                if transition.duration > 0.0 {
+                    self.transition = Some(ActiveTransition {
+                        target,
+                        duration: transition.duration,
+                        elapsed: 0.0,
+                    });
+                } else {
+                    self.current = target;
+                }
+            }
+        }
+
+        self.evaluate();
+    }
+
+    fn advance(&self, playback: StatePlayback, delta_time: f32) -> StatePlayback {
+        Self::advance_state(&self.graph, &self.parameters, playback, delta_time)
+    }
+
+    fn advance_state(
+        graph: &AnimationGraph,
+        parameters: &HashMap<String, ParameterValue>,
+        mut playback: StatePlayback,
+        delta_time: f32,
+    ) -> StatePlayback {
+        let state = &graph.states[playback.state];
+        let duration = state.motion.duration(parameters);
+        if duration > 0.0 {
+            playback.normalized_time += delta_time * state.speed / duration;
+            if state.motion.is_looping() {
+                playback.normalized_time = playback.normalized_time.rem_euclid(1.0);
+            } else {
+                playback.normalized_time = playback.normalized_time.clamp(0.0, 1.0);
+            }
+        }
+        playback
+    }
+
+    fn find_transition(&self) -> Option<usize> {
+        self.graph.transitions.iter().position(|transition| {
+            let from_matches = match transition.from {
+                Some(from) => from == self.current.state,
+                None => transition.to != self.current.state,
+            };
+            let exit_time_reached = transition
+                .exit_time
+                .map(|exit_time| self.current.normalized_time >= exit_time)
+                .unwrap_or(true);
+            from_matches
+                && exit_time_reached
+                && transition
+                    .conditions
+                    .iter()
+                    .all(|condition| self.condition_passes(condition))
+        })
+    }
+
+    fn condition_passes(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Greater(name, value) => get_float(&self.parameters, name) > *value,
+            Condition::Less(name, value) => get_float(&self.parameters, name) < *value,
+            Condition::IsTrue(name) => get_bool(&self.parameters, name),
+            Condition::IsFalse(name) => !get_bool(&self.parameters, name),
+            Condition::Trigger(name) => get_bool(&self.parameters, name),
+        }
+    }
+
+    fn consume_triggers(&mut self, transition_index: usize) {
+        for condition in self.graph.transitions[transition_index].conditions.iter() {
+            if let Condition::Trigger(name) = condition {
+                if let Some(parameter) = self.parameters.get_mut(name) {
+                    *parameter = ParameterValue::Trigger(false);
+                }
+            }
+        }
+    }
+
+    fn evaluate(&mut self) {
+        let rest_pose = self.skeleton.rest_pose();
+
+        self.sampled_pose.joints.clone_from(&rest_pose.joints);
+        self.graph.states[self.current.state].motion.sample(
+            self.current.normalized_time,
+            &self.parameters,
+            &mut self.sampled_pose,
+        );
+
+        if let Some(transition) = &self.transition {
+            let mut target_pose = rest_pose;
+            self.graph.states[transition.target.state].motion.sample(
+                transition.target.normalized_time,
+                &self.parameters,
+                &mut target_pose,
+            );
+            self.sampled_pose
+                .blend(&target_pose, transition.elapsed / transition.duration);
+        }
+
+        self.pose.joints.clone_from(&self.sampled_pose.joints);
+        self.ik_rig.solve(&self.skeleton, &mut self.pose);
+    }
+}
+
+fn get_float(parameters: &HashMap<String, ParameterValue>, name: &str) -> f32 {
+    match parameters.get(name) {
+        Some(ParameterValue::Float(value)) => *value,
+        _ => 0.0,
+    }
+}
+
+fn get_bool(parameters: &HashMap<String, ParameterValue>, name: &str) -> bool {
+    match parameters.get(name) {
+        Some(ParameterValue::Bool(value)) | Some(ParameterValue::Trigger(value)) => *value,
+        _ => false,
+    }
+}
@@ -0,0 +1,242 @@
+use crate::animation::clip::AnimationClip;
+use crate::animation::graph::{AnimationGraph, Condition, Motion, ParameterValue, Transition};
+use anyhow::Context;
+use glam::Vec2;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParameterAsset {
+    Float(f32),
+    Bool(bool),
+    Trigger,
+}
+
+impl ParameterAsset {
+    fn value(&self) -> ParameterValue {
+        match self {
+            ParameterAsset::Float(value) => ParameterValue::Float(*value),
+            ParameterAsset::Bool(value) => ParameterValue::Bool(*value),
+            ParameterAsset::Trigger => ParameterValue::Trigger(false),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConditionAsset {
+    Greater(String, f32),
+    Less(String, f32),
+    IsTrue(String),
+    IsFalse(String),
+    Trigger(String),
+}
+
+impl ConditionAsset {
+    fn condition(&self) -> Condition {
+        match self {
+            ConditionAsset::Greater(name, value) => Condition::Greater(name.clone(), *value),
+            ConditionAsset::Less(name, value) => Condition::Less(name.clone(), *value),
+            ConditionAsset::IsTrue(name) => Condition::IsTrue(name.clone()),
+            ConditionAsset::IsFalse(name) => Condition::IsFalse(name.clone()),
+            ConditionAsset::Trigger(name) => Condition::Trigger(name.clone()),
+        }
+    }
+
+    fn parameter(&self) -> &str {
+        match self {
+            ConditionAsset::Greater(name, _)
+            | ConditionAsset::Less(name, _)
+            | ConditionAsset::IsTrue(name)
+            | ConditionAsset::IsFalse(name)
+            | ConditionAsset::Trigger(name) => name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlendChild1D {
+    pub threshold: f32,
+    pub motion: MotionAsset,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlendChild2D {
+    pub position: [f32; 2],
+    pub motion: MotionAsset,
+}
+
+/// Clips are referenced by the name they have in the model
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MotionAsset {
+    Clip {
+        name: String,
+        #[serde(default = "MotionAsset::default_looping")]
+        looping: bool,
+    },
+    #[serde(rename = "blend_tree_1d")]
+    BlendTree1D {
+        parameter: String,
+        children: Vec<BlendChild1D>,
+    },
+    #[serde(rename = "blend_tree_2d")]
+    BlendTree2D {
+        x_parameter: String,
+        y_parameter: String,
+        children: Vec<BlendChild2D>,
+    },
+}
+
+impl MotionAsset {
+    fn default_looping() -> bool {
+        true
+    }
+
+    fn motion(&self, clips: &[Arc<AnimationClip>]) -> anyhow::Result<Motion> {
+        Ok(match self {
+            MotionAsset::Clip { name, looping } => Motion::Clip {
+                clip: clips
+                    .iter()
+                    .find(|clip| &clip.name == name)
+                    .cloned()
+                    .with_context(|| format!("Model has no animation named {}", name))?,
+                looping: *looping,
+            },
+            MotionAsset::BlendTree1D {
+                parameter,
+                children,
+            } => {
+                let mut children = children
+                    .iter()
+                    .map(|child| Ok((child.threshold, child.motion.motion(clips)?)))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                children.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+                Motion::BlendTree1D {
+                    parameter: parameter.clone(),
+                    children,
+                }
+            }
+            MotionAsset::BlendTree2D {
+                x_parameter,
+                y_parameter,
+                children,
+            } => Motion::BlendTree2D {
+                x_parameter: x_parameter.clone(),
+                y_parameter: y_parameter.clone(),
+                children: children
+                    .iter()
+                    .map(|child| Ok((Vec2::from(child.position), child.motion.motion(clips)?)))
+                    .collect::<anyhow::Result<Vec<_>>>()?,
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StateAsset {
+    pub name: String,
+    pub motion: MotionAsset,
+    #[serde(default = "StateAsset::default_speed")]
+    pub speed: f32,
+}
+
+impl StateAsset {
+    fn default_speed() -> f32 {
+        1.0
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransitionAsset {
+    /// State name, any state when left out
+    pub from: Option<String>,
+    pub to: String,
+    #[serde(default)]
+    pub duration: f32,
+    pub exit_time: Option<f32>,
+    #[serde(default)]
+    pub conditions: Vec<ConditionAsset>,
+}
+
+/// On disk description of an animation graph, stored as toml.
+/// Only the structure is authored, the clips come from whichever model the graph is used with
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnimationGraphAsset {
+    /// First state when left out
+    pub entry_state: Option<String>,
+    #[serde(default)]
+    pub parameters: HashMap<String, ParameterAsset>,
+    pub states: Vec<StateAsset>,
+    #[serde(default)]
+    pub transitions: Vec<TransitionAsset>,
+}
+
+impl AnimationGraphAsset {
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file_content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read animation graph {}", path.display()))?;
+        toml::from_str(&file_content)
+            .with_context(|| format!("Failed to parse animation graph {}", path.display()))
+    }
+
+    pub fn create_graph(&self, clips: &[Arc<AnimationClip>]) -> anyhow::Result<AnimationGraph> {
+        let mut graph = AnimationGraph::default();
+        for (name, parameter) in self.parameters.iter() {
+            graph.add_parameter(name, parameter.value());
+        }
+
+        for state in self.states.iter() {
+            let index = graph.add_state(
+                &state.name,
+                state
+                    .motion
+                    .motion(clips)
+                    .with_context(|| format!("In state {}", state.name))?,
+            );
+            graph.states[index].speed = state.speed;
+        }
+
+        let state_index = |name: &str| {
+            graph
+                .state_index(name)
+                .with_context(|| format!("Animation graph has no state named {}", name))
+        };
+        let entry_state = self.entry_state.as_deref().map(state_index).transpose()?;
+
+        let mut transitions = Vec::with_capacity(self.transitions.len());
+        for transition in self.transitions.iter() {
+            if let Some(condition) = transition
+                .conditions
+                .iter()
+                .find(|condition| !graph.parameters.contains_key(condition.parameter()))
+            {
+                anyhow::bail!(
+                    "Transition to {} uses missing parameter {}",
+                    transition.to,
+                    condition.parameter()
+                );
+            }
+            transitions.push(Transition {
+                from: transition.from.as_deref().map(state_index).transpose()?,
+                to: state_index(&transition.to)?,
+                duration: transition.duration,
+                exit_time: transition.exit_time,
+                conditions: transition
+                    .conditions
+                    .iter()
+                    .map(ConditionAsset::condition)
+                    .collect(),
+            });
+        }
+        for transition in transitions {
+            graph.add_transition(transition);
+        }
+        graph.entry_state = entry_state.unwrap_or_default();
+        Ok(graph)
+    }
+}
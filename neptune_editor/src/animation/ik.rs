@@ -0,0 +1,429 @@
+use crate::animation::skeleton::Skeleton;
+use crate::animation::Pose;
+use crate::mesh::procedural::ProceduralMesh;
+use glam::{Mat4, Quat, Vec3, Vec4};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum IkSolver {
+    /// Analytic solve for exactly three joints (upper, lower, end), bends towards the pole if set
+    TwoBone { pole: Option<Vec3> },
+    /// Iterative solve for chains of any length
+    Fabrik { iterations: u32, tolerance: f32 },
+}
+
+/// Offsets a chain's target by the height of the ground under its end joint,
+/// relative to the character's origin which is expected to be on the floor
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FootPlacement {
+    /// How far above the animated foot the ground probe starts
+    pub probe_height: f32,
+    /// How far below the animated foot ground is still found
+    pub probe_depth: f32,
+}
+
+/// Joints from the root of the chain to the end effector, targets are in skeleton space
+#[derive(Debug, Clone)]
+pub struct IkChain {
+    pub name: String,
+    pub joints: Vec<usize>,
+    pub solver: IkSolver,
+    pub target: Vec3,
+    /// 0 keeps the animated pose, 1 fully reaches for the target
+    pub weight: f32,
+    pub foot_placement: Option<FootPlacement>,
+}
+
+/// Rotates a joint so its local `forward` axis points at the target
+#[derive(Debug, Clone)]
+pub struct LookAt {
+    pub joint: usize,
+    pub forward: Vec3,
+    pub target: Vec3,
+    pub weight: f32,
+    /// Radians, the rotation is clamped so heads don't spin around
+    pub max_angle: f32,
+}
+
+/// IK constraints applied to the sampled pose before the skinning matrices are built
+#[derive(Debug, Clone, Default)]
+pub struct IkRig {
+    pub chains: Vec<IkChain>,
+    pub look_ats: Vec<LookAt>,
+}
+
+impl IkRig {
+    /// A two bone chain with foot placement for every joint named like a foot (LeftFoot, foot.R), ending at the foot's grandparent
+    pub fn foot_placement(skeleton: &Skeleton) -> Self {
+        let is_foot = |joint: usize| skeleton.joints[joint].name.to_lowercase().contains("foot");
+        let chains = (0..skeleton.joints.len())
+            .filter(|&joint| is_foot(joint))
+            .filter_map(|foot| {
+                let knee = skeleton.joints[foot]
+                    .parent
+                    .filter(|&knee| !is_foot(knee))?;
+                let hip = skeleton.joints[knee].parent?;
+                Some(IkChain {
+                    name: skeleton.joints[foot].name.clone(),
+                    joints: vec![hip, knee, foot],
+                    solver: IkSolver::TwoBone { pole: None },
+                    target: Vec3::ZERO,
+                    weight: 1.0,
+                    foot_placement: Some(FootPlacement {
+                        probe_height: 0.5,
+                        probe_depth: 0.5,
+                    }),
+                })
+            })
+            .collect();
+        Self {
+            chains,
+            look_ats: Vec::new(),
+        }
+    }
+
+    /// Foot placement plus a look at on the head and a fabrik reach chain from each shoulder to its hand.
+    /// The look at and reach chains have no weight until they're given a target with `set_focus`
+    pub fn humanoid(skeleton: &Skeleton) -> Self {
+        let mut rig = Self::foot_placement(skeleton);
+        let name_contains =
+            |joint: usize, part: &str| skeleton.joints[joint].name.to_lowercase().contains(part);
+        //Fingers and head end joints are usually named after the joint they hang from
+        let is_first = |joint: usize, part: &str| {
+            name_contains(joint, part)
+                && !skeleton.joints[joint]
+                    .parent
+                    .is_some_and(|parent| name_contains(parent, part))
+        };
+
+        for hand in (0..skeleton.joints.len()).filter(|&joint| is_first(joint, "hand")) {
+            //Hand, forearm, upper arm and shoulder
+            let mut joints = vec![hand];
+            while joints.len() < 4 {
+                match skeleton.joints[joints[joints.len() - 1]].parent {
+                    Some(parent) => joints.push(parent),
+                    None => break,
+                }
+            }
+            if joints.len() < 3 {
+                continue;
+            }
+            joints.reverse();
+            rig.chains.push(IkChain {
+                name: skeleton.joints[hand].name.clone(),
+                joints,
+                solver: IkSolver::Fabrik {
+                    iterations: 10,
+                    tolerance: 0.001,
+                },
+                target: Vec3::ZERO,
+                weight: 0.0,
+                foot_placement: None,
+            });
+        }
+
+        //Models face +Z, the head's forward is that direction in its rest space
+        let rest_matrices = skeleton.model_matrices(&skeleton.rest_pose());
+        rig.look_ats = (0..skeleton.joints.len())
+            .filter(|&joint| is_first(joint, "head"))
+            .map(|head| LookAt {
+                joint: head,
+                forward: rest_matrices[head]
+                    .to_scale_rotation_translation()
+                    .1
+                    .inverse()
+                    * Vec3::Z,
+                target: Vec3::ZERO,
+                weight: 0.0,
+                max_angle: 70f32.to_radians(),
+            })
+            .collect();
+        rig
+    }
+
+    /// Points the look ats and every chain without foot placement at `target` in skeleton space, None releases them
+    pub fn set_focus(&mut self, target: Option<Vec3>) {
+        let weight = if target.is_some() { 1.0 } else { 0.0 };
+        let target = target.unwrap_or_default();
+        for chain in self
+            .chains
+            .iter_mut()
+            .filter(|chain| chain.foot_placement.is_none())
+        {
+            chain.target = target;
+            chain.weight = weight;
+        }
+        for look_at in self.look_ats.iter_mut() {
+            look_at.target = target;
+            look_at.weight = weight;
+        }
+    }
+
+    pub fn chain_mut(&mut self, name: &str) -> Option<&mut IkChain> {
+        self.chains.iter_mut().find(|chain| chain.name == name)
+    }
+
+    /// Updates the targets of chains with foot placement, `ray_cast(origin, direction, distance)` returns the hit point in world space
+    pub fn place_feet(
+        &mut self,
+        skeleton: &Skeleton,
+        pose: &Pose,
+        model_matrix: &Mat4,
+        mut ray_cast: impl FnMut(Vec3, Vec3, f32) -> Option<Vec3>,
+    ) {
+        let matrices = skeleton.model_matrices(pose);
+        let inverse_model_matrix = model_matrix.inverse();
+        let up = model_matrix.transform_vector3(Vec3::Y).normalize_or_zero();
+        for chain in self.chains.iter_mut() {
+            let (Some(foot_placement), Some(&end_joint)) =
+                (chain.foot_placement, chain.joints.last())
+            else {
+                continue;
+            };
+
+            let animated_position = matrices[end_joint].w_axis.truncate();
+            let world_position = model_matrix.transform_point3(animated_position);
+            let origin = world_position + up * foot_placement.probe_height;
+            let distance = foot_placement.probe_height + foot_placement.probe_depth;
+            chain.target = match ray_cast(origin, -up, distance) {
+                Some(hit) => {
+                    let ground_height = inverse_model_matrix.transform_point3(hit).y;
+                    animated_position + Vec3::Y * ground_height
+                }
+                None => animated_position,
+            };
+        }
+    }
+
+    pub fn solve(&self, skeleton: &Skeleton, pose: &mut Pose) {
+        for chain in self.chains.iter() {
+            if chain.weight <= 0.0 || chain.joints.len() < 2 {
+                continue;
+            }
+
+            let matrices = skeleton.model_matrices(pose);
+            let positions: Vec<Vec3> = chain
+                .joints
+                .iter()
+                .map(|joint| matrices[*joint].w_axis.truncate())
+                .collect();
+
+            let solved = match chain.solver {
+                IkSolver::TwoBone { pole } if positions.len() == 3 => {
+                    solve_two_bone(&positions, chain.target, pole)
+                }
+                IkSolver::TwoBone { .. } => {
+                    warn!(
+                        "IkChain {} needs 3 joints for a two bone solve, it has {}",
+                        chain.name,
+                        positions.len()
+                    );
+                    continue;
+                }
+                IkSolver::Fabrik {
+                    iterations,
+                    tolerance,
+                } => solve_fabrik(&positions, chain.target, iterations, tolerance),
+            };
+            apply_positions(skeleton, pose, &chain.joints, &solved, chain.weight);
+        }
+
+        for look_at in self.look_ats.iter() {
+            if look_at.weight <= 0.0 {
+                continue;
+            }
+
+            let matrices = skeleton.model_matrices(pose);
+            let (_, rotation, position) = matrices[look_at.joint].to_scale_rotation_translation();
+            let forward = (rotation * look_at.forward).normalize_or_zero();
+            let to_target = (look_at.target - position).normalize_or_zero();
+            if forward == Vec3::ZERO || to_target == Vec3::ZERO {
+                continue;
+            }
+
+            let delta = Quat::from_rotation_arc(forward, to_target);
+            let angle = delta.angle_between(Quat::IDENTITY);
+            let clamp = if angle > look_at.max_angle {
+                look_at.max_angle / angle
+            } else {
+                1.0
+            };
+            let delta = Quat::IDENTITY.slerp(delta, clamp * look_at.weight.min(1.0));
+            set_global_rotation(skeleton, pose, &matrices, look_at.joint, delta * rotation);
+        }
+    }
+
+    /// Chains in yellow, chain targets in red and look at targets in blue, in skeleton space
+    pub fn debug_mesh(&self, skeleton: &Skeleton, pose: &Pose) -> ProceduralMesh {
+        const JOINT_SIZE: f32 = 0.03;
+        const CHAIN_COLOR: Vec4 = Vec4::new(1.0, 0.85, 0.0, 1.0);
+        const TARGET_COLOR: Vec4 = Vec4::new(1.0, 0.1, 0.1, 1.0);
+        const LOOK_AT_COLOR: Vec4 = Vec4::new(0.1, 0.3, 1.0, 1.0);
+
+        let joint_mesh = ProceduralMesh::cube(Vec3::splat(JOINT_SIZE));
+        let bone_mesh = ProceduralMesh::cylinder(JOINT_SIZE * 0.4, 1.0, 6);
+        let matrices = skeleton.model_matrices(pose);
+
+        let mut mesh = ProceduralMesh::default();
+        for chain in self.chains.iter() {
+            let positions: Vec<Vec3> = chain
+                .joints
+                .iter()
+                .map(|joint| matrices[*joint].w_axis.truncate())
+                .collect();
+            for position in positions.iter() {
+                mesh.append(&joint_mesh, Mat4::from_translation(*position), CHAIN_COLOR);
+            }
+            for pair in positions.windows(2) {
+                let bone = pair[1] - pair[0];
+                if bone.length_squared() > 0.0 {
+                    mesh.append(
+                        &bone_mesh,
+                        Mat4::from_scale_rotation_translation(
+                            Vec3::new(1.0, bone.length(), 1.0),
+                            Quat::from_rotation_arc(Vec3::Y, bone.normalize()),
+                            (pair[0] + pair[1]) * 0.5,
+                        ),
+                        CHAIN_COLOR,
+                    );
+                }
+            }
+            mesh.append(
+                &joint_mesh,
+                Mat4::from_scale_rotation_translation(
+                    Vec3::splat(1.5),
+                    Quat::IDENTITY,
+                    chain.target,
+                ),
+                TARGET_COLOR,
+            );
+        }
+
+        for look_at in self.look_ats.iter() {
+            mesh.append(
+                &joint_mesh,
+                Mat4::from_scale_rotation_translation(
+                    Vec3::splat(1.5),
+                    Quat::IDENTITY,
+                    look_at.target,
+                ),
+                LOOK_AT_COLOR,
+            );
+        }
+        mesh
+    }
+}
+
+fn solve_two_bone(positions: &[Vec3], target: Vec3, pole: Option<Vec3>) -> Vec<Vec3> {
+    let (root, middle, end) = (positions[0], positions[1], positions[2]);
+    let upper_length = root.distance(middle);
+    let lower_length = middle.distance(end);
+
+    let to_target = target - root;
+    let direction = to_target.normalize_or_zero();
+    if direction == Vec3::ZERO {
+        return positions.to_vec();
+    }
+
+    //Keep a little slack so the knee never fully locks
+    let distance = to_target
+        .length()
+        .clamp(1e-4, (upper_length + lower_length) * 0.9999);
+
+    //Without a pole the chain keeps bending the way it's currently bent
+    let bend_hint = pole.unwrap_or(middle) - root;
+    let mut bend = bend_hint - direction * bend_hint.dot(direction);
+    if bend.length_squared() < 1e-8 {
+        bend = direction.any_orthonormal_vector();
+    }
+    let bend = bend.normalize();
+
+    let cos_root = ((upper_length * upper_length + distance * distance
+        - lower_length * lower_length)
+        / (2.0 * upper_length * distance))
+        .clamp(-1.0, 1.0);
+    let sin_root = (1.0 - cos_root * cos_root).sqrt();
+
+    vec![
+        root,
+        root + direction * (upper_length * cos_root) + bend * (upper_length * sin_root),
+        root + direction * distance,
+    ]
+}
+
+fn solve_fabrik(positions: &[Vec3], target: Vec3, iterations: u32, tolerance: f32) -> Vec<Vec3> {
+    let lengths: Vec<f32> = positions
+        .windows(2)
+        .map(|pair| pair[0].distance(pair[1]))
+        .collect();
+    let root = positions[0];
+    let mut solved = positions.to_vec();
+
+    //Out of reach, the chain points straight at the target
+    if root.distance(target) >= lengths.iter().sum::<f32>() {
+        let direction = (target - root).normalize_or_zero();
+        for i in 1..solved.len() {
+            solved[i] = solved[i - 1] + direction * lengths[i - 1];
+        }
+        return solved;
+    }
+
+    let last = solved.len() - 1;
+    for _ in 0..iterations {
+        if solved[last].distance(target) <= tolerance {
+            break;
+        }
+
+        //Backward pass from the end effector
+        solved[last] = target;
+        for i in (0..last).rev() {
+            let direction = (solved[i] - solved[i + 1]).normalize_or_zero();
+            solved[i] = solved[i + 1] + direction * lengths[i];
+        }
+
+        //Forward pass from the pinned root
+        solved[0] = root;
+        for i in 1..=last {
+            let direction = (solved[i] - solved[i - 1]).normalize_or_zero();
+            solved[i] = solved[i - 1] + direction * lengths[i - 1];
+        }
+    }
+    solved
+}
+
+/// Rotates each joint of the chain so its child lands on the solved position
+fn apply_positions(
+    skeleton: &Skeleton,
+    pose: &mut Pose,
+    joints: &[usize],
+    solved: &[Vec3],
+    weight: f32,
+) {
+    for i in 0..joints.len() - 1 {
+        let matrices = skeleton.model_matrices(pose);
+        let (_, rotation, position) = matrices[joints[i]].to_scale_rotation_translation();
+        let child_position = matrices[joints[i + 1]].w_axis.truncate();
+
+        let from = (child_position - position).normalize_or_zero();
+        let to = (solved[i + 1] - position).normalize_or_zero();
+        if from == Vec3::ZERO || to == Vec3::ZERO {
+            continue;
+        }
+
+        let delta = Quat::IDENTITY.slerp(Quat::from_rotation_arc(from, to), weight.min(1.0));
+        set_global_rotation(skeleton, pose, &matrices, joints[i], delta * rotation);
+    }
+}
+
+fn set_global_rotation(
+    skeleton: &Skeleton,
+    pose: &mut Pose,
+    matrices: &[Mat4],
+    joint: usize,
+    rotation: Quat,
+) {
+    let parent_rotation = skeleton.joints[joint]
+        .parent
+        .map(|parent| matrices[parent].to_scale_rotation_translation().1)
+        .unwrap_or(Quat::IDENTITY);
+    pose.joints[joint].rotation = (parent_rotation.inverse() * rotation).normalize();
+}
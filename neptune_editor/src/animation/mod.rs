@@ -0,0 +1,27 @@
+pub mod clip;
+pub mod graph;
+pub mod graph_asset;
+pub mod ik;
+pub mod skeleton;
+
+use crate::transform::Transform;
+
+/// Local transform of every joint in a skeleton
+#[derive(Debug, Clone)]
+pub struct Pose {
+    pub joints: Vec<Transform>,
+}
+
+impl Pose {
+    /// Blends towards `other` by `weight`, both poses must come from the same skeleton
+    pub fn blend(&mut self, other: &Pose, weight: f32) {
+        debug_assert_eq!(self.joints.len(), other.joints.len());
+        if weight <= 0.0 {
+            return;
+        }
+
+        for (joint, other_joint) in self.joints.iter_mut().zip(other.joints.iter()) {
+            *joint = joint.lerp(other_joint, weight.min(1.0));
+        }
+    }
+}
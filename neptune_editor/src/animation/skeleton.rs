@@ -0,0 +1,75 @@
+use crate::animation::Pose;
+use crate::transform::Transform;
+use glam::Mat4;
+
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub rest_transform: Transform,
+    pub inverse_bind_matrix: Mat4,
+}
+
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+
+    //Parents are always evaluated before their children
+    evaluation_order: Vec<usize>,
+}
+
+impl Skeleton {
+    pub fn new(joints: Vec<Joint>) -> Self {
+        let depth = |mut index: usize| {
+            let mut depth = 0;
+            while let Some(parent) = joints[index].parent {
+                index = parent;
+                depth += 1;
+            }
+            depth
+        };
+        let mut evaluation_order: Vec<usize> = (0..joints.len()).collect();
+        evaluation_order.sort_by_key(|index| depth(*index));
+
+        Self {
+            joints,
+            evaluation_order,
+        }
+    }
+
+    pub fn joint_index(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|joint| joint.name == name)
+    }
+
+    pub fn rest_pose(&self) -> Pose {
+        Pose {
+            joints: self
+                .joints
+                .iter()
+                .map(|joint| joint.rest_transform.clone())
+                .collect(),
+        }
+    }
+
+    /// Joint transforms relative to the skeleton's root
+    pub fn model_matrices(&self, pose: &Pose) -> Vec<Mat4> {
+        let mut matrices = vec![Mat4::IDENTITY; self.joints.len()];
+        for &index in self.evaluation_order.iter() {
+            let local = pose.joints[index].model_matrix();
+            matrices[index] = match self.joints[index].parent {
+                Some(parent) => matrices[parent] * local,
+                None => local,
+            };
+        }
+        matrices
+    }
+
+    /// Matrices for the skinning shader, moves vertices from bind space into the posed joint space
+    pub fn skinning_matrices(&self, pose: &Pose) -> Vec<Mat4> {
+        self.model_matrices(pose)
+            .iter()
+            .zip(self.joints.iter())
+            .map(|(matrix, joint)| *matrix * joint.inverse_bind_matrix)
+            .collect()
+    }
+}
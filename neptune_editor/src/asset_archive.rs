@@ -0,0 +1,261 @@
+use anyhow::Context;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Little endian layout:
+/// - Header: magic, version, index offset (u64), index size (u64), entry count, padding to 32 bytes
+/// - Chunk data, each chunk starts on a `CHUNK_ALIGNMENT` boundary
+/// - Index: per entry the path (u32 length + utf8), file size (u64), chunk count (u32), then per chunk its offset (u64), stored size (u32) and whether it's lz4 compressed (u8)
+const MAGIC: [u8; 4] = *b"NPAK";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 32;
+/// Files are split into chunks so a big file can be decompressed in pieces and a small one doesn't pay for a big block
+const CHUNK_SIZE: usize = 256 * 1024;
+const CHUNK_ALIGNMENT: u64 = 16;
+
+#[derive(Debug, Copy, Clone)]
+struct ArchiveChunk {
+    offset: u64,
+    stored_size: u32,
+    compressed: bool,
+}
+
+#[derive(Debug, Clone)]
+struct ArchiveEntry {
+    size: u64,
+    chunks: Vec<ArchiveChunk>,
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct PackStats {
+    pub files: usize,
+    pub uncompressed_size: u64,
+    pub archive_size: u64,
+}
+
+/// Read only, memory mapped archive of every file in a directory
+pub struct AssetArchive {
+    path: PathBuf,
+    mmap: memmap2::Mmap,
+    entries: HashMap<String, ArchiveEntry>,
+}
+
+impl AssetArchive {
+    pub fn open<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open archive {}", path.display()))?;
+        //Safety: the archive is never written while it's open, a modified file would at worst give garbage data
+        let mmap = unsafe { memmap2::Mmap::map(&file) }
+            .with_context(|| format!("Failed to map archive {}", path.display()))?;
+
+        let mut header = ByteReader::new(&mmap);
+        if header.bytes(MAGIC.len())? != MAGIC {
+            anyhow::bail!("{} isn't an asset archive", path.display());
+        }
+        let version = header.u32()?;
+        if version != VERSION {
+            anyhow::bail!(
+                "{} is archive version {}, expected {}",
+                path.display(),
+                version,
+                VERSION
+            );
+        }
+        let index_offset = header.u64()? as usize;
+        let index_size = header.u64()? as usize;
+        let entry_count = header.u32()?;
+
+        let index_bytes = mmap
+            .get(index_offset..(index_offset + index_size))
+            .with_context(|| format!("{} index is out of bounds", path.display()))?;
+        let mut index = ByteReader::new(index_bytes);
+        let mut entries = HashMap::with_capacity(entry_count as usize);
+        for _ in 0..entry_count {
+            let path_length = index.u32()? as usize;
+            let entry_path = std::str::from_utf8(index.bytes(path_length)?)?.to_string();
+            let size = index.u64()?;
+            let chunk_count = index.u32()?;
+            let chunks = (0..chunk_count)
+                .map(|_| {
+                    Ok(ArchiveChunk {
+                        offset: index.u64()?,
+                        stored_size: index.u32()?,
+                        compressed: index.u8()? != 0,
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            entries.insert(entry_path, ArchiveEntry { size, chunks });
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            mmap,
+            entries,
+        })
+    }
+
+    /// A file stored in one uncompressed chunk is borrowed straight from the mapping, anything else is decompressed
+    pub fn read(&self, path: &str) -> anyhow::Result<Cow<'_, [u8]>> {
+        let entry = self
+            .entries
+            .get(path)
+            .with_context(|| format!("{} isn't in archive {}", path, self.path.display()))?;
+
+        if let [chunk] = entry.chunks.as_slice() {
+            if !chunk.compressed {
+                return Ok(Cow::Borrowed(self.chunk_bytes(chunk)?));
+            }
+        }
+
+        let mut data = vec![0; entry.size as usize];
+        for (chunk, output) in entry.chunks.iter().zip(data.chunks_mut(CHUNK_SIZE)) {
+            let stored = self.chunk_bytes(chunk)?;
+            if chunk.compressed {
+                let written = lz4_flex::block::decompress_into(stored, output)
+                    .with_context(|| format!("Failed to decompress {}", path))?;
+                if written != output.len() {
+                    anyhow::bail!("{} decompressed to the wrong size", path);
+                }
+            } else {
+                output.copy_from_slice(stored);
+            }
+        }
+        Ok(Cow::Owned(data))
+    }
+
+    fn chunk_bytes(&self, chunk: &ArchiveChunk) -> anyhow::Result<&[u8]> {
+        let start = chunk.offset as usize;
+        self.mmap
+            .get(start..(start + chunk.stored_size as usize))
+            .with_context(|| format!("Chunk in {} is out of bounds", self.path.display()))
+    }
+
+    /// Packs every file under `directory` into an archive at `output`, paths are stored relative to `directory`.
+    /// Meta files are left out, guids are only needed by the editor
+    pub fn pack<P: AsRef<Path>, O: AsRef<Path>>(
+        directory: P,
+        output: O,
+    ) -> anyhow::Result<PackStats> {
+        let directory = directory.as_ref();
+        let output = output.as_ref();
+
+        let mut paths = Vec::new();
+        collect_files(directory, &mut paths)?;
+        paths.retain(|path| path.extension().map(|extension| extension == "meta") != Some(true));
+        paths.sort();
+
+        let mut stats = PackStats::default();
+        let mut data = vec![0; HEADER_SIZE];
+        let mut index = Vec::new();
+        for path in paths.iter() {
+            let relative_path = archive_path(path.strip_prefix(directory)?)
+                .with_context(|| format!("{} isn't a valid archive path", path.display()))?;
+            let file_data = std::fs::read(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+
+            index.extend_from_slice(&(relative_path.len() as u32).to_le_bytes());
+            index.extend_from_slice(relative_path.as_bytes());
+            index.extend_from_slice(&(file_data.len() as u64).to_le_bytes());
+            index.extend_from_slice(&(file_data.chunks(CHUNK_SIZE).len() as u32).to_le_bytes());
+            for chunk in file_data.chunks(CHUNK_SIZE) {
+                data.resize(align_up(data.len() as u64, CHUNK_ALIGNMENT) as usize, 0);
+                let offset = data.len() as u64;
+
+                //Already compressed files like pngs are stored as is
+                let compressed = lz4_flex::block::compress(chunk);
+                let is_compressed = compressed.len() < chunk.len();
+                let stored: &[u8] = if is_compressed { &compressed } else { chunk };
+                data.extend_from_slice(stored);
+
+                index.extend_from_slice(&offset.to_le_bytes());
+                index.extend_from_slice(&(stored.len() as u32).to_le_bytes());
+                index.push(is_compressed as u8);
+            }
+
+            stats.files += 1;
+            stats.uncompressed_size += file_data.len() as u64;
+        }
+
+        let index_offset = data.len() as u64;
+        data.extend_from_slice(&index);
+
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        header.extend_from_slice(&MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&index_offset.to_le_bytes());
+        header.extend_from_slice(&(index.len() as u64).to_le_bytes());
+        header.extend_from_slice(&(stats.files as u32).to_le_bytes());
+        data[..header.len()].copy_from_slice(&header);
+
+        if let Some(parent) = output.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::File::create(output)
+            .and_then(|mut file| file.write_all(&data))
+            .with_context(|| format!("Failed to write archive {}", output.display()))?;
+        stats.archive_size = data.len() as u64;
+        Ok(stats)
+    }
+}
+
+fn collect_files(directory: &Path, paths: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(directory)
+        .with_context(|| format!("Failed to read directory {}", directory.display()))?
+    {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, paths)?;
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Always '/' separated so an archive packed on windows works everywhere
+pub fn archive_path(path: &Path) -> Option<String> {
+    let components = path
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+    Some(components.join("/"))
+}
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    fn bytes(&mut self, length: usize) -> anyhow::Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.offset..(self.offset + length))
+            .context("Unexpected end of archive")?;
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into()?))
+    }
+}
@@ -0,0 +1,366 @@
+use crate::material_asset::MaterialAsset;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Stable id for an asset, stored in a `.meta` file next to it so it survives moves and renames
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct AssetGuid(u128);
+
+impl AssetGuid {
+    /// Random enough to never collide between machines, built from std's randomly seeded hasher so there isn't another dependency
+    pub fn generate() -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+
+        let half = || {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u128(time);
+            hasher.write_u64(count);
+            hasher.finish() as u128
+        };
+        Self((half() << 64) | half())
+    }
+}
+
+impl Display for AssetGuid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl FromStr for AssetGuid {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u128::from_str_radix(s, 16).map(Self)
+    }
+}
+
+impl Serialize for AssetGuid {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for AssetGuid {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let string = String::deserialize(deserializer)?;
+        string.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AssetType {
+    Material,
+    ShaderGraph,
+    ColorLut,
+    Model,
+    Texture,
+}
+
+impl AssetType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AssetType::Material => "material",
+            AssetType::ShaderGraph => "shader_graph",
+            AssetType::ColorLut => "lut",
+            AssetType::Model => "model",
+            AssetType::Texture => "texture",
+        }
+    }
+
+    /// Toml files are told apart by the directory they are in, everything else goes by extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "toml" => match path.parent()?.file_name()?.to_str()? {
+                "materials" => Some(AssetType::Material),
+                "shader_graphs" => Some(AssetType::ShaderGraph),
+                _ => None,
+            },
+            "cube" => Some(AssetType::ColorLut),
+            "gltf" | "glb" | "obj" => Some(AssetType::Model),
+            "png" | "jpg" | "jpeg" | "hdr" | "exr" => Some(AssetType::Texture),
+            _ => None,
+        }
+    }
+}
+
+/// Contents of an asset's `.meta` file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssetMeta {
+    pub guid: AssetGuid,
+}
+
+impl AssetMeta {
+    const EXTENSION: &'static str = "meta";
+
+    /// `model.glb` has its meta in `model.glb.meta`
+    pub fn path_for(asset_path: &Path) -> PathBuf {
+        let mut file_name = asset_path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".");
+        file_name.push(Self::EXTENSION);
+        asset_path.with_file_name(file_name)
+    }
+
+    /// Reads the asset's meta, writing a new one with a fresh guid if it doesn't have one yet
+    pub fn load_or_create(asset_path: &Path) -> anyhow::Result<Self> {
+        let meta_path = Self::path_for(asset_path);
+        if meta_path.exists() {
+            let file_content = std::fs::read_to_string(&meta_path)
+                .with_context(|| format!("Failed to read {}", meta_path.display()))?;
+            return toml::from_str(&file_content)
+                .with_context(|| format!("Failed to parse {}", meta_path.display()));
+        }
+
+        let meta = Self {
+            guid: AssetGuid::generate(),
+        };
+        std::fs::write(&meta_path, toml::to_string_pretty(&meta)?)
+            .with_context(|| format!("Failed to write {}", meta_path.display()))?;
+        info!(target: "assets", "Created {}", meta_path.display());
+        Ok(meta)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AssetRecord {
+    pub path: PathBuf,
+    pub asset_type: AssetType,
+    /// Other assets this one references, e.g. the textures and shader graph of a material
+    pub dependencies: Vec<AssetGuid>,
+}
+
+impl AssetRecord {
+    /// File name without the extension, what older assets used to reference each other by
+    pub fn name(&self) -> &str {
+        self.path
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+    }
+}
+
+/// Every asset in the watched directories by guid, along with what they depend on
+#[derive(Default)]
+pub struct AssetDatabase {
+    directories: Vec<PathBuf>,
+    assets: HashMap<AssetGuid, AssetRecord>,
+}
+
+impl AssetDatabase {
+    /// Assets are found in each directory but not its sub directories
+    pub fn new<P: AsRef<Path>>(directories: &[P]) -> Self {
+        let mut asset_database = Self {
+            directories: directories
+                .iter()
+                .map(|directory| directory.as_ref().to_path_buf())
+                .collect(),
+            ..Default::default()
+        };
+        asset_database.refresh();
+        asset_database
+    }
+
+    /// Rescans the directories, new assets are given a guid and dependencies are rebuilt
+    pub fn refresh(&mut self) {
+        self.assets.clear();
+
+        let paths: Vec<PathBuf> = self
+            .directories
+            .iter()
+            .filter_map(|directory| std::fs::read_dir(directory).ok())
+            .flat_map(|entries| entries.flatten().map(|entry| entry.path()))
+            .collect();
+        for path in paths {
+            let Some(asset_type) = AssetType::from_path(&path) else {
+                continue;
+            };
+            match AssetMeta::load_or_create(&path) {
+                Ok(meta) => self.insert(meta.guid, path, asset_type),
+                Err(err) => warn!(target: "assets", "{:#}", err),
+            }
+        }
+
+        let guids: Vec<AssetGuid> = self.assets.keys().copied().collect();
+        for guid in guids.iter() {
+            if let Err(err) = self.upgrade_references(*guid) {
+                warn!(target: "assets", "{:#}", err);
+            }
+        }
+        for guid in guids {
+            let dependencies = self.find_dependencies(guid);
+            if let Some(record) = self.assets.get_mut(&guid) {
+                record.dependencies = dependencies;
+            }
+        }
+    }
+
+    fn insert(&mut self, guid: AssetGuid, path: PathBuf, asset_type: AssetType) {
+        if let Some(existing) = self.assets.get(&guid) {
+            //Usually an asset copied along with its meta file
+            warn!(
+                target: "assets",
+                "{} has the same guid as {}, skipping it",
+                path.display(),
+                existing.path.display()
+            );
+            return;
+        }
+        self.assets.insert(
+            guid,
+            AssetRecord {
+                path,
+                asset_type,
+                dependencies: Vec::new(),
+            },
+        );
+    }
+
+    /// Materials saved before the asset database reference other assets by name, they're rewritten to use guids
+    fn upgrade_references(&self, guid: AssetGuid) -> anyhow::Result<()> {
+        let Some(record) = self.assets.get(&guid) else {
+            return Ok(());
+        };
+        if record.asset_type != AssetType::Material {
+            return Ok(());
+        }
+        let mut material = MaterialAsset::load(&record.path)?;
+        if self.reference_by_guid(&mut material) {
+            material.save(&record.path)?;
+            info!(
+                target: "assets",
+                "Updated {} to reference assets by guid",
+                record.path.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Swaps every texture and shader graph the material references by name for the asset's guid,
+    /// returns true if anything changed. Names that don't match an asset are left as they are
+    pub fn reference_by_guid(&self, material: &mut MaterialAsset) -> bool {
+        let mut replaced = false;
+        for (reference, asset_type) in material.references_mut() {
+            if reference.parse::<AssetGuid>().is_ok() {
+                continue;
+            }
+            if let Some(guid) = self.find(reference, asset_type) {
+                *reference = guid.to_string();
+                replaced = true;
+            }
+        }
+        replaced
+    }
+
+    fn find_dependencies(&self, guid: AssetGuid) -> Vec<AssetGuid> {
+        let Some(record) = self.assets.get(&guid) else {
+            return Vec::new();
+        };
+        if record.asset_type != AssetType::Material {
+            return Vec::new();
+        }
+        let mut material = match MaterialAsset::load(&record.path) {
+            Ok(material) => material,
+            Err(err) => {
+                warn!(target: "assets", "{:#}", err);
+                return Vec::new();
+            }
+        };
+
+        let mut dependencies: Vec<AssetGuid> = material
+            .references_mut()
+            .into_iter()
+            .filter_map(|(reference, asset_type)| self.resolve(reference, asset_type))
+            .collect();
+        dependencies.sort();
+        dependencies.dedup();
+        dependencies
+    }
+
+    pub fn get(&self, guid: AssetGuid) -> Option<&AssetRecord> {
+        self.assets.get(&guid)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (AssetGuid, &AssetRecord)> {
+        self.assets.iter().map(|(guid, record)| (*guid, record))
+    }
+
+    /// Looks up a guid reference from another asset, None if it isn't an asset of `asset_type`
+    pub fn resolve(&self, reference: &str, asset_type: AssetType) -> Option<AssetGuid> {
+        reference.parse().ok().filter(|guid| {
+            self.assets
+                .get(guid)
+                .is_some_and(|record| record.asset_type == asset_type)
+        })
+    }
+
+    /// Looks up an asset by file name. Only for things that have nothing but a name to go on,
+    /// like the materials in a model, references between assets are guids
+    pub fn find(&self, name: &str, asset_type: AssetType) -> Option<AssetGuid> {
+        self.assets
+            .iter()
+            .find(|(_, record)| record.asset_type == asset_type && record.name() == name)
+            .map(|(guid, _)| *guid)
+    }
+
+    /// Assets that reference `guid`, e.g. the materials using a texture
+    pub fn dependents(&self, guid: AssetGuid) -> Vec<AssetGuid> {
+        self.assets
+            .iter()
+            .filter(|(_, record)| record.dependencies.contains(&guid))
+            .map(|(guid, _)| *guid)
+            .collect()
+    }
+
+    /// Moves or renames an asset along with its meta file, its dependents reference it by guid so they don't change
+    pub fn move_asset(&mut self, guid: AssetGuid, new_path: &Path) -> anyhow::Result<()> {
+        let record = self
+            .assets
+            .get(&guid)
+            .with_context(|| format!("Asset {} doesn't exist", guid))?
+            .clone();
+        if AssetType::from_path(new_path) != Some(record.asset_type) {
+            anyhow::bail!(
+                "{} isn't a valid path for a {}",
+                new_path.display(),
+                record.asset_type.name()
+            );
+        }
+        if new_path.exists() {
+            anyhow::bail!("{} already exists", new_path.display());
+        }
+
+        if let Some(parent) = new_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(&record.path, new_path)
+            .with_context(|| format!("Failed to move {}", record.path.display()))?;
+        std::fs::rename(
+            AssetMeta::path_for(&record.path),
+            AssetMeta::path_for(new_path),
+        )
+        .with_context(|| format!("Failed to move the meta of {}", record.path.display()))?;
+        info!(
+            target: "assets",
+            "Moved {} to {}",
+            record.path.display(),
+            new_path.display()
+        );
+
+        if let Some(record) = self.assets.get_mut(&guid) {
+            record.path = new_path.to_path_buf();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,477 @@
+use crate::scene::gpu_primitives::{GpuPrimitives, ReduceOp};
+use crate::scene::sprite_renderer::{Sprite, SpriteRenderer};
+use glam::{Vec2, Vec3, Vec4};
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph::QueueType;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferReadCallback, ComputePassBuilder, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{
+    vk, BufferHandle, BufferUsage, ComputePipelineHandle, Device, ImageDescription2D, ImageHandle,
+};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BufferElement {
+    U32,
+    F32,
+}
+
+impl BufferElement {
+    fn value(&self, word: [u8; 4]) -> f32 {
+        match self {
+            BufferElement::U32 => u32::from_ne_bytes(word) as f32,
+            BufferElement::F32 => f32::from_ne_bytes(word),
+        }
+    }
+}
+
+/// A storage buffer the inspector can show. Some only exist for one frame's graph, so they are listed again every frame.
+/// Buffers need `BufferUsage::TRANSFER` to be read back
+#[derive(Debug, Clone)]
+pub struct InspectableBuffer {
+    pub name: String,
+    pub buffer: BufferHandle,
+    pub size: usize,
+    /// Every 4 bytes is read as one of these, structs just show up as runs of their fields
+    pub element: BufferElement,
+}
+
+/// Contents of a buffer as read back from the gpu, a few frames behind
+struct BufferReadback {
+    name: String,
+    element: BufferElement,
+    data: Vec<u8>,
+}
+
+impl BufferReadback {
+    fn values(&self) -> impl Iterator<Item = f32> + '_ {
+        self.data
+            .chunks_exact(4)
+            .map(|word| self.element.value([word[0], word[1], word[2], word[3]]))
+    }
+
+    /// Min and max of the finite values, the heatmap is normalized to this
+    fn range(&self) -> Option<[f32; 2]> {
+        self.values()
+            .filter(|value| value.is_finite())
+            .fold(None, |range, value| match range {
+                None => Some([value; 2]),
+                Some([min, max]) => Some([min.min(value), max.max(value)]),
+            })
+    }
+
+    fn histogram(&self, [min, max]: [f32; 2]) -> [u32; BufferInspector::HISTOGRAM_BINS] {
+        let mut bins = [0; BufferInspector::HISTOGRAM_BINS];
+        let range = (max - min).max(f32::EPSILON);
+        for value in self.values().filter(|value| value.is_finite()) {
+            let bin = (((value - min) / range) * bins.len() as f32) as usize;
+            bins[bin.min(bins.len() - 1)] += 1;
+        }
+        bins
+    }
+}
+
+/// Sum, min and max of every element of a u32 buffer, reduced on the gpu since the readback stops at `MAX_ELEMENTS`
+struct BufferTotals {
+    name: String,
+    sum: u32,
+    min: u32,
+    max: u32,
+}
+
+/// Debug view of a gpu buffer (light cluster counts, cull results, etc), drawn as a heatmap with a histogram of its values under it.
+/// There is no text rendering, so the values themselves are dumped to the log as a table
+pub struct BufferInspector {
+    pub visible: bool,
+    selected: Option<String>,
+    dump_requested: bool,
+
+    heatmap_pipeline: ComputePipelineHandle,
+    heatmap_image: Option<(ImageHandle, [u32; 2])>,
+    /// Size the selected buffer needs, the image is only recreated in `update`
+    heatmap_size: Option<[u32; 2]>,
+    readback: Arc<Mutex<Option<BufferReadback>>>,
+    totals: Arc<Mutex<Option<BufferTotals>>>,
+}
+
+impl BufferInspector {
+    const LAYER: i32 = 1050;
+    /// One element per pixel, bigger buffers only show their start
+    const MAX_ELEMENTS: usize = 256 * 256;
+    const HISTOGRAM_BINS: usize = 32;
+    const PANEL_SIZE: f32 = 256.0;
+    const HISTOGRAM_HEIGHT: f32 = 48.0;
+    const MARGIN: f32 = 8.0;
+    /// Rows logged by a dump, the rest are summarized
+    const DUMP_ROWS: usize = 64;
+
+    pub fn new(device: &mut Device) -> anyhow::Result<Self> {
+        let heatmap_pipeline = device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+            code: crate::shader::BUFFER_HEATMAP_COMP,
+            entry: "main",
+        })?;
+
+        Ok(Self {
+            visible: false,
+            selected: None,
+            dump_requested: false,
+            heatmap_pipeline,
+            heatmap_image: None,
+            heatmap_size: None,
+            readback: Arc::new(Mutex::new(None)),
+            totals: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Moves the selection `offset` places through `buffers`, wrapping around
+    pub fn select_next(&mut self, buffers: &[InspectableBuffer], offset: isize) {
+        if buffers.is_empty() {
+            return;
+        }
+        let current = self
+            .selected
+            .as_ref()
+            .and_then(|name| buffers.iter().position(|buffer| &buffer.name == name))
+            .unwrap_or_default();
+        let next = (current as isize + offset).rem_euclid(buffers.len() as isize) as usize;
+        self.select(&buffers[next]);
+    }
+
+    fn select(&mut self, buffer: &InspectableBuffer) {
+        info!(
+            "Inspecting {} ({} bytes, {:?})",
+            buffer.name, buffer.size, buffer.element
+        );
+        self.selected = Some(buffer.name.clone());
+    }
+
+    /// Logs the values of the selected buffer once the next readback is in
+    pub fn request_dump(&mut self) {
+        self.dump_requested = true;
+    }
+
+    /// Resizes the heatmap to the buffer selected last frame, the only part that needs the device
+    pub fn update(&mut self, device: &mut Device) -> anyhow::Result<()> {
+        if let Some(size) = self.heatmap_size.filter(|_| self.visible) {
+            self.resize_heatmap(device, size)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        gpu_primitives: &GpuPrimitives,
+        buffers: &[InspectableBuffer],
+        surface_size: [u32; 2],
+        sprite_renderer: &mut SpriteRenderer,
+        render_graph_builder: &mut T,
+    ) {
+        if !self.visible {
+            return;
+        }
+
+        let buffer = match self
+            .selected
+            .as_ref()
+            .and_then(|name| buffers.iter().find(|buffer| &buffer.name == name))
+        {
+            Some(buffer) => buffer,
+            None => match buffers.first() {
+                Some(buffer) => {
+                    self.select(buffer);
+                    buffer
+                }
+                None => return,
+            },
+        };
+
+        let count = (buffer.size / 4).min(Self::MAX_ELEMENTS);
+        if count == 0 {
+            return;
+        }
+        let width = (count as f32).sqrt().ceil() as u32;
+        let size = [width, (count as u32).div_ceil(width)];
+        self.heatmap_size = Some(size);
+        //A newly selected buffer shows up the frame after its heatmap is resized
+        let Some((heatmap_image, _)) = self
+            .heatmap_image
+            .filter(|(_, image_size)| *image_size == size)
+        else {
+            return;
+        };
+
+        //Until the first readback of this buffer is in there is no range to normalize to
+        let (range, histogram) = {
+            let readback = self.readback.lock().unwrap();
+            match readback
+                .as_ref()
+                .filter(|readback| readback.name == buffer.name)
+            {
+                Some(readback) => {
+                    if self.dump_requested {
+                        self.dump_requested = false;
+                        let totals = self.totals.lock().unwrap();
+                        log_table(
+                            readback,
+                            totals.as_ref().filter(|totals| totals.name == buffer.name),
+                        );
+                    }
+                    let range = readback.range().unwrap_or([0.0, 1.0]);
+                    (range, Some(readback.histogram(range)))
+                }
+                None => ([0.0, 1.0], None),
+            }
+        };
+
+        let mut heatmap_pass_builder = ComputePassBuilder::new(
+            "Buffer Heatmap Pass",
+            QueueType::Graphics,
+            self.heatmap_pipeline,
+        );
+        heatmap_pass_builder.read_buffer(buffer.buffer);
+        heatmap_pass_builder.write_storage_image(heatmap_image);
+        heatmap_pass_builder.push_constant(count as u32);
+        heatmap_pass_builder.push_constant((buffer.element == BufferElement::F32) as u32);
+        heatmap_pass_builder.push_constant(range[0].to_bits());
+        heatmap_pass_builder.push_constant(range[1].to_bits());
+        heatmap_pass_builder.dispatch_threads([size[0], size[1], 1]);
+        heatmap_pass_builder.build(render_graph_builder);
+
+        let readback = self.readback.clone();
+        let name = buffer.name.clone();
+        let element = buffer.element;
+        render_graph_builder.add_buffer_read(
+            BufferOffset {
+                buffer: buffer.buffer,
+                offset: 0,
+            },
+            count * 4,
+            BufferReadCallback::new(move |slice| {
+                *readback.lock().unwrap() = Some(BufferReadback {
+                    name: name.clone(),
+                    element,
+                    data: slice.to_vec(),
+                });
+            }),
+        );
+
+        if buffer.element == BufferElement::U32 {
+            self.write_totals_passes(gpu_primitives, buffer, render_graph_builder);
+        }
+
+        self.draw(
+            heatmap_image,
+            size,
+            histogram.as_ref(),
+            surface_size,
+            sprite_renderer,
+        );
+    }
+
+    fn write_totals_passes<T: RenderGraphBuilderTrait>(
+        &self,
+        gpu_primitives: &GpuPrimitives,
+        buffer: &InspectableBuffer,
+        render_graph_builder: &mut T,
+    ) {
+        let totals_buffer = render_graph_builder.create_transient_buffer(
+            3 * std::mem::size_of::<u32>(),
+            BufferUsage::STORAGE | BufferUsage::TRANSFER,
+            MemoryLocation::GpuOnly,
+        );
+        for (index, op) in [ReduceOp::Sum, ReduceOp::Min, ReduceOp::Max]
+            .into_iter()
+            .enumerate()
+        {
+            gpu_primitives.reduce(
+                render_graph_builder,
+                buffer.buffer,
+                (buffer.size / 4) as u32,
+                op,
+                BufferOffset {
+                    buffer: totals_buffer,
+                    offset: index * std::mem::size_of::<u32>(),
+                },
+            );
+        }
+
+        let totals = self.totals.clone();
+        let name = buffer.name.clone();
+        render_graph_builder.add_buffer_read(
+            BufferOffset {
+                buffer: totals_buffer,
+                offset: 0,
+            },
+            3 * std::mem::size_of::<u32>(),
+            BufferReadCallback::new(move |slice| {
+                let word = |index: usize| {
+                    u32::from_ne_bytes(slice[index * 4..index * 4 + 4].try_into().unwrap())
+                };
+                *totals.lock().unwrap() = Some(BufferTotals {
+                    name: name.clone(),
+                    sum: word(0),
+                    min: word(1),
+                    max: word(2),
+                });
+            }),
+        );
+    }
+
+    fn resize_heatmap(
+        &mut self,
+        device: &mut Device,
+        size: [u32; 2],
+    ) -> anyhow::Result<ImageHandle> {
+        if let Some((image, image_size)) = self.heatmap_image {
+            if image_size == size {
+                return Ok(image);
+            }
+            device.destroy_image(image);
+            self.heatmap_image = None;
+        }
+
+        let image = device.create_image(
+            "Buffer Heatmap Image",
+            &ImageDescription2D {
+                size,
+                format: vk::Format::R8G8B8A8_UNORM,
+                usage: vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+                mip_levels: 1,
+                location: MemoryLocation::GpuOnly,
+            },
+        )?;
+        self.heatmap_image = Some((image, size));
+        Ok(image)
+    }
+
+    /// Heatmap in the top right corner scaled up to the panel size, with the histogram under it
+    fn draw(
+        &self,
+        heatmap_image: ImageHandle,
+        size: [u32; 2],
+        histogram: Option<&[u32; Self::HISTOGRAM_BINS]>,
+        surface_size: [u32; 2],
+        sprite_renderer: &mut SpriteRenderer,
+    ) {
+        //Whole number scale so each element stays a crisp square
+        let scale = (Self::PANEL_SIZE / size[0].max(size[1]) as f32)
+            .floor()
+            .max(1.0);
+        let heatmap_size = Vec2::new(size[0] as f32, size[1] as f32) * scale;
+        let origin = Vec2::new(
+            surface_size[0] as f32 - Self::PANEL_SIZE - Self::MARGIN,
+            Self::MARGIN,
+        );
+
+        draw_rect(
+            sprite_renderer,
+            origin - Vec2::splat(Self::MARGIN * 0.5),
+            Vec2::new(
+                Self::PANEL_SIZE,
+                Self::PANEL_SIZE + Self::HISTOGRAM_HEIGHT + Self::MARGIN,
+            ) + Vec2::splat(Self::MARGIN),
+            Vec4::new(0.0, 0.0, 0.0, 0.6),
+            0,
+        );
+        sprite_renderer.draw(Sprite {
+            layer: Self::LAYER + 1,
+            position: (origin + heatmap_size * 0.5).extend(0.0),
+            size: heatmap_size,
+            image: Some(heatmap_image),
+            pixel_perfect: true,
+            ..Default::default()
+        });
+
+        let Some(histogram) = histogram else {
+            return;
+        };
+        let max_count = histogram.iter().copied().max().unwrap_or_default().max(1);
+        let bin_width = Self::PANEL_SIZE / histogram.len() as f32;
+        let bottom = origin.y + Self::PANEL_SIZE + Self::MARGIN + Self::HISTOGRAM_HEIGHT;
+        for (index, count) in histogram.iter().enumerate() {
+            let height = Self::HISTOGRAM_HEIGHT * (*count as f32 / max_count as f32);
+            draw_rect(
+                sprite_renderer,
+                Vec2::new(origin.x + index as f32 * bin_width, bottom - height),
+                Vec2::new((bin_width - 1.0).max(1.0), height),
+                Vec4::new(0.8, 0.8, 0.8, 1.0),
+                1,
+            );
+        }
+    }
+}
+
+fn draw_rect(
+    sprite_renderer: &mut SpriteRenderer,
+    position: Vec2,
+    size: Vec2,
+    color: Vec4,
+    layer: i32,
+) {
+    if size.x <= 0.0 || size.y <= 0.0 {
+        return;
+    }
+
+    sprite_renderer.draw(Sprite {
+        layer: BufferInspector::LAYER + layer,
+        position: Vec3::new(position.x + size.x * 0.5, position.y + size.y * 0.5, 0.0),
+        size,
+        color,
+        ..Default::default()
+    });
+}
+
+fn log_table(readback: &BufferReadback, totals: Option<&BufferTotals>) {
+    let values: Vec<f32> = readback.values().collect();
+    let range = readback.range().unwrap_or_default();
+    info!(
+        "{}: {} values, min {} max {}",
+        readback.name,
+        values.len(),
+        range[0],
+        range[1]
+    );
+    if let Some(totals) = totals {
+        //The sum wraps around like any u32 add on the gpu
+        info!(
+            "  whole buffer: sum {} min {} max {}",
+            totals.sum, totals.min, totals.max
+        );
+    }
+
+    //Eight per row keeps it readable in the console
+    for (row, chunk) in values
+        .chunks(8)
+        .take(BufferInspector::DUMP_ROWS)
+        .enumerate()
+    {
+        let columns: Vec<String> = chunk.iter().map(|value| format!("{:>10}", value)).collect();
+        info!("{:>6}: {}", row * 8, columns.join(" "));
+    }
+    if values.len() > BufferInspector::DUMP_ROWS * 8 {
+        info!(
+            "... {} more values",
+            values.len() - BufferInspector::DUMP_ROWS * 8
+        );
+    }
+
+    let histogram = readback.histogram(range);
+    let bin_size = (range[1] - range[0]) / histogram.len() as f32;
+    for (index, count) in histogram
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+    {
+        info!(
+            "  [{:>10}, {:>10}): {}",
+            range[0] + index as f32 * bin_size,
+            range[0] + (index + 1) as f32 * bin_size,
+            count
+        );
+    }
+}
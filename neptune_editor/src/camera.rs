@@ -1,4 +1,5 @@
-use glam::Mat4;
+use glam::{Mat4, Vec4};
+use neptune_vulkan::DepthMode;
 
 #[derive(Debug, Clone, Copy)]
 pub enum FieldOfView {
@@ -22,6 +23,8 @@ pub struct Camera {
     pub fov: FieldOfView,
     pub near_clip: f32,
     pub far_clip: Option<f32>,
+    /// Vertical size of the view, replaces the perspective projection when set
+    pub orthographic_height: Option<f32>,
 }
 
 impl Default for Camera {
@@ -30,23 +33,51 @@ impl Default for Camera {
             fov: FieldOfView::X(75.0),
             near_clip: 0.1,
             far_clip: Some(1000.0),
+            orthographic_height: None,
         }
     }
 }
 
 impl Camera {
+    const DEFAULT_ORTHOGRAPHIC_FAR_CLIP: f32 = 1000.0;
+
     pub fn new(fov: FieldOfView, near_clip: f32, far_clip: Option<f32>) -> Self {
         Self {
             fov,
             near_clip,
             far_clip,
+            orthographic_height: None,
         }
     }
 
+    pub fn orthographic(height: f32, near_clip: f32, far_clip: f32) -> Self {
+        Self {
+            fov: FieldOfView::Y(90.0),
+            near_clip,
+            far_clip: Some(far_clip),
+            orthographic_height: Some(height),
+        }
+    }
+
+    pub fn fov_y_rad(&self, aspect_ratio: f32) -> f32 {
+        self.fov.get_fov_y_rad(aspect_ratio)
+    }
+
     pub fn projection_matrix(&self, aspect_ratio: f32) -> Mat4 {
-        let fov_y = self.fov.get_fov_y_rad(aspect_ratio);
+        let fov_y = self.fov_y_rad(aspect_ratio);
 
-        let mut matrix = if let Some(far_clip) = self.far_clip {
+        let mut matrix = if let Some(height) = self.orthographic_height {
+            let half_height = height / 2.0;
+            let half_width = half_height * aspect_ratio;
+            Mat4::orthographic_rh(
+                -half_width,
+                half_width,
+                -half_height,
+                half_height,
+                self.near_clip,
+                self.far_clip.unwrap_or(Self::DEFAULT_ORTHOGRAPHIC_FAR_CLIP),
+            )
+        } else if let Some(far_clip) = self.far_clip {
             Mat4::perspective_rh(fov_y, aspect_ratio, self.near_clip, far_clip)
         } else {
             Mat4::perspective_infinite_rh(fov_y, aspect_ratio, self.near_clip)
@@ -54,4 +85,31 @@ impl Camera {
         matrix.y_axis.y *= -1.0;
         matrix
     }
+
+    /// `projection_matrix` with its depth range converted to `depth_mode`
+    pub fn depth_projection_matrix(&self, aspect_ratio: f32, depth_mode: DepthMode) -> Mat4 {
+        match depth_mode {
+            DepthMode::Standard => self.projection_matrix(aspect_ratio),
+            DepthMode::Reversed => reverse_depth_matrix() * self.projection_matrix(aspect_ratio),
+        }
+    }
+
+    /// Far plane used by `projection_matrix`, None for an infinite perspective projection
+    pub fn far_plane(&self) -> Option<f32> {
+        if self.orthographic_height.is_some() {
+            Some(self.far_clip.unwrap_or(Self::DEFAULT_ORTHOGRAPHIC_FAR_CLIP))
+        } else {
+            self.far_clip
+        }
+    }
+}
+
+/// Maps clip space z to w - z, so every depth d becomes 1 - d. Works for perspective, infinite and orthographic projections
+pub fn reverse_depth_matrix() -> Mat4 {
+    Mat4::from_cols(
+        Vec4::X,
+        Vec4::Y,
+        Vec4::new(0.0, 0.0, -1.0, 0.0),
+        Vec4::new(0.0, 0.0, 1.0, 1.0),
+    )
 }
@@ -0,0 +1,76 @@
+use crate::camera::Camera;
+use crate::transform::Transform;
+use glam::{Quat, Vec2};
+
+/// Orthographic camera looking down -Z at the XY plane, sized so one world unit is always `pixels_per_unit * zoom` pixels.
+/// Goes along with world space sprites, a sprite `n` texels wide should be `n / pixels_per_unit` units wide
+#[derive(Debug, Clone, Copy)]
+pub struct Camera2d {
+    pub position: Vec2,
+    pub pixels_per_unit: f32,
+    /// Whole numbers only, so every texel covers the same number of screen pixels
+    pub zoom: u32,
+    /// Snaps the camera to the screen pixel grid so sprites don't shimmer while it moves
+    pub pixel_perfect: bool,
+}
+
+impl Default for Camera2d {
+    fn default() -> Self {
+        Self {
+            position: Vec2::ZERO,
+            pixels_per_unit: 16.0,
+            zoom: 2,
+            pixel_perfect: true,
+        }
+    }
+}
+
+impl Camera2d {
+    pub const MAX_ZOOM: u32 = 8;
+    /// How far the camera sits from the plane, sprites between it and the far clip are visible
+    const DISTANCE: f32 = 100.0;
+
+    /// Screen pixels per world unit
+    pub fn scale(&self) -> f32 {
+        self.pixels_per_unit * self.zoom.max(1) as f32
+    }
+
+    pub fn zoom_in(&mut self) {
+        self.zoom = (self.zoom + 1).min(Self::MAX_ZOOM);
+    }
+
+    pub fn zoom_out(&mut self) {
+        self.zoom = self.zoom.saturating_sub(1).max(1);
+    }
+
+    /// The view height follows the window, so resizing shows more of the world instead of stretching it
+    pub fn camera(&self, view_size: [u32; 2]) -> Camera {
+        Camera::orthographic(
+            view_size[1].max(1) as f32 / self.scale(),
+            0.1,
+            Self::DISTANCE * 2.0,
+        )
+    }
+
+    pub fn transform(&self, view_size: [u32; 2]) -> Transform {
+        let position = self.view_position(view_size);
+        let mut transform = Transform::with_rotation(Quat::from_rotation_y(180.0f32.to_radians()));
+        transform.position = position.extend(Self::DISTANCE);
+        transform
+    }
+
+    /// Where the center of the view actually is, after snapping
+    fn view_position(&self, view_size: [u32; 2]) -> Vec2 {
+        if !self.pixel_perfect {
+            return self.position;
+        }
+
+        //An odd sized view has a pixel center in the middle instead of an edge, so the grid is shifted half a pixel
+        let scale = self.scale();
+        let half_pixel = Vec2::new(
+            (view_size[0] % 2) as f32 * 0.5,
+            (view_size[1] % 2) as f32 * 0.5,
+        );
+        ((self.position * scale - half_pixel).round() + half_pixel) / scale
+    }
+}
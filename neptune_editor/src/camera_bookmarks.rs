@@ -0,0 +1,102 @@
+use crate::camera::Camera;
+use crate::mesh::BoundingSphere;
+use crate::transform::Transform;
+
+/// Saved editor viewpoints that can be cycled through
+#[derive(Default)]
+pub struct CameraBookmarks {
+    bookmarks: Vec<Transform>,
+    current: Option<usize>,
+}
+
+impl CameraBookmarks {
+    pub fn add(&mut self, transform: &Transform) -> usize {
+        self.bookmarks.push(transform.clone());
+        let index = self.bookmarks.len() - 1;
+        self.current = Some(index);
+        index
+    }
+
+    /// Returns the bookmark after the last visited one, wrapping around at the end
+    pub fn next(&mut self) -> Option<(usize, &Transform)> {
+        if self.bookmarks.is_empty() {
+            return None;
+        }
+
+        let index = self
+            .current
+            .map(|index| (index + 1) % self.bookmarks.len())
+            .unwrap_or(0);
+        self.current = Some(index);
+        Some((index, &self.bookmarks[index]))
+    }
+
+    pub fn previous(&mut self) -> Option<(usize, &Transform)> {
+        if self.bookmarks.is_empty() {
+            return None;
+        }
+
+        let index = self
+            .current
+            .map(|index| (index + self.bookmarks.len() - 1) % self.bookmarks.len())
+            .unwrap_or(self.bookmarks.len() - 1);
+        self.current = Some(index);
+        Some((index, &self.bookmarks[index]))
+    }
+}
+
+/// Smoothly moves a camera from one transform to another
+pub struct CameraFlight {
+    from: Transform,
+    to: Transform,
+    elapsed: f32,
+    duration: f32,
+}
+
+impl CameraFlight {
+    pub fn new(from: &Transform, to: &Transform, duration: f32) -> Self {
+        Self {
+            from: from.clone(),
+            to: to.clone(),
+            elapsed: 0.0,
+            duration: duration.max(f32::EPSILON),
+        }
+    }
+
+    /// Advances the flight and writes the new camera transform, returns true once the target is reached
+    pub fn update(&mut self, delta_time: f32, transform: &mut Transform) -> bool {
+        self.elapsed = (self.elapsed + delta_time).min(self.duration);
+
+        //Smoothstep so the camera eases in and out
+        let t = self.elapsed / self.duration;
+        let t = t * t * (3.0 - (2.0 * t));
+
+        transform.position = self.from.position.lerp(self.to.position, t);
+        transform.rotation = self.from.rotation.slerp(self.to.rotation, t);
+        self.elapsed >= self.duration
+    }
+}
+
+/// Camera transform that keeps the current view direction and fits the bounds on screen
+pub fn frame_bounds(
+    camera: &Camera,
+    camera_transform: &Transform,
+    aspect_ratio: f32,
+    bounds: &BoundingSphere,
+) -> Transform {
+    const PADDING: f32 = 1.1;
+
+    //Fit the bounding sphere inside the smaller of the two fovs
+    let radius = bounds.radius.max(0.1) * PADDING;
+    let fov_y = camera.fov_y_rad(aspect_ratio);
+    let fov_x = ((fov_y / 2.0).tan() * aspect_ratio).atan() * 2.0;
+    let half_fov = fov_y.min(fov_x) / 2.0;
+    let distance = (radius / half_fov.sin()).max(camera.near_clip + radius);
+
+    let forward = camera_transform.rotation * glam::Vec3::Z;
+    Transform {
+        position: bounds.center - (forward * distance),
+        rotation: camera_transform.rotation,
+        scale: camera_transform.scale,
+    }
+}
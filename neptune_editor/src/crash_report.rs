@@ -0,0 +1,129 @@
+use crate::log_console;
+use neptune_vulkan::{DeviceCapabilities, FrameBreadcrumbs};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Everything the panic hook can't get to on its own, filled in as the editor runs
+#[derive(Default)]
+struct CrashContext {
+    directory: PathBuf,
+    device_info: Option<String>,
+    gpu_state: Option<String>,
+}
+
+static CONTEXT: Mutex<Option<CrashContext>> = Mutex::new(None);
+
+fn with_context(function: impl FnOnce(&mut CrashContext)) {
+    let mut context = CONTEXT.lock().unwrap_or_else(|err| err.into_inner());
+    function(context.get_or_insert_with(CrashContext::default));
+}
+
+/// Installs a panic hook that writes `crash_<time>.txt` to `directory`, the default hook still prints the panic first
+pub fn install(directory: &Path) {
+    with_context(|context| context.directory = directory.to_path_buf());
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |panic_info| {
+        default_hook(panic_info);
+
+        let message = panic_info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| panic_info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Unknown panic payload".to_string());
+        let location = panic_info
+            .location()
+            .map(|location| location.to_string())
+            .unwrap_or_default();
+
+        match write_report(&message, &location) {
+            Ok(path) => eprintln!("Crash report written to {}", path.display()),
+            Err(err) => eprintln!("Failed to write crash report: {:#}", err),
+        }
+    }));
+}
+
+pub fn set_device_info(capabilities: &DeviceCapabilities) {
+    let device_info = capabilities.to_string();
+    with_context(|context| context.device_info = Some(device_info));
+}
+
+/// Called when a submit fails, the device is likely lost by then so this is the last chance to ask how far the gpu got
+pub fn record_gpu_state(breadcrumbs: &[FrameBreadcrumbs]) {
+    let mut gpu_state = String::new();
+    for (index, frame) in breadcrumbs.iter().enumerate() {
+        let _ = writeln!(
+            gpu_state,
+            "Frame in flight {} ({} passes): last completed {:?}, current {:?}",
+            index,
+            frame.passes.len(),
+            frame.last_completed_pass(),
+            frame.current_pass(),
+        );
+    }
+    with_context(|context| context.gpu_state = Some(gpu_state));
+}
+
+fn write_report(message: &str, location: &str) -> anyhow::Result<PathBuf> {
+    const LOG_LINES: usize = 64;
+
+    let mut report = String::new();
+    writeln!(report, "Panic: {}", message)?;
+    writeln!(report, "Location: {}", location)?;
+    writeln!(
+        report,
+        "Thread: {}",
+        std::thread::current().name().unwrap_or("unnamed")
+    )?;
+
+    writeln!(report, "\n== Backtrace ==")?;
+    writeln!(report, "{}", std::backtrace::Backtrace::force_capture())?;
+
+    //try_lock since the panic could have come from inside one of these locks
+    let directory = match CONTEXT.try_lock().as_deref() {
+        Ok(Some(context)) => {
+            writeln!(report, "== Device ==")?;
+            writeln!(
+                report,
+                "{}",
+                context
+                    .device_info
+                    .as_deref()
+                    .unwrap_or("No device created")
+            )?;
+            writeln!(report, "\n== Gpu State ==")?;
+            writeln!(
+                report,
+                "{}",
+                context
+                    .gpu_state
+                    .as_deref()
+                    .unwrap_or("No failed submit recorded")
+            )?;
+            context.directory.clone()
+        }
+        _ => PathBuf::new(),
+    };
+
+    writeln!(report, "\n== Last {} Log Lines ==", LOG_LINES)?;
+    for line in log_console::recent_lines(LOG_LINES) {
+        writeln!(
+            report,
+            "{:<5} {:<6} {}",
+            line.level,
+            line.category.name(),
+            line.message
+        )?;
+    }
+
+    let time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    std::fs::create_dir_all(&directory)?;
+    let path = directory.join(format!("crash_{}.txt", time));
+    std::fs::write(&path, report)?;
+    Ok(path)
+}
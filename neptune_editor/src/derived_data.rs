@@ -0,0 +1,98 @@
+use std::path::{Path, PathBuf};
+
+/// Identifies one cached import result, a stable hash of the importer's version and everything the output depends on
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct DerivedDataKey {
+    importer: &'static str,
+    hash: u64,
+}
+
+impl DerivedDataKey {
+    /// `version` should be bumped whenever the importer's output changes so old entries are never read back
+    pub fn new(importer: &'static str, version: u32, parts: &[&[u8]]) -> Self {
+        //FNV-1a, std's hasher isn't guaranteed to be stable between builds
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let version_bytes = version.to_le_bytes();
+        for byte in std::iter::once(version_bytes.as_slice())
+            .chain(parts.iter().copied())
+            .flatten()
+        {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        Self { importer, hash }
+    }
+}
+
+/// On disk cache for the output of slow import steps, each importer gets its own sub directory.
+/// Entries are never invalidated, a changed source or importer version just hashes to a new entry
+#[derive(Debug, Clone)]
+pub struct DerivedDataCache {
+    directory: PathBuf,
+}
+
+impl DerivedDataCache {
+    pub const DEFAULT_DIRECTORY: &'static str = "neptune_editor/derived_data";
+
+    pub fn new<P: AsRef<Path>>(directory: P) -> Self {
+        Self {
+            directory: directory.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Where the entry lives, for importers that read and write their own file format
+    pub fn path(&self, key: &DerivedDataKey, extension: &str) -> PathBuf {
+        self.directory
+            .join(key.importer)
+            .join(format!("{:016x}.{}", key.hash, extension))
+    }
+
+    pub fn get(&self, key: &DerivedDataKey, extension: &str) -> Option<Vec<u8>> {
+        std::fs::read(self.path(key, extension)).ok()
+    }
+
+    /// Failing to write isn't fatal, the data just gets built again next time
+    pub fn put(&self, key: &DerivedDataKey, extension: &str, data: &[u8]) {
+        let path = self.path(key, extension);
+        if let Err(err) = self.write(&path, |temp_path| Ok(std::fs::write(temp_path, data)?)) {
+            warn!(
+                target: "assets",
+                "Failed to write derived data {}: {:#}",
+                path.display(),
+                err
+            );
+        }
+    }
+
+    /// Writes through a temporary file then renames it into place, so a crash mid write never leaves a truncated entry behind
+    pub fn write(
+        &self,
+        path: &Path,
+        write: impl FnOnce(&Path) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let temp_path = path.with_extension(format!("tmp{}", std::process::id()));
+        let result = write(&temp_path).and_then(|_| Ok(std::fs::rename(&temp_path, path)?));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temp_path);
+        }
+        result
+    }
+
+    /// Returns the cached data for `key`, `build` is only called on a miss and its output is cached
+    pub fn get_or_build(
+        &self,
+        key: &DerivedDataKey,
+        extension: &str,
+        build: impl FnOnce() -> anyhow::Result<Vec<u8>>,
+    ) -> anyhow::Result<Vec<u8>> {
+        if let Some(data) = self.get(key, extension) {
+            return Ok(data);
+        }
+        let data = build()?;
+        self.put(key, extension, &data);
+        Ok(data)
+    }
+}
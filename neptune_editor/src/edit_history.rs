@@ -0,0 +1,141 @@
+use crate::game::entity::StaticEntity;
+use crate::game::world::World;
+use crate::scene::scene_renderer::SceneInstanceHandle;
+use std::collections::VecDeque;
+
+/// A reversible change to the world's static entities
+pub enum EditAction {
+    /// Entities that were added, undone by removing them
+    Added(Vec<SceneInstanceHandle>),
+    /// Entities that were removed along with the instance they had, undone by adding them back
+    Removed(Vec<(SceneInstanceHandle, StaticEntity)>),
+}
+
+impl EditAction {
+    /// Reverts the action in `world` and returns the action that reapplies it.
+    /// Entities added back get new instances, the old to new pairs are pushed to `remapped`
+    fn revert(
+        self,
+        world: &mut World,
+        remapped: &mut Vec<(SceneInstanceHandle, SceneInstanceHandle)>,
+    ) -> EditAction {
+        match self {
+            EditAction::Added(handles) => EditAction::Removed(
+                handles
+                    .into_iter()
+                    .filter_map(|handle| {
+                        world
+                            .remove_static_entity(handle)
+                            .map(|entity| (handle, entity))
+                    })
+                    .collect(),
+            ),
+            EditAction::Removed(entities) => EditAction::Added(
+                entities
+                    .into_iter()
+                    .filter_map(|(old_handle, entity)| {
+                        let new_handle = world.add_static_entity(entity)?;
+                        remapped.push((old_handle, new_handle));
+                        Some(new_handle)
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    fn remap(&mut self, old_handle: SceneInstanceHandle, new_handle: SceneInstanceHandle) {
+        let replace = |handle: &mut SceneInstanceHandle| {
+            if *handle == old_handle {
+                *handle = new_handle;
+            }
+        };
+        match self {
+            EditAction::Added(handles) => handles.iter_mut().for_each(replace),
+            EditAction::Removed(entities) => entities
+                .iter_mut()
+                .map(|(handle, _)| handle)
+                .for_each(replace),
+        }
+    }
+
+    /// Instances the action left in the world
+    pub fn added_instances(&self) -> &[SceneInstanceHandle] {
+        match self {
+            EditAction::Added(handles) => handles,
+            EditAction::Removed(_) => &[],
+        }
+    }
+}
+
+/// Undo and redo stacks for editor changes to the world
+#[derive(Default)]
+pub struct EditHistory {
+    undo_stack: VecDeque<EditAction>,
+    redo_stack: Vec<EditAction>,
+}
+
+impl EditHistory {
+    const MAX_UNDO_DEPTH: usize = 128;
+
+    /// Records an action that was just applied, anything that could be redone is dropped
+    pub fn push(&mut self, action: EditAction) {
+        self.redo_stack.clear();
+        self.undo_stack.push_back(action);
+        if self.undo_stack.len() > Self::MAX_UNDO_DEPTH {
+            self.undo_stack.pop_front();
+        }
+    }
+
+    /// Returns the action that redoes it, None if there was nothing to undo
+    pub fn undo(&mut self, world: &mut World) -> Option<&EditAction> {
+        let action = self.undo_stack.pop_back()?;
+        let redo_action = self.revert(action, world);
+        self.redo_stack.push(redo_action);
+        self.redo_stack.last()
+    }
+
+    /// Returns the action that undoes it, None if there was nothing to redo
+    pub fn redo(&mut self, world: &mut World) -> Option<&EditAction> {
+        let action = self.redo_stack.pop()?;
+        let undo_action = self.revert(action, world);
+        self.undo_stack.push_back(undo_action);
+        self.undo_stack.back()
+    }
+
+    fn revert(&mut self, action: EditAction, world: &mut World) -> EditAction {
+        let mut remapped = Vec::new();
+        let inverse_action = action.revert(world, &mut remapped);
+
+        //Older actions still point at the instances the re-added entities used to have
+        for (old_handle, new_handle) in remapped {
+            for action in self.undo_stack.iter_mut().chain(self.redo_stack.iter_mut()) {
+                action.remap(old_handle, new_handle);
+            }
+        }
+        inverse_action
+    }
+}
+
+/// Copies of entities for pasting.
+/// There's no scene file format to serialize into yet, so this holds detached copies instead
+#[derive(Default)]
+pub struct EntityClipboard {
+    entities: Vec<StaticEntity>,
+}
+
+impl EntityClipboard {
+    pub fn set(&mut self, entities: Vec<StaticEntity>) {
+        self.entities = entities;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn copies(&self) -> Vec<StaticEntity> {
+        self.entities
+            .iter()
+            .map(StaticEntity::detached_copy)
+            .collect()
+    }
+}
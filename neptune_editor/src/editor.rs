@@ -1,22 +1,78 @@
+use crate::animation::graph::{AnimationGraph, AnimationPlayer};
+use crate::animation::graph_asset::AnimationGraphAsset;
+use crate::animation::ik::IkRig;
+use crate::asset_database::{AssetDatabase, AssetGuid, AssetType};
+use crate::buffer_inspector::BufferInspector;
 use crate::camera::{Camera, FieldOfView};
+use crate::camera_2d::Camera2d;
+use crate::camera_bookmarks::{frame_bounds, CameraBookmarks, CameraFlight};
+use crate::crash_report;
+use crate::derived_data::DerivedDataCache;
+use crate::edit_history::{EditAction, EditHistory, EntityClipboard};
+use crate::events::{AssetLoaded, CollisionEvent, EntitySpawned, EventBus, WindowResized};
+use crate::fallback;
+use crate::frame_recorder::FrameRecorder;
 use crate::game::entity::StaticEntity;
 use crate::game::player::Player;
 use crate::game::ship::{Module, ModuleType, Ship};
 use crate::game::world::{World, WorldData};
-use crate::gltf_loader::{load_gltf_resources, load_gltf_scene};
+use crate::gltf_loader::{load_gltf_resources, load_model_scene, GltfScene};
 use crate::input::{ButtonState, InputEventReceiver, StaticString};
+use crate::input_system::{InputSystem, MouseCapture};
+use crate::log_console::LogConsole;
+use crate::material::{Material, MaterialPalette, MaterialTexture};
+use crate::material_asset::MaterialAsset;
+use crate::material_editor::MaterialEditorPanel;
+use crate::mesh::batching::StaticBatching;
+use crate::mesh::dynamic::DynamicMesh;
+use crate::mesh::primitive_cache::PrimitiveCache;
+use crate::mesh::procedural::ProceduralMesh;
+use crate::mesh::BoundingBox;
+use crate::navmesh::{NavMesh, NavMeshSettings};
 use crate::physics::physics_world::{Collider, PhysicsWorld};
 use crate::platform::WindowEventReceiver;
-use crate::scene::scene_renderer::{Model, ModelPrimitive, Scene, SceneCamera, SceneRenderer};
+use crate::render_settings::{RenderSettings, RenderSettingsWatcher};
+use crate::render_thread::RenderThread;
+use crate::scene::cloth::{Cloth, ClothPinning, ClothSettings};
+use crate::scene::color_grading::ColorGrading;
+use crate::scene::foliage::{FoliageGround, FoliageLayer, FoliageScatterSettings};
+use crate::scene::lightmap_baker::LightmapBaker;
+use crate::scene::particles::ParticleBlend;
+use crate::scene::post_effects::PostEffectSettings;
+use crate::scene::post_volumes::{PostProcessVolume, PostVolumeOverrides};
+use crate::scene::reduced_resolution::ReducedResolution;
+use crate::scene::reflection_probes::{ReflectionProbe, ReflectionProbes};
+use crate::scene::render_texture::{Plane, RenderTextureDescription, RenderTextureHandle};
+use crate::scene::scene_renderer::{
+    Model, ModelPrimitive, RenderMode, Scene, SceneCamera, SceneInstanceHandle, SceneRenderer,
+};
+use crate::scene::skinning::Skinning;
+use crate::scene::spatial::Ray;
+use crate::scene::sprite_renderer::SpriteRenderer;
+use crate::scene::ui_compositor::UiColorSpace;
+use crate::scene::upscaler::UpscalerMode;
+use crate::scene::visibility::VisibilityCell;
+use crate::scene::water::WaterSurface;
+use crate::scene_loader::{LoadProgress, SceneLoader};
+use crate::search::{SearchBox, SearchItem, SearchTarget};
+use crate::shader_graph::compiler::ShaderGraphCompiler;
+use crate::shader_graph::graph::ShaderGraph;
+use crate::stats_overlay::{draw_loading_screen, draw_progress_bar, StatsOverlay};
+use crate::texture_cache::{TextureCache, TextureImportSettings};
+use crate::texture_preview::TexturePreview;
+use crate::time::Time;
 use crate::transform::Transform;
+use crate::vfs::Vfs;
+use crate::viewport::{SplitView, ViewportLayout, Viewports};
 use anyhow::Context;
-use glam::Vec3;
+use glam::{Vec2, Vec3};
 use neptune_vulkan::gpu_allocator::MemoryLocation;
 use neptune_vulkan::render_graph_builder::{
     BufferOffset, BufferReadCallback, BufferWriteCallback, RenderGraphBuilderTrait,
 };
-use neptune_vulkan::{vk, BufferUsage, DeviceSettings};
+use neptune_vulkan::{vk, BufferUsage, DepthMode, DeviceSettings};
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
+use std::collections::HashSet;
 use std::sync::Arc;
 
 #[derive(clap::Parser)]
@@ -24,19 +80,132 @@ use std::sync::Arc;
 pub struct EditorConfig {
     #[arg(short, long)]
     pub fullscreen: bool,
+
+    /// Extra model (gltf, glb or obj) to place at the origin of the test world
+    #[arg(short, long)]
+    pub model: Option<std::path::PathBuf>,
+
+    /// Merge the --model's static meshes that share a material into a few large batches when loading it
+    #[arg(long)]
+    pub batch_static: bool,
+
+    /// Bake lightmaps on the gpu for the --model's static batches once it's loaded
+    #[arg(long, requires = "batch_static")]
+    pub bake_lightmaps: bool,
+
+    /// Megabytes of mesh and texture data uploaded per frame while the --model streams in
+    #[arg(long, default_value_t = 16.0)]
+    pub upload_budget: f32,
+
+    /// Render with reversed depth, for better depth precision far from the camera
+    #[arg(long)]
+    pub reversed_depth: bool,
+
+    /// Present with a 10-bit swapchain if the surface supports it, falls back to 8-bit otherwise
+    #[arg(long)]
+    pub ten_bit_output: bool,
+
+    /// Render the scenes listed in this golden test manifest headlessly, compare them to their golden images and exit
+    #[arg(long)]
+    pub golden_test: Option<std::path::PathBuf>,
+
+    /// Record every input event and frame time to this file, written on exit
+    #[arg(long, conflicts_with = "play_input")]
+    pub record_input: Option<std::path::PathBuf>,
+
+    /// Replace live input with a recording made with --record-input, exits once it's finished
+    #[arg(long)]
+    pub play_input: Option<std::path::PathBuf>,
+
+    /// With --golden-test, overwrite the golden images with the new renders instead of comparing
+    #[arg(long)]
+    pub update_golden: bool,
+
+    /// Unaccelerated mouse motion for the fly camera, where the platform supports it
+    #[arg(long)]
+    pub raw_mouse_input: bool,
+
+    /// Pack the resource directory into an asset archive at this path and exit
+    #[arg(long)]
+    pub pack: Option<std::path::PathBuf>,
+
+    /// Print the capabilities of every Vulkan device and exit, paste this into bug reports
+    #[arg(long)]
+    pub gpu_info: bool,
+
+    /// Load resources from an archive made with --pack instead of the loose files
+    #[arg(long)]
+    pub asset_archive: Option<std::path::PathBuf>,
+
+    /// Log the output of debugPrintfEXT calls in shaders, needs the validation layer
+    #[arg(long)]
+    pub shader_printf: bool,
+
+    /// Validate descriptor and buffer access inside shaders, overridden by --shader-printf
+    #[arg(long)]
+    pub gpu_validation: bool,
+
+    /// Validate barriers and other synchronization between commands
+    #[arg(long)]
+    pub sync_validation: bool,
+
+    /// Warn about valid but slow api usage
+    #[arg(long)]
+    pub best_practices: bool,
+
+    /// Out of bounds shader access returns zero instead of losing the device, always on in debug builds
+    #[arg(long)]
+    pub robust_access: bool,
+
+    /// Move long lived gpu buffers out of fragmented memory during frames with no uploads
+    #[arg(long)]
+    pub defragment: bool,
+
+    /// Point freed bindless slots at a magenta error image and log shaders sampling stale bindings
+    #[arg(long)]
+    pub validate_bindings: bool,
+
+    /// Frame rate of recordings started with Ctrl+R
+    #[arg(long, default_value_t = 30)]
+    pub record_fps: u32,
+
+    /// Encode recordings to an mp4 with ffmpeg instead of writing a png sequence
+    #[arg(long)]
+    pub record_video: bool,
+
+    /// Frame rate cap while the window doesn't have focus, 0 to leave it uncapped
+    #[arg(long, default_value_t = 10)]
+    pub unfocused_fps: u32,
+}
+
+impl EditorConfig {
+    fn static_batching(&self) -> StaticBatching {
+        match (self.batch_static, self.bake_lightmaps) {
+            (false, _) => StaticBatching::Off,
+            (true, false) => StaticBatching::Batched,
+            (true, true) => StaticBatching::Lightmapped,
+        }
+    }
 }
 
 pub struct Editor {
     instance: neptune_vulkan::Instance,
     surface_handle: neptune_vulkan::SurfaceHandle,
     surface_size: [u32; 2],
+    surface_format: vk::SurfaceFormatKHR,
+    present_mode: vk::PresentModeKHR,
 
-    device: neptune_vulkan::Device,
+    render_thread: RenderThread,
     scene_renderer: SceneRenderer,
+    sprite_renderer: SpriteRenderer,
+    input_system: InputSystem,
+    viewports: Viewports,
 
     camera: Camera,
     camera_transform: Transform,
     scene_camera: SceneCamera,
+    /// Replaces the editor camera while set
+    camera_2d: Option<Camera2d>,
 
     world: World,
 
@@ -45,10 +214,64 @@ pub struct Editor {
 
     camera_rotate_speed: Vec3,
     camera_rotate_input: Vec3,
+
+    camera_bookmarks: CameraBookmarks,
+    camera_flight: Option<CameraFlight>,
+
+    material_editor: Option<MaterialEditorPanel>,
+    shader_graph_compiler: ShaderGraphCompiler,
+
+    navmesh_debug_instance: Option<SceneInstanceHandle>,
+    cell_debug_instance: Option<SceneInstanceHandle>,
+    ik_debug: Option<IkDebugView>,
+    scene_loader: Option<SceneLoader>,
+    /// Shares identical meshes between loaded models
+    primitive_cache: PrimitiveCache,
+    /// Keeps the loading screen up until the loaded scene's pipelines are built
+    precompiling_loaded_scene: bool,
+    cloth_sample: Option<ClothSample>,
+    mirror_sample: Option<MirrorSample>,
+    stats_overlay: StatsOverlay,
+    buffer_inspector: BufferInspector,
+    texture_preview: TexturePreview,
+    frame_recorder: FrameRecorder,
+    log_console: LogConsole,
+    render_settings_watcher: RenderSettingsWatcher,
+
+    edit_history: EditHistory,
+    clipboard: EntityClipboard,
+    next_shape: usize,
+    search_box: SearchBox,
+    asset_database: AssetDatabase,
 }
 
 impl Editor {
     const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+    const PICK_DISTANCE: f32 = 1000.0;
+    /// Yaw step of `rotate_selection`, in degrees
+    const ROTATE_SNAP: f32 = 15.0;
+    const CAMERA_FLIGHT_DURATION: f32 = 0.5;
+    /// Screen pixels per second
+    const CAMERA_2D_PAN_SPEED: f32 = 400.0;
+    /// Plus the main view, four fills the 2x2 grid
+    const MAX_SPLIT_VIEWS: usize = 3;
+    /// Game hours per second, a full day in 48 seconds
+    const DAY_CYCLE_SPEED: f32 = 0.5;
+    const MATERIAL_DIRECTORY: &'static str = "neptune_editor/resource/materials";
+    /// Graphs are matched to models by file name, `Fox.glb` uses `Fox.toml`
+    const ANIMATION_GRAPH_DIRECTORY: &'static str = "neptune_editor/resource/animation_graphs";
+    const SHADER_GRAPH_DIRECTORY: &'static str = "neptune_editor/resource/shader_graphs";
+    const COLOR_LUT_DIRECTORY: &'static str = "neptune_editor/resource/luts";
+    const POST_EFFECTS_PATH: &'static str = "neptune_editor/resource/post_effects.toml";
+    const RESOURCE_DIRECTORY: &'static str = "neptune_editor/resource";
+    const RENDER_SETTINGS_PATH: &'static str = "neptune_editor/resource/render_settings.toml";
+    const CLOTH_SAMPLE_RESOLUTION: [u32; 2] = [32, 32];
+    const FOLIAGE_SAMPLE_CAPACITY: usize = 65536;
+    const FOLIAGE_SCATTER_SIZE: f32 = 40.0;
+    const FOLIAGE_BRUSH_RADIUS: f32 = 2.0;
+    const FOLIAGE_BRUSH_COUNT: usize = 32;
+    const MIRROR_SAMPLE_SIZE: [u32; 2] = [512, 512];
+    const MIRROR_SAMPLE_RECURSION_DEPTH: usize = 2;
 
     pub fn new<W: HasRawDisplayHandle + HasRawWindowHandle>(
         window: &W,
@@ -60,6 +283,12 @@ impl Editor {
         let mut instance = neptune_vulkan::Instance::new(
             &neptune_vulkan::AppInfo::new("Neptune Engine", [0, 0, 1, 0]),
             &neptune_vulkan::AppInfo::new(crate::APP_NAME, [0, 0, 1, 0]),
+            neptune_vulkan::InstanceSettings {
+                debug_printf: config.shader_printf,
+                gpu_assisted: config.gpu_validation,
+                sync_validation: config.sync_validation,
+                best_practices: config.best_practices,
+            },
             Some(raw_display_handle),
         )?;
 
@@ -108,33 +337,65 @@ impl Editor {
             .context("Failed to find a suitable Vulkan device")?;
 
         info!("Selected Device: {:#?}", physical_device);
+        crash_report::set_device_info(&physical_device.capabilities());
 
         const FRAME_IN_FLIGHT_COUNT: u32 = 3;
 
         let mut device = physical_device
             .create_device(DeviceSettings {
                 frames_in_flight: FRAME_IN_FLIGHT_COUNT,
+                robust_access: config.robust_access || cfg!(debug_assertions),
+                defragment: config.defragment,
+                validate_bindings: config.validate_bindings,
             })
             .context("Failed to initialize vulkan device")?;
 
         let surface_size = window_size;
+        let surface_format = select_surface_format(&device, surface_handle, config.ten_bit_output)?;
+        info!("Surface Format: {:?}", surface_format);
 
         device.configure_surface(
             surface_handle,
             &neptune_vulkan::SurfaceSettings {
                 image_count: FRAME_IN_FLIGHT_COUNT,
-                format: vk::SurfaceFormatKHR {
-                    format: vk::Format::B8G8R8A8_UNORM,
-                    color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
-                },
+                format: surface_format,
                 size: surface_size,
-                usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST,
+                //Transfer src for the frame recorder
+                usage: vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::TRANSFER_DST,
                 present_mode: vk::PresentModeKHR::FIFO,
             },
         )?;
         clear_surfaces(&mut device, [0.0; 3], &[surface_handle])?;
 
-        let scene_renderer = SceneRenderer::new(&mut device, Self::DEPTH_FORMAT)?;
+        let depth_mode = if config.reversed_depth {
+            DepthMode::Reversed
+        } else {
+            DepthMode::Standard
+        };
+        let mut scene_renderer = SceneRenderer::new(
+            &mut device,
+            surface_format.format,
+            Self::DEPTH_FORMAT,
+            depth_mode,
+        )?;
+        if std::path::Path::new(Self::COLOR_LUT_DIRECTORY).is_dir() {
+            scene_renderer
+                .color_grading
+                .load_directory(&mut device, Self::COLOR_LUT_DIRECTORY)?;
+        }
+        let viewports = Viewports::new(&mut device, surface_format.format, depth_mode)?;
+        //Fly camera, left click locks the pointer
+        let mut input_system = InputSystem::default();
+        input_system.set_relative_mouse(true);
+        input_system.set_raw_mouse_input(config.raw_mouse_input);
+
+        let mut sprite_renderer = SpriteRenderer::new(&mut device, surface_format.format, 4096)?;
+        let buffer_inspector = BufferInspector::new(&mut device)?;
+        if surface_format.color_space == vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT {
+            sprite_renderer.ui_compositor.settings.color_space = UiColorSpace::Linear;
+        }
 
         // let gltf_scene_path = if let Some(path) = &config.gltf_scene_path {
         //     path.clone()
@@ -146,230 +407,2369 @@ impl Editor {
         //         .expect("Failed to pick a gltf file")
         // };
 
-        let scene_camera = SceneCamera::new(&mut device)?;
+        let scene_camera = SceneCamera::new(&mut device, depth_mode)?;
 
         //let world = load_world(&mut device, gltf_scene_path)?;
-        let world = create_test_world(&mut device)?;
+        let vfs = Vfs::new(config.asset_archive.as_deref())?;
+        //Gltf models load in the background behind a loading screen, obj files are still loaded up front
+        let background_model = config.model.as_deref().filter(|path| {
+            !path
+                .extension()
+                .map(|extension| extension.eq_ignore_ascii_case("obj"))
+                .unwrap_or(false)
+        });
+        let scene_loader = background_model
+            .map(|path| {
+                SceneLoader::new(
+                    path,
+                    config.static_batching(),
+                    (config.upload_budget * 1024.0 * 1024.0) as usize,
+                )
+            })
+            .transpose()?;
+        let mut primitive_cache = PrimitiveCache::default();
+        let asset_database = AssetDatabase::new(&[
+            Self::MATERIAL_DIRECTORY,
+            Self::SHADER_GRAPH_DIRECTORY,
+            Self::COLOR_LUT_DIRECTORY,
+            Self::RESOURCE_DIRECTORY,
+        ]);
+        let world = create_test_world(
+            &mut device,
+            &mut primitive_cache,
+            &mut scene_renderer,
+            &asset_database,
+            &vfs,
+            config.model.as_deref().filter(|_| scene_loader.is_none()),
+            config.static_batching(),
+        )?;
+
+        fallback::log_report("the startup scene");
 
         let new_world = crate::universe::world::init_test_world();
         drop(new_world);
 
-        Ok(Self {
+        let mut editor = Self {
             instance,
             surface_handle,
             surface_size,
-            device,
+            surface_format,
+            present_mode: vk::PresentModeKHR::FIFO,
+            render_thread: RenderThread::new(device)?,
             scene_renderer,
+            sprite_renderer,
+            input_system,
+            viewports,
             camera: Camera::new(FieldOfView::X(90.0), 0.1, None),
             camera_transform: Transform::with_position(Vec3::NEG_Z),
             scene_camera,
+            camera_2d: None,
             world,
             camera_move_speed: Vec3::splat(1.0),
             camera_move_input: Vec3::ZERO,
             camera_rotate_speed: Vec3::new(0.0, 60.0f32.to_radians(), 0.0),
             camera_rotate_input: Vec3::ZERO,
-        })
+            camera_bookmarks: CameraBookmarks::default(),
+            camera_flight: None,
+            material_editor: None,
+            shader_graph_compiler: ShaderGraphCompiler::new(Some(DerivedDataCache::new(
+                DerivedDataCache::DEFAULT_DIRECTORY,
+            )))?,
+            navmesh_debug_instance: None,
+            cell_debug_instance: None,
+            ik_debug: None,
+            scene_loader,
+            primitive_cache,
+            precompiling_loaded_scene: false,
+            cloth_sample: None,
+            mirror_sample: None,
+            stats_overlay: StatsOverlay::default(),
+            buffer_inspector,
+            texture_preview: TexturePreview::new(),
+            frame_recorder: FrameRecorder::new(config.record_fps, config.record_video),
+            log_console: LogConsole::default(),
+            render_settings_watcher: RenderSettingsWatcher::new(Self::RENDER_SETTINGS_PATH),
+            edit_history: EditHistory::default(),
+            clipboard: EntityClipboard::default(),
+            next_shape: 0,
+            search_box: SearchBox::default(),
+            asset_database,
+        };
+        editor.log_console.resize(surface_size);
+        editor.precompile_scene_shaders();
+        Ok(editor)
+    }
+
+    /// Adds the background loaded scene to the world once it's ready
+    fn poll_scene_loader(&mut self) {
+        if self.precompiling_loaded_scene
+            && self.shader_graph_compiler.precompile_progress().is_none()
+        {
+            self.precompiling_loaded_scene = false;
+        }
+
+        let Some(scene_loader) = &mut self.scene_loader else {
+            return;
+        };
+        let poll_result = scene_loader.poll(
+            &mut self.render_thread.device(),
+            &mut self.primitive_cache,
+            &self.scene_renderer.material_palette,
+            self.scene_camera.position(),
+            &self.scene_camera.view_projection_matrix(),
+        );
+        match poll_result {
+            Ok(None) => {}
+            Ok(Some(mut model_scene)) => {
+                let path = scene_loader.path().to_path_buf();
+                self.scene_loader = None;
+                apply_material_assets(
+                    &mut self.render_thread.device(),
+                    &TextureCache::new(DerivedDataCache::new(DerivedDataCache::DEFAULT_DIRECTORY)),
+                    &self.scene_renderer.material_palette,
+                    &self.asset_database,
+                    &mut model_scene,
+                );
+                add_model_scene(
+                    &mut self.world,
+                    &mut self.render_thread.device(),
+                    &mut self.scene_renderer.lightmap_baker,
+                    &mut self.scene_renderer.skinning,
+                    &path,
+                    &model_scene,
+                );
+                self.precompile_scene_shaders();
+                self.precompiling_loaded_scene = true;
+                info!("Loaded {}", path.display());
+                fallback::log_report(&path.display().to_string());
+                let stats = self.primitive_cache.stats();
+                info!(
+                    "Primitive Cache: {} shared primitives, {} shared buffers, {:.1}MB saved",
+                    stats.shared_primitives,
+                    stats.shared_buffers,
+                    stats.saved_bytes as f32 / (1024.0 * 1024.0)
+                );
+            }
+            Err(err) => {
+                error!(
+                    "Failed to load {}: {:#}",
+                    scene_loader.path().display(),
+                    err
+                );
+                self.scene_loader = None;
+            }
+        }
+    }
+
+    /// Progress of the background scene load, None once it and its pipelines are done
+    fn load_progress(&self) -> Option<LoadProgress> {
+        let pipelines = self
+            .shader_graph_compiler
+            .precompile_progress()
+            .unwrap_or((0, 0));
+        match &self.scene_loader {
+            Some(scene_loader) => Some(scene_loader.progress()),
+            None if self.precompiling_loaded_scene => Some(LoadProgress {
+                meshes: (1, 1),
+                textures: (1, 1),
+                pipelines,
+            }),
+            None => None,
+        }
+    }
+
+    /// Queues every shader graph used by the scene's materials, so they're compiled before the first time they're needed
+    fn precompile_scene_shaders(&mut self) {
+        let material_names: HashSet<&str> = self
+            .world
+            .data
+            .scene
+            .instances()
+            .flat_map(|(_, model)| model.primitives.iter())
+            .filter_map(|primitive| primitive.material.as_ref())
+            .map(|material| material.name.as_str())
+            .collect();
+
+        let graphs: HashSet<AssetGuid> = material_names
+            .into_iter()
+            .filter_map(|name| self.asset_database.find(name, AssetType::Material))
+            .filter_map(|guid| self.asset_database.get(guid))
+            .flat_map(|record| record.dependencies.iter().copied())
+            .filter(|guid| {
+                self.asset_database
+                    .get(*guid)
+                    .map(|record| record.asset_type == AssetType::ShaderGraph)
+                    == Some(true)
+            })
+            .collect();
+
+        for record in graphs
+            .into_iter()
+            .filter_map(|guid| self.asset_database.get(guid))
+        {
+            if let Err(err) = ShaderGraph::load(&record.path)
+                .and_then(|graph| self.shader_graph_compiler.precompile(record.name(), &graph))
+            {
+                warn!("Failed to queue shader graph {}: {:#}", record.name(), err);
+            }
+        }
     }
 
     pub fn window_resize(&mut self, new_size: [u32; 2]) -> anyhow::Result<()> {
+        //Minimizing can shrink the window to nothing, the old size is kept until it comes back
+        if new_size.contains(&0) {
+            return Ok(());
+        }
+
         info!("Swapchain Resize: {:?}", new_size);
         self.surface_size = new_size;
-        self.device.configure_surface(
+        self.world
+            .data
+            .events
+            .send(WindowResized { size: new_size });
+        self.render_thread.device().configure_surface(
             self.surface_handle,
             &neptune_vulkan::SurfaceSettings {
                 image_count: 3,
-                format: vk::SurfaceFormatKHR {
-                    format: vk::Format::B8G8R8A8_UNORM,
-                    color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
-                },
+                format: self.surface_format,
                 size: new_size,
-                usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST,
-                present_mode: vk::PresentModeKHR::FIFO,
+                usage: vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::TRANSFER_DST,
+                present_mode: self.present_mode,
             },
         )?;
         Ok(())
     }
 
-    pub fn update(&mut self, delta_time: f32) {
-        self.camera_transform.rotate(
-            self.camera_transform.rotation * Vec3::Y,
-            self.camera_rotate_speed.y * self.camera_rotate_input.y * delta_time,
-        );
+    fn apply_render_settings(&mut self, settings: &RenderSettings) -> anyhow::Result<()> {
+        let present_mode = select_present_mode(
+            &self.render_thread.device(),
+            self.surface_handle,
+            settings.vsync,
+        )?;
+        if present_mode != self.present_mode {
+            info!("Present Mode: {:?}", present_mode);
+            self.present_mode = present_mode;
+            self.window_resize(self.surface_size)?;
+        }
 
-        self.camera_transform.translate(
-            self.camera_transform.rotation
-                * (self.camera_move_speed * self.camera_move_input * delta_time),
-        );
+        self.scene_renderer.render_mode = settings.render_mode;
+        self.scene_renderer.upscaler.settings.mode = settings.upscaler;
+        self.scene_renderer.dithering = settings.dithering;
+        self.scene_renderer.voxel_gi.settings.enabled = settings.voxel_gi;
+        self.scene_renderer.particles.settings.enabled = settings.particles;
+        self.scene_renderer.sky.settings.enabled = settings.sky;
+        let lens_settings = &mut self.scene_renderer.lens_effects.settings;
+        lens_settings.light_shafts = settings.lens_effects;
+        lens_settings.lens_flare = settings.lens_effects;
+        self.scene_renderer.viewport_helpers.settings.show_grid = settings.show_grid;
+        self.scene_renderer
+            .viewport_helpers
+            .settings
+            .show_axis_gizmo = settings.show_axis_gizmo;
+        self.stats_overlay.visible = settings.show_stats;
+        self.viewports.aspect_lock = settings.aspect_lock;
+        if let Some(post_effects) = &settings.post_effects {
+            self.world.data.scene.post_effects = post_effects.clone();
+        }
+        Ok(())
+    }
 
-        let camera_transform = match &self.world.entities.player {
-            None => self.camera_transform.clone(),
-            Some(player) => player.get_camera_transform(),
-        };
+    /// Reacts to the events sent last frame
+    fn read_events(&mut self) {
+        let events = &self.world.data.events;
+        for event in events.read::<WindowResized>() {
+            self.log_console.resize(event.size);
+        }
 
-        self.scene_camera.update(
-            &self.camera,
-            &camera_transform,
-            (self.surface_size[0] as f32) / (self.surface_size[1] as f32),
-        );
+        //Loading a model can write new meta files, they should show up in search right away
+        if events.read::<AssetLoaded>().iter().any(|event| {
+            !self
+                .asset_database
+                .iter()
+                .any(|(_, record)| record.path == event.path)
+        }) {
+            self.asset_database.refresh();
+        }
+
+        for event in events.read::<EntitySpawned>() {
+            debug!("Spawned {} at {}", event.kind, event.transform.position);
+        }
 
-        self.world.update(delta_time);
+        for event in events.read::<CollisionEvent>() {
+            debug!(
+                "Collision {} between {:?} and {:?}",
+                if event.started { "started" } else { "stopped" },
+                event.collider1,
+                event.collider2
+            );
+        }
     }
 
-    pub fn render(&mut self) -> anyhow::Result<()> {
-        let mut render_graph_builder =
-            neptune_vulkan::basic_render_graph_builder::BasicRenderGraphBuilder::default();
+    /// `frame_time` is the unscaled wall clock time of the last frame
+    pub fn update(&mut self, frame_time: f32) {
+        if let Some(settings) = self.render_settings_watcher.poll(frame_time) {
+            if let Err(err) = self.apply_render_settings(&settings) {
+                error!("Failed to apply render settings: {:#}", err);
+            }
+        }
 
-        let swapchain_image = render_graph_builder.acquire_swapchain_image(self.surface_handle);
+        self.world.data.time.begin_frame(frame_time);
+        self.world.data.events.update();
+        self.read_events();
 
-        self.scene_camera
-            .write_render_passes(&mut render_graph_builder);
-        self.world
-            .data
-            .scene
-            .write_render_passes(&mut render_graph_builder);
-        self.scene_renderer.write_render_passes(
-            swapchain_image,
-            &self.scene_camera,
-            &self.world.data.scene,
-            &mut render_graph_builder,
-        );
+        //The editor camera keeps moving while the game is paused or slowed down
+        let delta_time = self.world.data.time.unscaled_delta();
 
-        //Round-trip Upload/Download Test
-        {
-            let test_data = &[127u8; 16];
-            let test_buffer = render_graph_builder.create_transient_buffer(
-                test_data.len(),
-                BufferUsage::TRANSFER,
-                MemoryLocation::GpuOnly,
-            );
+        //Any manual movement takes control back from the flight
+        if self.camera_move_input != Vec3::ZERO || self.camera_rotate_input != Vec3::ZERO {
+            self.camera_flight = None;
+        }
 
-            //Upload data to gpu
-            render_graph_builder.add_buffer_write(
-                BufferOffset {
-                    buffer: test_buffer,
-                    offset: 0,
-                },
-                test_data.len(),
-                BufferWriteCallback::new(move |slice| {
-                    slice.copy_from_slice(test_data);
-                }),
+        if let Some(camera_flight) = &mut self.camera_flight {
+            if camera_flight.update(delta_time, &mut self.camera_transform) {
+                self.camera_flight = None;
+            }
+        }
+
+        if let Some(camera_2d) = &mut self.camera_2d {
+            //In screen pixels so panning feels the same at every zoom
+            camera_2d.position += self.camera_move_input.truncate()
+                * (Self::CAMERA_2D_PAN_SPEED / camera_2d.scale() * delta_time);
+        } else {
+            self.camera_transform.rotate(
+                self.camera_transform.rotation * Vec3::Y,
+                self.camera_rotate_speed.y * self.camera_rotate_input.y * delta_time,
             );
 
-            //Download from gpu
-            render_graph_builder.add_buffer_read(
-                BufferOffset {
-                    buffer: test_buffer,
-                    offset: 0,
-                },
-                test_data.len(),
-                BufferReadCallback::new(move |slice| {
-                    assert_eq!(
-                        slice, test_data,
-                        "Downloaded data doesn't match uploaded data"
-                    );
-                }),
+            self.camera_transform.translate(
+                self.camera_transform.rotation
+                    * (self.camera_move_speed * self.camera_move_input * delta_time),
             );
         }
 
-        let render_graph = render_graph_builder.build();
-        self.device.submit_graph(&render_graph)?;
-        Ok(())
+        let camera_transform = self.active_camera_transform();
+
+        let main_view_size = self.viewports.main_view_size(self.surface_size);
+        self.scene_camera.jitter = self.scene_renderer.next_jitter();
+        if self.camera_2d.map(|camera_2d| camera_2d.pixel_perfect) == Some(true) {
+            //Jitter would undo the snapping
+            self.scene_camera.jitter = Vec2::ZERO;
+        }
+        self.scene_camera.render_scale = self.scene_renderer.render_scale();
+        self.scene_camera
+            .update(&self.active_camera(), &camera_transform, main_view_size);
+        self.scene_renderer
+            .update_render_textures(&camera_transform);
+        self.scene_renderer
+            .particles
+            .update(self.world.data.time.delta());
+        self.scene_renderer
+            .water
+            .update(self.world.data.time.delta());
+        self.scene_renderer
+            .foliage
+            .update(self.world.data.time.delta());
+        if let Some(cloth_sample) = &mut self.cloth_sample {
+            cloth_sample
+                .cloth
+                .update(self.world.data.time.delta(), &cloth_sample.transform);
+        }
+
+        //Follows game time so pausing also stops the sun
+        self.scene_renderer.sky.update(self.world.data.time.delta());
+
+        self.stream_assets();
+        if self.ik_debug.is_some() {
+            //Heads follow the camera and hands reach for it while the chains are shown
+            self.world
+                .set_ik_focus(Some(self.active_camera_transform().position));
+        }
+        self.world.update();
+        for (skin, matrices) in self.world.skin_poses() {
+            self.scene_renderer
+                .skinning
+                .set_joint_matrices(skin, matrices);
+        }
     }
-}
 
-impl Drop for Editor {
-    fn drop(&mut self) {
-        self.device.release_surface(self.surface_handle);
-        self.instance.destroy_surface(self.surface_handle);
+    /// Runs every update, so loading keeps going while the window is hidden and nothing is rendered.
+    /// Only waits on the render thread when there is something to hand to the device
+    fn stream_assets(&mut self) {
+        if self.shader_graph_compiler.precompile_progress().is_some() {
+            self.shader_graph_compiler
+                .poll_precompiled(&mut self.render_thread.device(), &self.scene_renderer);
+        }
+        self.poll_scene_loader();
+        if self.primitive_cache.has_unused() {
+            self.primitive_cache
+                .collect(&mut self.render_thread.device());
+        }
     }
-}
 
-impl WindowEventReceiver for Editor {
-    fn on_window_size_changed(&mut self, new_size: [u32; 2]) -> anyhow::Result<()> {
-        self.window_resize(new_size)
+    fn main_view_aspect_ratio(&self) -> f32 {
+        let size = self.viewports.main_view_size(self.surface_size);
+        (size[0] as f32) / (size[1] as f32)
     }
-}
 
-impl InputEventReceiver for Editor {
-    fn requests_mouse_capture(&mut self) -> bool {
-        true
+    /// Adds a view fixed where the camera is now, to compare against while moving the main camera
+    fn add_split_view(&mut self) -> anyhow::Result<()> {
+        let transform = self.active_camera_transform();
+        let split_view = SplitView::new(
+            &mut self.render_thread.device(),
+            self.camera,
+            transform,
+            self.scene_renderer.depth_mode(),
+        )?;
+        self.viewports.split_views.push(split_view);
+        Ok(())
     }
 
-    fn on_button_event(&mut self, button_name: StaticString, state: ButtonState) -> bool {
-        if let Some(player) = &mut self.world.entities.player {
-            return player.on_button_event(button_name, state);
+    fn active_camera(&self) -> Camera {
+        match &self.camera_2d {
+            Some(camera_2d) => camera_2d.camera(self.viewports.main_view_size(self.surface_size)),
+            None => self.camera,
         }
-
-        false
     }
 
-    fn on_axis_event(&mut self, axis_name: StaticString, value: f32) -> bool {
-        if let Some(player) = &mut self.world.entities.player {
-            return player.on_axis_event(axis_name, value);
+    fn active_camera_transform(&self) -> Transform {
+        if let Some(camera_2d) = &self.camera_2d {
+            return camera_2d.transform(self.viewports.main_view_size(self.surface_size));
+        }
+        match &self.world.entities.player {
+            None => self.camera_transform.clone(),
+            Some(player) => player.get_camera_transform(),
         }
+    }
 
-        match axis_name {
-            "player_move_left_right" => {
-                self.camera_move_input.x = value;
-                true
-            }
-            "player_move_up_down" => {
-                self.camera_move_input.y = value;
-                true
-            }
-            "player_move_forward_back" => {
-                self.camera_move_input.z = value;
-                true
+    /// Selects whatever is under the center of the screen, since the mouse is captured there is no cursor to pick with
+    fn pick_selection(&mut self) {
+        let camera_transform = self.active_camera_transform();
+        let ray = Ray::new(
+            camera_transform.position,
+            camera_transform.rotation * Vec3::Z,
+        );
+
+        let scene = &mut self.world.data.scene;
+        let hit = scene.ray_cast(&ray, Self::PICK_DISTANCE);
+        match hit {
+            //Picking something already selected only takes it out of the selection
+            Some(hit) if scene.is_selected(hit.item) => {
+                scene.deselect(hit.item);
+                info!("Deselected: {:?}", hit.item);
             }
-            "player_move_yaw" => {
-                self.camera_rotate_input.y = value;
-                true
+            Some(hit) => {
+                scene.clear_selection();
+                scene.select(hit.item);
+                info!("Selected: {:?}", hit.item);
             }
-            "player_move_pitch" => {
-                self.camera_rotate_input.x = value;
-                true
+            None => scene.clear_selection(),
+        }
+    }
+
+    /// Adds everything touching the selection's bounding sphere to the selection
+    fn grow_selection(&mut self) {
+        let scene = &mut self.world.data.scene;
+        let Some(bounds) = scene.selection_bounds() else {
+            return;
+        };
+        for handle in scene.sphere_overlap(bounds.center, bounds.radius) {
+            scene.select(handle);
+        }
+        info!("Selected {} entities", scene.selection().count());
+    }
+
+    /// Turns the selected entities to the next `ROTATE_SNAP` yaw step, pitch and roll are kept
+    fn rotate_selection(&mut self) {
+        let selection: Vec<_> = self.world.data.scene.selection().collect();
+        for handle in selection {
+            let Some(entity) = self.world.get_static_entity(handle) else {
+                continue;
+            };
+            let mut transform = Transform::decompose(&entity.world_matrix(&self.world.data));
+            let mut angles = transform.euler_angles();
+            angles.x = ((angles.x / Self::ROTATE_SNAP).round() + 1.0) * Self::ROTATE_SNAP;
+            transform.set_euler_angles(angles);
+            self.world.set_static_entity_transform(handle, transform);
+        }
+    }
+
+    /// Walks the selected entities along the navmesh to whatever is under the center of the screen
+    fn walk_selection(&mut self) {
+        if self.world.data.navmesh.is_none() {
+            warn!("Build a navmesh before walking entities");
+            return;
+        }
+
+        let camera_transform = self.active_camera_transform();
+        let ray = Ray::new(
+            camera_transform.position,
+            camera_transform.rotation * Vec3::Z,
+        );
+        let Some(hit) = self.world.data.scene.ray_cast(&ray, Self::PICK_DISTANCE) else {
+            return;
+        };
+        let target = ray.at(hit.distance);
+
+        let selection: Vec<_> = self.world.data.scene.selection().collect();
+        for instance in selection {
+            if !self.world.walk_static_entity(instance, target) {
+                warn!("No navmesh path from {:?} to {}", instance, target);
             }
+        }
+    }
 
-            _ => false,
+    /// Copies of the selected static entities, other selected instances can't be copied
+    fn copy_selection(&self) -> Vec<StaticEntity> {
+        self.world
+            .data
+            .scene
+            .selection()
+            .filter_map(|handle| self.world.get_static_entity(handle))
+            .map(StaticEntity::detached_copy)
+            .collect()
+    }
+
+    fn select_instances(&mut self, handles: &[SceneInstanceHandle]) {
+        let scene = &mut self.world.data.scene;
+        scene.clear_selection();
+        for handle in handles {
+            scene.select(*handle);
         }
     }
 
-    fn on_text_event(&mut self, _text: String) -> bool {
-        false
+    /// Adds the entities as one undo step and selects them
+    fn spawn_entities(&mut self, entities: Vec<StaticEntity>) {
+        let handles: Vec<_> = entities
+            .into_iter()
+            .filter_map(|entity| self.world.add_static_entity(entity))
+            .collect();
+        if handles.is_empty() {
+            return;
+        }
+        self.select_instances(&handles);
+        info!("Added {} entities", handles.len());
+        self.edit_history.push(EditAction::Added(handles));
     }
-}
 
-fn create_test_world(device: &mut neptune_vulkan::Device) -> anyhow::Result<World> {
-    let gltf_data = load_gltf_resources(device, "neptune_editor/resource/NeptuneResources.glb")?;
+    /// Places the next built-in shape in front of the camera, cycling through every generator
+    fn spawn_shape(&mut self) -> anyhow::Result<()> {
+        let (name, mesh, collider) = match self.next_shape % 8 {
+            0 => (
+                "Cube",
+                ProceduralMesh::cube(Vec3::splat(0.5)),
+                Some(Collider::Box(Vec3::splat(0.5))),
+            ),
+            1 => (
+                "UV Sphere",
+                ProceduralMesh::uv_sphere(0.5, 32, 16),
+                Some(Collider::Sphere(0.5)),
+            ),
+            2 => (
+                "Icosphere",
+                ProceduralMesh::icosphere(0.5, 3),
+                Some(Collider::Sphere(0.5)),
+            ),
+            3 => (
+                "Plane",
+                ProceduralMesh::plane(Vec2::splat(2.0), 3),
+                Some(Collider::Box(Vec3::new(1.0, 0.01, 1.0))),
+            ),
+            4 => ("Cylinder", ProceduralMesh::cylinder(0.5, 1.0, 32), None),
+            5 => ("Cone", ProceduralMesh::cone(0.5, 1.0, 32), None),
+            6 => ("Torus", ProceduralMesh::torus(0.4, 0.15, 32, 16), None),
+            _ => (
+                "Capsule",
+                ProceduralMesh::capsule(0.3, 0.6, 32, 8),
+                Some(Collider::CapsuleY(0.3, 0.3)),
+            ),
+        };
+        self.next_shape += 1;
+
+        let primitive = self
+            .primitive_cache
+            .get_or_create(&mut self.render_thread.device(), &mesh.primitive_data())?;
+        let model = Model {
+            name: name.to_string(),
+            primitives: vec![ModelPrimitive {
+                primitive,
+                material: None,
+                lightmap: None,
+            }],
+        };
 
-    info!("Available Meshes: {:?}", gltf_data.meshes.keys());
-    info!("Available Materials: {:?}", gltf_data.materials.keys());
+        let camera_transform = self.active_camera_transform();
+        let transform = Transform {
+            position: camera_transform.position + camera_transform.rotation * Vec3::Z * 3.0,
+            ..Default::default()
+        };
+        self.spawn_entities(vec![StaticEntity::new(transform, model, collider)]);
+        Ok(())
+    }
 
-    let mut world = World {
-        data: WorldData {
-            scene: Scene::new(device, 1024)?,
-            physics: PhysicsWorld::new(),
-        },
-        entities: Default::default(),
-    };
+    fn delete_selection(&mut self) {
+        let selection: Vec<_> = self.world.data.scene.selection().collect();
+        let removed: Vec<_> = selection
+            .into_iter()
+            .filter_map(|handle| {
+                self.world
+                    .remove_static_entity(handle)
+                    .map(|entity| (handle, entity))
+            })
+            .collect();
+        if removed.is_empty() {
+            return;
+        }
+        info!("Deleted {} entities", removed.len());
+        self.edit_history.push(EditAction::Removed(removed));
+    }
 
-    let purple_cube_model = Model {
-        name: "PurpleCube".to_string(),
-        primitives: vec![ModelPrimitive {
-            primitive: gltf_data.meshes["Cube"].primitives[0].clone(),
-            material: gltf_data.materials.get("Purple").cloned().map(Arc::new),
-        }],
-    };
+    fn undo_edit(&mut self, redo: bool) {
+        let action = if redo {
+            self.edit_history.redo(&mut self.world)
+        } else {
+            self.edit_history.undo(&mut self.world)
+        };
+        let Some(action) = action else {
+            return;
+        };
+        let handles = action.added_instances().to_vec();
+        self.select_instances(&handles);
+    }
 
-    let orange_cube_model = Model {
-        name: "OrangeCube".to_string(),
-        primitives: vec![ModelPrimitive {
-            primitive: gltf_data.meshes["Cube"].primitives[0].clone(),
-            material: gltf_data.materials.get("Orange").cloned().map(Arc::new),
-        }],
-    };
+    fn open_search(&mut self) {
+        self.asset_database.refresh();
+        let mut items = SearchItem::from_world(&self.world);
+        items.extend(SearchItem::from_asset_database(&self.asset_database));
+        self.search_box.open(items);
 
-    {
+        //Keys typed into the search shouldn't keep the camera moving
+        self.camera_move_input = Vec3::ZERO;
+        self.camera_rotate_input = Vec3::ZERO;
+    }
+
+    /// Cross-fades the selected animated entities to the next state of their graph, every animated entity if none are selected
+    fn next_animation_state(&mut self) {
+        let mut instances: Vec<_> = self.world.data.scene.selection().collect();
+        if instances.is_empty() {
+            instances = self
+                .world
+                .static_entities()
+                .iter()
+                .filter(|entity| entity.animation().is_some())
+                .filter_map(StaticEntity::scene_instance)
+                .collect();
+        }
+
+        for instance in instances {
+            let Some(animation) = self.world.animation_mut(instance) else {
+                continue;
+            };
+            let player = &mut animation.player;
+            let graph = player.graph().clone();
+            if graph.states.is_empty() {
+                continue;
+            }
+            let current = graph
+                .state_index(player.current_state())
+                .unwrap_or_default();
+            let next = &graph.states[(current + 1) % graph.states.len()].name;
+            player.set_trigger(next);
+            info!("Animation: {}", next);
+        }
+    }
+
+    /// Selects every entity in the search results and previews the best matching texture,
+    /// or renames the best matching asset if the query has a `rename:`
+    fn select_search_results(&mut self) {
+        let results = self.search_box.results();
+        let asset = results.iter().find_map(|item| match item.target {
+            SearchTarget::Asset(guid, _) => Some(guid),
+            SearchTarget::Entity(_) => None,
+        });
+        if let Some(new_name) = self.search_box.rename() {
+            match asset {
+                Some(guid) => {
+                    if let Err(err) = self.rename_asset(guid, &new_name) {
+                        error!("Failed to rename asset: {:#}", err);
+                    }
+                }
+                None => warn!("No asset matches the search to rename"),
+            }
+            return;
+        }
+
+        let handles: Vec<_> = results
+            .iter()
+            .filter_map(|item| match item.target {
+                SearchTarget::Entity(handle) => Some(handle),
+                SearchTarget::Asset(..) => None,
+            })
+            .collect();
+        let texture_path = results.iter().find_map(|item| match &item.target {
+            SearchTarget::Asset(_, path) if item.kind == AssetType::Texture.name() => {
+                Some(path.clone())
+            }
+            _ => None,
+        });
+
+        self.select_instances(&handles);
+        info!("Selected {} entities", handles.len());
+        if let Some(path) = texture_path {
+            self.texture_preview.open(path);
+        }
+        if let Some(guid) = asset {
+            for dependent in self
+                .asset_database
+                .dependents(guid)
+                .into_iter()
+                .filter_map(|dependent| self.asset_database.get(dependent))
+            {
+                info!(
+                    "  Used by {} {}",
+                    dependent.asset_type.name(),
+                    dependent.path.display()
+                );
+            }
+        }
+    }
+
+    /// Renames an asset where it is, keeping its extension.
+    /// Materials are matched to models by file name so they can't be renamed
+    fn rename_asset(&mut self, guid: AssetGuid, new_name: &str) -> anyhow::Result<()> {
+        let record = self
+            .asset_database
+            .get(guid)
+            .with_context(|| format!("Asset {} doesn't exist", guid))?;
+        if record.asset_type == AssetType::Material {
+            anyhow::bail!(
+                "Material {} is matched to models by its file name",
+                record.name()
+            );
+        }
+
+        let mut file_name = std::ffi::OsString::from(new_name);
+        if let Some(extension) = record.path.extension() {
+            file_name.push(".");
+            file_name.push(extension);
+        }
+        let new_path = record.path.with_file_name(file_name);
+        let dependents = self.asset_database.dependents(guid).len();
+        self.asset_database.move_asset(guid, &new_path)?;
+        info!(
+            "Renamed to {}, {} assets reference it by guid",
+            new_path.display(),
+            dependents
+        );
+        Ok(())
+    }
+
+    fn fly_camera_to(&mut self, target: &Transform) {
+        self.camera_flight = Some(CameraFlight::new(
+            &self.camera_transform,
+            target,
+            Self::CAMERA_FLIGHT_DURATION,
+        ));
+    }
+
+    /// Frames the selection, or selects and frames whatever is closest to the camera when nothing is selected
+    fn frame_selection(&mut self) {
+        let scene = &mut self.world.data.scene;
+        if scene.selection().next().is_none() {
+            let position = self.camera_transform.position;
+            if let Some(nearest) = scene.k_nearest(position, 1).first() {
+                scene.select(nearest.item);
+            }
+        }
+        let Some(bounds) = scene.selection_bounds() else {
+            return;
+        };
+
+        if let Some(camera_2d) = &mut self.camera_2d {
+            camera_2d.position = bounds.center.truncate();
+            return;
+        }
+
+        let target = frame_bounds(
+            &self.camera,
+            &self.camera_transform,
+            self.main_view_aspect_ratio(),
+            &bounds,
+        );
+        self.fly_camera_to(&target);
+    }
+
+    /// Opens the material of the first selected instance's first primitive
+    fn open_material_editor(&mut self) -> anyhow::Result<()> {
+        let scene = &self.world.data.scene;
+        let material = scene
+            .selection()
+            .find_map(|handle| scene.get_model(handle))
+            .and_then(|model| {
+                model
+                    .primitives
+                    .iter()
+                    .find_map(|primitive| primitive.material.clone())
+            });
+
+        let Some(material) = material else {
+            info!("Selection has no material to edit");
+            return Ok(());
+        };
+
+        //Materials are saved under the name they have in the model
+        let guid = self
+            .asset_database
+            .find(&material.name, AssetType::Material);
+        let path = match guid.and_then(|guid| self.asset_database.get(guid)) {
+            Some(record) => record.path.clone(),
+            None => std::path::Path::new(Self::MATERIAL_DIRECTORY)
+                .join(format!("{}.toml", material.name)),
+        };
+        if let Some(record) = guid.and_then(|guid| self.asset_database.get(guid)) {
+            for dependency in record
+                .dependencies
+                .iter()
+                .filter_map(|dependency| self.asset_database.get(*dependency))
+            {
+                info!(
+                    "Material {} uses {} {}",
+                    material.name,
+                    dependency.asset_type.name(),
+                    dependency.path.display()
+                );
+            }
+        }
+        self.material_editor = Some(MaterialEditorPanel::open(&material, &path)?);
+        self.compile_material_shader_graph()
+    }
+
+    /// Rebuilds the world's navmesh from the scene and replaces its debug overlay
+    fn build_navmesh(&mut self) -> anyhow::Result<()> {
+        let scene = &mut self.world.data.scene;
+
+        //The old overlay would otherwise be voxelized into the new navmesh
+        if let Some(handle) = self.navmesh_debug_instance.take() {
+            remove_debug_instance(&mut self.render_thread.device(), scene, handle);
+        }
+
+        let navmesh = NavMesh::from_scene(scene, NavMeshSettings::default());
+        info!("Built NavMesh: {} polygons", navmesh.polygons.len());
+
+        let debug_mesh = navmesh.debug_mesh();
+        if !debug_mesh.indices.is_empty() {
+            let model = Model {
+                name: "NavMesh Debug".to_string(),
+                primitives: vec![ModelPrimitive {
+                    primitive: Arc::new(
+                        debug_mesh.create_primitive(&mut self.render_thread.device())?,
+                    ),
+                    material: None,
+                    lightmap: None,
+                }],
+            };
+            self.navmesh_debug_instance = scene.add_instance(Transform::default(), model);
+        }
+
+        self.world.data.navmesh = Some(navmesh);
+        Ok(())
+    }
+
+    /// Shows the scene's cells and portals colored by what the main camera can see right now, or hides them if they're already shown
+    fn toggle_cell_debug(&mut self) -> anyhow::Result<()> {
+        let scene = &mut self.world.data.scene;
+        if let Some(handle) = self.cell_debug_instance.take() {
+            remove_debug_instance(&mut self.render_thread.device(), scene, handle);
+            return Ok(());
+        }
+
+        let visible_cells = scene.cells.visible_cells(
+            &self.scene_camera.view_projection_matrix(),
+            self.scene_camera.position(),
+        );
+        match &visible_cells {
+            Some(visible_cells) => info!(
+                "Visible Cells: {} of {}",
+                visible_cells.iter().filter(|visible| **visible).count(),
+                visible_cells.len()
+            ),
+            None => info!("Camera is outside every cell, nothing is culled"),
+        }
+
+        let debug_mesh = scene.cells.debug_mesh(visible_cells.as_deref());
+        if !debug_mesh.indices.is_empty() {
+            let model = Model {
+                name: "Cell Debug".to_string(),
+                primitives: vec![ModelPrimitive {
+                    primitive: Arc::new(
+                        debug_mesh.create_primitive(&mut self.render_thread.device())?,
+                    ),
+                    material: None,
+                    lightmap: None,
+                }],
+            };
+            self.cell_debug_instance = scene.add_instance(Transform::default(), model);
+        }
+        Ok(())
+    }
+
+    /// Draws the IK chains of every animated entity, redrawn each frame, or hides them if they're already shown.
+    /// While shown the look ats and reach chains are pointed at the camera
+    fn toggle_ik_debug(&mut self) -> anyhow::Result<()> {
+        if let Some(ik_debug) = self.ik_debug.take() {
+            self.world.set_ik_focus(None);
+            self.world.data.scene.remove_instance(ik_debug.instance);
+            ik_debug.mesh.destroy(&mut self.render_thread.device());
+            return Ok(());
+        }
+
+        let mesh = DynamicMesh::new(&mut self.render_thread.device(), "IK Debug", 1024, 4096)?;
+        let Some(instance) = self
+            .world
+            .data
+            .scene
+            .add_instance(Transform::default(), IkDebugView::model(&mesh))
+        else {
+            mesh.destroy(&mut self.render_thread.device());
+            anyhow::bail!("Scene is out of instances");
+        };
+        self.ik_debug = Some(IkDebugView { mesh, instance });
+        Ok(())
+    }
+
+    /// Hangs a cloth in front of the camera, or removes it if one is already out
+    fn toggle_cloth_sample(&mut self) -> anyhow::Result<()> {
+        if let Some(cloth_sample) = self.cloth_sample.take() {
+            self.world.data.scene.remove_instance(cloth_sample.instance);
+            cloth_sample.cloth.destroy(&mut self.render_thread.device());
+            return Ok(());
+        }
+
+        let camera_transform = self.active_camera_transform();
+        let transform = Transform {
+            position: camera_transform.position
+                + camera_transform.rotation * Vec3::new(0.0, 1.0, -3.0),
+            rotation: camera_transform.rotation,
+            scale: Vec3::ONE,
+        };
+
+        let cloth = Cloth::new(
+            &mut self.render_thread.device(),
+            "Cloth Sample",
+            Self::CLOTH_SAMPLE_RESOLUTION,
+            glam::Vec2::splat(2.0),
+            ClothSettings::default(),
+        )?;
+        let model = Model {
+            name: "Cloth Sample".to_string(),
+            primitives: vec![ModelPrimitive {
+                primitive: cloth.primitive(),
+                material: None,
+                lightmap: None,
+            }],
+        };
+        let Some(instance) = self.world.data.scene.add_instance(transform.clone(), model) else {
+            cloth.destroy(&mut self.render_thread.device());
+            anyhow::bail!("Scene is out of instances");
+        };
+
+        self.cloth_sample = Some(ClothSample {
+            cloth,
+            instance,
+            transform,
+        });
+        Ok(())
+    }
+
+    /// Lays a floor mirror out in front of the camera, or removes it if one is already out
+    fn toggle_mirror_sample(&mut self) -> anyhow::Result<()> {
+        if let Some(mirror_sample) = self.mirror_sample.take() {
+            remove_debug_instance(
+                &mut self.render_thread.device(),
+                &mut self.world.data.scene,
+                mirror_sample.instance,
+            );
+            self.scene_renderer
+                .remove_render_texture(&mut self.render_thread.device(), mirror_sample.texture);
+            return Ok(());
+        }
+
+        let camera_transform = self.active_camera_transform();
+        let forward = (camera_transform.rotation * Vec3::Z) * Vec3::new(1.0, 0.0, 1.0);
+        let position = camera_transform.position
+            + forward.normalize_or_zero() * 3.0
+            + Vec3::new(0.0, -1.5, 0.0);
+
+        let description = RenderTextureDescription {
+            name: "Mirror Sample".to_string(),
+            size: Self::MIRROR_SAMPLE_SIZE,
+            camera: self.active_camera(),
+            recursion_depth: Self::MIRROR_SAMPLE_RECURSION_DEPTH,
+        };
+        let texture = self
+            .scene_renderer
+            .add_render_texture(&mut self.render_thread.device(), &description)?;
+        if let Some(render_texture) = self.scene_renderer.get_render_texture_mut(texture) {
+            render_texture.reflection_plane = Some(Plane::new(position, Vec3::Y));
+            info!(
+                "Mirror Sample: {:?} with {} levels",
+                render_texture.size(),
+                render_texture.recursion_depth()
+            );
+        }
+
+        let material = self.scene_renderer.render_texture_material(texture)?;
+        let mesh = ProceduralMesh::plane(Vec2::splat(4.0), 0);
+        let model = Model {
+            name: "Mirror Sample".to_string(),
+            primitives: vec![ModelPrimitive {
+                primitive: Arc::new(mesh.create_primitive(&mut self.render_thread.device())?),
+                material: Some(Arc::new(material)),
+                lightmap: None,
+            }],
+        };
+        let transform = Transform {
+            position,
+            ..Default::default()
+        };
+        let Some(instance) = self.world.data.scene.add_instance(transform, model) else {
+            self.scene_renderer
+                .remove_render_texture(&mut self.render_thread.device(), texture);
+            anyhow::bail!("Scene is out of instances");
+        };
+
+        self.mirror_sample = Some(MirrorSample { texture, instance });
+        Ok(())
+    }
+
+    /// Adds an empty foliage layer of small cones to the scene
+    fn create_foliage_sample_layer(&mut self) -> anyhow::Result<()> {
+        let cone = ProceduralMesh::cone(0.08, 0.6, 6);
+        let mut mesh = ProceduralMesh::default();
+        mesh.append(
+            &cone,
+            glam::Mat4::from_translation(Vec3::Y * 0.3),
+            glam::Vec4::new(0.2, 0.6, 0.15, 1.0),
+        );
+
+        let model_primitive = ModelPrimitive {
+            primitive: Arc::new(mesh.create_primitive(&mut self.render_thread.device())?),
+            material: None,
+            lightmap: None,
+        };
+        let layer = FoliageLayer::new(
+            &mut self.render_thread.device(),
+            "Foliage Sample",
+            model_primitive,
+            Self::FOLIAGE_SAMPLE_CAPACITY,
+        )?;
+        self.world.data.scene.foliage.push(layer);
+        Ok(())
+    }
+
+    /// Scatters foliage over the ground around the camera, or removes it all if there is already some
+    fn toggle_foliage_sample(&mut self) -> anyhow::Result<()> {
+        let scene = &mut self.world.data.scene;
+        if !scene.foliage.is_empty() {
+            for layer in scene.foliage.drain(..) {
+                let primitive = &layer.model_primitive.primitive;
+                self.render_thread
+                    .device()
+                    .destroy_buffer(primitive.position_buffer);
+                self.render_thread
+                    .device()
+                    .destroy_buffer(primitive.attributes_buffer);
+                if let Some(index_buffer) = &primitive.index_buffer {
+                    self.render_thread
+                        .device()
+                        .destroy_buffer(index_buffer.buffer);
+                }
+                layer.destroy(&mut self.render_thread.device());
+            }
+            return Ok(());
+        }
+
+        self.create_foliage_sample_layer()?;
+
+        let camera_position = self.active_camera_transform().position;
+        let scene = &mut self.world.data.scene;
+        let instances = FoliageGround::new(scene).scatter(
+            glam::Vec2::new(camera_position.x, camera_position.z),
+            glam::Vec2::splat(Self::FOLIAGE_SCATTER_SIZE),
+            &FoliageScatterSettings::default(),
+        );
+        info!("Scattered {} foliage instances", instances.len());
+        scene.foliage[0].add_instances(instances);
+        Ok(())
+    }
+
+    /// Paints foliage around the point at the center of the screen
+    fn paint_foliage(&mut self) -> anyhow::Result<()> {
+        if self.world.data.scene.foliage.is_empty() {
+            self.create_foliage_sample_layer()?;
+        }
+
+        let camera_transform = self.active_camera_transform();
+        let ray = Ray::new(
+            camera_transform.position,
+            camera_transform.rotation * Vec3::Z,
+        );
+
+        let scene = &mut self.world.data.scene;
+        let instances = FoliageGround::new(scene).paint(
+            &ray,
+            Self::FOLIAGE_BRUSH_RADIUS,
+            Self::FOLIAGE_BRUSH_COUNT,
+            &FoliageScatterSettings::default(),
+        );
+        scene.foliage[0].add_instances(instances);
+        Ok(())
+    }
+
+    /// Recompiles the open material's shader graph and swaps it in, this is the live preview path
+    fn compile_material_shader_graph(&mut self) -> anyhow::Result<()> {
+        let Some(material_editor) = &self.material_editor else {
+            return Ok(());
+        };
+
+        let Some(graph_reference) = material_editor.asset.shader_graph() else {
+            material_editor.set_pipeline(None);
+            return Ok(());
+        };
+
+        let record = self
+            .asset_database
+            .resolve(graph_reference, AssetType::ShaderGraph)
+            .and_then(|guid| self.asset_database.get(guid))
+            .with_context(|| format!("Shader graph {} doesn't exist", graph_reference))?;
+        let graph_name = record.name();
+        let graph = ShaderGraph::load(&record.path)?;
+        let pipeline = self.shader_graph_compiler.get_pipeline(
+            &mut self.render_thread.device(),
+            &self.scene_renderer,
+            graph_name,
+            &graph,
+        )?;
+        material_editor.set_pipeline(Some(pipeline));
+        info!(
+            "Material {} using shader graph {}",
+            material_editor.asset.name, graph_name
+        );
+        Ok(())
+    }
+
+    /// Cycles the open material between the default shader and every graph in the shader graph directory
+    fn cycle_material_shader(&mut self) -> anyhow::Result<()> {
+        let Some(material_editor) = &mut self.material_editor else {
+            return Ok(());
+        };
+
+        //Graphs are referenced by guid so renaming one doesn't break the material
+        let mut graphs: Vec<_> = self
+            .asset_database
+            .iter()
+            .filter(|(_, record)| record.asset_type == AssetType::ShaderGraph)
+            .map(|(guid, record)| (record.name().to_string(), guid))
+            .collect();
+        graphs.sort();
+        let mut shaders = vec![MaterialAsset::DEFAULT_SHADER.to_string()];
+        shaders.extend(graphs.into_iter().map(|(_, guid)| guid.to_string()));
+
+        let current_shader = match material_editor.asset.shader_graph() {
+            Some(graph) => self
+                .asset_database
+                .resolve(graph, AssetType::ShaderGraph)
+                .map(|guid| guid.to_string()),
+            None => Some(MaterialAsset::DEFAULT_SHADER.to_string()),
+        };
+        let next_index = shaders
+            .iter()
+            .position(|shader| Some(shader) == current_shader.as_ref())
+            .map(|index| (index + 1) % shaders.len())
+            .unwrap_or_default();
+        material_editor.asset.shader = shaders[next_index].clone();
+        self.compile_material_shader_graph()
+    }
+
+    /// Counts of the last submitted frame, for catching performance regressions without a gpu profiler
+    pub fn frame_stats(&mut self) -> neptune_vulkan::FrameStats {
+        self.render_thread.device().frame_stats().clone()
+    }
+
+    pub fn render(&mut self) -> anyhow::Result<()> {
+        //Everything that creates or destroys resources is done under one lock,
+        //the packet itself is built after it's released
+        let focus = self.active_camera_transform().position;
+        let mut render_graph_builder = {
+            let mut device = self.render_thread.device();
+            self.stats_overlay.update(
+                self.world.data.time.unscaled_delta(),
+                device.frame_profile(),
+                device.frame_stats(),
+                device.defragment_stats(),
+            );
+            self.stats_overlay.draw(
+                device.frame_profile(),
+                device.frame_stats(),
+                &mut self.sprite_renderer,
+            );
+
+            self.viewports
+                .update(&mut device, self.surface_size, focus)?;
+
+            self.scene_renderer.resize_targets.resize(
+                &mut device,
+                self.viewports.main_view_size(self.surface_size),
+            )?;
+            self.scene_renderer
+                .upscaler
+                .update(&mut device, &mut self.scene_renderer.resize_targets)?;
+
+            self.scene_renderer
+                .voxel_gi
+                .update(&mut device, &self.world.data.scene)?;
+
+            if self.scene_renderer.render_mode == RenderMode::PathTraced {
+                self.scene_renderer.path_tracer.update(
+                    &mut device,
+                    &mut self.scene_renderer.resize_targets,
+                    &self.world.data.scene,
+                    &self.scene_camera,
+                )?;
+            }
+
+            self.buffer_inspector.update(&mut device)?;
+            self.texture_preview.update(&mut device)?;
+            self.scene_renderer.skinning.update(&mut device);
+            if let Some(ik_debug) = &mut self.ik_debug {
+                let debug_mesh = self.world.ik_debug_mesh();
+                ik_debug.mesh.update(
+                    &mut device,
+                    debug_mesh.positions,
+                    debug_mesh.attributes,
+                    debug_mesh.indices,
+                )?;
+            }
+            self.scene_renderer.lightmap_baker.update(
+                &mut device,
+                &self.world.data.scene,
+                &self.scene_renderer.path_tracer.settings,
+            )?;
+
+            device.render_graph_builder()
+        };
+
+        let swapchain_image = render_graph_builder.acquire_swapchain_image(self.surface_handle);
+
+        self.scene_camera
+            .write_render_passes(&mut render_graph_builder);
+        self.scene_renderer
+            .lightmap_baker
+            .write_render_passes(&mut render_graph_builder);
+        //Swapped in before the scene's passes so this frame draws the new geometry
+        if let Some(ik_debug) = &mut self.ik_debug {
+            ik_debug.mesh.write_render_passes(&mut render_graph_builder);
+            self.world
+                .data
+                .scene
+                .update_instance_model(ik_debug.instance, IkDebugView::model(&ik_debug.mesh));
+        }
+        self.world
+            .data
+            .scene
+            .write_render_passes(&mut render_graph_builder);
+        if let Some(cloth_sample) = &mut self.cloth_sample {
+            cloth_sample
+                .cloth
+                .write_render_passes(&mut render_graph_builder);
+        }
+        self.scene_renderer
+            .skinning
+            .write_render_passes(&mut render_graph_builder);
+        self.viewports.write_render_passes(
+            swapchain_image,
+            self.surface_size,
+            &self.scene_camera,
+            &self.world.data.scene,
+            &mut self.scene_renderer,
+            &mut render_graph_builder,
+        );
+        //After the scene so this frame's transient buffers have been listed
+        let inspectable_buffers = self
+            .scene_renderer
+            .inspectable_buffers(&self.world.data.scene);
+        self.buffer_inspector.write_render_passes(
+            &self.scene_renderer.gpu_primitives,
+            &inspectable_buffers,
+            self.surface_size,
+            &mut self.sprite_renderer,
+            &mut render_graph_builder,
+        );
+        self.texture_preview.draw(
+            self.world.data.time.unscaled_elapsed(),
+            self.surface_size,
+            &mut self.sprite_renderer,
+        );
+        self.log_console
+            .draw(self.surface_size, &mut self.sprite_renderer);
+        if let Some(load_progress) = self.load_progress() {
+            draw_loading_screen(&mut self.sprite_renderer, self.surface_size, &load_progress);
+        } else if let Some((done, total)) = self.shader_graph_compiler.precompile_progress() {
+            draw_progress_bar(
+                &mut self.sprite_renderer,
+                self.surface_size,
+                done as f32 / total as f32,
+            );
+        }
+        self.sprite_renderer.write_render_passes(
+            swapchain_image,
+            self.surface_size,
+            &self.scene_camera,
+            &mut render_graph_builder,
+        );
+
+        //Round-trip Upload/Download Test
+        {
+            let test_data = &[127u8; 16];
+            let test_buffer = render_graph_builder.create_transient_buffer(
+                test_data.len(),
+                BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+            );
+
+            //Upload data to gpu
+            render_graph_builder.add_buffer_write(
+                BufferOffset {
+                    buffer: test_buffer,
+                    offset: 0,
+                },
+                test_data.len(),
+                BufferWriteCallback::new(move |slice| {
+                    slice.copy_from_slice(test_data);
+                }),
+            );
+
+            //Download from gpu
+            render_graph_builder.add_buffer_read(
+                BufferOffset {
+                    buffer: test_buffer,
+                    offset: 0,
+                },
+                test_data.len(),
+                BufferReadCallback::new(move |slice| {
+                    assert_eq!(
+                        slice, test_data,
+                        "Downloaded data doesn't match uploaded data"
+                    );
+                }),
+            );
+        }
+
+        self.frame_recorder.write_render_passes(
+            self.world.data.time.unscaled_delta(),
+            swapchain_image,
+            self.surface_size,
+            &mut render_graph_builder,
+        );
+
+        self.render_thread.submit(render_graph_builder)
+    }
+}
+
+struct ClothSample {
+    cloth: Cloth,
+    instance: SceneInstanceHandle,
+    transform: Transform,
+}
+
+struct IkDebugView {
+    mesh: DynamicMesh,
+    instance: SceneInstanceHandle,
+}
+
+impl IkDebugView {
+    fn model(mesh: &DynamicMesh) -> Model {
+        Model {
+            name: "IK Debug".to_string(),
+            primitives: vec![ModelPrimitive {
+                primitive: mesh.primitive(),
+                material: None,
+                lightmap: None,
+            }],
+        }
+    }
+}
+
+struct MirrorSample {
+    texture: RenderTextureHandle,
+    instance: SceneInstanceHandle,
+}
+
+impl Drop for Editor {
+    fn drop(&mut self) {
+        self.render_thread
+            .device()
+            .release_surface(self.surface_handle);
+        self.instance.destroy_surface(self.surface_handle);
+    }
+}
+
+impl WindowEventReceiver for Editor {
+    fn on_window_size_changed(&mut self, new_size: [u32; 2]) -> anyhow::Result<()> {
+        self.window_resize(new_size)
+    }
+}
+
+impl InputEventReceiver for Editor {
+    fn mouse_capture(&mut self) -> MouseCapture {
+        self.input_system.mouse_capture()
+    }
+
+    fn on_button_event(&mut self, button_name: StaticString, state: ButtonState) -> bool {
+        if button_name == "editor_search" {
+            if state.is_down() {
+                if self.search_box.is_active() {
+                    self.search_box.close();
+                } else {
+                    self.open_search();
+                }
+            }
+            return true;
+        }
+
+        //Everything else is typing while the search is open
+        if self.search_box.is_active() {
+            if state.is_down() {
+                match button_name {
+                    "search_backspace" => self.search_box.backspace(),
+                    "search_select" => {
+                        self.select_search_results();
+                        self.search_box.close();
+                    }
+                    _ => {}
+                }
+            }
+            return true;
+        }
+
+        if button_name == "sky_toggle_day_cycle" {
+            if state.is_down() {
+                let settings = &mut self.scene_renderer.sky.settings;
+                settings.day_speed = if settings.day_speed == 0.0 {
+                    Self::DAY_CYCLE_SPEED
+                } else {
+                    0.0
+                };
+            }
+            return true;
+        }
+
+        if button_name == "sky_earlier" || button_name == "sky_later" {
+            if state.is_down() {
+                let step = if button_name == "sky_later" {
+                    1.0
+                } else {
+                    -1.0
+                };
+                let settings = &mut self.scene_renderer.sky.settings;
+                settings.time_of_day = (settings.time_of_day + step).rem_euclid(24.0);
+                info!("Time of day: {:.1}", settings.time_of_day);
+            }
+            return true;
+        }
+
+        if button_name == "probe_place" {
+            if state.is_down() {
+                let position = self.active_camera_transform().position;
+                let probes = &mut self.world.data.scene.reflection_probes;
+                if probes.len() >= ReflectionProbes::MAX_PROBES {
+                    warn!(
+                        "Only {} reflection probes are supported",
+                        ReflectionProbes::MAX_PROBES
+                    );
+                } else {
+                    probes.push(ReflectionProbe {
+                        position,
+                        ..Default::default()
+                    });
+                    info!(
+                        "Placed reflection probe {} at {}",
+                        probes.len() - 1,
+                        position
+                    );
+                }
+            }
+            return true;
+        }
+
+        if button_name == "post_volume_toggle" {
+            if state.is_down() {
+                let position = self.active_camera_transform().position;
+                let has_luts = !self.scene_renderer.color_grading.luts().is_empty();
+                let volumes = &mut self.world.data.scene.post_volumes;
+                //Removes the volume the camera is standing in, otherwise places a darker and foggier one around it
+                if let Some(index) = volumes
+                    .iter()
+                    .position(|volume| volume.weight(position) >= 1.0)
+                {
+                    volumes.remove(index);
+                    info!("Removed post process volume {}", index);
+                } else {
+                    volumes.push(PostProcessVolume {
+                        center: position,
+                        overrides: PostVolumeOverrides {
+                            exposure: Some(-1.0),
+                            fog_density: Some(0.1),
+                            lut: has_luts.then_some(0),
+                        },
+                        ..Default::default()
+                    });
+                    info!("Placed post process volume at {}", position);
+                }
+            }
+            return true;
+        }
+
+        if button_name == "cell_place" {
+            if state.is_down() {
+                let position = self.active_camera_transform().position;
+                let cells = &mut self.world.data.scene.cells;
+                cells.cells.push(VisibilityCell {
+                    bounds: BoundingBox {
+                        min: position - Vec3::new(4.0, 2.0, 4.0),
+                        max: position + Vec3::new(4.0, 2.0, 4.0),
+                    },
+                });
+                info!(
+                    "Placed visibility cell {} at {}",
+                    cells.cells.len() - 1,
+                    position
+                );
+            }
+            return true;
+        }
+
+        if button_name == "cell_place_portal" {
+            if state.is_down() {
+                let position = self.active_camera_transform().position;
+                let added = self.world.data.scene.cells.add_portal(BoundingBox {
+                    min: position - Vec3::new(1.0, 1.5, 1.0),
+                    max: position + Vec3::new(1.0, 1.5, 1.0),
+                });
+                if added == 0 {
+                    warn!("Portals need to touch at least two cells");
+                } else {
+                    info!("Placed portal at {}", position);
+                }
+            }
+            return true;
+        }
+
+        if button_name == "cell_toggle_debug" {
+            if state.is_down() {
+                if let Err(err) = self.toggle_cell_debug() {
+                    error!("Failed to create cell debug mesh: {:#}", err);
+                }
+            }
+            return true;
+        }
+
+        if button_name == "cell_toggle_culling" {
+            if state.is_down() {
+                self.scene_renderer.portal_culling = !self.scene_renderer.portal_culling;
+                info!("Portal Culling: {}", self.scene_renderer.portal_culling);
+            }
+            return true;
+        }
+
+        if button_name == "probe_rebake" {
+            if state.is_down() {
+                self.scene_renderer.reflection_probes.rebake();
+                info!("Rebaking reflection probes");
+            }
+            return true;
+        }
+
+        if button_name == "editor_toggle_recording" {
+            if state.is_down() {
+                self.frame_recorder
+                    .toggle(self.surface_size, self.surface_format.format);
+            }
+            return true;
+        }
+
+        if button_name == "debug_toggle_buffer_inspector" {
+            if state.is_down() {
+                self.buffer_inspector.toggle();
+            }
+            return true;
+        }
+
+        if button_name == "inspector_next_buffer" || button_name == "inspector_previous_buffer" {
+            if state.is_down() {
+                let buffers = self
+                    .scene_renderer
+                    .inspectable_buffers(&self.world.data.scene);
+                let offset = if button_name == "inspector_next_buffer" {
+                    1
+                } else {
+                    -1
+                };
+                self.buffer_inspector.select_next(&buffers, offset);
+            }
+            return true;
+        }
+
+        if button_name == "animation_next_state" {
+            if state.is_down() {
+                self.next_animation_state();
+            }
+            return true;
+        }
+
+        if button_name == "animation_toggle_ik_debug" {
+            if state.is_down() {
+                if let Err(err) = self.toggle_ik_debug() {
+                    error!("Failed to create ik debug mesh: {:#}", err);
+                }
+            }
+            return true;
+        }
+
+        if button_name == "texture_preview_cycle_grid" {
+            if state.is_down() {
+                self.texture_preview.cycle_grid();
+            }
+            return true;
+        }
+
+        if button_name == "texture_preview_close" {
+            if state.is_down() {
+                self.texture_preview.close();
+            }
+            return true;
+        }
+
+        if button_name == "texture_preview_brighter" || button_name == "texture_preview_darker" {
+            if state.is_down() {
+                let steps = if button_name == "texture_preview_brighter" {
+                    1
+                } else {
+                    -1
+                };
+                self.texture_preview.step_exposure(steps);
+            }
+            return true;
+        }
+
+        if button_name == "inspector_dump" {
+            if state.is_down() {
+                self.buffer_inspector.request_dump();
+            }
+            return true;
+        }
+
+        if button_name == "debug_toggle_stats" {
+            if state.is_down() {
+                self.stats_overlay.toggle();
+            }
+            return true;
+        }
+
+        if button_name == "debug_toggle_console" {
+            if state.is_down() {
+                self.log_console.toggle();
+            }
+            return true;
+        }
+
+        if button_name == "debug_console_cycle_category" {
+            if state.is_down() {
+                self.log_console.cycle_category();
+            }
+            return true;
+        }
+
+        if button_name == "debug_console_cycle_level" {
+            if state.is_down() {
+                self.log_console.cycle_level();
+            }
+            return true;
+        }
+
+        if button_name == "debug_toggle_path_tracer" {
+            if state.is_down() {
+                self.scene_renderer.render_mode = match self.scene_renderer.render_mode {
+                    RenderMode::Raster => RenderMode::PathTraced,
+                    RenderMode::PathTraced => RenderMode::Raster,
+                };
+                info!("Render Mode: {:?}", self.scene_renderer.render_mode);
+            }
+            return true;
+        }
+
+        if button_name == "debug_toggle_pause" {
+            if state.is_down() {
+                let time = &mut self.world.data.time;
+                time.set_paused(!time.is_paused());
+                info!(
+                    "Paused: {} at frame {} ({:.2}s game time)",
+                    time.is_paused(),
+                    time.frame_count(),
+                    time.elapsed()
+                );
+            }
+            return true;
+        }
+
+        if button_name == "debug_step_frame" {
+            if state.is_down() {
+                self.world.data.time.step();
+            }
+            return true;
+        }
+
+        if button_name == "debug_toggle_slow_motion" {
+            if state.is_down() {
+                let time = &mut self.world.data.time;
+                time.time_scale = if time.time_scale < 1.0 { 1.0 } else { 0.25 };
+                info!("Time Scale: {}", time.time_scale);
+            }
+            return true;
+        }
+
+        if button_name == "editor_toggle_viewport_layout" {
+            if state.is_down() {
+                self.viewports.layout = self.viewports.layout.next();
+                if self.viewports.layout == ViewportLayout::Split
+                    && self.viewports.split_views.is_empty()
+                {
+                    if let Err(err) = self.add_split_view() {
+                        error!("Failed to add split view: {:#}", err);
+                    }
+                }
+                info!("Viewport Layout: {:?}", self.viewports.layout);
+            }
+            return true;
+        }
+
+        if button_name == "editor_toggle_2d_camera" {
+            if state.is_down() {
+                self.camera_2d = match self.camera_2d {
+                    Some(_) => None,
+                    None => Some(Camera2d {
+                        position: self.camera_transform.position.truncate(),
+                        ..Default::default()
+                    }),
+                };
+                info!("2D Camera: {}", self.camera_2d.is_some());
+            }
+            return true;
+        }
+
+        if let Some(camera_2d) = &mut self.camera_2d {
+            let handled = match button_name {
+                "camera_2d_zoom_in" => {
+                    if state.is_down() {
+                        camera_2d.zoom_in();
+                    }
+                    true
+                }
+                "camera_2d_zoom_out" => {
+                    if state.is_down() {
+                        camera_2d.zoom_out();
+                    }
+                    true
+                }
+                "camera_2d_toggle_pixel_perfect" => {
+                    if state.is_down() {
+                        camera_2d.pixel_perfect = !camera_2d.pixel_perfect;
+                    }
+                    true
+                }
+                _ => false,
+            };
+            if handled {
+                if state.is_down() {
+                    info!(
+                        "2D Camera Zoom: {}x Pixel Perfect: {}",
+                        camera_2d.zoom, camera_2d.pixel_perfect
+                    );
+                }
+                return true;
+            }
+        }
+
+        if button_name == "editor_add_split_view" {
+            if state.is_down() {
+                self.viewports.layout = ViewportLayout::Split;
+                if self.viewports.split_views.len() >= Self::MAX_SPLIT_VIEWS {
+                    self.viewports.split_views.truncate(1);
+                    info!("Split Views: 2");
+                } else {
+                    match self.add_split_view() {
+                        Ok(()) => info!("Split Views: {}", self.viewports.split_views.len() + 1),
+                        Err(err) => error!("Failed to add split view: {:#}", err),
+                    }
+                }
+            }
+            return true;
+        }
+
+        if button_name == "editor_copy" || button_name == "editor_cut" {
+            if state.is_down() {
+                let entities = self.copy_selection();
+                info!("Copied {} entities", entities.len());
+                self.clipboard.set(entities);
+                if button_name == "editor_cut" {
+                    self.delete_selection();
+                }
+            }
+            return true;
+        }
+
+        if button_name == "editor_paste" {
+            if state.is_down() && !self.clipboard.is_empty() {
+                self.spawn_entities(self.clipboard.copies());
+            }
+            return true;
+        }
+
+        if button_name == "editor_spawn_shape" {
+            if state.is_down() {
+                if let Err(err) = self.spawn_shape() {
+                    error!("Failed to spawn shape: {:#}", err);
+                }
+            }
+            return true;
+        }
+
+        if button_name == "editor_duplicate" {
+            if state.is_down() {
+                let entities = self.copy_selection();
+                self.spawn_entities(entities);
+            }
+            return true;
+        }
+
+        if button_name == "editor_rotate_selection" {
+            if state.is_down() {
+                self.rotate_selection();
+            }
+            return true;
+        }
+
+        if button_name == "editor_delete" {
+            if state.is_down() {
+                self.delete_selection();
+            }
+            return true;
+        }
+
+        if button_name == "editor_undo" || button_name == "editor_redo" {
+            if state.is_down() {
+                self.undo_edit(button_name == "editor_redo");
+            }
+            return true;
+        }
+
+        if button_name == "editor_toggle_mouse_lock" {
+            if state.is_down() {
+                self.input_system.toggle_relative_mouse();
+                info!("Mouse Lock: {}", self.input_system.mouse_capture().relative);
+            }
+            return true;
+        }
+
+        if button_name == "editor_cycle_aspect_lock" {
+            if state.is_down() {
+                self.viewports.aspect_lock = self.viewports.aspect_lock.next();
+                info!("Aspect Ratio Lock: {:?}", self.viewports.aspect_lock);
+            }
+            return true;
+        }
+
+        if button_name == "post_cycle_lut" || button_name == "post_cycle_secondary_lut" {
+            if state.is_down() {
+                let color_grading = &mut self.scene_renderer.color_grading;
+                let lut_count = color_grading.luts().len();
+                let lut = if button_name == "post_cycle_lut" {
+                    &mut color_grading.settings.lut
+                } else {
+                    &mut color_grading.settings.secondary_lut
+                };
+                ColorGrading::cycle_lut(lut, lut_count);
+                info!(
+                    "Color Grading: {} -> {} ({:.2})",
+                    color_grading.lut_name(color_grading.settings.lut),
+                    color_grading.lut_name(color_grading.settings.secondary_lut),
+                    color_grading.settings.blend
+                );
+            }
+            return true;
+        }
+
+        if button_name == "post_cycle_lut_blend" {
+            if state.is_down() {
+                let settings = &mut self.scene_renderer.color_grading.settings;
+                settings.blend = if settings.blend >= 1.0 {
+                    0.0
+                } else {
+                    (settings.blend + 0.25).min(1.0)
+                };
+                info!("Color Grading Blend: {:.2}", settings.blend);
+            }
+            return true;
+        }
+
+        if button_name == "render_toggle_particles" {
+            if state.is_down() {
+                let camera_transform = self.active_camera_transform();
+                let settings = &mut self.scene_renderer.particles.settings;
+                settings.enabled = !settings.enabled;
+                if settings.enabled {
+                    //Emit a little in front of the camera so the particles land on whatever it's looking at
+                    settings.emitter_position =
+                        camera_transform.position + camera_transform.rotation * Vec3::NEG_Z * 3.0;
+                }
+                info!("Particles: {}", settings.enabled);
+            }
+            return true;
+        }
+
+        if button_name == "render_toggle_particle_blend" {
+            if state.is_down() {
+                let settings = &mut self.scene_renderer.particles.settings;
+                settings.blend = match settings.blend {
+                    ParticleBlend::Additive => ParticleBlend::AlphaBlend,
+                    ParticleBlend::AlphaBlend => ParticleBlend::Additive,
+                };
+                info!("Particle Blend: {:?}", settings.blend);
+            }
+            return true;
+        }
+
+        if button_name == "render_toggle_water" {
+            if state.is_down() {
+                let water_surfaces = &mut self.world.data.scene.water_surfaces;
+                if water_surfaces.is_empty() {
+                    //Just above the ground so the refraction and absorption have something to show
+                    water_surfaces.push(WaterSurface {
+                        height: 0.25,
+                        ..Default::default()
+                    });
+                } else {
+                    water_surfaces.clear();
+                }
+                info!("Water: {}", !water_surfaces.is_empty());
+            }
+            return true;
+        }
+
+        if button_name == "render_toggle_voxel_gi" {
+            if state.is_down() {
+                let settings = &mut self.scene_renderer.voxel_gi.settings;
+                settings.enabled = !settings.enabled;
+                info!("Voxel GI: {}", settings.enabled);
+            }
+            return true;
+        }
+
+        if button_name == "foliage_toggle_sample" {
+            if state.is_down() {
+                if let Err(err) = self.toggle_foliage_sample() {
+                    error!("Failed to create foliage sample: {:#}", err);
+                }
+            }
+            return true;
+        }
+
+        if button_name == "foliage_paint" {
+            if state.is_down() {
+                if let Err(err) = self.paint_foliage() {
+                    error!("Failed to paint foliage: {:#}", err);
+                }
+            }
+            return true;
+        }
+
+        if button_name == "cloth_toggle_sample" {
+            if state.is_down() {
+                if let Err(err) = self.toggle_cloth_sample() {
+                    error!("Failed to create cloth sample: {:#}", err);
+                }
+            }
+            return true;
+        }
+
+        if button_name == "render_toggle_mirror_sample" {
+            if state.is_down() {
+                if let Err(err) = self.toggle_mirror_sample() {
+                    error!("Failed to create mirror sample: {:#}", err);
+                }
+            }
+            return true;
+        }
+
+        if button_name == "cloth_cycle_pinning" {
+            if state.is_down() {
+                if let Some(cloth_sample) = &mut self.cloth_sample {
+                    let settings = &mut cloth_sample.cloth.settings;
+                    settings.pinning = match settings.pinning {
+                        ClothPinning::TopEdge => ClothPinning::TopCorners,
+                        ClothPinning::TopCorners => ClothPinning::None,
+                        ClothPinning::None => ClothPinning::TopEdge,
+                    };
+                    //Re-pinning a fallen cloth would yank it back up, start over instead
+                    if settings.pinning == ClothPinning::TopEdge {
+                        cloth_sample.cloth.reset();
+                    }
+                    info!("Cloth Pinning: {:?}", cloth_sample.cloth.settings.pinning);
+                }
+            }
+            return true;
+        }
+
+        if button_name == "cloth_cycle_wind" {
+            if state.is_down() {
+                if let Some(cloth_sample) = &mut self.cloth_sample {
+                    let settings = &mut cloth_sample.cloth.settings;
+                    let direction = Vec3::new(0.8, 0.0, 0.6);
+                    let speed = match settings.wind.length() {
+                        speed if speed < 1.0 => 3.5,
+                        speed if speed < 6.0 => 10.0,
+                        _ => 0.0,
+                    };
+                    settings.wind = direction * speed;
+                    info!("Cloth Wind: {} m/s", speed);
+                }
+            }
+            return true;
+        }
+
+        if button_name == "render_cycle_upscaler" {
+            if state.is_down() {
+                let upscaler = &mut self.scene_renderer.upscaler;
+                upscaler.settings.mode = match upscaler.settings.mode {
+                    UpscalerMode::Off => UpscalerMode::Spatial,
+                    UpscalerMode::Spatial => UpscalerMode::Temporal,
+                    UpscalerMode::Temporal => UpscalerMode::Checkerboard,
+                    UpscalerMode::Checkerboard => UpscalerMode::Off,
+                };
+                info!(
+                    "Upscaler: {:?} ({} render scale)",
+                    upscaler.settings.mode,
+                    upscaler.render_scale()
+                );
+            }
+            return true;
+        }
+
+        if button_name == "render_cycle_effect_scale" {
+            if state.is_down() {
+                let reduced_resolution = &mut self.scene_renderer.reduced_resolution;
+                reduced_resolution.settings.scale = match reduced_resolution.scale() {
+                    scale if scale >= 1.0 => 0.5,
+                    scale if scale >= 0.5 => ReducedResolution::MIN_SCALE,
+                    _ => 1.0,
+                };
+                info!("Effect Resolution Scale: {}", reduced_resolution.scale());
+            }
+            return true;
+        }
+
+        if button_name == "post_toggle_dithering" {
+            if state.is_down() {
+                self.scene_renderer.dithering = !self.scene_renderer.dithering;
+                info!(
+                    "Dithering: {} ({:?})",
+                    self.scene_renderer.dithering,
+                    self.scene_renderer.output_format()
+                );
+            }
+            return true;
+        }
+
+        if button_name == "post_toggle_vignette"
+            || button_name == "post_toggle_chromatic_aberration"
+            || button_name == "post_toggle_film_grain"
+        {
+            if state.is_down() {
+                let post_effects = &mut self.world.data.scene.post_effects;
+                let enabled = match button_name {
+                    "post_toggle_vignette" => &mut post_effects.vignette.enabled,
+                    "post_toggle_chromatic_aberration" => {
+                        &mut post_effects.chromatic_aberration.enabled
+                    }
+                    _ => &mut post_effects.film_grain.enabled,
+                };
+                *enabled = !*enabled;
+                info!("Post Effects: {:?}", post_effects);
+                if let Err(err) = post_effects.save(Self::POST_EFFECTS_PATH) {
+                    error!("{:#}", err);
+                }
+            }
+            return true;
+        }
+
+        if button_name == "editor_toggle_grid" {
+            if state.is_down() {
+                let settings = &mut self.scene_renderer.viewport_helpers.settings;
+                settings.show_grid = !settings.show_grid;
+            }
+            return true;
+        }
+
+        if button_name == "editor_toggle_axis_gizmo" {
+            if state.is_down() {
+                let settings = &mut self.scene_renderer.viewport_helpers.settings;
+                settings.show_axis_gizmo = !settings.show_axis_gizmo;
+            }
+            return true;
+        }
+
+        if button_name == "editor_grow_selection" {
+            if state.is_down() {
+                self.grow_selection();
+            }
+            return true;
+        }
+
+        if button_name == "editor_frame_selected" {
+            if state.is_down() {
+                self.frame_selection();
+            }
+            return true;
+        }
+
+        if button_name == "editor_add_bookmark" {
+            if state.is_down() {
+                let index = self.camera_bookmarks.add(&self.camera_transform);
+                info!("Added Camera Bookmark {}", index);
+            }
+            return true;
+        }
+
+        if button_name == "editor_next_bookmark" || button_name == "editor_previous_bookmark" {
+            if state.is_down() {
+                let bookmark = if button_name == "editor_next_bookmark" {
+                    self.camera_bookmarks.next()
+                } else {
+                    self.camera_bookmarks.previous()
+                }
+                .map(|(index, transform)| (index, transform.clone()));
+
+                if let Some((index, transform)) = bookmark {
+                    info!("Camera Bookmark {}", index);
+                    self.fly_camera_to(&transform);
+                }
+            }
+            return true;
+        }
+
+        if button_name == "navmesh_walk_selection" {
+            if state.is_down() {
+                self.walk_selection();
+            }
+            return true;
+        }
+
+        if button_name == "editor_build_navmesh" {
+            if state.is_down() {
+                if let Err(err) = self.build_navmesh() {
+                    error!("Failed to build navmesh: {:#}", err);
+                }
+            }
+            return true;
+        }
+
+        if button_name == "editor_open_material" {
+            if state.is_down() {
+                if let Err(err) = self.open_material_editor() {
+                    error!("Failed to open material editor: {}", err);
+                }
+            }
+            return true;
+        }
+
+        if let Some(material_editor) = &mut self.material_editor {
+            let handled = match button_name {
+                "material_next_parameter" => {
+                    if state.is_down() {
+                        material_editor.select_next_parameter();
+                    }
+                    true
+                }
+                "material_previous_parameter" => {
+                    if state.is_down() {
+                        material_editor.select_previous_parameter();
+                    }
+                    true
+                }
+                "material_increase_parameter" | "material_decrease_parameter" => {
+                    if state.is_down() {
+                        let delta = if button_name == "material_increase_parameter" {
+                            MaterialEditorPanel::ADJUST_STEP
+                        } else {
+                            -MaterialEditorPanel::ADJUST_STEP
+                        };
+                        material_editor.adjust_selected(delta);
+                    }
+                    true
+                }
+                "material_compile_shader_graph" => {
+                    if state.is_down() {
+                        if let Err(err) = self.compile_material_shader_graph() {
+                            error!("Failed to compile shader graph: {:#}", err);
+                        }
+                    }
+                    true
+                }
+                "material_cycle_shader" => {
+                    if state.is_down() {
+                        if let Err(err) = self.cycle_material_shader() {
+                            error!("Failed to change material shader: {:#}", err);
+                        }
+                    }
+                    true
+                }
+                "material_save" => {
+                    if state.is_down() {
+                        if let Err(err) = material_editor.save(&self.asset_database) {
+                            error!("Failed to save material: {}", err);
+                        }
+                        //A new material needs a meta file before other assets can depend on it
+                        self.asset_database.refresh();
+                    }
+                    true
+                }
+                _ => false,
+            };
+            if handled {
+                return true;
+            }
+        }
+
+        if button_name == "editor_select" {
+            if state.is_down() {
+                self.pick_selection();
+            }
+            return true;
+        }
+
+        if let Some(player) = &mut self.world.entities.player {
+            return player.on_button_event(button_name, state);
+        }
+
+        false
+    }
+
+    fn on_axis_event(&mut self, axis_name: StaticString, value: f32) -> bool {
+        if self.search_box.is_active() {
+            return true;
+        }
+
+        if let Some(player) = &mut self.world.entities.player {
+            return player.on_axis_event(axis_name, value);
+        }
+
+        match axis_name {
+            "player_move_left_right" => {
+                self.camera_move_input.x = value;
+                true
+            }
+            "player_move_up_down" => {
+                self.camera_move_input.y = value;
+                true
+            }
+            "player_move_forward_back" => {
+                self.camera_move_input.z = value;
+                true
+            }
+            "player_move_yaw" => {
+                self.camera_rotate_input.y = value;
+                true
+            }
+            "player_move_pitch" => {
+                self.camera_rotate_input.x = value;
+                true
+            }
+
+            _ => false,
+        }
+    }
+
+    fn on_text_event(&mut self, text: String) -> bool {
+        if self.search_box.is_active() {
+            self.search_box.push_text(&text);
+            return true;
+        }
+        false
+    }
+}
+
+pub(crate) fn create_test_world(
+    device: &mut neptune_vulkan::Device,
+    primitive_cache: &mut PrimitiveCache,
+    scene_renderer: &mut SceneRenderer,
+    asset_database: &AssetDatabase,
+    vfs: &Vfs,
+    model_path: Option<&std::path::Path>,
+    static_batching: StaticBatching,
+) -> anyhow::Result<World> {
+    let material_palette = &scene_renderer.material_palette;
+    let texture_cache =
+        TextureCache::new(DerivedDataCache::new(DerivedDataCache::DEFAULT_DIRECTORY));
+    let gltf_data = load_gltf_resources(
+        device,
+        &texture_cache,
+        primitive_cache,
+        material_palette,
+        vfs,
+        "neptune_editor/resource/NeptuneResources.glb",
+    )?;
+
+    info!("Available Meshes: {:?}", gltf_data.meshes.keys());
+    info!("Available Materials: {:?}", gltf_data.materials.keys());
+
+    let mut world = World {
+        data: WorldData {
+            scene: Scene::new(device, 1024)?,
+            hierarchy: Default::default(),
+            physics: PhysicsWorld::new(),
+            navmesh: None,
+            events: EventBus::default(),
+            time: Time::default(),
+        },
+        entities: Default::default(),
+    };
+    world.data.events.send(AssetLoaded {
+        path: "neptune_editor/resource/NeptuneResources.glb".into(),
+    });
+    if std::path::Path::new(Editor::POST_EFFECTS_PATH).exists() {
+        world.data.scene.post_effects = PostEffectSettings::load(Editor::POST_EFFECTS_PATH)?;
+    }
+
+    let purple_cube_model = Model {
+        name: "PurpleCube".to_string(),
+        primitives: vec![ModelPrimitive {
+            primitive: gltf_data.meshes["Cube"].primitives[0].clone(),
+            material: gltf_data.materials.get("Purple").cloned().map(Arc::new),
+            lightmap: None,
+        }],
+    };
+
+    let orange_cube_model = Model {
+        name: "OrangeCube".to_string(),
+        primitives: vec![ModelPrimitive {
+            primitive: gltf_data.meshes["Cube"].primitives[0].clone(),
+            material: gltf_data.materials.get("Orange").cloned().map(Arc::new),
+            lightmap: None,
+        }],
+    };
+
+    {
         let ground_size = Vec3::new(8.0, 0.5, 8.0);
         world.add_static_entity(StaticEntity::new(
             Transform {
@@ -391,6 +2791,32 @@ fn create_test_world(device: &mut neptune_vulkan::Device) -> anyhow::Result<Worl
         ));
     }
 
+    if let Some(model_path) = model_path {
+        let mut model_scene = load_model_scene(
+            device,
+            &texture_cache,
+            primitive_cache,
+            material_palette,
+            model_path,
+            static_batching,
+        )?;
+        apply_material_assets(
+            device,
+            &texture_cache,
+            material_palette,
+            asset_database,
+            &mut model_scene,
+        );
+        add_model_scene(
+            &mut world,
+            device,
+            &mut scene_renderer.lightmap_baker,
+            &mut scene_renderer.skinning,
+            model_path,
+            &model_scene,
+        );
+    }
+
     world.add_player(Player::with_position(Vec3::Y * 3.0));
 
     //Ship
@@ -417,22 +2843,250 @@ fn create_test_world(device: &mut neptune_vulkan::Device) -> anyhow::Result<Worl
             ],
             transform: Transform::with_position(Vec3::Y * 5.0 + Vec3::Z * 2.0),
             rigid_body_handle: None,
+            node: None,
             modules: vec![],
         };
         world.add_ship(ship);
+
+        //Beacon riding on top of the ship
+        let ship_node = world.ships().last().and_then(|ship| ship.node);
+        let beacon = world.add_static_entity(StaticEntity::new(
+            Transform {
+                scale: Vec3::splat(0.25),
+                ..Default::default()
+            },
+            orange_cube_model.clone(),
+            None,
+        ));
+        if let (Some(ship_node), Some(beacon)) = (ship_node, beacon) {
+            if !world.attach_static_entity(
+                beacon,
+                ship_node,
+                "Module 2",
+                Transform {
+                    position: Vec3::Y * 1.25,
+                    scale: Vec3::splat(0.25),
+                    ..Default::default()
+                },
+            ) {
+                warn!("Failed to attach the beacon to the ship");
+            }
+        }
     }
 
     Ok(world)
 }
 
+/// Places every node of a loaded model as a static entity, queuing a bake for the lightmapped ones
+/// Skinned nodes use the model's authored animation graph if it has one,
+/// otherwise they get a state per clip, see `AnimationGraph::from_clips`
+fn add_model_scene(
+    world: &mut World,
+    device: &mut neptune_vulkan::Device,
+    lightmap_baker: &mut LightmapBaker,
+    skinning: &mut Skinning,
+    model_path: &std::path::Path,
+    model_scene: &GltfScene,
+) {
+    const ANIMATION_BLEND_TIME: f32 = 0.25;
+
+    world.data.events.send(AssetLoaded {
+        path: model_path.to_path_buf(),
+    });
+    let materials: Vec<Arc<Material>> = model_scene
+        .materials
+        .iter()
+        .cloned()
+        .map(Arc::new)
+        .collect();
+
+    let graph_path = model_path
+        .file_stem()
+        .map(|name| {
+            std::path::Path::new(Editor::ANIMATION_GRAPH_DIRECTORY)
+                .join(format!("{}.toml", name.to_string_lossy()))
+        })
+        .filter(|path| path.exists());
+    let graph_asset = graph_path.and_then(|path| {
+        AnimationGraphAsset::load(path)
+            .map_err(|err| error!("{:#}", err))
+            .ok()
+    });
+
+    for node in model_scene.mesh_nodes.iter() {
+        let mesh = &model_scene.meshes[node.mesh_index];
+        let lightmap = node.lightmap.as_ref().and_then(|layout| {
+            lightmap_baker
+                .add(
+                    device,
+                    &format!("{} Lightmap", mesh.name),
+                    layout,
+                    &node.transform,
+                )
+                .map_err(|err| error!("Failed to create the lightmap of {}: {:#}", mesh.name, err))
+                .ok()
+        });
+
+        let mut primitives = mesh.primitives.clone();
+        let mut transform = node.transform;
+        let mut animation = None;
+        if let Some(skin) = node.skin.and_then(|index| model_scene.skins.get(index)) {
+            match skinning.add(
+                device,
+                &mesh.name,
+                &mesh.primitives,
+                skin.skeleton.joints.len(),
+            ) {
+                Ok((skin_handle, skinned_primitives)) => {
+                    primitives = skinned_primitives;
+                    transform = skin.transform;
+                    let graph = graph_asset
+                        .as_ref()
+                        .and_then(|graph_asset| {
+                            graph_asset
+                                .create_graph(&skin.animations)
+                                .map_err(|err| error!("{:#}", err))
+                                .ok()
+                        })
+                        .unwrap_or_else(|| {
+                            AnimationGraph::from_clips(&skin.animations, ANIMATION_BLEND_TIME)
+                        });
+                    let mut player = AnimationPlayer::new(Arc::new(graph), skin.skeleton.clone());
+                    *player.ik_rig_mut() = IkRig::humanoid(&skin.skeleton);
+                    animation = Some((player, skin_handle));
+                }
+                Err(err) => error!("Failed to skin {}: {:#}", mesh.name, err),
+            }
+        }
+
+        let model = Model {
+            name: mesh.name.clone(),
+            primitives: primitives
+                .iter()
+                .zip(node.primitive_materials.iter())
+                .map(|(primitive, &material)| ModelPrimitive {
+                    primitive: primitive.clone(),
+                    material: materials.get(material).cloned(),
+                    lightmap: lightmap.clone(),
+                })
+                .collect(),
+        };
+        let mut entity = StaticEntity::new(Transform::decompose(&transform), model, None);
+        if let Some((player, skin_handle)) = animation {
+            entity = entity.with_animation(player, skin_handle);
+        }
+        world.add_static_entity(entity);
+    }
+}
+
+/// Swaps each loaded material that has a saved material asset of the same name for one created from that asset,
+/// so edits made in the material editor are still there the next time the model is loaded
+fn apply_material_assets(
+    device: &mut neptune_vulkan::Device,
+    texture_cache: &TextureCache,
+    material_palette: &MaterialPalette,
+    asset_database: &AssetDatabase,
+    model_scene: &mut GltfScene,
+) {
+    let sampler = model_scene.samplers.default;
+    for material in model_scene.materials.iter_mut() {
+        let Some(record) = asset_database
+            .find(&material.name, AssetType::Material)
+            .and_then(|guid| asset_database.get(guid))
+        else {
+            continue;
+        };
+
+        let images = &mut model_scene.images;
+        let result = MaterialAsset::load(&record.path).and_then(|asset| {
+            asset.create_material(material_palette, |reference| {
+                let texture_record = asset_database
+                    .resolve(reference, AssetType::Texture)
+                    .and_then(|guid| asset_database.get(guid))?;
+                let image = texture_cache
+                    .load_file(&texture_record.path, TextureImportSettings::default())
+                    .and_then(|texture| texture.create_image(device, texture_record.name()))
+                    .map_err(|err| warn!("{:#}", err))
+                    .ok()?;
+                images.push(image);
+                Some(MaterialTexture {
+                    image,
+                    sampler,
+                    uv_index: 0,
+                })
+            })
+        });
+        match result {
+            Ok(asset_material) => {
+                info!(
+                    "Material {} loaded from {}",
+                    material.name,
+                    record.path.display()
+                );
+                *material = asset_material;
+            }
+            Err(err) => warn!("{:#}", err),
+        }
+    }
+}
+
 /// Simple Render Graph to clear the screen before asset loading happens
+/// 8-bit unorm is always used unless `ten_bit` is set and the surface supports a 10-bit sRGB format
+/// FIFO always vsyncs, without vsync mailbox is preferred since it doesn't tear
+fn select_present_mode(
+    device: &neptune_vulkan::Device,
+    surface_handle: neptune_vulkan::SurfaceHandle,
+    vsync: bool,
+) -> anyhow::Result<vk::PresentModeKHR> {
+    if vsync {
+        return Ok(vk::PresentModeKHR::FIFO);
+    }
+
+    let supported_modes = device.get_surface_present_modes(surface_handle)?;
+    Ok([vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::IMMEDIATE]
+        .into_iter()
+        .find(|mode| supported_modes.contains(mode))
+        .unwrap_or_else(|| {
+            warn!("Surface only supports vsync, presenting with FIFO");
+            vk::PresentModeKHR::FIFO
+        }))
+}
+
+fn select_surface_format(
+    device: &neptune_vulkan::Device,
+    surface_handle: neptune_vulkan::SurfaceHandle,
+    ten_bit: bool,
+) -> anyhow::Result<vk::SurfaceFormatKHR> {
+    const FALLBACK_FORMAT: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR {
+        format: vk::Format::B8G8R8A8_UNORM,
+        color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+    };
+
+    if !ten_bit {
+        return Ok(FALLBACK_FORMAT);
+    }
+
+    let supported_formats = device.get_surface_formats(surface_handle)?;
+    let ten_bit_format = supported_formats.into_iter().find(|surface_format| {
+        surface_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+            && matches!(
+                surface_format.format,
+                vk::Format::A2B10G10R10_UNORM_PACK32 | vk::Format::A2R10G10B10_UNORM_PACK32
+            )
+    });
+
+    if ten_bit_format.is_none() {
+        warn!("10-bit output isn't supported by this surface, falling back to 8-bit");
+    }
+    Ok(ten_bit_format.unwrap_or(FALLBACK_FORMAT))
+}
+
 fn clear_surfaces(
     device: &mut neptune_vulkan::Device,
     color: [f32; 3],
     surface_handles: &[neptune_vulkan::SurfaceHandle],
 ) -> anyhow::Result<()> {
-    let mut render_graph_builder =
-        neptune_vulkan::basic_render_graph_builder::BasicRenderGraphBuilder::default();
+    let mut render_graph_builder = device.render_graph_builder();
 
     for handle in surface_handles {
         let swapchain_image = render_graph_builder.acquire_swapchain_image(*handle);
@@ -444,6 +3098,25 @@ fn clear_surfaces(
     }
 
     let render_graph = render_graph_builder.build();
-    device.submit_graph(&render_graph)?;
+    device.submit_graph(render_graph)?;
     Ok(())
 }
+
+/// Removes an overlay instance made from a procedural mesh and frees its buffers
+fn remove_debug_instance(
+    device: &mut neptune_vulkan::Device,
+    scene: &mut Scene,
+    handle: SceneInstanceHandle,
+) {
+    if let Some(model) = scene.get_model(handle) {
+        for model_primitive in model.primitives.iter() {
+            let primitive = &model_primitive.primitive;
+            device.destroy_buffer(primitive.position_buffer);
+            device.destroy_buffer(primitive.attributes_buffer);
+            if let Some(index_buffer) = &primitive.index_buffer {
+                device.destroy_buffer(index_buffer.buffer);
+            }
+        }
+    }
+    scene.remove_instance(handle);
+}
@@ -0,0 +1,98 @@
+use crate::transform::Transform;
+use rapier3d::geometry::ColliderHandle;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// Engine events, anything 'static can be sent so game code can add its own types
+
+#[derive(Debug, Clone)]
+pub struct WindowResized {
+    pub size: [u32; 2],
+}
+
+#[derive(Debug, Clone)]
+pub struct AssetLoaded {
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone)]
+pub struct EntitySpawned {
+    pub kind: &'static str,
+    pub transform: Transform,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct CollisionEvent {
+    pub collider1: ColliderHandle,
+    pub collider2: ColliderHandle,
+    /// False when the colliders stopped touching
+    pub started: bool,
+}
+
+trait AnyEventQueue {
+    fn swap(&mut self);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+struct EventQueue<T> {
+    pending: Vec<T>,
+    current: Vec<T>,
+}
+
+impl<T: 'static> AnyEventQueue for EventQueue<T> {
+    fn swap(&mut self) {
+        self.current.clear();
+        std::mem::swap(&mut self.current, &mut self.pending);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Typed events between subsystems. Events sent during a frame are delivered to every reader on the next frame,
+/// so the order subsystems update in doesn't matter
+#[derive(Default)]
+pub struct EventBus {
+    queues: HashMap<TypeId, Box<dyn AnyEventQueue>>,
+}
+
+impl EventBus {
+    pub fn send<T: 'static>(&mut self, event: T) {
+        self.queues
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| {
+                Box::new(EventQueue::<T> {
+                    pending: Vec::new(),
+                    current: Vec::new(),
+                })
+            })
+            .as_any_mut()
+            .downcast_mut::<EventQueue<T>>()
+            .unwrap()
+            .pending
+            .push(event);
+    }
+
+    /// Events delivered this frame
+    pub fn read<T: 'static>(&self) -> &[T] {
+        self.queues
+            .get(&TypeId::of::<T>())
+            .and_then(|queue| queue.as_any().downcast_ref::<EventQueue<T>>())
+            .map(|queue| queue.current.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Delivers last frame's events and drops the ones that were already read, call once at the start of a frame
+    pub fn update(&mut self) {
+        for queue in self.queues.values_mut() {
+            queue.swap();
+        }
+    }
+}
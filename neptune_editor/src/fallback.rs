@@ -0,0 +1,99 @@
+use crate::texture_cache::ImportedTexture;
+use neptune_vulkan::vk;
+use std::sync::Mutex;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FallbackKind {
+    /// Replaced with the magenta checkerboard from `error_texture`
+    Texture,
+    /// Replaced with the plain white default material
+    Material,
+}
+
+/// An asset that couldn't be loaded and was swapped for a placeholder
+#[derive(Debug, Clone)]
+pub struct Fallback {
+    pub kind: FallbackKind,
+    pub asset: String,
+    pub reason: String,
+}
+
+//Loaders run on worker threads too, so fallbacks are collected globally rather than threaded through every loader
+static FALLBACKS: Mutex<Vec<Fallback>> = Mutex::new(Vec::new());
+
+pub fn record(kind: FallbackKind, asset: &str, reason: impl std::fmt::Display) {
+    let fallback = Fallback {
+        kind,
+        asset: asset.to_string(),
+        reason: reason.to_string(),
+    };
+    warn!(
+        "{} {} fell back to a placeholder: {}",
+        kind_name(fallback.kind),
+        fallback.asset,
+        fallback.reason
+    );
+    FALLBACKS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .push(fallback);
+}
+
+/// Fallbacks recorded since the last call
+pub fn take() -> Vec<Fallback> {
+    std::mem::take(&mut *FALLBACKS.lock().unwrap_or_else(|err| err.into_inner()))
+}
+
+/// Lists every fallback since the last report, meant to be called once a load finishes
+pub fn log_report(load_name: &str) {
+    let fallbacks = take();
+    if fallbacks.is_empty() {
+        return;
+    }
+
+    warn!(
+        "{} asset(s) in {} fell back to placeholders:",
+        fallbacks.len(),
+        load_name
+    );
+    for fallback in fallbacks.iter() {
+        warn!(
+            "  {} {}: {}",
+            kind_name(fallback.kind),
+            fallback.asset,
+            fallback.reason
+        );
+    }
+}
+
+fn kind_name(kind: FallbackKind) -> &'static str {
+    match kind {
+        FallbackKind::Texture => "Texture",
+        FallbackKind::Material => "Material",
+    }
+}
+
+/// Magenta and black checkerboard, hard to mistake for a real texture at any distance
+pub fn error_texture() -> ImportedTexture {
+    const SIZE: u32 = 8;
+    const CELL_SIZE: u32 = 4;
+    const MAGENTA: [u8; 4] = [255, 0, 255, 255];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+
+    let pixels = (0..SIZE * SIZE)
+        .flat_map(|index| {
+            let (x, y) = (index % SIZE, index / SIZE);
+            if (x / CELL_SIZE + y / CELL_SIZE).is_multiple_of(2) {
+                MAGENTA
+            } else {
+                BLACK
+            }
+        })
+        .collect();
+
+    ImportedTexture {
+        format: vk::Format::R8G8B8A8_UNORM,
+        size: [SIZE; 2],
+        mips: vec![pixels],
+    }
+}
@@ -0,0 +1,305 @@
+use anyhow::Context;
+use image::RgbaImage;
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph::QueueType;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferReadCallback, ImageCopyBuffer, ImageCopyImage, RenderGraphBuilderTrait,
+    TransferPassBuilder,
+};
+use neptune_vulkan::{vk, BufferUsage, ImageHandle};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{channel, Sender};
+use std::thread::JoinHandle;
+
+struct RecordedFrame {
+    pixels: Vec<u8>,
+    /// How many output frames this capture covers, more than one when the editor runs slower than the recording
+    repeat: u32,
+}
+
+enum FrameWriter {
+    ImageSequence {
+        directory: PathBuf,
+        frame_index: u32,
+    },
+    Ffmpeg(Child),
+}
+
+impl FrameWriter {
+    fn write(&mut self, size: [u32; 2], rgba: &[u8]) -> anyhow::Result<()> {
+        match self {
+            FrameWriter::ImageSequence {
+                directory,
+                frame_index,
+            } => {
+                let path = directory.join(format!("frame_{:06}.png", frame_index));
+                RgbaImage::from_raw(size[0], size[1], rgba.to_vec())
+                    .context("Frame size doesn't match the recording size")?
+                    .save(&path)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                *frame_index += 1;
+            }
+            FrameWriter::Ffmpeg(child) => {
+                child
+                    .stdin
+                    .as_mut()
+                    .context("ffmpeg's input was closed")?
+                    .write_all(rgba)
+                    .context("Failed to send a frame to ffmpeg")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        if let FrameWriter::Ffmpeg(mut child) = self {
+            //Closing stdin lets ffmpeg finish the file
+            drop(child.stdin.take());
+            let status = child.wait().context("Failed to wait for ffmpeg")?;
+            anyhow::ensure!(status.success(), "ffmpeg exited with {}", status);
+        }
+        Ok(())
+    }
+}
+
+struct Recording {
+    output: PathBuf,
+    size: [u32; 2],
+    /// Time since the last captured frame
+    frame_timer: f32,
+    frame_sender: Sender<RecordedFrame>,
+    writer_thread: JoinHandle<anyhow::Result<u32>>,
+}
+
+/// Copies the presented swapchain image back to the cpu at a fixed rate and writes it out on a worker thread.
+/// Frames are written as a png sequence, or piped into ffmpeg when encoding a video
+pub struct FrameRecorder {
+    pub frame_rate: u32,
+    /// Needs `ffmpeg` in the path
+    pub encode_video: bool,
+    output_directory: PathBuf,
+    recording: Option<Recording>,
+}
+
+impl FrameRecorder {
+    pub const DEFAULT_DIRECTORY: &'static str = "recordings";
+
+    pub fn new(frame_rate: u32, encode_video: bool) -> Self {
+        Self {
+            frame_rate: frame_rate.max(1),
+            encode_video,
+            output_directory: PathBuf::from(Self::DEFAULT_DIRECTORY),
+            recording: None,
+        }
+    }
+
+    pub fn toggle(&mut self, surface_size: [u32; 2], surface_format: vk::Format) {
+        if self.recording.is_some() {
+            self.stop();
+        } else if let Err(err) = self.start(surface_size, surface_format) {
+            error!("Failed to start recording: {:#}", err);
+        }
+    }
+
+    fn start(&mut self, size: [u32; 2], format: vk::Format) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            matches!(
+                format,
+                vk::Format::B8G8R8A8_UNORM
+                    | vk::Format::R8G8B8A8_UNORM
+                    | vk::Format::A2B10G10R10_UNORM_PACK32
+                    | vk::Format::A2R10G10B10_UNORM_PACK32
+            ),
+            "Surface format {:?} can't be recorded",
+            format
+        );
+
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        std::fs::create_dir_all(&self.output_directory)?;
+        let (output, writer) = if self.encode_video {
+            let output = self
+                .output_directory
+                .join(format!("recording_{}.mp4", time));
+            let writer = spawn_ffmpeg(&output, size, self.frame_rate)?;
+            (output, writer)
+        } else {
+            let output = self.output_directory.join(format!("recording_{}", time));
+            std::fs::create_dir_all(&output)?;
+            let writer = FrameWriter::ImageSequence {
+                directory: output.clone(),
+                frame_index: 0,
+            };
+            (output, writer)
+        };
+
+        let (frame_sender, frame_receiver) = channel::<RecordedFrame>();
+        let writer_thread = std::thread::Builder::new()
+            .name("Frame Recorder".to_string())
+            .spawn(move || {
+                let mut writer = writer;
+                let mut frame_count = 0;
+                //Ends once the recording drops its sender
+                for frame in frame_receiver {
+                    let rgba = to_rgba8(format, &frame.pixels);
+                    for _ in 0..frame.repeat {
+                        writer.write(size, &rgba)?;
+                        frame_count += 1;
+                    }
+                }
+                writer.finish()?;
+                Ok(frame_count)
+            })
+            .context("Failed to start frame recorder thread")?;
+
+        info!("Recording {}x{} to {}", size[0], size[1], output.display());
+        self.recording = Some(Recording {
+            output,
+            size,
+            //Starts a full interval in so the first frame is captured right away
+            frame_timer: 1.0 / self.frame_rate as f32,
+            frame_sender,
+            writer_thread,
+        });
+        Ok(())
+    }
+
+    /// Blocks until every frame that was captured has been written
+    pub fn stop(&mut self) {
+        let Some(recording) = self.recording.take() else {
+            return;
+        };
+
+        drop(recording.frame_sender);
+        match recording.writer_thread.join() {
+            Ok(Ok(frame_count)) => info!(
+                "Recorded {} frames to {}",
+                frame_count,
+                recording.output.display()
+            ),
+            Ok(Err(err)) => error!("Recording failed: {:#}", err),
+            Err(_) => error!("Frame recorder thread panicked"),
+        }
+    }
+
+    /// Captures `swapchain_image` if a frame is due, should be the last thing written before the graph is submitted
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        delta_time: f32,
+        swapchain_image: ImageHandle,
+        surface_size: [u32; 2],
+        builder: &mut T,
+    ) {
+        let Some(recording) = &mut self.recording else {
+            return;
+        };
+
+        //Every frame would need scaling to keep a single output size, stopping is simpler
+        if recording.size != surface_size {
+            warn!("The window was resized, stopping the recording");
+            self.stop();
+            return;
+        }
+
+        let frame_interval = 1.0 / self.frame_rate as f32;
+        recording.frame_timer += delta_time;
+        let repeat = (recording.frame_timer / frame_interval) as u32;
+        if repeat == 0 {
+            return;
+        }
+        recording.frame_timer -= repeat as f32 * frame_interval;
+
+        let size = recording.size;
+        let readback_size = (size[0] * size[1] * 4) as usize;
+        let readback_buffer = builder.create_transient_buffer(
+            readback_size,
+            BufferUsage::TRANSFER,
+            MemoryLocation::GpuOnly,
+        );
+        let mut transfer_pass_builder =
+            TransferPassBuilder::new("Frame Recorder Capture", QueueType::Graphics);
+        transfer_pass_builder.copy_image_to_buffer(
+            ImageCopyImage {
+                image: swapchain_image,
+                offset: [0; 2],
+                mip_level: 0,
+            },
+            ImageCopyBuffer {
+                buffer: readback_buffer,
+                offset: 0,
+                row_length: None,
+                row_height: None,
+            },
+            size,
+        );
+        transfer_pass_builder.build(builder);
+
+        let frame_sender = recording.frame_sender.clone();
+        builder.add_buffer_read(
+            BufferOffset {
+                buffer: readback_buffer,
+                offset: 0,
+            },
+            readback_size,
+            BufferReadCallback::new(move |slice| {
+                //Only fails once the recording has stopped
+                let _ = frame_sender.send(RecordedFrame {
+                    pixels: slice.to_vec(),
+                    repeat,
+                });
+            }),
+        );
+    }
+}
+
+impl Drop for FrameRecorder {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn spawn_ffmpeg(output: &Path, size: [u32; 2], frame_rate: u32) -> anyhow::Result<FrameWriter> {
+    let child = Command::new("ffmpeg")
+        .args([
+            "-y",
+            "-loglevel",
+            "error",
+            "-f",
+            "rawvideo",
+            "-pix_fmt",
+            "rgba",
+        ])
+        .args(["-s", &format!("{}x{}", size[0], size[1])])
+        .args(["-r", &frame_rate.to_string()])
+        .args(["-i", "-", "-c:v", "libx264", "-pix_fmt", "yuv420p"])
+        .arg(output)
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("Failed to start ffmpeg, is it installed?")?;
+    Ok(FrameWriter::Ffmpeg(child))
+}
+
+/// Alpha is forced to opaque, the swapchain's alpha is whatever the last pass left in it
+fn to_rgba8(format: vk::Format, pixels: &[u8]) -> Vec<u8> {
+    let words = pixels.chunks_exact(4);
+    match format {
+        vk::Format::B8G8R8A8_UNORM => words.flat_map(|p| [p[2], p[1], p[0], 255]).collect(),
+        vk::Format::A2B10G10R10_UNORM_PACK32 | vk::Format::A2R10G10B10_UNORM_PACK32 => words
+            .flat_map(|p| {
+                let word = u32::from_le_bytes([p[0], p[1], p[2], p[3]]);
+                //Top 8 bits of each 10 bit channel, lowest channel first
+                let [low, mid, high] = [word >> 2, word >> 12, word >> 22].map(|c| c as u8);
+                if format == vk::Format::A2B10G10R10_UNORM_PACK32 {
+                    [low, mid, high, 255]
+                } else {
+                    [high, mid, low, 255]
+                }
+            })
+            .collect(),
+        _ => words.flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+    }
+}
@@ -1,9 +1,11 @@
-use crate::game::world::WorldData;
+use crate::animation::graph::AnimationPlayer;
+use crate::game::world::{WorldData, WorldNode};
 use crate::input::{ButtonState, InputEventReceiver, StaticString};
 use crate::physics::physics_world::Collider;
 use crate::scene::scene_renderer::{Model, SceneInstanceHandle};
-use crate::transform::Transform;
-use glam::{EulerRot, Quat, Vec2, Vec3};
+use crate::scene::skinning::SkinHandle;
+use crate::transform::{Transform, TransformNodeHandle};
+use glam::{EulerRot, Mat4, Quat, Vec2, Vec3};
 use rapier3d::geometry::ColliderHandle;
 
 //TODO: use this to abstract entity types?
@@ -18,26 +20,119 @@ pub trait Entity {
     fn update(&mut self, delta_time: f32, world_data: &mut WorldData);
 }
 
+/// Animation graph driving the skin the entity's model draws
+#[derive(Clone)]
+pub struct EntityAnimation {
+    pub player: AnimationPlayer,
+    pub skin: SkinHandle,
+}
+
 //TODO: entities will need a UUID at some point
 pub struct StaticEntity {
     // Definition
-    transform: Transform,
+    pub(crate) transform: Transform,
     model: Model,
     collider: Option<Collider>,
+    animation: Option<EntityAnimation>,
 
     // World Values
     scene_instance: Option<SceneInstanceHandle>,
+    node: Option<TransformNodeHandle>,
     collider_handle: Option<ColliderHandle>,
+    /// Waypoints left to walk, in world space
+    nav_path: Vec<Vec3>,
 }
 
 impl StaticEntity {
+    /// Meters per second along a nav path
+    const WALK_SPEED: f32 = 1.5;
+    /// Animation parameters set while walking a nav path
+    const WALKING_PARAMETER: &'static str = "walking";
+    const SPEED_PARAMETER: &'static str = "speed";
+
     pub fn new(transform: Transform, model: Model, collider: Option<Collider>) -> Self {
         Self {
             transform,
             model,
             collider,
+            animation: None,
             scene_instance: None,
+            node: None,
             collider_handle: None,
+            nav_path: Vec::new(),
+        }
+    }
+
+    /// `model` has to draw the primitives `Skinning::add` returned for `skin`
+    pub fn with_animation(mut self, player: AnimationPlayer, skin: SkinHandle) -> Self {
+        self.animation = Some(EntityAnimation { player, skin });
+        self
+    }
+
+    pub fn scene_instance(&self) -> Option<SceneInstanceHandle> {
+        self.scene_instance
+    }
+
+    pub fn model(&self) -> &Model {
+        &self.model
+    }
+
+    /// Once attached the node's local transform is the offset from the socket
+    pub fn node(&self) -> Option<TransformNodeHandle> {
+        self.node
+    }
+
+    /// Where the model is drawn, including the sockets the entity is attached to
+    pub fn world_matrix(&self, world_data: &WorldData) -> Mat4 {
+        self.node
+            .and_then(|node| world_data.hierarchy.world_matrix(node))
+            .unwrap_or_else(|| self.transform.model_matrix())
+    }
+
+    /// Places the entity in world space, detaching it from any socket it's on
+    pub fn set_transform(&mut self, world_data: &mut WorldData, transform: Transform) {
+        self.transform = transform;
+        if let Some(node) = self.node {
+            world_data
+                .hierarchy
+                .set_parent(node, None, self.transform.clone());
+        }
+    }
+
+    /// Walks the waypoints from `NavMesh::find_path`, detaching the entity from any socket it's on
+    pub fn walk_path(&mut self, world_data: &mut WorldData, path: Vec<Vec3>) {
+        let Some(node) = self.node else {
+            return;
+        };
+        self.transform = Transform::decompose(&self.world_matrix(world_data));
+        world_data
+            .hierarchy
+            .set_parent(node, None, self.transform.clone());
+        self.nav_path = path;
+    }
+
+    pub fn has_collider(&self) -> bool {
+        self.collider.is_some()
+    }
+
+    pub fn animation(&self) -> Option<&EntityAnimation> {
+        self.animation.as_ref()
+    }
+
+    pub fn animation_mut(&mut self) -> Option<&mut EntityAnimation> {
+        self.animation.as_mut()
+    }
+
+    /// Copy of the definition that isn't in any world yet, what the editor clipboard and undo history hold.
+    /// Animated copies share the skin, so they show whichever pose was set last
+    pub fn detached_copy(&self) -> Self {
+        Self {
+            animation: self.animation.clone(),
+            ..Self::new(
+                self.transform.clone(),
+                self.model.clone(),
+                self.collider.clone(),
+            )
         }
     }
 }
@@ -55,6 +150,16 @@ impl Entity for StaticEntity {
                 collider,
             ));
         }
+
+        self.node = Some(world_data.hierarchy.insert(
+            None,
+            self.transform.clone(),
+            WorldNode {
+                name: self.model.name.clone(),
+                scene_instance: self.scene_instance,
+                collider: self.collider_handle,
+            },
+        ));
     }
 
     fn remove_from_world(&mut self, world_data: &mut WorldData) {
@@ -62,22 +167,59 @@ impl Entity for StaticEntity {
             world_data.scene.remove_instance(scene_instance);
         }
 
+        if let Some(node) = self.node.take() {
+            world_data.hierarchy.remove(node);
+        }
+
         if let Some(collider_handle) = self.collider_handle.take() {
             world_data.physics.remove_collider(collider_handle)
         }
     }
 
     fn update(&mut self, delta_time: f32, world_data: &mut WorldData) {
-        if let Some(scene_instance) = self.scene_instance {
+        let walking = !self.nav_path.is_empty();
+        let mut walked_distance = 0.0;
+
+        //Static entities only move with their node, which pushes its changes to the scene and physics
+        if let (Some(node), Some(&waypoint)) = (self.node, self.nav_path.first()) {
+            let step = Self::WALK_SPEED * delta_time;
+            let to_waypoint = waypoint - self.transform.position;
+            if to_waypoint.length() <= step {
+                walked_distance = to_waypoint.length();
+                self.transform.position = waypoint;
+                self.nav_path.remove(0);
+            } else {
+                walked_distance = step;
+                self.transform.position += to_waypoint.normalize() * step;
+            }
+
+            //Turn to face the way it's walking, ignoring any slope
+            let heading = to_waypoint * Vec3::new(1.0, 0.0, 1.0);
+            if heading.length_squared() > f32::EPSILON {
+                self.transform.rotation = Quat::from_rotation_y(heading.x.atan2(heading.z));
+            }
             world_data
-                .scene
-                .update_instance(scene_instance, self.transform.clone());
+                .hierarchy
+                .set_local_transform(node, self.transform.clone());
         }
 
-        if let Some(collider_handle) = &self.collider_handle {
-            world_data
-                .physics
-                .update_collider_transform(*collider_handle, &self.transform);
+        let model_matrix = self.world_matrix(world_data);
+        if let Some(animation) = &mut self.animation {
+            //Only graphs authored with these parameters react to walking, see `AnimationGraphAsset`
+            let player = &mut animation.player;
+            if player.get_parameter(Self::WALKING_PARAMETER).is_some() {
+                player.set_bool(Self::WALKING_PARAMETER, walking);
+            }
+            if player.get_parameter(Self::SPEED_PARAMETER).is_some() && delta_time > 0.0 {
+                player.set_float(Self::SPEED_PARAMETER, walked_distance / delta_time);
+            }
+
+            animation
+                .player
+                .place_feet(&model_matrix, |origin, direction, distance| {
+                    world_data.physics.ray_cast(origin, direction, distance)
+                });
+            animation.player.update(&world_data.time);
         }
     }
 }
@@ -1,12 +1,13 @@
 use crate::game::entity::Entity;
 use crate::game::world::WorldData;
 use crate::input::{ButtonState, InputEventReceiver, StaticString};
+use crate::input_system::MouseCapture;
 use crate::physics::character::CharacterController;
 use crate::transform::Transform;
 use glam::{Quat, Vec2, Vec3};
 
 pub struct Player {
-    transform: Transform,
+    pub(crate) transform: Transform,
 
     camera_pitch: f32,
     camera_offset: Vec3,
@@ -118,7 +119,7 @@ impl Entity for Player {
 }
 
 impl InputEventReceiver for Player {
-    fn requests_mouse_capture(&mut self) -> bool {
+    fn mouse_capture(&mut self) -> MouseCapture {
         todo!()
     }
 
@@ -1,8 +1,8 @@
 use crate::game::entity::Entity;
-use crate::game::world::WorldData;
+use crate::game::world::{WorldData, WorldNode};
 use crate::physics::physics_world::Collider;
 use crate::scene::scene_renderer::{Model, SceneInstanceHandle};
-use crate::transform::Transform;
+use crate::transform::{Transform, TransformNodeHandle};
 use rapier3d::dynamics::RigidBodyHandle;
 use rapier3d::geometry::ColliderHandle;
 
@@ -19,7 +19,7 @@ pub struct Module {
 }
 
 pub struct ModuleInstance {
-    transform: Transform,
+    node: TransformNodeHandle,
     model_handle: SceneInstanceHandle,
     collider_handle: ColliderHandle,
 }
@@ -33,30 +33,57 @@ pub struct Ship {
 
     pub transform: Transform,
     pub rigid_body_handle: Option<RigidBodyHandle>,
+    /// Parent of every module's node
+    pub node: Option<TransformNodeHandle>,
     pub modules: Vec<ModuleInstance>,
 }
 
+impl Ship {
+    pub fn module_instances(&self) -> impl Iterator<Item = SceneInstanceHandle> + '_ {
+        self.modules.iter().map(|module| module.model_handle)
+    }
+}
+
 impl Entity for Ship {
     fn add_to_world(&mut self, world_data: &mut WorldData) {
         let rigid_body_handle = world_data.physics.add_rigid_body(&self.transform);
         self.rigid_body_handle = Some(rigid_body_handle);
+        let ship_node = world_data.hierarchy.insert(
+            None,
+            self.transform.clone(),
+            WorldNode {
+                name: "Ship".to_string(),
+                ..Default::default()
+            },
+        );
+        self.node = Some(ship_node);
 
         self.modules = self
             .module_list
             .iter()
-            .map(|(transform, module_type)| {
+            .enumerate()
+            .map(|(index, (transform, module_type))| {
                 let module = match module_type {
                     ModuleType::Connector => &self.connector_module,
                     ModuleType::Hallway => &self.hallway_module,
                     ModuleType::Room => &self.room_module,
                 };
 
+                let model_handle = world_data
+                    .scene
+                    .add_instance(self.transform.transform(transform), module.model.clone())
+                    .unwrap();
                 ModuleInstance {
-                    transform: transform.clone(),
-                    model_handle: world_data
-                        .scene
-                        .add_instance(transform.clone(), module.model.clone())
-                        .unwrap(),
+                    node: world_data.hierarchy.insert(
+                        Some(ship_node),
+                        transform.clone(),
+                        WorldNode {
+                            name: format!("Module {}", index),
+                            scene_instance: Some(model_handle),
+                            collider: None,
+                        },
+                    ),
+                    model_handle,
                     collider_handle: world_data.physics.add_collider(
                         self.rigid_body_handle,
                         transform,
@@ -68,7 +95,12 @@ impl Entity for Ship {
     }
 
     fn remove_from_world(&mut self, world_data: &mut WorldData) {
-        let _ = world_data;
+        for module in self.modules.iter() {
+            world_data.hierarchy.remove(module.node);
+        }
+        if let Some(node) = self.node.take() {
+            world_data.hierarchy.remove(node);
+        }
     }
 
     fn update(&mut self, delta_time: f32, world_data: &mut WorldData) {
@@ -80,13 +112,13 @@ impl Entity for Ship {
             .get_mut_rigid_body(self.rigid_body_handle)
         {
             rigid_body_ref.get_transform(&mut self.transform);
+        }
 
-            for module in self.modules.iter() {
-                world_data.scene.update_instance(
-                    module.model_handle,
-                    self.transform.transform(&module.transform),
-                );
-            }
+        //Moving the ship's node carries every module with it
+        if let Some(node) = self.node {
+            world_data
+                .hierarchy
+                .set_local_transform(node, self.transform.clone());
         }
     }
 }
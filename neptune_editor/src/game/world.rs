@@ -1,8 +1,16 @@
-use crate::game::entity::{Entity, StaticEntity};
+use crate::events::{CollisionEvent, EntitySpawned, EventBus};
+use crate::game::entity::{Entity, EntityAnimation, StaticEntity};
 use crate::game::player::Player;
 use crate::game::ship::Ship;
+use crate::mesh::procedural::ProceduralMesh;
+use crate::navmesh::NavMesh;
 use crate::physics::physics_world::PhysicsWorld;
-use crate::scene::scene_renderer::Scene;
+use crate::scene::scene_renderer::{Scene, SceneInstanceHandle};
+use crate::scene::skinning::SkinHandle;
+use crate::time::Time;
+use crate::transform::{Transform, TransformHierarchy, TransformNodeHandle};
+use glam::{Mat4, Vec3, Vec4};
+use rapier3d::geometry::ColliderHandle;
 
 pub struct World {
     pub data: WorldData,
@@ -12,21 +20,202 @@ pub struct World {
 impl World {
     pub fn add_player(&mut self, mut player: Player) {
         player.add_to_world(&mut self.data);
+        self.data.events.send(EntitySpawned {
+            kind: "Player",
+            transform: player.transform.clone(),
+        });
         self.entities.player = Some(player);
     }
 
-    pub fn add_static_entity(&mut self, mut static_entity: StaticEntity) {
+    /// Returns the entity's scene instance, None if the scene is full
+    pub fn add_static_entity(
+        &mut self,
+        mut static_entity: StaticEntity,
+    ) -> Option<SceneInstanceHandle> {
         static_entity.add_to_world(&mut self.data);
+        self.data.events.send(EntitySpawned {
+            kind: "StaticEntity",
+            transform: static_entity.transform.clone(),
+        });
+        let scene_instance = static_entity.scene_instance();
         self.entities.static_entities.push(static_entity);
+        scene_instance
+    }
+
+    pub fn static_entities(&self) -> &[StaticEntity] {
+        &self.entities.static_entities
+    }
+
+    pub fn ships(&self) -> &[Ship] {
+        &self.entities.ships
+    }
+
+    pub fn get_static_entity(&self, scene_instance: SceneInstanceHandle) -> Option<&StaticEntity> {
+        self.entities
+            .static_entities
+            .iter()
+            .find(|entity| entity.scene_instance() == Some(scene_instance))
+    }
+
+    /// Animation of the static entity owning `scene_instance`, this is how game code drives its graph
+    pub fn animation_mut(
+        &mut self,
+        scene_instance: SceneInstanceHandle,
+    ) -> Option<&mut EntityAnimation> {
+        self.entities
+            .static_entities
+            .iter_mut()
+            .find(|entity| entity.scene_instance() == Some(scene_instance))
+            .and_then(StaticEntity::animation_mut)
+    }
+
+    /// Moves the static entity owning `scene_instance` in world space, false if it isn't a static entity
+    pub fn set_static_entity_transform(
+        &mut self,
+        scene_instance: SceneInstanceHandle,
+        transform: Transform,
+    ) -> bool {
+        let Some(entity) = self
+            .entities
+            .static_entities
+            .iter_mut()
+            .find(|entity| entity.scene_instance() == Some(scene_instance))
+        else {
+            return false;
+        };
+        entity.set_transform(&mut self.data, transform);
+        true
+    }
+
+    /// Sends the static entity owning `scene_instance` along the navmesh to `target`, false if there's no path there
+    pub fn walk_static_entity(
+        &mut self,
+        scene_instance: SceneInstanceHandle,
+        target: Vec3,
+    ) -> bool {
+        let Some(navmesh) = &self.data.navmesh else {
+            return false;
+        };
+        let Some(entity) = self
+            .entities
+            .static_entities
+            .iter_mut()
+            .find(|entity| entity.scene_instance() == Some(scene_instance))
+        else {
+            return false;
+        };
+
+        let start = entity.world_matrix(&self.data).w_axis.truncate();
+        let Some(path) = navmesh.find_path(start, target) else {
+            return false;
+        };
+        entity.walk_path(&mut self.data, path);
+        true
+    }
+
+    /// Points every animated entity's look ats and reach chains at a world space target, None releases them
+    pub fn set_ik_focus(&mut self, target: Option<Vec3>) {
+        for entity in self.entities.static_entities.iter_mut() {
+            let inverse_model_matrix = entity.world_matrix(&self.data).inverse();
+            if let Some(animation) = entity.animation_mut() {
+                animation
+                    .player
+                    .ik_rig_mut()
+                    .set_focus(target.map(|target| inverse_model_matrix.transform_point3(target)));
+            }
+        }
+    }
+
+    /// Skinning matrices for every animated entity's current pose
+    pub fn skin_poses(&self) -> Vec<(SkinHandle, Vec<Mat4>)> {
+        self.entities
+            .static_entities
+            .iter()
+            .filter_map(StaticEntity::animation)
+            .map(|animation| (animation.skin, animation.player.skinning_matrices()))
+            .collect()
+    }
+
+    /// IK chains and targets of every animated entity in world space, see `IkRig::debug_mesh`
+    pub fn ik_debug_mesh(&self) -> ProceduralMesh {
+        let mut mesh = ProceduralMesh::default();
+        for entity in self.entities.static_entities.iter() {
+            if let Some(animation) = entity.animation() {
+                mesh.append(
+                    &animation.player.ik_debug_mesh(),
+                    entity.world_matrix(&self.data),
+                    Vec4::ONE,
+                );
+            }
+        }
+        mesh
+    }
+
+    /// Takes the entity owning `scene_instance` out of the world, None if it isn't a static entity
+    pub fn remove_static_entity(
+        &mut self,
+        scene_instance: SceneInstanceHandle,
+    ) -> Option<StaticEntity> {
+        let index = self
+            .entities
+            .static_entities
+            .iter()
+            .position(|entity| entity.scene_instance() == Some(scene_instance))?;
+        let mut static_entity = self.entities.static_entities.swap_remove(index);
+        static_entity.remove_from_world(&mut self.data);
+        Some(static_entity)
+    }
+
+    /// Keeps the static entity on the node named `socket` in `parent`'s subtree (weapon in hand, beacon on a ship).
+    /// `offset` is relative to the socket, false if the entity or socket is missing or the socket is attached to the entity
+    pub fn attach_static_entity(
+        &mut self,
+        scene_instance: SceneInstanceHandle,
+        parent: TransformNodeHandle,
+        socket: &str,
+        offset: Transform,
+    ) -> bool {
+        let Some(socket_node) = self
+            .data
+            .hierarchy
+            .find(parent, |node, _| node.name == socket)
+        else {
+            return false;
+        };
+        let Some(node) = self
+            .get_static_entity(scene_instance)
+            .and_then(StaticEntity::node)
+        else {
+            return false;
+        };
+        self.data
+            .hierarchy
+            .set_parent(node, Some(socket_node), offset)
     }
 
     pub fn add_ship(&mut self, mut ship: Ship) {
         ship.add_to_world(&mut self.data);
+        self.data.events.send(EntitySpawned {
+            kind: "Ship",
+            transform: ship.transform.clone(),
+        });
         self.entities.ships.push(ship);
     }
 
-    pub fn update(&mut self, delta_time: f32) {
-        self.data.physics.step(delta_time);
+    /// Steps everything by the scaled delta of `data.time`, nothing moves while it's paused
+    pub fn update(&mut self) {
+        let delta_time = self.data.time.delta();
+        if delta_time > 0.0 {
+            self.data.physics.step(delta_time);
+        }
+        let collision_events: Vec<_> = self.data.physics.drain_collision_events().collect();
+        for event in collision_events {
+            self.data.events.send(CollisionEvent {
+                collider1: event.collider1(),
+                collider2: event.collider2(),
+                started: event.started(),
+            });
+        }
 
         for entity in self.entities.static_entities.iter_mut() {
             entity.update(delta_time, &mut self.data);
@@ -39,12 +228,48 @@ impl World {
         if let Some(player) = &mut self.entities.player {
             player.update(delta_time, &mut self.data);
         }
+
+        self.data.update_transforms();
+        self.data.scene.update_spatial();
     }
 }
 
 pub struct WorldData {
     pub scene: Scene,
+    /// Entity and ship module transforms, changed nodes are pushed to the scene and physics once per update
+    pub hierarchy: TransformHierarchy<WorldNode>,
     pub physics: PhysicsWorld,
+    /// Built from the scene on request, used by entities to path find
+    pub navmesh: Option<NavMesh>,
+    pub events: EventBus,
+    pub time: Time,
+}
+
+impl WorldData {
+    /// Moves what every node whose world transform changed since the last call is carrying
+    pub fn update_transforms(&mut self) {
+        let scene = &mut self.scene;
+        let physics = &mut self.physics;
+        self.hierarchy.update_transforms(|node, world_matrix| {
+            let transform = Transform::decompose(world_matrix);
+            if let Some(collider) = node.collider {
+                physics.update_collider_transform(collider, &transform);
+            }
+            if let Some(scene_instance) = node.scene_instance {
+                scene.update_instance(scene_instance, transform);
+            }
+        });
+    }
+}
+
+/// What a hierarchy node moves when its world transform changes
+#[derive(Default)]
+pub struct WorldNode {
+    /// Socket name other entities can attach to
+    pub name: String,
+    pub scene_instance: Option<SceneInstanceHandle>,
+    /// Only colliders without a rigid body, ones on a body already move with it
+    pub collider: Option<ColliderHandle>,
 }
 
 #[derive(Default)]
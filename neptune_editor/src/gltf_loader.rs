@@ -1,14 +1,32 @@
-use crate::material::{Material, MaterialTexture};
+use crate::animation::clip::{AnimationClip, Channel, ChannelValues, Interpolation};
+use crate::animation::skeleton::{Joint, Skeleton};
+use crate::fallback::{self, FallbackKind};
+use crate::lightmap::LightmapLayout;
+use crate::material::{
+    Material, MaterialConstants, MaterialConstantsData, MaterialPalette, MaterialPipeline,
+    MaterialTexture,
+};
+use crate::mesh::batching::{StaticBatcher, StaticBatching};
+use crate::mesh::primitive_cache::PrimitiveCache;
 use crate::mesh::{
-    BoundingBox, IndexBuffer, Mesh, Primitive, VertexAttributes, VertexSkinningAttributes,
+    BoundingBox, BoundingSphere, IndexBuffer, Mesh, Primitive, PrimitiveGeometry, VertexAttributes,
+    VertexSkinningAttributes,
+};
+use crate::obj_loader::load_obj_scene;
+use crate::texture_cache::{
+    hdr_format, HdrImportSettings, ImportedTexture, TextureCache, TextureImportSettings,
 };
+use crate::transform::Transform;
+use crate::vfs::Vfs;
 use anyhow::anyhow;
-use glam::{Mat4, Vec2, Vec3, Vec4};
+use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
 use gltf::image::Format;
 use gltf::texture::{MagFilter, MinFilter, WrappingMode};
 use neptune_vulkan::gpu_allocator::MemoryLocation;
-use neptune_vulkan::{vk, AddressMode, FilterMode, ImageHandle, SamplerHandle};
+use neptune_vulkan::{AddressMode, FilterMode, ImageHandle, SamplerHandle};
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 fn neptune_address_mode(mode: WrappingMode) -> AddressMode {
@@ -24,11 +42,11 @@ pub struct GltfSamplers {
     pub samplers: Vec<SamplerHandle>,
 }
 
-pub fn load_samplers(
+/// Repeating linear sampler, used for textures that don't specify one
+pub fn create_default_sampler(
     device: &mut neptune_vulkan::Device,
-    gltf_doc: &gltf::Document,
-) -> anyhow::Result<GltfSamplers> {
-    let default_sampler = device.create_sampler(
+) -> anyhow::Result<SamplerHandle> {
+    Ok(device.create_sampler(
         "Gltf Default Sampler",
         &neptune_vulkan::SamplerDescription {
             address_mode_u: AddressMode::Repeat,
@@ -42,7 +60,14 @@ pub fn load_samplers(
             border_color: Default::default(),
             unnormalized_coordinates: false,
         },
-    )?;
+    )?)
+}
+
+pub fn load_samplers(
+    device: &mut neptune_vulkan::Device,
+    gltf_doc: &gltf::Document,
+) -> anyhow::Result<GltfSamplers> {
+    let default_sampler = create_default_sampler(device)?;
 
     let mut samplers = Vec::with_capacity(gltf_doc.samplers().len());
     for gltf_sampler in gltf_doc.samplers() {
@@ -100,69 +125,132 @@ pub fn load_samplers(
 
 pub fn load_images(
     device: &mut neptune_vulkan::Device,
+    texture_cache: &TextureCache,
     gltf_doc: &gltf::Document,
-    gltf_images: &[gltf::image::Data],
+    base_path: &Path,
+    buffer_data: &[gltf::buffer::Data],
 ) -> anyhow::Result<Vec<ImageHandle>> {
     let mut images = Vec::with_capacity(gltf_doc.images().len());
     for gltf_image in gltf_doc.images() {
-        let name = gltf_image
-            .name()
-            .map(|str| str.to_string())
-            .unwrap_or_else(|| format!("Unnamed Image {}", gltf_image.index()));
-
-        let gltf_image_data = &gltf_images[gltf_image.index()];
-        let mut format = match gltf_image_data.format {
-            Format::R8 => vk::Format::R8_UNORM,
-            Format::R8G8 => vk::Format::R8G8_UNORM,
-            Format::R8G8B8 => vk::Format::R8G8B8_UNORM,
-            Format::R8G8B8A8 => vk::Format::R8G8B8A8_UNORM,
-            Format::R16 => vk::Format::R16_UNORM,
-            Format::R16G16 => vk::Format::R16G16_UNORM,
-            Format::R16G16B16 => vk::Format::R16G16B16_UNORM,
-            Format::R16G16B16A16 => vk::Format::R16G16B16A16_UNORM,
-            Format::R32G32B32FLOAT => vk::Format::R32G32B32_SFLOAT,
-            Format::R32G32B32A32FLOAT => vk::Format::R32G32B32A32_SFLOAT,
-        };
+        let (name, imported_texture) =
+            import_image(texture_cache, &gltf_image, base_path, buffer_data)?;
+        images.push(imported_texture.create_image(device, &name)?);
+    }
+
+    Ok(images)
+}
 
-        let mut image_data_slice: &[u8] = &gltf_image_data.pixels;
+/// Decodes and compresses the image through the texture cache, doesn't need the device so it can run on any thread
+pub(crate) fn import_image(
+    texture_cache: &TextureCache,
+    gltf_image: &gltf::Image,
+    base_path: &Path,
+    buffer_data: &[gltf::buffer::Data],
+) -> anyhow::Result<(String, ImportedTexture)> {
+    let name = gltf_image
+        .name()
+        .map(|str| str.to_string())
+        .unwrap_or_else(|| format!("Unnamed Image {}", gltf_image.index()));
 
-        //Because Nvidia doesn't like non-32 aligned RGB image formats
-        let image_data_slice_new: Vec<u8>;
-        if format == vk::Format::R8G8B8_UNORM {
-            format = vk::Format::R8G8B8A8_UNORM;
-            image_data_slice_new = image_data_slice
-                .chunks_exact(3)
-                .flat_map(|chunk| [chunk[0], chunk[1], chunk[2], 255])
-                .collect();
-            image_data_slice = &image_data_slice_new;
-        } else if format == vk::Format::R16G16B16_UNORM {
-            format = vk::Format::R16G16B16A16_UNORM;
-            image_data_slice_new = image_data_slice
-                .chunks_exact(6)
-                .flat_map(|chunk| {
-                    [
-                        chunk[0], chunk[1], chunk[2], chunk[3], chunk[4], chunk[5], 255, 255,
-                    ]
-                })
-                .collect();
-            image_data_slice = &image_data_slice_new;
+    //Cache key is the encoded image, so cached images are never decoded
+    let source_bytes: Cow<[u8]> = match gltf_image.source() {
+        gltf::image::Source::View { view, .. } => {
+            let buffer = &buffer_data[view.buffer().index()];
+            Cow::Borrowed(&buffer[view.offset()..(view.offset() + view.length())])
         }
-        let description = neptune_vulkan::ImageDescription2D {
-            size: [gltf_image_data.width, gltf_image_data.height],
-            format,
-            usage: vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
-            mip_levels: 1,
-            location: MemoryLocation::GpuOnly,
-        };
+        gltf::image::Source::Uri { uri, .. } => std::fs::read(base_path.join(uri))
+            .map(Cow::Owned)
+            .unwrap_or(Cow::Borrowed(uri.as_bytes())),
+    };
 
-        images.push(device.create_image_init(&name, &description, image_data_slice)?);
+    //Hdr images keep their range as float textures, gltf can't decode them itself
+    let imported_texture = match hdr_format(&source_bytes) {
+        Some(format) => {
+            texture_cache.load_hdr(&name, &source_bytes, HdrImportSettings::for_source(format))
+        }
+        None => texture_cache.load(
+            &name,
+            &source_bytes,
+            TextureImportSettings::default(),
+            || {
+                let image_data = gltf::image::Data::from_source(
+                    gltf_image.source(),
+                    Some(base_path),
+                    buffer_data,
+                )?;
+                gltf_image_to_rgba8(image_data)
+            },
+        ),
     }
+    .unwrap_or_else(|err| {
+        fallback::record(FallbackKind::Texture, &name, format!("{:#}", err));
+        fallback::error_texture()
+    });
+    Ok((name, imported_texture))
+}
 
-    Ok(images)
+fn gltf_image_to_rgba8(image_data: gltf::image::Data) -> anyhow::Result<image::RgbaImage> {
+    let pixels = &image_data.pixels;
+    let rgba: Vec<u8> = match image_data.format {
+        Format::R8 => pixels.iter().flat_map(|&r| [r, r, r, 255]).collect(),
+        Format::R8G8 => pixels
+            .chunks_exact(2)
+            .flat_map(|chunk| [chunk[0], chunk[1], 0, 255])
+            .collect(),
+        Format::R8G8B8 => pixels
+            .chunks_exact(3)
+            .flat_map(|chunk| [chunk[0], chunk[1], chunk[2], 255])
+            .collect(),
+        Format::R8G8B8A8 => pixels.clone(),
+        //16 bit channels keep their high byte
+        Format::R16 => pixels
+            .chunks_exact(2)
+            .flat_map(|chunk| [chunk[1], chunk[1], chunk[1], 255])
+            .collect(),
+        Format::R16G16 => pixels
+            .chunks_exact(4)
+            .flat_map(|chunk| [chunk[1], chunk[3], 0, 255])
+            .collect(),
+        Format::R16G16B16 => pixels
+            .chunks_exact(6)
+            .flat_map(|chunk| [chunk[1], chunk[3], chunk[5], 255])
+            .collect(),
+        Format::R16G16B16A16 => pixels
+            .chunks_exact(8)
+            .flat_map(|chunk| [chunk[1], chunk[3], chunk[5], chunk[7]])
+            .collect(),
+        Format::R32G32B32FLOAT | Format::R32G32B32A32FLOAT => {
+            let channels = match image_data.format {
+                Format::R32G32B32FLOAT => 3,
+                _ => 4,
+            };
+            pixels
+                .chunks_exact(4 * channels)
+                .flat_map(|chunk| {
+                    let mut pixel = [255u8; 4];
+                    for (channel, value) in pixel.iter_mut().take(channels).enumerate() {
+                        let float = f32::from_le_bytes([
+                            chunk[channel * 4],
+                            chunk[channel * 4 + 1],
+                            chunk[channel * 4 + 2],
+                            chunk[channel * 4 + 3],
+                        ]);
+                        *value = (float.clamp(0.0, 1.0) * 255.0).round() as u8;
+                    }
+                    pixel
+                })
+                .collect()
+        }
+    };
+
+    image::RgbaImage::from_raw(image_data.width, image_data.height, rgba)
+        .ok_or_else(|| anyhow!("Image data doesn't match its size"))
 }
 
+/// Primitives come from `primitive_cache`, so ones already loaded from another file are shared
 pub fn load_meshes(
     device: &mut neptune_vulkan::Device,
+    primitive_cache: &mut PrimitiveCache,
     gltf_doc: &gltf::Document,
     gltf_buffers: &[gltf::buffer::Data],
 ) -> anyhow::Result<Vec<Mesh>> {
@@ -174,29 +262,49 @@ pub fn load_meshes(
             .map(|str| str.to_string())
             .unwrap_or_else(|| format!("Unnamed Mesh {}", gltf_mesh.index()));
 
-        let mut mesh = Mesh {
-            name,
-            primitives: Vec::new(),
-        };
-
-        for gltf_primitive in gltf_mesh.primitives() {
-            mesh.primitives.push(Arc::new(load_primitive(
-                device,
-                gltf_buffers,
-                &gltf_primitive,
-            )?));
-        }
-        meshes.push(mesh);
+        let primitives = gltf_mesh
+            .primitives()
+            .map(|gltf_primitive| {
+                primitive_cache
+                    .get_or_create(device, &read_primitive(gltf_buffers, &gltf_primitive)?)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        meshes.push(Mesh::new(name, primitives));
     }
 
     Ok(meshes)
 }
 
-pub fn load_primitive(
-    device: &mut neptune_vulkan::Device,
+pub(crate) struct PrimitiveData {
+    pub bounding_box: BoundingBox,
+    pub positions: Vec<Vec3>,
+    pub attributes: Vec<VertexAttributes>,
+    pub skinning: Option<Vec<VertexSkinningAttributes>>,
+    pub indices: Option<Vec<u32>>,
+}
+
+impl PrimitiveData {
+    /// Bytes the primitive's buffers take up once uploaded
+    pub fn byte_size(&self) -> usize {
+        std::mem::size_of_val(self.positions.as_slice())
+            + std::mem::size_of_val(self.attributes.as_slice())
+            + self
+                .skinning
+                .as_ref()
+                .map(|skinning| std::mem::size_of_val(skinning.as_slice()))
+                .unwrap_or_default()
+            + self
+                .indices
+                .as_ref()
+                .map(|indices| std::mem::size_of_val(indices.as_slice()))
+                .unwrap_or_default()
+    }
+}
+
+pub(crate) fn read_primitive(
     gltf_buffers: &[gltf::buffer::Data],
     gltf_primitive: &gltf::Primitive,
-) -> anyhow::Result<Primitive> {
+) -> anyhow::Result<PrimitiveData> {
     let reader = gltf_primitive.reader(|buffer| Some(&gltf_buffers[buffer.index()]));
 
     let bounding_box = BoundingBox {
@@ -204,17 +312,14 @@ pub fn load_primitive(
         max: Vec3::from_array(gltf_primitive.bounding_box().max),
     };
 
-    let (position_buffer, vertex_count) = {
-        let positions: Vec<Vec3> = match reader.read_positions() {
-            None => return Err(anyhow!("Mesh contains no vertex positions")),
-            Some(positions) => positions,
-        }
-        .map(Vec3::from_array)
-        .collect();
-        (create_vertex_buffer(device, &positions)?, positions.len())
-    };
+    let positions: Vec<Vec3> = match reader.read_positions() {
+        None => return Err(anyhow!("Mesh contains no vertex positions")),
+        Some(positions) => positions,
+    }
+    .map(Vec3::from_array)
+    .collect();
 
-    let attributes_buffer = {
+    let attributes = {
         let mut attributes: Vec<VertexAttributes> = if let Some(normals) = reader.read_normals() {
             if let Some(tangents) = reader.read_tangents() {
                 if let Some(tex_coords) = reader.read_tex_coords(0) {
@@ -252,12 +357,12 @@ pub fn load_primitive(
                 attribute.color = Vec4::from_array(color);
             }
         }
-        create_vertex_buffer(device, &attributes)?
+        attributes
     };
 
-    let skinning_buffer = if let Some(joints) = reader.read_joints(0) {
-        if let Some(weights) = reader.read_weights(0) {
-            let array: Vec<VertexSkinningAttributes> = joints
+    let skinning = if let Some(joints) = reader.read_joints(0) {
+        reader.read_weights(0).map(|weights| {
+            joints
                 .into_u16()
                 .zip(weights.into_f32())
                 .map(|(joint, weights)| VertexSkinningAttributes {
@@ -269,37 +374,79 @@ pub fn load_primitive(
                     ),
                     weight: Vec4::from_array(weights),
                 })
-                .collect();
-            Some(create_vertex_buffer(device, &array)?)
-        } else {
-            None
-        }
+                .collect()
+        })
     } else {
         None
     };
 
-    let index_buffer = match reader.read_indices() {
+    let indices = reader
+        .read_indices()
+        .map(|indices| indices.into_u32().collect());
+
+    Ok(PrimitiveData {
+        bounding_box,
+        positions,
+        attributes,
+        skinning,
+        indices,
+    })
+}
+
+pub(crate) fn create_primitive(
+    device: &mut neptune_vulkan::Device,
+    data: &PrimitiveData,
+) -> anyhow::Result<Primitive> {
+    let skinning_buffer = match &data.skinning {
+        Some(skinning) => Some(create_vertex_buffer(device, skinning)?),
+        None => None,
+    };
+
+    let index_buffer = match &data.indices {
         None => None,
-        Some(indices) => {
-            let indices_vec: Vec<u32> = indices.into_u32().collect();
-            Some(IndexBuffer {
-                count: indices_vec.len() as u32,
-                buffer: create_index_buffer(device, &indices_vec)?,
-            })
-        }
+        Some(indices) => Some(IndexBuffer {
+            count: indices.len() as u32,
+            buffer: create_index_buffer(device, indices)?,
+        }),
     };
 
-    Ok(Primitive {
-        bounding_box,
-        vertex_count,
+    Ok(primitive_from_buffers(
+        data,
+        create_vertex_buffer(device, &data.positions)?,
+        create_vertex_buffer(device, &data.attributes)?,
+        skinning_buffer,
+        index_buffer,
+    ))
+}
+
+/// Primitive for already uploaded buffers, which may be shared with other primitives
+pub(crate) fn primitive_from_buffers(
+    data: &PrimitiveData,
+    position_buffer: neptune_vulkan::BufferHandle,
+    attributes_buffer: neptune_vulkan::BufferHandle,
+    skinning_buffer: Option<neptune_vulkan::BufferHandle>,
+    index_buffer: Option<IndexBuffer>,
+) -> Primitive {
+    Primitive {
+        bounding_box: data.bounding_box,
+        bounding_sphere: BoundingSphere::from_points(&data.positions),
+        geometry: Arc::new(PrimitiveGeometry {
+            positions: data.positions.clone(),
+            indices: data
+                .indices
+                .clone()
+                .unwrap_or_else(|| (0..data.positions.len() as u32).collect()),
+        }),
+        vertex_count: data.positions.len(),
+        vertex_offset: 0,
         position_buffer,
         attributes_buffer,
         skinning_buffer,
         index_buffer,
-    })
+    }
 }
 
-fn create_vertex_buffer<T>(
+pub(crate) fn create_vertex_buffer<T>(
     device: &mut neptune_vulkan::Device,
     data: &[T],
 ) -> anyhow::Result<neptune_vulkan::BufferHandle> {
@@ -309,13 +456,16 @@ fn create_vertex_buffer<T>(
 
     Ok(device.create_buffer_init(
         "Vertex Buffer",
-        neptune_vulkan::BufferUsage::VERTEX | neptune_vulkan::BufferUsage::TRANSFER,
+        //Storage so compute skinning can read the source vertices
+        neptune_vulkan::BufferUsage::VERTEX
+            | neptune_vulkan::BufferUsage::STORAGE
+            | neptune_vulkan::BufferUsage::TRANSFER,
         MemoryLocation::GpuOnly,
         data_bytes,
     )?)
 }
 
-fn create_index_buffer(
+pub(crate) fn create_index_buffer(
     device: &mut neptune_vulkan::Device,
     data: &[u32],
 ) -> anyhow::Result<neptune_vulkan::BufferHandle> {
@@ -332,10 +482,11 @@ fn create_index_buffer(
 }
 
 pub fn load_materials(
+    material_palette: &MaterialPalette,
     gltf_doc: &gltf::Document,
     images: &[ImageHandle],
     samplers: &GltfSamplers,
-) -> Vec<Material> {
+) -> anyhow::Result<Vec<Material>> {
     gltf_doc
         .materials()
         .map(|gltf_material| {
@@ -349,18 +500,26 @@ pub fn load_materials(
                     )
                 });
 
-            Material {
+            let base_color = gltf_material
+                .pbr_metallic_roughness()
+                .base_color_factor()
+                .into();
+            let metallic_roughness_factor = Vec2::new(
+                gltf_material.pbr_metallic_roughness().metallic_factor(),
+                gltf_material.pbr_metallic_roughness().roughness_factor(),
+            );
+            let emissive_color = gltf_material.emissive_factor().into();
+            let constants = MaterialConstants::new(
+                material_palette,
+                MaterialConstantsData::new(base_color, metallic_roughness_factor, emissive_color),
+            )?;
+
+            Ok(Material {
                 name,
                 alpha_blending: gltf_material.alpha_mode() == gltf::material::AlphaMode::Blend,
-                base_color: gltf_material
-                    .pbr_metallic_roughness()
-                    .base_color_factor()
-                    .into(),
-                metallic_roughness_factor: Vec2::new(
-                    gltf_material.pbr_metallic_roughness().metallic_factor(),
-                    gltf_material.pbr_metallic_roughness().roughness_factor(),
-                ),
-                emissive_color: gltf_material.emissive_factor().into(),
+                base_color,
+                metallic_roughness_factor,
+                emissive_color,
                 base_color_texture: gltf_material
                     .pbr_metallic_roughness()
                     .base_color_texture()
@@ -390,7 +549,9 @@ pub fn load_materials(
                 emissive_texture: gltf_material.emissive_texture().map(|info| {
                     load_material_texture(&info.texture(), info.tex_coord(), images, samplers)
                 }),
-            }
+                constants,
+                pipeline: MaterialPipeline::default(),
+            })
         })
         .collect()
 }
@@ -421,43 +582,141 @@ pub struct GltfScene {
     pub materials: Vec<Material>,
 
     pub mesh_nodes: Vec<GltfNode>,
+    pub skins: Vec<GltfSkin>,
+}
+
+/// Skeleton with every animation in the file that targets its joints
+pub struct GltfSkin {
+    pub skeleton: Arc<Skeleton>,
+    pub animations: Vec<Arc<AnimationClip>>,
+    /// World transform of the node the skeleton hangs from, the joint matrices are relative to it
+    pub transform: Mat4,
 }
 
 pub struct GltfNode {
     pub transform: Mat4,
     pub mesh_index: usize,
     pub primitive_materials: Vec<usize>,
+    /// Static batches loaded with `StaticBatching::Lightmapped`, their one primitive has the lightmap uvs in uv1
+    pub lightmap: Option<LightmapLayout>,
+    /// Index into `GltfScene::skins`, skinned nodes are placed at the skin's transform instead of their own
+    pub skin: Option<usize>,
 }
 
 pub fn load_gltf_scene<P: AsRef<std::path::Path>>(
     device: &mut neptune_vulkan::Device,
+    texture_cache: &TextureCache,
+    primitive_cache: &mut PrimitiveCache,
+    material_palette: &MaterialPalette,
     path: P,
+    static_batching: StaticBatching,
 ) -> anyhow::Result<GltfScene> {
-    let (gltf_doc, buffer_data, image_data) = {
-        let now = std::time::Instant::now();
-        let result = gltf::import(path)?;
-        info!("File Loading: {}", now.elapsed().as_secs_f32());
-        result
-    };
+    let path = path.as_ref();
+    let file_data = std::fs::read(path)?;
+    load_gltf_scene_from_slice(
+        device,
+        texture_cache,
+        primitive_cache,
+        material_palette,
+        &file_data,
+        path.parent().unwrap_or_else(|| Path::new("./")),
+        static_batching,
+    )
+}
+
+/// Parsed file with its buffers loaded, images are left encoded since they go through the texture cache
+pub(crate) struct GltfSource {
+    pub document: gltf::Document,
+    pub buffer_data: Vec<gltf::buffer::Data>,
+    pub base_path: PathBuf,
+}
+
+impl GltfSource {
+    pub fn parse(file_data: &[u8], base_path: &Path) -> anyhow::Result<Self> {
+        let gltf::Gltf { document, blob } = gltf::Gltf::from_slice(file_data)?;
+        let buffer_data = gltf::import_buffers(&document, Some(base_path), blob)?;
+        Ok(Self {
+            document,
+            buffer_data,
+            base_path: base_path.to_path_buf(),
+        })
+    }
+}
 
+/// For files that aren't loose on disk, like ones in an asset archive. External buffers and images are still read from `base_path`.
+/// With `static_batching` the nodes are merged into per material batches, see `StaticBatcher`
+pub fn load_gltf_scene_from_slice(
+    device: &mut neptune_vulkan::Device,
+    texture_cache: &TextureCache,
+    primitive_cache: &mut PrimitiveCache,
+    material_palette: &MaterialPalette,
+    file_data: &[u8],
+    base_path: &Path,
+    static_batching: StaticBatching,
+) -> anyhow::Result<GltfScene> {
     let now = std::time::Instant::now();
-    let meshes = load_meshes(device, &gltf_doc, &buffer_data)?;
+    let source = GltfSource::parse(file_data, base_path)?;
+    info!("File Loading: {}", now.elapsed().as_secs_f32());
+
+    let now = std::time::Instant::now();
+    let meshes = load_meshes(
+        device,
+        primitive_cache,
+        &source.document,
+        &source.buffer_data,
+    )?;
     info!("Mesh Convert/Upload: {}", now.elapsed().as_secs_f32());
 
     let now = std::time::Instant::now();
-    let images = load_images(device, &gltf_doc, &image_data)?;
+    let images = load_images(
+        device,
+        texture_cache,
+        &source.document,
+        base_path,
+        &source.buffer_data,
+    )?;
     info!("Image Convert/Upload: {}", now.elapsed().as_secs_f32());
 
-    let samplers = load_samplers(device, &gltf_doc)?;
+    finish_gltf_scene(
+        device,
+        material_palette,
+        &source,
+        meshes,
+        images,
+        static_batching,
+    )
+}
 
-    let materials = load_materials(&gltf_doc, &images, &samplers);
+/// Everything after the meshes and images are uploaded, shared with the background `SceneLoader`
+pub(crate) fn finish_gltf_scene(
+    device: &mut neptune_vulkan::Device,
+    material_palette: &MaterialPalette,
+    source: &GltfSource,
+    mut meshes: Vec<Mesh>,
+    images: Vec<ImageHandle>,
+    static_batching: StaticBatching,
+) -> anyhow::Result<GltfScene> {
+    let gltf_doc = &source.document;
+    let samplers = load_samplers(device, gltf_doc)?;
 
-    let mut mesh_nodes = Vec::new();
+    let materials = load_materials(material_palette, gltf_doc, &images, &samplers)?;
 
-    if let Some(scene) = gltf_doc.default_scene() {
-        for root_node in scene.nodes() {
-            gltf_node(Mat4::IDENTITY, &mut mesh_nodes, &root_node);
-        }
+    let skins = load_skins(gltf_doc, &source.buffer_data);
+
+    let mut mesh_nodes = scene_mesh_nodes(gltf_doc);
+
+    if static_batching != StaticBatching::Off {
+        let now = std::time::Instant::now();
+        mesh_nodes = batch_static_nodes(
+            device,
+            gltf_doc,
+            &source.buffer_data,
+            &materials,
+            mesh_nodes,
+            &mut meshes,
+            static_batching == StaticBatching::Lightmapped,
+        )?;
+        info!("Static Batching: {}", now.elapsed().as_secs_f32());
     }
 
     Ok(GltfScene {
@@ -466,9 +725,248 @@ pub fn load_gltf_scene<P: AsRef<std::path::Path>>(
         samplers,
         materials,
         mesh_nodes,
+        skins,
     })
 }
 
+/// Merges every node that isn't skinned or blended into per material batches, returns the nodes left as they were.
+/// The original meshes are kept since they're still resources other things can look up by name
+fn batch_static_nodes(
+    device: &mut neptune_vulkan::Device,
+    gltf_doc: &gltf::Document,
+    gltf_buffers: &[gltf::buffer::Data],
+    materials: &[Material],
+    mesh_nodes: Vec<GltfNode>,
+    meshes: &mut Vec<Mesh>,
+    lightmaps: bool,
+) -> anyhow::Result<Vec<GltfNode>> {
+    let gltf_meshes: Vec<gltf::Mesh> = gltf_doc.meshes().collect();
+    let mut batcher = StaticBatcher::new(lightmaps);
+    let mut remaining_nodes = Vec::new();
+
+    for node in mesh_nodes {
+        let gltf_mesh = &gltf_meshes[node.mesh_index];
+        let skinned = gltf_mesh
+            .primitives()
+            .any(|primitive| primitive.get(&gltf::Semantic::Joints(0)).is_some());
+        //Blended draws are sorted by instance, merging them would break the ordering
+        let blended = node.primitive_materials.iter().any(|&material| {
+            materials
+                .get(material)
+                .map(|material| material.alpha_blending)
+                .unwrap_or_default()
+        });
+        if skinned || blended {
+            remaining_nodes.push(node);
+            continue;
+        }
+
+        for (gltf_primitive, &material) in
+            gltf_mesh.primitives().zip(node.primitive_materials.iter())
+        {
+            batcher.add(
+                &node.transform,
+                &read_primitive(gltf_buffers, &gltf_primitive)?,
+                material,
+            );
+        }
+    }
+
+    let primitive_count = batcher.primitive_count();
+    let batch_count = batcher.build(device, meshes, &mut remaining_nodes)?;
+    info!(
+        "Merged {} static primitives into {} batches",
+        primitive_count, batch_count
+    );
+    Ok(remaining_nodes)
+}
+
+pub fn load_skins(gltf_doc: &gltf::Document, buffer_data: &[gltf::buffer::Data]) -> Vec<GltfSkin> {
+    let mut node_parents = HashMap::new();
+    for node in gltf_doc.nodes() {
+        for child in node.children() {
+            node_parents.insert(child.index(), node.index());
+        }
+    }
+    let nodes: Vec<gltf::Node> = gltf_doc.nodes().collect();
+    let world_matrix = |mut index: Option<usize>| {
+        let mut matrix = Mat4::IDENTITY;
+        while let Some(node) = index {
+            matrix = Mat4::from_cols_array_2d(&nodes[node].transform().matrix()) * matrix;
+            index = node_parents.get(&node).copied();
+        }
+        matrix
+    };
+
+    gltf_doc
+        .skins()
+        .map(|skin| {
+            let joint_nodes: Vec<gltf::Node> = skin.joints().collect();
+            let node_to_joint: HashMap<usize, usize> = joint_nodes
+                .iter()
+                .enumerate()
+                .map(|(joint, node)| (node.index(), joint))
+                .collect();
+
+            let reader = skin.reader(|buffer| Some(&buffer_data[buffer.index()]));
+            let inverse_bind_matrices: Vec<Mat4> = reader
+                .read_inverse_bind_matrices()
+                .map(|matrices| {
+                    matrices
+                        .map(|matrix| Mat4::from_cols_array_2d(&matrix))
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            let joints = joint_nodes
+                .iter()
+                .enumerate()
+                .map(|(index, node)| {
+                    let (translation, rotation, scale) = node.transform().decomposed();
+                    Joint {
+                        name: node
+                            .name()
+                            .map(str::to_string)
+                            .unwrap_or_else(|| format!("Joint {}", index)),
+                        parent: node_parents
+                            .get(&node.index())
+                            .and_then(|parent| node_to_joint.get(parent))
+                            .copied(),
+                        rest_transform: Transform {
+                            position: Vec3::from(translation),
+                            rotation: Quat::from_array(rotation),
+                            scale: Vec3::from(scale),
+                        },
+                        inverse_bind_matrix: inverse_bind_matrices
+                            .get(index)
+                            .copied()
+                            .unwrap_or(Mat4::IDENTITY),
+                    }
+                })
+                .collect();
+
+            //Root joints are relative to their parent node, all of them are expected to share one
+            let root_parent = joint_nodes
+                .iter()
+                .map(|node| node_parents.get(&node.index()).copied())
+                .find(|parent| !parent.is_some_and(|parent| node_to_joint.contains_key(&parent)))
+                .flatten();
+            let transform = world_matrix(root_parent);
+
+            GltfSkin {
+                transform,
+                skeleton: Arc::new(Skeleton::new(joints)),
+                animations: gltf_doc
+                    .animations()
+                    .filter_map(|animation| load_animation(&animation, buffer_data, &node_to_joint))
+                    .map(Arc::new)
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/// Channels targeting nodes outside of the skin are skipped, None if nothing is left
+fn load_animation(
+    animation: &gltf::Animation,
+    buffer_data: &[gltf::buffer::Data],
+    node_to_joint: &HashMap<usize, usize>,
+) -> Option<AnimationClip> {
+    use gltf::animation::util::ReadOutputs;
+
+    let mut channels = Vec::new();
+    for channel in animation.channels() {
+        let Some(&joint) = node_to_joint.get(&channel.target().node().index()) else {
+            continue;
+        };
+
+        let reader = channel.reader(|buffer| Some(&buffer_data[buffer.index()]));
+        let (Some(inputs), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else {
+            continue;
+        };
+
+        //Cubic splines store (in tangent, value, out tangent) per key, only the values are kept
+        let (interpolation, stride, offset) = match channel.sampler().interpolation() {
+            gltf::animation::Interpolation::Step => (Interpolation::Step, 1, 0),
+            gltf::animation::Interpolation::Linear => (Interpolation::Linear, 1, 0),
+            gltf::animation::Interpolation::CubicSpline => (Interpolation::Linear, 3, 1),
+        };
+
+        let values = match outputs {
+            ReadOutputs::Translations(values) => ChannelValues::Translation(
+                values
+                    .skip(offset)
+                    .step_by(stride)
+                    .map(Vec3::from)
+                    .collect(),
+            ),
+            ReadOutputs::Rotations(values) => ChannelValues::Rotation(
+                values
+                    .into_f32()
+                    .skip(offset)
+                    .step_by(stride)
+                    .map(Quat::from_array)
+                    .collect(),
+            ),
+            ReadOutputs::Scales(values) => ChannelValues::Scale(
+                values
+                    .skip(offset)
+                    .step_by(stride)
+                    .map(Vec3::from)
+                    .collect(),
+            ),
+            ReadOutputs::MorphTargetWeights(_) => continue,
+        };
+
+        channels.push(Channel {
+            joint,
+            interpolation,
+            times: inputs.collect(),
+            values,
+        });
+    }
+
+    if channels.is_empty() {
+        None
+    } else {
+        //Unique names since the animation graph's states and triggers are named after their clips
+        Some(AnimationClip::new(
+            &animation
+                .name()
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("Animation {}", animation.index())),
+            channels,
+        ))
+    }
+}
+
+/// Every node in the default scene that has a mesh, with its world transform
+pub(crate) fn scene_mesh_nodes(gltf_doc: &gltf::Document) -> Vec<GltfNode> {
+    let mut mesh_nodes = Vec::new();
+    if let Some(scene) = gltf_doc.default_scene() {
+        for root_node in scene.nodes() {
+            gltf_node(Mat4::IDENTITY, &mut mesh_nodes, &root_node);
+        }
+    }
+    mesh_nodes
+}
+
+/// Indices of the images the material samples
+pub(crate) fn material_images(gltf_material: &gltf::Material) -> Vec<usize> {
+    let pbr = gltf_material.pbr_metallic_roughness();
+    [
+        pbr.base_color_texture().map(|info| info.texture()),
+        pbr.metallic_roughness_texture().map(|info| info.texture()),
+        gltf_material.normal_texture().map(|info| info.texture()),
+        gltf_material.occlusion_texture().map(|info| info.texture()),
+        gltf_material.emissive_texture().map(|info| info.texture()),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|texture| texture.source().index())
+    .collect()
+}
+
 fn gltf_node(parent_transform: Mat4, mesh_nodes: &mut Vec<GltfNode>, node: &gltf::Node) {
     let local_transform: Mat4 = Mat4::from_cols_array_2d(&node.transform().matrix());
     let world_transform = parent_transform * local_transform;
@@ -481,6 +979,8 @@ fn gltf_node(parent_transform: Mat4, mesh_nodes: &mut Vec<GltfNode>, node: &gltf
                 .primitives()
                 .map(|primitive| primitive.material().index().unwrap_or_default())
                 .collect(),
+            lightmap: None,
+            skin: node.skin().map(|skin| skin.index()),
         });
     }
 
@@ -494,22 +994,77 @@ pub struct GltfResources {
     pub materials: HashMap<String, Material>,
 }
 
+impl GltfResources {
+    pub fn from_scene(mut gltf_scene: GltfScene) -> Self {
+        Self {
+            meshes: gltf_scene
+                .meshes
+                .drain(..)
+                .map(|mesh| (mesh.name.clone(), mesh))
+                .collect(),
+            materials: gltf_scene
+                .materials
+                .drain(..)
+                .map(|material| (material.name.clone(), material))
+                .collect(),
+        }
+    }
+}
+
+/// Picks the loader from the file extension, obj files go through the obj loader and everything else is treated as gltf.
+/// `static_batching` merges the static geometry into per material batches
+pub fn load_model_scene<P: AsRef<std::path::Path>>(
+    device: &mut neptune_vulkan::Device,
+    texture_cache: &TextureCache,
+    primitive_cache: &mut PrimitiveCache,
+    material_palette: &MaterialPalette,
+    path: P,
+    static_batching: StaticBatching,
+) -> anyhow::Result<GltfScene> {
+    let path = path.as_ref();
+    let is_obj = path
+        .extension()
+        .map(|extension| extension.eq_ignore_ascii_case("obj"))
+        .unwrap_or(false);
+
+    if is_obj {
+        load_obj_scene(
+            device,
+            texture_cache,
+            primitive_cache,
+            material_palette,
+            path,
+            static_batching,
+        )
+    } else {
+        load_gltf_scene(
+            device,
+            texture_cache,
+            primitive_cache,
+            material_palette,
+            path,
+            static_batching,
+        )
+    }
+}
+
+/// Reads through `vfs` so the resources can come from an asset archive
 pub fn load_gltf_resources<P: AsRef<std::path::Path>>(
     device: &mut neptune_vulkan::Device,
+    texture_cache: &TextureCache,
+    primitive_cache: &mut PrimitiveCache,
+    material_palette: &MaterialPalette,
+    vfs: &Vfs,
     path: P,
 ) -> anyhow::Result<GltfResources> {
-    let mut gltf_scene = load_gltf_scene(device, path)?;
-
-    Ok(GltfResources {
-        meshes: gltf_scene
-            .meshes
-            .drain(..)
-            .map(|mesh| (mesh.name.clone(), mesh))
-            .collect(),
-        materials: gltf_scene
-            .materials
-            .drain(..)
-            .map(|material| (material.name.clone(), material))
-            .collect(),
-    })
+    let path = path.as_ref();
+    Ok(GltfResources::from_scene(load_gltf_scene_from_slice(
+        device,
+        texture_cache,
+        primitive_cache,
+        material_palette,
+        &vfs.read(path)?,
+        path.parent().unwrap_or_else(|| Path::new("./")),
+        StaticBatching::Off,
+    )?))
 }
@@ -0,0 +1,345 @@
+use crate::asset_database::AssetDatabase;
+use crate::camera::{Camera, FieldOfView};
+use crate::editor::create_test_world;
+use crate::mesh::batching::StaticBatching;
+use crate::mesh::primitive_cache::PrimitiveCache;
+use crate::scene::scene_renderer::{SceneCamera, SceneRenderer};
+use crate::transform::Transform;
+use crate::vfs::Vfs;
+use anyhow::Context;
+use glam::{EulerRot, Quat, Vec3};
+use image::RgbaImage;
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph_builder::RenderGraphBuilderTrait;
+use neptune_vulkan::{vk, DepthMode, DeviceSettings, ImageDescription2D, VulkanFuture};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// One view of a scene to render and compare against `<name>.png` next to the manifest
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GoldenCase {
+    pub name: String,
+    pub size: [u32; 2],
+    /// Extra model placed in the test world, same as the editor's `--model`
+    pub model: Option<PathBuf>,
+    pub camera_position: [f32; 3],
+    /// Yaw and pitch in degrees
+    pub camera_rotation: [f32; 2],
+    /// Frames rendered before the capture so temporal effects can settle
+    pub warmup_frames: u32,
+    /// Largest perceptual difference, 0 to 1, a pixel can have and still match
+    pub pixel_tolerance: f32,
+    /// Fraction of the pixels allowed to be over `pixel_tolerance`
+    pub max_differing_pixels: f32,
+}
+
+impl Default for GoldenCase {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            size: [640, 360],
+            model: None,
+            camera_position: [0.0, 0.0, -1.0],
+            camera_rotation: [0.0, 0.0],
+            warmup_frames: 8,
+            pixel_tolerance: 0.02,
+            max_differing_pixels: 0.001,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GoldenManifest {
+    #[serde(rename = "case")]
+    cases: Vec<GoldenCase>,
+}
+
+pub struct ImageDiff {
+    pub differing_pixels: usize,
+    pub max_difference: f32,
+    /// Matching pixels dimmed to greyscale, differing pixels in red
+    pub diff_image: RgbaImage,
+}
+
+impl ImageDiff {
+    pub fn differing_fraction(&self) -> f32 {
+        let pixel_count = (self.diff_image.width() * self.diff_image.height()).max(1);
+        self.differing_pixels as f32 / pixel_count as f32
+    }
+}
+
+/// Luma weighted distance between two colors, small hue shifts in dark or blue areas matter less than the same shift in green
+fn perceptual_difference(a: &image::Rgba<u8>, b: &image::Rgba<u8>) -> f32 {
+    const WEIGHTS: [f32; 3] = [0.299, 0.587, 0.114];
+    (0..3)
+        .map(|channel| {
+            let delta = (a[channel] as f32 - b[channel] as f32) / 255.0;
+            WEIGHTS[channel] * delta * delta
+        })
+        .sum::<f32>()
+        .sqrt()
+}
+
+/// Images must be the same size
+pub fn diff_images(expected: &RgbaImage, actual: &RgbaImage, pixel_tolerance: f32) -> ImageDiff {
+    let mut diff_image = RgbaImage::new(actual.width(), actual.height());
+    let mut differing_pixels = 0;
+    let mut max_difference = 0.0f32;
+
+    for ((expected_pixel, actual_pixel), diff_pixel) in expected
+        .pixels()
+        .zip(actual.pixels())
+        .zip(diff_image.pixels_mut())
+    {
+        let difference = perceptual_difference(expected_pixel, actual_pixel);
+        max_difference = max_difference.max(difference);
+        *diff_pixel = if difference > pixel_tolerance {
+            differing_pixels += 1;
+            image::Rgba([255, 0, 0, 255])
+        } else {
+            let luma = (actual_pixel[0] as u32 * 299
+                + actual_pixel[1] as u32 * 587
+                + actual_pixel[2] as u32 * 114)
+                / 3000;
+            image::Rgba([luma as u8, luma as u8, luma as u8, 255])
+        };
+    }
+
+    ImageDiff {
+        differing_pixels,
+        max_difference,
+        diff_image,
+    }
+}
+
+/// Renders scenes into offscreen images with no window or swapchain
+struct HeadlessRenderer {
+    device: neptune_vulkan::Device,
+    scene_renderer: SceneRenderer,
+    scene_camera: SceneCamera,
+    /// Every case loads the same resources, so their meshes are shared between cases
+    primitive_cache: PrimitiveCache,
+    _instance: neptune_vulkan::Instance,
+}
+
+impl HeadlessRenderer {
+    const OUTPUT_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+    const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+    const FRAMES_IN_FLIGHT: u32 = 3;
+
+    fn new() -> anyhow::Result<Self> {
+        let instance = neptune_vulkan::Instance::new(
+            &neptune_vulkan::AppInfo::new("Neptune Engine", [0, 0, 1, 0]),
+            &neptune_vulkan::AppInfo::new(crate::APP_NAME, [0, 0, 1, 0]),
+            neptune_vulkan::InstanceSettings::default(),
+            None,
+        )?;
+
+        let physical_device = instance
+            .select_physical_device(None, |physical_device| {
+                physical_device.supports_graphics() as usize
+            })
+            .context("Failed to find a suitable Vulkan device")?;
+        info!("Selected Device: {}", physical_device.info.name);
+
+        let mut device = physical_device
+            .create_device(DeviceSettings {
+                frames_in_flight: Self::FRAMES_IN_FLIGHT,
+                robust_access: false,
+                defragment: false,
+                validate_bindings: false,
+            })
+            .context("Failed to initialize vulkan device")?;
+
+        let mut scene_renderer = SceneRenderer::new(
+            &mut device,
+            Self::OUTPUT_FORMAT,
+            Self::DEPTH_FORMAT,
+            DepthMode::Standard,
+        )?;
+        //Dithering is random per frame, it would never match
+        scene_renderer.dithering = false;
+
+        let scene_camera = SceneCamera::new(&mut device, DepthMode::Standard)?;
+
+        Ok(Self {
+            device,
+            scene_renderer,
+            scene_camera,
+            primitive_cache: PrimitiveCache::default(),
+            _instance: instance,
+        })
+    }
+
+    fn render(&mut self, case: &GoldenCase) -> anyhow::Result<RgbaImage> {
+        //The last case's world is gone by now
+        self.primitive_cache.collect(&mut self.device);
+        let mut world = create_test_world(
+            &mut self.device,
+            &mut self.primitive_cache,
+            &mut self.scene_renderer,
+            //Saved material edits would change the render, the goldens only use the models as they are
+            &AssetDatabase::default(),
+            &Vfs::Loose,
+            case.model.as_deref(),
+            StaticBatching::Off,
+        )?;
+
+        let [width, height] = case.size;
+        let target_image = self.device.create_image(
+            "Golden Target",
+            &ImageDescription2D {
+                size: case.size,
+                format: Self::OUTPUT_FORMAT,
+                usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+                mip_levels: 1,
+                location: MemoryLocation::GpuOnly,
+            },
+        )?;
+
+        let camera = Camera::new(FieldOfView::X(90.0), 0.1, None);
+        let camera_transform = Transform {
+            position: Vec3::from_array(case.camera_position),
+            rotation: Quat::from_euler(
+                EulerRot::YXZ,
+                case.camera_rotation[0].to_radians(),
+                case.camera_rotation[1].to_radians(),
+                0.0,
+            ),
+            scale: Vec3::ONE,
+        };
+
+        //Readbacks finish once the frame's slot comes back around, so keep rendering until it does
+        let mut pixels: Option<VulkanFuture<Vec<u8>>> = None;
+        let capture_frame = case.warmup_frames;
+        let last_frame = capture_frame + Self::FRAMES_IN_FLIGHT + 1;
+        for frame in 0..=last_frame {
+            if pixels.as_ref().is_some_and(VulkanFuture::is_ready) {
+                break;
+            }
+            if frame == capture_frame {
+                pixels = Some(self.device.read_image(target_image)?);
+            }
+
+            let mut render_graph_builder = self.device.render_graph_builder();
+            self.scene_camera
+                .update(&camera, &camera_transform, case.size);
+            self.scene_camera
+                .write_render_passes(&mut render_graph_builder);
+            world
+                .data
+                .scene
+                .write_render_passes(&mut render_graph_builder);
+            self.scene_renderer.write_render_passes(
+                target_image,
+                &self.scene_camera,
+                &world.data.scene,
+                &mut render_graph_builder,
+            );
+
+            self.device.submit_graph(render_graph_builder.build())?;
+        }
+
+        self.device.destroy_image(target_image);
+
+        let pixels = pixels
+            .and_then(|mut pixels| pixels.try_take())
+            .with_context(|| format!("Capture of {} was never read back", case.name))?;
+        RgbaImage::from_raw(width, height, pixels).context("Readback size doesn't match image size")
+    }
+}
+
+/// Renders every case in the manifest and compares it to its golden image, failures write `<name>.actual.png` and `<name>.diff.png` to an `output` directory next to the manifest.
+/// A missing golden image is a failure, with `update` set the render is saved as the new golden image instead
+pub fn run(manifest_path: &Path, update: bool) -> anyhow::Result<()> {
+    let manifest: GoldenManifest = toml::from_str(
+        &std::fs::read_to_string(manifest_path)
+            .with_context(|| format!("Failed to read {}", manifest_path.display()))?,
+    )
+    .with_context(|| format!("Failed to parse {}", manifest_path.display()))?;
+
+    let golden_directory = manifest_path.parent().unwrap_or(Path::new("."));
+    let output_directory = golden_directory.join("output");
+
+    let mut renderer = HeadlessRenderer::new()?;
+    let mut failures = Vec::new();
+    for case in manifest.cases.iter() {
+        let actual = renderer
+            .render(case)
+            .with_context(|| format!("Failed to render {}", case.name))?;
+
+        let golden_path = golden_directory.join(format!("{}.png", case.name));
+        if update {
+            actual.save(&golden_path)?;
+            info!(
+                "{}: saved golden image {}",
+                case.name,
+                golden_path.display()
+            );
+            continue;
+        }
+
+        //A missing golden would otherwise pass whatever was rendered
+        if !golden_path.exists() {
+            std::fs::create_dir_all(&output_directory)?;
+            actual.save(output_directory.join(format!("{}.actual.png", case.name)))?;
+            error!(
+                "{}: golden image {} doesn't exist, run with --update-golden to accept the render in {}",
+                case.name,
+                golden_path.display(),
+                output_directory.display()
+            );
+            failures.push(case.name.clone());
+            continue;
+        }
+
+        let expected = image::open(&golden_path)
+            .with_context(|| format!("Failed to load {}", golden_path.display()))?
+            .into_rgba8();
+        if expected.dimensions() != actual.dimensions() {
+            error!(
+                "{}: golden image is {:?} but the render is {:?}",
+                case.name,
+                expected.dimensions(),
+                actual.dimensions()
+            );
+            failures.push(case.name.clone());
+            continue;
+        }
+
+        let diff = diff_images(&expected, &actual, case.pixel_tolerance);
+        if diff.differing_fraction() <= case.max_differing_pixels {
+            info!(
+                "{}: passed ({:.3}% differing, max difference {:.3})",
+                case.name,
+                diff.differing_fraction() * 100.0,
+                diff.max_difference
+            );
+            continue;
+        }
+
+        std::fs::create_dir_all(&output_directory)?;
+        actual.save(output_directory.join(format!("{}.actual.png", case.name)))?;
+        diff.diff_image
+            .save(output_directory.join(format!("{}.diff.png", case.name)))?;
+        error!(
+            "{}: failed ({:.3}% differing, max difference {:.3}), see {}",
+            case.name,
+            diff.differing_fraction() * 100.0,
+            diff.max_difference,
+            output_directory.display()
+        );
+        failures.push(case.name.clone());
+    }
+
+    anyhow::ensure!(
+        failures.is_empty(),
+        "{} of {} golden tests failed: {:?}",
+        failures.len(),
+        manifest.cases.len(),
+        failures
+    );
+    info!("All {} golden tests passed", manifest.cases.len());
+    Ok(())
+}
@@ -1,3 +1,7 @@
+pub mod replay;
+
+use crate::input_system::MouseCapture;
+
 pub type StaticString = &'static str;
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub enum ButtonState {
@@ -12,7 +16,7 @@ impl ButtonState {
 }
 
 pub trait InputEventReceiver {
-    fn requests_mouse_capture(&mut self) -> bool;
+    fn mouse_capture(&mut self) -> MouseCapture;
 
     fn on_button_event(&mut self, button_name: StaticString, state: ButtonState) -> bool;
     fn on_axis_event(&mut self, axis_name: StaticString, value: f32) -> bool;
@@ -0,0 +1,251 @@
+use crate::input::{ButtonState, InputEventReceiver, StaticString};
+use crate::input_system::MouseCapture;
+use crate::platform::WindowEventReceiver;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Input after it's been through the platform's bindings, so a recording doesn't depend on the keyboard layout or controller
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecordedEvent {
+    Button { name: String, pressed: bool },
+    Axis { name: String, value: f32 },
+    Text { text: String },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    /// Seconds since the recording started
+    pub time: f64,
+    /// Frame time passed to `Editor::update`, playback uses it instead of the real frame time
+    pub delta_time: f32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<RecordedEvent>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InputRecording {
+    #[serde(rename = "frame")]
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl InputRecording {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        toml::from_str(
+            &std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?,
+        )
+        .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, toml::to_string(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+enum ReplayMode {
+    Record {
+        path: PathBuf,
+        start: Instant,
+        recording: InputRecording,
+        events: Vec<RecordedEvent>,
+    },
+    Playback {
+        recording: InputRecording,
+        frame: usize,
+        /// Recorded names need to be &'static to go back through `InputEventReceiver`
+        names: HashMap<String, StaticString>,
+    },
+}
+
+/// Sits between the platform and the editor, either recording every input event or replacing live input with a recording
+pub struct InputReplay {
+    mode: ReplayMode,
+}
+
+impl InputReplay {
+    pub fn record(path: &Path) -> Self {
+        Self {
+            mode: ReplayMode::Record {
+                path: path.to_path_buf(),
+                start: Instant::now(),
+                recording: InputRecording::default(),
+                events: Vec::new(),
+            },
+        }
+    }
+
+    pub fn playback(path: &Path) -> anyhow::Result<Self> {
+        let recording = InputRecording::load(path)?;
+        info!(
+            "Playing back {} frames of input from {}",
+            recording.frames.len(),
+            path.display()
+        );
+        Ok(Self {
+            mode: ReplayMode::Playback {
+                recording,
+                frame: 0,
+                names: HashMap::new(),
+            },
+        })
+    }
+
+    /// Wraps the app for `Sdl2Platform::process_events`
+    pub fn receiver<'a, T: WindowEventReceiver + InputEventReceiver>(
+        &'a mut self,
+        app: &'a mut T,
+    ) -> ReplayReceiver<'a, T> {
+        ReplayReceiver { replay: self, app }
+    }
+
+    /// Sends the current frame's recorded events to the app and returns its recorded frame time.
+    /// While recording this does nothing and returns `delta_time` unchanged
+    pub fn begin_frame<T: InputEventReceiver>(&mut self, app: &mut T, delta_time: f32) -> f32 {
+        let ReplayMode::Playback {
+            recording,
+            frame,
+            names,
+        } = &mut self.mode
+        else {
+            return delta_time;
+        };
+
+        let Some(recorded_frame) = recording.frames.get(*frame) else {
+            return delta_time;
+        };
+
+        for event in recorded_frame.events.iter() {
+            match event {
+                RecordedEvent::Button { name, pressed } => {
+                    let state = if *pressed {
+                        ButtonState::Pressed
+                    } else {
+                        ButtonState::Released
+                    };
+                    let _ = app.on_button_event(intern(names, name), state);
+                }
+                RecordedEvent::Axis { name, value } => {
+                    let _ = app.on_axis_event(intern(names, name), *value);
+                }
+                RecordedEvent::Text { text } => {
+                    let _ = app.on_text_event(text.clone());
+                }
+            }
+        }
+        recorded_frame.delta_time
+    }
+
+    pub fn end_frame(&mut self, delta_time: f32) {
+        match &mut self.mode {
+            ReplayMode::Record {
+                start,
+                recording,
+                events,
+                ..
+            } => recording.frames.push(RecordedFrame {
+                time: start.elapsed().as_secs_f64(),
+                delta_time,
+                events: std::mem::take(events),
+            }),
+            ReplayMode::Playback { frame, .. } => *frame += 1,
+        }
+    }
+
+    /// Playback is finished once every recorded frame has been sent, recording never finishes
+    pub fn is_finished(&self) -> bool {
+        match &self.mode {
+            ReplayMode::Record { .. } => false,
+            ReplayMode::Playback {
+                recording, frame, ..
+            } => *frame >= recording.frames.len(),
+        }
+    }
+
+    /// Writes the recording to its file, does nothing during playback
+    pub fn save(&self) -> anyhow::Result<()> {
+        if let ReplayMode::Record {
+            path, recording, ..
+        } = &self.mode
+        {
+            recording.save(path)?;
+            info!(
+                "Recorded {} frames of input to {}",
+                recording.frames.len(),
+                path.display()
+            );
+        }
+        Ok(())
+    }
+
+    fn push_event(&mut self, event: RecordedEvent) {
+        if let ReplayMode::Record { events, .. } = &mut self.mode {
+            events.push(event);
+        }
+    }
+
+    fn is_playing_back(&self) -> bool {
+        matches!(self.mode, ReplayMode::Playback { .. })
+    }
+}
+
+fn intern(names: &mut HashMap<String, StaticString>, name: &str) -> StaticString {
+    names
+        .entry(name.to_string())
+        .or_insert_with(|| Box::leak(name.to_string().into_boxed_str()))
+}
+
+/// Forwards window events as is, live input is recorded or dropped during playback
+pub struct ReplayReceiver<'a, T> {
+    replay: &'a mut InputReplay,
+    app: &'a mut T,
+}
+
+impl<'a, T: WindowEventReceiver> WindowEventReceiver for ReplayReceiver<'a, T> {
+    fn on_window_size_changed(&mut self, new_size: [u32; 2]) -> anyhow::Result<()> {
+        self.app.on_window_size_changed(new_size)
+    }
+}
+
+impl<'a, T: InputEventReceiver> InputEventReceiver for ReplayReceiver<'a, T> {
+    fn mouse_capture(&mut self) -> MouseCapture {
+        let mut mouse_capture = self.app.mouse_capture();
+        mouse_capture.relative &= !self.replay.is_playing_back();
+        mouse_capture
+    }
+
+    fn on_button_event(&mut self, button_name: StaticString, state: ButtonState) -> bool {
+        if self.replay.is_playing_back() {
+            return false;
+        }
+        self.replay.push_event(RecordedEvent::Button {
+            name: button_name.to_string(),
+            pressed: state.is_down(),
+        });
+        self.app.on_button_event(button_name, state)
+    }
+
+    fn on_axis_event(&mut self, axis_name: StaticString, value: f32) -> bool {
+        if self.replay.is_playing_back() {
+            return false;
+        }
+        self.replay.push_event(RecordedEvent::Axis {
+            name: axis_name.to_string(),
+            value,
+        });
+        self.app.on_axis_event(axis_name, value)
+    }
+
+    fn on_text_event(&mut self, text: String) -> bool {
+        if self.replay.is_playing_back() {
+            return false;
+        }
+        self.replay
+            .push_event(RecordedEvent::Text { text: text.clone() });
+        self.app.on_text_event(text)
+    }
+}
@@ -6,10 +6,54 @@ trait InputDevice {
     fn is_button_pressed(&self, button_name: StaticString) -> Option<bool>;
 }
 
+/// How the app wants the mouse handled, the platform applies it at the start of every `process_events`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MouseCapture {
+    /// Pointer lock: the cursor is hidden and held in place and only relative motion is reported, for fly-camera control.
+    /// Turning it on locks right away, while it's on left clicking the window locks again after escape released it
+    pub relative: bool,
+    /// Cursor visibility while the pointer isn't locked
+    pub cursor_visible: bool,
+    /// Unaccelerated relative motion, skipping the OS pointer scaling on platforms that allow it
+    pub raw_input: bool,
+}
+
+impl Default for MouseCapture {
+    fn default() -> Self {
+        Self {
+            relative: false,
+            cursor_visible: true,
+            raw_input: false,
+        }
+    }
+}
+
 #[derive(Default)]
-pub struct InputSystem {}
+pub struct InputSystem {
+    mouse_capture: MouseCapture,
+}
 
 impl InputSystem {
+    pub fn mouse_capture(&self) -> MouseCapture {
+        self.mouse_capture
+    }
+
+    pub fn set_relative_mouse(&mut self, relative: bool) {
+        self.mouse_capture.relative = relative;
+    }
+
+    pub fn toggle_relative_mouse(&mut self) {
+        self.mouse_capture.relative = !self.mouse_capture.relative;
+    }
+
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.mouse_capture.cursor_visible = visible;
+    }
+
+    pub fn set_raw_mouse_input(&mut self, raw_input: bool) {
+        self.mouse_capture.raw_input = raw_input;
+    }
+
     pub fn get_axis(&self, axis_name: StaticString) -> Option<f32> {
         None
     }
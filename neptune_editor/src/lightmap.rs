@@ -0,0 +1,407 @@
+use crate::gltf_loader::PrimitiveData;
+use crate::scene::spatial::Ray;
+use glam::{Mat3, Mat4, Vec2, Vec3, Vec4};
+use std::collections::HashMap;
+
+/// Cpu side copy of a primitive used for unwrapping and baking
+#[derive(Debug, Default, Clone)]
+pub struct LightmapMesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub indices: Vec<u32>,
+    pub lightmap_uvs: Vec<Vec2>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LightmapAtlas {
+    pub size: [u32; 2],
+    /// Source vertex for every vertex of the unwrapped mesh, vertices on chart seams are duplicated
+    pub vertex_remap: Vec<u32>,
+}
+
+struct Chart {
+    triangles: Vec<usize>,
+    axis: usize,
+    min: Vec2,
+    size: [u32; 2],
+    offset: [u32; 2],
+}
+
+impl LightmapMesh {
+    /// Generates the second uv set by splitting the mesh into charts of connected triangles facing the same axis,
+    /// projecting each chart onto that axis and shelf packing the charts into an atlas
+    pub fn unwrap(&mut self, texels_per_unit: f32, padding: u32) -> LightmapAtlas {
+        let triangle_count = self.indices.len() / 3;
+        let triangle = |index: usize| {
+            [
+                self.indices[index * 3] as usize,
+                self.indices[index * 3 + 1] as usize,
+                self.indices[index * 3 + 2] as usize,
+            ]
+        };
+
+        //Dominant axis of the face normal, +X -X +Y -Y +Z -Z
+        let triangle_axis: Vec<usize> = (0..triangle_count)
+            .map(|index| {
+                let [a, b, c] = triangle(index);
+                let normal = (self.positions[b] - self.positions[a])
+                    .cross(self.positions[c] - self.positions[a]);
+                let abs_normal = normal.abs();
+                let axis = if abs_normal.x >= abs_normal.y && abs_normal.x >= abs_normal.z {
+                    0
+                } else if abs_normal.y >= abs_normal.z {
+                    1
+                } else {
+                    2
+                };
+                axis * 2 + (normal[axis] < 0.0) as usize
+            })
+            .collect();
+
+        let mut vertex_triangles = vec![Vec::new(); self.positions.len()];
+        for index in 0..triangle_count {
+            for vertex in triangle(index) {
+                vertex_triangles[vertex].push(index);
+            }
+        }
+
+        let mut charts: Vec<Chart> = Vec::new();
+        let mut triangle_chart = vec![usize::MAX; triangle_count];
+        for start in 0..triangle_count {
+            if triangle_chart[start] != usize::MAX {
+                continue;
+            }
+
+            let chart_index = charts.len();
+            let axis = triangle_axis[start];
+            let mut triangles = Vec::new();
+            let mut stack = vec![start];
+            triangle_chart[start] = chart_index;
+            while let Some(index) = stack.pop() {
+                triangles.push(index);
+                for vertex in triangle(index) {
+                    for &neighbor in vertex_triangles[vertex].iter() {
+                        if triangle_chart[neighbor] == usize::MAX && triangle_axis[neighbor] == axis
+                        {
+                            triangle_chart[neighbor] = chart_index;
+                            stack.push(neighbor);
+                        }
+                    }
+                }
+            }
+
+            let mut min = Vec2::splat(f32::MAX);
+            let mut max = Vec2::splat(f32::MIN);
+            for &index in triangles.iter() {
+                for vertex in triangle(index) {
+                    let point = project(self.positions[vertex], axis) * texels_per_unit;
+                    min = min.min(point);
+                    max = max.max(point);
+                }
+            }
+            let extent = (max - min).ceil().max(Vec2::ONE);
+
+            charts.push(Chart {
+                triangles,
+                axis,
+                min,
+                size: [
+                    extent.x as u32 + (padding * 2),
+                    extent.y as u32 + (padding * 2),
+                ],
+                offset: [0; 2],
+            });
+        }
+
+        //Shelf packing, tallest charts first
+        let total_area: u32 = charts
+            .iter()
+            .map(|chart| chart.size[0] * chart.size[1])
+            .sum();
+        let widest_chart = charts.iter().map(|chart| chart.size[0]).max().unwrap_or(1);
+        let atlas_width = widest_chart
+            .max((total_area as f32).sqrt().ceil() as u32)
+            .next_power_of_two();
+
+        let mut order: Vec<usize> = (0..charts.len()).collect();
+        order.sort_by(|a, b| charts[*b].size[1].cmp(&charts[*a].size[1]));
+
+        let mut shelf_x = 0;
+        let mut shelf_y = 0;
+        let mut shelf_height = 0;
+        for chart_index in order {
+            let chart = &mut charts[chart_index];
+            if shelf_x + chart.size[0] > atlas_width {
+                shelf_x = 0;
+                shelf_y += shelf_height;
+                shelf_height = 0;
+            }
+            chart.offset = [shelf_x, shelf_y];
+            shelf_x += chart.size[0];
+            shelf_height = shelf_height.max(chart.size[1]);
+        }
+        let atlas_size = [atlas_width, (shelf_y + shelf_height).next_power_of_two()];
+
+        //Rebuild the vertices so each chart owns its own copies
+        let mut vertex_remap = Vec::with_capacity(self.positions.len());
+        let mut lightmap_uvs = Vec::with_capacity(self.positions.len());
+        let mut indices = vec![0; self.indices.len()];
+        for chart in charts.iter() {
+            let mut chart_vertices: HashMap<usize, u32> = HashMap::new();
+            for &index in chart.triangles.iter() {
+                for (corner, vertex) in triangle(index).into_iter().enumerate() {
+                    indices[index * 3 + corner] =
+                        *chart_vertices.entry(vertex).or_insert_with(|| {
+                            let texel = (project(self.positions[vertex], chart.axis)
+                                * texels_per_unit)
+                                - chart.min
+                                + Vec2::new(
+                                    (chart.offset[0] + padding) as f32,
+                                    (chart.offset[1] + padding) as f32,
+                                );
+                            lightmap_uvs.push(
+                                texel / Vec2::new(atlas_size[0] as f32, atlas_size[1] as f32),
+                            );
+                            vertex_remap.push(vertex as u32);
+                            (vertex_remap.len() - 1) as u32
+                        });
+                }
+            }
+        }
+
+        self.positions = vertex_remap
+            .iter()
+            .map(|&vertex| self.positions[vertex as usize])
+            .collect();
+        self.normals = vertex_remap
+            .iter()
+            .map(|&vertex| self.normals[vertex as usize])
+            .collect();
+        self.indices = indices;
+        self.lightmap_uvs = lightmap_uvs;
+
+        LightmapAtlas {
+            size: atlas_size,
+            vertex_remap,
+        }
+    }
+}
+
+fn project(position: Vec3, axis: usize) -> Vec2 {
+    match axis / 2 {
+        0 => Vec2::new(position.z, position.y),
+        1 => Vec2::new(position.x, position.z),
+        _ => Vec2::new(position.x, position.y),
+    }
+}
+
+/// Where a texel of a lightmap is on its surface, matches LightmapTexel in lightmap_bake.comp
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+pub struct LightmapTexel {
+    /// w is 1 for texels a chart covers
+    pub position_covered: Vec4,
+    pub normal: Vec4,
+}
+
+/// An unwrapped static primitive, kept on the cpu until its lightmap is baked
+#[derive(Debug, Clone)]
+pub struct LightmapLayout {
+    pub mesh: LightmapMesh,
+    pub size: [u32; 2],
+}
+
+impl LightmapLayout {
+    /// Density of the lightmaps generated for static geometry
+    pub const TEXELS_PER_UNIT: f32 = 4.0;
+    /// The density is halved until the atlas fits in this
+    pub const MAX_SIZE: u32 = 4096;
+    const PADDING: u32 = 2;
+    /// Covered texels grown into the padding so bilinear filtering doesn't pull in black at chart edges
+    const DILATE_PASSES: u32 = 2;
+
+    /// Replaces `data`'s vertices with the unwrapped ones, with the lightmap uvs written to uv1
+    pub(crate) fn unwrap(data: &mut PrimitiveData) -> Self {
+        let source = LightmapMesh {
+            positions: data.positions.clone(),
+            normals: data
+                .attributes
+                .iter()
+                .map(|attribute| attribute.normal)
+                .collect(),
+            indices: data
+                .indices
+                .clone()
+                .unwrap_or_else(|| (0..data.positions.len() as u32).collect()),
+            lightmap_uvs: Vec::new(),
+        };
+
+        let mut texels_per_unit = Self::TEXELS_PER_UNIT;
+        let (mesh, atlas) = loop {
+            let mut mesh = source.clone();
+            let atlas = mesh.unwrap(texels_per_unit, Self::PADDING);
+            if atlas.size[0].max(atlas.size[1]) <= Self::MAX_SIZE {
+                break (mesh, atlas);
+            }
+            texels_per_unit *= 0.5;
+        };
+
+        data.positions = mesh.positions.clone();
+        data.attributes = atlas
+            .vertex_remap
+            .iter()
+            .zip(mesh.lightmap_uvs.iter())
+            .map(|(&vertex, uv)| {
+                let mut attribute = data.attributes[vertex as usize];
+                attribute.tex_coords[2] = uv.x;
+                attribute.tex_coords[3] = uv.y;
+                attribute
+            })
+            .collect();
+        data.skinning = data.skinning.take().map(|skinning| {
+            atlas
+                .vertex_remap
+                .iter()
+                .map(|&vertex| skinning[vertex as usize])
+                .collect()
+        });
+        data.indices = Some(mesh.indices.clone());
+
+        Self {
+            mesh,
+            size: atlas.size,
+        }
+    }
+
+    /// World space position and normal of every texel, what the baker traces from
+    pub fn texels(&self, transform: &Mat4) -> Vec<LightmapTexel> {
+        let normal_matrix = Mat3::from_mat4(*transform).inverse().transpose();
+        let size = self.size;
+        let texel_size = Vec2::new(size[0] as f32, size[1] as f32);
+        let mesh = &self.mesh;
+
+        let mut texels = vec![LightmapTexel::default(); (size[0] * size[1]) as usize];
+        for indices in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [
+                indices[0] as usize,
+                indices[1] as usize,
+                indices[2] as usize,
+            ];
+            let uvs = [
+                mesh.lightmap_uvs[a] * texel_size,
+                mesh.lightmap_uvs[b] * texel_size,
+                mesh.lightmap_uvs[c] * texel_size,
+            ];
+
+            let min = uvs[0].min(uvs[1]).min(uvs[2]).floor().max(Vec2::ZERO);
+            let max = uvs[0]
+                .max(uvs[1])
+                .max(uvs[2])
+                .ceil()
+                .min(texel_size - Vec2::ONE);
+
+            for y in (min.y as u32)..=(max.y as u32) {
+                for x in (min.x as u32)..=(max.x as u32) {
+                    let Some([w0, w1, w2]) =
+                        barycentric(Vec2::new(x as f32 + 0.5, y as f32 + 0.5), &uvs)
+                    else {
+                        continue;
+                    };
+
+                    let position = transform.transform_point3(
+                        (mesh.positions[a] * w0)
+                            + (mesh.positions[b] * w1)
+                            + (mesh.positions[c] * w2),
+                    );
+                    let normal = (normal_matrix
+                        * ((mesh.normals[a] * w0)
+                            + (mesh.normals[b] * w1)
+                            + (mesh.normals[c] * w2)))
+                        .normalize_or_zero();
+
+                    texels[(y * size[0] + x) as usize] = LightmapTexel {
+                        position_covered: position.extend(1.0),
+                        normal: normal.extend(0.0),
+                    };
+                }
+            }
+        }
+
+        dilate(&mut texels, size, Self::DILATE_PASSES);
+        texels
+    }
+}
+
+/// Uncovered texels take the average surface of their covered neighbors
+fn dilate(texels: &mut [LightmapTexel], size: [u32; 2], passes: u32) {
+    let [width, height] = [size[0] as i32, size[1] as i32];
+    for _ in 0..passes {
+        let source = texels.to_vec();
+        for y in 0..height {
+            for x in 0..width {
+                if source[(y * width + x) as usize].position_covered.w > 0.0 {
+                    continue;
+                }
+
+                let mut position_sum = Vec4::ZERO;
+                let mut normal_sum = Vec4::ZERO;
+                for (offset_x, offset_y) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                    let (sample_x, sample_y) = (x + offset_x, y + offset_y);
+                    if (0..width).contains(&sample_x) && (0..height).contains(&sample_y) {
+                        let texel = source[(sample_y * width + sample_x) as usize];
+                        if texel.position_covered.w > 0.0 {
+                            position_sum += texel.position_covered;
+                            normal_sum += texel.normal;
+                        }
+                    }
+                }
+
+                if position_sum.w > 0.0 {
+                    texels[(y * width + x) as usize] = LightmapTexel {
+                        position_covered: (position_sum / position_sum.w).truncate().extend(1.0),
+                        normal: normal_sum.truncate().normalize_or_zero().extend(0.0),
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Möller–Trumbore, double sided
+pub(crate) fn ray_triangle_intersect(ray: &Ray, [a, b, c]: &[Vec3; 3]) -> Option<f32> {
+    let edge1 = *b - *a;
+    let edge2 = *c - *a;
+    let p = ray.direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let inverse_determinant = 1.0 / determinant;
+    let t = ray.origin - *a;
+    let u = t.dot(p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = t.cross(edge1);
+    let v = ray.direction.dot(q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let distance = edge2.dot(q) * inverse_determinant;
+    (distance > 0.0).then_some(distance)
+}
+
+fn barycentric(point: Vec2, [a, b, c]: &[Vec2; 3]) -> Option<[f32; 3]> {
+    let area = (*b - *a).perp_dot(*c - *a);
+    if area.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let w0 = (*b - point).perp_dot(*c - point) / area;
+    let w1 = (*c - point).perp_dot(*a - point) / area;
+    let w2 = 1.0 - w0 - w1;
+    (w0 >= 0.0 && w1 >= 0.0 && w2 >= 0.0).then_some([w0, w1, w2])
+}
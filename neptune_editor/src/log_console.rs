@@ -0,0 +1,388 @@
+use crate::scene::sprite_renderer::{Sprite, SpriteRenderer};
+use glam::{Vec2, Vec3, Vec4};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Subsystem a log line came from, each one has its own level that can be changed at runtime
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LogCategory {
+    Vulkan,
+    Graph,
+    Assets,
+    Editor,
+    /// Shader printf output
+    Shader,
+    /// Everything outside the engine crates (sdl, rapier, etc)
+    Other,
+}
+
+impl LogCategory {
+    pub const ALL: [LogCategory; 6] = [
+        LogCategory::Vulkan,
+        LogCategory::Graph,
+        LogCategory::Assets,
+        LogCategory::Editor,
+        LogCategory::Shader,
+        LogCategory::Other,
+    ];
+
+    /// Target prefixes for each category, checked in order so the more specific module paths come first.
+    /// The short names can also be used directly, e.g. `info!(target: "assets", ...)`
+    const TARGETS: &'static [(&'static str, LogCategory)] = &[
+        ("graph", LogCategory::Graph),
+        ("neptune_vulkan::render_graph", LogCategory::Graph),
+        (
+            "neptune_vulkan::basic_render_graph_builder",
+            LogCategory::Graph,
+        ),
+        ("vulkan", LogCategory::Vulkan),
+        ("neptune_vulkan", LogCategory::Vulkan),
+        ("assets", LogCategory::Assets),
+        ("neptune_editor::gltf_loader", LogCategory::Assets),
+        ("neptune_editor::obj_loader", LogCategory::Assets),
+        ("neptune_editor::texture_cache", LogCategory::Assets),
+        ("neptune_editor::material_asset", LogCategory::Assets),
+        ("neptune_editor::mesh", LogCategory::Assets),
+        ("shader", LogCategory::Shader),
+        ("editor", LogCategory::Editor),
+        ("neptune_editor", LogCategory::Editor),
+    ];
+
+    pub fn from_target(target: &str) -> Self {
+        Self::TARGETS
+            .iter()
+            .find(|(prefix, _)| target.starts_with(prefix))
+            .map(|(_, category)| *category)
+            .unwrap_or(LogCategory::Other)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogCategory::Vulkan => "vulkan",
+            LogCategory::Graph => "graph",
+            LogCategory::Assets => "assets",
+            LogCategory::Editor => "editor",
+            LogCategory::Shader => "shader",
+            LogCategory::Other => "other",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|category| category.name().eq_ignore_ascii_case(name))
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+
+    fn color(&self) -> Vec4 {
+        match self {
+            LogCategory::Vulkan => Vec4::new(0.8, 0.2, 0.2, 1.0),
+            LogCategory::Graph => Vec4::new(0.9, 0.6, 0.1, 1.0),
+            LogCategory::Assets => Vec4::new(0.2, 0.7, 0.9, 1.0),
+            LogCategory::Editor => Vec4::new(0.3, 0.8, 0.3, 1.0),
+            LogCategory::Shader => Vec4::new(0.8, 0.4, 0.9, 1.0),
+            LogCategory::Other => Vec4::new(0.6, 0.6, 0.6, 1.0),
+        }
+    }
+}
+
+pub fn category_level(category: LogCategory) -> LevelFilter {
+    level_from_usize(CATEGORY_LEVELS[category.index()].load(Ordering::Relaxed))
+}
+
+pub fn set_category_level(category: LogCategory, level: LevelFilter) {
+    CATEGORY_LEVELS[category.index()].store(level as usize, Ordering::Relaxed);
+}
+
+fn level_from_usize(value: usize) -> LevelFilter {
+    LevelFilter::iter().nth(value).unwrap_or(LevelFilter::Trace)
+}
+
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub level: Level,
+    pub category: LogCategory,
+    pub message: String,
+}
+
+static CATEGORY_LEVELS: [AtomicUsize; LogCategory::ALL.len()] = [
+    AtomicUsize::new(LevelFilter::Info as usize),
+    AtomicUsize::new(LevelFilter::Info as usize),
+    AtomicUsize::new(LevelFilter::Info as usize),
+    AtomicUsize::new(LevelFilter::Info as usize),
+    AtomicUsize::new(LevelFilter::Info as usize),
+    AtomicUsize::new(LevelFilter::Warn as usize),
+];
+static HISTORY: Mutex<VecDeque<LogLine>> = Mutex::new(VecDeque::new());
+const HISTORY_CAPACITY: usize = 256;
+
+/// Up to `count` of the most recent lines that passed their category's filter, oldest first
+pub fn recent_lines(count: usize) -> Vec<LogLine> {
+    let history = HISTORY.lock().unwrap_or_else(|err| err.into_inner());
+    history
+        .iter()
+        .skip(history.len().saturating_sub(count))
+        .cloned()
+        .collect()
+}
+
+/// Filters by category then hands the line to pretty_env_logger's formatting and keeps a copy for the console
+struct CategoryLogger {
+    output: pretty_env_logger::env_logger::Logger,
+}
+
+impl Log for CategoryLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= category_level(LogCategory::from_target(metadata.target()))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        self.output.log(record);
+
+        //Formatted before locking so a panicking Display impl can't leave the history locked for the crash report
+        let line = LogLine {
+            level: record.level(),
+            category: LogCategory::from_target(record.target()),
+            message: record.args().to_string(),
+        };
+        let mut history = HISTORY.lock().unwrap_or_else(|err| err.into_inner());
+        if history.len() >= HISTORY_CAPACITY {
+            history.pop_front();
+        }
+        history.push_back(line);
+    }
+
+    fn flush(&self) {
+        self.output.flush();
+    }
+}
+
+/// Replaces `pretty_env_logger::init_timed`.
+/// Starting levels come from `NEPTUNE_LOG`, either a single level for every category or a list like `vulkan=warn,graph=debug`
+pub fn init() {
+    if let Ok(filters) = std::env::var("NEPTUNE_LOG") {
+        for filter in filters.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            match filter.split_once('=') {
+                Some((name, level)) => match (LogCategory::from_name(name), level.parse()) {
+                    (Some(category), Ok(level)) => set_category_level(category, level),
+                    _ => eprintln!("Invalid NEPTUNE_LOG filter: {}", filter),
+                },
+                None => match filter.parse() {
+                    Ok(level) => LogCategory::ALL
+                        .into_iter()
+                        .for_each(|category| set_category_level(category, level)),
+                    Err(_) => eprintln!("Invalid NEPTUNE_LOG level: {}", filter),
+                },
+            }
+        }
+    }
+
+    //Filtering is all done by category, so the inner logger lets everything through
+    let output = pretty_env_logger::formatted_timed_builder()
+        .filter_level(LevelFilter::Trace)
+        .build();
+    log::set_boxed_logger(Box::new(CategoryLogger { output }))
+        .expect("Logger was already initialized");
+    log::set_max_level(LevelFilter::Trace);
+}
+
+/// Recent log lines along the bottom of the screen, drawn with sprites since there is no text rendering yet.
+/// Each row is a severity marker, a category marker, then a bar as long as the message
+pub struct LogConsole {
+    pub visible: bool,
+    /// Only lines from this category are shown and `cycle_level` only changes its level, None is every category
+    selected: Option<LogCategory>,
+    visible_lines: usize,
+}
+
+impl Default for LogConsole {
+    fn default() -> Self {
+        Self {
+            visible: false,
+            selected: None,
+            visible_lines: Self::MAX_VISIBLE_LINES,
+        }
+    }
+}
+
+impl LogConsole {
+    const MAX_VISIBLE_LINES: usize = 32;
+    const LAYER: i32 = 1100;
+
+    const MARGIN: f32 = 8.0;
+    const ROW_HEIGHT: f32 = 6.0;
+    const MARKER_WIDTH: f32 = 8.0;
+    const CHARACTER_WIDTH: f32 = 3.0;
+    const MAX_BAR_WIDTH: f32 = 480.0;
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Shrinks the console to at most a third of the window's height
+    pub fn resize(&mut self, surface_size: [u32; 2]) {
+        let rows = (surface_size[1] as f32 / 3.0 - Self::MARGIN * 2.0) / Self::ROW_HEIGHT;
+        //One row is the category header
+        self.visible_lines = (rows as usize)
+            .saturating_sub(1)
+            .clamp(1, Self::MAX_VISIBLE_LINES);
+    }
+
+    pub fn cycle_category(&mut self) {
+        self.selected = match self.selected {
+            None => Some(LogCategory::ALL[0]),
+            Some(category) => LogCategory::ALL.get(category.index() + 1).copied(),
+        };
+        info!(
+            "Log console showing: {}",
+            self.selected
+                .map(|category| category.name())
+                .unwrap_or("all")
+        );
+    }
+
+    /// Steps the selected categories through Error, Warn, Info, Debug, Trace then Off
+    pub fn cycle_level(&mut self) {
+        let categories: Vec<LogCategory> = match self.selected {
+            Some(category) => vec![category],
+            None => LogCategory::ALL.to_vec(),
+        };
+
+        let current = category_level(categories[0]);
+        let next = if current == LevelFilter::Trace {
+            LevelFilter::Off
+        } else {
+            level_from_usize(current as usize + 1)
+        };
+        for category in categories.iter() {
+            set_category_level(*category, next);
+        }
+
+        //Logged to stderr so it still shows when the editor category was just turned off
+        eprintln!(
+            "Log level for {}: {}",
+            self.selected
+                .map(|category| category.name())
+                .unwrap_or("all"),
+            next
+        );
+    }
+
+    pub fn draw(&self, surface_size: [u32; 2], sprite_renderer: &mut SpriteRenderer) {
+        if !self.visible {
+            return;
+        }
+
+        let lines: Vec<LogLine> = recent_lines(HISTORY_CAPACITY)
+            .into_iter()
+            .filter(|line| {
+                self.selected
+                    .is_none_or(|category| line.category == category)
+            })
+            .collect();
+        let lines = &lines[lines.len().saturating_sub(self.visible_lines)..];
+
+        //One extra row for the category header
+        let panel_size = Vec2::new(
+            Self::MAX_BAR_WIDTH + Self::MARKER_WIDTH * 2.0 + Self::MARGIN * 4.0,
+            (self.visible_lines + 1) as f32 * Self::ROW_HEIGHT + Self::MARGIN * 2.0,
+        );
+        let panel_position = Vec2::new(0.0, surface_size[1] as f32 - panel_size.y);
+        draw_rect(
+            sprite_renderer,
+            panel_position,
+            panel_size,
+            Vec4::new(0.0, 0.0, 0.0, 0.7),
+            0,
+        );
+
+        //Header has a marker per category, dimmed when not shown and as wide as its level
+        let mut position = panel_position + Vec2::splat(Self::MARGIN);
+        for category in LogCategory::ALL {
+            let shown = self.selected.is_none_or(|selected| selected == category);
+            let width = Self::MARKER_WIDTH * (category_level(category) as usize as f32 + 1.0);
+            let alpha = if shown { 1.0 } else { 0.25 };
+            draw_rect(
+                sprite_renderer,
+                position,
+                Vec2::new(width, Self::ROW_HEIGHT - 2.0),
+                category.color() * Vec4::new(1.0, 1.0, 1.0, alpha),
+                1,
+            );
+            position.x += Self::MARKER_WIDTH * 7.0;
+        }
+
+        let mut position = panel_position
+            + Vec2::new(
+                Self::MARGIN,
+                Self::MARGIN + Self::ROW_HEIGHT * (self.visible_lines - lines.len() + 1) as f32,
+            );
+        for line in lines {
+            draw_rect(
+                sprite_renderer,
+                position,
+                Vec2::new(Self::MARKER_WIDTH, Self::ROW_HEIGHT - 1.0),
+                level_color(line.level),
+                1,
+            );
+            draw_rect(
+                sprite_renderer,
+                position + Vec2::new(Self::MARKER_WIDTH + Self::MARGIN * 0.5, 0.0),
+                Vec2::new(Self::MARKER_WIDTH, Self::ROW_HEIGHT - 1.0),
+                line.category.color(),
+                1,
+            );
+            draw_rect(
+                sprite_renderer,
+                position + Vec2::new(Self::MARKER_WIDTH * 2.0 + Self::MARGIN, 1.0),
+                Vec2::new(
+                    (line.message.len() as f32 * Self::CHARACTER_WIDTH).min(Self::MAX_BAR_WIDTH),
+                    Self::ROW_HEIGHT - 3.0,
+                ),
+                level_color(line.level) * Vec4::new(0.7, 0.7, 0.7, 1.0),
+                1,
+            );
+            position.y += Self::ROW_HEIGHT;
+        }
+    }
+}
+
+fn level_color(level: Level) -> Vec4 {
+    match level {
+        Level::Error => Vec4::new(1.0, 0.2, 0.2, 1.0),
+        Level::Warn => Vec4::new(1.0, 0.8, 0.2, 1.0),
+        Level::Info => Vec4::new(0.9, 0.9, 0.9, 1.0),
+        Level::Debug => Vec4::new(0.4, 0.6, 1.0, 1.0),
+        Level::Trace => Vec4::new(0.6, 0.4, 0.8, 1.0),
+    }
+}
+
+/// `position` is the top left corner in pixels
+fn draw_rect(
+    sprite_renderer: &mut SpriteRenderer,
+    position: Vec2,
+    size: Vec2,
+    color: Vec4,
+    layer: i32,
+) {
+    if size.x <= 0.0 || size.y <= 0.0 {
+        return;
+    }
+
+    sprite_renderer.draw(Sprite {
+        layer: LogConsole::LAYER + layer,
+        position: Vec3::new(position.x + size.x * 0.5, position.y + size.y * 0.5, 0.0),
+        size,
+        color,
+        ..Default::default()
+    });
+}
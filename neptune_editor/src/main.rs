@@ -1,33 +1,97 @@
+mod animation;
+mod asset_archive;
+mod asset_database;
+mod buffer_inspector;
 mod camera;
+mod camera_2d;
+mod camera_bookmarks;
+mod crash_report;
+mod derived_data;
+mod edit_history;
 mod editor;
+mod events;
+mod fallback;
+mod frame_recorder;
 mod game;
 mod gltf_loader;
+mod golden_test;
 mod input;
 mod input_system;
+mod lightmap;
+mod log_console;
 mod material;
+mod material_asset;
+mod material_editor;
 mod mesh;
+mod navmesh;
+mod obj_loader;
 mod physics;
 mod platform;
+mod render_settings;
+mod render_thread;
 mod scene;
+mod scene_loader;
+mod search;
 mod shader;
+mod shader_graph;
+mod shader_library;
+mod stats_overlay;
+mod texture_cache;
+mod texture_preview;
+mod time;
 mod transform;
 mod universe;
+mod upload_scheduler;
+mod vfs;
+mod viewport;
 
 #[macro_use]
 extern crate log;
 
 use crate::editor::{Editor, EditorConfig};
+use crate::input::replay::InputReplay;
 use crate::platform::sdl2::WindowSize;
 use clap::Parser;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 pub const APP_NAME: &str = "Neptune Editor";
 
 fn main() -> anyhow::Result<()> {
-    pretty_env_logger::init_timed();
+    log_console::init();
+    crash_report::install(std::path::Path::new("crash_reports"));
 
     let config = EditorConfig::parse();
 
+    if let Some(archive_path) = &config.pack {
+        let stats = asset_archive::AssetArchive::pack(vfs::Vfs::RESOURCE_ROOT, archive_path)?;
+        info!(
+            "Packed {} files into {}: {} -> {} bytes",
+            stats.files,
+            archive_path.display(),
+            stats.uncompressed_size,
+            stats.archive_size
+        );
+        return Ok(());
+    }
+
+    if config.gpu_info {
+        //No window, so devices that can't present are listed too
+        let instance = neptune_vulkan::Instance::new(
+            &neptune_vulkan::AppInfo::new("Neptune Engine", [0, 0, 1, 0]),
+            &neptune_vulkan::AppInfo::new(APP_NAME, [0, 0, 1, 0]),
+            neptune_vulkan::InstanceSettings::default(),
+            None,
+        )?;
+        for physical_device in instance.physical_devices() {
+            println!("{}", physical_device.capabilities());
+        }
+        return Ok(());
+    }
+
+    if let Some(manifest_path) = &config.golden_test {
+        return golden_test::run(manifest_path, config.update_golden);
+    }
+
     let mut platform = platform::sdl2::Sdl2Platform::new(
         APP_NAME,
         if config.fullscreen {
@@ -41,17 +105,52 @@ fn main() -> anyhow::Result<()> {
     info!("window_size: {:?}", window_size);
     let mut editor = Editor::new(&platform.window, [window_size.0, window_size.1], &config)?;
 
+    let mut input_replay = if let Some(path) = &config.record_input {
+        Some(InputReplay::record(path))
+    } else if let Some(path) = &config.play_input {
+        Some(InputReplay::playback(path)?)
+    } else {
+        None
+    };
+
+    const HIDDEN_FRAME_TIME: Duration = Duration::from_millis(100);
+    let unfocused_frame_time = (config.unfocused_fps > 0)
+        .then(|| Duration::from_secs_f32(1.0 / config.unfocused_fps as f32));
+
     let mut last_frame_start = Instant::now();
     let mut frame_count_time: (u32, f32) = (0, 0.0);
     while !platform.should_quit() {
-        platform.process_events(&mut editor)?;
-
         let last_frame_time = last_frame_start.elapsed();
         last_frame_start = Instant::now();
 
-        editor.update(last_frame_time.as_secs_f32());
+        let mut delta_time = last_frame_time.as_secs_f32();
+        if let Some(input_replay) = &mut input_replay {
+            if input_replay.is_finished() {
+                info!("Input playback finished");
+                break;
+            }
+            platform.process_events(&mut input_replay.receiver(&mut editor))?;
+            delta_time = input_replay.begin_frame(&mut editor, delta_time);
+            input_replay.end_frame(delta_time);
+        } else {
+            platform.process_events(&mut editor)?;
+        }
 
-        editor.render().expect("Failed to render a frame");
+        editor.update(delta_time);
+
+        if platform.is_window_visible() {
+            editor.render().expect("Failed to render a frame");
+        } else {
+            //Nothing can be presented, so keep the simulation ticking at a low rate instead of spinning on acquire
+            std::thread::sleep(HIDDEN_FRAME_TIME.saturating_sub(last_frame_start.elapsed()));
+        }
+
+        //The sleep lands in the next frame's delta time, so the simulation still runs at real time speed
+        if let Some(unfocused_frame_time) =
+            unfocused_frame_time.filter(|_| !platform.is_window_focused())
+        {
+            std::thread::sleep(unfocused_frame_time.saturating_sub(last_frame_start.elapsed()));
+        }
 
         frame_count_time.0 += 1;
         frame_count_time.1 += last_frame_time.as_secs_f32();
@@ -63,5 +162,8 @@ fn main() -> anyhow::Result<()> {
     }
 
     info!("Exiting Main Loop!");
+    if let Some(input_replay) = &input_replay {
+        input_replay.save()?;
+    }
     Ok(())
 }
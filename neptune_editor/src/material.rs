@@ -1,5 +1,16 @@
+use crate::scene::scene_renderer::slice_to_bytes_unsafe;
+use anyhow::Context;
 use glam::{Vec2, Vec3, Vec4};
-use neptune_vulkan::{ImageHandle, SamplerHandle};
+use neptune_core::id_pool::IdPool;
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{
+    BufferHandle, BufferUsage, Device, ImageHandle, RasterPipelineHandle, SamplerHandle,
+};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Clone)]
 pub struct MaterialTexture {
@@ -22,4 +33,167 @@ pub struct Material {
     pub normal_texture: Option<MaterialTexture>,
     pub occlusion_texture: Option<(MaterialTexture, f32)>,
     pub emissive_texture: Option<MaterialTexture>,
+
+    pub constants: MaterialConstants,
+    pub pipeline: MaterialPipeline,
+}
+
+/// Shader graph pipeline override, the default mesh pipeline is used when unset
+#[derive(Debug, Default, Clone)]
+pub struct MaterialPipeline(Arc<Mutex<Option<RasterPipelineHandle>>>);
+
+impl MaterialPipeline {
+    pub fn get(&self) -> Option<RasterPipelineHandle> {
+        *self.0.lock().unwrap()
+    }
+
+    /// Shared by every clone of the material
+    pub fn set(&self, pipeline: Option<RasterPipelineHandle>) {
+        *self.0.lock().unwrap() = pipeline;
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, PartialEq)]
+pub struct MaterialConstantsData {
+    pub base_color: Vec4,
+    /// w is unused
+    pub emissive_color: Vec4,
+    /// x: metallic, y: roughness
+    pub metallic_roughness: Vec4,
+}
+
+impl MaterialConstantsData {
+    pub fn new(base_color: Vec4, metallic_roughness_factor: Vec2, emissive_color: Vec3) -> Self {
+        Self {
+            base_color,
+            emissive_color: emissive_color.extend(0.0),
+            metallic_roughness: metallic_roughness_factor.extend(0.0).extend(0.0),
+        }
+    }
+}
+
+struct MaterialPaletteData {
+    constants: Vec<MaterialConstantsData>,
+    slot_pool: IdPool,
+    /// Slots changed since the last upload
+    dirty: Option<Range<usize>>,
+}
+
+impl MaterialPaletteData {
+    fn mark_dirty(&mut self, slot: usize) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(slot)..dirty.end.max(slot + 1),
+            None => slot..(slot + 1),
+        });
+    }
+}
+
+/// Every material's constants in a single storage buffer, shaders index it with the material's slot.
+/// All changes made during a frame are uploaded together, no matter how many materials there are
+#[derive(Clone)]
+pub struct MaterialPalette {
+    buffer: BufferHandle,
+    data: Arc<Mutex<MaterialPaletteData>>,
+}
+
+impl MaterialPalette {
+    pub fn new(device: &mut Device, capacity: usize) -> anyhow::Result<Self> {
+        let constants = vec![MaterialConstantsData::default(); capacity];
+        let buffer = device.create_buffer_init(
+            "Material Palette",
+            BufferUsage::STORAGE | BufferUsage::TRANSFER,
+            MemoryLocation::GpuOnly,
+            unsafe { slice_to_bytes_unsafe(&constants) },
+        )?;
+        Ok(Self {
+            buffer,
+            data: Arc::new(Mutex::new(MaterialPaletteData {
+                constants,
+                slot_pool: IdPool::new(0..capacity),
+                dirty: None,
+            })),
+        })
+    }
+
+    pub fn buffer(&self) -> BufferHandle {
+        self.buffer
+    }
+
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(&self, render_graph_builder: &mut T) {
+        let mut data_mut = self.data.lock().unwrap();
+        let Some(dirty) = data_mut.dirty.take() else {
+            return;
+        };
+
+        let constants = data_mut.constants[dirty.clone()].to_vec();
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: self.buffer,
+                offset: dirty.start * std::mem::size_of::<MaterialConstantsData>(),
+            },
+            std::mem::size_of_val(constants.as_slice()),
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&constants) });
+            }),
+        );
+    }
+}
+
+struct MaterialSlot {
+    index: usize,
+    palette: Arc<Mutex<MaterialPaletteData>>,
+}
+
+impl Drop for MaterialSlot {
+    fn drop(&mut self) {
+        self.palette.lock().unwrap().slot_pool.free(self.index);
+    }
+}
+
+/// A material's factors in the palette, changes are uploaded with the palette's next write.
+/// The slot is freed once every clone is dropped
+#[derive(Clone)]
+pub struct MaterialConstants(Arc<MaterialSlot>);
+
+impl std::fmt::Debug for MaterialConstants {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("MaterialConstants")
+            .field(&self.0.index)
+            .finish()
+    }
+}
+
+impl MaterialConstants {
+    pub fn new(palette: &MaterialPalette, data: MaterialConstantsData) -> anyhow::Result<Self> {
+        let index = palette
+            .data
+            .lock()
+            .unwrap()
+            .slot_pool
+            .get()
+            .context("Material palette is full")?;
+        let constants = Self(Arc::new(MaterialSlot {
+            index,
+            palette: palette.data.clone(),
+        }));
+        constants.set(data);
+        Ok(constants)
+    }
+
+    /// Index into the palette buffer
+    pub fn index(&self) -> u32 {
+        self.0.index as u32
+    }
+
+    pub fn get(&self) -> MaterialConstantsData {
+        self.0.palette.lock().unwrap().constants[self.0.index]
+    }
+
+    /// Shared by every clone of the material
+    pub fn set(&self, data: MaterialConstantsData) {
+        let mut palette_mut = self.0.palette.lock().unwrap();
+        palette_mut.constants[self.0.index] = data;
+        palette_mut.mark_dirty(self.0.index);
+    }
 }
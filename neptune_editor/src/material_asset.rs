@@ -0,0 +1,187 @@
+use crate::asset_database::AssetType;
+use crate::material::{
+    Material, MaterialConstants, MaterialConstantsData, MaterialPalette, MaterialPipeline,
+    MaterialTexture,
+};
+use anyhow::Context;
+use glam::{Vec2, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialRenderState {
+    pub alpha_blending: bool,
+    //TODO: the scene renderer only has a back face culled pipeline for now
+    pub double_sided: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialParameters {
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive_color: [f32; 3],
+}
+
+impl Default for MaterialParameters {
+    fn default() -> Self {
+        Self {
+            base_color: [1.0; 4],
+            metallic: 0.0,
+            roughness: 1.0,
+            emissive_color: [0.0; 3],
+        }
+    }
+}
+
+impl MaterialParameters {
+    pub fn constants_data(&self) -> MaterialConstantsData {
+        MaterialConstantsData::new(
+            Vec4::from(self.base_color),
+            Vec2::new(self.metallic, self.roughness),
+            Vec3::from(self.emissive_color),
+        )
+    }
+}
+
+/// Textures bound to each slot by asset guid, resolved by whoever creates the material.
+/// Materials saved before the asset database use texture names, `AssetDatabase::refresh` upgrades them
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialTextureSlots {
+    pub base_color: Option<String>,
+    pub metallic_roughness: Option<String>,
+    pub normal: Option<String>,
+    pub occlusion: Option<String>,
+    pub emissive: Option<String>,
+}
+
+impl MaterialTextureSlots {
+    fn slots_mut(&mut self) -> [&mut Option<String>; 5] {
+        [
+            &mut self.base_color,
+            &mut self.metallic_roughness,
+            &mut self.normal,
+            &mut self.occlusion,
+            &mut self.emissive,
+        ]
+    }
+}
+
+/// On disk description of a material, stored as toml
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaterialAsset {
+    pub name: String,
+    /// Shader the material is drawn with, either "mesh" or the guid of a graph in `resource/shader_graphs`
+    #[serde(default = "MaterialAsset::default_shader")]
+    pub shader: String,
+    #[serde(default)]
+    pub render_state: MaterialRenderState,
+    #[serde(default)]
+    pub parameters: MaterialParameters,
+    #[serde(default)]
+    pub textures: MaterialTextureSlots,
+}
+
+impl MaterialAsset {
+    pub const DEFAULT_SHADER: &'static str = "mesh";
+
+    fn default_shader() -> String {
+        Self::DEFAULT_SHADER.to_string()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file_content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read material {}", path.display()))?;
+        toml::from_str(&file_content)
+            .with_context(|| format!("Failed to parse material {}", path.display()))
+    }
+
+    pub fn shader_graph(&self) -> Option<&str> {
+        (self.shader != Self::DEFAULT_SHADER).then_some(self.shader.as_str())
+    }
+
+    /// Every texture slot and the shader graph, along with the type of asset each one references
+    pub fn references_mut(&mut self) -> Vec<(&mut String, AssetType)> {
+        let mut references: Vec<_> = self
+            .textures
+            .slots_mut()
+            .into_iter()
+            .flatten()
+            .map(|slot| (slot, AssetType::Texture))
+            .collect();
+        if self.shader != Self::DEFAULT_SHADER {
+            references.push((&mut self.shader, AssetType::ShaderGraph));
+        }
+        references
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write material {}", path.display()))
+    }
+
+    /// Texture slot names can't be recovered from a loaded material so they are left empty
+    pub fn from_material(material: &Material) -> Self {
+        Self {
+            name: material.name.clone(),
+            shader: Self::default_shader(),
+            render_state: MaterialRenderState {
+                alpha_blending: material.alpha_blending,
+                double_sided: false,
+            },
+            parameters: MaterialParameters {
+                base_color: material.base_color.to_array(),
+                metallic: material.metallic_roughness_factor.x,
+                roughness: material.metallic_roughness_factor.y,
+                emissive_color: material.emissive_color.to_array(),
+            },
+            textures: MaterialTextureSlots::default(),
+        }
+    }
+
+    pub fn create_material(
+        &self,
+        material_palette: &MaterialPalette,
+        mut resolve_texture: impl FnMut(&str) -> Option<MaterialTexture>,
+    ) -> anyhow::Result<Material> {
+        let mut resolve_slot = |slot: &Option<String>| {
+            slot.as_ref().and_then(|texture_name| {
+                let texture = resolve_texture(texture_name);
+                if texture.is_none() {
+                    warn!(
+                        "Material {} references missing texture {}",
+                        self.name, texture_name
+                    );
+                }
+                texture
+            })
+        };
+
+        //TODO: occlusion strength should be a parameter
+        Ok(Material {
+            name: self.name.clone(),
+            alpha_blending: self.render_state.alpha_blending,
+            base_color: Vec4::from(self.parameters.base_color),
+            metallic_roughness_factor: Vec2::new(
+                self.parameters.metallic,
+                self.parameters.roughness,
+            ),
+            emissive_color: Vec3::from(self.parameters.emissive_color),
+            base_color_texture: resolve_slot(&self.textures.base_color),
+            metallic_roughness_texture: resolve_slot(&self.textures.metallic_roughness),
+            normal_texture: resolve_slot(&self.textures.normal),
+            occlusion_texture: resolve_slot(&self.textures.occlusion).map(|texture| (texture, 1.0)),
+            emissive_texture: resolve_slot(&self.textures.emissive),
+            constants: MaterialConstants::new(material_palette, self.parameters.constants_data())?,
+            pipeline: MaterialPipeline::default(),
+        })
+    }
+}
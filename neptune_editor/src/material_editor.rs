@@ -0,0 +1,156 @@
+use crate::asset_database::AssetDatabase;
+use crate::material::{Material, MaterialConstants, MaterialPipeline};
+use crate::material_asset::MaterialAsset;
+use neptune_vulkan::RasterPipelineHandle;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MaterialParameter {
+    BaseColorR,
+    BaseColorG,
+    BaseColorB,
+    BaseColorA,
+    Metallic,
+    Roughness,
+    EmissiveR,
+    EmissiveG,
+    EmissiveB,
+}
+
+impl MaterialParameter {
+    pub const ALL: [MaterialParameter; 9] = [
+        MaterialParameter::BaseColorR,
+        MaterialParameter::BaseColorG,
+        MaterialParameter::BaseColorB,
+        MaterialParameter::BaseColorA,
+        MaterialParameter::Metallic,
+        MaterialParameter::Roughness,
+        MaterialParameter::EmissiveR,
+        MaterialParameter::EmissiveG,
+        MaterialParameter::EmissiveB,
+    ];
+
+    fn value_mut<'a>(&self, asset: &'a mut MaterialAsset) -> &'a mut f32 {
+        let parameters = &mut asset.parameters;
+        match self {
+            MaterialParameter::BaseColorR => &mut parameters.base_color[0],
+            MaterialParameter::BaseColorG => &mut parameters.base_color[1],
+            MaterialParameter::BaseColorB => &mut parameters.base_color[2],
+            MaterialParameter::BaseColorA => &mut parameters.base_color[3],
+            MaterialParameter::Metallic => &mut parameters.metallic,
+            MaterialParameter::Roughness => &mut parameters.roughness,
+            MaterialParameter::EmissiveR => &mut parameters.emissive_color[0],
+            MaterialParameter::EmissiveG => &mut parameters.emissive_color[1],
+            MaterialParameter::EmissiveB => &mut parameters.emissive_color[2],
+        }
+    }
+
+    fn value(&self, asset: &MaterialAsset) -> f32 {
+        let parameters = &asset.parameters;
+        match self {
+            MaterialParameter::BaseColorR => parameters.base_color[0],
+            MaterialParameter::BaseColorG => parameters.base_color[1],
+            MaterialParameter::BaseColorB => parameters.base_color[2],
+            MaterialParameter::BaseColorA => parameters.base_color[3],
+            MaterialParameter::Metallic => parameters.metallic,
+            MaterialParameter::Roughness => parameters.roughness,
+            MaterialParameter::EmissiveR => parameters.emissive_color[0],
+            MaterialParameter::EmissiveG => parameters.emissive_color[1],
+            MaterialParameter::EmissiveB => parameters.emissive_color[2],
+        }
+    }
+
+    /// Emissive is hdr, everything else is a 0-1 factor
+    fn range(&self) -> (f32, f32) {
+        match self {
+            MaterialParameter::EmissiveR
+            | MaterialParameter::EmissiveG
+            | MaterialParameter::EmissiveB => (0.0, f32::MAX),
+            _ => (0.0, 1.0),
+        }
+    }
+}
+
+/// Live material tweaking, every change is pushed straight to the material's gpu constants
+pub struct MaterialEditorPanel {
+    pub asset: MaterialAsset,
+    pub path: PathBuf,
+    constants: MaterialConstants,
+    pipeline: MaterialPipeline,
+    selected_parameter: usize,
+}
+
+impl MaterialEditorPanel {
+    pub const ADJUST_STEP: f32 = 0.05;
+
+    /// Opens the asset at `path` if there is one, otherwise starts from the material's current values
+    pub fn open(material: &Material, path: &Path) -> anyhow::Result<Self> {
+        let asset = if path.exists() {
+            MaterialAsset::load(path)?
+        } else {
+            MaterialAsset::from_material(material)
+        };
+        material.constants.set(asset.parameters.constants_data());
+
+        let panel = Self {
+            asset,
+            path: path.to_path_buf(),
+            constants: material.constants.clone(),
+            pipeline: material.pipeline.clone(),
+            selected_parameter: 0,
+        };
+        panel.log_state();
+        Ok(panel)
+    }
+
+    pub fn selected_parameter(&self) -> MaterialParameter {
+        MaterialParameter::ALL[self.selected_parameter]
+    }
+
+    pub fn select_next_parameter(&mut self) {
+        self.selected_parameter = (self.selected_parameter + 1) % MaterialParameter::ALL.len();
+        self.log_state();
+    }
+
+    pub fn select_previous_parameter(&mut self) {
+        self.selected_parameter = (self.selected_parameter + MaterialParameter::ALL.len() - 1)
+            % MaterialParameter::ALL.len();
+        self.log_state();
+    }
+
+    pub fn adjust_selected(&mut self, delta: f32) {
+        let parameter = self.selected_parameter();
+        let (min, max) = parameter.range();
+        let value = parameter.value_mut(&mut self.asset);
+        *value = (*value + delta).clamp(min, max);
+        self.constants.set(self.asset.parameters.constants_data());
+        self.log_state();
+    }
+
+    /// Swaps the pipeline the material is drawn with, None goes back to the default mesh pipeline
+    pub fn set_pipeline(&self, pipeline: Option<RasterPipelineHandle>) {
+        self.pipeline.set(pipeline);
+    }
+
+    /// Textures and shader graphs are saved as guids, even if the material was loaded with names
+    pub fn save(&mut self, asset_database: &AssetDatabase) -> anyhow::Result<()> {
+        asset_database.reference_by_guid(&mut self.asset);
+        self.asset.save(&self.path)?;
+        info!(
+            "Saved material {} to {}",
+            self.asset.name,
+            self.path.display()
+        );
+        Ok(())
+    }
+
+    //TODO: draw this in an actual ui once the editor has one
+    fn log_state(&self) {
+        let parameter = self.selected_parameter();
+        let value = parameter.value(&self.asset);
+        info!(
+            "Material {}: {:?} = {:.2}",
+            self.asset.name, parameter, value
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use crate::gltf_loader::{create_primitive, GltfNode, PrimitiveData};
+use crate::lightmap::LightmapLayout;
+use crate::mesh::{Mesh, VertexAttributes};
+use glam::{Mat3, Mat4};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// How the static geometry of a loaded model is merged
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum StaticBatching {
+    #[default]
+    Off,
+    Batched,
+    /// Every batch is also unwrapped for a lightmap, baked once it's in the world
+    Lightmapped,
+}
+
+/// Static geometry moved into world space and merged by material, so a level built from many small meshes
+/// only needs one draw per material
+pub(crate) struct StaticBatcher {
+    batches: BTreeMap<usize, Vec<PrimitiveData>>,
+    primitive_count: usize,
+    lightmaps: bool,
+}
+
+impl StaticBatcher {
+    /// Batches are split at this size so they can still be culled on their own
+    const MAX_BATCH_VERTICES: usize = 1 << 16;
+
+    pub fn new(lightmaps: bool) -> Self {
+        Self {
+            batches: BTreeMap::new(),
+            primitive_count: 0,
+            lightmaps,
+        }
+    }
+
+    pub fn add(&mut self, transform: &Mat4, data: &PrimitiveData, material: usize) {
+        let batches = self.batches.entry(material).or_default();
+        let needs_new_batch = batches
+            .last()
+            .map(|batch| batch.positions.len() + data.positions.len() > Self::MAX_BATCH_VERTICES)
+            .unwrap_or(true);
+        if needs_new_batch {
+            batches.push(PrimitiveData {
+                bounding_box: data.bounding_box.transform(transform),
+                positions: Vec::new(),
+                attributes: Vec::new(),
+                skinning: None,
+                indices: Some(Vec::new()),
+            });
+        }
+        let batch = batches.last_mut().unwrap();
+
+        //Mirrored transforms turn the triangles inside out, so the winding and tangent handedness flip back
+        let mirrored = transform.determinant() < 0.0;
+        let tangent_matrix = Mat3::from_mat4(*transform);
+        let normal_matrix = tangent_matrix.inverse().transpose();
+        let handedness = if mirrored { -1.0 } else { 1.0 };
+
+        let first_vertex = batch.positions.len() as u32;
+        batch.positions.extend(
+            data.positions
+                .iter()
+                .map(|position| transform.transform_point3(*position)),
+        );
+        batch
+            .attributes
+            .extend(data.attributes.iter().map(|attributes| {
+                VertexAttributes {
+                    normal: (normal_matrix * attributes.normal).normalize_or_zero(),
+                    tangent: (tangent_matrix * attributes.tangent.truncate())
+                        .normalize_or_zero()
+                        .extend(attributes.tangent.w * handedness),
+                    ..*attributes
+                }
+            }));
+
+        let indices = batch.indices.get_or_insert_with(Vec::new);
+        let mut push_triangle = |triangle: [u32; 3]| {
+            let [a, b, c] = triangle.map(|index| index + first_vertex);
+            if mirrored {
+                indices.extend_from_slice(&[a, c, b]);
+            } else {
+                indices.extend_from_slice(&[a, b, c]);
+            }
+        };
+        match &data.indices {
+            Some(source_indices) => source_indices
+                .chunks_exact(3)
+                .for_each(|triangle| push_triangle([triangle[0], triangle[1], triangle[2]])),
+            None => (0..data.positions.len() as u32 / 3)
+                .for_each(|triangle| push_triangle([0, 1, 2].map(|i| triangle * 3 + i))),
+        }
+
+        batch.bounding_box = batch
+            .bounding_box
+            .union(&data.bounding_box.transform(transform));
+        self.primitive_count += 1;
+    }
+
+    /// Primitives added so far
+    pub fn primitive_count(&self) -> usize {
+        self.primitive_count
+    }
+
+    /// Uploads every batch as a mesh of its own, each placed at the origin by a new node
+    pub fn build(
+        self,
+        device: &mut neptune_vulkan::Device,
+        meshes: &mut Vec<Mesh>,
+        mesh_nodes: &mut Vec<GltfNode>,
+    ) -> anyhow::Result<usize> {
+        let mut batch_count = 0;
+        for (material, batches) in self.batches {
+            for (index, mut data) in batches.into_iter().enumerate() {
+                let lightmap = self.lightmaps.then(|| LightmapLayout::unwrap(&mut data));
+                mesh_nodes.push(GltfNode {
+                    transform: Mat4::IDENTITY,
+                    mesh_index: meshes.len(),
+                    primitive_materials: vec![material],
+                    lightmap,
+                    skin: None,
+                });
+                meshes.push(Mesh::new(
+                    format!("Static Batch {} {}", material, index),
+                    vec![Arc::new(create_primitive(device, &data)?)],
+                ));
+                batch_count += 1;
+            }
+        }
+        Ok(batch_count)
+    }
+}
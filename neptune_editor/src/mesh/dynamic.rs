@@ -0,0 +1,227 @@
+use crate::mesh::{
+    BoundingBox, BoundingSphere, IndexBuffer, Primitive, PrimitiveGeometry, VertexAttributes,
+};
+use crate::scene::scene_renderer::slice_to_bytes_unsafe;
+use glam::Vec3;
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{BufferHandle, BufferUsage, Device};
+use std::sync::Arc;
+
+struct DynamicMeshBuffers {
+    position_buffer: BufferHandle,
+    attributes_buffer: BufferHandle,
+    index_buffer: BufferHandle,
+    vertex_capacity: usize,
+    index_capacity: usize,
+}
+
+impl DynamicMeshBuffers {
+    fn new(
+        device: &mut Device,
+        name: &str,
+        vertex_capacity: usize,
+        index_capacity: usize,
+    ) -> anyhow::Result<Self> {
+        //Zero sized buffers aren't allowed
+        let vertex_capacity = vertex_capacity.max(1);
+        let index_capacity = index_capacity.max(1);
+        Ok(Self {
+            position_buffer: device.create_buffer(
+                &format!("{} Positions", name),
+                vertex_capacity * std::mem::size_of::<Vec3>(),
+                BufferUsage::VERTEX | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+            )?,
+            attributes_buffer: device.create_buffer(
+                &format!("{} Attributes", name),
+                vertex_capacity * std::mem::size_of::<VertexAttributes>(),
+                BufferUsage::VERTEX | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+            )?,
+            index_buffer: device.create_buffer(
+                &format!("{} Indices", name),
+                index_capacity * std::mem::size_of::<u32>(),
+                BufferUsage::INDEX | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+            )?,
+            vertex_capacity,
+            index_capacity,
+        })
+    }
+
+    fn destroy(&self, device: &mut Device) {
+        device.destroy_buffer(self.position_buffer);
+        device.destroy_buffer(self.attributes_buffer);
+        device.destroy_buffer(self.index_buffer);
+    }
+}
+
+struct DynamicMeshData {
+    positions: Arc<Vec<Vec3>>,
+    attributes: Arc<Vec<VertexAttributes>>,
+    indices: Arc<Vec<u32>>,
+}
+
+/// Mesh whose geometry can be replaced every frame, for trails, ropes, soft bodies and editor drawn shapes.
+/// Uploads go through the render graph's buffer writes into the back buffer set while the front set is still being drawn
+pub struct DynamicMesh {
+    name: String,
+    buffers: [DynamicMeshBuffers; 2],
+    front: usize,
+    pending: Option<DynamicMeshData>,
+    primitive: Arc<Primitive>,
+}
+
+impl DynamicMesh {
+    pub fn new(
+        device: &mut Device,
+        name: &str,
+        vertex_capacity: usize,
+        index_capacity: usize,
+    ) -> anyhow::Result<Self> {
+        let buffers = [
+            DynamicMeshBuffers::new(device, name, vertex_capacity, index_capacity)?,
+            DynamicMeshBuffers::new(device, name, vertex_capacity, index_capacity)?,
+        ];
+        let primitive = Arc::new(Self::create_primitive(
+            &buffers[0],
+            BoundingBox::default(),
+            PrimitiveGeometry::default(),
+        ));
+        Ok(Self {
+            name: name.to_string(),
+            buffers,
+            front: 0,
+            pending: None,
+            primitive,
+        })
+    }
+
+    pub fn destroy(self, device: &mut Device) {
+        for buffers in self.buffers.iter() {
+            buffers.destroy(device);
+        }
+    }
+
+    /// Primitive for the last uploaded geometry, a new one is created every time the mesh is uploaded
+    pub fn primitive(&self) -> Arc<Primitive> {
+        self.primitive.clone()
+    }
+
+    /// Replaces the geometry, it's uploaded the next time `write_render_passes` is called.
+    /// The back buffers are reallocated if the new geometry doesn't fit
+    pub fn update(
+        &mut self,
+        device: &mut Device,
+        positions: Vec<Vec3>,
+        attributes: Vec<VertexAttributes>,
+        indices: Vec<u32>,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            positions.len() == attributes.len(),
+            "DynamicMesh {} has {} positions but {} attributes",
+            self.name,
+            positions.len(),
+            attributes.len()
+        );
+
+        let back = &mut self.buffers[1 - self.front];
+        if positions.len() > back.vertex_capacity || indices.len() > back.index_capacity {
+            let new_buffers = DynamicMeshBuffers::new(
+                device,
+                &self.name,
+                positions
+                    .len()
+                    .max(back.vertex_capacity)
+                    .next_power_of_two(),
+                indices.len().max(back.index_capacity).next_power_of_two(),
+            )?;
+            //Destruction is deferred until the frames using the old buffers are done
+            std::mem::replace(back, new_buffers).destroy(device);
+        }
+
+        self.pending = Some(DynamicMeshData {
+            positions: Arc::new(positions),
+            attributes: Arc::new(attributes),
+            indices: Arc::new(indices),
+        });
+        Ok(())
+    }
+
+    /// Uploads any pending geometry into the back buffers and swaps them to the front
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        render_graph_builder: &mut T,
+    ) {
+        let Some(data) = self.pending.take() else {
+            return;
+        };
+
+        let back = 1 - self.front;
+        let buffers = &self.buffers[back];
+        write_buffer(
+            render_graph_builder,
+            buffers.position_buffer,
+            &data.positions,
+        );
+        write_buffer(
+            render_graph_builder,
+            buffers.attributes_buffer,
+            &data.attributes,
+        );
+        write_buffer(render_graph_builder, buffers.index_buffer, &data.indices);
+
+        self.primitive = Arc::new(Self::create_primitive(
+            buffers,
+            BoundingBox::from_points(&data.positions),
+            PrimitiveGeometry {
+                positions: data.positions.to_vec(),
+                indices: data.indices.to_vec(),
+            },
+        ));
+        self.front = back;
+    }
+
+    fn create_primitive(
+        buffers: &DynamicMeshBuffers,
+        bounding_box: BoundingBox,
+        geometry: PrimitiveGeometry,
+    ) -> Primitive {
+        Primitive {
+            bounding_box,
+            bounding_sphere: BoundingSphere::from_points(&geometry.positions),
+            vertex_count: geometry.positions.len(),
+            vertex_offset: 0,
+            position_buffer: buffers.position_buffer,
+            attributes_buffer: buffers.attributes_buffer,
+            skinning_buffer: None,
+            index_buffer: Some(IndexBuffer {
+                buffer: buffers.index_buffer,
+                count: geometry.indices.len() as u32,
+            }),
+            geometry: Arc::new(geometry),
+        }
+    }
+}
+
+fn write_buffer<T: RenderGraphBuilderTrait, D: Send + Sync + 'static>(
+    render_graph_builder: &mut T,
+    buffer: BufferHandle,
+    data: &Arc<Vec<D>>,
+) {
+    if data.is_empty() {
+        return;
+    }
+
+    let data = data.clone();
+    render_graph_builder.add_buffer_write(
+        BufferOffset { buffer, offset: 0 },
+        std::mem::size_of_val(data.as_slice()),
+        BufferWriteCallback::new(move |slice| {
+            slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&data) });
+        }),
+    );
+}
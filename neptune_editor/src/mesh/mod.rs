@@ -0,0 +1,352 @@
+pub mod batching;
+pub mod dynamic;
+pub mod primitive_cache;
+pub mod procedural;
+
+use glam::Vec3;
+use memoffset::offset_of;
+use neptune_vulkan::vk;
+use std::sync::Arc;
+
+#[repr(transparent)]
+pub struct VertexPosition(glam::Vec3);
+
+impl VertexPosition {
+    #[allow(unused)]
+    pub const VERTEX_BUFFER_LAYOUT: neptune_vulkan::VertexBufferLayout<'static> =
+        neptune_vulkan::VertexBufferLayout {
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+            attributes: &[neptune_vulkan::VertexAttribute {
+                shader_location: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: offset_of!(Self, 0) as u32,
+            }],
+        };
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct VertexAttributes {
+    pub normal: glam::Vec3,
+    pub tangent: glam::Vec4,
+    pub tex_coords: glam::Vec4,
+    pub color: glam::Vec4,
+}
+
+impl VertexAttributes {
+    #[allow(unused)]
+    pub const VERTEX_BUFFER_LAYOUT: neptune_vulkan::VertexBufferLayout<'static> =
+        neptune_vulkan::VertexBufferLayout {
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+            attributes: &[
+                neptune_vulkan::VertexAttribute {
+                    shader_location: 1,
+                    format: vk::Format::R32G32B32_SFLOAT,
+                    offset: offset_of!(Self, normal) as u32,
+                },
+                neptune_vulkan::VertexAttribute {
+                    shader_location: 2,
+                    format: vk::Format::R32G32B32A32_SFLOAT,
+                    offset: offset_of!(Self, tangent) as u32,
+                },
+                neptune_vulkan::VertexAttribute {
+                    shader_location: 3,
+                    format: vk::Format::R32G32B32A32_SFLOAT,
+                    offset: offset_of!(Self, tex_coords) as u32,
+                },
+                neptune_vulkan::VertexAttribute {
+                    shader_location: 4,
+                    format: vk::Format::R32G32B32A32_SFLOAT,
+                    offset: offset_of!(Self, color) as u32,
+                },
+            ],
+        };
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct VertexSkinningAttributes {
+    pub joint: glam::UVec4,
+    pub weight: glam::Vec4,
+}
+
+impl VertexSkinningAttributes {
+    #[allow(unused)]
+    pub const VERTEX_BUFFER_LAYOUT: neptune_vulkan::VertexBufferLayout<'static> =
+        neptune_vulkan::VertexBufferLayout {
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+            attributes: &[
+                neptune_vulkan::VertexAttribute {
+                    shader_location: 5,
+                    format: vk::Format::R32G32B32_UINT,
+                    offset: offset_of!(Self, joint) as u32,
+                },
+                neptune_vulkan::VertexAttribute {
+                    shader_location: 6,
+                    format: vk::Format::R32G32B32A32_SFLOAT,
+                    offset: offset_of!(Self, weight) as u32,
+                },
+            ],
+        };
+}
+
+#[derive(Default, Clone)]
+pub struct Mesh {
+    pub name: String,
+    pub primitives: Vec<Arc<Primitive>>,
+    /// Covers every primitive, computed in `new`
+    pub bounding_box: BoundingBox,
+    pub bounding_sphere: BoundingSphere,
+}
+
+impl Mesh {
+    pub fn new(name: String, primitives: Vec<Arc<Primitive>>) -> Self {
+        let bounding_box =
+            BoundingBox::union_all(primitives.iter().map(|primitive| primitive.bounding_box))
+                .unwrap_or_default();
+        let bounding_sphere =
+            BoundingSphere::union_all(primitives.iter().map(|primitive| primitive.bounding_sphere))
+                .unwrap_or_default();
+        Self {
+            name,
+            primitives,
+            bounding_box,
+            bounding_sphere,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct BoundingBox {
+    pub min: glam::Vec3,
+    pub max: glam::Vec3,
+}
+
+impl BoundingBox {
+    /// Smallest box containing every point, empty lists give a zero sized box at the origin
+    pub fn from_points(points: &[Vec3]) -> BoundingBox {
+        match points.split_first() {
+            Some((first, rest)) => rest.iter().fold(
+                BoundingBox {
+                    min: *first,
+                    max: *first,
+                },
+                |bounding_box, &point| BoundingBox {
+                    min: bounding_box.min.min(point),
+                    max: bounding_box.max.max(point),
+                },
+            ),
+            None => BoundingBox::default(),
+        }
+    }
+
+    pub fn center(&self) -> glam::Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extent(&self) -> glam::Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn union(&self, other: &BoundingBox) -> BoundingBox {
+        BoundingBox {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// Box containing every box, None if there are none
+    pub fn union_all(boxes: impl IntoIterator<Item = BoundingBox>) -> Option<BoundingBox> {
+        boxes
+            .into_iter()
+            .reduce(|bounds, other| bounds.union(&other))
+    }
+
+    /// Returns the box that encloses this box after being transformed by the matrix
+    pub fn transform(&self, matrix: &glam::Mat4) -> BoundingBox {
+        let center = matrix.transform_point3(self.center());
+        let half_extent = self.half_extent();
+        let world_half_extent = matrix.x_axis.truncate().abs() * half_extent.x
+            + matrix.y_axis.truncate().abs() * half_extent.y
+            + matrix.z_axis.truncate().abs() * half_extent.z;
+        BoundingBox {
+            min: center - world_half_extent,
+            max: center + world_half_extent,
+        }
+    }
+
+    /// Squared distance from the point to the closest point in the box, zero if inside
+    pub fn distance_squared(&self, point: glam::Vec3) -> f32 {
+        point.clamp(self.min, self.max).distance_squared(point)
+    }
+
+    /// Returns the distance along the ray where it enters the box, if it hits at all
+    pub fn ray_intersect(&self, origin: glam::Vec3, inverse_direction: glam::Vec3) -> Option<f32> {
+        let t0 = (self.min - origin) * inverse_direction;
+        let t1 = (self.max - origin) * inverse_direction;
+        let t_enter = t0.min(t1).max_element().max(0.0);
+        let t_exit = t0.max(t1).min_element();
+        (t_enter <= t_exit).then_some(t_enter)
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct BoundingSphere {
+    pub center: glam::Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Centered on the points' bounding box, not the smallest sphere but close enough for culling
+    pub fn from_points(points: &[Vec3]) -> BoundingSphere {
+        let center = BoundingBox::from_points(points).center();
+        let radius = points
+            .iter()
+            .map(|point| point.distance_squared(center))
+            .fold(0.0, f32::max)
+            .sqrt();
+        BoundingSphere { center, radius }
+    }
+
+    /// Sphere through the box's corners, looser than `from_points` when the vertices are available
+    pub fn from_box(bounds: &BoundingBox) -> BoundingSphere {
+        BoundingSphere {
+            center: bounds.center(),
+            radius: bounds.half_extent().length(),
+        }
+    }
+
+    pub fn union(&self, other: &BoundingSphere) -> BoundingSphere {
+        let offset = other.center - self.center;
+        let distance = offset.length();
+        if distance + other.radius <= self.radius {
+            return *self;
+        }
+        if distance + self.radius <= other.radius {
+            return *other;
+        }
+        let radius = (distance + self.radius + other.radius) * 0.5;
+        BoundingSphere {
+            center: self.center + offset * ((radius - self.radius) / distance),
+            radius,
+        }
+    }
+
+    /// Sphere containing every sphere, None if there are none
+    pub fn union_all(spheres: impl IntoIterator<Item = BoundingSphere>) -> Option<BoundingSphere> {
+        spheres
+            .into_iter()
+            .reduce(|sphere, other| sphere.union(&other))
+    }
+
+    /// Returns the sphere that encloses this sphere after being transformed by the matrix, non-uniform scales use the largest axis
+    pub fn transform(&self, matrix: &glam::Mat4) -> BoundingSphere {
+        let scale = matrix
+            .x_axis
+            .truncate()
+            .length_squared()
+            .max(matrix.y_axis.truncate().length_squared())
+            .max(matrix.z_axis.truncate().length_squared())
+            .sqrt();
+        BoundingSphere {
+            center: matrix.transform_point3(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct IndexBuffer {
+    pub buffer: neptune_vulkan::BufferHandle,
+    pub count: u32,
+}
+
+/// Cpu copy of a primitive's triangles, kept around for tools that need the geometry (path tracing, baking)
+#[derive(Debug, Default, Clone)]
+pub struct PrimitiveGeometry {
+    pub positions: Vec<glam::Vec3>,
+    pub indices: Vec<u32>,
+}
+
+#[derive(Clone)]
+pub struct Primitive {
+    pub bounding_box: BoundingBox,
+    pub bounding_sphere: BoundingSphere,
+    pub geometry: Arc<PrimitiveGeometry>,
+
+    pub vertex_count: usize,
+    /// First vertex in the vertex buffers, skinned primitives share theirs with other skins
+    pub vertex_offset: usize,
+    pub position_buffer: neptune_vulkan::BufferHandle,
+    pub attributes_buffer: neptune_vulkan::BufferHandle,
+    pub skinning_buffer: Option<neptune_vulkan::BufferHandle>,
+    pub index_buffer: Option<IndexBuffer>,
+}
+
+impl Primitive {
+    pub fn position_buffer_offset(&self) -> neptune_vulkan::render_graph_builder::BufferOffset {
+        neptune_vulkan::render_graph_builder::BufferOffset {
+            buffer: self.position_buffer,
+            offset: self.vertex_offset * std::mem::size_of::<Vec3>(),
+        }
+    }
+
+    pub fn attributes_buffer_offset(&self) -> neptune_vulkan::render_graph_builder::BufferOffset {
+        neptune_vulkan::render_graph_builder::BufferOffset {
+            buffer: self.attributes_buffer,
+            offset: self.vertex_offset * std::mem::size_of::<VertexAttributes>(),
+        }
+    }
+}
+
+/// Generates tangents from the uv0 set, normals must already be filled in
+pub fn generate_tangents(positions: &[Vec3], attributes: &mut [VertexAttributes], indices: &[u32]) {
+    let mut tangents = vec![Vec3::ZERO; positions.len()];
+    let mut bitangents = vec![Vec3::ZERO; positions.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let [i0, i1, i2] = [
+            triangle[0] as usize,
+            triangle[1] as usize,
+            triangle[2] as usize,
+        ];
+        let edge1 = positions[i1] - positions[i0];
+        let edge2 = positions[i2] - positions[i0];
+
+        let uv0 = attributes[i0].tex_coords.truncate().truncate();
+        let delta_uv1 = attributes[i1].tex_coords.truncate().truncate() - uv0;
+        let delta_uv2 = attributes[i2].tex_coords.truncate().truncate() - uv0;
+        let determinant = delta_uv1.x * delta_uv2.y - delta_uv2.x * delta_uv1.y;
+        if determinant.abs() <= f32::EPSILON {
+            continue;
+        }
+
+        let r = 1.0 / determinant;
+        let tangent = (edge1 * delta_uv2.y - edge2 * delta_uv1.y) * r;
+        let bitangent = (edge2 * delta_uv1.x - edge1 * delta_uv2.x) * r;
+        for i in [i0, i1, i2] {
+            tangents[i] += tangent;
+            bitangents[i] += bitangent;
+        }
+    }
+
+    for (i, attribute) in attributes.iter_mut().enumerate() {
+        //Gram-Schmidt against the normal, falls back to any perpendicular vector without uvs
+        let normal = attribute.normal;
+        let tangent = (tangents[i] - normal * normal.dot(tangents[i])).normalize_or_zero();
+        let tangent = if tangent == Vec3::ZERO {
+            normal.any_orthonormal_vector()
+        } else {
+            tangent
+        };
+        let handedness = if normal.cross(tangent).dot(bitangents[i]) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        attribute.tangent = tangent.extend(handedness);
+    }
+}
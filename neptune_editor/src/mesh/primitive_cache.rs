@@ -0,0 +1,218 @@
+use crate::gltf_loader::{
+    create_index_buffer, create_vertex_buffer, primitive_from_buffers, PrimitiveData,
+};
+use crate::mesh::{IndexBuffer, Primitive};
+use neptune_vulkan::{BufferHandle, Device};
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::sync::{Arc, Weak};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum BufferKind {
+    Positions,
+    Attributes,
+    Skinning,
+    Indices,
+}
+
+/// Content hash plus length, a hash collision would also need the sizes to match
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct ContentKey {
+    kind: BufferKind,
+    hash: u64,
+    size: usize,
+}
+
+impl ContentKey {
+    fn new<T>(kind: BufferKind, data: &[T]) -> Self {
+        let bytes = as_bytes(data);
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        hasher.write(bytes);
+        Self {
+            kind,
+            hash: hasher.finish(),
+            size: bytes.len(),
+        }
+    }
+}
+
+struct SharedBuffer {
+    handle: BufferHandle,
+    ref_count: usize,
+}
+
+/// Buffers each cached primitive holds a reference to, released once the primitive is dropped
+struct CachedPrimitive {
+    primitive: Weak<Primitive>,
+    buffers: Vec<ContentKey>,
+}
+
+/// Totals since the cache was created
+#[derive(Debug, Default, Copy, Clone)]
+pub struct PrimitiveCacheStats {
+    /// Primitives handed out that were already loaded, usually the same model in another file
+    pub shared_primitives: usize,
+    /// Buffers reused by a different primitive, like index buffers shared by meshes with the same topology
+    pub shared_buffers: usize,
+    pub saved_bytes: usize,
+    pub freed_buffers: usize,
+}
+
+/// Loaded primitives and their buffers by content, so identical meshes in different files share gpu memory.
+/// Buffers are reference counted by the primitives using them and freed in `collect` once the last one is dropped
+#[derive(Default)]
+pub struct PrimitiveCache {
+    buffers: HashMap<ContentKey, SharedBuffer>,
+    primitives: HashMap<[ContentKey; 4], Weak<Primitive>>,
+    cached: Vec<CachedPrimitive>,
+    stats: PrimitiveCacheStats,
+}
+
+impl PrimitiveCache {
+    pub fn get_or_create(
+        &mut self,
+        device: &mut Device,
+        data: &PrimitiveData,
+    ) -> anyhow::Result<Arc<Primitive>> {
+        let position_key = ContentKey::new(BufferKind::Positions, &data.positions);
+        let attributes_key = ContentKey::new(BufferKind::Attributes, &data.attributes);
+        let skinning_key = data
+            .skinning
+            .as_deref()
+            .map(|skinning| ContentKey::new(BufferKind::Skinning, skinning));
+        let index_key = data
+            .indices
+            .as_deref()
+            .map(|indices| ContentKey::new(BufferKind::Indices, indices));
+
+        //Missing buffers still need a key so skinned and unskinned copies of a mesh don't match
+        let empty_key = |kind| ContentKey {
+            kind,
+            hash: 0,
+            size: 0,
+        };
+        let primitive_key = [
+            position_key,
+            attributes_key,
+            skinning_key.unwrap_or(empty_key(BufferKind::Skinning)),
+            index_key.unwrap_or(empty_key(BufferKind::Indices)),
+        ];
+        if let Some(primitive) = self.primitives.get(&primitive_key).and_then(Weak::upgrade) {
+            self.stats.shared_primitives += 1;
+            self.stats.saved_bytes += data.byte_size();
+            return Ok(primitive);
+        }
+
+        let mut buffer_keys = Vec::with_capacity(4);
+        let position_buffer = self.get_or_create_buffer(position_key, &mut buffer_keys, || {
+            create_vertex_buffer(device, &data.positions)
+        })?;
+        let attributes_buffer =
+            self.get_or_create_buffer(attributes_key, &mut buffer_keys, || {
+                create_vertex_buffer(device, &data.attributes)
+            })?;
+        let skinning_buffer = match (skinning_key, &data.skinning) {
+            (Some(key), Some(skinning)) => {
+                Some(self.get_or_create_buffer(key, &mut buffer_keys, || {
+                    create_vertex_buffer(device, skinning)
+                })?)
+            }
+            _ => None,
+        };
+        let index_buffer = match (index_key, &data.indices) {
+            (Some(key), Some(indices)) => Some(IndexBuffer {
+                count: indices.len() as u32,
+                buffer: self.get_or_create_buffer(key, &mut buffer_keys, || {
+                    create_index_buffer(device, indices)
+                })?,
+            }),
+            _ => None,
+        };
+
+        let primitive = Arc::new(primitive_from_buffers(
+            data,
+            position_buffer,
+            attributes_buffer,
+            skinning_buffer,
+            index_buffer,
+        ));
+        self.primitives
+            .insert(primitive_key, Arc::downgrade(&primitive));
+        self.cached.push(CachedPrimitive {
+            primitive: Arc::downgrade(&primitive),
+            buffers: buffer_keys,
+        });
+        Ok(primitive)
+    }
+
+    fn get_or_create_buffer(
+        &mut self,
+        key: ContentKey,
+        buffer_keys: &mut Vec<ContentKey>,
+        create: impl FnOnce() -> anyhow::Result<BufferHandle>,
+    ) -> anyhow::Result<BufferHandle> {
+        let handle = match self.buffers.get_mut(&key) {
+            Some(shared) => {
+                shared.ref_count += 1;
+                self.stats.shared_buffers += 1;
+                self.stats.saved_bytes += key.size;
+                shared.handle
+            }
+            None => {
+                let handle = create()?;
+                self.buffers.insert(
+                    key,
+                    SharedBuffer {
+                        handle,
+                        ref_count: 1,
+                    },
+                );
+                handle
+            }
+        };
+        buffer_keys.push(key);
+        Ok(handle)
+    }
+
+    /// Some primitive was dropped since the last `collect`
+    pub fn has_unused(&self) -> bool {
+        self.cached
+            .iter()
+            .any(|cached| cached.primitive.strong_count() == 0)
+    }
+
+    /// Frees the buffers no live primitive uses anymore, returns how many were freed
+    pub fn collect(&mut self, device: &mut Device) -> usize {
+        let mut freed = 0;
+        let buffers = &mut self.buffers;
+        self.cached.retain(|cached| {
+            if cached.primitive.strong_count() > 0 {
+                return true;
+            }
+            for key in cached.buffers.iter() {
+                let Some(shared) = buffers.get_mut(key) else {
+                    continue;
+                };
+                shared.ref_count -= 1;
+                if shared.ref_count == 0 {
+                    device.destroy_buffer(shared.handle);
+                    buffers.remove(key);
+                    freed += 1;
+                }
+            }
+            false
+        });
+        self.primitives
+            .retain(|_, primitive| primitive.strong_count() > 0);
+        self.stats.freed_buffers += freed;
+        freed
+    }
+
+    pub fn stats(&self) -> PrimitiveCacheStats {
+        self.stats
+    }
+}
+
+fn as_bytes<T>(data: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data)) }
+}
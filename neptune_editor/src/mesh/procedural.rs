@@ -0,0 +1,373 @@
+use crate::gltf_loader::{create_primitive, PrimitiveData};
+use crate::mesh::{generate_tangents, BoundingBox, Primitive, VertexAttributes};
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
+
+/// Cpu side mesh built by the generators below, Y up with counter-clockwise front faces
+#[derive(Debug, Default, Clone)]
+pub struct ProceduralMesh {
+    pub positions: Vec<Vec3>,
+    pub attributes: Vec<VertexAttributes>,
+    pub indices: Vec<u32>,
+}
+
+impl ProceduralMesh {
+    fn add_vertex(&mut self, position: Vec3, normal: Vec3, uv: Vec2) -> u32 {
+        self.positions.push(position);
+        self.attributes.push(VertexAttributes {
+            normal,
+            tangent: Vec4::ZERO,
+            tex_coords: uv.extend(0.0).extend(0.0),
+            color: Vec4::ONE,
+        });
+        self.positions.len() as u32 - 1
+    }
+
+    /// Adds a (columns + 1) x (rows + 1) vertex grid, `vertex` returns the position and normal for a grid point.
+    /// The surface must be laid out so that the row direction crossed with the column direction points outwards
+    fn add_grid(&mut self, columns: u32, rows: u32, vertex: impl Fn(u32, u32) -> (Vec3, Vec3)) {
+        let first = self.positions.len() as u32;
+        for row in 0..=rows {
+            for column in 0..=columns {
+                let (position, normal) = vertex(column, row);
+                let uv = Vec2::new(column as f32 / columns as f32, row as f32 / rows as f32);
+                self.add_vertex(position, normal, uv);
+            }
+        }
+
+        let stride = columns + 1;
+        for row in 0..rows {
+            for column in 0..columns {
+                let a = first + row * stride + column;
+                let b = a + 1;
+                let c = b + stride;
+                let d = a + stride;
+                self.indices.extend_from_slice(&[a, d, c, a, c, b]);
+            }
+        }
+    }
+
+    /// Flat disc facing +Y or -Y, the ring matches the vertices of the side grids
+    fn add_cap(&mut self, center: Vec3, radius: f32, segments: u32, facing_up: bool) {
+        let normal = if facing_up { Vec3::Y } else { Vec3::NEG_Y };
+        let center_index = self.add_vertex(center, normal, Vec2::splat(0.5));
+        let first = self.positions.len() as u32;
+        for segment in 0..=segments {
+            let angle = segment as f32 / segments as f32 * TAU;
+            let (sin, cos) = angle.sin_cos();
+            self.add_vertex(
+                center + Vec3::new(cos, 0.0, -sin) * radius,
+                normal,
+                Vec2::new(0.5 + cos * 0.5, 0.5 - sin * 0.5),
+            );
+        }
+
+        for segment in 0..segments {
+            let (current, next) = (first + segment, first + segment + 1);
+            if facing_up {
+                self.indices
+                    .extend_from_slice(&[center_index, current, next]);
+            } else {
+                self.indices
+                    .extend_from_slice(&[center_index, next, current]);
+            }
+        }
+    }
+
+    fn finish(mut self) -> Self {
+        generate_tangents(&self.positions, &mut self.attributes, &self.indices);
+        self
+    }
+
+    /// Box centered on the origin, every face gets the full 0-1 uv range
+    pub fn cube(half_extent: Vec3) -> Self {
+        //(normal, u axis, v axis) where u cross v is the normal
+        const FACES: [(Vec3, Vec3, Vec3); 6] = [
+            (Vec3::X, Vec3::NEG_Z, Vec3::Y),
+            (Vec3::NEG_X, Vec3::Z, Vec3::Y),
+            (Vec3::Y, Vec3::X, Vec3::NEG_Z),
+            (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+            (Vec3::Z, Vec3::X, Vec3::Y),
+            (Vec3::NEG_Z, Vec3::NEG_X, Vec3::Y),
+        ];
+
+        let mut mesh = Self::default();
+        for (normal, u, v) in FACES {
+            let first = mesh.positions.len() as u32;
+            for (corner_u, corner_v, uv) in [
+                (-1.0, -1.0, Vec2::new(0.0, 1.0)),
+                (1.0, -1.0, Vec2::new(1.0, 1.0)),
+                (1.0, 1.0, Vec2::new(1.0, 0.0)),
+                (-1.0, 1.0, Vec2::new(0.0, 0.0)),
+            ] {
+                let position = (normal + u * corner_u + v * corner_v) * half_extent;
+                mesh.add_vertex(position, normal, uv);
+            }
+            mesh.indices.extend_from_slice(&[
+                first,
+                first + 1,
+                first + 2,
+                first,
+                first + 2,
+                first + 3,
+            ]);
+        }
+        mesh.finish()
+    }
+
+    /// Plane on the XZ axes facing +Y, each side is split into `subdivisions + 1` quads
+    pub fn plane(size: Vec2, subdivisions: u32) -> Self {
+        let segments = subdivisions + 1;
+        let mut mesh = Self::default();
+        mesh.add_grid(segments, segments, |column, row| {
+            let uv = Vec2::new(column as f32, row as f32) / segments as f32;
+            let position = (uv - Vec2::splat(0.5)) * size;
+            (Vec3::new(position.x, 0.0, position.y), Vec3::Y)
+        });
+        mesh.finish()
+    }
+
+    pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> Self {
+        let mut mesh = Self::default();
+        mesh.add_grid(segments, rings, |column, row| {
+            let (sin, cos) = (row as f32 / rings as f32 * PI).sin_cos();
+            let normal =
+                ring_direction(column as f32 / segments as f32 * TAU) * sin + Vec3::Y * cos;
+            (normal * radius, normal)
+        });
+        mesh.finish()
+    }
+
+    /// Subdivided icosahedron, uvs use the same equirectangular mapping as the uv sphere
+    pub fn icosphere(radius: f32, subdivisions: u32) -> Self {
+        let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+        let mut points: Vec<Vec3> = [
+            Vec3::new(-1.0, t, 0.0),
+            Vec3::new(1.0, t, 0.0),
+            Vec3::new(-1.0, -t, 0.0),
+            Vec3::new(1.0, -t, 0.0),
+            Vec3::new(0.0, -1.0, t),
+            Vec3::new(0.0, 1.0, t),
+            Vec3::new(0.0, -1.0, -t),
+            Vec3::new(0.0, 1.0, -t),
+            Vec3::new(t, 0.0, -1.0),
+            Vec3::new(t, 0.0, 1.0),
+            Vec3::new(-t, 0.0, -1.0),
+            Vec3::new(-t, 0.0, 1.0),
+        ]
+        .into_iter()
+        .map(Vec3::normalize)
+        .collect();
+
+        let mut triangles: Vec<[u32; 3]> = vec![
+            [0, 11, 5],
+            [0, 5, 1],
+            [0, 1, 7],
+            [0, 7, 10],
+            [0, 10, 11],
+            [1, 5, 9],
+            [5, 11, 4],
+            [11, 10, 2],
+            [10, 7, 6],
+            [7, 1, 8],
+            [3, 9, 4],
+            [3, 4, 2],
+            [3, 2, 6],
+            [3, 6, 8],
+            [3, 8, 9],
+            [4, 9, 5],
+            [2, 4, 11],
+            [6, 2, 10],
+            [8, 6, 7],
+            [9, 8, 1],
+        ];
+
+        //Make sure every face winds outwards, subdividing keeps the winding
+        for triangle in triangles.iter_mut() {
+            let [a, b, c] = triangle.map(|index| points[index as usize]);
+            if (b - a).cross(c - a).dot(a + b + c) < 0.0 {
+                triangle.swap(1, 2);
+            }
+        }
+
+        for _ in 0..subdivisions {
+            let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+            let mut midpoint = |a: u32, b: u32| {
+                *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    points.push(((points[a as usize] + points[b as usize]) * 0.5).normalize());
+                    points.len() as u32 - 1
+                })
+            };
+
+            triangles = triangles
+                .iter()
+                .flat_map(|&[a, b, c]| {
+                    let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                    [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+                })
+                .collect();
+        }
+
+        let uv = |normal: Vec3| {
+            Vec2::new(
+                (-normal.z).atan2(normal.x).rem_euclid(TAU) / TAU,
+                normal.y.clamp(-1.0, 1.0).acos() / PI,
+            )
+        };
+
+        //Triangles crossing the u seam get their own vertices with u wrapped past 1
+        let mut mesh = Self::default();
+        for &normal in points.iter() {
+            mesh.add_vertex(normal * radius, normal, uv(normal));
+        }
+        let mut wrapped: HashMap<u32, u32> = HashMap::new();
+        for triangle in triangles {
+            let us = triangle.map(|index| mesh.attributes[index as usize].tex_coords.x);
+            let crosses_seam = us.iter().cloned().fold(f32::MIN, f32::max)
+                - us.iter().cloned().fold(f32::MAX, f32::min)
+                > 0.5;
+
+            for (index, u) in triangle.into_iter().zip(us) {
+                let index = if crosses_seam && u < 0.5 {
+                    *wrapped.entry(index).or_insert_with(|| {
+                        let normal = points[index as usize];
+                        let uv = uv(normal) + Vec2::X;
+                        mesh.add_vertex(normal * radius, normal, uv)
+                    })
+                } else {
+                    index
+                };
+                mesh.indices.push(index);
+            }
+        }
+        mesh.finish()
+    }
+
+    /// Cylinder centered on the origin along the Y axis, caps included
+    pub fn cylinder(radius: f32, height: f32, segments: u32) -> Self {
+        let half_height = height * 0.5;
+        let mut mesh = Self::default();
+        mesh.add_grid(segments, 1, |column, row| {
+            let normal = ring_direction(column as f32 / segments as f32 * TAU);
+            let y = if row == 0 { half_height } else { -half_height };
+            (normal * radius + Vec3::Y * y, normal)
+        });
+        mesh.add_cap(Vec3::Y * half_height, radius, segments, true);
+        mesh.add_cap(Vec3::NEG_Y * half_height, radius, segments, false);
+        mesh.finish()
+    }
+
+    /// Cone centered on the origin along the Y axis with the tip at the top, base cap included
+    pub fn cone(radius: f32, height: f32, segments: u32) -> Self {
+        let half_height = height * 0.5;
+        let mut mesh = Self::default();
+        mesh.add_grid(segments, 1, |column, row| {
+            let direction = ring_direction(column as f32 / segments as f32 * TAU);
+            let normal = (direction * height + Vec3::Y * radius).normalize();
+            let position = if row == 0 {
+                Vec3::Y * half_height
+            } else {
+                direction * radius - Vec3::Y * half_height
+            };
+            (position, normal)
+        });
+        mesh.add_cap(Vec3::NEG_Y * half_height, radius, segments, false);
+        mesh.finish()
+    }
+
+    /// Torus around the Y axis, `major_radius` is the distance from the center to the middle of the tube
+    pub fn torus(
+        major_radius: f32,
+        minor_radius: f32,
+        major_segments: u32,
+        minor_segments: u32,
+    ) -> Self {
+        let mut mesh = Self::default();
+        mesh.add_grid(major_segments, minor_segments, |column, row| {
+            let direction = ring_direction(column as f32 / major_segments as f32 * TAU);
+            let (sin, cos) = (-(row as f32) / minor_segments as f32 * TAU).sin_cos();
+            let normal = direction * cos + Vec3::Y * sin;
+            (direction * major_radius + normal * minor_radius, normal)
+        });
+        mesh.finish()
+    }
+
+    /// Capsule along the Y axis, `height` is the length of the cylinder between the two hemisphere centers
+    pub fn capsule(radius: f32, height: f32, segments: u32, hemisphere_rings: u32) -> Self {
+        let half_height = height * 0.5;
+        let mut mesh = Self::default();
+
+        //The row after the top hemisphere repeats its last ring shifted down, forming the cylinder
+        mesh.add_grid(segments, hemisphere_rings * 2 + 1, |column, row| {
+            let (polar, y) = if row <= hemisphere_rings {
+                (row as f32 / hemisphere_rings as f32 * PI * 0.5, half_height)
+            } else {
+                (
+                    (row - 1) as f32 / hemisphere_rings as f32 * PI * 0.5,
+                    -half_height,
+                )
+            };
+            let (sin, cos) = polar.sin_cos();
+            let normal =
+                ring_direction(column as f32 / segments as f32 * TAU) * sin + Vec3::Y * cos;
+            (normal * radius + Vec3::Y * y, normal)
+        });
+        mesh.finish()
+    }
+
+    /// Merges another mesh into this one, used to build debug meshes out of several shapes
+    pub fn append(&mut self, other: &ProceduralMesh, matrix: Mat4, color: Vec4) {
+        let first = self.positions.len() as u32;
+        self.positions.extend(
+            other
+                .positions
+                .iter()
+                .map(|position| matrix.transform_point3(*position)),
+        );
+        self.attributes
+            .extend(other.attributes.iter().map(|attributes| {
+                VertexAttributes {
+                    normal: matrix
+                        .transform_vector3(attributes.normal)
+                        .normalize_or_zero(),
+                    tangent: matrix
+                        .transform_vector3(attributes.tangent.truncate())
+                        .normalize_or_zero()
+                        .extend(attributes.tangent.w),
+                    tex_coords: attributes.tex_coords,
+                    color: attributes.color * color,
+                }
+            }));
+        self.indices
+            .extend(other.indices.iter().map(|index| first + index));
+    }
+
+    pub fn bounding_box(&self) -> BoundingBox {
+        BoundingBox::from_points(&self.positions)
+    }
+
+    /// For `PrimitiveCache::get_or_create`, so identical shapes share their buffers
+    pub fn primitive_data(&self) -> PrimitiveData {
+        PrimitiveData {
+            bounding_box: self.bounding_box(),
+            positions: self.positions.clone(),
+            attributes: self.attributes.clone(),
+            skinning: None,
+            indices: Some(self.indices.clone()),
+        }
+    }
+
+    pub fn create_primitive(
+        &self,
+        device: &mut neptune_vulkan::Device,
+    ) -> anyhow::Result<Primitive> {
+        create_primitive(device, &self.primitive_data())
+    }
+}
+
+/// Direction in the XZ plane, angles increase counter-clockwise when looking down from +Y
+fn ring_direction(angle: f32) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    Vec3::new(cos, 0.0, -sin)
+}
@@ -0,0 +1,287 @@
+use crate::mesh::BoundingBox;
+use crate::navmesh::{NavMeshInput, NavMeshSettings};
+use glam::{Vec2, Vec3};
+use std::collections::VecDeque;
+
+/// +x, +z, -x, -z
+pub(crate) const DIRECTIONS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+/// Solid voxel run in a column, in cell_height units from the bottom of the bounds
+#[derive(Debug, Copy, Clone)]
+struct Span {
+    min: i32,
+    max: i32,
+    walkable: bool,
+}
+
+/// Top of a walkable span with the open space above it
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct OpenSpan {
+    pub x: i32,
+    pub z: i32,
+    pub floor: i32,
+    pub ceiling: i32,
+    pub connections: [Option<usize>; 4],
+}
+
+/// Walkable surfaces of the input, voxelized into columns of cells (the recast compact heightfield)
+pub(crate) struct Heightfield {
+    pub origin: Vec3,
+    pub cell_size: f32,
+    pub cell_height: f32,
+    pub spans: Vec<OpenSpan>,
+}
+
+impl Heightfield {
+    pub fn build(input: &NavMeshInput, settings: &NavMeshSettings) -> Option<Self> {
+        let bounds = BoundingBox::from_points(&input.positions);
+        if input.indices.len() < 3 {
+            return None;
+        }
+
+        let cell_size = settings.cell_size.max(0.01);
+        let cell_height = settings.cell_height.max(0.01);
+        let width = ((bounds.max.x - bounds.min.x) / cell_size).ceil() as i32 + 1;
+        let depth = ((bounds.max.z - bounds.min.z) / cell_size).ceil() as i32 + 1;
+
+        let walkable_climb = (settings.max_climb / cell_height).floor() as i32;
+        let walkable_height = (settings.agent_height / cell_height).ceil() as i32;
+        let min_walkable_normal_y = settings.max_slope_degrees.to_radians().cos();
+
+        let mut columns: Vec<Vec<Span>> = vec![Vec::new(); (width * depth) as usize];
+        for triangle in input.indices.chunks_exact(3) {
+            let vertices = [
+                input.positions[triangle[0] as usize],
+                input.positions[triangle[1] as usize],
+                input.positions[triangle[2] as usize],
+            ];
+            let normal = (vertices[1] - vertices[0])
+                .cross(vertices[2] - vertices[0])
+                .normalize_or_zero();
+            let walkable = normal.y >= min_walkable_normal_y;
+            rasterize_triangle(
+                &vertices,
+                walkable,
+                bounds.min,
+                cell_size,
+                cell_height,
+                width,
+                depth,
+                walkable_climb,
+                &mut columns,
+            );
+        }
+
+        //Only the tops of walkable spans with enough room above them are kept
+        let mut spans = Vec::new();
+        let mut column_spans: Vec<Vec<usize>> = vec![Vec::new(); columns.len()];
+        for z in 0..depth {
+            for x in 0..width {
+                let column_index = (x + z * width) as usize;
+                let column = &columns[column_index];
+                for (i, span) in column.iter().enumerate() {
+                    let ceiling = column.get(i + 1).map(|next| next.min).unwrap_or(i32::MAX);
+                    if span.walkable && ceiling - span.max >= walkable_height {
+                        column_spans[column_index].push(spans.len());
+                        spans.push(OpenSpan {
+                            x,
+                            z,
+                            floor: span.max,
+                            ceiling,
+                            connections: [None; 4],
+                        });
+                    }
+                }
+            }
+        }
+
+        for span_index in 0..spans.len() {
+            let span = spans[span_index];
+            for (direction, (dx, dz)) in DIRECTIONS.iter().enumerate() {
+                let (nx, nz) = (span.x + dx, span.z + dz);
+                if nx < 0 || nz < 0 || nx >= width || nz >= depth {
+                    continue;
+                }
+
+                spans[span_index].connections[direction] = column_spans[(nx + nz * width) as usize]
+                    .iter()
+                    .copied()
+                    .find(|&neighbor_index| {
+                        let neighbor = &spans[neighbor_index];
+                        let gap =
+                            span.ceiling.min(neighbor.ceiling) - span.floor.max(neighbor.floor);
+                        (neighbor.floor - span.floor).abs() <= walkable_climb
+                            && gap >= walkable_height
+                    });
+            }
+        }
+
+        let mut heightfield = Self {
+            origin: bounds.min,
+            cell_size,
+            cell_height,
+            spans,
+        };
+        heightfield.erode((settings.agent_radius / cell_size).ceil() as u32);
+        Some(heightfield)
+    }
+
+    pub fn floor_height(&self, span: &OpenSpan) -> f32 {
+        self.origin.y + span.floor as f32 * self.cell_height
+    }
+
+    /// Removes spans closer than `radius` cells to an edge, so agents don't clip into walls
+    fn erode(&mut self, radius: u32) {
+        if radius == 0 {
+            return;
+        }
+
+        let mut distance = vec![u32::MAX; self.spans.len()];
+        let mut queue = VecDeque::new();
+        for (index, span) in self.spans.iter().enumerate() {
+            if span.connections.iter().any(Option::is_none) {
+                distance[index] = 1;
+                queue.push_back(index);
+            }
+        }
+        while let Some(index) = queue.pop_front() {
+            for neighbor in self.spans[index].connections.iter().flatten() {
+                if distance[*neighbor] == u32::MAX {
+                    distance[*neighbor] = distance[index] + 1;
+                    queue.push_back(*neighbor);
+                }
+            }
+        }
+
+        //Compact the remaining spans and fix up their connections
+        let mut remap = vec![None; self.spans.len()];
+        let mut kept = Vec::new();
+        for (index, span) in self.spans.iter().enumerate() {
+            if distance[index] > radius {
+                remap[index] = Some(kept.len());
+                kept.push(*span);
+            }
+        }
+        for span in kept.iter_mut() {
+            for connection in span.connections.iter_mut() {
+                *connection = connection.and_then(|index| remap[index]);
+            }
+        }
+        self.spans = kept;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn rasterize_triangle(
+    vertices: &[Vec3; 3],
+    walkable: bool,
+    origin: Vec3,
+    cell_size: f32,
+    cell_height: f32,
+    width: i32,
+    depth: i32,
+    walkable_climb: i32,
+    columns: &mut [Vec<Span>],
+) {
+    let min = vertices[0].min(vertices[1]).min(vertices[2]) - origin;
+    let max = vertices[0].max(vertices[1]).max(vertices[2]) - origin;
+    let x0 = ((min.x / cell_size).floor() as i32).clamp(0, width - 1);
+    let x1 = ((max.x / cell_size).floor() as i32).clamp(0, width - 1);
+    let z0 = ((min.z / cell_size).floor() as i32).clamp(0, depth - 1);
+    let z1 = ((max.z / cell_size).floor() as i32).clamp(0, depth - 1);
+
+    for z in z0..=z1 {
+        for x in x0..=x1 {
+            let cell_min = origin + Vec3::new(x as f32, 0.0, z as f32) * cell_size;
+            let clipped = clip_to_cell(
+                vertices,
+                Vec2::new(cell_min.x, cell_min.z),
+                Vec2::new(cell_min.x + cell_size, cell_min.z + cell_size),
+            );
+            if clipped.is_empty() {
+                continue;
+            }
+
+            let (y_min, y_max) = clipped
+                .iter()
+                .fold((f32::MAX, f32::MIN), |(y_min, y_max), vertex| {
+                    (y_min.min(vertex.y), y_max.max(vertex.y))
+                });
+            let span_min = ((y_min - origin.y) / cell_height).floor() as i32;
+            let span_max = (((y_max - origin.y) / cell_height).ceil() as i32).max(span_min + 1);
+            add_span(
+                &mut columns[(x + z * width) as usize],
+                Span {
+                    min: span_min,
+                    max: span_max,
+                    walkable,
+                },
+                walkable_climb,
+            );
+        }
+    }
+}
+
+/// Sutherland-Hodgman clip of the triangle against the cell's xz square
+fn clip_to_cell(vertices: &[Vec3; 3], min: Vec2, max: Vec2) -> Vec<Vec3> {
+    let mut polygon = vertices.to_vec();
+    //(axis, bound, keep greater)
+    for (axis, bound, keep_greater) in [
+        (0, min.x, true),
+        (0, max.x, false),
+        (2, min.y, true),
+        (2, max.y, false),
+    ] {
+        let inside = |vertex: &Vec3| {
+            if keep_greater {
+                vertex[axis] >= bound
+            } else {
+                vertex[axis] <= bound
+            }
+        };
+
+        let mut clipped = Vec::with_capacity(polygon.len() + 1);
+        for i in 0..polygon.len() {
+            let current = polygon[i];
+            let next = polygon[(i + 1) % polygon.len()];
+            if inside(&current) {
+                clipped.push(current);
+            }
+            if inside(&current) != inside(&next) {
+                let t = (bound - current[axis]) / (next[axis] - current[axis]);
+                clipped.push(current.lerp(next, t));
+            }
+        }
+        polygon = clipped;
+        if polygon.is_empty() {
+            break;
+        }
+    }
+    polygon
+}
+
+/// Merges the span into the column, spans that touch are combined into one
+fn add_span(column: &mut Vec<Span>, mut span: Span, walkable_climb: i32) {
+    let mut i = 0;
+    while i < column.len() {
+        let existing = column[i];
+        if existing.min > span.max {
+            break;
+        }
+        if existing.max < span.min {
+            i += 1;
+            continue;
+        }
+
+        //The top surface decides if the merged span is walkable
+        if (existing.max - span.max).abs() <= walkable_climb {
+            span.walkable |= existing.walkable;
+        } else if existing.max > span.max {
+            span.walkable = existing.walkable;
+        }
+        span.min = span.min.min(existing.min);
+        span.max = span.max.max(existing.max);
+        column.remove(i);
+    }
+    column.insert(i, span);
+}
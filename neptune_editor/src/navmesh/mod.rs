@@ -0,0 +1,379 @@
+mod heightfield;
+mod query;
+
+use crate::mesh::procedural::ProceduralMesh;
+use crate::mesh::VertexAttributes;
+use crate::navmesh::heightfield::{Heightfield, DIRECTIONS};
+use crate::scene::scene_renderer::Scene;
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub struct NavMeshSettings {
+    /// Voxel size on the xz plane
+    pub cell_size: f32,
+    /// Voxel size on the y axis
+    pub cell_height: f32,
+    pub agent_height: f32,
+    pub agent_radius: f32,
+    /// Tallest step an agent can walk up
+    pub max_climb: f32,
+    pub max_slope_degrees: f32,
+    /// Polygons are grown up to this many cells along each axis
+    pub max_polygon_cells: u32,
+}
+
+impl Default for NavMeshSettings {
+    fn default() -> Self {
+        Self {
+            cell_size: 0.25,
+            cell_height: 0.1,
+            agent_height: 1.8,
+            agent_radius: 0.4,
+            max_climb: 0.4,
+            max_slope_degrees: 45.0,
+            max_polygon_cells: 32,
+        }
+    }
+}
+
+/// World space triangles the navmesh is built from
+#[derive(Debug, Default, Clone)]
+pub struct NavMeshInput {
+    pub positions: Vec<Vec3>,
+    pub indices: Vec<u32>,
+}
+
+impl NavMeshInput {
+    pub fn add_triangles(&mut self, positions: &[Vec3], indices: &[u32], matrix: &Mat4) {
+        let first = self.positions.len() as u32;
+        self.positions.extend(
+            positions
+                .iter()
+                .map(|position| matrix.transform_point3(*position)),
+        );
+        self.indices
+            .extend(indices.iter().map(|index| first + index));
+    }
+
+    /// Gathers the cpu geometry of every instance in the scene
+    pub fn from_scene(scene: &Scene) -> Self {
+        let mut input = Self::default();
+        for (transform, model) in scene.instances() {
+            let matrix = transform.model_matrix();
+            for model_primitive in model.primitives.iter() {
+                let geometry = &model_primitive.primitive.geometry;
+                input.add_triangles(&geometry.positions, &geometry.indices, &matrix);
+            }
+        }
+        input
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct NavPortal {
+    pub polygon: usize,
+    pub start: Vec3,
+    pub end: Vec3,
+}
+
+/// Convex quad on the xz plane, corners are in counter-clockwise order seen from above
+#[derive(Debug, Clone)]
+pub struct NavPolygon {
+    pub corners: [Vec3; 4],
+    pub min: Vec2,
+    pub max: Vec2,
+    pub region: u32,
+    pub portals: Vec<NavPortal>,
+}
+
+impl NavPolygon {
+    pub fn center(&self) -> Vec3 {
+        (self.corners[0] + self.corners[1] + self.corners[2] + self.corners[3]) * 0.25
+    }
+
+    pub fn contains_xz(&self, point: Vec3) -> bool {
+        point.x >= self.min.x
+            && point.x <= self.max.x
+            && point.z >= self.min.y
+            && point.z <= self.max.y
+    }
+
+    /// Bilinear height of the polygon's surface at the point
+    pub fn height_at(&self, point: Vec3) -> f32 {
+        let size = (self.max - self.min).max(Vec2::splat(f32::EPSILON));
+        let t = ((Vec2::new(point.x, point.z) - self.min) / size).clamp(Vec2::ZERO, Vec2::ONE);
+        //corners: (min x, min z), (min x, max z), (max x, max z), (max x, min z)
+        let near = self.corners[0].y + (self.corners[3].y - self.corners[0].y) * t.x;
+        let far = self.corners[1].y + (self.corners[2].y - self.corners[1].y) * t.x;
+        near + (far - near) * t.y
+    }
+}
+
+/// Recast style navigation mesh: the input is voxelized, walkable cells are grown into convex polygons,
+/// and paths are found with A* over the polygons followed by string pulling through the shared edges
+#[derive(Debug, Default, Clone)]
+pub struct NavMesh {
+    pub settings: NavMeshSettings,
+    pub polygons: Vec<NavPolygon>,
+}
+
+impl NavMesh {
+    pub fn build(input: &NavMeshInput, settings: NavMeshSettings) -> Self {
+        let Some(heightfield) = Heightfield::build(input, &settings) else {
+            return Self {
+                settings,
+                polygons: Vec::new(),
+            };
+        };
+
+        let regions = build_regions(&heightfield);
+        let (polygons, span_polygons) = build_polygons(&heightfield, &regions, &settings);
+        let mut nav_mesh = Self { settings, polygons };
+        nav_mesh.build_portals(&heightfield, &span_polygons);
+        nav_mesh
+    }
+
+    pub fn from_scene(scene: &Scene, settings: NavMeshSettings) -> Self {
+        Self::build(&NavMeshInput::from_scene(scene), settings)
+    }
+
+    /// Polygon under the point, picking the closest surface if several are stacked
+    pub fn find_polygon(&self, point: Vec3) -> Option<usize> {
+        let max_distance = self.settings.agent_height;
+        self.polygons
+            .iter()
+            .enumerate()
+            .filter(|(_, polygon)| polygon.contains_xz(point))
+            .map(|(index, polygon)| (index, (polygon.height_at(point) - point.y).abs()))
+            .filter(|(_, distance)| *distance <= max_distance)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _)| index)
+    }
+
+    /// Straight path from start to end along the navmesh surface, None if they aren't connected
+    pub fn find_path(&self, start: Vec3, end: Vec3) -> Option<Vec<Vec3>> {
+        let start_polygon = self.find_polygon(start)?;
+        let end_polygon = self.find_polygon(end)?;
+        let start = Vec3::new(
+            start.x,
+            self.polygons[start_polygon].height_at(start),
+            start.z,
+        );
+        let end = Vec3::new(end.x, self.polygons[end_polygon].height_at(end), end.z);
+
+        let corridor = query::find_corridor(self, start_polygon, end_polygon, end)?;
+        Some(query::string_pull(self, &corridor, start, end))
+    }
+
+    /// Polygons colored by region, raised slightly so they draw over the ground they were built from
+    pub fn debug_mesh(&self) -> ProceduralMesh {
+        let mut mesh = ProceduralMesh::default();
+        let offset = Vec3::Y * self.settings.cell_height;
+        for polygon in self.polygons.iter() {
+            let color = region_color(polygon.region);
+            let first = mesh.positions.len() as u32;
+            for corner in polygon.corners.iter() {
+                mesh.positions.push(*corner + offset);
+                mesh.attributes.push(VertexAttributes {
+                    normal: Vec3::Y,
+                    tangent: Vec4::new(1.0, 0.0, 0.0, 1.0),
+                    tex_coords: Vec4::ZERO,
+                    color,
+                });
+            }
+            mesh.indices.extend_from_slice(&[
+                first,
+                first + 1,
+                first + 2,
+                first,
+                first + 2,
+                first + 3,
+            ]);
+        }
+        mesh
+    }
+
+    fn build_portals(&mut self, heightfield: &Heightfield, span_polygons: &[Option<usize>]) {
+        //(from, to) -> (edge start, edge end), the shared edge is grown one cell edge at a time
+        let mut edges: HashMap<(usize, usize), (Vec3, Vec3)> = HashMap::new();
+        let cell_size = heightfield.cell_size;
+
+        for (span_index, span) in heightfield.spans.iter().enumerate() {
+            let Some(polygon) = span_polygons[span_index] else {
+                continue;
+            };
+
+            for (direction, connection) in span.connections.iter().enumerate() {
+                let Some(neighbor_index) = *connection else {
+                    continue;
+                };
+                let Some(neighbor_polygon) = span_polygons[neighbor_index] else {
+                    continue;
+                };
+                if neighbor_polygon == polygon {
+                    continue;
+                }
+
+                let neighbor = &heightfield.spans[neighbor_index];
+                let height =
+                    (heightfield.floor_height(span) + heightfield.floor_height(neighbor)) * 0.5;
+                let (dx, dz) = DIRECTIONS[direction];
+                let cell_min =
+                    heightfield.origin + Vec3::new(span.x as f32, 0.0, span.z as f32) * cell_size;
+                let (a, b) = match (dx, dz) {
+                    (1, 0) => (
+                        Vec3::new(cell_min.x + cell_size, height, cell_min.z),
+                        Vec3::new(cell_min.x + cell_size, height, cell_min.z + cell_size),
+                    ),
+                    (-1, 0) => (
+                        Vec3::new(cell_min.x, height, cell_min.z),
+                        Vec3::new(cell_min.x, height, cell_min.z + cell_size),
+                    ),
+                    (0, 1) => (
+                        Vec3::new(cell_min.x, height, cell_min.z + cell_size),
+                        Vec3::new(cell_min.x + cell_size, height, cell_min.z + cell_size),
+                    ),
+                    _ => (
+                        Vec3::new(cell_min.x, height, cell_min.z),
+                        Vec3::new(cell_min.x + cell_size, height, cell_min.z),
+                    ),
+                };
+
+                //Edges are axis aligned so the endpoints can be ordered component wise
+                edges
+                    .entry((polygon, neighbor_polygon))
+                    .and_modify(|(start, end)| {
+                        if a.x + a.z < start.x + start.z {
+                            *start = a;
+                        }
+                        if b.x + b.z > end.x + end.z {
+                            *end = b;
+                        }
+                    })
+                    .or_insert((a, b));
+            }
+        }
+
+        for ((from, to), (start, end)) in edges {
+            self.polygons[from].portals.push(NavPortal {
+                polygon: to,
+                start,
+                end,
+            });
+        }
+    }
+}
+
+/// Flood fills connected spans, every island of walkable space gets its own region
+fn build_regions(heightfield: &Heightfield) -> Vec<u32> {
+    let mut regions = vec![u32::MAX; heightfield.spans.len()];
+    let mut next_region = 0;
+    let mut stack = Vec::new();
+    for start in 0..heightfield.spans.len() {
+        if regions[start] != u32::MAX {
+            continue;
+        }
+
+        regions[start] = next_region;
+        stack.push(start);
+        while let Some(index) = stack.pop() {
+            for neighbor in heightfield.spans[index].connections.iter().flatten() {
+                if regions[*neighbor] == u32::MAX {
+                    regions[*neighbor] = next_region;
+                    stack.push(*neighbor);
+                }
+            }
+        }
+        next_region += 1;
+    }
+    regions
+}
+
+/// Greedily grows rectangles of connected spans, first along +x then along +z
+fn build_polygons(
+    heightfield: &Heightfield,
+    regions: &[u32],
+    settings: &NavMeshSettings,
+) -> (Vec<NavPolygon>, Vec<Option<usize>>) {
+    const POSITIVE_X: usize = 0;
+    const POSITIVE_Z: usize = 1;
+
+    let max_cells = settings.max_polygon_cells.max(1) as usize;
+    let mut span_polygons: Vec<Option<usize>> = vec![None; heightfield.spans.len()];
+    let mut polygons = Vec::new();
+
+    for start in 0..heightfield.spans.len() {
+        if span_polygons[start].is_some() {
+            continue;
+        }
+
+        let mut row = vec![start];
+        while row.len() < max_cells {
+            match heightfield.spans[*row.last().unwrap()].connections[POSITIVE_X] {
+                Some(next) if span_polygons[next].is_none() => row.push(next),
+                _ => break,
+            }
+        }
+
+        let mut rows = vec![row];
+        'grow: while rows.len() < max_cells {
+            let previous = rows.last().unwrap();
+            let mut next_row: Vec<usize> = Vec::with_capacity(previous.len());
+            for (i, span) in previous.iter().enumerate() {
+                let Some(next) = heightfield.spans[*span].connections[POSITIVE_Z] else {
+                    break 'grow;
+                };
+                let connected_to_row = i == 0
+                    || heightfield.spans[next_row[i - 1]].connections[POSITIVE_X] == Some(next);
+                if span_polygons[next].is_some() || !connected_to_row {
+                    break 'grow;
+                }
+                next_row.push(next);
+            }
+            rows.push(next_row);
+        }
+
+        let polygon_index = polygons.len();
+        for span in rows.iter().flatten() {
+            span_polygons[*span] = Some(polygon_index);
+        }
+
+        let first_row = rows.first().unwrap();
+        let last_row = rows.last().unwrap();
+        let corner = |span: usize, x_offset: i32, z_offset: i32| {
+            let span = &heightfield.spans[span];
+            Vec3::new(
+                heightfield.origin.x + (span.x + x_offset) as f32 * heightfield.cell_size,
+                heightfield.floor_height(span),
+                heightfield.origin.z + (span.z + z_offset) as f32 * heightfield.cell_size,
+            )
+        };
+        let corners = [
+            corner(first_row[0], 0, 0),
+            corner(last_row[0], 0, 1),
+            corner(*last_row.last().unwrap(), 1, 1),
+            corner(*first_row.last().unwrap(), 1, 0),
+        ];
+        polygons.push(NavPolygon {
+            corners,
+            min: Vec2::new(corners[0].x, corners[0].z),
+            max: Vec2::new(corners[2].x, corners[2].z),
+            region: regions[start],
+            portals: Vec::new(),
+        });
+    }
+
+    (polygons, span_polygons)
+}
+
+fn region_color(region: u32) -> Vec4 {
+    let hash = region.wrapping_mul(2654435761);
+    Vec4::new(
+        ((hash >> 16) & 0xFF) as f32 / 255.0,
+        ((hash >> 8) & 0xFF) as f32 / 255.0,
+        (hash & 0xFF) as f32 / 255.0,
+        1.0,
+    ) * 0.5
+        + Vec4::new(0.25, 0.25, 0.25, 0.5)
+}
@@ -0,0 +1,176 @@
+use crate::navmesh::NavMesh;
+use glam::Vec3;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct OpenNode {
+    polygon: usize,
+    estimated_cost: f32,
+}
+
+impl Eq for OpenNode {}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        //Reversed so the BinaryHeap pops the cheapest node first
+        other.estimated_cost.total_cmp(&self.estimated_cost)
+    }
+}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A* over the polygon graph, returns the polygons from start to end
+pub(crate) fn find_corridor(
+    nav_mesh: &NavMesh,
+    start: usize,
+    end: usize,
+    end_point: Vec3,
+) -> Option<Vec<usize>> {
+    if start == end {
+        return Some(vec![start]);
+    }
+
+    let polygons = &nav_mesh.polygons;
+    let mut open = BinaryHeap::new();
+    let mut cost: HashMap<usize, f32> = HashMap::new();
+    let mut came_from: HashMap<usize, usize> = HashMap::new();
+
+    cost.insert(start, 0.0);
+    open.push(OpenNode {
+        polygon: start,
+        estimated_cost: polygons[start].center().distance(end_point),
+    });
+
+    while let Some(node) = open.pop() {
+        if node.polygon == end {
+            let mut corridor = vec![end];
+            let mut current = end;
+            while let Some(previous) = came_from.get(&current) {
+                corridor.push(*previous);
+                current = *previous;
+            }
+            corridor.reverse();
+            return Some(corridor);
+        }
+
+        let current_center = polygons[node.polygon].center();
+        let current_cost = cost[&node.polygon];
+        for portal in polygons[node.polygon].portals.iter() {
+            let next_center = polygons[portal.polygon].center();
+            let portal_center = (portal.start + portal.end) * 0.5;
+            let next_cost = current_cost
+                + current_center.distance(portal_center)
+                + portal_center.distance(next_center);
+
+            if cost
+                .get(&portal.polygon)
+                .map(|existing| next_cost < *existing)
+                .unwrap_or(true)
+            {
+                cost.insert(portal.polygon, next_cost);
+                came_from.insert(portal.polygon, node.polygon);
+                open.push(OpenNode {
+                    polygon: portal.polygon,
+                    estimated_cost: next_cost + next_center.distance(end_point),
+                });
+            }
+        }
+    }
+
+    None
+}
+
+/// Twice the signed area of the triangle on the xz plane, positive when c is right of a->b
+fn triangle_area_2d(a: Vec3, b: Vec3, c: Vec3) -> f32 {
+    (c.x - a.x) * (b.z - a.z) - (b.x - a.x) * (c.z - a.z)
+}
+
+/// Simple stupid funnel algorithm, pulls the path tight through the portals of the corridor
+pub(crate) fn string_pull(
+    nav_mesh: &NavMesh,
+    corridor: &[usize],
+    start: Vec3,
+    end: Vec3,
+) -> Vec<Vec3> {
+    //(left, right) as seen walking through the portal
+    let mut portals = Vec::with_capacity(corridor.len() + 1);
+    portals.push((start, start));
+    for pair in corridor.windows(2) {
+        let from = &nav_mesh.polygons[pair[0]];
+        let portal = from
+            .portals
+            .iter()
+            .find(|portal| portal.polygon == pair[1])
+            .expect("Corridor polygons must share a portal");
+
+        let from_center = from.center();
+        let to_center = nav_mesh.polygons[pair[1]].center();
+        if triangle_area_2d(from_center, to_center, portal.start)
+            < triangle_area_2d(from_center, to_center, portal.end)
+        {
+            portals.push((portal.start, portal.end));
+        } else {
+            portals.push((portal.end, portal.start));
+        }
+    }
+    portals.push((end, end));
+
+    let mut path = vec![start];
+    let mut apex = start;
+    let (mut left, mut right) = (start, start);
+    let (mut left_index, mut right_index) = (0, 0);
+
+    let mut i = 1;
+    while i < portals.len() {
+        let (portal_left, portal_right) = portals[i];
+
+        //Tighten the right side of the funnel
+        if triangle_area_2d(apex, right, portal_right) <= 0.0 {
+            if apex == right || triangle_area_2d(apex, left, portal_right) > 0.0 {
+                right = portal_right;
+                right_index = i;
+            } else {
+                //Right crossed over left, left becomes a corner of the path
+                path.push(left);
+                apex = left;
+                let apex_index = left_index;
+                left = apex;
+                right = apex;
+                left_index = apex_index;
+                right_index = apex_index;
+                i = apex_index + 1;
+                continue;
+            }
+        }
+
+        //Tighten the left side of the funnel
+        if triangle_area_2d(apex, left, portal_left) >= 0.0 {
+            if apex == left || triangle_area_2d(apex, right, portal_left) < 0.0 {
+                left = portal_left;
+                left_index = i;
+            } else {
+                path.push(right);
+                apex = right;
+                let apex_index = right_index;
+                left = apex;
+                right = apex;
+                left_index = apex_index;
+                right_index = apex_index;
+                i = apex_index + 1;
+                continue;
+            }
+        }
+
+        i += 1;
+    }
+
+    if path.last() != Some(&end) {
+        path.push(end);
+    }
+    path
+}
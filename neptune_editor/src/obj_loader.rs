@@ -0,0 +1,607 @@
+use crate::fallback::{self, FallbackKind};
+use crate::gltf_loader::{
+    create_default_sampler, GltfNode, GltfSamplers, GltfScene, PrimitiveData,
+};
+use crate::material::{
+    Material, MaterialConstants, MaterialConstantsData, MaterialPalette, MaterialPipeline,
+    MaterialTexture,
+};
+use crate::mesh::batching::{StaticBatcher, StaticBatching};
+use crate::mesh::primitive_cache::PrimitiveCache;
+use crate::mesh::{generate_tangents, BoundingBox, Mesh, VertexAttributes};
+use crate::texture_cache::{TextureCache, TextureImportSettings};
+use anyhow::{anyhow, Context};
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use neptune_vulkan::ImageHandle;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Indices into the obj's position/tex coord/normal lists, already resolved to be zero based
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+struct ObjIndex {
+    position: usize,
+    tex_coord: Option<usize>,
+    normal: Option<usize>,
+}
+
+struct ObjFaceGroup {
+    material: Option<String>,
+    triangles: Vec<[ObjIndex; 3]>,
+}
+
+struct ObjObject {
+    name: String,
+    groups: Vec<ObjFaceGroup>,
+}
+
+impl ObjObject {
+    fn current_group(&mut self, material: &Option<String>) -> &mut ObjFaceGroup {
+        if self.groups.last().map(|group| &group.material) != Some(material) {
+            self.groups.push(ObjFaceGroup {
+                material: material.clone(),
+                triangles: Vec::new(),
+            });
+        }
+        self.groups.last_mut().unwrap()
+    }
+}
+
+#[derive(Default)]
+struct ObjData {
+    positions: Vec<Vec3>,
+    /// Non-standard "v x y z r g b" extension, white when missing
+    colors: Vec<Vec4>,
+    normals: Vec<Vec3>,
+    tex_coords: Vec<Vec2>,
+    objects: Vec<ObjObject>,
+    material_libraries: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct ObjMaterial {
+    name: String,
+    diffuse: Vec3,
+    dissolve: f32,
+    emissive: Vec3,
+    metallic: f32,
+    roughness: f32,
+    diffuse_texture: Option<PathBuf>,
+    metallic_roughness_texture: Option<PathBuf>,
+    normal_texture: Option<PathBuf>,
+    emissive_texture: Option<PathBuf>,
+}
+
+impl ObjMaterial {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            diffuse: Vec3::ONE,
+            dissolve: 1.0,
+            emissive: Vec3::ZERO,
+            metallic: 0.0,
+            roughness: 1.0,
+            diffuse_texture: None,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            emissive_texture: None,
+        }
+    }
+}
+
+fn parse_floats<const N: usize>(line: &str, tokens: &[&str]) -> anyhow::Result<[f32; N]> {
+    let mut values = [0.0; N];
+    if tokens.len() < N {
+        return Err(anyhow!("Expected {} values: {}", N, line));
+    }
+    for (value, token) in values.iter_mut().zip(tokens) {
+        *value = token
+            .parse()
+            .with_context(|| format!("Invalid number {}: {}", token, line))?;
+    }
+    Ok(values)
+}
+
+/// Obj indices are one based, negative indices count back from the end of the list
+fn resolve_index(token: &str, count: usize, line: &str) -> anyhow::Result<usize> {
+    let index: isize = token
+        .parse()
+        .with_context(|| format!("Invalid index {}: {}", token, line))?;
+    let resolved = match index {
+        0 => None,
+        index if index > 0 => Some(index as usize - 1),
+        index => count.checked_sub(index.unsigned_abs()),
+    };
+    resolved
+        .filter(|&index| index < count)
+        .ok_or_else(|| anyhow!("Index {} is out of range: {}", token, line))
+}
+
+fn parse_obj(source: &str) -> anyhow::Result<ObjData> {
+    let mut data = ObjData::default();
+    let mut current_material: Option<String> = None;
+
+    for line in source.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let tokens: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => {
+                let [x, y, z] = parse_floats(line, &tokens)?;
+                data.positions.push(Vec3::new(x, y, z));
+                data.colors.push(if tokens.len() >= 6 {
+                    let [r, g, b] = parse_floats(line, &tokens[3..])?;
+                    Vec4::new(r, g, b, 1.0)
+                } else {
+                    Vec4::ONE
+                });
+            }
+            "vn" => data
+                .normals
+                .push(Vec3::from_array(parse_floats(line, &tokens)?)),
+            "vt" => {
+                //Obj uvs start at the bottom left
+                let [u] = parse_floats(line, &tokens)?;
+                let v = tokens
+                    .get(1)
+                    .map(|token| token.parse::<f32>())
+                    .transpose()
+                    .with_context(|| format!("Invalid tex coord: {}", line))?
+                    .unwrap_or(0.0);
+                data.tex_coords.push(Vec2::new(u, 1.0 - v));
+            }
+            "f" => {
+                if tokens.len() < 3 {
+                    return Err(anyhow!("Face has less than 3 vertices: {}", line));
+                }
+
+                let mut face = Vec::with_capacity(tokens.len());
+                for token in tokens.iter() {
+                    let mut parts = token.split('/');
+                    let position = resolve_index(
+                        parts.next().unwrap_or_default(),
+                        data.positions.len(),
+                        line,
+                    )?;
+                    let tex_coord = match parts.next() {
+                        Some(part) if !part.is_empty() => {
+                            Some(resolve_index(part, data.tex_coords.len(), line)?)
+                        }
+                        _ => None,
+                    };
+                    let normal = match parts.next() {
+                        Some(part) if !part.is_empty() => {
+                            Some(resolve_index(part, data.normals.len(), line)?)
+                        }
+                        _ => None,
+                    };
+                    face.push(ObjIndex {
+                        position,
+                        tex_coord,
+                        normal,
+                    });
+                }
+
+                if data.objects.is_empty() {
+                    data.objects.push(ObjObject {
+                        name: "Unnamed Object".to_string(),
+                        groups: Vec::new(),
+                    });
+                }
+                let group = data
+                    .objects
+                    .last_mut()
+                    .unwrap()
+                    .current_group(&current_material);
+
+                //Polygons are fanned, which is fine for the convex faces most exporters write
+                for i in 1..(face.len() - 1) {
+                    group.triangles.push([face[0], face[i], face[i + 1]]);
+                }
+            }
+            "o" | "g" => {
+                let name = if tokens.is_empty() {
+                    format!("Unnamed Object {}", data.objects.len())
+                } else {
+                    tokens.join(" ")
+                };
+
+                //Drop objects that never got any faces, exporters like to write a group before every object
+                if data
+                    .objects
+                    .last()
+                    .map(|object| object.groups.is_empty())
+                    .unwrap_or(false)
+                {
+                    data.objects.pop();
+                }
+                data.objects.push(ObjObject {
+                    name,
+                    groups: Vec::new(),
+                });
+            }
+            "usemtl" => current_material = Some(tokens.join(" ")),
+            "mtllib" => data
+                .material_libraries
+                .extend(tokens.iter().map(|token| token.to_string())),
+            //Smoothing groups, lines and points are ignored
+            _ => {}
+        }
+    }
+
+    data.objects.retain(|object| !object.groups.is_empty());
+    Ok(data)
+}
+
+/// Texture statements may have options before the file name, so the file is always the last token
+fn parse_texture_path(base_path: &Path, tokens: &[&str]) -> Option<PathBuf> {
+    tokens
+        .last()
+        .map(|file| base_path.join(file.replace('\\', "/")))
+}
+
+fn parse_mtl(source: &str, base_path: &Path) -> anyhow::Result<Vec<ObjMaterial>> {
+    let mut materials: Vec<ObjMaterial> = Vec::new();
+
+    for line in source.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let tokens: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            materials.push(ObjMaterial::new(&tokens.join(" ")));
+            continue;
+        }
+
+        let Some(material) = materials.last_mut() else {
+            continue;
+        };
+
+        match keyword {
+            "Kd" => material.diffuse = Vec3::from_array(parse_floats(line, &tokens)?),
+            "Ke" => material.emissive = Vec3::from_array(parse_floats(line, &tokens)?),
+            "d" => material.dissolve = parse_floats::<1>(line, &tokens)?[0],
+            "Tr" => material.dissolve = 1.0 - parse_floats::<1>(line, &tokens)?[0],
+            //Blinn-Phong exponent to roughness, same mapping blender uses
+            "Ns" => {
+                let exponent = parse_floats::<1>(line, &tokens)?[0].max(0.0);
+                material.roughness = (2.0 / (exponent + 2.0)).sqrt();
+            }
+            //PBR extension
+            "Pm" => material.metallic = parse_floats::<1>(line, &tokens)?[0],
+            "Pr" => material.roughness = parse_floats::<1>(line, &tokens)?[0],
+            "map_Kd" => material.diffuse_texture = parse_texture_path(base_path, &tokens),
+            "map_Ke" => material.emissive_texture = parse_texture_path(base_path, &tokens),
+            "map_Pr" | "map_Pm" => {
+                material.metallic_roughness_texture = parse_texture_path(base_path, &tokens)
+            }
+            "map_Bump" | "map_bump" | "bump" | "norm" => {
+                material.normal_texture = parse_texture_path(base_path, &tokens)
+            }
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+struct ObjTextures<'a> {
+    texture_cache: &'a TextureCache,
+    images: Vec<ImageHandle>,
+    loaded: HashMap<PathBuf, ImageHandle>,
+    /// Shared by every texture that failed to load
+    error_image: Option<ImageHandle>,
+}
+
+impl<'a> ObjTextures<'a> {
+    //Missing textures only cost the texture, not the whole model
+    fn load(
+        &mut self,
+        device: &mut neptune_vulkan::Device,
+        samplers: &GltfSamplers,
+        path: &Option<PathBuf>,
+    ) -> Option<MaterialTexture> {
+        let path = path.as_ref()?;
+
+        let image = match self.loaded.get(path) {
+            Some(image) => *image,
+            None => {
+                let image = match self
+                    .texture_cache
+                    .load_file(path, TextureImportSettings::default())
+                    .and_then(|texture| texture.create_image(device, &path.display().to_string()))
+                {
+                    Ok(image) => {
+                        self.images.push(image);
+                        image
+                    }
+                    Err(err) => {
+                        fallback::record(
+                            FallbackKind::Texture,
+                            &path.display().to_string(),
+                            format!("{:#}", err),
+                        );
+                        self.error_image(device)?
+                    }
+                };
+                self.loaded.insert(path.clone(), image);
+                image
+            }
+        };
+
+        Some(MaterialTexture {
+            image,
+            sampler: samplers.default,
+            uv_index: 0,
+        })
+    }
+
+    fn error_image(&mut self, device: &mut neptune_vulkan::Device) -> Option<ImageHandle> {
+        if self.error_image.is_none() {
+            match fallback::error_texture().create_image(device, "Error Texture") {
+                Ok(image) => {
+                    self.images.push(image);
+                    self.error_image = Some(image);
+                }
+                Err(err) => warn!("Failed to create the error texture: {:#}", err),
+            }
+        }
+        self.error_image
+    }
+}
+
+fn create_material(
+    device: &mut neptune_vulkan::Device,
+    material_palette: &MaterialPalette,
+    textures: &mut ObjTextures,
+    samplers: &GltfSamplers,
+    obj_material: &ObjMaterial,
+) -> anyhow::Result<Material> {
+    let base_color = obj_material.diffuse.extend(obj_material.dissolve);
+    let metallic_roughness_factor = Vec2::new(obj_material.metallic, obj_material.roughness);
+    let emissive_color = obj_material.emissive;
+    let constants = MaterialConstants::new(
+        material_palette,
+        MaterialConstantsData::new(base_color, metallic_roughness_factor, emissive_color),
+    )?;
+
+    Ok(Material {
+        name: obj_material.name.clone(),
+        alpha_blending: obj_material.dissolve < 1.0,
+        base_color,
+        metallic_roughness_factor,
+        emissive_color,
+        base_color_texture: textures.load(device, samplers, &obj_material.diffuse_texture),
+        metallic_roughness_texture: textures.load(
+            device,
+            samplers,
+            &obj_material.metallic_roughness_texture,
+        ),
+        normal_texture: textures.load(device, samplers, &obj_material.normal_texture),
+        occlusion_texture: None,
+        emissive_texture: textures.load(device, samplers, &obj_material.emissive_texture),
+        constants,
+        pipeline: MaterialPipeline::default(),
+    })
+}
+
+fn build_primitive_data(data: &ObjData, triangles: &[[ObjIndex; 3]]) -> PrimitiveData {
+    let mut vertex_map: HashMap<ObjIndex, u32> = HashMap::new();
+    let mut positions = Vec::new();
+    let mut attributes = Vec::new();
+    let mut has_normal = Vec::new();
+    let mut indices = Vec::with_capacity(triangles.len() * 3);
+
+    for index in triangles.iter().flatten() {
+        let vertex = *vertex_map.entry(*index).or_insert_with(|| {
+            positions.push(data.positions[index.position]);
+            has_normal.push(index.normal.is_some());
+            attributes.push(VertexAttributes {
+                normal: index
+                    .normal
+                    .map(|normal| data.normals[normal].normalize_or_zero())
+                    .unwrap_or_default(),
+                tangent: Vec4::ZERO,
+                tex_coords: index
+                    .tex_coord
+                    .map(|tex_coord| data.tex_coords[tex_coord].extend(0.0).extend(0.0))
+                    .unwrap_or_default(),
+                color: data.colors[index.position],
+            });
+            positions.len() as u32 - 1
+        });
+        indices.push(vertex);
+    }
+
+    let mut generated_normals = vec![Vec3::ZERO; positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [i0, i1, i2] = [
+            triangle[0] as usize,
+            triangle[1] as usize,
+            triangle[2] as usize,
+        ];
+
+        //Area weighted, only used for vertices without a normal
+        let face_normal = (positions[i1] - positions[i0]).cross(positions[i2] - positions[i0]);
+        for i in [i0, i1, i2] {
+            generated_normals[i] += face_normal;
+        }
+    }
+
+    for (i, attribute) in attributes.iter_mut().enumerate() {
+        if !has_normal[i] {
+            attribute.normal = generated_normals[i].normalize_or_zero();
+        }
+    }
+    generate_tangents(&positions, &mut attributes, &indices);
+
+    let bounding_box = BoundingBox::from_points(&positions);
+
+    PrimitiveData {
+        bounding_box,
+        positions,
+        attributes,
+        skinning: None,
+        indices: Some(indices),
+    }
+}
+
+/// Loads an obj and its mtl libraries, every object becomes a mesh with a primitive per material
+pub fn load_obj_scene<P: AsRef<Path>>(
+    device: &mut neptune_vulkan::Device,
+    texture_cache: &TextureCache,
+    primitive_cache: &mut PrimitiveCache,
+    material_palette: &MaterialPalette,
+    path: P,
+    static_batching: StaticBatching,
+) -> anyhow::Result<GltfScene> {
+    let path = path.as_ref();
+    let base_path = path.parent().unwrap_or_else(|| Path::new("./"));
+
+    let now = std::time::Instant::now();
+    let source = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read obj {}", path.display()))?;
+    let data = parse_obj(&source).with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    let mut obj_materials = Vec::new();
+    for library in data.material_libraries.iter() {
+        let library_path = base_path.join(library);
+        match std::fs::read_to_string(&library_path) {
+            Ok(source) => obj_materials.extend(
+                parse_mtl(&source, library_path.parent().unwrap_or(base_path))
+                    .with_context(|| format!("Failed to parse {}", library_path.display()))?,
+            ),
+            //Faces using its materials fall back to the default material, which records them
+            Err(err) => warn!(
+                "Failed to read material library {}: {}",
+                library_path.display(),
+                err
+            ),
+        }
+    }
+    info!("File Loading: {}", now.elapsed().as_secs_f32());
+
+    let samplers = GltfSamplers {
+        default: create_default_sampler(device)?,
+        samplers: Vec::new(),
+    };
+
+    let now = std::time::Instant::now();
+    let mut textures = ObjTextures {
+        texture_cache,
+        images: Vec::new(),
+        loaded: HashMap::new(),
+        error_image: None,
+    };
+    let mut materials = obj_materials
+        .iter()
+        .map(|obj_material| {
+            create_material(
+                device,
+                material_palette,
+                &mut textures,
+                &samplers,
+                obj_material,
+            )
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    info!("Image Convert/Upload: {}", now.elapsed().as_secs_f32());
+
+    //Faces without a usemtl or with a missing material share a plain white material
+    let mut default_material = None;
+    let mut missing_materials = HashSet::new();
+
+    let now = std::time::Instant::now();
+    let mut meshes = Vec::with_capacity(data.objects.len());
+    let mut mesh_nodes = Vec::with_capacity(data.objects.len());
+    let mut batcher = StaticBatcher::new(static_batching == StaticBatching::Lightmapped);
+    for object in data.objects.iter() {
+        let mut primitives = Vec::with_capacity(object.groups.len());
+        let mut primitive_materials = Vec::with_capacity(object.groups.len());
+        let mut batch_parts = Vec::new();
+
+        for group in object.groups.iter() {
+            let found_material = group
+                .material
+                .as_ref()
+                .and_then(|name| materials.iter().position(|material| &material.name == name));
+            if let (Some(name), None) = (&group.material, found_material) {
+                if missing_materials.insert(name.clone()) {
+                    fallback::record(FallbackKind::Material, name, "not in any material library");
+                }
+            }
+
+            let material_index = match found_material {
+                Some(index) => index,
+                None => *match &mut default_material {
+                    Some(index) => index,
+                    None => {
+                        let name = format!(
+                            "{} Default",
+                            path.file_stem().unwrap_or_default().to_string_lossy()
+                        );
+                        materials.push(create_material(
+                            device,
+                            material_palette,
+                            &mut textures,
+                            &samplers,
+                            &ObjMaterial::new(&name),
+                        )?);
+                        default_material.insert(materials.len() - 1)
+                    }
+                },
+            };
+
+            let primitive_data = build_primitive_data(&data, &group.triangles);
+            primitives.push(primitive_cache.get_or_create(device, &primitive_data)?);
+            primitive_materials.push(material_index);
+            if static_batching != StaticBatching::Off {
+                batch_parts.push((primitive_data, material_index));
+            }
+        }
+
+        //Same rule as the gltf loader, objects with a blended material are left as they are
+        let blended = primitive_materials
+            .iter()
+            .any(|&material| materials[material].alpha_blending);
+        if static_batching != StaticBatching::Off && !blended {
+            for (primitive_data, material_index) in batch_parts.iter() {
+                batcher.add(&Mat4::IDENTITY, primitive_data, *material_index);
+            }
+        } else {
+            mesh_nodes.push(GltfNode {
+                transform: Mat4::IDENTITY,
+                mesh_index: meshes.len(),
+                primitive_materials,
+                lightmap: None,
+                skin: None,
+            });
+        }
+        meshes.push(Mesh::new(object.name.clone(), primitives));
+    }
+    info!("Mesh Convert/Upload: {}", now.elapsed().as_secs_f32());
+
+    if static_batching != StaticBatching::Off {
+        let primitive_count = batcher.primitive_count();
+        let batch_count = batcher.build(device, &mut meshes, &mut mesh_nodes)?;
+        info!(
+            "Merged {} static primitives into {} batches",
+            primitive_count, batch_count
+        );
+    }
+
+    Ok(GltfScene {
+        meshes,
+        images: textures.images,
+        samplers,
+        materials,
+        mesh_nodes,
+        skins: Vec::new(),
+    })
+}
@@ -22,6 +22,11 @@ pub struct PhysicsWorld {
     impulse_joint_set: ImpulseJointSet,
     multibody_joint_set: MultibodyJointSet,
     ccd_solver: CCDSolver,
+
+    event_handler: ChannelEventCollector,
+    collision_receiver: rapier3d::crossbeam::channel::Receiver<CollisionEvent>,
+    //Contact force events aren't enabled on any collider, the channel is only needed by the collector
+    _contact_force_receiver: rapier3d::crossbeam::channel::Receiver<ContactForceEvent>,
 }
 
 impl PhysicsWorld {
@@ -39,6 +44,11 @@ impl PhysicsWorld {
         let multibody_joint_set = MultibodyJointSet::new();
         let ccd_solver = CCDSolver::new();
 
+        let (collision_sender, collision_receiver) = rapier3d::crossbeam::channel::unbounded();
+        let (contact_force_sender, contact_force_receiver) =
+            rapier3d::crossbeam::channel::unbounded();
+        let event_handler = ChannelEventCollector::new(collision_sender, contact_force_sender);
+
         Self {
             rigid_body_set,
             collider_set,
@@ -51,6 +61,9 @@ impl PhysicsWorld {
             impulse_joint_set,
             multibody_joint_set,
             ccd_solver,
+            event_handler,
+            collision_receiver,
+            _contact_force_receiver: contact_force_receiver,
         }
     }
 
@@ -58,7 +71,6 @@ impl PhysicsWorld {
         let gravity = vector![0.0, -9.8, 0.0];
 
         let physics_hooks = ();
-        let event_handler = ();
 
         self.integration_parameters.dt = delta_time as Real;
 
@@ -75,13 +87,41 @@ impl PhysicsWorld {
             &mut self.ccd_solver,
             None,
             &physics_hooks,
-            &event_handler,
+            &self.event_handler,
         );
 
         self.query_pipeline
             .update(&self.rigid_body_set, &self.collider_set);
     }
 
+    /// Collisions that started or stopped since the last call
+    pub fn drain_collision_events(&mut self) -> impl Iterator<Item = CollisionEvent> + '_ {
+        self.collision_receiver.try_iter()
+    }
+
+    /// Closest hit point along a normalized direction, against the colliders as of the last step
+    pub fn ray_cast(
+        &self,
+        origin: glam::Vec3,
+        direction: glam::Vec3,
+        max_distance: f32,
+    ) -> Option<glam::Vec3> {
+        let ray = Ray::new(
+            point![origin.x, origin.y, origin.z],
+            vector![direction.x, direction.y, direction.z],
+        );
+        self.query_pipeline
+            .cast_ray(
+                &self.rigid_body_set,
+                &self.collider_set,
+                &ray,
+                max_distance,
+                true,
+                QueryFilter::default(),
+            )
+            .map(|(_, distance)| origin + direction * distance)
+    }
+
     pub fn add_rigid_body(&mut self, transform: &Transform) -> RigidBodyHandle {
         self.rigid_body_set.insert(
             RigidBodyBuilder::dynamic()
@@ -141,6 +181,7 @@ impl PhysicsWorld {
             )
             .scaled_axis(),
         )
+        .active_events(ActiveEvents::COLLISION_EVENTS)
         .build();
 
         if let Some(rigid_body_handle) = rigid_body_handle {
@@ -1,8 +1,9 @@
 use crate::input::{ButtonState, InputEventReceiver, StaticString};
+use crate::input_system::MouseCapture;
 use crate::platform::WindowEventReceiver;
 use anyhow::anyhow;
 use sdl2::event::{Event, WindowEvent};
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::mouse::MouseButton;
 use std::collections::HashMap;
 
@@ -115,10 +116,20 @@ pub struct Sdl2Platform {
     pub(crate) window: sdl2::video::Window,
 
     should_quit: bool,
+    /// False while minimized or hidden, there's nothing to present to
+    window_visible: bool,
+    window_focused: bool,
 
     // Move binding into App at some point
     mouse_captured: bool,
+    /// Last `MouseCapture::relative` the app asked for, only a change locks the pointer without a click
+    relative_mouse_requested: bool,
+    cursor_visible: bool,
+    /// None until the hint is first set
+    raw_mouse_input: Option<bool>,
     key_bindings: HashMap<Keycode, ButtonBinding>,
+    /// Used instead of `key_bindings` while either ctrl key is held
+    ctrl_key_bindings: HashMap<Keycode, ButtonBinding>,
 
     mouse_button_bindings: HashMap<MouseButton, ButtonBinding>,
 
@@ -218,11 +229,189 @@ impl Sdl2Platform {
 
         key_bindings.insert(Keycode::Space, ButtonBinding::Button("player_jump"));
         key_bindings.insert(Keycode::LShift, ButtonBinding::Button("player_move_sprint"));
+        key_bindings.insert(
+            Keycode::F2,
+            ButtonBinding::Button("debug_toggle_path_tracer"),
+        );
+        key_bindings.insert(Keycode::F1, ButtonBinding::Button("debug_toggle_stats"));
+        key_bindings.insert(
+            Keycode::Backquote,
+            ButtonBinding::Button("debug_toggle_console"),
+        );
+        key_bindings.insert(
+            Keycode::Tab,
+            ButtonBinding::Button("debug_console_cycle_category"),
+        );
+        key_bindings.insert(
+            Keycode::Z,
+            ButtonBinding::Button("debug_console_cycle_level"),
+        );
+        key_bindings.insert(Keycode::P, ButtonBinding::Button("debug_toggle_pause"));
+        key_bindings.insert(Keycode::Period, ButtonBinding::Button("debug_step_frame"));
+        key_bindings.insert(
+            Keycode::Comma,
+            ButtonBinding::Button("debug_toggle_slow_motion"),
+        );
+        key_bindings.insert(Keycode::F3, ButtonBinding::Button("editor_toggle_grid"));
+        key_bindings.insert(
+            Keycode::F4,
+            ButtonBinding::Button("editor_toggle_axis_gizmo"),
+        );
+        key_bindings.insert(Keycode::F, ButtonBinding::Button("editor_frame_selected"));
+        key_bindings.insert(Keycode::F5, ButtonBinding::Button("editor_add_bookmark"));
+        key_bindings.insert(Keycode::F6, ButtonBinding::Button("editor_next_bookmark"));
+        key_bindings.insert(
+            Keycode::F7,
+            ButtonBinding::Button("editor_previous_bookmark"),
+        );
+        key_bindings.insert(
+            Keycode::F8,
+            ButtonBinding::Button("editor_toggle_viewport_layout"),
+        );
+        key_bindings.insert(
+            Keycode::Q,
+            ButtonBinding::Button("editor_cycle_aspect_lock"),
+        );
+        key_bindings.insert(Keycode::F9, ButtonBinding::Button("editor_open_material"));
+        key_bindings.insert(Keycode::N, ButtonBinding::Button("editor_build_navmesh"));
+        key_bindings.insert(Keycode::F10, ButtonBinding::Button("material_save"));
+        key_bindings.insert(
+            Keycode::F11,
+            ButtonBinding::Button("material_compile_shader_graph"),
+        );
+        key_bindings.insert(Keycode::F12, ButtonBinding::Button("material_cycle_shader"));
+        key_bindings.insert(
+            Keycode::LeftBracket,
+            ButtonBinding::Button("material_previous_parameter"),
+        );
+        key_bindings.insert(
+            Keycode::RightBracket,
+            ButtonBinding::Button("material_next_parameter"),
+        );
+        key_bindings.insert(
+            Keycode::Minus,
+            ButtonBinding::Button("material_decrease_parameter"),
+        );
+        key_bindings.insert(
+            Keycode::Equals,
+            ButtonBinding::Button("material_increase_parameter"),
+        );
+        key_bindings.insert(Keycode::L, ButtonBinding::Button("post_cycle_lut"));
+        key_bindings.insert(
+            Keycode::K,
+            ButtonBinding::Button("post_cycle_secondary_lut"),
+        );
+        key_bindings.insert(Keycode::J, ButtonBinding::Button("post_cycle_lut_blend"));
+        key_bindings.insert(Keycode::V, ButtonBinding::Button("post_toggle_vignette"));
+        key_bindings.insert(
+            Keycode::C,
+            ButtonBinding::Button("post_toggle_chromatic_aberration"),
+        );
+        key_bindings.insert(Keycode::G, ButtonBinding::Button("post_toggle_film_grain"));
+        key_bindings.insert(Keycode::B, ButtonBinding::Button("post_toggle_dithering"));
+        key_bindings.insert(
+            Keycode::H,
+            ButtonBinding::Button("render_cycle_effect_scale"),
+        );
+        key_bindings.insert(Keycode::U, ButtonBinding::Button("render_cycle_upscaler"));
+        key_bindings.insert(Keycode::O, ButtonBinding::Button("render_toggle_particles"));
+        key_bindings.insert(Keycode::T, ButtonBinding::Button("cloth_toggle_sample"));
+        key_bindings.insert(Keycode::Y, ButtonBinding::Button("cloth_cycle_pinning"));
+        key_bindings.insert(Keycode::I, ButtonBinding::Button("cloth_cycle_wind"));
+        key_bindings.insert(Keycode::E, ButtonBinding::Button("render_toggle_water"));
+        key_bindings.insert(Keycode::R, ButtonBinding::Button("foliage_toggle_sample"));
+        key_bindings.insert(Keycode::M, ButtonBinding::Button("foliage_paint"));
+        key_bindings.insert(Keycode::X, ButtonBinding::Button("render_toggle_voxel_gi"));
+        key_bindings.insert(
+            Keycode::Num1,
+            ButtonBinding::Button("editor_toggle_mouse_lock"),
+        );
+        key_bindings.insert(
+            Keycode::Num2,
+            ButtonBinding::Button("editor_toggle_2d_camera"),
+        );
+        key_bindings.insert(
+            Keycode::Num3,
+            ButtonBinding::Button("camera_2d_toggle_pixel_perfect"),
+        );
+        key_bindings.insert(
+            Keycode::Num4,
+            ButtonBinding::Button("render_toggle_mirror_sample"),
+        );
+        key_bindings.insert(Keycode::Delete, ButtonBinding::Button("editor_delete"));
+        key_bindings.insert(
+            Keycode::Backspace,
+            ButtonBinding::Button("search_backspace"),
+        );
+        key_bindings.insert(Keycode::Return, ButtonBinding::Button("search_select"));
+        key_bindings.insert(
+            Keycode::Backslash,
+            ButtonBinding::Button("texture_preview_close"),
+        );
+        key_bindings.insert(
+            Keycode::Quote,
+            ButtonBinding::Button("texture_preview_brighter"),
+        );
+        key_bindings.insert(
+            Keycode::Semicolon,
+            ButtonBinding::Button("texture_preview_darker"),
+        );
+        key_bindings.insert(
+            Keycode::Slash,
+            ButtonBinding::Button("texture_preview_cycle_grid"),
+        );
 
-        let mouse_button_bindings = HashMap::new();
-
-        //TODO: allow as setting
-        //const HINT_MOUSE_RELATIVE_SYSTEM_SCALE: &str = "SDL_HINT_MOUSE_RELATIVE_SYSTEM_SCALE"; // bool
+        let mut ctrl_key_bindings = HashMap::new();
+        ctrl_key_bindings.insert(Keycode::C, ButtonBinding::Button("editor_copy"));
+        ctrl_key_bindings.insert(Keycode::X, ButtonBinding::Button("editor_cut"));
+        ctrl_key_bindings.insert(Keycode::V, ButtonBinding::Button("editor_paste"));
+        ctrl_key_bindings.insert(Keycode::D, ButtonBinding::Button("editor_duplicate"));
+        ctrl_key_bindings.insert(Keycode::A, ButtonBinding::Button("editor_rotate_selection"));
+        ctrl_key_bindings.insert(Keycode::Q, ButtonBinding::Button("editor_grow_selection"));
+        ctrl_key_bindings.insert(Keycode::M, ButtonBinding::Button("editor_spawn_shape"));
+        ctrl_key_bindings.insert(Keycode::Z, ButtonBinding::Button("editor_undo"));
+        ctrl_key_bindings.insert(Keycode::Y, ButtonBinding::Button("editor_redo"));
+        ctrl_key_bindings.insert(Keycode::F, ButtonBinding::Button("editor_search"));
+        ctrl_key_bindings.insert(Keycode::F8, ButtonBinding::Button("editor_add_split_view"));
+        ctrl_key_bindings.insert(
+            Keycode::I,
+            ButtonBinding::Button("debug_toggle_buffer_inspector"),
+        );
+        ctrl_key_bindings.insert(
+            Keycode::RightBracket,
+            ButtonBinding::Button("inspector_next_buffer"),
+        );
+        ctrl_key_bindings.insert(
+            Keycode::LeftBracket,
+            ButtonBinding::Button("inspector_previous_buffer"),
+        );
+        ctrl_key_bindings.insert(Keycode::P, ButtonBinding::Button("inspector_dump"));
+        ctrl_key_bindings.insert(Keycode::N, ButtonBinding::Button("animation_next_state"));
+        ctrl_key_bindings.insert(Keycode::W, ButtonBinding::Button("navmesh_walk_selection"));
+        ctrl_key_bindings.insert(
+            Keycode::H,
+            ButtonBinding::Button("animation_toggle_ik_debug"),
+        );
+        ctrl_key_bindings.insert(
+            Keycode::O,
+            ButtonBinding::Button("render_toggle_particle_blend"),
+        );
+        ctrl_key_bindings.insert(Keycode::R, ButtonBinding::Button("editor_toggle_recording"));
+        ctrl_key_bindings.insert(Keycode::T, ButtonBinding::Button("sky_toggle_day_cycle"));
+        ctrl_key_bindings.insert(Keycode::Comma, ButtonBinding::Button("sky_earlier"));
+        ctrl_key_bindings.insert(Keycode::Period, ButtonBinding::Button("sky_later"));
+        ctrl_key_bindings.insert(Keycode::E, ButtonBinding::Button("probe_place"));
+        ctrl_key_bindings.insert(Keycode::B, ButtonBinding::Button("probe_rebake"));
+        ctrl_key_bindings.insert(Keycode::G, ButtonBinding::Button("post_volume_toggle"));
+        ctrl_key_bindings.insert(Keycode::J, ButtonBinding::Button("cell_place"));
+        ctrl_key_bindings.insert(Keycode::K, ButtonBinding::Button("cell_place_portal"));
+        ctrl_key_bindings.insert(Keycode::L, ButtonBinding::Button("cell_toggle_debug"));
+        ctrl_key_bindings.insert(Keycode::U, ButtonBinding::Button("cell_toggle_culling"));
+        ctrl_key_bindings.insert(Keycode::Equals, ButtonBinding::Button("camera_2d_zoom_in"));
+        ctrl_key_bindings.insert(Keycode::Minus, ButtonBinding::Button("camera_2d_zoom_out"));
+
+        let mut mouse_button_bindings = HashMap::new();
+        mouse_button_bindings.insert(MouseButton::Right, ButtonBinding::Button("editor_select"));
 
         Ok(Self {
             context,
@@ -234,8 +423,15 @@ impl Sdl2Platform {
 
             window,
             should_quit: false,
+            window_visible: true,
+            window_focused: true,
             mouse_captured: false,
+            //Starts requested so an app that wants the pointer locked waits for a click instead of grabbing it on launch
+            relative_mouse_requested: true,
+            cursor_visible: true,
+            raw_mouse_input: None,
             key_bindings,
+            ctrl_key_bindings,
             mouse_button_bindings,
             mouse_moved: false,
             mouse_axis_x_binding: Some(MouseAxisBinding {
@@ -255,13 +451,21 @@ impl Sdl2Platform {
         self.should_quit
     }
 
+    pub fn is_window_focused(&self) -> bool {
+        self.window_focused
+    }
+
+    /// Some platforms keep a minimized window's size, others shrink it to zero, so both are checked
+    pub fn is_window_visible(&self) -> bool {
+        let (width, height) = self.window.drawable_size();
+        self.window_visible && width > 0 && height > 0
+    }
+
     pub fn process_events<T: WindowEventReceiver + InputEventReceiver>(
         &mut self,
         app: &mut T,
     ) -> anyhow::Result<()> {
-        if !app.requests_mouse_capture() {
-            self.capture_mouse(false);
-        }
+        self.apply_mouse_capture(app.mouse_capture());
 
         // Clear movement from last frame
         if self.mouse_moved {
@@ -276,22 +480,28 @@ impl Sdl2Platform {
                 }
 
                 Event::KeyDown {
-                    keycode, repeat, ..
+                    keycode,
+                    keymod,
+                    repeat,
+                    ..
                 } => {
                     if !repeat {
                         // Escape should always free mouse, hardcoded here so that game bad logic can't hold the mouse hostage
                         if keycode == Some(Keycode::Escape) {
                             self.capture_mouse(false);
                         } else {
-                            self.process_key_event(app, keycode, ButtonState::Pressed);
+                            self.process_key_event(app, keycode, keymod, ButtonState::Pressed);
                         }
                     }
                 }
                 Event::KeyUp {
-                    keycode, repeat, ..
+                    keycode,
+                    keymod,
+                    repeat,
+                    ..
                 } => {
                     if !repeat {
-                        self.process_key_event(app, keycode, ButtonState::Released);
+                        self.process_key_event(app, keycode, keymod, ButtonState::Released);
                     }
                 }
 
@@ -300,7 +510,7 @@ impl Sdl2Platform {
                 }
 
                 Event::MouseButtonDown { mouse_btn, .. } => {
-                    if app.requests_mouse_capture()
+                    if self.relative_mouse_requested
                         && !self.window.mouse_grab()
                         && mouse_btn == MouseButton::Left
                     {
@@ -313,7 +523,7 @@ impl Sdl2Platform {
                 }
                 Event::MouseButtonUp { mouse_btn, .. } => {
                     if self.mouse_captured {
-                        self.process_mouse_button_event(app, mouse_btn, ButtonState::Pressed);
+                        self.process_mouse_button_event(app, mouse_btn, ButtonState::Released);
                     }
                 }
                 Event::MouseMotion { xrel, yrel, .. } => {
@@ -435,6 +645,32 @@ impl Sdl2Platform {
                 } => {
                     app.on_window_size_changed([width as u32, height as u32])?;
                 }
+                Event::Window {
+                    win_event: WindowEvent::Minimized | WindowEvent::Hidden,
+                    ..
+                } => {
+                    debug!("sdl2 window hidden");
+                    self.window_visible = false;
+                }
+                Event::Window {
+                    win_event:
+                        WindowEvent::Restored
+                        | WindowEvent::Maximized
+                        | WindowEvent::Shown
+                        | WindowEvent::Exposed,
+                    ..
+                } if !self.window_visible => {
+                    debug!("sdl2 window shown");
+                    self.window_visible = true;
+                }
+                Event::Window {
+                    win_event: WindowEvent::FocusGained,
+                    ..
+                } => self.window_focused = true,
+                Event::Window {
+                    win_event: WindowEvent::FocusLost,
+                    ..
+                } => self.window_focused = false,
                 _ => {}
             }
         }
@@ -442,6 +678,33 @@ impl Sdl2Platform {
         Ok(())
     }
 
+    fn apply_mouse_capture(&mut self, mouse_capture: MouseCapture) {
+        if Some(mouse_capture.raw_input) != self.raw_mouse_input {
+            //Only affects relative mode, 0 skips the system acceleration curve
+            const HINT_MOUSE_RELATIVE_SYSTEM_SCALE: &str = "SDL_MOUSE_RELATIVE_SYSTEM_SCALE";
+            let system_scale = if mouse_capture.raw_input { "0" } else { "1" };
+            if !sdl2::hint::set(HINT_MOUSE_RELATIVE_SYSTEM_SCALE, system_scale) {
+                warn!("Raw mouse input isn't supported on this platform");
+            }
+            self.raw_mouse_input = Some(mouse_capture.raw_input);
+        }
+
+        if mouse_capture.relative != self.relative_mouse_requested {
+            self.capture_mouse(mouse_capture.relative);
+            self.relative_mouse_requested = mouse_capture.relative;
+        } else if !mouse_capture.relative {
+            self.capture_mouse(false);
+        }
+
+        //Relative mode hides the cursor on its own
+        if mouse_capture.cursor_visible != self.cursor_visible {
+            self.context
+                .mouse()
+                .show_cursor(mouse_capture.cursor_visible);
+            self.cursor_visible = mouse_capture.cursor_visible;
+        }
+    }
+
     pub fn capture_mouse(&mut self, capture: bool) {
         // Don't re capture/free mouse
         if capture != self.mouse_captured {
@@ -486,13 +749,34 @@ impl Sdl2Platform {
         &mut self,
         app: &mut T,
         keycode: Option<Keycode>,
+        keymod: Mod,
         state: ButtonState,
     ) {
-        if let Some(keycode) = keycode {
-            if let Some(binding) = self.key_bindings.get(&keycode).cloned() {
-                self.process_button_event(app, binding, state);
+        let Some(keycode) = keycode else {
+            return;
+        };
+
+        let ctrl_held = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+        let ctrl_binding = self.ctrl_key_bindings.get(&keycode).cloned();
+        if let (true, Some(binding)) = (ctrl_held, ctrl_binding) {
+            //Ctrl is also a movement key, let go of it so the chord doesn't keep moving the camera
+            if state.is_down() {
+                for ctrl_keycode in [Keycode::LCtrl, Keycode::RCtrl] {
+                    if let Some(ctrl_binding) = self.key_bindings.get(&ctrl_keycode).cloned() {
+                        self.process_button_event(app, ctrl_binding, ButtonState::Released);
+                    }
+                }
+            }
+            self.process_button_event(app, binding, state);
+            //Still release the plain binding, the key may have been pressed before ctrl was
+            if state.is_down() {
+                return;
             }
         }
+
+        if let Some(binding) = self.key_bindings.get(&keycode).cloned() {
+            self.process_button_event(app, binding, state);
+        }
     }
 
     pub fn process_button_event<T: InputEventReceiver>(
@@ -0,0 +1,111 @@
+use crate::scene::post_effects::PostEffectSettings;
+use crate::scene::scene_renderer::RenderMode;
+use crate::scene::upscaler::UpscalerMode;
+use crate::viewport::AspectRatioLock;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Renderer options loaded from `render_settings.toml`, anything missing from the file keeps its default.
+/// There are no shadow maps or MSAA in the renderer yet, so there is nothing to configure for them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    /// Off presents with mailbox, or immediate if the surface doesn't support it
+    pub vsync: bool,
+    pub render_mode: RenderMode,
+    pub upscaler: UpscalerMode,
+    pub dithering: bool,
+    pub voxel_gi: bool,
+    pub particles: bool,
+    /// Atmospheric sky, its sun also lights the fog, voxel gi and path tracer
+    pub sky: bool,
+    /// Light shafts and a lens flare from the sky's sun
+    pub lens_effects: bool,
+    pub show_grid: bool,
+    pub show_axis_gizmo: bool,
+    pub show_stats: bool,
+    /// `"off"`, `"widescreen"`, `"standard"` or `{ custom = 2.39 }`
+    pub aspect_lock: AspectRatioLock,
+    /// Replaces the scene's post effects while set
+    pub post_effects: Option<PostEffectSettings>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            vsync: true,
+            render_mode: RenderMode::Raster,
+            upscaler: UpscalerMode::Off,
+            dithering: true,
+            voxel_gi: false,
+            particles: false,
+            sky: true,
+            lens_effects: true,
+            show_grid: true,
+            show_axis_gizmo: true,
+            show_stats: false,
+            aspect_lock: AspectRatioLock::Off,
+            post_effects: None,
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read render settings {}", path.display()))?;
+        toml::from_str(&text)
+            .with_context(|| format!("Failed to parse render settings {}", path.display()))
+    }
+}
+
+/// Polls the settings file's modified time, there is no file watcher dependency so this is checked a few times a second
+pub struct RenderSettingsWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    poll_timer: f32,
+}
+
+impl RenderSettingsWatcher {
+    const POLL_INTERVAL: f32 = 0.5;
+
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            modified: None,
+            poll_timer: 0.0,
+        }
+    }
+
+    /// Returns the new settings when the file was created or changed since the last poll.
+    /// A file that fails to parse is logged and skipped until it changes again
+    pub fn poll(&mut self, delta_time: f32) -> Option<RenderSettings> {
+        self.poll_timer -= delta_time;
+        if self.poll_timer > 0.0 {
+            return None;
+        }
+        self.poll_timer = Self::POLL_INTERVAL;
+
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok()?;
+        if self.modified == Some(modified) {
+            return None;
+        }
+        self.modified = Some(modified);
+
+        match RenderSettings::load(&self.path) {
+            Ok(settings) => {
+                info!("Loaded render settings from {}", self.path.display());
+                Some(settings)
+            }
+            Err(err) => {
+                error!("{:#}", err);
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::crash_report;
+use anyhow::anyhow;
+use neptune_vulkan::basic_render_graph_builder::BasicRenderGraphBuilder;
+use neptune_vulkan::render_graph_builder::RenderGraphBuilderTrait;
+use neptune_vulkan::{Device, VulkanError};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+
+/// Everything the render thread needs to finish a frame, built by the main thread
+struct FramePacket {
+    render_graph_builder: BasicRenderGraphBuilder,
+}
+
+/// Compiles and submits render graphs on their own thread, so the next frame's update runs while the last one is submitted.
+/// Double buffered: one packet is being submitted while the main thread works on the next.
+/// Any device access from the main thread first waits for the packet in flight,
+/// so resources are never created or destroyed under a graph that is still being compiled
+pub struct RenderThread {
+    device: Arc<Mutex<Device>>,
+    packet_sender: Option<SyncSender<FramePacket>>,
+    result_receiver: Receiver<Result<(), VulkanError>>,
+    frame_pending: bool,
+    last_error: Option<anyhow::Error>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RenderThread {
+    pub fn new(device: Device) -> anyhow::Result<Self> {
+        let device = Arc::new(Mutex::new(device));
+        let (packet_sender, packet_receiver) = sync_channel::<FramePacket>(1);
+        let (result_sender, result_receiver) = sync_channel(1);
+
+        let thread_device = device.clone();
+        let thread = std::thread::Builder::new()
+            .name("Render Thread".to_string())
+            .spawn(move || {
+                while let Ok(packet) = packet_receiver.recv() {
+                    let render_graph = packet.render_graph_builder.build();
+
+                    let mut device = thread_device.lock().unwrap();
+                    let result = device.submit_graph(render_graph);
+                    if result.is_err() {
+                        crash_report::record_gpu_state(&device.breadcrumbs());
+                    }
+                    drop(device);
+
+                    if result_sender.send(result).is_err() {
+                        break;
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            device,
+            packet_sender: Some(packet_sender),
+            result_receiver,
+            frame_pending: false,
+            last_error: None,
+            thread: Some(thread),
+        })
+    }
+
+    /// Waits for the frame in flight to be submitted, then locks the device for the main thread
+    pub fn device(&mut self) -> MutexGuard<'_, Device> {
+        self.wait_for_submit();
+        self.device.lock().unwrap()
+    }
+
+    /// Hands the frame over to the render thread, returns the error of the previous frame if its submit failed
+    pub fn submit(&mut self, render_graph_builder: BasicRenderGraphBuilder) -> anyhow::Result<()> {
+        self.wait_for_submit();
+        if let Some(err) = self.last_error.take() {
+            return Err(err);
+        }
+
+        let packet_sender = self.packet_sender.as_ref().unwrap();
+        if packet_sender
+            .send(FramePacket {
+                render_graph_builder,
+            })
+            .is_err()
+        {
+            return Err(anyhow!("Render thread stopped"));
+        }
+        self.frame_pending = true;
+        Ok(())
+    }
+
+    fn wait_for_submit(&mut self) {
+        if !self.frame_pending {
+            return;
+        }
+        self.frame_pending = false;
+
+        match self.result_receiver.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => self.last_error = Some(err.into()),
+            Err(_) => self.last_error = Some(anyhow!("Render thread stopped")),
+        }
+    }
+}
+
+impl Drop for RenderThread {
+    fn drop(&mut self) {
+        self.wait_for_submit();
+        drop(self.packet_sender.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,391 @@
+use crate::mesh::{
+    BoundingBox, BoundingSphere, IndexBuffer, Primitive, PrimitiveGeometry, VertexAttributes,
+};
+use crate::scene::scene_renderer::slice_to_bytes_unsafe;
+use crate::transform::Transform;
+use anyhow::Context;
+use glam::{Vec2, Vec3, Vec4};
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph::QueueType;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, ComputePassBuilder, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{BufferHandle, BufferUsage, ComputePipelineHandle, Device};
+use std::sync::Arc;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ClothPinning {
+    /// Every particle on the top row is held in place, like a banner
+    #[default]
+    TopEdge,
+    /// Only the two top corners are held, like a hammock
+    TopCorners,
+    /// Nothing is held, the cloth falls
+    None,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClothSettings {
+    pub pinning: ClothPinning,
+    pub gravity: Vec3,
+    /// Wind velocity, only the part along the cloth's normal pushes it
+    pub wind: Vec3,
+    /// How much the wind gusts, 0 is a constant wind
+    pub wind_turbulence: f32,
+    /// Fraction of the velocity lost every step
+    pub damping: f32,
+    /// 0 to 1, how much of each constraint's error is corrected per iteration
+    pub stiffness: f32,
+    /// Constraint passes per frame, more makes the cloth less stretchy
+    pub iterations: u32,
+}
+
+impl Default for ClothSettings {
+    fn default() -> Self {
+        Self {
+            pinning: ClothPinning::TopEdge,
+            gravity: Vec3::new(0.0, -9.8, 0.0),
+            wind: Vec3::new(3.0, 0.0, 2.0),
+            wind_turbulence: 0.5,
+            damping: 0.01,
+            stiffness: 1.0,
+            iterations: 16,
+        }
+    }
+}
+
+/// Matches ClothData in the cloth compute shaders
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct ClothData {
+    /// w: delta time
+    gravity: Vec4,
+    /// w: turbulence
+    wind: Vec4,
+    /// x: damping, y: stiffness, z: time
+    simulation: Vec4,
+    /// x: width, y: height, z: pinning, w: 1 to reset to the rest pose
+    grid: [u32; 4],
+    /// xy: distance between neighboring particles
+    spacing: Vec4,
+}
+
+/// Cloth grid simulated with position based dynamics in compute passes.
+/// The last pass writes the mesh's vertex buffers directly, so the scene draws it like any other primitive.
+/// Everything is simulated in the instance's local space, gravity and wind are rotated into it every frame
+pub struct Cloth {
+    pub settings: ClothSettings,
+
+    resolution: [u32; 2],
+    size: Vec2,
+
+    integrate_pipeline: ComputePipelineHandle,
+    constraint_pipeline: ComputePipelineHandle,
+    vertex_pipeline: ComputePipelineHandle,
+
+    cloth_data_buffer: BufferHandle,
+    position_buffers: [BufferHandle; 2],
+    previous_position_buffer: BufferHandle,
+    primitive: Arc<Primitive>,
+
+    /// Which of `position_buffers` holds the latest positions
+    current: usize,
+    local_gravity: Vec3,
+    local_wind: Vec3,
+    delta_time: f32,
+    time: f32,
+    reset: bool,
+}
+
+impl Cloth {
+    /// Larger steps make the simulation explode, slow frames just slow the cloth down
+    const MAX_DELTA_TIME: f32 = 1.0 / 30.0;
+
+    /// `resolution` is the particle count along each side, the cloth hangs down from its top edge at the local origin
+    pub fn new(
+        device: &mut Device,
+        name: &str,
+        resolution: [u32; 2],
+        size: Vec2,
+        settings: ClothSettings,
+    ) -> anyhow::Result<Self> {
+        let resolution = [resolution[0].max(2), resolution[1].max(2)];
+        let particle_count = (resolution[0] * resolution[1]) as usize;
+
+        let integrate_pipeline = device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+            code: crate::shader::CLOTH_INTEGRATE_COMP,
+            entry: "main",
+        })?;
+        let constraint_pipeline = device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+            code: crate::shader::CLOTH_CONSTRAINTS_COMP,
+            entry: "main",
+        })?;
+        let vertex_pipeline = device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+            code: crate::shader::CLOTH_VERTICES_COMP,
+            entry: "main",
+        })?;
+
+        let spacing = size / Vec2::new(resolution[0] as f32 - 1.0, resolution[1] as f32 - 1.0);
+        let mut rest_positions = Vec::with_capacity(particle_count);
+        for y in 0..resolution[1] {
+            for x in 0..resolution[0] {
+                rest_positions.push(Vec3::new(
+                    x as f32 * spacing.x - size.x * 0.5,
+                    -(y as f32) * spacing.y,
+                    0.0,
+                ));
+            }
+        }
+        let rest_positions_padded: Vec<Vec4> = rest_positions
+            .iter()
+            .map(|position| position.extend(0.0))
+            .collect();
+
+        let cloth_data_buffer = device
+            .create_buffer_init(
+                &format!("{} Data", name),
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                unsafe { slice_to_bytes_unsafe(&[ClothData::default()]) },
+            )
+            .context("Failed to create cloth data buffer")?;
+
+        let mut create_position_buffer = |name: String| {
+            device
+                .create_buffer_init(
+                    &name,
+                    BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                    MemoryLocation::GpuOnly,
+                    unsafe { slice_to_bytes_unsafe(&rest_positions_padded) },
+                )
+                .context("Failed to create cloth position buffer")
+        };
+        let position_buffers = [
+            create_position_buffer(format!("{} Positions 0", name))?,
+            create_position_buffer(format!("{} Positions 1", name))?,
+        ];
+        let previous_position_buffer =
+            create_position_buffer(format!("{} Previous Positions", name))?;
+
+        //The back side gets its own copy of the vertices with flipped normals, the scene culls back faces
+        let vertex_position_buffer = device.create_buffer(
+            &format!("{} Vertex Positions", name),
+            particle_count * 2 * std::mem::size_of::<Vec3>(),
+            BufferUsage::VERTEX | BufferUsage::STORAGE,
+            MemoryLocation::GpuOnly,
+        )?;
+        let vertex_attributes_buffer = device.create_buffer(
+            &format!("{} Vertex Attributes", name),
+            particle_count * 2 * std::mem::size_of::<VertexAttributes>(),
+            BufferUsage::VERTEX | BufferUsage::STORAGE,
+            MemoryLocation::GpuOnly,
+        )?;
+
+        let front_indices = Self::grid_indices(resolution);
+        let back_offset = particle_count as u32;
+        let mut indices = front_indices.clone();
+        indices.extend(
+            front_indices
+                .chunks_exact(3)
+                .flat_map(|triangle| [triangle[0], triangle[2], triangle[1]])
+                .map(|index| index + back_offset),
+        );
+        let index_buffer = device
+            .create_buffer_init(
+                &format!("{} Indices", name),
+                BufferUsage::INDEX | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                unsafe { slice_to_bytes_unsafe(&indices) },
+            )
+            .context("Failed to create cloth index buffer")?;
+
+        //Pinned cloth can swing anywhere within its own size of the top edge, unpinned cloth falls out of these bounds and gets culled
+        let reach = size.length();
+        let bounding_box = BoundingBox {
+            min: Vec3::splat(-reach),
+            max: Vec3::splat(reach),
+        };
+
+        let primitive = Arc::new(Primitive {
+            bounding_box,
+            bounding_sphere: BoundingSphere::from_box(&bounding_box),
+            geometry: Arc::new(PrimitiveGeometry {
+                positions: rest_positions,
+                indices: front_indices,
+            }),
+            vertex_count: particle_count * 2,
+            vertex_offset: 0,
+            position_buffer: vertex_position_buffer,
+            attributes_buffer: vertex_attributes_buffer,
+            skinning_buffer: None,
+            index_buffer: Some(IndexBuffer {
+                buffer: index_buffer,
+                count: indices.len() as u32,
+            }),
+        });
+
+        Ok(Self {
+            settings,
+            resolution,
+            size,
+            integrate_pipeline,
+            constraint_pipeline,
+            vertex_pipeline,
+            cloth_data_buffer,
+            position_buffers,
+            previous_position_buffer,
+            primitive,
+            current: 0,
+            local_gravity: Vec3::ZERO,
+            local_wind: Vec3::ZERO,
+            delta_time: 0.0,
+            time: 0.0,
+            reset: true,
+        })
+    }
+
+    /// Counter clockwise seen from +z, the front of the cloth
+    fn grid_indices(resolution: [u32; 2]) -> Vec<u32> {
+        let [width, height] = resolution;
+        let mut indices = Vec::with_capacity(((width - 1) * (height - 1) * 6) as usize);
+        for y in 0..(height - 1) {
+            for x in 0..(width - 1) {
+                let top_left = y * width + x;
+                let top_right = top_left + 1;
+                let bottom_left = top_left + width;
+                let bottom_right = bottom_left + 1;
+                indices.extend_from_slice(&[
+                    top_left,
+                    bottom_left,
+                    bottom_right,
+                    top_left,
+                    bottom_right,
+                    top_right,
+                ]);
+            }
+        }
+        indices
+    }
+
+    pub fn destroy(self, device: &mut Device) {
+        device.destroy_buffer(self.cloth_data_buffer);
+        for buffer in self.position_buffers {
+            device.destroy_buffer(buffer);
+        }
+        device.destroy_buffer(self.previous_position_buffer);
+        device.destroy_buffer(self.primitive.position_buffer);
+        device.destroy_buffer(self.primitive.attributes_buffer);
+        if let Some(index_buffer) = &self.primitive.index_buffer {
+            device.destroy_buffer(index_buffer.buffer);
+        }
+    }
+
+    /// The primitive's vertex buffers are rewritten by the simulation every frame, so it never needs to be swapped
+    pub fn primitive(&self) -> Arc<Primitive> {
+        self.primitive.clone()
+    }
+
+    /// Puts the cloth back in its rest pose next frame
+    pub fn reset(&mut self) {
+        self.reset = true;
+    }
+
+    /// Called once per frame with the game time step and the transform of the instance drawing the cloth
+    pub fn update(&mut self, delta_time: f32, transform: &Transform) {
+        self.delta_time = delta_time.min(Self::MAX_DELTA_TIME);
+        self.time += self.delta_time;
+
+        let inverse_rotation = transform.rotation.inverse();
+        let inverse_scale = transform.scale.recip();
+        self.local_gravity = inverse_rotation * self.settings.gravity * inverse_scale;
+        self.local_wind = inverse_rotation * self.settings.wind * inverse_scale;
+    }
+
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        render_graph_builder: &mut T,
+    ) {
+        //Nothing moves while paused, the vertex buffers still hold the last frame
+        if self.delta_time <= 0.0 && !self.reset {
+            return;
+        }
+
+        let settings = &self.settings;
+        let spacing = self.size
+            / Vec2::new(
+                self.resolution[0] as f32 - 1.0,
+                self.resolution[1] as f32 - 1.0,
+            );
+        let cloth_data = ClothData {
+            gravity: self.local_gravity.extend(self.delta_time),
+            wind: self.local_wind.extend(settings.wind_turbulence.max(0.0)),
+            simulation: Vec4::new(
+                settings.damping.clamp(0.0, 1.0),
+                settings.stiffness.clamp(0.0, 1.0),
+                self.time,
+                0.0,
+            ),
+            grid: [
+                self.resolution[0],
+                self.resolution[1],
+                settings.pinning as u32,
+                self.reset as u32,
+            ],
+            spacing: spacing.extend(0.0).extend(0.0),
+        };
+        self.reset = false;
+
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: self.cloth_data_buffer,
+                offset: 0,
+            },
+            std::mem::size_of::<ClothData>(),
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&[cloth_data]) });
+            }),
+        );
+
+        let thread_count = [self.resolution[0] * self.resolution[1], 1, 1];
+
+        //Each pass reads one position buffer and writes the other, so neighbors are never read mid update
+        let mut integrate_pass_builder = ComputePassBuilder::new(
+            "Cloth Integrate Pass",
+            QueueType::Graphics,
+            self.integrate_pipeline,
+        );
+        integrate_pass_builder.read_buffer(self.cloth_data_buffer);
+        integrate_pass_builder.read_buffer(self.position_buffers[self.current]);
+        integrate_pass_builder.write_buffer(self.position_buffers[1 - self.current]);
+        integrate_pass_builder.write_buffer(self.previous_position_buffer);
+        integrate_pass_builder.dispatch_threads(thread_count);
+        integrate_pass_builder.build(render_graph_builder);
+        self.current = 1 - self.current;
+
+        for _ in 0..self.settings.iterations {
+            let mut constraint_pass_builder = ComputePassBuilder::new(
+                "Cloth Constraint Pass",
+                QueueType::Graphics,
+                self.constraint_pipeline,
+            );
+            constraint_pass_builder.read_buffer(self.cloth_data_buffer);
+            constraint_pass_builder.read_buffer(self.position_buffers[self.current]);
+            constraint_pass_builder.write_buffer(self.position_buffers[1 - self.current]);
+            constraint_pass_builder.dispatch_threads(thread_count);
+            constraint_pass_builder.build(render_graph_builder);
+            self.current = 1 - self.current;
+        }
+
+        let mut vertex_pass_builder = ComputePassBuilder::new(
+            "Cloth Vertex Pass",
+            QueueType::Graphics,
+            self.vertex_pipeline,
+        );
+        vertex_pass_builder.read_buffer(self.cloth_data_buffer);
+        vertex_pass_builder.read_buffer(self.position_buffers[self.current]);
+        vertex_pass_builder.write_buffer(self.primitive.position_buffer);
+        vertex_pass_builder.write_buffer(self.primitive.attributes_buffer);
+        vertex_pass_builder.dispatch_threads(thread_count);
+        vertex_pass_builder.build(render_graph_builder);
+    }
+}
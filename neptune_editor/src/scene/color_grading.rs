@@ -0,0 +1,255 @@
+use crate::texture_cache::f32_to_f16;
+use anyhow::Context;
+use glam::Vec3;
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph_builder::RasterDrawCommandBuilder;
+use neptune_vulkan::{
+    vk, AddressMode, Device, FilterMode, ImageDescription3D, ImageHandle, SamplerDescription,
+    SamplerHandle,
+};
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub struct ColorGradingSettings {
+    pub enabled: bool,
+    /// Index into the loaded luts, None leaves the color unchanged
+    pub lut: Option<usize>,
+    pub secondary_lut: Option<usize>,
+    /// 0 is fully `lut`, 1 is fully `secondary_lut`
+    pub blend: f32,
+}
+
+impl Default for ColorGradingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            lut: None,
+            secondary_lut: None,
+            blend: 0.0,
+        }
+    }
+}
+
+/// A 3D lookup table from a .cube file
+pub struct ColorLut {
+    pub name: String,
+    image: ImageHandle,
+}
+
+/// Grades the final image with up to two 3D luts blended together, applied in the last post pass
+pub struct ColorGrading {
+    pub settings: ColorGradingSettings,
+    /// Set each frame from the post process volumes around the camera, takes the place of `secondary_lut` and `blend`
+    pub(crate) volume_lut: Option<(usize, f32)>,
+    luts: Vec<ColorLut>,
+    identity_lut: ImageHandle,
+    lut_sampler: SamplerHandle,
+}
+
+impl ColorGrading {
+    const LUT_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+    pub fn new(device: &mut Device, settings: ColorGradingSettings) -> anyhow::Result<Self> {
+        //Trilinear filtering of a 2x2x2 identity is exact, so it's all an identity lut needs
+        let identity_data: Vec<Vec3> = (0..8)
+            .map(|index| {
+                Vec3::new(
+                    (index & 1) as f32,
+                    ((index >> 1) & 1) as f32,
+                    ((index >> 2) & 1) as f32,
+                )
+            })
+            .collect();
+        let identity_lut = create_lut_image(device, "Identity Lut", 2, &identity_data)?;
+
+        let lut_sampler = device.create_sampler(
+            "Color Lut Sampler",
+            &SamplerDescription {
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                address_mode_w: AddressMode::ClampToEdge,
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+        )?;
+
+        Ok(Self {
+            settings,
+            volume_lut: None,
+            luts: Vec::new(),
+            identity_lut,
+            lut_sampler,
+        })
+    }
+
+    pub fn luts(&self) -> &[ColorLut] {
+        &self.luts
+    }
+
+    /// Loads every .cube file in `directory`, files that fail to parse are logged and skipped
+    pub fn load_directory<P: AsRef<Path>>(
+        &mut self,
+        device: &mut Device,
+        directory: P,
+    ) -> anyhow::Result<()> {
+        let mut paths: Vec<_> = std::fs::read_dir(directory.as_ref())?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map(|extension| extension == "cube") == Some(true))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            if let Err(err) = self.load_lut(device, &path) {
+                warn!("Failed to load color lut {}: {:#}", path.display(), err);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load_lut<P: AsRef<Path>>(
+        &mut self,
+        device: &mut Device,
+        path: P,
+    ) -> anyhow::Result<usize> {
+        let path = path.as_ref();
+        let file_content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let (size, data) = parse_cube(&file_content)?;
+
+        let name = path
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .unwrap_or("Unnamed Lut")
+            .to_string();
+        let image = create_lut_image(device, &name, size, &data)?;
+        info!("Loaded color lut {} ({}^3)", name, size);
+
+        self.luts.push(ColorLut { name, image });
+        Ok(self.luts.len() - 1)
+    }
+
+    /// Cycles `lut` through every loaded lut and back to None
+    pub fn cycle_lut(lut: &mut Option<usize>, lut_count: usize) {
+        *lut = match *lut {
+            None if lut_count > 0 => Some(0),
+            Some(index) if index + 1 < lut_count => Some(index + 1),
+            _ => None,
+        };
+    }
+
+    pub fn lut_name(&self, lut: Option<usize>) -> &str {
+        lut.and_then(|index| self.luts.get(index))
+            .map(|lut| lut.name.as_str())
+            .unwrap_or("None")
+    }
+
+    /// Binds both luts, the sampler and the blend factor, in that order
+    pub fn bind(&self, draw_command_builder: &mut RasterDrawCommandBuilder) {
+        let get_lut = |lut: Option<usize>| {
+            lut.filter(|_| self.settings.enabled)
+                .and_then(|index| self.luts.get(index))
+                .map(|lut| lut.image)
+                .unwrap_or(self.identity_lut)
+        };
+
+        let (secondary_lut, blend) = match self.volume_lut {
+            Some((lut, weight)) => (Some(lut), weight),
+            None => (self.settings.secondary_lut, self.settings.blend),
+        };
+        draw_command_builder.read_sampled_image(get_lut(self.settings.lut));
+        draw_command_builder.read_sampled_image(get_lut(secondary_lut));
+        draw_command_builder.read_sampler(self.lut_sampler);
+        draw_command_builder.push_constant(blend.clamp(0.0, 1.0).to_bits());
+    }
+}
+
+fn create_lut_image(
+    device: &mut Device,
+    name: &str,
+    size: u32,
+    data: &[Vec3],
+) -> anyhow::Result<ImageHandle> {
+    let bytes: Vec<u8> = data
+        .iter()
+        .flat_map(|color| color.extend(1.0).to_array())
+        .flat_map(|value| f32_to_f16(value).to_le_bytes())
+        .collect();
+    Ok(device.create_image_3d_init(
+        name,
+        &ImageDescription3D {
+            size: [size; 3],
+            format: ColorGrading::LUT_FORMAT,
+            usage: vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+            mip_levels: 1,
+            location: MemoryLocation::GpuOnly,
+        },
+        &bytes,
+    )?)
+}
+
+/// Parses an Adobe/Resolve .cube file, red changes fastest which matches the x axis of a 3D image.
+/// Only the default 0-1 input domain is supported since the shader samples with the color directly
+fn parse_cube(file_content: &str) -> anyhow::Result<(u32, Vec<Vec3>)> {
+    let mut size = None;
+    let mut domain_min = Vec3::ZERO;
+    let mut domain_max = Vec3::ONE;
+    let mut data = Vec::new();
+
+    let parse_vec3 = |values: &[&str]| -> anyhow::Result<Vec3> {
+        anyhow::ensure!(
+            values.len() == 3,
+            "Expected 3 values, found {}",
+            values.len()
+        );
+        let mut vec = Vec3::ZERO;
+        for (index, value) in values.iter().enumerate() {
+            vec[index] = value
+                .parse::<f32>()
+                .with_context(|| format!("Invalid value {}", value))?;
+        }
+        Ok(vec)
+    };
+
+    for (line_index, line) in file_content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let result = match parts[0] {
+            "TITLE" => Ok(()),
+            "LUT_1D_SIZE" => Err(anyhow::anyhow!("1D luts aren't supported")),
+            "LUT_3D_SIZE" => parts
+                .get(1)
+                .and_then(|value| value.parse::<u32>().ok())
+                .filter(|&value| value >= 2)
+                .map(|value| size = Some(value))
+                .context("Invalid LUT_3D_SIZE"),
+            "DOMAIN_MIN" => parse_vec3(&parts[1..]).map(|value| domain_min = value),
+            "DOMAIN_MAX" => parse_vec3(&parts[1..]).map(|value| domain_max = value),
+            _ => parse_vec3(&parts).map(|value| data.push(value)),
+        };
+        result.with_context(|| format!("Line {}", line_index + 1))?;
+    }
+
+    let size = size.context("Missing LUT_3D_SIZE")?;
+    let expected_count = (size * size * size) as usize;
+    anyhow::ensure!(
+        data.len() == expected_count,
+        "Expected {} entries, found {}",
+        expected_count,
+        data.len()
+    );
+
+    anyhow::ensure!(
+        domain_min == Vec3::ZERO && domain_max == Vec3::ONE,
+        "Unsupported domain {} to {}",
+        domain_min,
+        domain_max
+    );
+
+    Ok((size, data))
+}
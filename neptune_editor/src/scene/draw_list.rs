@@ -0,0 +1,120 @@
+use crate::scene::scene_renderer::{slice_to_bytes_unsafe, ModelPrimitive};
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{BufferHandle, BufferUsage, RasterPipelineHandle};
+use std::sync::Arc;
+
+/// Per draw values the mesh shaders look up instead of taking them as push constants.
+/// A draw's index is passed as its first instance, matches DrawData in neptune/draw.glsl
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+pub struct DrawData {
+    /// Into the scene's model matrix buffer
+    pub instance_index: u32,
+    /// Into the material palette
+    pub material_index: u32,
+    pub flags: u32,
+    _padding: u32,
+}
+
+impl DrawData {
+    pub const TRANSPARENT: u32 = 1;
+    pub const LIGHTMAPPED: u32 = 2;
+    pub const SHADER_GRAPH: u32 = 4;
+
+    pub fn new(instance_index: u32, material_index: u32, flags: u32) -> Self {
+        Self {
+            instance_index,
+            material_index,
+            flags,
+            _padding: 0,
+        }
+    }
+}
+
+/// Uploads the draws to a buffer that only lives for this frame, draw `i` is recorded with the instances `i..i + 1`
+pub fn write_draw_buffer<T: RenderGraphBuilderTrait>(
+    draws: Vec<DrawData>,
+    render_graph_builder: &mut T,
+) -> BufferHandle {
+    //Empty buffers can't be created, passes without draws still get one to bind
+    let size = std::mem::size_of::<DrawData>() * draws.len().max(1);
+    let buffer = render_graph_builder.create_transient_buffer(
+        size,
+        BufferUsage::STORAGE | BufferUsage::TRANSFER,
+        MemoryLocation::GpuOnly,
+    );
+    if !draws.is_empty() {
+        let write_size = std::mem::size_of_val(draws.as_slice());
+        render_graph_builder.add_buffer_write(
+            BufferOffset { buffer, offset: 0 },
+            write_size,
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&draws) });
+            }),
+        );
+    }
+    buffer
+}
+
+/// A single primitive instance waiting to be recorded
+pub struct DrawItem<'a> {
+    pub pipeline: RasterPipelineHandle,
+    pub model_primitive: &'a ModelPrimitive,
+    pub instance_index: u32,
+    /// Material palette index, the default material's if the primitive has none
+    pub material_index: u32,
+    pub flags: u32,
+    /// Squared distance from the camera, only used to order transparent draws
+    pub distance: f32,
+}
+
+impl DrawItem<'_> {
+    pub fn draw_data(&self) -> DrawData {
+        DrawData::new(self.instance_index, self.material_index, self.flags)
+    }
+
+    /// Pipeline first since it's the most expensive bind, then material and mesh so their resources stay bound
+    fn sort_key(&self) -> (RasterPipelineHandle, usize, usize) {
+        (
+            self.pipeline,
+            self.model_primitive
+                .material
+                .as_ref()
+                .map(|material| Arc::as_ptr(material) as usize)
+                .unwrap_or_default(),
+            Arc::as_ptr(&self.model_primitive.primitive) as usize,
+        )
+    }
+}
+
+/// Draws for one pass, collected up front so they can be ordered to minimize state changes
+#[derive(Default)]
+pub struct DrawList<'a> {
+    pub opaque: Vec<DrawItem<'a>>,
+    pub transparent: Vec<DrawItem<'a>>,
+}
+
+impl<'a> DrawList<'a> {
+    pub fn push(&mut self, item: DrawItem<'a>, transparent: bool) {
+        if transparent {
+            self.transparent.push(item);
+        } else {
+            self.opaque.push(item);
+        }
+    }
+
+    /// Opaque draws are grouped by state, transparent draws go back to front so they blend correctly
+    pub fn sort(&mut self) {
+        self.opaque.sort_unstable_by_key(|item| item.sort_key());
+        self.transparent
+            .sort_by(|a, b| b.distance.total_cmp(&a.distance));
+    }
+
+    /// Every draw in the order they're recorded, opaque then transparent
+    pub fn iter(&self) -> impl Iterator<Item = &DrawItem<'a>> {
+        self.opaque.iter().chain(self.transparent.iter())
+    }
+}
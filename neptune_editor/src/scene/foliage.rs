@@ -0,0 +1,516 @@
+use crate::buffer_inspector::{BufferElement, InspectableBuffer};
+use crate::lightmap::ray_triangle_intersect;
+use crate::mesh::BoundingBox;
+use crate::scene::scene_renderer::{slice_to_bytes_unsafe, ModelPrimitive, Scene, SceneCamera};
+use crate::scene::spatial::{Bvh, Ray};
+use anyhow::Context;
+use glam::{Vec2, Vec3, Vec4};
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph::QueueType;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, ComputePassBuilder, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{BufferHandle, BufferUsage, ComputePipelineHandle, Device};
+use std::cell::RefCell;
+use std::f32::consts::TAU;
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+pub struct FoliageScatterSettings {
+    /// Instances per square meter
+    pub density: f32,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    /// Steepest ground, in radians from flat, that still gets instances
+    pub max_slope: f32,
+    pub seed: u32,
+}
+
+impl Default for FoliageScatterSettings {
+    fn default() -> Self {
+        Self {
+            density: 4.0,
+            min_scale: 0.7,
+            max_scale: 1.3,
+            max_slope: 35f32.to_radians(),
+            seed: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FoliageWindSettings {
+    /// Direction on the xz plane, the length is how far the tips lean
+    pub direction: Vec2,
+    /// Sways per second
+    pub frequency: f32,
+}
+
+impl Default for FoliageWindSettings {
+    fn default() -> Self {
+        Self {
+            direction: Vec2::new(0.15, 0.05),
+            frequency: 1.2,
+        }
+    }
+}
+
+/// Matches FoliageInstance in foliage.vert and foliage_cull.comp
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+pub struct FoliageInstance {
+    /// w: scale
+    pub position_scale: Vec4,
+    /// x: sin of the yaw, y: cos of the yaw, z: wind phase
+    pub rotation_phase: Vec4,
+}
+
+impl FoliageInstance {
+    pub fn new(position: Vec3, yaw: f32, scale: f32, wind_phase: f32) -> Self {
+        let (sin, cos) = yaw.sin_cos();
+        Self {
+            position_scale: position.extend(scale),
+            rotation_phase: Vec4::new(sin, cos, wind_phase, 0.0),
+        }
+    }
+}
+
+/// Matches WindBuffer in foliage.vert
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct FoliageWindData {
+    /// xy: direction, z: frequency
+    wind: Vec4,
+    /// x: time, y: last frame's time
+    time: Vec4,
+}
+
+/// One mesh scattered many times across the scene, drawn with a single indirect draw per view
+pub struct FoliageLayer {
+    pub name: String,
+    pub model_primitive: ModelPrimitive,
+
+    capacity: usize,
+    instances: Arc<Vec<FoliageInstance>>,
+    instance_buffer: BufferHandle,
+    dirty: bool,
+}
+
+impl FoliageLayer {
+    pub fn new(
+        device: &mut Device,
+        name: &str,
+        model_primitive: ModelPrimitive,
+        capacity: usize,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            model_primitive.primitive.index_buffer.is_some(),
+            "Foliage layer {} needs an indexed mesh",
+            name
+        );
+
+        //Zero sized buffers aren't allowed
+        let capacity = capacity.max(1);
+        let instance_buffer = device
+            .create_buffer(
+                &format!("{} Foliage Instances", name),
+                capacity * std::mem::size_of::<FoliageInstance>(),
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+            )
+            .context("Failed to create foliage instance buffer")?;
+
+        Ok(Self {
+            name: name.to_string(),
+            model_primitive,
+            capacity,
+            instances: Arc::new(Vec::new()),
+            instance_buffer,
+            dirty: false,
+        })
+    }
+
+    pub fn destroy(self, device: &mut Device) {
+        device.destroy_buffer(self.instance_buffer);
+    }
+
+    pub fn instance_count(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Adds instances, anything past the layer's capacity is dropped
+    pub fn add_instances(&mut self, new_instances: Vec<FoliageInstance>) {
+        let room = self.capacity - self.instances.len();
+        if new_instances.len() > room {
+            warn!(
+                "Foliage layer {} is full, dropping {} instances",
+                self.name,
+                new_instances.len() - room
+            );
+        }
+        Arc::make_mut(&mut self.instances).extend(new_instances.into_iter().take(room));
+        self.dirty = true;
+    }
+
+    pub fn clear(&mut self) {
+        self.instances = Arc::new(Vec::new());
+        self.dirty = true;
+    }
+
+    /// Sphere around the instance origin that contains the mesh at scale 1 bent as far as the wind can push it
+    fn bounding_radius(&self, wind: &FoliageWindSettings) -> f32 {
+        let bounding_box = &self.model_primitive.primitive.bounding_box;
+        bounding_box.center().length()
+            + bounding_box.half_extent().length()
+            + wind.direction.length()
+    }
+
+    /// Height of the mesh above its origin, the sway is weighted by height so the base stays planted
+    pub fn height(&self) -> f32 {
+        self.model_primitive.primitive.bounding_box.max.y.max(1e-3)
+    }
+
+    /// Uploads the instances if they changed since the last call
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        render_graph_builder: &mut T,
+    ) {
+        if !self.dirty || self.instances.is_empty() {
+            self.dirty = false;
+            return;
+        }
+        self.dirty = false;
+
+        let instances = self.instances.clone();
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: self.instance_buffer,
+                offset: 0,
+            },
+            std::mem::size_of_val(instances.as_slice()),
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&instances) });
+            }),
+        );
+    }
+}
+
+/// Culled instances of one layer for one view
+pub struct VisibleFoliage {
+    pub instance_buffer: BufferHandle,
+    /// Holds a single VkDrawIndexedIndirectCommand
+    pub draw_buffer: BufferHandle,
+}
+
+/// Culls foliage layers against each view on the gpu, the drawing itself is done with the scene's mesh pass
+pub struct FoliageRenderer {
+    pub wind: FoliageWindSettings,
+    /// Instances further than this from the camera aren't drawn
+    pub max_distance: f32,
+
+    cull_pipeline: ComputePipelineHandle,
+    wind_buffer: BufferHandle,
+    time: f32,
+    previous_time: f32,
+    /// This frame's cull results, for the buffer inspector
+    culled_buffers: RefCell<Vec<InspectableBuffer>>,
+}
+
+impl FoliageRenderer {
+    /// u32 index_count, instance_count, first_index, vertex_offset and first_instance
+    pub(crate) const DRAW_COMMAND_SIZE: usize = 20;
+
+    pub fn new(device: &mut Device) -> anyhow::Result<Self> {
+        let cull_pipeline = device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+            code: crate::shader::FOLIAGE_CULL_COMP,
+            entry: "main",
+        })?;
+
+        let wind_buffer = device
+            .create_buffer_init(
+                "FoliageWindBuffer",
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                unsafe { slice_to_bytes_unsafe(&[FoliageWindData::default()]) },
+            )
+            .context("Failed to create foliage wind buffer")?;
+
+        Ok(Self {
+            wind: FoliageWindSettings::default(),
+            max_distance: 100.0,
+            cull_pipeline,
+            wind_buffer,
+            time: 0.0,
+            previous_time: 0.0,
+            culled_buffers: RefCell::new(Vec::new()),
+        })
+    }
+
+    /// Called once per frame with the game time step, so pausing the game freezes the sway
+    pub fn update(&mut self, delta_time: f32) {
+        self.previous_time = self.time;
+        self.time += delta_time;
+    }
+
+    pub fn wind_buffer(&self) -> BufferHandle {
+        self.wind_buffer
+    }
+
+    /// Visible instances and indirect draws of every cull this frame, only valid in this frame's graph
+    pub fn culled_buffers(&self) -> Vec<InspectableBuffer> {
+        self.culled_buffers.borrow().clone()
+    }
+
+    /// Uploads this frame's wind, the layers upload their own instances with the scene
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(&self, render_graph_builder: &mut T) {
+        self.culled_buffers.borrow_mut().clear();
+        let wind_data = FoliageWindData {
+            wind: Vec4::new(
+                self.wind.direction.x,
+                self.wind.direction.y,
+                self.wind.frequency * TAU,
+                0.0,
+            ),
+            time: Vec4::new(self.time, self.previous_time, 0.0, 0.0),
+        };
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: self.wind_buffer,
+                offset: 0,
+            },
+            std::mem::size_of::<FoliageWindData>(),
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&[wind_data]) });
+            }),
+        );
+    }
+
+    /// Frustum and distance culls the layer's instances into a compacted buffer and fills in the instance count of an indirect draw
+    pub fn cull<T: RenderGraphBuilderTrait>(
+        &self,
+        camera: &SceneCamera,
+        layer: &FoliageLayer,
+        render_graph_builder: &mut T,
+    ) -> Option<VisibleFoliage> {
+        let index_count = layer.model_primitive.primitive.index_buffer.as_ref()?.count;
+        let instance_count = layer.instances.len() as u32;
+        if instance_count == 0 {
+            return None;
+        }
+
+        let instance_buffer = render_graph_builder.create_transient_buffer(
+            instance_count as usize * std::mem::size_of::<FoliageInstance>(),
+            BufferUsage::STORAGE | BufferUsage::TRANSFER,
+            MemoryLocation::GpuOnly,
+        );
+        let draw_buffer = render_graph_builder.create_transient_buffer(
+            Self::DRAW_COMMAND_SIZE,
+            BufferUsage::STORAGE | BufferUsage::INDIRECT | BufferUsage::TRANSFER,
+            MemoryLocation::GpuOnly,
+        );
+
+        //The cull pass counts the instances up from 0
+        let draw_command: [u32; 5] = [index_count, 0, 0, 0, 0];
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: draw_buffer,
+                offset: 0,
+            },
+            Self::DRAW_COMMAND_SIZE,
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&draw_command) });
+            }),
+        );
+
+        let mut cull_pass_builder =
+            ComputePassBuilder::new("Foliage Cull Pass", QueueType::Graphics, self.cull_pipeline);
+        cull_pass_builder.read_buffer(camera.buffer());
+        cull_pass_builder.read_buffer(layer.instance_buffer);
+        cull_pass_builder.write_buffer(instance_buffer);
+        cull_pass_builder.write_buffer(draw_buffer);
+        cull_pass_builder.push_constant(instance_count);
+        cull_pass_builder.push_constant(layer.bounding_radius(&self.wind).to_bits());
+        cull_pass_builder.push_constant(self.max_distance.to_bits());
+        cull_pass_builder.dispatch_threads([instance_count, 1, 1]);
+        cull_pass_builder.build(render_graph_builder);
+
+        let mut culled_buffers = self.culled_buffers.borrow_mut();
+        let cull_index = culled_buffers.len() / 2;
+        culled_buffers.push(InspectableBuffer {
+            name: format!("Foliage Cull {} Draw", cull_index),
+            buffer: draw_buffer,
+            size: Self::DRAW_COMMAND_SIZE,
+            element: BufferElement::U32,
+        });
+        culled_buffers.push(InspectableBuffer {
+            name: format!("Foliage Cull {} Instances", cull_index),
+            buffer: instance_buffer,
+            size: instance_count as usize * std::mem::size_of::<FoliageInstance>(),
+            element: BufferElement::F32,
+        });
+
+        Some(VisibleFoliage {
+            instance_buffer,
+            draw_buffer,
+        })
+    }
+}
+
+/// World space triangles of the scene, foliage is placed by casting rays down onto them
+pub struct FoliageGround {
+    triangles: Vec<[Vec3; 3]>,
+    bvh: Bvh<usize>,
+    bounds: BoundingBox,
+}
+
+impl FoliageGround {
+    pub fn new(scene: &Scene) -> Self {
+        let mut triangles: Vec<[Vec3; 3]> = Vec::new();
+        for (transform, model) in scene.instances() {
+            let model_matrix = transform.model_matrix();
+            for model_primitive in model.primitives.iter() {
+                let geometry = &model_primitive.primitive.geometry;
+                for indices in geometry.indices.chunks_exact(3) {
+                    let indices: [u32; 3] = indices.try_into().unwrap();
+                    triangles.push(indices.map(|index| {
+                        model_matrix.transform_point3(geometry.positions[index as usize])
+                    }));
+                }
+            }
+        }
+
+        let bounding_boxes: Vec<(usize, BoundingBox)> = triangles
+            .iter()
+            .enumerate()
+            .map(|(index, triangle)| (index, BoundingBox::from_points(triangle)))
+            .collect();
+        let bounds = bounding_boxes
+            .iter()
+            .map(|(_, bounding_box)| *bounding_box)
+            .reduce(|bounds, bounding_box| bounds.union(&bounding_box))
+            .unwrap_or_default();
+
+        Self {
+            triangles,
+            bvh: Bvh::build(bounding_boxes),
+            bounds,
+        }
+    }
+
+    /// Returns the hit position and the face normal facing back along the ray
+    pub fn ray_cast(&self, ray: &Ray, max_distance: f32) -> Option<(Vec3, Vec3)> {
+        self.bvh
+            .ray_cast_by(ray, max_distance, |&index, _bounds| {
+                ray_triangle_intersect(ray, &self.triangles[index])
+            })
+            .map(|hit| {
+                let [a, b, c] = self.triangles[hit.item];
+                let normal = (b - a).cross(c - a).normalize_or_zero();
+                let normal = if normal.dot(ray.direction) > 0.0 {
+                    -normal
+                } else {
+                    normal
+                };
+                (ray.origin + ray.direction * hit.distance, normal)
+            })
+    }
+
+    /// Drops a point onto the highest ground below it
+    fn drop_point(&self, point: Vec2, settings: &FoliageScatterSettings) -> Option<Vec3> {
+        let top = self.bounds.max.y + 1.0;
+        let ray = Ray::new(Vec3::new(point.x, top, point.y), Vec3::NEG_Y);
+        let (position, normal) = self.ray_cast(&ray, top - self.bounds.min.y + 1.0)?;
+        (normal.y >= settings.max_slope.cos()).then_some(position)
+    }
+
+    fn create_instance(
+        &self,
+        point: Vec2,
+        settings: &FoliageScatterSettings,
+        random: &mut Random,
+    ) -> Option<FoliageInstance> {
+        let position = self.drop_point(point, settings)?;
+        let scale = settings.min_scale + (settings.max_scale - settings.min_scale) * random.next();
+        Some(FoliageInstance::new(
+            position,
+            random.next() * TAU,
+            scale,
+            random.next() * TAU,
+        ))
+    }
+
+    /// Jittered grid over `area` (center and size on the xz plane), points landing on ground that's too steep are skipped
+    pub fn scatter(
+        &self,
+        center: Vec2,
+        size: Vec2,
+        settings: &FoliageScatterSettings,
+    ) -> Vec<FoliageInstance> {
+        let spacing = 1.0 / settings.density.max(1e-3).sqrt();
+        let cells = (size / spacing).ceil().max(Vec2::ONE);
+        let min = center - size * 0.5;
+        let mut random = Random::new(settings.seed);
+
+        let mut instances = Vec::new();
+        for y in 0..(cells.y as u32) {
+            for x in 0..(cells.x as u32) {
+                let point = min
+                    + (Vec2::new(x as f32, y as f32) + Vec2::new(random.next(), random.next()))
+                        * spacing;
+                instances.extend(self.create_instance(point, settings, &mut random));
+            }
+        }
+        instances
+    }
+
+    /// Brush placement, `count` instances at random points in the circle under where `ray` hits the ground
+    pub fn paint(
+        &self,
+        ray: &Ray,
+        radius: f32,
+        count: usize,
+        settings: &FoliageScatterSettings,
+    ) -> Vec<FoliageInstance> {
+        let max_distance = (self.bounds.max - self.bounds.min).length()
+            + ray.origin.distance(self.bounds.center());
+        let Some((center, _)) = self.ray_cast(ray, max_distance) else {
+            return Vec::new();
+        };
+
+        let mut random = Random::new(
+            settings.seed
+                ^ center
+                    .to_array()
+                    .map(f32::to_bits)
+                    .iter()
+                    .fold(0, |hash, bits| hash ^ bits.rotate_left(7)),
+        );
+        (0..count)
+            .filter_map(|_| {
+                //Square root keeps the points evenly spread over the circle
+                let angle = random.next() * TAU;
+                let distance = random.next().sqrt() * radius;
+                let point =
+                    Vec2::new(center.x, center.z) + Vec2::new(angle.cos(), angle.sin()) * distance;
+                self.create_instance(point, settings, &mut random)
+            })
+            .collect()
+    }
+}
+
+/// Xorshift, placement only needs to be repeatable for a given seed
+struct Random(u32);
+
+impl Random {
+    fn new(seed: u32) -> Self {
+        Self(seed.max(1))
+    }
+
+    /// 0 to 1
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
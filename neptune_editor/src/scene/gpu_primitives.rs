@@ -0,0 +1,257 @@
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph::QueueType;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, ComputePassBuilder, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{BufferHandle, BufferUsage, ComputePipelineHandle, Device};
+
+/// How `GpuPrimitives::reduce` combines values, matches the constants in gpu_reduce.comp
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReduceOp {
+    Sum = 0,
+    Min = 1,
+    Max = 2,
+}
+
+/// Building blocks for gpu driven passes (culling, particles, sorting) that work on buffers of u32s.
+/// Each call adds its compute passes to the graph, larger inputs get extra passes over the per block results
+pub struct GpuPrimitives {
+    scan_blocks_pipeline: ComputePipelineHandle,
+    scan_add_pipeline: ComputePipelineHandle,
+    reduce_pipeline: ComputePipelineHandle,
+    compact_pipeline: ComputePipelineHandle,
+    radix_histogram_pipeline: ComputePipelineHandle,
+    radix_scatter_pipeline: ComputePipelineHandle,
+}
+
+impl GpuPrimitives {
+    /// Values handled by one workgroup, matches local_size_x in the shaders
+    pub const BLOCK_SIZE: u32 = 256;
+
+    /// Key bits sorted per radix pass, matches RADIX in the radix shaders
+    const RADIX_BITS: u32 = 4;
+
+    pub fn new(device: &mut Device) -> anyhow::Result<Self> {
+        let mut create_pipeline = |code| {
+            device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+                code,
+                entry: "main",
+            })
+        };
+        let gpu_primitives = Self {
+            scan_blocks_pipeline: create_pipeline(crate::shader::GPU_SCAN_BLOCKS_COMP)?,
+            scan_add_pipeline: create_pipeline(crate::shader::GPU_SCAN_ADD_COMP)?,
+            reduce_pipeline: create_pipeline(crate::shader::GPU_REDUCE_COMP)?,
+            compact_pipeline: create_pipeline(crate::shader::GPU_COMPACT_COMP)?,
+            radix_histogram_pipeline: create_pipeline(crate::shader::GPU_RADIX_HISTOGRAM_COMP)?,
+            radix_scatter_pipeline: create_pipeline(crate::shader::GPU_RADIX_SCATTER_COMP)?,
+        };
+
+        //The block sums are laid out per workgroup, so every shader has to agree on the block size
+        for pipeline in [
+            gpu_primitives.scan_blocks_pipeline,
+            gpu_primitives.scan_add_pipeline,
+            gpu_primitives.reduce_pipeline,
+            gpu_primitives.compact_pipeline,
+            gpu_primitives.radix_histogram_pipeline,
+            gpu_primitives.radix_scatter_pipeline,
+        ] {
+            anyhow::ensure!(
+                pipeline.workgroup_size() == [Self::BLOCK_SIZE, 1, 1],
+                "Gpu primitive workgroup size {:?} doesn't match the block size {}",
+                pipeline.workgroup_size(),
+                Self::BLOCK_SIZE
+            );
+        }
+
+        Ok(gpu_primitives)
+    }
+
+    /// Writes the sum of every value before each one to `output`, which must be a different buffer than `input`
+    pub fn exclusive_scan<T: RenderGraphBuilderTrait>(
+        &self,
+        render_graph_builder: &mut T,
+        input: BufferHandle,
+        output: BufferHandle,
+        count: u32,
+    ) {
+        if count == 0 {
+            return;
+        }
+
+        let block_count = count.div_ceil(Self::BLOCK_SIZE);
+        let block_sums = create_scratch_buffer(render_graph_builder, block_count);
+
+        let mut scan_pass_builder = ComputePassBuilder::new(
+            "Exclusive Scan Pass",
+            QueueType::Graphics,
+            self.scan_blocks_pipeline,
+        );
+        scan_pass_builder.read_buffer(input);
+        scan_pass_builder.write_buffer(output);
+        scan_pass_builder.write_buffer(block_sums);
+        scan_pass_builder.push_constant(count);
+        scan_pass_builder.dispatch_size([block_count, 1, 1]);
+        scan_pass_builder.build(render_graph_builder);
+
+        if block_count == 1 {
+            return;
+        }
+
+        //Every block needs the total of the blocks before it, which is just a smaller scan
+        let block_offsets = create_scratch_buffer(render_graph_builder, block_count);
+        self.exclusive_scan(render_graph_builder, block_sums, block_offsets, block_count);
+
+        let mut add_pass_builder = ComputePassBuilder::new(
+            "Exclusive Scan Add Pass",
+            QueueType::Graphics,
+            self.scan_add_pipeline,
+        );
+        add_pass_builder.write_buffer(output);
+        add_pass_builder.read_buffer(block_offsets);
+        add_pass_builder.push_constant(count);
+        add_pass_builder.dispatch_size([block_count, 1, 1]);
+        add_pass_builder.build(render_graph_builder);
+    }
+
+    /// Combines every value into one u32 written at `output`, which must be 4 byte aligned
+    pub fn reduce<T: RenderGraphBuilderTrait>(
+        &self,
+        render_graph_builder: &mut T,
+        input: BufferHandle,
+        count: u32,
+        op: ReduceOp,
+        output: BufferOffset,
+    ) {
+        if count == 0 {
+            return;
+        }
+
+        let block_count = count.div_ceil(Self::BLOCK_SIZE);
+        let (pass_output, pass_offset) = if block_count == 1 {
+            (output.buffer, output.offset as u32 / 4)
+        } else {
+            (create_scratch_buffer(render_graph_builder, block_count), 0)
+        };
+
+        let mut reduce_pass_builder =
+            ComputePassBuilder::new("Reduce Pass", QueueType::Graphics, self.reduce_pipeline);
+        reduce_pass_builder.read_buffer(input);
+        reduce_pass_builder.write_buffer(pass_output);
+        reduce_pass_builder.push_constant(pass_offset);
+        reduce_pass_builder.push_constant(count);
+        reduce_pass_builder.push_constant(op as u32);
+        reduce_pass_builder.dispatch_size([block_count, 1, 1]);
+        reduce_pass_builder.build(render_graph_builder);
+
+        if block_count > 1 {
+            self.reduce(render_graph_builder, pass_output, block_count, op, output);
+        }
+    }
+
+    /// Copies the values whose flag is 1 to the start of `output` keeping their order, flags must be 0 or 1.
+    /// The number copied is written at `count_output`, e.g. the instance count of an indirect draw
+    pub fn compact<T: RenderGraphBuilderTrait>(
+        &self,
+        render_graph_builder: &mut T,
+        input: BufferHandle,
+        flags: BufferHandle,
+        count: u32,
+        output: BufferHandle,
+        count_output: BufferOffset,
+    ) {
+        if count == 0 {
+            return;
+        }
+
+        let offsets = create_scratch_buffer(render_graph_builder, count);
+        self.exclusive_scan(render_graph_builder, flags, offsets, count);
+
+        let mut compact_pass_builder =
+            ComputePassBuilder::new("Compact Pass", QueueType::Graphics, self.compact_pipeline);
+        compact_pass_builder.read_buffer(input);
+        compact_pass_builder.read_buffer(flags);
+        compact_pass_builder.read_buffer(offsets);
+        compact_pass_builder.write_buffer(output);
+        compact_pass_builder.write_buffer(count_output.buffer);
+        compact_pass_builder.push_constant(count_output.offset as u32 / 4);
+        compact_pass_builder.push_constant(count);
+        compact_pass_builder.dispatch_threads([count, 1, 1]);
+        compact_pass_builder.build(render_graph_builder);
+    }
+
+    /// Sorts `keys` and the matching `values` in place by ascending key, equal keys keep their order.
+    /// Takes 8 passes of 4 bits, so use keys that put the entries to skip (e.g. culled draws) last
+    pub fn radix_sort<T: RenderGraphBuilderTrait>(
+        &self,
+        render_graph_builder: &mut T,
+        keys: BufferHandle,
+        values: BufferHandle,
+        count: u32,
+    ) {
+        if count == 0 {
+            return;
+        }
+
+        let radix = 1 << Self::RADIX_BITS;
+        let block_count = count.div_ceil(Self::BLOCK_SIZE);
+        let scratch_keys = create_scratch_buffer(render_graph_builder, count);
+        let scratch_values = create_scratch_buffer(render_graph_builder, count);
+
+        //An even number of passes so the result ends up back in the input buffers
+        let mut source = (keys, values);
+        let mut destination = (scratch_keys, scratch_values);
+        for shift in (0..u32::BITS).step_by(Self::RADIX_BITS as usize) {
+            let histogram = create_scratch_buffer(render_graph_builder, radix * block_count);
+            let offsets = create_scratch_buffer(render_graph_builder, radix * block_count);
+
+            let mut histogram_pass_builder = ComputePassBuilder::new(
+                "Radix Sort Histogram Pass",
+                QueueType::Graphics,
+                self.radix_histogram_pipeline,
+            );
+            histogram_pass_builder.read_buffer(source.0);
+            histogram_pass_builder.write_buffer(histogram);
+            histogram_pass_builder.push_constant(count);
+            histogram_pass_builder.push_constant(shift);
+            histogram_pass_builder.dispatch_size([block_count, 1, 1]);
+            histogram_pass_builder.build(render_graph_builder);
+
+            self.exclusive_scan(
+                render_graph_builder,
+                histogram,
+                offsets,
+                radix * block_count,
+            );
+
+            let mut scatter_pass_builder = ComputePassBuilder::new(
+                "Radix Sort Scatter Pass",
+                QueueType::Graphics,
+                self.radix_scatter_pipeline,
+            );
+            scatter_pass_builder.read_buffer(source.0);
+            scatter_pass_builder.read_buffer(source.1);
+            scatter_pass_builder.read_buffer(offsets);
+            scatter_pass_builder.write_buffer(destination.0);
+            scatter_pass_builder.write_buffer(destination.1);
+            scatter_pass_builder.push_constant(count);
+            scatter_pass_builder.push_constant(shift);
+            scatter_pass_builder.dispatch_size([block_count, 1, 1]);
+            scatter_pass_builder.build(render_graph_builder);
+
+            std::mem::swap(&mut source, &mut destination);
+        }
+    }
+}
+
+/// Frame lifetime buffer of u32s for intermediate results
+fn create_scratch_buffer<T: RenderGraphBuilderTrait>(
+    render_graph_builder: &mut T,
+    count: u32,
+) -> BufferHandle {
+    render_graph_builder.create_transient_buffer(
+        count as usize * std::mem::size_of::<u32>(),
+        BufferUsage::STORAGE,
+        MemoryLocation::GpuOnly,
+    )
+}
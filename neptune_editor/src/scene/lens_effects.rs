@@ -0,0 +1,317 @@
+use crate::scene::scene_renderer::{slice_to_bytes_unsafe, SceneCamera, SceneRenderer};
+use crate::scene::sky::Sky;
+use anyhow::Context;
+use glam::{Vec3, Vec4};
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph::QueueType;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, ComputePassBuilder, RasterDrawCommandBuilder,
+    RasterPassBuilder, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{
+    vk, AddressMode, BlendState, BufferHandle, BufferUsage, ComputePipelineHandle, Device,
+    FilterMode, ImageHandle, RasterPipelineHandle, SamplerDescription, SamplerHandle,
+    TransientImageDesc, TransientImageSize,
+};
+
+#[derive(Debug, Clone)]
+pub struct LensEffectSettings {
+    pub light_shafts: bool,
+    pub shaft_intensity: f32,
+    /// How much each step away from a pixel counts compared to the one before it
+    pub shaft_decay: f32,
+    /// Fraction of the way to the sun that each pixel samples
+    pub shaft_length: f32,
+    pub lens_flare: bool,
+    pub flare_intensity: f32,
+}
+
+impl Default for LensEffectSettings {
+    fn default() -> Self {
+        Self {
+            light_shafts: true,
+            shaft_intensity: 0.6,
+            shaft_decay: 0.97,
+            shaft_length: 0.8,
+            lens_flare: true,
+            flare_intensity: 0.5,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct LensEffectData {
+    /// xy: sun uv, z: aspect ratio, w: fade as the sun leaves the screen
+    sun_screen: Vec4,
+    /// w: flare intensity
+    sun_color: Vec4,
+    /// x: intensity, y: decay, z: length
+    shafts: Vec4,
+}
+
+/// Screen space light shafts and a lens flare for the sun, main view only.
+/// The flare's visibility is tested against the depth buffer on the gpu, so it never waits on a readback
+pub struct LensEffects {
+    pub settings: LensEffectSettings,
+
+    shaft_mask_pipeline: RasterPipelineHandle,
+    shaft_pipeline: RasterPipelineHandle,
+    flare_occlusion_pipeline: ComputePipelineHandle,
+    flare_pipeline: RasterPipelineHandle,
+
+    lens_buffer: BufferHandle,
+    visibility_buffer: BufferHandle,
+    sampler: SamplerHandle,
+}
+
+impl LensEffects {
+    /// Light shafts are blurry anyway, so the mask is half resolution
+    const MASK_DIVISOR: u32 = 2;
+    const MASK_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+    const FLARE_ELEMENT_COUNT: u32 = 7;
+    /// In ndc, how far past the edge of the screen the sun still casts shafts
+    const SCREEN_FADE_DISTANCE: f32 = 0.5;
+
+    pub fn new(
+        device: &mut Device,
+        color_format: vk::Format,
+        settings: LensEffectSettings,
+    ) -> anyhow::Result<Self> {
+        let shaft_mask_pipeline = Self::create_fullscreen_pipeline(
+            device,
+            crate::shader::LIGHT_SHAFT_MASK_FRAG,
+            Self::MASK_FORMAT,
+            None,
+        )?;
+        let shaft_pipeline = Self::create_fullscreen_pipeline(
+            device,
+            crate::shader::LIGHT_SHAFT_FRAG,
+            color_format,
+            Some(BlendState::Additive),
+        )?;
+        let flare_occlusion_pipeline =
+            device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+                code: crate::shader::LENS_FLARE_OCCLUSION_COMP,
+                entry: "main",
+            })?;
+
+        let flare_pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::LENS_FLARE_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::LENS_FLARE_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format: color_format,
+                        blend: Some(BlendState::Additive),
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })?;
+
+        let lens_buffer = device
+            .create_buffer_init(
+                "LensEffectBuffer",
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                unsafe { slice_to_bytes_unsafe(&[LensEffectData::default()]) },
+            )
+            .context("Failed to create lens effect buffer")?;
+        let visibility_buffer = device
+            .create_buffer_init(
+                "LensFlareVisibilityBuffer",
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                unsafe { slice_to_bytes_unsafe(&[0.0f32]) },
+            )
+            .context("Failed to create lens flare visibility buffer")?;
+
+        let sampler = device.create_sampler(
+            "Light Shaft Sampler",
+            &SamplerDescription {
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                address_mode_w: AddressMode::ClampToEdge,
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+        )?;
+
+        Ok(Self {
+            settings,
+            shaft_mask_pipeline,
+            shaft_pipeline,
+            flare_occlusion_pipeline,
+            flare_pipeline,
+            lens_buffer,
+            visibility_buffer,
+            sampler,
+        })
+    }
+
+    fn create_fullscreen_pipeline(
+        device: &mut Device,
+        fragment_code: &[u32],
+        format: vk::Format,
+        blend: Option<BlendState>,
+    ) -> anyhow::Result<RasterPipelineHandle> {
+        Ok(
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: fragment_code,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format,
+                        blend,
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })?,
+        )
+    }
+
+    /// Adds the shafts and flare for the sky's sun onto `color_image`, the sun and view depth come from the blackboard.
+    /// Nothing is drawn while the sun is behind the camera
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &self,
+        camera: &SceneCamera,
+        color_image: ImageHandle,
+        render_graph_builder: &mut T,
+    ) {
+        if !self.settings.light_shafts && !self.settings.lens_flare {
+            return;
+        }
+
+        //Only drawn while the sky has a sun up, otherwise there's nothing to flare
+        let blackboard = render_graph_builder.blackboard();
+        let Some((&(sun_direction, sun_color), &depth_image)) = blackboard
+            .get::<(Vec3, Vec3)>(Sky::SUN_LIGHT_KEY)
+            .zip(blackboard.get::<ImageHandle>(SceneRenderer::VIEW_DEPTH_KEY))
+        else {
+            return;
+        };
+
+        //The sun is infinitely far away, so it's projected as a direction
+        let clip_position = camera.view_projection_matrix() * sun_direction.extend(0.0);
+        if clip_position.w <= 0.0 {
+            return;
+        }
+        let sun_position = clip_position.truncate().truncate() / clip_position.w;
+        let edge_distance = (sun_position.abs().max_element() - 1.0).max(0.0);
+        let screen_fade = 1.0 - (edge_distance / Self::SCREEN_FADE_DISTANCE).min(1.0);
+        if screen_fade <= 0.0 {
+            return;
+        }
+
+        let viewport_size = camera.view_data().viewport_size;
+        let lens_data = LensEffectData {
+            sun_screen: Vec4::new(
+                sun_position.x * 0.5 + 0.5,
+                sun_position.y * 0.5 + 0.5,
+                viewport_size.x / viewport_size.y,
+                screen_fade,
+            ),
+            sun_color: sun_color.extend(self.settings.flare_intensity),
+            shafts: Vec4::new(
+                self.settings.shaft_intensity,
+                self.settings.shaft_decay,
+                self.settings.shaft_length,
+                0.0,
+            ),
+        };
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: self.lens_buffer,
+                offset: 0,
+            },
+            std::mem::size_of::<LensEffectData>(),
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&[lens_data]) });
+            }),
+        );
+
+        if self.settings.light_shafts {
+            let mask_image = render_graph_builder.create_transient_image(TransientImageDesc {
+                size: TransientImageSize::Divided([Self::MASK_DIVISOR; 2], color_image),
+                format: Self::MASK_FORMAT,
+                usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                mip_levels: 1,
+                memory_location: MemoryLocation::GpuOnly,
+            });
+
+            let mut mask_pass_builder = RasterPassBuilder::new("Light Shaft Mask Pass");
+            mask_pass_builder.add_color_attachment(mask_image, Some([0.0; 4]));
+            let mut draw_command_builder = RasterDrawCommandBuilder::new(self.shaft_mask_pipeline);
+            draw_command_builder.read_buffer(camera.buffer());
+            draw_command_builder.read_buffer(self.lens_buffer);
+            draw_command_builder.read_sampled_image(depth_image);
+            draw_command_builder.read_sampler(self.sampler);
+            draw_command_builder.draw(0..3, 0..1);
+            draw_command_builder.build(&mut mask_pass_builder);
+            mask_pass_builder.build(render_graph_builder);
+
+            let mut shaft_pass_builder = RasterPassBuilder::new("Light Shaft Pass");
+            shaft_pass_builder.add_color_attachment(color_image, None);
+            let mut draw_command_builder = RasterDrawCommandBuilder::new(self.shaft_pipeline);
+            draw_command_builder.read_buffer(self.lens_buffer);
+            draw_command_builder.read_sampled_image(mask_image);
+            draw_command_builder.read_sampler(self.sampler);
+            draw_command_builder.draw(0..3, 0..1);
+            draw_command_builder.build(&mut shaft_pass_builder);
+            shaft_pass_builder.build(render_graph_builder);
+        }
+
+        if self.settings.lens_flare {
+            let mut occlusion_pass_builder = ComputePassBuilder::new(
+                "Lens Flare Occlusion Pass",
+                QueueType::Graphics,
+                self.flare_occlusion_pipeline,
+            );
+            occlusion_pass_builder.read_buffer(camera.buffer());
+            occlusion_pass_builder.read_buffer(self.lens_buffer);
+            occlusion_pass_builder.read_sampled_image(depth_image);
+            occlusion_pass_builder.read_sampler(self.sampler);
+            occlusion_pass_builder.write_buffer(self.visibility_buffer);
+            occlusion_pass_builder.dispatch_size([1, 1, 1]);
+            occlusion_pass_builder.build(render_graph_builder);
+
+            let mut flare_pass_builder = RasterPassBuilder::new("Lens Flare Pass");
+            flare_pass_builder.add_color_attachment(color_image, None);
+            let mut draw_command_builder = RasterDrawCommandBuilder::new(self.flare_pipeline);
+            draw_command_builder.read_buffer(self.lens_buffer);
+            draw_command_builder.read_buffer(self.visibility_buffer);
+            draw_command_builder.draw(0..6, 0..Self::FLARE_ELEMENT_COUNT);
+            draw_command_builder.build(&mut flare_pass_builder);
+            flare_pass_builder.build(render_graph_builder);
+        }
+    }
+}
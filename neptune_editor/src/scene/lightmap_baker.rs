@@ -0,0 +1,183 @@
+use crate::lightmap::LightmapLayout;
+use crate::material::MaterialTexture;
+use crate::scene::path_tracer::{PathTracer, PathTracerScene, PathTracerSettings};
+use crate::scene::scene_renderer::{slice_to_bytes_unsafe, Scene};
+use glam::Mat4;
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph::QueueType;
+use neptune_vulkan::render_graph_builder::{ComputePassBuilder, RenderGraphBuilderTrait};
+use neptune_vulkan::{
+    vk, AddressMode, BufferHandle, BufferUsage, ComputePipelineHandle, Device, FilterMode,
+    ImageHandle, SamplerHandle,
+};
+
+struct LightmapBake {
+    texel_buffer: BufferHandle,
+    image: ImageHandle,
+    size: [u32; 2],
+    sample_index: u32,
+}
+
+/// Path traces lightmaps on the gpu, one sample per texel each frame until they converge.
+/// Lighting comes from the scene and path tracer settings as they were when the bake started
+pub struct LightmapBaker {
+    pub sample_count: u32,
+
+    bake_pipeline: ComputePipelineHandle,
+    sampler: SamplerHandle,
+    bakes: Vec<LightmapBake>,
+    scene: Option<PathTracerScene>,
+    scene_dirty: bool,
+    settings: PathTracerSettings,
+}
+
+impl LightmapBaker {
+    const FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+    pub fn new(device: &mut Device) -> anyhow::Result<Self> {
+        let bake_pipeline = device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+            code: crate::shader::LIGHTMAP_BAKE_COMP,
+            entry: "main",
+        })?;
+
+        let sampler = device.create_sampler(
+            "Lightmap Sampler",
+            &neptune_vulkan::SamplerDescription {
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                address_mode_w: AddressMode::ClampToEdge,
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+        )?;
+
+        Ok(Self {
+            sample_count: 256,
+            bake_pipeline,
+            sampler,
+            bakes: Vec::new(),
+            scene: None,
+            scene_dirty: false,
+            settings: PathTracerSettings::default(),
+        })
+    }
+
+    /// Creates the lightmap for a primitive placed at `transform` and queues its bake, the texture can be bound right away.
+    /// Every unfinished bake restarts so the new geometry shows up in all of them
+    pub fn add(
+        &mut self,
+        device: &mut Device,
+        name: &str,
+        layout: &LightmapLayout,
+        transform: &Mat4,
+    ) -> anyhow::Result<MaterialTexture> {
+        let texel_buffer = device.create_buffer_init(
+            "Lightmap Texels",
+            BufferUsage::STORAGE | BufferUsage::TRANSFER,
+            MemoryLocation::GpuOnly,
+            unsafe { slice_to_bytes_unsafe(&layout.texels(transform)) },
+        )?;
+        let image = device.create_image(
+            name,
+            &neptune_vulkan::ImageDescription2D {
+                size: layout.size,
+                format: Self::FORMAT,
+                usage: vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+                mip_levels: 1,
+                location: MemoryLocation::GpuOnly,
+            },
+        )?;
+
+        self.bakes.push(LightmapBake {
+            texel_buffer,
+            image,
+            size: layout.size,
+            sample_index: 0,
+        });
+        self.scene_dirty = true;
+
+        Ok(MaterialTexture {
+            image,
+            sampler: self.sampler,
+            uv_index: 1,
+        })
+    }
+
+    /// Snapshots the scene when bakes were added, frees it once they have all converged
+    pub fn update(
+        &mut self,
+        device: &mut Device,
+        scene: &Scene,
+        settings: &PathTracerSettings,
+    ) -> anyhow::Result<()> {
+        let sample_count = self.sample_count;
+        for bake in self.bakes.iter() {
+            if bake.sample_index >= sample_count {
+                device.destroy_buffer(bake.texel_buffer);
+            }
+        }
+        let was_baking = !self.bakes.is_empty();
+        self.bakes.retain(|bake| bake.sample_index < sample_count);
+        if was_baking && self.bakes.is_empty() {
+            info!("Finished baking lightmaps");
+        }
+
+        if self.scene_dirty || self.bakes.is_empty() {
+            if let Some(old_scene) = self.scene.take() {
+                old_scene.destroy(device);
+            }
+        }
+
+        if self.scene_dirty && !self.bakes.is_empty() {
+            self.scene_dirty = false;
+            self.scene = Some(PathTracer::build_scene(device, scene)?);
+            self.settings = settings.clone();
+            for bake in self.bakes.iter_mut() {
+                bake.sample_index = 0;
+            }
+            info!("Baking {} lightmaps", self.bakes.len());
+        }
+
+        Ok(())
+    }
+
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        render_graph_builder: &mut T,
+    ) {
+        let Some(scene) = &self.scene else {
+            return;
+        };
+
+        let settings = &self.settings;
+        for bake in self.bakes.iter_mut() {
+            let mut bake_pass_builder = ComputePassBuilder::new(
+                "Lightmap Bake Pass",
+                QueueType::Graphics,
+                self.bake_pipeline,
+            );
+            bake_pass_builder.read_buffer(scene.triangle_buffer);
+            bake_pass_builder.read_buffer(scene.bvh_buffer);
+            bake_pass_builder.read_buffer(bake.texel_buffer);
+            bake_pass_builder.write_storage_image(bake.image);
+            bake_pass_builder.push_constant(bake.sample_index);
+            bake_pass_builder.push_constant(settings.max_bounces);
+            bake_pass_builder.push_constant(scene.node_count);
+            bake_pass_builder.push_constant(0);
+            for vector in [
+                settings.sun_direction.normalize_or_zero(),
+                settings.sun_color,
+                settings.sky_color,
+            ] {
+                for value in vector.extend(0.0).to_array() {
+                    bake_pass_builder.push_constant(value.to_bits());
+                }
+            }
+            bake_pass_builder.dispatch_threads([bake.size[0], bake.size[1], 1]);
+            bake_pass_builder.build(render_graph_builder);
+
+            bake.sample_index += 1;
+        }
+    }
+}
@@ -1 +1,28 @@
+pub mod cloth;
+pub mod color_grading;
+pub mod draw_list;
+pub mod foliage;
+pub mod gpu_primitives;
+pub mod lens_effects;
+pub mod lightmap_baker;
+pub mod particles;
+pub mod path_tracer;
+pub mod post_effects;
+pub mod post_volumes;
+pub mod reduced_resolution;
+pub mod reflection_probes;
+pub mod render_texture;
+pub mod resize_targets;
 pub mod scene_renderer;
+pub mod selection_outline;
+pub mod skinning;
+pub mod sky;
+pub mod spatial;
+pub mod sprite_renderer;
+pub mod ui_compositor;
+pub mod upscaler;
+pub mod viewport_helpers;
+pub mod visibility;
+pub mod volumetric_fog;
+pub mod voxel_gi;
+pub mod water;
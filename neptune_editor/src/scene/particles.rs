@@ -0,0 +1,405 @@
+use crate::buffer_inspector::{BufferElement, InspectableBuffer};
+use crate::scene::gpu_primitives::GpuPrimitives;
+use crate::scene::scene_renderer::{slice_to_bytes_unsafe, SceneCamera};
+use anyhow::Context;
+use glam::{Vec3, Vec4};
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph::QueueType;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, ComputePassBuilder, RasterDrawCommandBuilder,
+    RasterPassBuilder, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{
+    vk, BlendState, BufferHandle, BufferUsage, ComputePipelineHandle, DepthMode, Device,
+    ImageHandle, RasterPipelineHandle, SamplerDescription, SamplerHandle,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ParticleBlend {
+    /// Order independent, so no sorting is needed
+    Additive,
+    /// Sorted back to front on the gpu every frame
+    AlphaBlend,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParticleSettings {
+    pub enabled: bool,
+    pub emitter_position: Vec3,
+    pub spawn_rate: f32,
+    pub spawn_velocity: Vec3,
+    /// Random velocity added to `spawn_velocity` in every direction
+    pub velocity_spread: f32,
+    pub gravity: Vec3,
+    /// Seconds a particle lives for
+    pub lifetime: f32,
+    pub size: f32,
+    pub color: Vec4,
+    pub blend: ParticleBlend,
+    /// Collide with the depth buffer, particles that are off screen or hidden behind other surfaces don't collide
+    pub depth_collision: bool,
+    /// How much of the velocity into a surface is kept after a bounce, 0 slides along it
+    pub bounce: f32,
+    /// How much of the velocity along a surface is lost on contact
+    pub friction: f32,
+    /// How far behind the depth buffer a particle can be and still collide, anything further is treated as hidden behind the surface
+    pub collision_thickness: f32,
+}
+
+impl Default for ParticleSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            emitter_position: Vec3::ZERO,
+            spawn_rate: 1024.0,
+            spawn_velocity: Vec3::new(0.0, 4.0, 0.0),
+            velocity_spread: 2.0,
+            gravity: Vec3::new(0.0, -9.8, 0.0),
+            lifetime: 4.0,
+            size: 0.05,
+            color: Vec4::new(1.0, 0.6, 0.2, 1.0),
+            blend: ParticleBlend::Additive,
+            depth_collision: true,
+            bounce: 0.4,
+            friction: 0.1,
+            collision_thickness: 0.5,
+        }
+    }
+}
+
+/// Matches Particle in particle_simulate.comp and particle.vert
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct Particle {
+    /// w: age in seconds
+    position_age: Vec4,
+    /// w: lifetime in seconds, dead particles have a lifetime of 0
+    velocity_lifetime: Vec4,
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct ParticleData {
+    /// w: delta time
+    emitter_position: Vec4,
+    /// w: velocity spread
+    spawn_velocity: Vec4,
+    /// w: lifetime
+    gravity: Vec4,
+    color: Vec4,
+    /// x: bounce, y: friction, z: thickness, w: 1 if depth collision is enabled
+    collision: Vec4,
+    /// x: first particle to spawn, y: spawn count, z: capacity, w: random seed
+    spawn: [u32; 4],
+    /// x: size
+    draw: Vec4,
+}
+
+/// Emitter simulated in a compute pass, particles bounce off the scene using the view's depth buffer.
+/// Particles live in a ring buffer, each frame respawns the oldest slots so no free list is needed
+pub struct ParticleSystem {
+    pub settings: ParticleSettings,
+
+    capacity: u32,
+    simulate_pipeline: ComputePipelineHandle,
+    sort_keys_pipeline: ComputePipelineHandle,
+    additive_draw_pipeline: RasterPipelineHandle,
+    alpha_blend_draw_pipeline: RasterPipelineHandle,
+    particle_buffer: BufferHandle,
+    particle_data_buffer: BufferHandle,
+    depth_sampler: SamplerHandle,
+
+    delta_time: f32,
+    spawn_accumulator: f32,
+    spawn_offset: u32,
+    frame_index: u32,
+}
+
+impl ParticleSystem {
+    pub fn inspectable_buffer(&self) -> InspectableBuffer {
+        InspectableBuffer {
+            name: "Particles".to_string(),
+            buffer: self.particle_buffer,
+            size: self.capacity as usize * std::mem::size_of::<Particle>(),
+            element: BufferElement::F32,
+        }
+    }
+
+    pub fn new(
+        device: &mut Device,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+        depth_mode: DepthMode,
+        capacity: u32,
+        settings: ParticleSettings,
+    ) -> anyhow::Result<Self> {
+        let simulate_pipeline = device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+            code: crate::shader::PARTICLE_SIMULATE_COMP,
+            entry: "main",
+        })?;
+
+        let sort_keys_pipeline = device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+            code: crate::shader::PARTICLE_SORT_KEYS_COMP,
+            entry: "main",
+        })?;
+
+        let mut create_draw_pipeline = |blend| {
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::PARTICLE_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: Some(neptune_vulkan::DepthState {
+                    format: depth_format,
+                    depth_enabled: true,
+                    write_depth: false,
+                    depth_op: depth_mode.compare_op(vk::CompareOp::LESS),
+                }),
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::PARTICLE_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format: color_format,
+                        blend: Some(blend),
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })
+        };
+        let additive_draw_pipeline = create_draw_pipeline(BlendState::Additive)?;
+        let alpha_blend_draw_pipeline = create_draw_pipeline(BlendState::AlphaBlend)?;
+
+        let particle_buffer = device
+            .create_buffer_init(
+                "ParticleBuffer",
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                unsafe { slice_to_bytes_unsafe(&vec![Particle::default(); capacity as usize]) },
+            )
+            .context("Failed to create particle buffer")?;
+
+        let particle_data_buffer = device
+            .create_buffer_init(
+                "ParticleDataBuffer",
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                unsafe { slice_to_bytes_unsafe(&[ParticleData::default()]) },
+            )
+            .context("Failed to create particle data buffer")?;
+
+        //Only used with texelFetch, so the filtering doesn't matter
+        let depth_sampler =
+            device.create_sampler("Particle Depth Sampler", &SamplerDescription::default())?;
+
+        Ok(Self {
+            settings,
+            capacity,
+            simulate_pipeline,
+            sort_keys_pipeline,
+            additive_draw_pipeline,
+            alpha_blend_draw_pipeline,
+            particle_buffer,
+            particle_data_buffer,
+            depth_sampler,
+            delta_time: 0.0,
+            spawn_accumulator: 0.0,
+            spawn_offset: 0,
+            frame_index: 0,
+        })
+    }
+
+    /// Called once per frame with the game time step, so pausing the game freezes the particles
+    pub fn update(&mut self, delta_time: f32) {
+        self.delta_time = delta_time;
+    }
+
+    /// u32 vertex_count, instance_count, first_vertex and first_instance
+    const DRAW_COMMAND_SIZE: usize = 16;
+
+    /// Simulates against `depth_image` from the view's scene pass, then draws the live particles over `color_image`
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        gpu_primitives: &GpuPrimitives,
+        camera: &SceneCamera,
+        color_image: ImageHandle,
+        depth_image: ImageHandle,
+        render_graph_builder: &mut T,
+    ) {
+        if !self.settings.enabled {
+            return;
+        }
+
+        self.spawn_accumulator += self.settings.spawn_rate.max(0.0) * self.delta_time;
+        let spawn_count = (self.spawn_accumulator as u32).min(self.capacity);
+        self.spawn_accumulator -= spawn_count as f32;
+
+        let settings = &self.settings;
+        let particle_data = ParticleData {
+            emitter_position: settings.emitter_position.extend(self.delta_time),
+            spawn_velocity: settings.spawn_velocity.extend(settings.velocity_spread),
+            gravity: settings.gravity.extend(settings.lifetime.max(0.0)),
+            color: settings.color,
+            collision: Vec4::new(
+                settings.bounce.clamp(0.0, 1.0),
+                settings.friction.clamp(0.0, 1.0),
+                settings.collision_thickness.max(0.0),
+                settings.depth_collision as u32 as f32,
+            ),
+            spawn: [
+                self.spawn_offset,
+                spawn_count,
+                self.capacity,
+                self.frame_index,
+            ],
+            draw: Vec4::new(settings.size, 0.0, 0.0, 0.0),
+        };
+        self.spawn_offset = (self.spawn_offset + spawn_count) % self.capacity;
+        self.frame_index = self.frame_index.wrapping_add(1);
+
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: self.particle_data_buffer,
+                offset: 0,
+            },
+            std::mem::size_of::<ParticleData>(),
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&[particle_data]) });
+            }),
+        );
+
+        let [alive_flags, slot_indices, alive_indices] = [0; 3].map(|_| {
+            render_graph_builder.create_transient_buffer(
+                self.capacity as usize * std::mem::size_of::<u32>(),
+                BufferUsage::STORAGE,
+                MemoryLocation::GpuOnly,
+            )
+        });
+        let draw_buffer = render_graph_builder.create_transient_buffer(
+            Self::DRAW_COMMAND_SIZE,
+            BufferUsage::STORAGE | BufferUsage::INDIRECT | BufferUsage::TRANSFER,
+            MemoryLocation::GpuOnly,
+        );
+
+        let mut simulate_pass_builder = ComputePassBuilder::new(
+            "Particle Simulate Pass",
+            QueueType::Graphics,
+            self.simulate_pipeline,
+        );
+        simulate_pass_builder.read_buffer(camera.buffer());
+        simulate_pass_builder.read_buffer(self.particle_data_buffer);
+        simulate_pass_builder.write_buffer(self.particle_buffer);
+        simulate_pass_builder.read_sampled_image(depth_image);
+        simulate_pass_builder.read_sampler(self.depth_sampler);
+        simulate_pass_builder.write_buffer(alive_flags);
+        simulate_pass_builder.write_buffer(slot_indices);
+        simulate_pass_builder.dispatch_threads([self.capacity, 1, 1]);
+        simulate_pass_builder.build(render_graph_builder);
+
+        //Only live particles are drawn, the compaction fills in the instance count
+        let draw_command: [u32; 4] = [6, 0, 0, 0];
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: draw_buffer,
+                offset: 0,
+            },
+            Self::DRAW_COMMAND_SIZE,
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&draw_command) });
+            }),
+        );
+        gpu_primitives.compact(
+            render_graph_builder,
+            slot_indices,
+            alive_flags,
+            self.capacity,
+            alive_indices,
+            BufferOffset {
+                buffer: draw_buffer,
+                offset: 4,
+            },
+        );
+
+        let (draw_indices, draw_pipeline) = match self.settings.blend {
+            ParticleBlend::Additive => (alive_indices, self.additive_draw_pipeline),
+            ParticleBlend::AlphaBlend => (
+                self.write_sort_passes(
+                    gpu_primitives,
+                    camera,
+                    alive_indices,
+                    draw_buffer,
+                    render_graph_builder,
+                ),
+                self.alpha_blend_draw_pipeline,
+            ),
+        };
+
+        let mut draw_pass_builder = RasterPassBuilder::new("Particle Draw Pass");
+        draw_pass_builder.add_color_attachment(color_image, None);
+        draw_pass_builder.add_depth_stencil_attachment(depth_image, None);
+        let mut draw_command_builder = RasterDrawCommandBuilder::new(draw_pipeline);
+        draw_command_builder.read_buffer(camera.buffer());
+        draw_command_builder.read_buffer(self.particle_data_buffer);
+        draw_command_builder.read_buffer(self.particle_buffer);
+        draw_command_builder.read_buffer(draw_indices);
+        draw_command_builder.draw_indirect(
+            BufferOffset {
+                buffer: draw_buffer,
+                offset: 0,
+            },
+            1,
+            Self::DRAW_COMMAND_SIZE as u32,
+        );
+        draw_command_builder.build(&mut draw_pass_builder);
+        draw_pass_builder.build(render_graph_builder);
+    }
+
+    /// Returns the live particle slots ordered back to front, the unused entries after `alive_count` stay at the end
+    fn write_sort_passes<T: RenderGraphBuilderTrait>(
+        &self,
+        gpu_primitives: &GpuPrimitives,
+        camera: &SceneCamera,
+        alive_indices: BufferHandle,
+        draw_buffer: BufferHandle,
+        render_graph_builder: &mut T,
+    ) -> BufferHandle {
+        let [sort_keys, sorted_indices] = [0; 2].map(|_| {
+            render_graph_builder.create_transient_buffer(
+                self.capacity as usize * std::mem::size_of::<u32>(),
+                BufferUsage::STORAGE,
+                MemoryLocation::GpuOnly,
+            )
+        });
+
+        let mut sort_keys_pass_builder = ComputePassBuilder::new(
+            "Particle Sort Keys Pass",
+            QueueType::Graphics,
+            self.sort_keys_pipeline,
+        );
+        sort_keys_pass_builder.read_buffer(camera.buffer());
+        sort_keys_pass_builder.read_buffer(self.particle_buffer);
+        sort_keys_pass_builder.read_buffer(alive_indices);
+        sort_keys_pass_builder.read_buffer(draw_buffer);
+        sort_keys_pass_builder.write_buffer(sort_keys);
+        sort_keys_pass_builder.write_buffer(sorted_indices);
+        sort_keys_pass_builder.push_constant(self.capacity);
+        sort_keys_pass_builder.dispatch_threads([self.capacity, 1, 1]);
+        sort_keys_pass_builder.build(render_graph_builder);
+
+        gpu_primitives.radix_sort(
+            render_graph_builder,
+            sort_keys,
+            sorted_indices,
+            self.capacity,
+        );
+        sorted_indices
+    }
+}
@@ -0,0 +1,345 @@
+use crate::mesh::BoundingBox;
+use crate::scene::resize_targets::{ResizeTargetDescription, ResizeTargetHandle, ResizeTargets};
+use crate::scene::scene_renderer::{slice_to_bytes_unsafe, Scene, SceneCamera};
+use crate::scene::spatial::{Bvh, FlatBvhNode};
+use glam::{Mat4, UVec4, Vec3, Vec4};
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph::QueueType;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, ComputePassBuilder, RasterDrawCommandBuilder,
+    RasterPassBuilder, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{
+    vk, BufferHandle, BufferUsage, ComputePipelineHandle, Device, ImageHandle, RasterPipelineHandle,
+};
+
+#[derive(Debug, Clone)]
+pub struct PathTracerSettings {
+    /// Direction towards the sun
+    pub sun_direction: Vec3,
+    pub sun_color: Vec3,
+    pub sky_color: Vec3,
+    pub max_bounces: u32,
+    /// Accumulation stops once this many samples per pixel are reached
+    pub max_samples: u32,
+}
+
+impl Default for PathTracerSettings {
+    fn default() -> Self {
+        Self {
+            sun_direction: Vec3::new(0.3, 1.0, 0.2).normalize(),
+            sun_color: Vec3::splat(1.0),
+            sky_color: Vec3::new(0.3, 0.4, 0.5),
+            max_bounces: 4,
+            max_samples: 4096,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct PathTracerData {
+    sun_direction: Vec4,
+    sun_color: Vec4,
+    sky_color: Vec4,
+    params: UVec4,
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct PathTracerTriangle {
+    position0_albedo_r: Vec4,
+    position1_albedo_g: Vec4,
+    position2_albedo_b: Vec4,
+    emissive: Vec4,
+}
+
+/// Triangles and bvh of every instance, also traced by the lightmap baker
+pub(crate) struct PathTracerScene {
+    pub(crate) triangle_buffer: BufferHandle,
+    pub(crate) bvh_buffer: BufferHandle,
+    pub(crate) node_count: u32,
+    version: u64,
+}
+
+impl PathTracerScene {
+    pub(crate) fn destroy(self, device: &mut Device) {
+        device.destroy_buffer(self.triangle_buffer);
+        device.destroy_buffer(self.bvh_buffer);
+    }
+}
+
+/// Progressive compute path tracer used as a ground truth reference for the raster renderer
+pub struct PathTracer {
+    pub settings: PathTracerSettings,
+
+    trace_pipeline: ComputePipelineHandle,
+    display_pipeline: RasterPipelineHandle,
+    settings_buffer: BufferHandle,
+
+    scene: Option<PathTracerScene>,
+    accumulation_image: Option<ResizeTargetHandle>,
+    accumulation_generation: Option<u64>,
+    last_view_projection_matrix: Mat4,
+    sample_index: u32,
+}
+
+impl PathTracer {
+    const ACCUMULATION_FORMAT: vk::Format = vk::Format::R32G32B32A32_SFLOAT;
+
+    pub fn new(device: &mut Device, output_format: vk::Format) -> anyhow::Result<Self> {
+        let trace_pipeline = device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+            code: crate::shader::PATH_TRACER_COMP,
+            entry: "main",
+        })?;
+
+        let display_pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::PATH_TRACER_DISPLAY_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format: output_format,
+                        blend: None,
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })?;
+
+        let settings_buffer = device.create_buffer_init(
+            "PathTracerBuffer",
+            BufferUsage::STORAGE | BufferUsage::TRANSFER,
+            MemoryLocation::GpuOnly,
+            unsafe { slice_to_bytes_unsafe(&[PathTracerData::default()]) },
+        )?;
+
+        Ok(Self {
+            settings: PathTracerSettings::default(),
+            trace_pipeline,
+            display_pipeline,
+            settings_buffer,
+            scene: None,
+            accumulation_image: None,
+            accumulation_generation: None,
+            last_view_projection_matrix: Mat4::ZERO,
+            sample_index: 0,
+        })
+    }
+
+    pub fn reset(&mut self) {
+        self.sample_index = 0;
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_index
+    }
+
+    /// Rebuilds the gpu scene when needed, any change to the scene, camera or size restarts accumulation
+    pub fn update(
+        &mut self,
+        device: &mut Device,
+        resize_targets: &mut ResizeTargets,
+        scene: &Scene,
+        camera: &SceneCamera,
+    ) -> anyhow::Result<()> {
+        if self.scene.as_ref().map(|gpu_scene| gpu_scene.version) != Some(scene.version()) {
+            if let Some(old_scene) = self.scene.take() {
+                old_scene.destroy(device);
+            }
+            self.scene = Some(Self::build_scene(device, scene)?);
+            self.reset();
+        }
+
+        let accumulation_image = match self.accumulation_image {
+            Some(accumulation_image) => accumulation_image,
+            None => *self.accumulation_image.insert(resize_targets.add(
+                device,
+                ResizeTargetDescription {
+                    name: "Path Tracer Accumulation".to_string(),
+                    scale: [1.0; 2],
+                    format: Self::ACCUMULATION_FORMAT,
+                    usage: vk::ImageUsageFlags::STORAGE,
+                    mip_levels: 1,
+                },
+            )?),
+        };
+        let accumulation_generation = resize_targets.generation(accumulation_image);
+        if accumulation_generation != self.accumulation_generation {
+            self.accumulation_generation = accumulation_generation;
+            self.reset();
+        }
+
+        let view_projection_matrix = camera.view_projection_matrix();
+        if view_projection_matrix != self.last_view_projection_matrix {
+            self.last_view_projection_matrix = view_projection_matrix;
+            self.reset();
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn build_scene(
+        device: &mut Device,
+        scene: &Scene,
+    ) -> anyhow::Result<PathTracerScene> {
+        let mut triangles = Vec::new();
+        for (transform, model) in scene.instances() {
+            let model_matrix = transform.model_matrix();
+            for model_primitive in model.primitives.iter() {
+                //TODO: sample the material textures instead of only using the factors
+                let (albedo, emissive) = model_primitive
+                    .material
+                    .as_ref()
+                    .map(|material| (material.base_color.truncate(), material.emissive_color))
+                    .unwrap_or((Vec3::splat(0.8), Vec3::ZERO));
+
+                let geometry = &model_primitive.primitive.geometry;
+                for indices in geometry.indices.chunks_exact(3) {
+                    let [a, b, c] = [0, 1, 2].map(|corner| {
+                        model_matrix.transform_point3(geometry.positions[indices[corner] as usize])
+                    });
+                    triangles.push(PathTracerTriangle {
+                        position0_albedo_r: a.extend(albedo.x),
+                        position1_albedo_g: b.extend(albedo.y),
+                        position2_albedo_b: c.extend(albedo.z),
+                        emissive: emissive.extend(0.0),
+                    });
+                }
+            }
+        }
+
+        let bvh = Bvh::build(
+            triangles
+                .iter()
+                .enumerate()
+                .map(|(index, triangle)| {
+                    let [a, b, c] = [
+                        triangle.position0_albedo_r.truncate(),
+                        triangle.position1_albedo_g.truncate(),
+                        triangle.position2_albedo_b.truncate(),
+                    ];
+                    (
+                        index,
+                        BoundingBox {
+                            min: a.min(b).min(c),
+                            max: a.max(b).max(c),
+                        },
+                    )
+                })
+                .collect(),
+        );
+
+        //Leaves index the bvh's item order
+        let mut sorted_triangles: Vec<PathTracerTriangle> = bvh
+            .items()
+            .iter()
+            .map(|(index, _)| triangles[*index])
+            .collect();
+        let mut nodes = bvh.flatten();
+        let node_count = nodes.len() as u32;
+
+        //Empty buffers can't be created
+        if sorted_triangles.is_empty() {
+            sorted_triangles.push(PathTracerTriangle::default());
+        }
+        if nodes.is_empty() {
+            nodes.push(FlatBvhNode::default());
+        }
+
+        Ok(PathTracerScene {
+            triangle_buffer: device.create_buffer_init(
+                "PathTracerTriangles",
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                unsafe { slice_to_bytes_unsafe(&sorted_triangles) },
+            )?,
+            bvh_buffer: device.create_buffer_init(
+                "PathTracerBvh",
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                unsafe { slice_to_bytes_unsafe(&nodes) },
+            )?,
+            node_count,
+            version: scene.version(),
+        })
+    }
+
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        resize_targets: &ResizeTargets,
+        target_image: ImageHandle,
+        camera: &SceneCamera,
+        render_graph_builder: &mut T,
+    ) {
+        let accumulation_target = self.accumulation_image;
+        let (Some(gpu_scene), Some(accumulation_image), Some(size)) = (
+            &self.scene,
+            accumulation_target.and_then(|target| resize_targets.image(target)),
+            accumulation_target.and_then(|target| resize_targets.size(target)),
+        ) else {
+            warn!("PathTracer::update must be called before rendering");
+            return;
+        };
+
+        if self.sample_index < self.settings.max_samples {
+            let data = PathTracerData {
+                sun_direction: self.settings.sun_direction.normalize_or_zero().extend(0.0),
+                sun_color: self.settings.sun_color.extend(0.0),
+                sky_color: self.settings.sky_color.extend(0.0),
+                params: UVec4::new(
+                    self.sample_index,
+                    self.settings.max_bounces,
+                    gpu_scene.node_count,
+                    0,
+                ),
+            };
+            render_graph_builder.add_buffer_write(
+                BufferOffset {
+                    buffer: self.settings_buffer,
+                    offset: 0,
+                },
+                std::mem::size_of::<PathTracerData>(),
+                BufferWriteCallback::new(move |slice| {
+                    slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&[data]) });
+                }),
+            );
+
+            let mut trace_pass_builder = ComputePassBuilder::new(
+                "Path Trace Pass",
+                QueueType::Graphics,
+                self.trace_pipeline,
+            );
+            trace_pass_builder.read_buffer(camera.buffer());
+            trace_pass_builder.read_buffer(self.settings_buffer);
+            trace_pass_builder.read_buffer(gpu_scene.triangle_buffer);
+            trace_pass_builder.read_buffer(gpu_scene.bvh_buffer);
+            trace_pass_builder.write_storage_image(accumulation_image);
+            trace_pass_builder.dispatch_threads([size[0], size[1], 1]);
+            trace_pass_builder.build(render_graph_builder);
+
+            self.sample_index += 1;
+        }
+
+        let mut display_pass_builder = RasterPassBuilder::new("Path Tracer Display Pass");
+        display_pass_builder.add_color_attachment(target_image, None);
+        let mut draw_command_builder = RasterDrawCommandBuilder::new(self.display_pipeline);
+        draw_command_builder.read_storage_image(accumulation_image);
+        draw_command_builder.draw(0..3, 0..1);
+        draw_command_builder.build(&mut display_pass_builder);
+        display_pass_builder.build(render_graph_builder);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::scene::scene_renderer::slice_to_bytes_unsafe;
+use anyhow::Context;
+use glam::Vec4;
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, RasterDrawCommandBuilder, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{BufferHandle, BufferUsage, Device};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VignetteSettings {
+    pub enabled: bool,
+    /// How dark the corners get, 0 to 1
+    pub intensity: f32,
+    /// Distance from the center where darkening starts, 1 is the corner
+    pub radius: f32,
+    /// Width of the falloff from `radius`
+    pub smoothness: f32,
+}
+
+impl Default for VignetteSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intensity: 0.4,
+            radius: 0.5,
+            smoothness: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChromaticAberrationSettings {
+    pub enabled: bool,
+    /// Red and blue offset in pixels at the corners, grows linearly from the center
+    pub intensity: f32,
+}
+
+impl Default for ChromaticAberrationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intensity: 3.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilmGrainSettings {
+    pub enabled: bool,
+    pub intensity: f32,
+    /// How much less grain bright pixels get, 0 applies it evenly
+    pub luminance_response: f32,
+}
+
+impl Default for FilmGrainSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intensity: 0.05,
+            luminance_response: 0.8,
+        }
+    }
+}
+
+/// Artistic full screen effects applied in the final post pass, stored with the scene so each scene keeps its own look
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostEffectSettings {
+    /// In stops, scales the color before the rest of the post stack
+    pub exposure: f32,
+    pub vignette: VignetteSettings,
+    pub chromatic_aberration: ChromaticAberrationSettings,
+    pub film_grain: FilmGrainSettings,
+}
+
+impl PostEffectSettings {
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file_content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read post effects {}", path.display()))?;
+        toml::from_str(&file_content)
+            .with_context(|| format!("Failed to parse post effects {}", path.display()))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write post effects {}", path.display()))
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct PostEffectData {
+    /// x: intensity, y: radius, z: smoothness, all 0 when disabled
+    vignette: Vec4,
+    /// x: pixel offset
+    chromatic_aberration: Vec4,
+    /// x: intensity, y: luminance response, z: frame seed
+    film_grain: Vec4,
+    /// x: color multiplier
+    exposure: Vec4,
+}
+
+impl PostEffectData {
+    fn new(settings: &PostEffectSettings, frame_index: u32) -> Self {
+        let vignette = &settings.vignette;
+        let chromatic_aberration = &settings.chromatic_aberration;
+        let film_grain = &settings.film_grain;
+        Self {
+            vignette: if vignette.enabled {
+                Vec4::new(
+                    vignette.intensity.clamp(0.0, 1.0),
+                    vignette.radius,
+                    vignette.smoothness.max(f32::EPSILON),
+                    0.0,
+                )
+            } else {
+                Vec4::ZERO
+            },
+            chromatic_aberration: if chromatic_aberration.enabled {
+                Vec4::new(chromatic_aberration.intensity, 0.0, 0.0, 0.0)
+            } else {
+                Vec4::ZERO
+            },
+            film_grain: if film_grain.enabled {
+                Vec4::new(
+                    film_grain.intensity,
+                    film_grain.luminance_response,
+                    (frame_index % 1024) as f32,
+                    0.0,
+                )
+            } else {
+                Vec4::ZERO
+            },
+            exposure: Vec4::new(settings.exposure.exp2(), 0.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// Uploads a scene's post effect settings for the final post pass
+pub struct PostEffects {
+    post_effect_buffer: BufferHandle,
+    frame_index: u32,
+}
+
+impl PostEffects {
+    pub fn new(device: &mut Device) -> anyhow::Result<Self> {
+        let post_effect_buffer = device
+            .create_buffer_init(
+                "PostEffectBuffer",
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                unsafe { slice_to_bytes_unsafe(&[PostEffectData::default()]) },
+            )
+            .context("Failed to create post effect buffer")?;
+
+        Ok(Self {
+            post_effect_buffer,
+            frame_index: 0,
+        })
+    }
+
+    /// Called once per frame, every view of the frame shares the same settings
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        settings: &PostEffectSettings,
+        render_graph_builder: &mut T,
+    ) {
+        let post_effect_data = PostEffectData::new(settings, self.frame_index);
+        self.frame_index = self.frame_index.wrapping_add(1);
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: self.post_effect_buffer,
+                offset: 0,
+            },
+            std::mem::size_of::<PostEffectData>(),
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&[post_effect_data]) });
+            }),
+        );
+    }
+
+    pub fn bind(&self, draw_command_builder: &mut RasterDrawCommandBuilder) {
+        draw_command_builder.read_buffer(self.post_effect_buffer);
+    }
+}
@@ -0,0 +1,94 @@
+use glam::Vec3;
+
+/// Values a volume replaces while the camera is inside it, None leaves the value alone
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PostVolumeOverrides {
+    /// In stops, see `PostEffectSettings::exposure`
+    pub exposure: Option<f32>,
+    pub fog_density: Option<f32>,
+    /// Index into the color grading luts
+    pub lut: Option<usize>,
+}
+
+/// Box placed in a `Scene` that overrides post processing and lighting values when the camera is in it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostProcessVolume {
+    pub center: Vec3,
+    /// Half size of the box
+    pub extents: Vec3,
+    /// Distance outside the box over which the overrides fade out
+    pub blend_distance: f32,
+    /// Higher priority volumes are applied on top of lower ones where they overlap
+    pub priority: i32,
+    pub overrides: PostVolumeOverrides,
+}
+
+impl Default for PostProcessVolume {
+    fn default() -> Self {
+        Self {
+            center: Vec3::ZERO,
+            extents: Vec3::splat(4.0),
+            blend_distance: 2.0,
+            priority: 0,
+            overrides: PostVolumeOverrides::default(),
+        }
+    }
+}
+
+impl PostProcessVolume {
+    /// 1 inside the box, fading to 0 at `blend_distance` outside it
+    pub fn weight(&self, point: Vec3) -> f32 {
+        let outside = ((point - self.center).abs() - self.extents).max(Vec3::ZERO);
+        let distance = outside.length();
+        if self.blend_distance <= 0.0 {
+            return if distance == 0.0 { 1.0 } else { 0.0 };
+        }
+        1.0 - (distance / self.blend_distance).min(1.0)
+    }
+}
+
+/// Final values after every volume around a point has been blended in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlendedPostVolumes {
+    pub exposure: f32,
+    pub fog_density: f32,
+    /// Lut to blend towards and how far, only the highest priority lut is used since only two can be blended
+    pub lut: Option<(usize, f32)>,
+}
+
+impl BlendedPostVolumes {
+    /// Starts from the scene's own values and blends each volume over them, lowest priority first
+    pub fn new(
+        volumes: &[PostProcessVolume],
+        point: Vec3,
+        base_exposure: f32,
+        base_fog_density: f32,
+    ) -> Self {
+        let mut sorted: Vec<&PostProcessVolume> = volumes.iter().collect();
+        sorted.sort_by_key(|volume| volume.priority);
+
+        let mut blended = Self {
+            exposure: base_exposure,
+            fog_density: base_fog_density,
+            lut: None,
+        };
+        for volume in sorted {
+            let weight = volume.weight(point);
+            if weight <= 0.0 {
+                continue;
+            }
+
+            let overrides = &volume.overrides;
+            if let Some(exposure) = overrides.exposure {
+                blended.exposure += (exposure - blended.exposure) * weight;
+            }
+            if let Some(fog_density) = overrides.fog_density {
+                blended.fog_density += (fog_density - blended.fog_density) * weight;
+            }
+            if let Some(lut) = overrides.lut {
+                blended.lut = Some((lut, weight));
+            }
+        }
+        blended
+    }
+}
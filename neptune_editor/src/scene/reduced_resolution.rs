@@ -0,0 +1,211 @@
+use crate::scene::scene_renderer::SceneCamera;
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph_builder::{
+    RasterDrawCommandBuilder, RasterPassBuilder, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{
+    vk, Device, ImageHandle, RasterPipelineHandle, SamplerDescription, SamplerHandle,
+    TransientImageDesc, TransientImageSize,
+};
+
+#[derive(Debug, Clone)]
+pub struct ReducedResolutionSettings {
+    /// Resolution of reduced resolution effects relative to the view, clamped to `ReducedResolution::MIN_SCALE` to 1
+    pub scale: f32,
+}
+
+impl Default for ReducedResolutionSettings {
+    fn default() -> Self {
+        Self { scale: 0.5 }
+    }
+}
+
+/// How an upsampled effect is combined with the full resolution color, must match bilateral_upsample.frag.
+/// Each reduced resolution effect adds the mode it needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpsampleComposite {
+    /// color * effect.a + effect.rgb (in-scattering with transmittance in alpha)
+    Transmittance = 0,
+}
+
+/// Lets expensive screen space effects render at a fraction of the view resolution.
+/// Effects render into `create_target` images using `downsample_depth`, then `upsample` brings them back with depth aware weights so edges don't bleed
+pub struct ReducedResolution {
+    pub settings: ReducedResolutionSettings,
+
+    color_format: vk::Format,
+    depth_downsample_pipeline: RasterPipelineHandle,
+    upsample_pipeline: RasterPipelineHandle,
+    point_sampler: SamplerHandle,
+}
+
+impl ReducedResolution {
+    pub const MIN_SCALE: f32 = 0.25;
+
+    /// Depth is stored as a color target so it can be written by a fullscreen pass
+    const DEPTH_FORMAT: vk::Format = vk::Format::R32_SFLOAT;
+
+    pub fn new(
+        device: &mut Device,
+        color_format: vk::Format,
+        settings: ReducedResolutionSettings,
+    ) -> anyhow::Result<Self> {
+        let depth_downsample_pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::DEPTH_DOWNSAMPLE_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format: Self::DEPTH_FORMAT,
+                        blend: None,
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })?;
+
+        let upsample_pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::BILATERAL_UPSAMPLE_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format: color_format,
+                        blend: None,
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })?;
+
+        //Only used with texelFetch, so the filtering doesn't matter
+        let point_sampler =
+            device.create_sampler("Reduced Resolution Sampler", &SamplerDescription::default())?;
+
+        Ok(Self {
+            settings,
+            color_format,
+            depth_downsample_pipeline,
+            upsample_pipeline,
+            point_sampler,
+        })
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.settings.scale.clamp(Self::MIN_SCALE, 1.0)
+    }
+
+    /// Transient image scaled relative to `view_image`, its size always matches the result of `downsample_depth` for the same view
+    pub fn create_target<T: RenderGraphBuilderTrait>(
+        &self,
+        view_image: ImageHandle,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        render_graph_builder: &mut T,
+    ) -> ImageHandle {
+        render_graph_builder.create_transient_image(TransientImageDesc {
+            size: TransientImageSize::Relative([self.scale(); 2], view_image),
+            format,
+            usage,
+            mip_levels: 1,
+            memory_location: MemoryLocation::GpuOnly,
+        })
+    }
+
+    /// Point samples the view depth down to the effect resolution, returns `depth_image` itself at full resolution.
+    /// Should be called once per view and shared by every reduced resolution effect
+    pub fn downsample_depth<T: RenderGraphBuilderTrait>(
+        &self,
+        depth_image: ImageHandle,
+        render_graph_builder: &mut T,
+    ) -> ImageHandle {
+        if self.scale() >= 1.0 {
+            return depth_image;
+        }
+
+        let reduced_depth_image = self.create_target(
+            depth_image,
+            Self::DEPTH_FORMAT,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            render_graph_builder,
+        );
+
+        let mut downsample_pass_builder = RasterPassBuilder::new("Depth Downsample Pass");
+        downsample_pass_builder.add_color_attachment(reduced_depth_image, None);
+        let mut draw_command_builder =
+            RasterDrawCommandBuilder::new(self.depth_downsample_pipeline);
+        draw_command_builder.read_sampled_image(depth_image);
+        draw_command_builder.read_sampler(self.point_sampler);
+        draw_command_builder.push_constant(self.scale().to_bits());
+        draw_command_builder.draw(0..3, 0..1);
+        draw_command_builder.build(&mut downsample_pass_builder);
+        downsample_pass_builder.build(render_graph_builder);
+
+        reduced_depth_image
+    }
+
+    /// Upsamples `effect_image` to the resolution of `color_image` and combines them, returns the new color image.
+    /// Each full resolution pixel blends the 4 nearest effect texels, weighted by how close their depth is to its own
+    #[allow(clippy::too_many_arguments)]
+    pub fn upsample<T: RenderGraphBuilderTrait>(
+        &self,
+        camera: &SceneCamera,
+        color_image: ImageHandle,
+        depth_image: ImageHandle,
+        effect_image: ImageHandle,
+        effect_depth_image: ImageHandle,
+        composite: UpsampleComposite,
+        render_graph_builder: &mut T,
+    ) -> ImageHandle {
+        let upsampled_color_image =
+            render_graph_builder.create_transient_image(TransientImageDesc {
+                size: TransientImageSize::Relative([1.0; 2], color_image),
+                format: self.color_format,
+                usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::STORAGE,
+                mip_levels: 1,
+                memory_location: MemoryLocation::GpuOnly,
+            });
+
+        let mut upsample_pass_builder = RasterPassBuilder::new("Bilateral Upsample Pass");
+        upsample_pass_builder.add_color_attachment(upsampled_color_image, None);
+        let mut draw_command_builder = RasterDrawCommandBuilder::new(self.upsample_pipeline);
+        draw_command_builder.read_buffer(camera.buffer());
+        draw_command_builder.read_storage_image(color_image);
+        draw_command_builder.read_sampled_image(depth_image);
+        draw_command_builder.read_sampled_image(effect_image);
+        draw_command_builder.read_sampled_image(effect_depth_image);
+        draw_command_builder.read_sampler(self.point_sampler);
+        draw_command_builder.push_constant(composite as u32);
+        draw_command_builder.draw(0..3, 0..1);
+        draw_command_builder.build(&mut upsample_pass_builder);
+        upsample_pass_builder.build(render_graph_builder);
+
+        upsampled_color_image
+    }
+}
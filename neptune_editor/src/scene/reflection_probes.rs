@@ -0,0 +1,426 @@
+use crate::camera::{Camera, FieldOfView};
+use crate::scene::scene_renderer::{slice_to_bytes_unsafe, SceneCamera};
+use crate::transform::Transform;
+use anyhow::Context;
+use glam::{Mat3, Mat4, Quat, UVec4, Vec3, Vec4};
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph::QueueType;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, ComputePassBuilder, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{
+    vk, AddressMode, BufferHandle, BufferUsage, ComputePipelineHandle, DepthMode, Device,
+    FilterMode, ImageDescription2D, ImageHandle, SamplerDescription, SamplerHandle,
+};
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum ProbeUpdate {
+    /// Captured once, and again when the probe changes or `ReflectionProbes::rebake` is called
+    #[default]
+    Baked,
+    /// Recaptured every few frames, probes take turns so only a couple are captured per frame
+    RealTime,
+}
+
+/// Placed in a `Scene`, surfaces inside the box reflect what the probe sees from `position`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReflectionProbe {
+    pub position: Vec3,
+    /// Half size of the box around `position` the probe affects
+    pub extents: Vec3,
+    /// How far inside the box the probe fades in, where boxes overlap the probes are blended
+    pub blend_distance: f32,
+    /// Treats the box as the walls of the room, so reflections line up with nearby geometry
+    pub box_projection: bool,
+    pub update: ProbeUpdate,
+}
+
+impl Default for ReflectionProbe {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            extents: Vec3::splat(5.0),
+            blend_distance: 1.0,
+            box_projection: true,
+            update: ProbeUpdate::Baked,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReflectionProbeSettings {
+    pub enabled: bool,
+    pub intensity: f32,
+}
+
+impl Default for ReflectionProbeSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            intensity: 1.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct ProbeData {
+    /// xyz: capture position, w: blend distance
+    position_blend: Vec4,
+    /// xyz: half size of the box, w: 1 if box projected
+    extents: Vec4,
+    /// xy: uv offset in the atlas, z: uv size, w: 1 once captured
+    tile: Vec4,
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct ProbeBufferHeader {
+    probe_count: u32,
+    intensity: f32,
+    half_texel: f32,
+    _padding: u32,
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct CaptureData {
+    face_view_projection: [Mat4; 6],
+    /// xy: pixel offset in the atlas, z: tile size
+    tile: UVec4,
+}
+
+/// Cameras for one probe capture, there are only a few of these so captures are spread over frames
+struct CaptureSlot {
+    face_cameras: [SceneCamera; 6],
+    capture_buffer: BufferHandle,
+}
+
+/// A probe picked to be captured this frame
+pub(crate) struct ProbeCapture {
+    slot: usize,
+    pub(crate) name: String,
+}
+
+/// Renders the scene's reflection probes into an atlas of octahedral maps that mesh.frag samples.
+/// There are no cube images in the renderer, so each probe is drawn as six faces and packed into its tile
+pub struct ReflectionProbes {
+    pub settings: ReflectionProbeSettings,
+
+    pack_pipeline: ComputePipelineHandle,
+    probe_buffer: BufferHandle,
+    atlas: ImageHandle,
+    sampler: SamplerHandle,
+
+    capture_slots: Vec<CaptureSlot>,
+    /// What each tile was last captured from, None until it has been captured
+    captured: Vec<Option<ReflectionProbe>>,
+    next_real_time: usize,
+}
+
+impl ReflectionProbes {
+    pub const MAX_PROBES: usize = 16;
+    pub const CAPTURES_PER_FRAME: usize = 2;
+    pub const FACE_SIZE: u32 = 128;
+    const ATLAS_COLUMNS: u32 = 4;
+    const ATLAS_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+    pub fn new(
+        device: &mut Device,
+        depth_mode: DepthMode,
+        settings: ReflectionProbeSettings,
+    ) -> anyhow::Result<Self> {
+        let pack_pipeline = device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+            code: crate::shader::REFLECTION_PROBE_PACK_COMP,
+            entry: "main",
+        })?;
+
+        let probe_buffer = device
+            .create_buffer_init(
+                "ReflectionProbeBuffer",
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                &Self::probe_buffer_bytes(&ProbeBufferHeader::default(), &[]),
+            )
+            .context("Failed to create reflection probe buffer")?;
+
+        let atlas_rows = (Self::MAX_PROBES as u32).div_ceil(Self::ATLAS_COLUMNS);
+        let atlas = device.create_image(
+            "Reflection Probe Atlas",
+            &ImageDescription2D {
+                size: [
+                    Self::ATLAS_COLUMNS * Self::FACE_SIZE,
+                    atlas_rows * Self::FACE_SIZE,
+                ],
+                format: Self::ATLAS_FORMAT,
+                usage: vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+                mip_levels: 1,
+                location: MemoryLocation::GpuOnly,
+            },
+        )?;
+
+        let sampler = device.create_sampler(
+            "Reflection Probe Sampler",
+            &SamplerDescription {
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                address_mode_w: AddressMode::ClampToEdge,
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+        )?;
+
+        let mut capture_slots = Vec::with_capacity(Self::CAPTURES_PER_FRAME);
+        for _ in 0..Self::CAPTURES_PER_FRAME {
+            let capture_buffer = device
+                .create_buffer_init(
+                    "ReflectionProbeCaptureBuffer",
+                    BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                    MemoryLocation::GpuOnly,
+                    unsafe { slice_to_bytes_unsafe(&[CaptureData::default()]) },
+                )
+                .context("Failed to create reflection probe capture buffer")?;
+            capture_slots.push(CaptureSlot {
+                face_cameras: [
+                    SceneCamera::new(device, depth_mode)?,
+                    SceneCamera::new(device, depth_mode)?,
+                    SceneCamera::new(device, depth_mode)?,
+                    SceneCamera::new(device, depth_mode)?,
+                    SceneCamera::new(device, depth_mode)?,
+                    SceneCamera::new(device, depth_mode)?,
+                ],
+                capture_buffer,
+            });
+        }
+
+        Ok(Self {
+            settings,
+            pack_pipeline,
+            probe_buffer,
+            atlas,
+            sampler,
+            capture_slots,
+            captured: vec![None; Self::MAX_PROBES],
+            next_real_time: 0,
+        })
+    }
+
+    fn probe_buffer_bytes(header: &ProbeBufferHeader, probes: &[ProbeData]) -> Vec<u8> {
+        let mut probe_data = [ProbeData::default(); Self::MAX_PROBES];
+        probe_data[..probes.len()].copy_from_slice(probes);
+        let mut bytes = unsafe { slice_to_bytes_unsafe(&[*header]) }.to_vec();
+        bytes.extend_from_slice(unsafe { slice_to_bytes_unsafe(&probe_data) });
+        bytes
+    }
+
+    pub fn buffer(&self) -> BufferHandle {
+        self.probe_buffer
+    }
+
+    pub fn atlas(&self) -> ImageHandle {
+        self.atlas
+    }
+
+    pub fn sampler(&self) -> SamplerHandle {
+        self.sampler
+    }
+
+    /// Recaptures every baked probe over the next few frames, for after the scene around them changed
+    pub fn rebake(&mut self) {
+        self.captured
+            .iter_mut()
+            .for_each(|captured| *captured = None);
+    }
+
+    /// Forward direction and up vector of each face, the order reflection_probe_pack.comp picks faces in
+    fn face_rotation(face: usize) -> Quat {
+        let (forward, up) = match face {
+            0 => (Vec3::X, Vec3::Y),
+            1 => (Vec3::NEG_X, Vec3::Y),
+            2 => (Vec3::Y, Vec3::Z),
+            3 => (Vec3::NEG_Y, Vec3::NEG_Z),
+            4 => (Vec3::Z, Vec3::Y),
+            _ => (Vec3::NEG_Z, Vec3::Y),
+        };
+        //Cameras look down -Z
+        let back = -forward;
+        Quat::from_mat3(&Mat3::from_cols(up.cross(back), up, back))
+    }
+
+    /// Picks which probes get captured this frame and points the capture cameras at them.
+    /// Changed or uncaptured probes go first, then real time probes take turns with the rest
+    pub(crate) fn update_captures<T: RenderGraphBuilderTrait>(
+        &mut self,
+        probes: &[ReflectionProbe],
+        near_clip: f32,
+        render_graph_builder: &mut T,
+    ) -> Vec<(usize, ProbeCapture)> {
+        if !self.settings.enabled {
+            return Vec::new();
+        }
+
+        let probe_count = probes.len().min(Self::MAX_PROBES);
+        for captured in self.captured[probe_count..].iter_mut() {
+            *captured = None;
+        }
+
+        let mut selected: Vec<usize> = (0..probe_count)
+            .filter(|&index| self.captured[index].as_ref() != Some(&probes[index]))
+            .take(Self::CAPTURES_PER_FRAME)
+            .collect();
+
+        for offset in 0..probe_count {
+            if selected.len() >= Self::CAPTURES_PER_FRAME {
+                break;
+            }
+            let index = (self.next_real_time + offset) % probe_count;
+            if probes[index].update == ProbeUpdate::RealTime && !selected.contains(&index) {
+                selected.push(index);
+                self.next_real_time = index + 1;
+            }
+        }
+
+        let camera = Camera::new(FieldOfView::Y(90.0), near_clip, None);
+        selected
+            .into_iter()
+            .enumerate()
+            .map(|(slot, index)| {
+                let probe = &probes[index];
+                for (face, face_camera) in
+                    self.capture_slots[slot].face_cameras.iter_mut().enumerate()
+                {
+                    let transform = Transform {
+                        position: probe.position,
+                        rotation: Self::face_rotation(face),
+                        scale: Vec3::ONE,
+                    };
+                    face_camera.update(&camera, &transform, [Self::FACE_SIZE; 2]);
+                    face_camera.write_render_passes(render_graph_builder);
+                }
+                self.captured[index] = Some(probe.clone());
+                (
+                    index,
+                    ProbeCapture {
+                        slot,
+                        name: format!("Reflection Probe {}", index),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    pub(crate) fn face_camera(&self, capture: &ProbeCapture, face: usize) -> &SceneCamera {
+        &self.capture_slots[capture.slot].face_cameras[face]
+    }
+
+    fn tile_offset(index: usize) -> [u32; 2] {
+        let index = index as u32;
+        [
+            (index % Self::ATLAS_COLUMNS) * Self::FACE_SIZE,
+            (index / Self::ATLAS_COLUMNS) * Self::FACE_SIZE,
+        ]
+    }
+
+    /// Packs the six rendered faces of a capture into the probe's tile
+    pub(crate) fn write_pack_pass<T: RenderGraphBuilderTrait>(
+        &self,
+        index: usize,
+        capture: &ProbeCapture,
+        face_images: &[ImageHandle; 6],
+        render_graph_builder: &mut T,
+    ) {
+        let slot = &self.capture_slots[capture.slot];
+        let tile_offset = Self::tile_offset(index);
+        let mut capture_data = CaptureData {
+            face_view_projection: [Mat4::IDENTITY; 6],
+            tile: UVec4::new(tile_offset[0], tile_offset[1], Self::FACE_SIZE, 0),
+        };
+        for (matrix, face_camera) in capture_data
+            .face_view_projection
+            .iter_mut()
+            .zip(slot.face_cameras.iter())
+        {
+            *matrix = face_camera.view_projection_matrix();
+        }
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: slot.capture_buffer,
+                offset: 0,
+            },
+            std::mem::size_of::<CaptureData>(),
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&[capture_data]) });
+            }),
+        );
+
+        let mut pack_pass_builder = ComputePassBuilder::new(
+            &format!("{} Pack Pass", capture.name),
+            QueueType::Graphics,
+            self.pack_pipeline,
+        );
+        pack_pass_builder.read_buffer(slot.capture_buffer);
+        for &face_image in face_images.iter() {
+            pack_pass_builder.read_sampled_image(face_image);
+        }
+        pack_pass_builder.read_sampler(self.sampler);
+        pack_pass_builder.write_storage_image(self.atlas);
+        pack_pass_builder.dispatch_threads([Self::FACE_SIZE, Self::FACE_SIZE, 1]);
+        pack_pass_builder.build(render_graph_builder);
+    }
+
+    /// Uploads the probes mesh.frag blends between, probes that haven't been captured yet are skipped by the shader
+    pub(crate) fn write_buffer_update<T: RenderGraphBuilderTrait>(
+        &self,
+        probes: &[ReflectionProbe],
+        render_graph_builder: &mut T,
+    ) {
+        let atlas_size = [
+            (Self::ATLAS_COLUMNS * Self::FACE_SIZE) as f32,
+            ((Self::MAX_PROBES as u32).div_ceil(Self::ATLAS_COLUMNS) * Self::FACE_SIZE) as f32,
+        ];
+        let probe_data: Vec<ProbeData> = if self.settings.enabled {
+            probes
+                .iter()
+                .take(Self::MAX_PROBES)
+                .enumerate()
+                .map(|(index, probe)| {
+                    let tile_offset = Self::tile_offset(index);
+                    let captured = self.captured[index].as_ref() == Some(probe);
+                    ProbeData {
+                        position_blend: probe.position.extend(probe.blend_distance),
+                        extents: probe
+                            .extents
+                            .extend(if probe.box_projection { 1.0 } else { 0.0 }),
+                        tile: Vec4::new(
+                            tile_offset[0] as f32 / atlas_size[0],
+                            tile_offset[1] as f32 / atlas_size[1],
+                            Self::FACE_SIZE as f32 / atlas_size[0],
+                            if captured { 1.0 } else { 0.0 },
+                        ),
+                    }
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let header = ProbeBufferHeader {
+            probe_count: probe_data.len() as u32,
+            intensity: self.settings.intensity,
+            half_texel: 0.5 / Self::FACE_SIZE as f32,
+            _padding: 0,
+        };
+        let bytes = Self::probe_buffer_bytes(&header, &probe_data);
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: self.probe_buffer,
+                offset: 0,
+            },
+            bytes.len(),
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(&bytes);
+            }),
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use crate::camera::Camera;
+use crate::scene::scene_renderer::SceneCamera;
+use crate::transform::Transform;
+use glam::{Mat3, Quat, Vec3};
+use neptune_vulkan::{Device, ImageHandle};
+
+#[derive(Debug, Copy, Clone)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3) -> Self {
+        let normal = normal.normalize();
+        Self {
+            normal,
+            distance: normal.dot(point),
+        }
+    }
+
+    pub fn signed_distance(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) - self.distance
+    }
+
+    pub fn reflect_point(&self, point: Vec3) -> Vec3 {
+        point - (self.normal * (2.0 * self.signed_distance(point)))
+    }
+
+    pub fn reflect_vector(&self, vector: Vec3) -> Vec3 {
+        vector - (self.normal * (2.0 * self.normal.dot(vector)))
+    }
+
+    /// Mirrors the transform across the plane, the result is kept right-handed so the image ends up flipped horizontally
+    pub fn reflect_transform(&self, transform: &Transform) -> Transform {
+        let forward = self.reflect_vector(transform.rotation * Vec3::Z);
+        let up = self.reflect_vector(transform.rotation * Vec3::Y);
+        Transform {
+            position: self.reflect_point(transform.position),
+            rotation: Quat::from_mat3(&Mat3::from_cols(up.cross(forward), up, forward)),
+            scale: transform.scale,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderTextureDescription {
+    pub name: String,
+    pub size: [u32; 2],
+    pub camera: Camera,
+    /// How many times this texture can show up inside itself or other render textures, must be at least 1
+    pub recursion_depth: usize,
+}
+
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct RenderTextureHandle(pub(crate) slotmap::DefaultKey);
+
+/// Secondary camera that renders the scene into an offscreen image each frame
+pub struct RenderTexture {
+    pub name: String,
+    pub camera: Camera,
+    pub transform: Transform,
+    /// When set the camera follows the main camera mirrored across the plane (mirrors, water, etc)
+    pub reflection_plane: Option<Plane>,
+
+    pub(crate) size: [u32; 2],
+    /// One image per recursion level, level 0 is what everything else sees
+    pub(crate) images: Vec<ImageHandle>,
+    pub(crate) scene_camera: SceneCamera,
+}
+
+impl RenderTexture {
+    pub fn image(&self) -> ImageHandle {
+        self.images[0]
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    pub fn recursion_depth(&self) -> usize {
+        self.images.len()
+    }
+
+    pub(crate) fn update(&mut self, main_camera_transform: &Transform) {
+        if let Some(plane) = &self.reflection_plane {
+            self.transform = plane.reflect_transform(main_camera_transform);
+        }
+
+        self.scene_camera
+            .update(&self.camera, &self.transform, self.size);
+    }
+
+    pub(crate) fn destroy(self, device: &mut Device) {
+        for image in self.images {
+            device.destroy_image(image);
+        }
+        device.destroy_buffer(self.scene_camera.buffer());
+    }
+}
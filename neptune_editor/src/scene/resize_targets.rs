@@ -0,0 +1,126 @@
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::{vk, Device, ImageDescription2D, ImageHandle};
+use slotmap::SlotMap;
+
+#[derive(Debug, Clone)]
+pub struct ResizeTargetDescription {
+    pub name: String,
+    /// Relative to the main view, floored with a minimum of 1 like the graph's relative images
+    pub scale: [f32; 2],
+    pub format: vk::Format,
+    pub usage: vk::ImageUsageFlags,
+    pub mip_levels: u32,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ResizeTargetHandle(slotmap::DefaultKey);
+
+struct ResizeTarget {
+    description: ResizeTargetDescription,
+    image: ImageHandle,
+    size: [u32; 2],
+    generation: u64,
+}
+
+/// Persistent images that have to follow the main view as the window resizes, like history buffers and accumulation images.
+/// Every target is recreated in `resize` before the frame is built, so no system sees a stale size or has to track resizes itself
+#[derive(Default)]
+pub struct ResizeTargets {
+    targets: SlotMap<slotmap::DefaultKey, ResizeTarget>,
+    main_view_size: [u32; 2],
+    next_generation: u64,
+}
+
+impl ResizeTargets {
+    pub fn main_view_size(&self) -> [u32; 2] {
+        self.main_view_size
+    }
+
+    pub fn add(
+        &mut self,
+        device: &mut Device,
+        description: ResizeTargetDescription,
+    ) -> anyhow::Result<ResizeTargetHandle> {
+        let size = self.target_size(description.scale);
+        let image = create_image(device, &description, size)?;
+        let generation = self.next_generation();
+        Ok(ResizeTargetHandle(self.targets.insert(ResizeTarget {
+            description,
+            image,
+            size,
+            generation,
+        })))
+    }
+
+    pub fn remove(&mut self, device: &mut Device, handle: ResizeTargetHandle) {
+        if let Some(target) = self.targets.remove(handle.0) {
+            device.destroy_image(target.image);
+        }
+    }
+
+    pub fn image(&self, handle: ResizeTargetHandle) -> Option<ImageHandle> {
+        self.targets.get(handle.0).map(|target| target.image)
+    }
+
+    pub fn size(&self, handle: ResizeTargetHandle) -> Option<[u32; 2]> {
+        self.targets.get(handle.0).map(|target| target.size)
+    }
+
+    /// Changes whenever the target's image is recreated, anything accumulated in the old image is gone
+    pub fn generation(&self, handle: ResizeTargetHandle) -> Option<u64> {
+        self.targets.get(handle.0).map(|target| target.generation)
+    }
+
+    /// Recreates the targets whose size changed, should be called once a frame before anything uses them
+    pub fn resize(&mut self, device: &mut Device, main_view_size: [u32; 2]) -> anyhow::Result<()> {
+        if self.main_view_size == main_view_size {
+            return Ok(());
+        }
+        self.main_view_size = main_view_size;
+
+        let keys: Vec<_> = self.targets.keys().collect();
+        for key in keys {
+            let size = self.target_size(self.targets[key].description.scale);
+            if self.targets[key].size == size {
+                continue;
+            }
+
+            let generation = self.next_generation();
+            let target = &mut self.targets[key];
+            let image = create_image(device, &target.description, size)?;
+            device.destroy_image(std::mem::replace(&mut target.image, image));
+            target.size = size;
+            target.generation = generation;
+        }
+        Ok(())
+    }
+
+    fn target_size(&self, scale: [f32; 2]) -> [u32; 2] {
+        [
+            ((self.main_view_size[0] as f32 * scale[0]).floor() as u32).max(1),
+            ((self.main_view_size[1] as f32 * scale[1]).floor() as u32).max(1),
+        ]
+    }
+
+    fn next_generation(&mut self) -> u64 {
+        self.next_generation += 1;
+        self.next_generation
+    }
+}
+
+fn create_image(
+    device: &mut Device,
+    description: &ResizeTargetDescription,
+    size: [u32; 2],
+) -> anyhow::Result<ImageHandle> {
+    Ok(device.create_image(
+        &description.name,
+        &ImageDescription2D {
+            size,
+            format: description.format,
+            usage: description.usage,
+            mip_levels: description.mip_levels,
+            location: MemoryLocation::GpuOnly,
+        },
+    )?)
+}
@@ -1,76 +1,176 @@
+use crate::buffer_inspector::{BufferElement, InspectableBuffer};
 use crate::camera::Camera;
-use crate::material::{Material, MaterialTexture};
+use crate::material::{
+    Material, MaterialConstants, MaterialConstantsData, MaterialPalette, MaterialPipeline,
+    MaterialTexture,
+};
 use crate::mesh;
-use crate::mesh::Primitive;
+use crate::mesh::{BoundingBox, BoundingSphere, Primitive};
+use crate::scene::color_grading::{ColorGrading, ColorGradingSettings};
+use crate::scene::draw_list::{write_draw_buffer, DrawData, DrawItem, DrawList};
+use crate::scene::foliage::{FoliageLayer, FoliageRenderer, VisibleFoliage};
+use crate::scene::gpu_primitives::GpuPrimitives;
+use crate::scene::lens_effects::{LensEffectSettings, LensEffects};
+use crate::scene::lightmap_baker::LightmapBaker;
+use crate::scene::particles::{ParticleSettings, ParticleSystem};
+use crate::scene::path_tracer::PathTracer;
+use crate::scene::post_effects::{PostEffectSettings, PostEffects};
+use crate::scene::post_volumes::{BlendedPostVolumes, PostProcessVolume};
+use crate::scene::reduced_resolution::{ReducedResolution, ReducedResolutionSettings};
+use crate::scene::reflection_probes::{ReflectionProbe, ReflectionProbeSettings, ReflectionProbes};
+use crate::scene::render_texture::{RenderTexture, RenderTextureDescription, RenderTextureHandle};
+use crate::scene::resize_targets::ResizeTargets;
+use crate::scene::selection_outline::{SelectionOutline, SelectionOutlineSettings};
+use crate::scene::skinning::Skinning;
+use crate::scene::sky::{Sky, SkySettings};
+use crate::scene::spatial::{Bvh, Ray, SpatialHit};
+use crate::scene::upscaler::{Upscaler, UpscalerSettings};
+use crate::scene::viewport_helpers::{ViewportHelperSettings, ViewportHelpers};
+use crate::scene::visibility::CellPortalGraph;
+use crate::scene::volumetric_fog::{VolumetricFog, VolumetricFogSettings};
+use crate::scene::voxel_gi::{VoxelGi, VoxelGiSettings};
+use crate::scene::water::{WaterRenderer, WaterSurface};
 use crate::transform::Transform;
 use anyhow::Context;
-use glam::{Mat4, Vec3};
+use glam::{Mat4, Vec2, Vec3, Vec4};
 use neptune_core::id_pool::IdPool;
 use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph::RenderArea;
 use neptune_vulkan::render_graph_builder::{
     BufferOffset, BufferWriteCallback, RenderGraphBuilderTrait,
 };
 use neptune_vulkan::{
-    vk, BufferUsage, Device, ImageDescription2D, ImageHandle, RasterPipelineHandle,
-    SamplerDescription, TransientImageDesc, TransientImageSize,
+    vk, BlendState, BufferUsage, DepthMode, Device, ImageDescription2D, ImageHandle,
+    RasterPipelineHandle, SamplerDescription, TransientImageDesc, TransientImageSize,
 };
+use serde::{Deserialize, Serialize};
 use slotmap::SlotMap;
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 pub(crate) unsafe fn slice_to_bytes_unsafe<T>(slice: &[T]) -> &[u8] {
     std::slice::from_raw_parts(slice.as_ptr() as *const u8, std::mem::size_of_val(slice))
 }
 
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderMode {
+    #[default]
+    Raster,
+    /// Progressive reference path tracer, see `PathTracer`
+    PathTraced,
+}
+
 pub struct SceneRenderer {
+    pub render_mode: RenderMode,
     depth_format: vk::Format,
+    depth_mode: DepthMode,
     raster_pipeline: RasterPipelineHandle,
+    transparent_pipeline: RasterPipelineHandle,
+    motion_blur_pipeline: RasterPipelineHandle,
+    foliage_pipeline: RasterPipelineHandle,
     default_texture: MaterialTexture,
+    pub material_palette: MaterialPalette,
+    default_material_constants: MaterialConstants,
+    pub volumetric_fog: VolumetricFog,
+    pub sky: Sky,
+    pub lens_effects: LensEffects,
+    pub reflection_probes: ReflectionProbes,
+    pub reduced_resolution: ReducedResolution,
+    pub upscaler: Upscaler,
+    /// Persistent images that follow the main view size, resized once a frame before any system updates
+    pub resize_targets: ResizeTargets,
+    pub particles: ParticleSystem,
+    pub water: WaterRenderer,
+    pub foliage: FoliageRenderer,
+    /// Scan, reduce and compaction passes for gpu driven rendering
+    pub gpu_primitives: GpuPrimitives,
+    pub voxel_gi: VoxelGi,
+    pub path_tracer: PathTracer,
+    pub lightmap_baker: LightmapBaker,
+    pub skinning: Skinning,
+    pub selection_outline: SelectionOutline,
+    pub viewport_helpers: ViewportHelpers,
+    pub color_grading: ColorGrading,
+    post_effects: PostEffects,
+    /// Dithers the final output to hide banding in dark gradients, scaled to the precision of `output_format`
+    pub dithering: bool,
+    /// Skips instances in cells that can't be seen through the scene's portals
+    pub portal_culling: bool,
+    output_format: vk::Format,
+    render_textures: SlotMap<slotmap::DefaultKey, RenderTexture>,
 }
 
 impl SceneRenderer {
-    pub fn new(device: &mut Device, depth_format: vk::Format) -> anyhow::Result<Self> {
-        let raster_pipeline = {
-            let vertex_shader_code = crate::shader::MESH_STATIC_VERT;
-            let fragment_shader_code = crate::shader::MESH_FRAG;
-
-            let vertex_state = neptune_vulkan::VertexState {
-                shader: neptune_vulkan::ShaderStage {
-                    code: vertex_shader_code,
-                    entry: "main",
-                },
-                layouts: &[
-                    mesh::VertexPosition::VERTEX_BUFFER_LAYOUT,
-                    mesh::VertexAttributes::VERTEX_BUFFER_LAYOUT,
-                ],
-            };
+    const COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+    const VELOCITY_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+    const MATERIAL_PALETTE_CAPACITY: usize = 4096;
+    const PARTICLE_CAPACITY: u32 = 16384;
+    const PROBE_NEAR_CLIP: f32 = 0.05;
+
+    /// Blackboard keys published for each view before its passes are written
+    pub const VIEW_DEPTH_KEY: &'static str = "view_depth";
+    pub const VIEW_VELOCITY_KEY: &'static str = "view_velocity";
+    pub const VIEW_BUFFER_KEY: &'static str = "view_buffer";
+
+    /// `depth_mode` and `output_format` are baked into the pipelines, so they can only be picked when the renderer is created
+    pub fn new(
+        device: &mut Device,
+        output_format: vk::Format,
+        depth_format: vk::Format,
+        depth_mode: DepthMode,
+    ) -> anyhow::Result<Self> {
+        let raster_pipeline = Self::create_mesh_pipeline(
+            device,
+            depth_format,
+            depth_mode,
+            crate::shader::MESH_STATIC_VERT,
+            crate::shader::MESH_FRAG,
+            false,
+        )?;
+        let transparent_pipeline = Self::create_mesh_pipeline(
+            device,
+            depth_format,
+            depth_mode,
+            crate::shader::MESH_STATIC_VERT,
+            crate::shader::MESH_FRAG,
+            true,
+        )?;
+        let foliage_pipeline = Self::create_mesh_pipeline(
+            device,
+            depth_format,
+            depth_mode,
+            crate::shader::FOLIAGE_VERT,
+            crate::shader::MESH_FRAG,
+            false,
+        )?;
 
+        let motion_blur_pipeline =
             device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
-                vertex: vertex_state,
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
                 primitive: neptune_vulkan::PrimitiveState {
                     front_face: vk::FrontFace::COUNTER_CLOCKWISE,
-                    cull_mode: vk::CullModeFlags::BACK,
+                    cull_mode: vk::CullModeFlags::NONE,
                 },
-                depth_state: Some(neptune_vulkan::DepthState {
-                    format: depth_format,
-                    depth_enabled: true,
-                    write_depth: true,
-                    depth_op: vk::CompareOp::LESS,
-                }),
+                depth_state: None,
                 fragment: Some(neptune_vulkan::FragmentState {
                     shader: neptune_vulkan::ShaderStage {
-                        code: fragment_shader_code,
+                        code: crate::shader::MOTION_BLUR_FRAG,
                         entry: "main",
                     },
                     targets: &[neptune_vulkan::ColorTargetState {
-                        format: vk::Format::B8G8R8A8_UNORM,
+                        format: output_format,
                         blend: None,
                         write_mask: vk::ColorComponentFlags::RGBA,
                     }],
                 }),
-            })?
-        };
+            })?;
 
         let default_texture = MaterialTexture {
             image: device.create_image_init(
@@ -88,12 +188,466 @@ impl SceneRenderer {
             uv_index: 0,
         };
 
+        let material_palette = MaterialPalette::new(device, Self::MATERIAL_PALETTE_CAPACITY)?;
+        let default_material_constants = MaterialConstants::new(
+            &material_palette,
+            MaterialConstantsData::new(Vec4::ONE, Vec2::new(0.0, 1.0), Vec3::ZERO),
+        )?;
+
+        let volumetric_fog = VolumetricFog::new(device, VolumetricFogSettings::default())?;
+        let sky = Sky::new(device, Self::COLOR_FORMAT, SkySettings::default())?;
+        let lens_effects =
+            LensEffects::new(device, Self::COLOR_FORMAT, LensEffectSettings::default())?;
+        let reflection_probes =
+            ReflectionProbes::new(device, depth_mode, ReflectionProbeSettings::default())?;
+        let reduced_resolution = ReducedResolution::new(
+            device,
+            Self::COLOR_FORMAT,
+            ReducedResolutionSettings::default(),
+        )?;
+
+        let upscaler = Upscaler::new(
+            device,
+            Self::COLOR_FORMAT,
+            Self::VELOCITY_FORMAT,
+            depth_format,
+            depth_mode,
+            UpscalerSettings::default(),
+        )?;
+
+        let particles = ParticleSystem::new(
+            device,
+            Self::COLOR_FORMAT,
+            depth_format,
+            depth_mode,
+            Self::PARTICLE_CAPACITY,
+            ParticleSettings::default(),
+        )?;
+
+        let water = WaterRenderer::new(device, Self::COLOR_FORMAT)?;
+        let foliage = FoliageRenderer::new(device)?;
+        let gpu_primitives = GpuPrimitives::new(device)?;
+        let voxel_gi = VoxelGi::new(device, Self::COLOR_FORMAT, VoxelGiSettings::default())?;
+
+        let path_tracer = PathTracer::new(device, output_format)?;
+        let lightmap_baker = LightmapBaker::new(device)?;
+        let skinning = Skinning::new(device)?;
+
+        let selection_outline = SelectionOutline::new(
+            device,
+            Self::COLOR_FORMAT,
+            SelectionOutlineSettings::default(),
+        )?;
+
+        let viewport_helpers = ViewportHelpers::new(
+            device,
+            Self::COLOR_FORMAT,
+            ViewportHelperSettings::default(),
+        )?;
+
+        let color_grading = ColorGrading::new(device, ColorGradingSettings::default())?;
+        let post_effects = PostEffects::new(device)?;
+
         Ok(Self {
+            render_mode: RenderMode::default(),
             depth_format,
+            depth_mode,
             raster_pipeline,
+            transparent_pipeline,
+            motion_blur_pipeline,
+            foliage_pipeline,
             default_texture,
+            material_palette,
+            default_material_constants,
+            volumetric_fog,
+            sky,
+            lens_effects,
+            reflection_probes,
+            reduced_resolution,
+            upscaler,
+            resize_targets: ResizeTargets::default(),
+            particles,
+            water,
+            foliage,
+            gpu_primitives,
+            voxel_gi,
+            path_tracer,
+            lightmap_baker,
+            skinning,
+            selection_outline,
+            viewport_helpers,
+            color_grading,
+            post_effects,
+            dithering: true,
+            portal_culling: true,
+            output_format,
+            render_textures: SlotMap::default(),
         })
     }
+
+    /// Mesh pipeline with a custom fragment shader, used for shader graph materials
+    pub fn create_material_pipeline(
+        &self,
+        device: &mut Device,
+        fragment_shader_code: &[u32],
+    ) -> anyhow::Result<RasterPipelineHandle> {
+        Self::create_mesh_pipeline(
+            device,
+            self.depth_format,
+            self.depth_mode,
+            crate::shader::MESH_STATIC_VERT,
+            fragment_shader_code,
+            false,
+        )
+    }
+
+    pub fn depth_mode(&self) -> DepthMode {
+        self.depth_mode
+    }
+
+    /// Resolution the main view is rendered at relative to its output, smaller than 1 when upscaling
+    pub fn render_scale(&self) -> Vec2 {
+        if self.render_mode == RenderMode::PathTraced {
+            Vec2::ONE
+        } else {
+            self.upscaler.render_scale()
+        }
+    }
+
+    /// Sub-pixel jitter for the main camera this frame, see `SceneCamera::jitter`
+    pub fn next_jitter(&mut self) -> Vec2 {
+        if self.render_mode == RenderMode::PathTraced {
+            Vec2::ZERO
+        } else {
+            self.upscaler.next_jitter()
+        }
+    }
+
+    pub fn output_format(&self) -> vk::Format {
+        self.output_format
+    }
+
+    /// One step of the output format, float formats have enough precision that they don't need dithering
+    fn dither_scale(&self) -> f32 {
+        let bits = match self.output_format {
+            _ if !self.dithering => return 0.0,
+            vk::Format::A2B10G10R10_UNORM_PACK32 | vk::Format::A2R10G10B10_UNORM_PACK32 => 10,
+            vk::Format::R16G16B16A16_SFLOAT => return 0.0,
+            _ => 8,
+        };
+        1.0 / ((1u32 << bits) - 1) as f32
+    }
+
+    /// Transparent pipelines blend over the color target, and don't write depth or velocity
+    fn create_mesh_pipeline(
+        device: &mut Device,
+        depth_format: vk::Format,
+        depth_mode: DepthMode,
+        vertex_shader_code: &[u32],
+        fragment_shader_code: &[u32],
+        transparent: bool,
+    ) -> anyhow::Result<RasterPipelineHandle> {
+        let vertex_state = neptune_vulkan::VertexState {
+            shader: neptune_vulkan::ShaderStage {
+                code: vertex_shader_code,
+                entry: "main",
+            },
+            layouts: &[
+                mesh::VertexPosition::VERTEX_BUFFER_LAYOUT,
+                mesh::VertexAttributes::VERTEX_BUFFER_LAYOUT,
+            ],
+        };
+
+        Ok(
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: vertex_state,
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::BACK,
+                },
+                depth_state: Some(neptune_vulkan::DepthState {
+                    format: depth_format,
+                    depth_enabled: true,
+                    write_depth: !transparent,
+                    depth_op: depth_mode.compare_op(vk::CompareOp::LESS),
+                }),
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: fragment_shader_code,
+                        entry: "main",
+                    },
+                    targets: &[
+                        neptune_vulkan::ColorTargetState {
+                            format: Self::COLOR_FORMAT,
+                            blend: transparent.then_some(BlendState::AlphaBlend),
+                            write_mask: vk::ColorComponentFlags::RGBA,
+                        },
+                        neptune_vulkan::ColorTargetState {
+                            format: Self::VELOCITY_FORMAT,
+                            blend: None,
+                            write_mask: if transparent {
+                                vk::ColorComponentFlags::empty()
+                            } else {
+                                vk::ColorComponentFlags::RGBA
+                            },
+                        },
+                    ],
+                }),
+            })?,
+        )
+    }
+
+    pub fn add_render_texture(
+        &mut self,
+        device: &mut Device,
+        description: &RenderTextureDescription,
+    ) -> anyhow::Result<RenderTextureHandle> {
+        anyhow::ensure!(
+            description.recursion_depth > 0,
+            "Render texture recursion depth must be at least 1"
+        );
+
+        let mut images = Vec::with_capacity(description.recursion_depth);
+        for level in 0..description.recursion_depth {
+            images.push(device.create_image(
+                &format!("{} Level {}", description.name, level),
+                &ImageDescription2D {
+                    size: description.size,
+                    format: Self::COLOR_FORMAT,
+                    usage: vk::ImageUsageFlags::COLOR_ATTACHMENT
+                        | vk::ImageUsageFlags::SAMPLED
+                        | vk::ImageUsageFlags::STORAGE,
+                    mip_levels: 1,
+                    location: MemoryLocation::GpuOnly,
+                },
+            )?);
+        }
+
+        Ok(RenderTextureHandle(self.render_textures.insert(
+            RenderTexture {
+                name: description.name.clone(),
+                camera: description.camera,
+                transform: Transform::default(),
+                reflection_plane: None,
+                size: description.size,
+                images,
+                scene_camera: SceneCamera::new(device, self.depth_mode)?,
+            },
+        )))
+    }
+
+    pub fn remove_render_texture(&mut self, device: &mut Device, handle: RenderTextureHandle) {
+        if let Some(render_texture) = self.render_textures.remove(handle.0) {
+            render_texture.destroy(device);
+        } else {
+            warn!("RenderTexture({:?}) doesn't exist", handle.0)
+        }
+    }
+
+    pub fn get_render_texture(&self, handle: RenderTextureHandle) -> Option<&RenderTexture> {
+        self.render_textures.get(handle.0)
+    }
+
+    pub fn get_render_texture_mut(
+        &mut self,
+        handle: RenderTextureHandle,
+    ) -> Option<&mut RenderTexture> {
+        self.render_textures.get_mut(handle.0)
+    }
+
+    /// Material showing the render texture as its base color, for mirrors, monitors and portals
+    pub fn render_texture_material(&self, handle: RenderTextureHandle) -> anyhow::Result<Material> {
+        let render_texture = self
+            .get_render_texture(handle)
+            .context("Render texture doesn't exist")?;
+        let base_color = Vec4::ONE;
+        let metallic_roughness_factor = Vec2::new(0.0, 0.0);
+        let constants = MaterialConstants::new(
+            &self.material_palette,
+            MaterialConstantsData::new(base_color, metallic_roughness_factor, Vec3::ZERO),
+        )?;
+        Ok(Material {
+            name: render_texture.name.clone(),
+            alpha_blending: false,
+            base_color,
+            metallic_roughness_factor,
+            emissive_color: Vec3::ZERO,
+            base_color_texture: Some(MaterialTexture {
+                image: render_texture.image(),
+                sampler: self.default_texture.sampler,
+                uv_index: 0,
+            }),
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+            constants,
+            pipeline: MaterialPipeline::default(),
+        })
+    }
+
+    /// Every buffer the buffer inspector can show this frame
+    pub fn inspectable_buffers(&self, scene: &Scene) -> Vec<InspectableBuffer> {
+        let mut buffers = vec![
+            scene.inspectable_buffer(),
+            self.particles.inspectable_buffer(),
+        ];
+        buffers.extend(self.foliage.culled_buffers());
+        buffers
+    }
+
+    pub fn update_render_textures(&mut self, main_camera_transform: &Transform) {
+        for (_key, render_texture) in self.render_textures.iter_mut() {
+            render_texture.update(main_camera_transform);
+        }
+    }
+
+    /// Captures the probes picked for this frame, each one is six scene passes and a pack into the probe atlas
+    fn write_reflection_probe_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        scene: &Scene,
+        render_graph_builder: &mut T,
+    ) {
+        let captures = self.reflection_probes.update_captures(
+            &scene.reflection_probes,
+            Self::PROBE_NEAR_CLIP,
+            render_graph_builder,
+        );
+
+        let size = vk::Extent2D {
+            width: ReflectionProbes::FACE_SIZE,
+            height: ReflectionProbes::FACE_SIZE,
+        };
+        for (index, capture) in captures.iter() {
+            let face_images: [ImageHandle; 6] = std::array::from_fn(|face| {
+                let camera = self.reflection_probes.face_camera(capture, face);
+                let color_image = render_graph_builder.create_transient_image(TransientImageDesc {
+                    size: TransientImageSize::Exact(size),
+                    format: Self::COLOR_FORMAT,
+                    usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                    mip_levels: 1,
+                    memory_location: MemoryLocation::GpuOnly,
+                });
+                let velocity_image =
+                    render_graph_builder.create_transient_image(TransientImageDesc {
+                        size: TransientImageSize::Exact(size),
+                        format: Self::VELOCITY_FORMAT,
+                        usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+                        mip_levels: 1,
+                        memory_location: MemoryLocation::GpuOnly,
+                    });
+                let depth_image = render_graph_builder.create_transient_image(TransientImageDesc {
+                    size: TransientImageSize::Exact(size),
+                    format: self.depth_format,
+                    usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                        | vk::ImageUsageFlags::SAMPLED,
+                    mip_levels: 1,
+                    memory_location: MemoryLocation::GpuOnly,
+                });
+
+                self.write_scene_pass(
+                    &format!("{} Face {}", capture.name, face),
+                    [color_image, velocity_image, depth_image],
+                    camera,
+                    scene,
+                    &HashMap::new(),
+                    false,
+                    render_graph_builder,
+                );
+                self.sky.write_render_passes(
+                    camera,
+                    color_image,
+                    depth_image,
+                    render_graph_builder,
+                );
+                color_image
+            });
+            self.reflection_probes.write_pack_pass(
+                *index,
+                capture,
+                &face_images,
+                render_graph_builder,
+            );
+        }
+
+        self.reflection_probes
+            .write_buffer_update(&scene.reflection_probes, render_graph_builder);
+    }
+
+    /// Renders every render texture, deepest recursion level first so each level can sample the one below it
+    fn write_render_texture_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        scene: &Scene,
+        render_graph_builder: &mut T,
+    ) {
+        for (_key, render_texture) in self.render_textures.iter_mut() {
+            render_texture
+                .scene_camera
+                .write_render_passes(render_graph_builder);
+        }
+
+        let max_depth = self
+            .render_textures
+            .values()
+            .map(RenderTexture::recursion_depth)
+            .max()
+            .unwrap_or_default();
+
+        for level in (0..max_depth).rev() {
+            //Past the last level render textures show the default texture instead
+            let texture_remap: HashMap<ImageHandle, ImageHandle> = self
+                .render_textures
+                .values()
+                .map(|render_texture| {
+                    (
+                        render_texture.image(),
+                        render_texture
+                            .images
+                            .get(level + 1)
+                            .copied()
+                            .unwrap_or(self.default_texture.image),
+                    )
+                })
+                .collect();
+
+            for render_texture in self.render_textures.values() {
+                let Some(&target_image) = render_texture.images.get(level) else {
+                    continue;
+                };
+
+                let size = vk::Extent2D {
+                    width: render_texture.size[0],
+                    height: render_texture.size[1],
+                };
+                let depth_image = render_graph_builder.create_transient_image(TransientImageDesc {
+                    size: TransientImageSize::Exact(size),
+                    format: self.depth_format,
+                    usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+                    mip_levels: 1,
+                    memory_location: MemoryLocation::GpuOnly,
+                });
+                let velocity_image =
+                    render_graph_builder.create_transient_image(TransientImageDesc {
+                        size: TransientImageSize::Exact(size),
+                        format: Self::VELOCITY_FORMAT,
+                        usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+                        mip_levels: 1,
+                        memory_location: MemoryLocation::GpuOnly,
+                    });
+
+                self.write_scene_pass(
+                    &format!("{} Pass Level {}", render_texture.name, level),
+                    [target_image, velocity_image, depth_image],
+                    &render_texture.scene_camera,
+                    scene,
+                    &texture_remap,
+                    false,
+                    render_graph_builder,
+                );
+            }
+        }
+    }
+
     pub fn write_render_passes<T: RenderGraphBuilderTrait>(
         &mut self,
         target_image: ImageHandle,
@@ -101,80 +655,616 @@ impl SceneRenderer {
         scene: &Scene,
         render_graph_builder: &mut T,
     ) {
+        self.apply_post_volumes(scene, camera.position(), render_graph_builder);
+        self.foliage.write_render_passes(render_graph_builder);
+        self.apply_sun_light();
+        //Secondary views still draw the sky while the main view is path traced
+        self.sky.write_lut_passes(render_graph_builder);
+
+        if self.render_mode == RenderMode::PathTraced {
+            self.path_tracer.write_render_passes(
+                &self.resize_targets,
+                target_image,
+                camera,
+                render_graph_builder,
+            );
+            return;
+        }
+
+        self.write_reflection_probe_passes(scene, render_graph_builder);
+        self.write_render_texture_passes(scene, render_graph_builder);
+        self.write_view_passes(
+            target_image,
+            None,
+            camera,
+            scene,
+            true,
+            render_graph_builder,
+        );
+    }
+
+    /// Draws each camera into its own area of `target_image`, for split screen and side by side comparisons.
+    /// Every view is treated like a secondary view and the path tracer isn't used, both only support one full size view
+    pub fn write_split_view_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        target_image: ImageHandle,
+        views: &[(RenderArea, &SceneCamera)],
+        scene: &Scene,
+        render_graph_builder: &mut T,
+    ) {
+        //Post effects are shared by every view, so the volumes are blended around the first camera
+        let camera_position = views
+            .first()
+            .map(|(_, camera)| camera.position())
+            .unwrap_or_default();
+        self.apply_post_volumes(scene, camera_position, render_graph_builder);
+        self.foliage.write_render_passes(render_graph_builder);
+        self.apply_sun_light();
+        self.sky.write_lut_passes(render_graph_builder);
+        self.write_reflection_probe_passes(scene, render_graph_builder);
+        self.write_render_texture_passes(scene, render_graph_builder);
+        for (area, camera) in views {
+            self.write_view_passes(
+                target_image,
+                Some(*area),
+                camera,
+                scene,
+                false,
+                render_graph_builder,
+            );
+        }
+    }
+
+    /// Blends the post process volumes around `camera_position` into this frame's post effects, fog and color grading
+    fn apply_post_volumes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        scene: &Scene,
+        camera_position: Vec3,
+        render_graph_builder: &mut T,
+    ) {
+        let blended = BlendedPostVolumes::new(
+            &scene.post_volumes,
+            camera_position,
+            scene.post_effects.exposure,
+            self.volumetric_fog.settings.density,
+        );
+
+        let mut post_effects = scene.post_effects.clone();
+        post_effects.exposure = blended.exposure;
+        self.post_effects
+            .write_render_passes(&post_effects, render_graph_builder);
+        self.volumetric_fog.density_override = Some(blended.fog_density);
+        self.color_grading.volume_lut = blended.lut;
+    }
+
+    /// The sky's sun replaces the light direction and color of every effect that has one while it's enabled
+    fn apply_sun_light(&mut self) {
+        let Some((direction, color)) = self.sky.sun_light() else {
+            return;
+        };
+
+        self.volumetric_fog.settings.sun_direction = direction;
+        self.volumetric_fog.settings.sun_color = color;
+        self.voxel_gi.settings.sun_direction = direction;
+        self.voxel_gi.settings.sun_color = color;
+
+        //Accumulated samples are stale once the sun moves
+        let path_tracer_settings = &mut self.path_tracer.settings;
+        if path_tracer_settings.sun_direction != direction
+            || path_tracer_settings.sun_color != color
+        {
+            path_tracer_settings.sun_direction = direction;
+            path_tracer_settings.sun_color = color;
+            self.path_tracer.reset();
+        }
+    }
+
+    /// Draws the scene from an extra camera (editor viewports, etc), temporal effects are skipped since their history belongs to the main view
+    pub fn write_secondary_view_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        target_image: ImageHandle,
+        camera: &SceneCamera,
+        scene: &Scene,
+        render_graph_builder: &mut T,
+    ) {
+        self.write_view_passes(
+            target_image,
+            None,
+            camera,
+            scene,
+            false,
+            render_graph_builder,
+        );
+    }
+
+    /// Renders into `area` of the target if there is one, otherwise all of it
+    fn write_view_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        target_image: ImageHandle,
+        area: Option<RenderArea>,
+        camera: &SceneCamera,
+        scene: &Scene,
+        main_view: bool,
+        render_graph_builder: &mut T,
+    ) {
+        //Only the main view is upscaled since the upscaler's history belongs to it
+        let upscale = main_view && self.upscaler.is_active();
+        let render_scale = if upscale {
+            self.upscaler.render_scale().to_array()
+        } else {
+            [1.0; 2]
+        };
+        let view_size = match area {
+            Some(area) => TransientImageSize::Exact(vk::Extent2D {
+                width: area.size[0].max(1),
+                height: area.size[1].max(1),
+            }),
+            None => TransientImageSize::Relative(render_scale, target_image),
+        };
+
         let depth_image = render_graph_builder.create_transient_image(TransientImageDesc {
-            size: TransientImageSize::Relative([1.0; 2], target_image),
+            size: view_size.clone(),
             format: self.depth_format,
-            usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            mip_levels: 1,
+            memory_location: MemoryLocation::GpuOnly,
+        });
+
+        let color_image = render_graph_builder.create_transient_image(TransientImageDesc {
+            size: view_size.clone(),
+            format: Self::COLOR_FORMAT,
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::STORAGE,
+            mip_levels: 1,
+            memory_location: MemoryLocation::GpuOnly,
+        });
+
+        let velocity_image = render_graph_builder.create_transient_image(TransientImageDesc {
+            size: view_size,
+            format: Self::VELOCITY_FORMAT,
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::STORAGE,
             mip_levels: 1,
             memory_location: MemoryLocation::GpuOnly,
         });
 
+        //Later passes of this view find these without being handed them
+        let blackboard = render_graph_builder.blackboard_mut();
+        blackboard.insert(Self::VIEW_DEPTH_KEY, depth_image);
+        blackboard.insert(Self::VIEW_VELOCITY_KEY, velocity_image);
+        blackboard.insert(Self::VIEW_BUFFER_KEY, camera.buffer());
+
+        self.write_scene_pass(
+            "Scene Pass",
+            [color_image, velocity_image, depth_image],
+            camera,
+            scene,
+            &HashMap::new(),
+            upscale && self.upscaler.masks_scene(),
+            render_graph_builder,
+        );
+        self.sky
+            .write_render_passes(camera, color_image, depth_image, render_graph_builder);
+
+        //The volume is revoxelized around the main camera every frame, other views would fight over it
+        let color_image = if main_view {
+            self.voxel_gi.write_render_passes(
+                camera,
+                color_image,
+                depth_image,
+                render_graph_builder,
+            )
+        } else {
+            color_image
+        };
+
+        //Particles are simulated once per frame, so only the main view runs them
+        if main_view {
+            self.particles.write_render_passes(
+                &self.gpu_primitives,
+                camera,
+                color_image,
+                depth_image,
+                render_graph_builder,
+            );
+        }
+
+        let color_image = self.water.write_render_passes(
+            camera,
+            &scene.water_surfaces,
+            color_image,
+            depth_image,
+            render_graph_builder,
+        );
+
+        //Only downsampled when an effect needs it, the graph doesn't cull unused passes
+        let color_image = if main_view && self.volumetric_fog.settings.enabled {
+            let effect_depth_image = self
+                .reduced_resolution
+                .downsample_depth(depth_image, render_graph_builder);
+            self.volumetric_fog.write_render_passes(
+                camera,
+                color_image,
+                depth_image,
+                &self.reduced_resolution,
+                effect_depth_image,
+                render_graph_builder,
+            )
+        } else {
+            color_image
+        };
+
+        //Drawn over the fog so the shafts aren't washed out by it
+        if main_view {
+            self.lens_effects
+                .write_render_passes(camera, color_image, render_graph_builder);
+        }
+
+        let color_image =
+            self.viewport_helpers
+                .write_render_passes(camera, color_image, render_graph_builder);
+
+        let color_image = self.selection_outline.write_render_passes(
+            camera,
+            scene,
+            color_image,
+            render_graph_builder,
+        );
+
+        let color_image = if upscale {
+            self.upscaler.write_render_passes(
+                &self.resize_targets,
+                target_image,
+                color_image,
+                velocity_image,
+                render_graph_builder,
+            )
+        } else {
+            color_image
+        };
+
+        let mut motion_blur_pass_builder =
+            neptune_vulkan::render_graph_builder::RasterPassBuilder::new("Motion Blur Pass");
+        motion_blur_pass_builder.add_color_attachment(target_image, None);
+        let view_offset = area.map(|area| area.offset).unwrap_or_default();
+        if let Some(area) = area {
+            motion_blur_pass_builder.set_render_area(area.offset, area.size);
+        }
+        let mut draw_command_builder =
+            neptune_vulkan::render_graph_builder::RasterDrawCommandBuilder::new(
+                self.motion_blur_pipeline,
+            );
+        draw_command_builder.read_storage_image(color_image);
+        draw_command_builder.read_storage_image(velocity_image);
+        self.color_grading.bind(&mut draw_command_builder);
+        self.post_effects.bind(&mut draw_command_builder);
+        draw_command_builder.push_constant(self.dither_scale().to_bits());
+        draw_command_builder.push_constant(view_offset[0]);
+        draw_command_builder.push_constant(view_offset[1]);
+        draw_command_builder.draw(0..3, 0..1);
+        draw_command_builder.build(&mut motion_blur_pass_builder);
+        motion_blur_pass_builder.build(render_graph_builder);
+    }
+
+    /// Draws the scene geometry, sampled images found in `texture_remap` are swapped before drawing.
+    /// Opaque draws are sorted by pipeline, material and mesh, then transparent draws are blended on top back to front.
+    /// `checkerboard_mask` draws the upscaler's mask first so only this frame's half of the pixels is shaded
+    #[allow(clippy::too_many_arguments)]
+    fn write_scene_pass<T: RenderGraphBuilderTrait>(
+        &self,
+        name: &str,
+        [color_image, velocity_image, depth_image]: [ImageHandle; 3],
+        camera: &SceneCamera,
+        scene: &Scene,
+        texture_remap: &HashMap<ImageHandle, ImageHandle>,
+        checkerboard_mask: bool,
+        render_graph_builder: &mut T,
+    ) {
         let mut raster_pass_builder =
-            neptune_vulkan::render_graph_builder::RasterPassBuilder::new("Swapchain Pass");
-        raster_pass_builder.add_color_attachment(target_image, Some([0.0, 0.0, 0.0, 1.0]));
-        raster_pass_builder.add_depth_stencil_attachment(depth_image, Some((1.0, 0)));
+            neptune_vulkan::render_graph_builder::RasterPassBuilder::new(name);
+        raster_pass_builder.add_color_attachment(color_image, Some([0.0, 0.0, 0.0, 1.0]));
+        raster_pass_builder.add_color_attachment(velocity_image, Some([0.0; 4]));
+        raster_pass_builder
+            .add_depth_stencil_attachment(depth_image, Some(self.depth_mode.clear_value()));
+        if checkerboard_mask {
+            self.upscaler
+                .write_checkerboard_mask(&mut raster_pass_builder);
+        }
+
+        self.material_palette
+            .write_render_passes(render_graph_builder);
+
+        let camera_position = camera.position();
+        let visible_cells = if self.portal_culling {
+            scene
+                .cells
+                .visible_cells(&camera.view_projection_matrix(), camera_position)
+        } else {
+            None
+        };
 
+        let mut draw_list = DrawList::default();
         for (_key, instance) in scene.instance_map.iter() {
+            if let Some(visible_cells) = &visible_cells {
+                let visible = instance
+                    .world_bounds()
+                    .map(|bounds| scene.cells.is_visible(&bounds, visible_cells))
+                    .unwrap_or(true);
+                if !visible {
+                    continue;
+                }
+            }
+
+            let model_matrix = instance.transform.model_matrix();
             for model_primitive in instance.model.primitives.iter() {
-                if model_primitive
+                let transparent = model_primitive
                     .material
                     .as_ref()
                     .map(|material| material.alpha_blending)
-                    .unwrap_or_default()
-                {
-                    continue;
-                }
-
-                let texture = model_primitive
+                    .unwrap_or_default();
+                let graph_pipeline = model_primitive
                     .material
                     .as_ref()
-                    .and_then(|material| material.base_color_texture.clone())
-                    .unwrap_or_else(|| self.default_texture.clone());
+                    .and_then(|material| material.pipeline.get());
+                let pipeline = match (graph_pipeline, transparent) {
+                    (Some(graph_pipeline), _) => graph_pipeline,
+                    (None, false) => self.raster_pipeline,
+                    (None, true) => self.transparent_pipeline,
+                };
 
-                let mut draw_command_builder =
-                    neptune_vulkan::render_graph_builder::RasterDrawCommandBuilder::new(
-                        self.raster_pipeline,
-                    );
+                let distance = if transparent {
+                    model_matrix
+                        .transform_point3(model_primitive.primitive.bounding_box.center())
+                        .distance_squared(camera_position)
+                } else {
+                    0.0
+                };
 
-                draw_command_builder.add_vertex_buffer(BufferOffset {
-                    buffer: model_primitive.primitive.position_buffer,
-                    offset: 0,
-                });
-                draw_command_builder.add_vertex_buffer(BufferOffset {
-                    buffer: model_primitive.primitive.attributes_buffer,
-                    offset: 0,
-                });
-                draw_command_builder.read_buffer(camera.camera_buffer);
-                draw_command_builder.read_buffer(scene.model_matrix_buffer);
-                draw_command_builder.read_sampler(texture.sampler);
-                draw_command_builder.read_sampled_image(texture.image);
+                let mut flags = 0;
+                if transparent {
+                    flags |= DrawData::TRANSPARENT;
+                }
+                if model_primitive.lightmap.is_some() {
+                    flags |= DrawData::LIGHTMAPPED;
+                }
+                if graph_pipeline.is_some() {
+                    flags |= DrawData::SHADER_GRAPH;
+                }
 
-                let instance_range = (instance.index as u32)..(instance.index as u32 + 1);
+                draw_list.push(
+                    DrawItem {
+                        pipeline,
+                        model_primitive,
+                        instance_index: instance.index as u32,
+                        material_index: self.material_index(model_primitive),
+                        flags,
+                        distance,
+                    },
+                    transparent,
+                );
+            }
+        }
+        draw_list.sort();
+        let draw_buffer = write_draw_buffer(
+            draw_list.iter().map(DrawItem::draw_data).collect(),
+            render_graph_builder,
+        );
 
-                if let Some(index_buffer_ref) = &model_primitive.primitive.index_buffer {
-                    draw_command_builder.draw_indexed(
-                        0,
-                        0..index_buffer_ref.count,
-                        instance_range,
-                        BufferOffset {
-                            buffer: index_buffer_ref.buffer,
-                            offset: 0,
-                        },
-                        neptune_vulkan::render_graph::IndexType::U32,
-                    );
-                } else {
-                    draw_command_builder.draw(
-                        0..model_primitive.primitive.vertex_count as u32,
-                        instance_range,
-                    );
-                }
+        for (draw_index, draw_item) in draw_list.opaque.iter().enumerate() {
+            self.write_draw_command(
+                draw_item,
+                draw_index as u32,
+                draw_buffer,
+                camera,
+                scene,
+                texture_remap,
+                &mut raster_pass_builder,
+            );
+        }
 
-                draw_command_builder.build(&mut raster_pass_builder);
+        //The cull passes are built before the scene pass, so they run first
+        for layer in scene.foliage.iter() {
+            if let Some(visible_foliage) = self.foliage.cull(camera, layer, render_graph_builder) {
+                let mut flags = 0;
+                if layer.model_primitive.lightmap.is_some() {
+                    flags |= DrawData::LIGHTMAPPED;
+                }
+                let foliage_draw_buffer = write_draw_buffer(
+                    vec![DrawData::new(
+                        0,
+                        self.material_index(&layer.model_primitive),
+                        flags,
+                    )],
+                    render_graph_builder,
+                );
+                self.write_foliage_draw_command(
+                    layer,
+                    &visible_foliage,
+                    foliage_draw_buffer,
+                    camera,
+                    texture_remap,
+                    &mut raster_pass_builder,
+                );
             }
         }
 
+        for (draw_index, draw_item) in draw_list
+            .transparent
+            .iter()
+            .enumerate()
+            .map(|(index, draw_item)| (draw_list.opaque.len() + index, draw_item))
+        {
+            self.write_draw_command(
+                draw_item,
+                draw_index as u32,
+                draw_buffer,
+                camera,
+                scene,
+                texture_remap,
+                &mut raster_pass_builder,
+            );
+        }
+
         raster_pass_builder.build(render_graph_builder);
     }
+
+    /// `draw_index` is the item's position in `draw_buffer`
+    #[allow(clippy::too_many_arguments)]
+    fn write_draw_command(
+        &self,
+        draw_item: &DrawItem,
+        draw_index: u32,
+        draw_buffer: neptune_vulkan::BufferHandle,
+        camera: &SceneCamera,
+        scene: &Scene,
+        texture_remap: &HashMap<ImageHandle, ImageHandle>,
+        raster_pass_builder: &mut neptune_vulkan::render_graph_builder::RasterPassBuilder,
+    ) {
+        let model_primitive = draw_item.model_primitive;
+        let mut draw_command_builder =
+            neptune_vulkan::render_graph_builder::RasterDrawCommandBuilder::new(draw_item.pipeline);
+
+        draw_command_builder.add_vertex_buffer(model_primitive.primitive.position_buffer_offset());
+        draw_command_builder
+            .add_vertex_buffer(model_primitive.primitive.attributes_buffer_offset());
+        draw_command_builder.read_buffer(camera.camera_buffer);
+        draw_command_builder.read_buffer(draw_buffer);
+        draw_command_builder.read_buffer(scene.model_matrix_buffer);
+        let texture = self.bind_material(model_primitive, texture_remap, &mut draw_command_builder);
+
+        //Shader graphs can sample any of the material textures
+        let graph_pipeline = model_primitive
+            .material
+            .as_ref()
+            .and_then(|material| material.pipeline.get());
+        if let (Some(_), Some(material)) = (graph_pipeline, &model_primitive.material) {
+            draw_command_builder.read_sampler(texture.sampler);
+            for material_texture in [
+                material.base_color_texture.as_ref(),
+                material.metallic_roughness_texture.as_ref(),
+                material.normal_texture.as_ref(),
+                material
+                    .occlusion_texture
+                    .as_ref()
+                    .map(|(texture, _)| texture),
+                material.emissive_texture.as_ref(),
+            ] {
+                let image = material_texture.unwrap_or(&self.default_texture).image;
+                draw_command_builder
+                    .read_sampled_image(texture_remap.get(&image).copied().unwrap_or(image));
+            }
+        }
+
+        let instance_range = draw_index..(draw_index + 1);
+
+        if let Some(index_buffer_ref) = &model_primitive.primitive.index_buffer {
+            draw_command_builder.draw_indexed(
+                0,
+                0..index_buffer_ref.count,
+                instance_range,
+                BufferOffset {
+                    buffer: index_buffer_ref.buffer,
+                    offset: 0,
+                },
+                neptune_vulkan::render_graph::IndexType::U32,
+            );
+        } else {
+            draw_command_builder.draw(
+                0..model_primitive.primitive.vertex_count as u32,
+                instance_range,
+            );
+        }
+
+        draw_command_builder.build(raster_pass_builder);
+    }
+
+    /// Palette index of the primitive's material constants
+    fn material_index(&self, model_primitive: &ModelPrimitive) -> u32 {
+        model_primitive
+            .material
+            .as_ref()
+            .map(|material| &material.constants)
+            .unwrap_or(&self.default_material_constants)
+            .index()
+    }
+
+    /// Binds the base color texture, lightmap, material palette and reflection probes in the order mesh.frag expects them, returns the base color texture.
+    /// The material index comes from the draw buffer
+    fn bind_material(
+        &self,
+        model_primitive: &ModelPrimitive,
+        texture_remap: &HashMap<ImageHandle, ImageHandle>,
+        draw_command_builder: &mut neptune_vulkan::render_graph_builder::RasterDrawCommandBuilder,
+    ) -> MaterialTexture {
+        let texture = model_primitive
+            .material
+            .as_ref()
+            .and_then(|material| material.base_color_texture.clone())
+            .unwrap_or_else(|| self.default_texture.clone());
+        let texture_image = texture_remap
+            .get(&texture.image)
+            .copied()
+            .unwrap_or(texture.image);
+        draw_command_builder.read_sampler(texture.sampler);
+        draw_command_builder.read_sampled_image(texture_image);
+
+        let lightmap = model_primitive
+            .lightmap
+            .as_ref()
+            .unwrap_or(&self.default_texture);
+        draw_command_builder.read_sampler(lightmap.sampler);
+        draw_command_builder.read_sampled_image(lightmap.image);
+
+        draw_command_builder.read_buffer(self.material_palette.buffer());
+
+        draw_command_builder.read_buffer(self.reflection_probes.buffer());
+        draw_command_builder.read_sampler(self.reflection_probes.sampler());
+        draw_command_builder.read_sampled_image(self.reflection_probes.atlas());
+
+        texture
+    }
+
+    /// Draws a foliage layer's visible instances with one indirect draw, the instance count is filled in by the cull pass
+    fn write_foliage_draw_command(
+        &self,
+        layer: &FoliageLayer,
+        visible_foliage: &VisibleFoliage,
+        draw_buffer: neptune_vulkan::BufferHandle,
+        camera: &SceneCamera,
+        texture_remap: &HashMap<ImageHandle, ImageHandle>,
+        raster_pass_builder: &mut neptune_vulkan::render_graph_builder::RasterPassBuilder,
+    ) {
+        let model_primitive = &layer.model_primitive;
+        let Some(index_buffer_ref) = &model_primitive.primitive.index_buffer else {
+            return;
+        };
+
+        let mut draw_command_builder =
+            neptune_vulkan::render_graph_builder::RasterDrawCommandBuilder::new(
+                self.foliage_pipeline,
+            );
+        draw_command_builder.add_vertex_buffer(model_primitive.primitive.position_buffer_offset());
+        draw_command_builder
+            .add_vertex_buffer(model_primitive.primitive.attributes_buffer_offset());
+        draw_command_builder.read_buffer(camera.camera_buffer);
+        draw_command_builder.read_buffer(draw_buffer);
+        draw_command_builder.read_buffer(visible_foliage.instance_buffer);
+        self.bind_material(model_primitive, texture_remap, &mut draw_command_builder);
+        draw_command_builder.read_buffer(self.foliage.wind_buffer());
+        draw_command_builder.push_constant(layer.height().to_bits());
+        draw_command_builder.draw_indirect_indexed(
+            BufferOffset {
+                buffer: visible_foliage.draw_buffer,
+                offset: 0,
+            },
+            1,
+            FoliageRenderer::DRAW_COMMAND_SIZE as u32,
+            BufferOffset {
+                buffer: index_buffer_ref.buffer,
+                offset: 0,
+            },
+            neptune_vulkan::render_graph::IndexType::U32,
+        );
+        draw_command_builder.build(raster_pass_builder);
+    }
 }
 
 #[derive(Clone)]
@@ -183,13 +1273,65 @@ pub struct Model {
     pub primitives: Vec<ModelPrimitive>,
 }
 
+impl Model {
+    /// Local space bounds of every primitive, None for an empty model
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        BoundingBox::union_all(
+            self.primitives
+                .iter()
+                .map(|model_primitive| model_primitive.primitive.bounding_box),
+        )
+    }
+
+    pub fn bounding_sphere(&self) -> Option<BoundingSphere> {
+        BoundingSphere::union_all(
+            self.primitives
+                .iter()
+                .map(|model_primitive| model_primitive.primitive.bounding_sphere),
+        )
+    }
+}
+
 #[derive(Clone)]
 pub struct ModelPrimitive {
     pub primitive: Arc<Primitive>,
     pub material: Option<Arc<Material>>,
+    /// Baked lighting sampled with uv1, only meant for static geometry
+    pub lightmap: Option<MaterialTexture>,
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct SceneInstanceData {
+    model_matrix: Mat4,
+    /// Stored as a Mat4 to match the std140 layout, only the upper 3x3 is used
+    normal_matrix: Mat4,
+    /// Model matrix as of the last upload, used to write motion vectors
+    previous_model_matrix: Mat4,
+}
+
+impl SceneInstanceData {
+    fn new(transform: &Transform) -> Self {
+        let model_matrix = transform.model_matrix();
+        Self {
+            model_matrix,
+            normal_matrix: Self::normal_matrix(&model_matrix),
+            previous_model_matrix: model_matrix,
+        }
+    }
+
+    fn update(&mut self, transform: &Transform) {
+        self.model_matrix = transform.model_matrix();
+        self.normal_matrix = Self::normal_matrix(&self.model_matrix);
+    }
+
+    //Taken from the composed matrix so it matches what the vertices are transformed by, the shader only reads the 3x3
+    fn normal_matrix(model_matrix: &Mat4) -> Mat4 {
+        model_matrix.inverse().transpose()
+    }
 }
 
-#[derive(Default, Copy, Clone)]
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub struct SceneInstanceHandle(slotmap::DefaultKey);
 
 struct SceneInstance {
@@ -198,18 +1340,47 @@ struct SceneInstance {
     model: Model,
 }
 
+impl SceneInstance {
+    fn world_bounds(&self) -> Option<BoundingBox> {
+        self.model
+            .bounding_box()
+            .map(|bounds| bounds.transform(&self.transform.model_matrix()))
+    }
+
+    fn world_sphere(&self) -> Option<BoundingSphere> {
+        self.model
+            .bounding_sphere()
+            .map(|sphere| sphere.transform(&self.transform.model_matrix()))
+    }
+}
+
 pub struct Scene {
     instance_map: SlotMap<slotmap::DefaultKey, SceneInstance>,
 
     model_matrix_index_pool: IdPool,
     model_matrix_buffer: neptune_vulkan::BufferHandle,
     model_matrix_buffer_size: usize,
-    model_matrix_data: Rc<RefCell<Vec<Mat4>>>,
+    model_matrix_data: Vec<SceneInstanceData>,
+
+    spatial: Bvh<SceneInstanceHandle>,
+    spatial_dirty: bool,
+
+    selection: HashSet<SceneInstanceHandle>,
+
+    pub post_effects: PostEffectSettings,
+    pub water_surfaces: Vec<WaterSurface>,
+    pub reflection_probes: Vec<ReflectionProbe>,
+    pub post_volumes: Vec<PostProcessVolume>,
+    pub foliage: Vec<FoliageLayer>,
+    pub cells: CellPortalGraph,
+
+    /// Bumped whenever an instance is added, removed or moved
+    version: u64,
 }
 
 impl Scene {
     pub fn new(device: &mut Device, instance_count: usize) -> anyhow::Result<Self> {
-        let model_matrix_data = vec![Mat4::ZERO; instance_count];
+        let model_matrix_data = vec![SceneInstanceData::default(); instance_count];
         let model_matrix_buffer = device
             .create_buffer_init(
                 "ModelMatrixBuffer",
@@ -219,7 +1390,7 @@ impl Scene {
             )
             .context("Failed to create camera buffer")?;
         let model_matrix_index_pool = IdPool::new(0..instance_count);
-        let model_matrix_buffer_size = instance_count * std::mem::size_of::<Mat4>();
+        let model_matrix_buffer_size = instance_count * std::mem::size_of::<SceneInstanceData>();
 
         let instance_map = SlotMap::default();
 
@@ -228,7 +1399,17 @@ impl Scene {
             model_matrix_index_pool,
             model_matrix_buffer,
             model_matrix_buffer_size,
-            model_matrix_data: Rc::new(RefCell::new(model_matrix_data)),
+            model_matrix_data,
+            spatial: Bvh::default(),
+            spatial_dirty: false,
+            selection: HashSet::new(),
+            post_effects: PostEffectSettings::default(),
+            water_surfaces: Vec::new(),
+            reflection_probes: Vec::new(),
+            post_volumes: Vec::new(),
+            foliage: Vec::new(),
+            cells: CellPortalGraph::default(),
+            version: 0,
         })
     }
 
@@ -238,8 +1419,9 @@ impl Scene {
         model: Model,
     ) -> Option<SceneInstanceHandle> {
         if let Some(index) = self.model_matrix_index_pool.get() {
-            let mut data_mut = self.model_matrix_data.borrow_mut();
-            data_mut[index] = transform.model_matrix();
+            self.model_matrix_data[index] = SceneInstanceData::new(&transform);
+            self.spatial_dirty = true;
+            self.version += 1;
             Some(SceneInstanceHandle(self.instance_map.insert(
                 SceneInstance {
                     index,
@@ -255,10 +1437,12 @@ impl Scene {
     pub fn remove_instance(&mut self, instance_handle: SceneInstanceHandle) {
         if let Some(instance) = self.instance_map.remove(instance_handle.0) {
             //Clear the old matrix,
-            let mut data_mut = self.model_matrix_data.borrow_mut();
-            data_mut[instance.index] = Mat4::ZERO;
+            self.model_matrix_data[instance.index] = SceneInstanceData::default();
 
             self.model_matrix_index_pool.free(instance.index);
+            self.selection.remove(&instance_handle);
+            self.spatial_dirty = true;
+            self.version += 1;
         } else {
             warn!("SceneInstance({:?}) doesn't exist", instance_handle.0)
         }
@@ -266,20 +1450,141 @@ impl Scene {
 
     pub fn update_instance(&mut self, instance_handle: SceneInstanceHandle, transform: Transform) {
         if let Some(instance) = self.instance_map.get_mut(instance_handle.0) {
-            let mut data_mut = self.model_matrix_data.borrow_mut();
-            data_mut[instance.index] = transform.model_matrix();
+            self.model_matrix_data[instance.index].update(&transform);
 
             instance.transform = transform;
+            self.spatial_dirty = true;
+            self.version += 1;
+        } else {
+            warn!("SceneInstance({:?}) doesn't exist", instance_handle.0)
+        }
+    }
+
+    /// Swaps the instance's model, used by geometry that changes every frame like `DynamicMesh`
+    pub fn update_instance_model(&mut self, instance_handle: SceneInstanceHandle, model: Model) {
+        if let Some(instance) = self.instance_map.get_mut(instance_handle.0) {
+            instance.model = model;
+            self.spatial_dirty = true;
+            self.version += 1;
         } else {
             warn!("SceneInstance({:?}) doesn't exist", instance_handle.0)
         }
     }
 
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn inspectable_buffer(&self) -> InspectableBuffer {
+        InspectableBuffer {
+            name: "Model Matrices".to_string(),
+            buffer: self.model_matrix_buffer,
+            size: self.model_matrix_buffer_size,
+            element: BufferElement::F32,
+        }
+    }
+
+    pub fn instances(&self) -> impl Iterator<Item = (&Transform, &Model)> {
+        self.instance_map
+            .values()
+            .map(|instance| (&instance.transform, &instance.model))
+    }
+
+    pub fn get_model(&self, instance_handle: SceneInstanceHandle) -> Option<&Model> {
+        self.instance_map
+            .get(instance_handle.0)
+            .map(|instance| &instance.model)
+    }
+
+    pub fn select(&mut self, instance_handle: SceneInstanceHandle) {
+        if self.instance_map.contains_key(instance_handle.0) {
+            self.selection.insert(instance_handle);
+        }
+    }
+
+    pub fn deselect(&mut self, instance_handle: SceneInstanceHandle) {
+        self.selection.remove(&instance_handle);
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection.clear();
+    }
+
+    pub fn is_selected(&self, instance_handle: SceneInstanceHandle) -> bool {
+        self.selection.contains(&instance_handle)
+    }
+
+    pub fn selection(&self) -> impl Iterator<Item = SceneInstanceHandle> + '_ {
+        self.selection.iter().copied()
+    }
+
+    /// World space bounding sphere of the whole selection
+    pub fn selection_bounds(&self) -> Option<BoundingSphere> {
+        BoundingSphere::union_all(
+            self.selection
+                .iter()
+                .filter_map(|handle| self.instance_map.get(handle.0))
+                .filter_map(SceneInstance::world_sphere),
+        )
+    }
+
+    /// Gpu instance index and model of every selected instance
+    pub(crate) fn selected_instances(&self) -> impl Iterator<Item = (u32, &Model)> {
+        self.selection.iter().filter_map(|handle| {
+            self.instance_map
+                .get(handle.0)
+                .map(|instance| (instance.index as u32, &instance.model))
+        })
+    }
+
+    pub(crate) fn model_matrix_buffer(&self) -> neptune_vulkan::BufferHandle {
+        self.model_matrix_buffer
+    }
+
+    /// Rebuilds the spatial structure if any instance was added, removed or moved since the last call
+    pub fn update_spatial(&mut self) {
+        if self.spatial_dirty {
+            self.spatial = Bvh::build(
+                self.instance_map
+                    .iter()
+                    .filter_map(|(key, instance)| {
+                        instance
+                            .world_bounds()
+                            .map(|bounds| (SceneInstanceHandle(key), bounds))
+                    })
+                    .collect(),
+            );
+            self.spatial_dirty = false;
+        }
+    }
+
+    pub fn ray_cast(
+        &self,
+        ray: &Ray,
+        max_distance: f32,
+    ) -> Option<SpatialHit<SceneInstanceHandle>> {
+        self.spatial.ray_cast(ray, max_distance)
+    }
+
+    pub fn sphere_overlap(&self, center: Vec3, radius: f32) -> Vec<SceneInstanceHandle> {
+        self.spatial.sphere_overlap(center, radius)
+    }
+
+    pub fn k_nearest(&self, point: Vec3, count: usize) -> Vec<SpatialHit<SceneInstanceHandle>> {
+        self.spatial.k_nearest(point, count)
+    }
+
     pub fn write_render_passes<T: RenderGraphBuilderTrait>(
         &mut self,
         render_graph_builder: &mut T,
     ) {
-        let model_matrix_data_clone = self.model_matrix_data.clone();
+        //Copied now since the render thread runs the write while the next frame is being updated
+        let model_matrix_data = self.model_matrix_data.clone();
+
+        //The uploaded matrices become the previous frame's matrices
+        for instance_data in self.model_matrix_data.iter_mut() {
+            instance_data.previous_model_matrix = instance_data.model_matrix;
+        }
         render_graph_builder.add_buffer_write(
             BufferOffset {
                 buffer: self.model_matrix_buffer,
@@ -287,73 +1592,168 @@ impl Scene {
             },
             self.model_matrix_buffer_size,
             BufferWriteCallback::new(move |slice| {
-                let model_matrix_data = model_matrix_data_clone.borrow();
                 slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&model_matrix_data) });
             }),
         );
+
+        for layer in self.foliage.iter_mut() {
+            layer.write_render_passes(render_graph_builder);
+        }
     }
 }
 
+/// Everything a pass needs to know about the view it's rendering, uploaded once per view per frame.
+/// Shaders declare the same layout as a `ViewBuffer` block and index it with the camera buffer binding
 #[repr(C)]
 #[derive(Default, Debug, Clone)]
-struct SceneCameraData {
-    view_projection_matrix: Mat4,
-    camera_position: Vec3,
+pub struct ViewData {
+    pub view_projection_matrix: Mat4,
+    pub previous_view_projection_matrix: Mat4,
+    pub inverse_view_projection_matrix: Mat4,
+    pub camera_position: Vec3,
+    pub near_clip: f32,
+    pub view_matrix: Mat4,
+    /// Includes the jitter
+    pub projection_matrix: Mat4,
+    pub inverse_view_matrix: Mat4,
+    pub inverse_projection_matrix: Mat4,
+    /// Pixel size of the view's render target
+    pub viewport_size: Vec2,
+    /// Sub-pixel offset in pixels
+    pub jitter: Vec2,
+    /// 0 for an infinite far plane
+    pub far_clip: f32,
+    /// 1 if the near plane is at depth 1, see `DepthMode`
+    pub reversed_depth: u32,
+    _padding: [f32; 2],
 }
 
-impl SceneCameraData {
-    fn new(camera: &Camera, camera_transform: &Transform, aspect_ratio: f32) -> Self {
-        let projection_matrix = camera.projection_matrix(aspect_ratio);
+impl ViewData {
+    fn new(
+        camera: &Camera,
+        camera_transform: &Transform,
+        aspect_ratio: f32,
+        viewport_size: Vec2,
+        jitter: Vec2,
+        depth_mode: DepthMode,
+        previous_view_projection_matrix: Mat4,
+    ) -> Self {
+        let projection_matrix = Mat4::from_translation((jitter * 2.0 / viewport_size).extend(0.0))
+            * camera.depth_projection_matrix(aspect_ratio, depth_mode);
         let view_matrix = camera_transform.view_matrix();
         let view_projection_matrix = projection_matrix * view_matrix;
         Self {
             view_projection_matrix,
+            previous_view_projection_matrix,
+            inverse_view_projection_matrix: view_projection_matrix.inverse(),
             camera_position: camera_transform.position,
+            near_clip: camera.near_clip,
+            view_matrix,
+            projection_matrix,
+            inverse_view_matrix: view_matrix.inverse(),
+            inverse_projection_matrix: projection_matrix.inverse(),
+            viewport_size,
+            jitter,
+            far_clip: camera.far_plane().unwrap_or_default(),
+            reversed_depth: (depth_mode == DepthMode::Reversed) as u32,
+            _padding: [0.0; 2],
         }
     }
 }
 
 pub struct SceneCamera {
+    /// Sub-pixel projection offset in pixels for temporal techniques, zero otherwise
+    pub jitter: Vec2,
+    /// Size of the render target relative to the size passed to `update`, the aspect ratio always comes from the unscaled size
+    pub render_scale: Vec2,
+    depth_mode: DepthMode,
     camera_buffer: neptune_vulkan::BufferHandle,
-    camera_data: Rc<RefCell<SceneCameraData>>,
+    view_data: ViewData,
 }
 
 impl SceneCamera {
-    pub fn new(device: &mut Device) -> anyhow::Result<Self> {
-        let camera_data = SceneCameraData::default();
+    /// `depth_mode` must match the renderer drawing the view
+    pub fn new(device: &mut Device, depth_mode: DepthMode) -> anyhow::Result<Self> {
+        let view_data = ViewData::default();
         let camera_buffer = device
             .create_buffer_init(
                 "SceneCamera",
                 BufferUsage::STORAGE | BufferUsage::TRANSFER,
                 MemoryLocation::GpuOnly,
-                unsafe { slice_to_bytes_unsafe(&[camera_data.clone()]) },
+                unsafe { slice_to_bytes_unsafe(std::slice::from_ref(&view_data)) },
             )
             .context("Failed to create camera buffer")?;
         Ok(Self {
+            jitter: Vec2::ZERO,
+            render_scale: Vec2::ONE,
+            depth_mode,
             camera_buffer,
-            camera_data: Rc::new(RefCell::new(camera_data)),
+            view_data,
         })
     }
 
-    pub fn update(&mut self, camera: &Camera, camera_transform: &Transform, aspect_ratio: f32) {
-        let mut data_mut = self.camera_data.borrow_mut();
-        *data_mut = SceneCameraData::new(camera, camera_transform, aspect_ratio);
+    pub fn buffer(&self) -> neptune_vulkan::BufferHandle {
+        self.camera_buffer
+    }
+
+    /// The data uploaded for the current frame
+    pub fn view_data(&self) -> ViewData {
+        self.view_data.clone()
+    }
+
+    pub fn view_projection_matrix(&self) -> Mat4 {
+        self.view_data.view_projection_matrix
+    }
+
+    pub fn view_matrix(&self) -> Mat4 {
+        self.view_data.view_matrix
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.view_data.camera_position
+    }
+
+    pub fn update(
+        &mut self,
+        camera: &Camera,
+        camera_transform: &Transform,
+        viewport_size: [u32; 2],
+    ) {
+        let output_size =
+            Vec2::new(viewport_size[0] as f32, viewport_size[1] as f32).max(Vec2::ONE);
+        //Truncated the same way as the graph's relative images
+        let viewport_size = (output_size * self.render_scale).floor().max(Vec2::ONE);
+        let mut view_data = ViewData::new(
+            camera,
+            camera_transform,
+            output_size.x / output_size.y,
+            viewport_size,
+            self.jitter,
+            self.depth_mode,
+            self.view_data.view_projection_matrix,
+        );
+        //No previous frame on the first update, so there is no camera motion
+        if self.view_data.viewport_size == Vec2::ZERO {
+            view_data.previous_view_projection_matrix = view_data.view_projection_matrix;
+        }
+        self.view_data = view_data;
     }
 
     pub fn write_render_passes<T: RenderGraphBuilderTrait>(
         &mut self,
         render_graph_builder: &mut T,
     ) {
-        let camera_data_clone = self.camera_data.clone();
+        let view_data = self.view_data.clone();
         render_graph_builder.add_buffer_write(
             BufferOffset {
                 buffer: self.camera_buffer,
                 offset: 0,
             },
-            std::mem::size_of::<SceneCameraData>(),
+            std::mem::size_of::<ViewData>(),
             BufferWriteCallback::new(move |slice| {
-                let camera_data = camera_data_clone.borrow().clone();
-                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&[camera_data]) });
+                slice.copy_from_slice(unsafe {
+                    slice_to_bytes_unsafe(std::slice::from_ref(&view_data))
+                });
             }),
         );
     }
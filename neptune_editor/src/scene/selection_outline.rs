@@ -0,0 +1,310 @@
+use crate::mesh;
+use crate::scene::draw_list::{write_draw_buffer, DrawData};
+use crate::scene::scene_renderer::{slice_to_bytes_unsafe, Scene, SceneCamera};
+use anyhow::Context;
+use glam::{UVec4, Vec4};
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, RasterDrawCommandBuilder, RasterPassBuilder,
+    RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{
+    vk, BufferHandle, BufferUsage, Device, ImageHandle, RasterPipelineHandle, TransientImageDesc,
+    TransientImageSize,
+};
+
+#[derive(Debug, Clone)]
+pub struct SelectionOutlineSettings {
+    pub color: Vec4,
+    /// Outline width in pixels, clamped to `SelectionOutline::MAX_WIDTH`
+    pub width: u32,
+}
+
+impl Default for SelectionOutlineSettings {
+    fn default() -> Self {
+        Self {
+            color: Vec4::new(1.0, 0.6, 0.0, 1.0),
+            width: 3,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct SelectionOutlineData {
+    color: Vec4,
+    /// x: width in pixels
+    params: Vec4,
+}
+
+/// Draws an outline around selected scene instances using a jump flood distance field
+pub struct SelectionOutline {
+    pub settings: SelectionOutlineSettings,
+
+    color_format: vk::Format,
+    mask_pipeline: RasterPipelineHandle,
+    jump_flood_pipeline: RasterPipelineHandle,
+    composite_pipeline: RasterPipelineHandle,
+    outline_buffer: BufferHandle,
+}
+
+impl SelectionOutline {
+    pub const MAX_WIDTH: u32 = 32;
+
+    /// Stores the pixel position of the nearest selected pixel, negative when there is none
+    const SEED_FORMAT: vk::Format = vk::Format::R32G32_SFLOAT;
+
+    pub fn new(
+        device: &mut Device,
+        color_format: vk::Format,
+        settings: SelectionOutlineSettings,
+    ) -> anyhow::Result<Self> {
+        let mask_pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::MESH_STATIC_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[
+                        mesh::VertexPosition::VERTEX_BUFFER_LAYOUT,
+                        mesh::VertexAttributes::VERTEX_BUFFER_LAYOUT,
+                    ],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                //No depth test so hidden parts of the selection are still outlined
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::SELECTION_MASK_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format: Self::SEED_FORMAT,
+                        blend: None,
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })?;
+
+        let jump_flood_pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::SELECTION_JUMP_FLOOD_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format: Self::SEED_FORMAT,
+                        blend: None,
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })?;
+
+        let composite_pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::SELECTION_OUTLINE_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format: color_format,
+                        blend: None,
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })?;
+
+        let outline_buffer = device
+            .create_buffer_init(
+                "SelectionOutlineBuffer",
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                unsafe { slice_to_bytes_unsafe(&[SelectionOutlineData::default()]) },
+            )
+            .context("Failed to create selection outline buffer")?;
+
+        Ok(Self {
+            settings,
+            color_format,
+            mask_pipeline,
+            jump_flood_pipeline,
+            composite_pipeline,
+            outline_buffer,
+        })
+    }
+
+    /// Returns the outlined color image, or the input image if nothing is selected
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &self,
+        camera: &SceneCamera,
+        scene: &Scene,
+        color_image: ImageHandle,
+        render_graph_builder: &mut T,
+    ) -> ImageHandle {
+        let width = self.settings.width.min(Self::MAX_WIDTH);
+        if width == 0 || scene.selected_instances().next().is_none() {
+            return color_image;
+        }
+
+        let outline_data = SelectionOutlineData {
+            color: self.settings.color,
+            params: Vec4::new(width as f32, 0.0, 0.0, 0.0),
+        };
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: self.outline_buffer,
+                offset: 0,
+            },
+            std::mem::size_of::<SelectionOutlineData>(),
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&[outline_data]) });
+            }),
+        );
+
+        let seed_images = [0, 1].map(|_| {
+            render_graph_builder.create_transient_image(TransientImageDesc {
+                size: TransientImageSize::Relative([1.0; 2], color_image),
+                format: Self::SEED_FORMAT,
+                usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::STORAGE,
+                mip_levels: 1,
+                memory_location: MemoryLocation::GpuOnly,
+            })
+        });
+
+        let mut mask_pass_builder = RasterPassBuilder::new("Selection Mask Pass");
+        mask_pass_builder.add_color_attachment(seed_images[0], Some([-1.0; 4]));
+        let selected_primitives: Vec<_> = scene
+            .selected_instances()
+            .flat_map(|(instance_index, model)| {
+                model
+                    .primitives
+                    .iter()
+                    .map(move |model_primitive| (instance_index, model_primitive))
+            })
+            .collect();
+        let draw_buffer = write_draw_buffer(
+            selected_primitives
+                .iter()
+                .map(|(instance_index, _)| DrawData::new(*instance_index, 0, 0))
+                .collect(),
+            render_graph_builder,
+        );
+        for (draw_index, (_, model_primitive)) in selected_primitives.iter().enumerate() {
+            let mut draw_command_builder = RasterDrawCommandBuilder::new(self.mask_pipeline);
+            draw_command_builder
+                .add_vertex_buffer(model_primitive.primitive.position_buffer_offset());
+            draw_command_builder
+                .add_vertex_buffer(model_primitive.primitive.attributes_buffer_offset());
+            draw_command_builder.read_buffer(camera.buffer());
+            draw_command_builder.read_buffer(draw_buffer);
+            draw_command_builder.read_buffer(scene.model_matrix_buffer());
+
+            let draw_index = draw_index as u32;
+            let instance_range = draw_index..(draw_index + 1);
+            if let Some(index_buffer_ref) = &model_primitive.primitive.index_buffer {
+                draw_command_builder.draw_indexed(
+                    0,
+                    0..index_buffer_ref.count,
+                    instance_range,
+                    BufferOffset {
+                        buffer: index_buffer_ref.buffer,
+                        offset: 0,
+                    },
+                    neptune_vulkan::render_graph::IndexType::U32,
+                );
+            } else {
+                draw_command_builder.draw(
+                    0..model_primitive.primitive.vertex_count as u32,
+                    instance_range,
+                );
+            }
+            draw_command_builder.build(&mut mask_pass_builder);
+        }
+        mask_pass_builder.build(render_graph_builder);
+
+        //Only distances up to the outline width matter, so the largest step can start there
+        let mut seed_index = 0;
+        let mut step_size = width.next_power_of_two();
+        while step_size > 0 {
+            let step_buffer = render_graph_builder.create_transient_buffer(
+                std::mem::size_of::<UVec4>(),
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+            );
+            let step_data = UVec4::new(step_size, 0, 0, 0);
+            render_graph_builder.add_buffer_write(
+                BufferOffset {
+                    buffer: step_buffer,
+                    offset: 0,
+                },
+                std::mem::size_of::<UVec4>(),
+                BufferWriteCallback::new(move |slice| {
+                    slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&[step_data]) });
+                }),
+            );
+
+            let mut jump_flood_pass_builder =
+                RasterPassBuilder::new(&format!("Selection Jump Flood Pass {}", step_size));
+            jump_flood_pass_builder.add_color_attachment(seed_images[1 - seed_index], None);
+            let mut draw_command_builder = RasterDrawCommandBuilder::new(self.jump_flood_pipeline);
+            draw_command_builder.read_buffer(step_buffer);
+            draw_command_builder.read_storage_image(seed_images[seed_index]);
+            draw_command_builder.draw(0..3, 0..1);
+            draw_command_builder.build(&mut jump_flood_pass_builder);
+            jump_flood_pass_builder.build(render_graph_builder);
+
+            seed_index = 1 - seed_index;
+            step_size /= 2;
+        }
+
+        let outline_color_image = render_graph_builder.create_transient_image(TransientImageDesc {
+            size: TransientImageSize::Relative([1.0; 2], color_image),
+            format: self.color_format,
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::STORAGE,
+            mip_levels: 1,
+            memory_location: MemoryLocation::GpuOnly,
+        });
+
+        let mut composite_pass_builder = RasterPassBuilder::new("Selection Outline Pass");
+        composite_pass_builder.add_color_attachment(outline_color_image, None);
+        let mut draw_command_builder = RasterDrawCommandBuilder::new(self.composite_pipeline);
+        draw_command_builder.read_buffer(self.outline_buffer);
+        draw_command_builder.read_storage_image(color_image);
+        draw_command_builder.read_storage_image(seed_images[seed_index]);
+        draw_command_builder.draw(0..3, 0..1);
+        draw_command_builder.build(&mut composite_pass_builder);
+        composite_pass_builder.build(render_graph_builder);
+
+        outline_color_image
+    }
+}
@@ -0,0 +1,307 @@
+use crate::mesh::{BoundingBox, BoundingSphere, Primitive, VertexAttributes};
+use crate::scene::scene_renderer::slice_to_bytes_unsafe;
+use glam::{Mat4, Vec3};
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph::QueueType;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, ComputePassBuilder, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{BufferHandle, BufferUsage, ComputePipelineHandle, Device};
+use slotmap::SlotMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct SkinHandle(slotmap::DefaultKey);
+
+/// A skinned primitive and the range of the arena the skinning pass writes it into
+struct SkinnedPrimitive {
+    source: Arc<Primitive>,
+    skinning_buffer: BufferHandle,
+    output: Arc<Primitive>,
+    page: usize,
+}
+
+struct Skin {
+    joint_buffer: BufferHandle,
+    joint_count: usize,
+    primitives: Vec<SkinnedPrimitive>,
+    /// Set by `set_joint_matrices`, only uploaded and skinned when the pose changes
+    pending_matrices: Option<Vec<Mat4>>,
+}
+
+impl Skin {
+    fn is_unused(&self) -> bool {
+        self.primitives
+            .iter()
+            .all(|primitive| Arc::strong_count(&primitive.output) == 1)
+    }
+}
+
+/// One pair of skinned vertex buffers, skins get ranges of vertices out of it
+struct ArenaPage {
+    position_buffer: BufferHandle,
+    attributes_buffer: BufferHandle,
+    /// Sorted by start, neighbours are merged when freed
+    free: Vec<Range<usize>>,
+    capacity: usize,
+}
+
+impl ArenaPage {
+    fn allocate(&mut self, vertex_count: usize) -> Option<usize> {
+        let index = self
+            .free
+            .iter()
+            .position(|range| range.len() >= vertex_count)?;
+        let start = self.free[index].start;
+        self.free[index].start += vertex_count;
+        if self.free[index].is_empty() {
+            self.free.remove(index);
+        }
+        Some(start)
+    }
+
+    fn free(&mut self, range: Range<usize>) {
+        let index = self.free.partition_point(|free| free.start < range.start);
+        self.free.insert(index, range);
+        if index + 1 < self.free.len() && self.free[index].end == self.free[index + 1].start {
+            self.free[index].end = self.free.remove(index + 1).end;
+        }
+        if index > 0 && self.free[index - 1].end == self.free[index].start {
+            self.free[index - 1].end = self.free.remove(index).end;
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.free.len() == 1 && self.free[0].len() == self.capacity
+    }
+}
+
+/// Pooled output buffers for every skin. Pages are added when the live skins outgrow the arena
+/// and released once nothing is allocated from them, so characters don't own vertex buffers of their own
+#[derive(Default)]
+struct SkinningArena {
+    pages: Vec<Option<ArenaPage>>,
+}
+
+impl SkinningArena {
+    /// Vertices per page, bigger primitives get a page sized to fit them
+    const PAGE_SIZE: usize = 65536;
+
+    fn allocate(
+        &mut self,
+        device: &mut Device,
+        vertex_count: usize,
+    ) -> anyhow::Result<(usize, usize)> {
+        let vertex_count = vertex_count.max(1);
+        for (index, page) in self.pages.iter_mut().enumerate() {
+            if let Some(start) = page.as_mut().and_then(|page| page.allocate(vertex_count)) {
+                return Ok((index, start));
+            }
+        }
+
+        let capacity = vertex_count.max(Self::PAGE_SIZE).next_power_of_two();
+        let mut page = ArenaPage {
+            position_buffer: device.create_buffer(
+                "Skinned Positions Arena",
+                capacity * std::mem::size_of::<Vec3>(),
+                BufferUsage::VERTEX | BufferUsage::STORAGE,
+                MemoryLocation::GpuOnly,
+            )?,
+            attributes_buffer: device.create_buffer(
+                "Skinned Attributes Arena",
+                capacity * std::mem::size_of::<VertexAttributes>(),
+                BufferUsage::VERTEX | BufferUsage::STORAGE,
+                MemoryLocation::GpuOnly,
+            )?,
+            free: std::iter::once(0..capacity).collect(),
+            capacity,
+        };
+        let start = page.allocate(vertex_count).unwrap_or_default();
+
+        //Slots of released pages are reused so the indices held by skins stay valid
+        let index = match self.pages.iter().position(Option::is_none) {
+            Some(index) => index,
+            None => {
+                self.pages.push(None);
+                self.pages.len() - 1
+            }
+        };
+        self.pages[index] = Some(page);
+        Ok((index, start))
+    }
+
+    fn page(&self, index: usize) -> &ArenaPage {
+        self.pages[index]
+            .as_ref()
+            .expect("Skinned primitive points at a released arena page")
+    }
+
+    fn free(&mut self, index: usize, range: Range<usize>) {
+        if let Some(page) = &mut self.pages[index] {
+            page.free(range);
+        }
+    }
+
+    /// Destroys the pages nothing is allocated from anymore
+    fn release_empty(&mut self, device: &mut Device) {
+        for slot in self.pages.iter_mut() {
+            if slot.as_ref().is_some_and(ArenaPage::is_empty) {
+                let page = slot.take().unwrap();
+                device.destroy_buffer(page.position_buffer);
+                device.destroy_buffer(page.attributes_buffer);
+            }
+        }
+    }
+}
+
+/// Compute skinning, each skin is written into a range of the shared arena whenever its pose changes.
+/// Models draw the ranges like any other primitive, skins are freed once no model uses them anymore
+pub struct Skinning {
+    pipeline: ComputePipelineHandle,
+    skins: SlotMap<slotmap::DefaultKey, Skin>,
+    arena: SkinningArena,
+}
+
+impl Skinning {
+    pub fn new(device: &mut Device) -> anyhow::Result<Self> {
+        let pipeline = device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+            code: crate::shader::SKINNING_COMP,
+            entry: "main",
+        })?;
+
+        Ok(Self {
+            pipeline,
+            skins: SlotMap::default(),
+            arena: SkinningArena::default(),
+        })
+    }
+
+    /// Returns the primitives models should draw, primitives without skinning data are returned as they are.
+    /// The skinned ones show the rest pose until the first `set_joint_matrices`
+    pub fn add(
+        &mut self,
+        device: &mut Device,
+        name: &str,
+        primitives: &[Arc<Primitive>],
+        joint_count: usize,
+    ) -> anyhow::Result<(SkinHandle, Vec<Arc<Primitive>>)> {
+        let joint_buffer = device.create_buffer_init(
+            &format!("{} Joints", name),
+            BufferUsage::STORAGE | BufferUsage::TRANSFER,
+            MemoryLocation::GpuOnly,
+            unsafe { slice_to_bytes_unsafe(&vec![Mat4::IDENTITY; joint_count.max(1)]) },
+        )?;
+
+        let mut skinned_primitives = Vec::new();
+        let mut model_primitives = Vec::with_capacity(primitives.len());
+        for primitive in primitives.iter() {
+            let Some(skinning_buffer) = primitive.skinning_buffer else {
+                model_primitives.push(primitive.clone());
+                continue;
+            };
+
+            //Animated vertices can leave the rest pose's bounds, so they're padded by their own size
+            let bounding_box = BoundingBox {
+                min: primitive.bounding_box.min - primitive.bounding_box.half_extent(),
+                max: primitive.bounding_box.max + primitive.bounding_box.half_extent(),
+            };
+            let (page_index, vertex_offset) =
+                self.arena.allocate(device, primitive.vertex_count)?;
+            let page = self.arena.page(page_index);
+            let output = Arc::new(Primitive {
+                bounding_box,
+                bounding_sphere: BoundingSphere::from_box(&bounding_box),
+                geometry: primitive.geometry.clone(),
+                vertex_count: primitive.vertex_count,
+                vertex_offset,
+                position_buffer: page.position_buffer,
+                attributes_buffer: page.attributes_buffer,
+                skinning_buffer: None,
+                index_buffer: primitive.index_buffer.clone(),
+            });
+            model_primitives.push(output.clone());
+            skinned_primitives.push(SkinnedPrimitive {
+                source: primitive.clone(),
+                skinning_buffer,
+                output,
+                page: page_index,
+            });
+        }
+
+        let handle = SkinHandle(self.skins.insert(Skin {
+            joint_buffer,
+            joint_count: joint_count.max(1),
+            primitives: skinned_primitives,
+            pending_matrices: Some(vec![Mat4::IDENTITY; joint_count.max(1)]),
+        }));
+        Ok((handle, model_primitives))
+    }
+
+    /// Skinning matrices from `Skeleton::skinning_matrices`, missing joints keep the identity
+    pub fn set_joint_matrices(&mut self, handle: SkinHandle, mut matrices: Vec<Mat4>) {
+        if let Some(skin) = self.skins.get_mut(handle.0) {
+            matrices.resize(skin.joint_count, Mat4::IDENTITY);
+            skin.pending_matrices = Some(matrices);
+        }
+    }
+
+    /// Frees the skins no model draws anymore, along with any arena page left empty
+    pub fn update(&mut self, device: &mut Device) {
+        let arena = &mut self.arena;
+        self.skins.retain(|_, skin| {
+            if !skin.is_unused() {
+                return true;
+            }
+            device.destroy_buffer(skin.joint_buffer);
+            for primitive in skin.primitives.iter() {
+                let output = &primitive.output;
+                let start = output.vertex_offset;
+                arena.free(primitive.page, start..start + output.vertex_count.max(1));
+            }
+            false
+        });
+        self.arena.release_empty(device);
+    }
+
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        render_graph_builder: &mut T,
+    ) {
+        for skin in self.skins.values_mut() {
+            let Some(matrices) = skin.pending_matrices.take() else {
+                continue;
+            };
+
+            render_graph_builder.add_buffer_write(
+                BufferOffset {
+                    buffer: skin.joint_buffer,
+                    offset: 0,
+                },
+                std::mem::size_of_val(matrices.as_slice()),
+                BufferWriteCallback::new(move |slice| {
+                    slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&matrices) });
+                }),
+            );
+
+            for primitive in skin.primitives.iter() {
+                let mut skinning_pass_builder =
+                    ComputePassBuilder::new("Skinning Pass", QueueType::Graphics, self.pipeline);
+                skinning_pass_builder.read_buffer(primitive.source.position_buffer);
+                skinning_pass_builder.read_buffer(primitive.source.attributes_buffer);
+                skinning_pass_builder.read_buffer(primitive.skinning_buffer);
+                skinning_pass_builder.read_buffer(skin.joint_buffer);
+                skinning_pass_builder.write_buffer(primitive.output.position_buffer);
+                skinning_pass_builder.write_buffer(primitive.output.attributes_buffer);
+                skinning_pass_builder.push_constant(primitive.source.vertex_count as u32);
+                skinning_pass_builder.push_constant(primitive.output.vertex_offset as u32);
+                skinning_pass_builder.dispatch_threads([
+                    primitive.source.vertex_count as u32,
+                    1,
+                    1,
+                ]);
+                skinning_pass_builder.build(render_graph_builder);
+            }
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use crate::scene::scene_renderer::{slice_to_bytes_unsafe, SceneCamera};
+use anyhow::Context;
+use glam::{Quat, Vec3, Vec4};
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph::QueueType;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, ComputePassBuilder, RasterDrawCommandBuilder,
+    RasterPassBuilder, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{
+    vk, AddressMode, BufferHandle, BufferUsage, ComputePipelineHandle, Device, FilterMode,
+    ImageDescription2D, ImageHandle, RasterPipelineHandle, SamplerDescription, SamplerHandle,
+};
+
+#[derive(Debug, Clone)]
+pub struct SkySettings {
+    pub enabled: bool,
+    /// Hours, the sun is highest at 12 and sets at 18
+    pub time_of_day: f32,
+    /// Game hours per second, 0 stops the sun
+    pub day_speed: f32,
+    /// Degrees the sun's path leans away from straight overhead, towards +Z
+    pub sun_tilt: f32,
+    /// Brightness of the sun light handed to the other effects, 1 matches their defaults at noon
+    pub sun_intensity: f32,
+    /// The sky is tonemapped on its own since the scene is lit in ldr
+    pub exposure: f32,
+    pub ground_albedo: Vec3,
+}
+
+impl Default for SkySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            time_of_day: 10.0,
+            day_speed: 0.0,
+            sun_tilt: 30.0,
+            sun_intensity: 1.0,
+            exposure: 40.0,
+            ground_albedo: Vec3::splat(0.3),
+        }
+    }
+}
+
+impl SkySettings {
+    /// Direction towards the sun, below the horizon at night
+    pub fn sun_direction(&self) -> Vec3 {
+        let hour_angle = (self.time_of_day / 24.0 - 0.5) * std::f32::consts::TAU;
+        //Rises in -X, sets in +X
+        let direction = Vec3::new(hour_angle.sin(), hour_angle.cos(), 0.0);
+        Quat::from_rotation_x(self.sun_tilt.to_radians()) * direction
+    }
+}
+
+/// Earth like atmosphere, distances are in km.
+/// The editor's scenes are small enough that the sky is always seen from just above the ground
+struct Atmosphere;
+
+impl Atmosphere {
+    const GROUND_RADIUS: f32 = 6360.0;
+    const TOP_RADIUS: f32 = 6460.0;
+    const VIEWER_HEIGHT: f32 = 0.2;
+    const RAYLEIGH_SCATTERING: Vec3 = Vec3::new(5.802e-3, 13.558e-3, 33.1e-3);
+    const RAYLEIGH_HEIGHT: f32 = 8.0;
+    const MIE_SCATTERING: f32 = 3.996e-3;
+    const MIE_EXTINCTION: f32 = 4.44e-3;
+    const MIE_HEIGHT: f32 = 1.2;
+    const MIE_ANISOTROPY: f32 = 0.8;
+    const OZONE_ABSORPTION: Vec3 = Vec3::new(0.650e-3, 1.881e-3, 0.085e-3);
+    const OZONE_HEIGHT: f32 = 25.0;
+    const OZONE_HALF_WIDTH: f32 = 15.0;
+
+    fn extinction(height: f32) -> Vec3 {
+        Self::RAYLEIGH_SCATTERING * (-height / Self::RAYLEIGH_HEIGHT).exp()
+            + Vec3::splat(Self::MIE_EXTINCTION * (-height / Self::MIE_HEIGHT).exp())
+            + Self::OZONE_ABSORPTION
+                * (1.0 - (height - Self::OZONE_HEIGHT).abs() / Self::OZONE_HALF_WIDTH).max(0.0)
+    }
+
+    /// Same integration as sky_transmittance.comp, for the light color on the cpu
+    fn transmittance_to_sun(sun_direction: Vec3) -> Vec3 {
+        const STEP_COUNT: u32 = 40;
+
+        let origin = Vec3::new(0.0, Self::GROUND_RADIUS + Self::VIEWER_HEIGHT, 0.0);
+        let b = origin.dot(sun_direction);
+        let c = origin.length_squared() - Self::GROUND_RADIUS * Self::GROUND_RADIUS;
+        //The planet is in the way
+        if b < 0.0 && b * b - c > 0.0 {
+            return Vec3::ZERO;
+        }
+
+        let c = origin.length_squared() - Self::TOP_RADIUS * Self::TOP_RADIUS;
+        let step_size = (-b + (b * b - c).max(0.0).sqrt()) / STEP_COUNT as f32;
+        let optical_depth: Vec3 = (0..STEP_COUNT)
+            .map(|i| {
+                let position = origin + sun_direction * ((i as f32 + 0.5) * step_size);
+                Self::extinction(position.length() - Self::GROUND_RADIUS) * step_size
+            })
+            .sum();
+        (-optical_depth).exp()
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct SkyData {
+    sun_direction: Vec4,
+    rayleigh: Vec4,
+    mie: Vec4,
+    ozone: Vec4,
+    planet: Vec4,
+    ground_albedo: Vec4,
+}
+
+/// Hillaire style sky, a transmittance lut is built once and a sky view lut every frame as the sun moves.
+/// Only single scattering is done, multiple scattering is roughly approximated in the sky view pass
+pub struct Sky {
+    pub settings: SkySettings,
+
+    transmittance_pipeline: ComputePipelineHandle,
+    sky_view_pipeline: ComputePipelineHandle,
+    sky_pipeline: RasterPipelineHandle,
+
+    sky_buffer: BufferHandle,
+    transmittance_lut: ImageHandle,
+    sky_view_lut: ImageHandle,
+    lut_sampler: SamplerHandle,
+    transmittance_built: bool,
+}
+
+impl Sky {
+    const TRANSMITTANCE_LUT_SIZE: [u32; 2] = [256, 64];
+    const SKY_VIEW_LUT_SIZE: [u32; 2] = [192, 108];
+    const LUT_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+    /// Blackboard key for the frame's `sun_light` as a (direction, color) pair, missing while the sky is off
+    pub const SUN_LIGHT_KEY: &'static str = "sun_light";
+
+    pub fn new(
+        device: &mut Device,
+        color_format: vk::Format,
+        settings: SkySettings,
+    ) -> anyhow::Result<Self> {
+        let transmittance_pipeline =
+            device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+                code: crate::shader::SKY_TRANSMITTANCE_COMP,
+                entry: "main",
+            })?;
+        let sky_view_pipeline = device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+            code: crate::shader::SKY_VIEW_COMP,
+            entry: "main",
+        })?;
+
+        let sky_pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::SKY_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format: color_format,
+                        blend: None,
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })?;
+
+        let sky_buffer = device
+            .create_buffer_init(
+                "SkyBuffer",
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                unsafe { slice_to_bytes_unsafe(&[SkyData::default()]) },
+            )
+            .context("Failed to create sky buffer")?;
+
+        let lut_description = ImageDescription2D {
+            size: Self::TRANSMITTANCE_LUT_SIZE,
+            format: Self::LUT_FORMAT,
+            usage: vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+            mip_levels: 1,
+            location: MemoryLocation::GpuOnly,
+        };
+        let transmittance_lut = device.create_image("Sky Transmittance Lut", &lut_description)?;
+        let sky_view_lut = device.create_image(
+            "Sky View Lut",
+            &ImageDescription2D {
+                size: Self::SKY_VIEW_LUT_SIZE,
+                ..lut_description
+            },
+        )?;
+
+        let lut_sampler = device.create_sampler(
+            "Sky Lut Sampler",
+            &SamplerDescription {
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                address_mode_w: AddressMode::ClampToEdge,
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+        )?;
+
+        Ok(Self {
+            settings,
+            transmittance_pipeline,
+            sky_view_pipeline,
+            sky_pipeline,
+            sky_buffer,
+            transmittance_lut,
+            sky_view_lut,
+            lut_sampler,
+            transmittance_built: false,
+        })
+    }
+
+    /// Moves the sun along with game time
+    pub fn update(&mut self, delta_time: f32) {
+        if self.settings.enabled && self.settings.day_speed != 0.0 {
+            self.settings.time_of_day =
+                (self.settings.time_of_day + self.settings.day_speed * delta_time).rem_euclid(24.0);
+        }
+    }
+
+    /// Direction towards the sun and its color after passing through the atmosphere, None while the sky is off
+    pub fn sun_light(&self) -> Option<(Vec3, Vec3)> {
+        if !self.settings.enabled {
+            return None;
+        }
+        let direction = self.settings.sun_direction();
+        let color = Atmosphere::transmittance_to_sun(direction) * self.settings.sun_intensity;
+        Some((direction, color))
+    }
+
+    /// Builds the luts for this frame, once per frame before any view draws the sky
+    pub fn write_lut_passes<T: RenderGraphBuilderTrait>(&mut self, render_graph_builder: &mut T) {
+        let Some(sun_light) = self.sun_light() else {
+            return;
+        };
+        render_graph_builder
+            .blackboard_mut()
+            .insert(Self::SUN_LIGHT_KEY, sun_light);
+
+        let sky_data = SkyData {
+            sun_direction: self.settings.sun_direction().extend(self.settings.exposure),
+            rayleigh: Atmosphere::RAYLEIGH_SCATTERING.extend(Atmosphere::RAYLEIGH_HEIGHT),
+            mie: Vec4::new(
+                Atmosphere::MIE_SCATTERING,
+                Atmosphere::MIE_EXTINCTION,
+                Atmosphere::MIE_HEIGHT,
+                Atmosphere::MIE_ANISOTROPY,
+            ),
+            ozone: Atmosphere::OZONE_ABSORPTION.extend(Atmosphere::OZONE_HALF_WIDTH),
+            planet: Vec4::new(
+                Atmosphere::GROUND_RADIUS,
+                Atmosphere::TOP_RADIUS,
+                Atmosphere::GROUND_RADIUS + Atmosphere::VIEWER_HEIGHT,
+                Atmosphere::OZONE_HEIGHT,
+            ),
+            ground_albedo: self.settings.ground_albedo.extend(0.0),
+        };
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: self.sky_buffer,
+                offset: 0,
+            },
+            std::mem::size_of::<SkyData>(),
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&[sky_data]) });
+            }),
+        );
+
+        //The atmosphere never changes, only the sun does
+        if !self.transmittance_built {
+            self.transmittance_built = true;
+            let mut transmittance_pass_builder = ComputePassBuilder::new(
+                "Sky Transmittance Pass",
+                QueueType::Graphics,
+                self.transmittance_pipeline,
+            );
+            transmittance_pass_builder.read_buffer(self.sky_buffer);
+            transmittance_pass_builder.write_storage_image(self.transmittance_lut);
+            transmittance_pass_builder.dispatch_threads([
+                Self::TRANSMITTANCE_LUT_SIZE[0],
+                Self::TRANSMITTANCE_LUT_SIZE[1],
+                1,
+            ]);
+            transmittance_pass_builder.build(render_graph_builder);
+        }
+
+        let mut sky_view_pass_builder =
+            ComputePassBuilder::new("Sky View Pass", QueueType::Graphics, self.sky_view_pipeline);
+        sky_view_pass_builder.read_buffer(self.sky_buffer);
+        sky_view_pass_builder.read_sampled_image(self.transmittance_lut);
+        sky_view_pass_builder.read_sampler(self.lut_sampler);
+        sky_view_pass_builder.write_storage_image(self.sky_view_lut);
+        sky_view_pass_builder.dispatch_threads([
+            Self::SKY_VIEW_LUT_SIZE[0],
+            Self::SKY_VIEW_LUT_SIZE[1],
+            1,
+        ]);
+        sky_view_pass_builder.build(render_graph_builder);
+    }
+
+    /// Fills the pixels of `color_image` that nothing was drawn to
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &self,
+        camera: &SceneCamera,
+        color_image: ImageHandle,
+        depth_image: ImageHandle,
+        render_graph_builder: &mut T,
+    ) {
+        if !self.settings.enabled {
+            return;
+        }
+
+        let mut sky_pass_builder = RasterPassBuilder::new("Sky Pass");
+        sky_pass_builder.add_color_attachment(color_image, None);
+        let mut draw_command_builder = RasterDrawCommandBuilder::new(self.sky_pipeline);
+        draw_command_builder.read_buffer(camera.buffer());
+        draw_command_builder.read_buffer(self.sky_buffer);
+        draw_command_builder.read_sampled_image(depth_image);
+        draw_command_builder.read_sampled_image(self.sky_view_lut);
+        draw_command_builder.read_sampled_image(self.transmittance_lut);
+        draw_command_builder.read_sampler(self.lut_sampler);
+        draw_command_builder.draw(0..3, 0..1);
+        draw_command_builder.build(&mut sky_pass_builder);
+        sky_pass_builder.build(render_graph_builder);
+    }
+}
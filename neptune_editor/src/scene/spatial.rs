@@ -0,0 +1,314 @@
+use crate::mesh::BoundingBox;
+use glam::Vec3;
+
+#[derive(Debug, Copy, Clone)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn at(&self, distance: f32) -> Vec3 {
+        self.origin + (self.direction * distance)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SpatialHit<T> {
+    pub item: T,
+    pub distance: f32,
+}
+
+enum BvhNode {
+    Leaf {
+        bounds: BoundingBox,
+        item_range: std::ops::Range<usize>,
+    },
+    Branch {
+        bounds: BoundingBox,
+        children: [usize; 2],
+    },
+}
+
+impl BvhNode {
+    fn bounds(&self) -> &BoundingBox {
+        match self {
+            BvhNode::Leaf { bounds, .. } => bounds,
+            BvhNode::Branch { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// Gpu friendly node layout, a branch's left child always directly follows it
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct FlatBvhNode {
+    pub min: Vec3,
+    /// First item for leaves, right child for branches
+    pub first: u32,
+    pub max: Vec3,
+    /// Item count for leaves, 0 for branches
+    pub count: u32,
+}
+
+/// Bounding volume hierarchy over world space boxes, rebuilt whenever the set of items changes
+pub struct Bvh<T: Copy> {
+    nodes: Vec<BvhNode>,
+    items: Vec<(T, BoundingBox)>,
+}
+
+impl<T: Copy> Default for Bvh<T> {
+    fn default() -> Self {
+        Self {
+            nodes: Vec::new(),
+            items: Vec::new(),
+        }
+    }
+}
+
+impl<T: Copy> Bvh<T> {
+    const MAX_LEAF_ITEMS: usize = 4;
+
+    pub fn build(items: Vec<(T, BoundingBox)>) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::new(),
+            items,
+        };
+
+        if !bvh.items.is_empty() {
+            let item_count = bvh.items.len();
+            bvh.build_node(0..item_count);
+        }
+
+        bvh
+    }
+
+    fn build_node(&mut self, item_range: std::ops::Range<usize>) -> usize {
+        let items = &mut self.items[item_range.clone()];
+        let bounds = items
+            .iter()
+            .skip(1)
+            .fold(items[0].1, |bounds, (_, item_bounds)| {
+                bounds.union(item_bounds)
+            });
+
+        let node_index = self.nodes.len();
+
+        if items.len() <= Self::MAX_LEAF_ITEMS {
+            self.nodes.push(BvhNode::Leaf { bounds, item_range });
+            return node_index;
+        }
+
+        //Median split along the longest axis of the item centers
+        let center_bounds = items.iter().skip(1).fold(
+            BoundingBox {
+                min: items[0].1.center(),
+                max: items[0].1.center(),
+            },
+            |bounds, (_, item_bounds)| {
+                let center = item_bounds.center();
+                BoundingBox {
+                    min: bounds.min.min(center),
+                    max: bounds.max.max(center),
+                }
+            },
+        );
+        let extent = center_bounds.max - center_bounds.min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+
+        items.sort_unstable_by(|(_, a), (_, b)| a.center()[axis].total_cmp(&b.center()[axis]));
+
+        //Reserve the slot so children can be pushed after it
+        self.nodes.push(BvhNode::Leaf {
+            bounds,
+            item_range: 0..0,
+        });
+
+        let middle = item_range.start + (item_range.len() / 2);
+        let left = self.build_node(item_range.start..middle);
+        let right = self.build_node(middle..item_range.end);
+        self.nodes[node_index] = BvhNode::Branch {
+            bounds,
+            children: [left, right],
+        };
+
+        node_index
+    }
+
+    /// Items in the order the leaves index them
+    pub fn items(&self) -> &[(T, BoundingBox)] {
+        &self.items
+    }
+
+    pub fn flatten(&self) -> Vec<FlatBvhNode> {
+        self.nodes
+            .iter()
+            .map(|node| match node {
+                BvhNode::Leaf { bounds, item_range } => FlatBvhNode {
+                    min: bounds.min,
+                    first: item_range.start as u32,
+                    max: bounds.max,
+                    count: item_range.len() as u32,
+                },
+                BvhNode::Branch { bounds, children } => FlatBvhNode {
+                    min: bounds.min,
+                    first: children[1] as u32,
+                    max: bounds.max,
+                    count: 0,
+                },
+            })
+            .collect()
+    }
+
+    /// Returns the closest item whose bounds are hit by the ray
+    pub fn ray_cast(&self, ray: &Ray, max_distance: f32) -> Option<SpatialHit<T>> {
+        let inverse_direction = ray.direction.recip();
+        self.ray_cast_by(ray, max_distance, |_item, bounds| {
+            bounds.ray_intersect(ray.origin, inverse_direction)
+        })
+    }
+
+    /// Same as `ray_cast` but each item in a visited leaf is tested with `item_test`, for exact shapes inside the bounds
+    pub fn ray_cast_by(
+        &self,
+        ray: &Ray,
+        max_distance: f32,
+        item_test: impl Fn(&T, &BoundingBox) -> Option<f32>,
+    ) -> Option<SpatialHit<T>> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let inverse_direction = ray.direction.recip();
+        let mut closest: Option<SpatialHit<T>> = None;
+        let mut stack = vec![0];
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            let closest_distance = closest.map(|hit| hit.distance).unwrap_or(max_distance);
+            match node.bounds().ray_intersect(ray.origin, inverse_direction) {
+                Some(distance) if distance <= closest_distance => {}
+                _ => continue,
+            }
+
+            match node {
+                BvhNode::Leaf { item_range, .. } => {
+                    for (item, bounds) in self.items[item_range.clone()].iter() {
+                        if let Some(distance) = item_test(item, bounds) {
+                            if distance <= closest.map(|hit| hit.distance).unwrap_or(max_distance) {
+                                closest = Some(SpatialHit {
+                                    item: *item,
+                                    distance,
+                                });
+                            }
+                        }
+                    }
+                }
+                BvhNode::Branch { children, .. } => stack.extend_from_slice(children),
+            }
+        }
+
+        closest
+    }
+
+    /// Returns all items whose bounds overlap the sphere
+    pub fn sphere_overlap(&self, center: Vec3, radius: f32) -> Vec<T> {
+        let radius_squared = radius * radius;
+        let mut results = Vec::new();
+
+        if self.nodes.is_empty() {
+            return results;
+        }
+
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if node.bounds().distance_squared(center) > radius_squared {
+                continue;
+            }
+
+            match node {
+                BvhNode::Leaf { item_range, .. } => results.extend(
+                    self.items[item_range.clone()]
+                        .iter()
+                        .filter(|(_, bounds)| bounds.distance_squared(center) <= radius_squared)
+                        .map(|(item, _)| *item),
+                ),
+                BvhNode::Branch { children, .. } => stack.extend_from_slice(children),
+            }
+        }
+
+        results
+    }
+
+    /// Returns up to `count` items sorted by the distance from the point to their bounds
+    pub fn k_nearest(&self, point: Vec3, count: usize) -> Vec<SpatialHit<T>> {
+        let mut results: Vec<SpatialHit<T>> = Vec::with_capacity(count + 1);
+
+        if self.nodes.is_empty() || count == 0 {
+            return results;
+        }
+
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+
+            //Skip nodes that can't beat the current worst result
+            if results.len() == count {
+                let worst = results[count - 1].distance;
+                if node.bounds().distance_squared(point) > worst * worst {
+                    continue;
+                }
+            }
+
+            match node {
+                BvhNode::Leaf { item_range, .. } => {
+                    for (item, bounds) in self.items[item_range.clone()].iter() {
+                        let distance = bounds.distance_squared(point).sqrt();
+                        if results.len() == count && distance >= results[count - 1].distance {
+                            continue;
+                        }
+
+                        let index = results.partition_point(|hit| hit.distance <= distance);
+                        results.insert(
+                            index,
+                            SpatialHit {
+                                item: *item,
+                                distance,
+                            },
+                        );
+                        results.truncate(count);
+                    }
+                }
+                BvhNode::Branch { children, .. } => {
+                    //Visit the closer child first so the result set tightens sooner
+                    let [left, right] = *children;
+                    if self.nodes[left].bounds().distance_squared(point)
+                        < self.nodes[right].bounds().distance_squared(point)
+                    {
+                        stack.push(right);
+                        stack.push(left);
+                    } else {
+                        stack.push(left);
+                        stack.push(right);
+                    }
+                }
+            }
+        }
+
+        results
+    }
+}
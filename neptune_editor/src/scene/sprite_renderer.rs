@@ -0,0 +1,436 @@
+use crate::scene::scene_renderer::{slice_to_bytes_unsafe, SceneCamera};
+use crate::scene::ui_compositor::UiCompositor;
+use anyhow::Context;
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, RasterDrawCommandBuilder, RasterPassBuilder,
+    RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{
+    vk, AddressMode, BlendState, BufferHandle, BufferUsage, Device, FilterMode, ImageDescription2D,
+    ImageHandle, RasterPipelineHandle, SamplerDescription, SamplerHandle,
+};
+use std::collections::HashMap;
+use std::ops::Range;
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum SpriteSpace {
+    /// Position and size are in pixels, with the origin in the top left of the target
+    #[default]
+    Screen,
+    /// Quad on the XY plane at the position, drawn with the scene camera
+    World,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AtlasRegion {
+    pub uv_min: Vec2,
+    pub uv_max: Vec2,
+}
+
+impl Default for AtlasRegion {
+    fn default() -> Self {
+        Self {
+            uv_min: Vec2::ZERO,
+            uv_max: Vec2::ONE,
+        }
+    }
+}
+
+/// Named regions of a single image, sprites sharing an atlas are drawn in one batch
+#[derive(Debug, Clone)]
+pub struct TextureAtlas {
+    pub image: ImageHandle,
+    pub size: [u32; 2],
+    regions: HashMap<String, AtlasRegion>,
+}
+
+impl TextureAtlas {
+    pub fn new(image: ImageHandle, size: [u32; 2]) -> Self {
+        Self {
+            image,
+            size,
+            regions: HashMap::new(),
+        }
+    }
+
+    /// Splits the image into equally sized cells named "0", "1", ... in row major order
+    pub fn grid(image: ImageHandle, size: [u32; 2], columns: u32, rows: u32) -> Self {
+        let mut atlas = Self::new(image, size);
+        let cell_size = [size[0] / columns.max(1), size[1] / rows.max(1)];
+        for row in 0..rows {
+            for column in 0..columns {
+                atlas.add_region(
+                    &(row * columns + column).to_string(),
+                    [column * cell_size[0], row * cell_size[1]],
+                    cell_size,
+                );
+            }
+        }
+        atlas
+    }
+
+    /// Offset and size are in pixels
+    pub fn add_region(&mut self, name: &str, offset: [u32; 2], size: [u32; 2]) {
+        let image_size = Vec2::new(self.size[0] as f32, self.size[1] as f32);
+        let uv_min = Vec2::new(offset[0] as f32, offset[1] as f32) / image_size;
+        let uv_max = uv_min + Vec2::new(size[0] as f32, size[1] as f32) / image_size;
+        self.regions
+            .insert(name.to_string(), AtlasRegion { uv_min, uv_max });
+    }
+
+    pub fn region(&self, name: &str) -> Option<AtlasRegion> {
+        self.regions.get(name).copied()
+    }
+
+    pub fn sprite(&self, name: &str) -> Option<Sprite> {
+        self.region(name).map(|region| Sprite {
+            image: Some(self.image),
+            region,
+            ..Default::default()
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sprite {
+    pub space: SpriteSpace,
+    /// Sprites are drawn from the lowest layer to the highest, in submission order within a layer
+    pub layer: i32,
+    /// Center of the quad
+    pub position: Vec3,
+    pub size: Vec2,
+    /// Radians, clockwise on screen and counter-clockwise in the world
+    pub rotation: f32,
+    pub color: Vec4,
+    /// A plain colored quad is drawn when unset
+    pub image: Option<ImageHandle>,
+    pub region: AtlasRegion,
+    /// Nearest filtering, for pixel art
+    pub pixel_perfect: bool,
+}
+
+impl Default for Sprite {
+    fn default() -> Self {
+        Self {
+            space: SpriteSpace::Screen,
+            layer: 0,
+            position: Vec3::ZERO,
+            size: Vec2::ONE,
+            rotation: 0.0,
+            color: Vec4::ONE,
+            image: None,
+            region: AtlasRegion::default(),
+            pixel_perfect: false,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct SpriteInstanceData {
+    position_rotation: Vec4,
+    size_space: Vec4,
+    uv_rect: Vec4,
+    color: Vec4,
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct SpriteViewData {
+    screen_matrix: Mat4,
+}
+
+/// Batched 2D quads drawn on top of the final image, for HUDs and simple 2D games.
+/// Sprites are submitted every frame and cleared once they are drawn.
+/// World sprites are drawn straight onto the target, screen sprites make up the ui layer that's composited after them
+pub struct SpriteRenderer {
+    pub ui_compositor: UiCompositor,
+    pipeline: RasterPipelineHandle,
+    ui_pipeline: RasterPipelineHandle,
+    max_sprites: usize,
+    instance_buffer: BufferHandle,
+    view_buffer: BufferHandle,
+    white_image: ImageHandle,
+    linear_sampler: SamplerHandle,
+    nearest_sampler: SamplerHandle,
+    sprites: Vec<Sprite>,
+}
+
+impl SpriteRenderer {
+    pub fn new(
+        device: &mut Device,
+        color_format: vk::Format,
+        max_sprites: usize,
+    ) -> anyhow::Result<Self> {
+        let pipeline = create_sprite_pipeline(
+            device,
+            crate::shader::SPRITE_FRAG,
+            color_format,
+            BlendState::AlphaBlend,
+        )?;
+        let ui_pipeline = create_sprite_pipeline(
+            device,
+            crate::shader::SPRITE_UI_FRAG,
+            UiCompositor::LAYER_FORMAT,
+            BlendState::PremultipliedAlpha,
+        )?;
+
+        let max_sprites = max_sprites.max(1);
+        let instance_buffer = device
+            .create_buffer(
+                "SpriteInstanceBuffer",
+                max_sprites * std::mem::size_of::<SpriteInstanceData>(),
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+            )
+            .context("Failed to create sprite instance buffer")?;
+        let view_buffer = device
+            .create_buffer_init(
+                "SpriteViewBuffer",
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                unsafe { slice_to_bytes_unsafe(&[SpriteViewData::default()]) },
+            )
+            .context("Failed to create sprite view buffer")?;
+
+        let white_image = device.create_image_init(
+            "Sprite White Image",
+            &ImageDescription2D {
+                size: [1; 2],
+                format: vk::Format::R8G8B8A8_UNORM,
+                usage: vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+                mip_levels: 1,
+                location: MemoryLocation::GpuOnly,
+            },
+            &[255u8; 4],
+        )?;
+
+        let linear_sampler = device.create_sampler(
+            "Sprite Linear Sampler",
+            &SamplerDescription {
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                address_mode_w: AddressMode::ClampToEdge,
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+        )?;
+        let nearest_sampler = device.create_sampler(
+            "Sprite Nearest Sampler",
+            &SamplerDescription {
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                address_mode_w: AddressMode::ClampToEdge,
+                mag_filter: FilterMode::Nearest,
+                min_filter: FilterMode::Nearest,
+                ..Default::default()
+            },
+        )?;
+
+        Ok(Self {
+            ui_compositor: UiCompositor::new(device, color_format)?,
+            pipeline,
+            ui_pipeline,
+            max_sprites,
+            instance_buffer,
+            view_buffer,
+            white_image,
+            linear_sampler,
+            nearest_sampler,
+            sprites: Vec::new(),
+        })
+    }
+
+    pub fn draw(&mut self, sprite: Sprite) {
+        self.sprites.push(sprite);
+    }
+
+    /// Draws every submitted sprite onto `target_image`, world sprites in one pass and the ui layer in another before it's composited
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        target_image: ImageHandle,
+        target_size: [u32; 2],
+        camera: &SceneCamera,
+        render_graph_builder: &mut T,
+    ) {
+        if self.sprites.is_empty() {
+            return;
+        }
+
+        let mut sprites = std::mem::take(&mut self.sprites);
+        if sprites.len() > self.max_sprites {
+            warn!(
+                "Sprite batch has {} sprites but only {} fit, the rest are dropped",
+                sprites.len(),
+                self.max_sprites
+            );
+        }
+
+        //Stable so submission order is kept within a layer, world sprites go under the hud
+        sprites.sort_by_key(|sprite| (sprite.space == SpriteSpace::Screen, sprite.layer));
+        sprites.truncate(self.max_sprites);
+
+        let instances: Vec<SpriteInstanceData> = sprites
+            .iter()
+            .map(|sprite| SpriteInstanceData {
+                position_rotation: sprite.position.extend(sprite.rotation),
+                size_space: Vec4::new(
+                    sprite.size.x,
+                    sprite.size.y,
+                    (sprite.space == SpriteSpace::World) as u32 as f32,
+                    0.0,
+                ),
+                uv_rect: Vec4::new(
+                    sprite.region.uv_min.x,
+                    sprite.region.uv_min.y,
+                    sprite.region.uv_max.x,
+                    sprite.region.uv_max.y,
+                ),
+                color: sprite.color,
+            })
+            .collect();
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: self.instance_buffer,
+                offset: 0,
+            },
+            std::mem::size_of_val(instances.as_slice()),
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&instances) });
+            }),
+        );
+
+        //Pixels to ndc, vulkan's y already points down
+        let view_data = SpriteViewData {
+            screen_matrix: Mat4::from_translation(Vec3::new(-1.0, -1.0, 0.0))
+                * Mat4::from_scale(Vec3::new(
+                    2.0 / target_size[0].max(1) as f32,
+                    2.0 / target_size[1].max(1) as f32,
+                    0.0,
+                )),
+        };
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: self.view_buffer,
+                offset: 0,
+            },
+            std::mem::size_of::<SpriteViewData>(),
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&[view_data]) });
+            }),
+        );
+
+        //Sorted so the world sprites all come before the screen ones
+        let ui_start = sprites
+            .iter()
+            .position(|sprite| sprite.space == SpriteSpace::Screen)
+            .unwrap_or(sprites.len());
+
+        if ui_start > 0 {
+            let mut sprite_pass_builder = RasterPassBuilder::new("Sprite Pass");
+            sprite_pass_builder.add_color_attachment(target_image, None);
+            self.add_batches(
+                &sprites,
+                0..ui_start,
+                self.pipeline,
+                camera,
+                &mut sprite_pass_builder,
+            );
+            sprite_pass_builder.build(render_graph_builder);
+        }
+
+        if ui_start < sprites.len() {
+            let ui_layer = self
+                .ui_compositor
+                .create_layer(target_image, render_graph_builder);
+            let mut ui_pass_builder = RasterPassBuilder::new("Ui Pass");
+            ui_pass_builder.add_color_attachment(ui_layer, Some([0.0; 4]));
+            self.add_batches(
+                &sprites,
+                ui_start..sprites.len(),
+                self.ui_pipeline,
+                camera,
+                &mut ui_pass_builder,
+            );
+            ui_pass_builder.build(render_graph_builder);
+
+            self.ui_compositor
+                .write_render_passes(ui_layer, target_image, render_graph_builder);
+        }
+    }
+
+    /// One draw per run of sprites sharing an image and sampler
+    fn add_batches(
+        &self,
+        sprites: &[Sprite],
+        range: Range<usize>,
+        pipeline: RasterPipelineHandle,
+        camera: &SceneCamera,
+        pass_builder: &mut RasterPassBuilder,
+    ) {
+        let mut batch_start = range.start;
+        while batch_start < range.end {
+            let key = |sprite: &Sprite| (sprite.image, sprite.pixel_perfect);
+            let batch_key = key(&sprites[batch_start]);
+            let batch_end = sprites[batch_start..range.end]
+                .iter()
+                .position(|sprite| key(sprite) != batch_key)
+                .map(|length| batch_start + length)
+                .unwrap_or(range.end);
+
+            let (image, pixel_perfect) = batch_key;
+            let mut draw_command_builder = RasterDrawCommandBuilder::new(pipeline);
+            draw_command_builder.read_buffer(camera.buffer());
+            draw_command_builder.read_buffer(self.view_buffer);
+            draw_command_builder.read_buffer(self.instance_buffer);
+            draw_command_builder.read_sampled_image(image.unwrap_or(self.white_image));
+            draw_command_builder.read_sampler(if pixel_perfect {
+                self.nearest_sampler
+            } else {
+                self.linear_sampler
+            });
+            draw_command_builder.draw(0..6, batch_start as u32..batch_end as u32);
+            draw_command_builder.build(pass_builder);
+
+            batch_start = batch_end;
+        }
+    }
+}
+
+fn create_sprite_pipeline(
+    device: &mut Device,
+    fragment_code: &[u32],
+    format: vk::Format,
+    blend: BlendState,
+) -> anyhow::Result<RasterPipelineHandle> {
+    Ok(
+        device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+            vertex: neptune_vulkan::VertexState {
+                shader: neptune_vulkan::ShaderStage {
+                    code: crate::shader::SPRITE_VERT,
+                    entry: "main",
+                },
+                layouts: &[],
+            },
+            primitive: neptune_vulkan::PrimitiveState {
+                front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                cull_mode: vk::CullModeFlags::NONE,
+            },
+            depth_state: None,
+            fragment: Some(neptune_vulkan::FragmentState {
+                shader: neptune_vulkan::ShaderStage {
+                    code: fragment_code,
+                    entry: "main",
+                },
+                targets: &[neptune_vulkan::ColorTargetState {
+                    format,
+                    blend: Some(blend),
+                    write_mask: vk::ColorComponentFlags::RGBA,
+                }],
+            }),
+        })?,
+    )
+}
@@ -0,0 +1,119 @@
+use neptune_vulkan::render_graph_builder::{
+    RasterDrawCommandBuilder, RasterPassBuilder, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{
+    vk, BlendState, Device, ImageHandle, RasterPipelineHandle, SamplerDescription, SamplerHandle,
+    TransientImageDesc, TransientImageSize,
+};
+use serde::{Deserialize, Serialize};
+
+/// How the target the ui is composited onto encodes its colors
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UiColorSpace {
+    /// Srgb encoded values, what the 8 and 10-bit unorm swapchains hold after post processing
+    #[default]
+    Srgb,
+    /// Linear values, for hdr swapchains using extended srgb linear
+    Linear,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiCompositeSettings {
+    pub color_space: UiColorSpace,
+    /// Linear brightness of ui white, 1.0 is sdr white. Only used with `UiColorSpace::Linear`
+    pub paper_white: f32,
+}
+
+impl Default for UiCompositeSettings {
+    fn default() -> Self {
+        Self {
+            color_space: UiColorSpace::Srgb,
+            paper_white: 1.0,
+        }
+    }
+}
+
+/// Blends the ui layer over the final image after tonemapping and post effects.
+/// The layer holds premultiplied srgb colors, it's converted here so the ui looks the same on an sdr or hdr swapchain
+pub struct UiCompositor {
+    pub settings: UiCompositeSettings,
+    pipeline: RasterPipelineHandle,
+    sampler: SamplerHandle,
+}
+
+impl UiCompositor {
+    /// Float so low alpha premultiplied colors keep their precision
+    pub const LAYER_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+    pub fn new(device: &mut Device, output_format: vk::Format) -> anyhow::Result<Self> {
+        let pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::UI_COMPOSITE_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format: output_format,
+                        blend: Some(BlendState::PremultipliedAlpha),
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })?;
+
+        Ok(Self {
+            settings: UiCompositeSettings::default(),
+            pipeline,
+            sampler: device
+                .create_sampler("Ui Composite Sampler", &SamplerDescription::default())?,
+        })
+    }
+
+    /// A transparent layer the same size as `target_image` for the ui to be drawn into
+    pub fn create_layer<T: RenderGraphBuilderTrait>(
+        &self,
+        target_image: ImageHandle,
+        render_graph_builder: &mut T,
+    ) -> ImageHandle {
+        render_graph_builder.create_transient_image(TransientImageDesc {
+            size: TransientImageSize::Relative([1.0; 2], target_image),
+            format: Self::LAYER_FORMAT,
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            mip_levels: 1,
+            memory_location: neptune_vulkan::gpu_allocator::MemoryLocation::GpuOnly,
+        })
+    }
+
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &self,
+        ui_layer: ImageHandle,
+        target_image: ImageHandle,
+        render_graph_builder: &mut T,
+    ) {
+        let mut composite_pass_builder = RasterPassBuilder::new("Ui Composite Pass");
+        composite_pass_builder.add_color_attachment(target_image, None);
+        let mut draw_command_builder = RasterDrawCommandBuilder::new(self.pipeline);
+        draw_command_builder.read_sampled_image(ui_layer);
+        draw_command_builder.read_sampler(self.sampler);
+        draw_command_builder
+            .push_constant((self.settings.color_space == UiColorSpace::Linear) as u32);
+        draw_command_builder.push_constant(self.settings.paper_white.to_bits());
+        draw_command_builder.draw(0..3, 0..1);
+        draw_command_builder.build(&mut composite_pass_builder);
+        composite_pass_builder.build(render_graph_builder);
+    }
+}
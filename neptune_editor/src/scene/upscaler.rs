@@ -0,0 +1,527 @@
+use crate::scene::resize_targets::{ResizeTargetDescription, ResizeTargetHandle, ResizeTargets};
+use glam::Vec2;
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph_builder::{
+    RasterDrawCommandBuilder, RasterPassBuilder, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{
+    vk, AddressMode, DepthMode, Device, FilterMode, ImageHandle, RasterPipelineHandle,
+    SamplerDescription, SamplerHandle, TransientImageDesc, TransientImageSize,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpscalerMode {
+    /// Render at the output resolution
+    #[default]
+    Off,
+    /// Edge aware Lanczos upscale of the current frame only
+    Spatial,
+    /// Jittered frames are accumulated into a full resolution history, reprojected with the velocity buffer
+    Temporal,
+    /// Each frame shades every other pixel in a checkerboard, alternating between frames.
+    /// A depth mask written before the scene rejects the skipped pixels, they come from the reprojected previous frame bounded by their four shaded neighbors
+    Checkerboard,
+}
+
+#[derive(Debug, Clone)]
+pub struct UpscalerSettings {
+    pub mode: UpscalerMode,
+    /// Render resolution relative to the output for the spatial and temporal modes, clamped to `Upscaler::MIN_RENDER_SCALE` to 1
+    pub render_scale: f32,
+    /// Contrast adaptive sharpening applied after the upscale, 0 disables the pass
+    pub sharpness: f32,
+    /// How much of the reprojected history is kept each frame in temporal mode
+    pub history_weight: f32,
+}
+
+impl Default for UpscalerSettings {
+    fn default() -> Self {
+        Self {
+            mode: UpscalerMode::Off,
+            render_scale: 0.67,
+            sharpness: 0.2,
+            history_weight: 0.9,
+        }
+    }
+}
+
+/// Renders the main view below the output resolution and upscales it before the final post pass
+pub struct Upscaler {
+    pub settings: UpscalerSettings,
+
+    color_format: vk::Format,
+    spatial_pipeline: RasterPipelineHandle,
+    temporal_pipeline: RasterPipelineHandle,
+    checkerboard_pipeline: RasterPipelineHandle,
+    checkerboard_mask_pipeline: RasterPipelineHandle,
+    depth_mode: DepthMode,
+    sharpen_pipeline: RasterPipelineHandle,
+    history_sampler: SamplerHandle,
+
+    output_size: [u32; 2],
+    history_images: Option<[ResizeTargetHandle; 2]>,
+    /// Generation of the history targets the history was accumulated in
+    history_generation: Option<u64>,
+    history_valid: bool,
+    history_index: usize,
+    frame_index: u32,
+    /// Which pixels the current frame shades in checkerboard mode, 0 for the ones where x + y is even
+    field: u32,
+    jitter: Vec2,
+    previous_jitter: Vec2,
+}
+
+impl Upscaler {
+    pub const MIN_RENDER_SCALE: f32 = 0.5;
+
+    /// Kept at full precision so slow accumulation doesn't get stuck on 8-bit steps
+    const HISTORY_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+    /// The depth and velocity formats are the scene pass's, the checkerboard mask is drawn inside it
+    pub fn new(
+        device: &mut Device,
+        color_format: vk::Format,
+        velocity_format: vk::Format,
+        depth_format: vk::Format,
+        depth_mode: DepthMode,
+        settings: UpscalerSettings,
+    ) -> anyhow::Result<Self> {
+        let spatial_pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::UPSCALE_SPATIAL_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format: color_format,
+                        blend: None,
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })?;
+
+        let temporal_pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::UPSCALE_TEMPORAL_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[
+                        neptune_vulkan::ColorTargetState {
+                            format: color_format,
+                            blend: None,
+                            write_mask: vk::ColorComponentFlags::RGBA,
+                        },
+                        neptune_vulkan::ColorTargetState {
+                            format: Self::HISTORY_FORMAT,
+                            blend: None,
+                            write_mask: vk::ColorComponentFlags::RGBA,
+                        },
+                    ],
+                }),
+            })?;
+
+        let checkerboard_pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::UPSCALE_CHECKERBOARD_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[
+                        neptune_vulkan::ColorTargetState {
+                            format: color_format,
+                            blend: None,
+                            write_mask: vk::ColorComponentFlags::RGBA,
+                        },
+                        neptune_vulkan::ColorTargetState {
+                            format: Self::HISTORY_FORMAT,
+                            blend: None,
+                            write_mask: vk::ColorComponentFlags::RGBA,
+                        },
+                    ],
+                }),
+            })?;
+
+        //Only writes depth, the scene's color and velocity are left at their clear values under the mask
+        let checkerboard_mask_pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: Some(neptune_vulkan::DepthState {
+                    format: depth_format,
+                    depth_enabled: true,
+                    write_depth: true,
+                    depth_op: vk::CompareOp::ALWAYS,
+                }),
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::CHECKERBOARD_MASK_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[
+                        neptune_vulkan::ColorTargetState {
+                            format: color_format,
+                            blend: None,
+                            write_mask: vk::ColorComponentFlags::empty(),
+                        },
+                        neptune_vulkan::ColorTargetState {
+                            format: velocity_format,
+                            blend: None,
+                            write_mask: vk::ColorComponentFlags::empty(),
+                        },
+                    ],
+                }),
+            })?;
+
+        let sharpen_pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::UPSCALE_SHARPEN_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format: color_format,
+                        blend: None,
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })?;
+
+        let history_sampler = device.create_sampler(
+            "Upscaler History Sampler",
+            &SamplerDescription {
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                address_mode_w: AddressMode::ClampToEdge,
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+        )?;
+
+        Ok(Self {
+            settings,
+            color_format,
+            spatial_pipeline,
+            temporal_pipeline,
+            checkerboard_pipeline,
+            checkerboard_mask_pipeline,
+            depth_mode,
+            sharpen_pipeline,
+            history_sampler,
+            output_size: [0; 2],
+            history_images: None,
+            history_generation: None,
+            history_valid: false,
+            history_index: 0,
+            frame_index: 0,
+            field: 0,
+            jitter: Vec2::ZERO,
+            previous_jitter: Vec2::ZERO,
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.settings.mode != UpscalerMode::Off
+    }
+
+    /// Render resolution relative to the output
+    pub fn render_scale(&self) -> Vec2 {
+        match self.settings.mode {
+            UpscalerMode::Off | UpscalerMode::Checkerboard => Vec2::ONE,
+            UpscalerMode::Spatial | UpscalerMode::Temporal => Vec2::splat(
+                self.settings
+                    .render_scale
+                    .clamp(Self::MIN_RENDER_SCALE, 1.0),
+            ),
+        }
+    }
+
+    /// Advances the sub-pixel jitter for the next frame, in render pixels.
+    /// Temporal mode uses a Halton(2, 3) sequence with more phases at lower render scales so every output pixel gets covered,
+    /// checkerboard mode doesn't jitter, it swaps which half of the pixels is shaded instead
+    pub fn next_jitter(&mut self) -> Vec2 {
+        self.previous_jitter = self.jitter;
+        self.jitter = match self.settings.mode {
+            UpscalerMode::Temporal => {
+                let render_scale = self.render_scale();
+                let phase_count = (8.0 / (render_scale.x * render_scale.y)).ceil() as u32;
+                let index = (self.frame_index % phase_count) + 1;
+                Vec2::new(halton(index, 2), halton(index, 3)) - 0.5
+            }
+            UpscalerMode::Checkerboard => {
+                self.field = self.frame_index % 2;
+                Vec2::ZERO
+            }
+            _ => Vec2::ZERO,
+        };
+        self.frame_index = self.frame_index.wrapping_add(1);
+        self.jitter
+    }
+
+    fn uses_history(&self) -> bool {
+        matches!(
+            self.settings.mode,
+            UpscalerMode::Temporal | UpscalerMode::Checkerboard
+        )
+    }
+
+    /// The scene pass has to draw `write_checkerboard_mask` first
+    pub fn masks_scene(&self) -> bool {
+        self.settings.mode == UpscalerMode::Checkerboard
+    }
+
+    /// Covers the pixels skipped this frame with near plane depth, drawn first in the scene pass right after the depth clear
+    pub fn write_checkerboard_mask(&self, raster_pass_builder: &mut RasterPassBuilder) {
+        let mut draw_command_builder =
+            RasterDrawCommandBuilder::new(self.checkerboard_mask_pipeline);
+        draw_command_builder.push_constant(self.field);
+        draw_command_builder.push_constant(self.depth_mode.near_depth().to_bits());
+        draw_command_builder.draw(0..3, 0..1);
+        draw_command_builder.build(raster_pass_builder);
+    }
+
+    /// Size of the view before upscaling, rounded the same way as the graph's relative images
+    fn render_size(&self) -> Vec2 {
+        (Vec2::new(self.output_size[0] as f32, self.output_size[1] as f32) * self.render_scale())
+            .floor()
+            .max(Vec2::ONE)
+    }
+
+    /// Adds or removes the history when the mode changes, `resize_targets` keeps it at the output size
+    pub fn update(
+        &mut self,
+        device: &mut Device,
+        resize_targets: &mut ResizeTargets,
+    ) -> anyhow::Result<()> {
+        self.output_size = resize_targets.main_view_size();
+
+        let uses_history = self.uses_history();
+        if self.history_images.is_some() != uses_history {
+            if let Some(history_images) = self.history_images.take() {
+                for image in history_images {
+                    resize_targets.remove(device, image);
+                }
+            }
+
+            if uses_history {
+                let description = |name: &str| ResizeTargetDescription {
+                    name: name.to_string(),
+                    scale: [1.0; 2],
+                    format: Self::HISTORY_FORMAT,
+                    usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                    mip_levels: 1,
+                };
+                self.history_images = Some([
+                    resize_targets.add(device, description("Upscaler History 0"))?,
+                    resize_targets.add(device, description("Upscaler History 1"))?,
+                ]);
+            }
+        }
+
+        //A new or recreated history has nothing to blend with yet
+        let history_generation = self
+            .history_images
+            .and_then(|history_images| resize_targets.generation(history_images[0]));
+        if history_generation != self.history_generation {
+            self.history_generation = history_generation;
+            self.history_valid = false;
+        }
+        Ok(())
+    }
+
+    /// Returns `color_image` upscaled to the size of `output_image`.
+    /// `color_image` and `velocity_image` must be `render_scale` relative to `output_image`
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        resize_targets: &ResizeTargets,
+        output_image: ImageHandle,
+        color_image: ImageHandle,
+        velocity_image: ImageHandle,
+        render_graph_builder: &mut T,
+    ) -> ImageHandle {
+        let upscaled_color_image = self.create_output_image(output_image, render_graph_builder);
+
+        //Velocity includes the change in jitter, it's removed so only real motion is reprojected
+        let jitter_delta = (self.jitter - self.previous_jitter) / self.render_size();
+
+        let history_images = self.history_images.and_then(|history_images| {
+            Some([
+                resize_targets.image(history_images[0])?,
+                resize_targets.image(history_images[1])?,
+            ])
+        });
+        match (self.settings.mode, history_images) {
+            (UpscalerMode::Temporal, Some(history_images)) => {
+                self.history_index = 1 - self.history_index;
+                let history_index = self.history_index;
+                let history_weight = if self.history_valid {
+                    self.settings.history_weight.clamp(0.0, 0.99)
+                } else {
+                    0.0
+                };
+                self.history_valid = true;
+
+                let mut temporal_pass_builder = RasterPassBuilder::new("Temporal Upscale Pass");
+                temporal_pass_builder.add_color_attachment(upscaled_color_image, None);
+                temporal_pass_builder.add_color_attachment(history_images[history_index], None);
+                let mut draw_command_builder =
+                    RasterDrawCommandBuilder::new(self.temporal_pipeline);
+                draw_command_builder.read_storage_image(color_image);
+                draw_command_builder.read_storage_image(velocity_image);
+                draw_command_builder.read_sampled_image(history_images[1 - history_index]);
+                draw_command_builder.read_sampler(self.history_sampler);
+                draw_command_builder.push_constant(self.output_size[0]);
+                draw_command_builder.push_constant(self.output_size[1]);
+                draw_command_builder.push_constant(self.jitter.x.to_bits());
+                draw_command_builder.push_constant(self.jitter.y.to_bits());
+                draw_command_builder.push_constant(jitter_delta.x.to_bits());
+                draw_command_builder.push_constant(jitter_delta.y.to_bits());
+                draw_command_builder.push_constant(history_weight.to_bits());
+                draw_command_builder.draw(0..3, 0..1);
+                draw_command_builder.build(&mut temporal_pass_builder);
+                temporal_pass_builder.build(render_graph_builder);
+            }
+            (UpscalerMode::Checkerboard, Some(history_images)) => {
+                self.history_index = 1 - self.history_index;
+                let history_index = self.history_index;
+                let history_valid = std::mem::replace(&mut self.history_valid, true);
+
+                let mut checkerboard_pass_builder =
+                    RasterPassBuilder::new("Checkerboard Reconstruct Pass");
+                checkerboard_pass_builder.add_color_attachment(upscaled_color_image, None);
+                checkerboard_pass_builder.add_color_attachment(history_images[history_index], None);
+                let mut draw_command_builder =
+                    RasterDrawCommandBuilder::new(self.checkerboard_pipeline);
+                draw_command_builder.read_storage_image(color_image);
+                draw_command_builder.read_storage_image(velocity_image);
+                draw_command_builder.read_sampled_image(history_images[1 - history_index]);
+                draw_command_builder.read_sampler(self.history_sampler);
+                draw_command_builder.push_constant(self.output_size[0]);
+                draw_command_builder.push_constant(self.output_size[1]);
+                draw_command_builder.push_constant(self.field);
+                draw_command_builder.push_constant(history_valid as u32);
+                draw_command_builder.draw(0..3, 0..1);
+                draw_command_builder.build(&mut checkerboard_pass_builder);
+                checkerboard_pass_builder.build(render_graph_builder);
+            }
+            _ => {
+                self.history_valid = false;
+
+                let mut spatial_pass_builder = RasterPassBuilder::new("Spatial Upscale Pass");
+                spatial_pass_builder.add_color_attachment(upscaled_color_image, None);
+                let mut draw_command_builder = RasterDrawCommandBuilder::new(self.spatial_pipeline);
+                draw_command_builder.read_storage_image(color_image);
+                draw_command_builder.push_constant(self.output_size[0]);
+                draw_command_builder.push_constant(self.output_size[1]);
+                draw_command_builder.draw(0..3, 0..1);
+                draw_command_builder.build(&mut spatial_pass_builder);
+                spatial_pass_builder.build(render_graph_builder);
+            }
+        }
+
+        if self.settings.sharpness <= 0.0 {
+            return upscaled_color_image;
+        }
+
+        let sharpened_color_image = self.create_output_image(output_image, render_graph_builder);
+        let mut sharpen_pass_builder = RasterPassBuilder::new("Upscale Sharpen Pass");
+        sharpen_pass_builder.add_color_attachment(sharpened_color_image, None);
+        let mut draw_command_builder = RasterDrawCommandBuilder::new(self.sharpen_pipeline);
+        draw_command_builder.read_storage_image(upscaled_color_image);
+        draw_command_builder.push_constant(self.settings.sharpness.clamp(0.0, 1.0).to_bits());
+        draw_command_builder.draw(0..3, 0..1);
+        draw_command_builder.build(&mut sharpen_pass_builder);
+        sharpen_pass_builder.build(render_graph_builder);
+
+        sharpened_color_image
+    }
+
+    fn create_output_image<T: RenderGraphBuilderTrait>(
+        &self,
+        output_image: ImageHandle,
+        render_graph_builder: &mut T,
+    ) -> ImageHandle {
+        render_graph_builder.create_transient_image(TransientImageDesc {
+            size: TransientImageSize::Relative([1.0; 2], output_image),
+            format: self.color_format,
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::STORAGE,
+            mip_levels: 1,
+            memory_location: MemoryLocation::GpuOnly,
+        })
+    }
+}
+
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
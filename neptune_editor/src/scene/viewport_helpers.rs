@@ -0,0 +1,197 @@
+use crate::scene::scene_renderer::{slice_to_bytes_unsafe, SceneCamera, SceneRenderer};
+use anyhow::Context;
+use glam::Vec4;
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, RasterDrawCommandBuilder, RasterPassBuilder,
+    RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{
+    vk, AddressMode, BufferHandle, BufferUsage, Device, FilterMode, ImageHandle,
+    RasterPipelineHandle, SamplerDescription, SamplerHandle, TransientImageDesc,
+    TransientImageSize,
+};
+
+#[derive(Debug, Clone)]
+pub struct ViewportHelperSettings {
+    pub show_grid: bool,
+    pub show_axis_gizmo: bool,
+    pub minor_line_spacing: f32,
+    /// Every n'th minor line is drawn as a major line
+    pub major_line_interval: u32,
+    pub minor_line_color: Vec4,
+    pub major_line_color: Vec4,
+    /// The grid fades out between these distances from the camera
+    pub fade_start: f32,
+    pub fade_end: f32,
+    /// Size of the axis gizmo in the bottom left corner in pixels
+    pub axis_gizmo_size: f32,
+}
+
+impl Default for ViewportHelperSettings {
+    fn default() -> Self {
+        Self {
+            show_grid: true,
+            show_axis_gizmo: true,
+            minor_line_spacing: 1.0,
+            major_line_interval: 10,
+            minor_line_color: Vec4::new(0.5, 0.5, 0.5, 0.4),
+            major_line_color: Vec4::new(0.8, 0.8, 0.8, 0.7),
+            fade_start: 20.0,
+            fade_end: 100.0,
+            axis_gizmo_size: 96.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct ViewportHelperData {
+    minor_line_color: Vec4,
+    major_line_color: Vec4,
+    grid_params: Vec4,
+    gizmo_params: Vec4,
+}
+
+/// Editor only overlays: an infinite ground grid on the y = 0 plane and a world axis gizmo
+pub struct ViewportHelpers {
+    pub settings: ViewportHelperSettings,
+
+    color_format: vk::Format,
+    pipeline: RasterPipelineHandle,
+    helper_buffer: BufferHandle,
+    depth_sampler: SamplerHandle,
+}
+
+impl ViewportHelpers {
+    pub fn new(
+        device: &mut Device,
+        color_format: vk::Format,
+        settings: ViewportHelperSettings,
+    ) -> anyhow::Result<Self> {
+        let pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::VIEWPORT_HELPERS_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format: color_format,
+                        blend: None,
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })?;
+
+        let helper_buffer = device
+            .create_buffer_init(
+                "ViewportHelperBuffer",
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                unsafe { slice_to_bytes_unsafe(&[ViewportHelperData::default()]) },
+            )
+            .context("Failed to create viewport helper buffer")?;
+
+        let depth_sampler = device.create_sampler(
+            "Viewport Helper Depth Sampler",
+            &SamplerDescription {
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                address_mode_w: AddressMode::ClampToEdge,
+                mag_filter: FilterMode::Nearest,
+                min_filter: FilterMode::Nearest,
+                ..Default::default()
+            },
+        )?;
+
+        Ok(Self {
+            settings,
+            color_format,
+            pipeline,
+            helper_buffer,
+            depth_sampler,
+        })
+    }
+
+    /// Returns the color image with the helpers drawn on top, or the input image if all helpers are hidden
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &self,
+        camera: &SceneCamera,
+        color_image: ImageHandle,
+        render_graph_builder: &mut T,
+    ) -> ImageHandle {
+        if !self.settings.show_grid && !self.settings.show_axis_gizmo {
+            return color_image;
+        }
+        let Some(&depth_image) = render_graph_builder
+            .blackboard()
+            .get::<ImageHandle>(SceneRenderer::VIEW_DEPTH_KEY)
+        else {
+            return color_image;
+        };
+
+        let helper_data = ViewportHelperData {
+            minor_line_color: self.settings.minor_line_color,
+            major_line_color: self.settings.major_line_color,
+            grid_params: Vec4::new(
+                self.settings.minor_line_spacing.max(f32::EPSILON),
+                self.settings.major_line_interval.max(1) as f32,
+                self.settings.fade_start,
+                self.settings
+                    .fade_end
+                    .max(self.settings.fade_start + f32::EPSILON),
+            ),
+            gizmo_params: Vec4::new(
+                self.settings.show_grid as u32 as f32,
+                self.settings.show_axis_gizmo as u32 as f32,
+                self.settings.axis_gizmo_size,
+                0.0,
+            ),
+        };
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: self.helper_buffer,
+                offset: 0,
+            },
+            std::mem::size_of::<ViewportHelperData>(),
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&[helper_data]) });
+            }),
+        );
+
+        let helper_color_image = render_graph_builder.create_transient_image(TransientImageDesc {
+            size: TransientImageSize::Relative([1.0; 2], color_image),
+            format: self.color_format,
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::STORAGE,
+            mip_levels: 1,
+            memory_location: MemoryLocation::GpuOnly,
+        });
+
+        let mut helper_pass_builder = RasterPassBuilder::new("Viewport Helper Pass");
+        helper_pass_builder.add_color_attachment(helper_color_image, None);
+        let mut draw_command_builder = RasterDrawCommandBuilder::new(self.pipeline);
+        draw_command_builder.read_buffer(camera.buffer());
+        draw_command_builder.read_buffer(self.helper_buffer);
+        draw_command_builder.read_storage_image(color_image);
+        draw_command_builder.read_sampled_image(depth_image);
+        draw_command_builder.read_sampler(self.depth_sampler);
+        draw_command_builder.draw(0..3, 0..1);
+        draw_command_builder.build(&mut helper_pass_builder);
+        helper_pass_builder.build(render_graph_builder);
+
+        helper_color_image
+    }
+}
@@ -0,0 +1,281 @@
+use crate::mesh::procedural::ProceduralMesh;
+use crate::mesh::{BoundingBox, VertexAttributes};
+use glam::{Mat4, Vec2, Vec3, Vec4};
+
+/// Room or corridor, anything inside it can only be seen through its portals
+#[derive(Debug, Clone)]
+pub struct VisibilityCell {
+    pub bounds: BoundingBox,
+}
+
+/// Opening between two cells, usually a thin box filling a doorway
+#[derive(Debug, Clone)]
+pub struct Portal {
+    pub cells: [usize; 2],
+    pub bounds: BoundingBox,
+}
+
+/// Cells and portals for indoor scenes, an empty graph culls nothing
+#[derive(Debug, Default, Clone)]
+pub struct CellPortalGraph {
+    pub cells: Vec<VisibilityCell>,
+    pub portals: Vec<Portal>,
+}
+
+/// Stops the flood fill from walking around loops of portals forever
+const MAX_PORTAL_DEPTH: usize = 16;
+
+/// Area of the screen that can still be seen through, in ndc
+#[derive(Debug, Copy, Clone)]
+struct ScreenRect {
+    min: Vec2,
+    max: Vec2,
+}
+
+impl ScreenRect {
+    const FULL: ScreenRect = ScreenRect {
+        min: Vec2::NEG_ONE,
+        max: Vec2::ONE,
+    };
+
+    fn intersect(&self, other: &ScreenRect) -> Option<ScreenRect> {
+        let min = self.min.max(other.min);
+        let max = self.max.min(other.max);
+        (min.x < max.x && min.y < max.y).then_some(ScreenRect { min, max })
+    }
+}
+
+fn overlaps(a: &BoundingBox, b: &BoundingBox) -> bool {
+    a.min.cmple(b.max).all() && b.min.cmple(a.max).all()
+}
+
+fn contains(bounds: &BoundingBox, point: Vec3) -> bool {
+    bounds.min.cmple(point).all() && point.cmple(bounds.max).all()
+}
+
+fn corners(bounds: &BoundingBox) -> [Vec3; 8] {
+    let (min, max) = (bounds.min, bounds.max);
+    [
+        Vec3::new(min.x, min.y, min.z),
+        Vec3::new(max.x, min.y, min.z),
+        Vec3::new(min.x, max.y, min.z),
+        Vec3::new(max.x, max.y, min.z),
+        Vec3::new(min.x, min.y, max.z),
+        Vec3::new(max.x, min.y, max.z),
+        Vec3::new(min.x, max.y, max.z),
+        Vec3::new(max.x, max.y, max.z),
+    ]
+}
+
+/// Screen rect covered by the box, None if it's entirely behind the camera.
+/// Boxes crossing the camera plane can't be projected, so they cover the whole screen
+fn project_bounds(bounds: &BoundingBox, view_projection: &Mat4) -> Option<ScreenRect> {
+    let clip_corners = corners(bounds).map(|corner| *view_projection * corner.extend(1.0));
+    if clip_corners.iter().all(|corner| corner.w <= 0.0) {
+        return None;
+    }
+    if clip_corners.iter().any(|corner| corner.w <= 1e-4) {
+        return Some(ScreenRect::FULL);
+    }
+
+    let mut rect = ScreenRect {
+        min: Vec2::splat(f32::MAX),
+        max: Vec2::splat(f32::MIN),
+    };
+    for corner in clip_corners.iter() {
+        let ndc = Vec2::new(corner.x, corner.y) / corner.w;
+        rect.min = rect.min.min(ndc);
+        rect.max = rect.max.max(ndc);
+    }
+    Some(rect)
+}
+
+impl CellPortalGraph {
+    /// Smallest cell containing the point, so small rooms can sit inside larger ones
+    pub fn cell_at(&self, point: Vec3) -> Option<usize> {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| contains(&cell.bounds, point))
+            .min_by(|(_, a), (_, b)| {
+                let a_size = a.bounds.half_extent();
+                let b_size = b.bounds.half_extent();
+                (a_size.x * a_size.y * a_size.z).total_cmp(&(b_size.x * b_size.y * b_size.z))
+            })
+            .map(|(index, _)| index)
+    }
+
+    /// Connects every cell the portal's box touches to the first one, returns the number of portals added
+    pub fn add_portal(&mut self, bounds: BoundingBox) -> usize {
+        let touching: Vec<usize> = self
+            .cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| overlaps(&cell.bounds, &bounds))
+            .map(|(index, _)| index)
+            .collect();
+        let Some((&first, rest)) = touching.split_first() else {
+            return 0;
+        };
+        for &other in rest {
+            self.portals.push(Portal {
+                cells: [first, other],
+                bounds,
+            });
+        }
+        rest.len()
+    }
+
+    /// Which cells can be seen from the camera, None when the camera isn't in any cell and nothing should be culled.
+    /// Walks out from the camera's cell, shrinking the visible screen rect to each portal it looks through
+    pub fn visible_cells(
+        &self,
+        view_projection: &Mat4,
+        camera_position: Vec3,
+    ) -> Option<Vec<bool>> {
+        let start = self.cell_at(camera_position)?;
+        let mut visible = vec![false; self.cells.len()];
+        visible[start] = true;
+        let mut path = vec![start];
+        self.flood(
+            ScreenRect::FULL,
+            view_projection,
+            camera_position,
+            &mut path,
+            &mut visible,
+        );
+        Some(visible)
+    }
+
+    fn flood(
+        &self,
+        rect: ScreenRect,
+        view_projection: &Mat4,
+        camera_position: Vec3,
+        path: &mut Vec<usize>,
+        visible: &mut [bool],
+    ) {
+        if path.len() > MAX_PORTAL_DEPTH {
+            return;
+        }
+
+        let cell = *path.last().unwrap();
+        for portal in self.portals.iter() {
+            let next = match portal.cells {
+                [a, b] if a == cell => b,
+                [a, b] if b == cell => a,
+                _ => continue,
+            };
+            if path.contains(&next) {
+                continue;
+            }
+
+            //Standing in the doorway sees everything the current rect does
+            let portal_rect = if contains(&portal.bounds, camera_position) {
+                Some(rect)
+            } else {
+                project_bounds(&portal.bounds, view_projection)
+                    .and_then(|portal_rect| portal_rect.intersect(&rect))
+            };
+            let Some(portal_rect) = portal_rect else {
+                continue;
+            };
+
+            visible[next] = true;
+            path.push(next);
+            self.flood(portal_rect, view_projection, camera_position, path, visible);
+            path.pop();
+        }
+    }
+
+    /// Objects outside every cell are always drawn, otherwise at least one cell they touch must be visible
+    pub fn is_visible(&self, bounds: &BoundingBox, visible_cells: &[bool]) -> bool {
+        let mut in_any_cell = false;
+        for (cell, visible) in self.cells.iter().zip(visible_cells.iter()) {
+            if overlaps(&cell.bounds, bounds) {
+                if *visible {
+                    return true;
+                }
+                in_any_cell = true;
+            }
+        }
+        !in_any_cell
+    }
+
+    /// Outlines of every cell and portal, visible cells in green, culled cells in red and portals in yellow
+    pub fn debug_mesh(&self, visible_cells: Option<&[bool]>) -> ProceduralMesh {
+        const LINE_WIDTH: f32 = 0.05;
+        let mut mesh = ProceduralMesh::default();
+        for (index, cell) in self.cells.iter().enumerate() {
+            let color = match visible_cells.map(|visible| visible[index]) {
+                Some(false) => Vec4::new(1.0, 0.1, 0.1, 1.0),
+                _ => Vec4::new(0.1, 1.0, 0.1, 1.0),
+            };
+            add_box_outline(&mut mesh, &cell.bounds, LINE_WIDTH, color);
+        }
+        for portal in self.portals.iter() {
+            add_box_outline(
+                &mut mesh,
+                &portal.bounds,
+                LINE_WIDTH,
+                Vec4::new(1.0, 0.9, 0.1, 1.0),
+            );
+        }
+        mesh
+    }
+}
+
+/// Each of the box's 12 edges as a thin box
+fn add_box_outline(mesh: &mut ProceduralMesh, bounds: &BoundingBox, width: f32, color: Vec4) {
+    let half_width = Vec3::splat(width * 0.5);
+    let (min, max) = (bounds.min, bounds.max);
+    for axis in 0..3 {
+        for corner in 0..4 {
+            let mut start = min;
+            let mut end = min;
+            let other_axes = [(axis + 1) % 3, (axis + 2) % 3];
+            for (bit, &other_axis) in other_axes.iter().enumerate() {
+                if corner & (1 << bit) != 0 {
+                    start[other_axis] = max[other_axis];
+                    end[other_axis] = max[other_axis];
+                }
+            }
+            end[axis] = max[axis];
+            add_solid_box(
+                mesh,
+                &BoundingBox {
+                    min: start - half_width,
+                    max: end + half_width,
+                },
+                color,
+            );
+        }
+    }
+}
+
+fn add_solid_box(mesh: &mut ProceduralMesh, bounds: &BoundingBox, color: Vec4) {
+    let box_corners = corners(bounds);
+    //Corner indices of each face wound counter clockwise from outside, with the face normal
+    const FACES: [([usize; 4], Vec3); 6] = [
+        ([1, 3, 7, 5], Vec3::X),
+        ([4, 6, 2, 0], Vec3::NEG_X),
+        ([2, 6, 7, 3], Vec3::Y),
+        ([0, 1, 5, 4], Vec3::NEG_Y),
+        ([4, 5, 7, 6], Vec3::Z),
+        ([0, 2, 3, 1], Vec3::NEG_Z),
+    ];
+    for (face, normal) in FACES.iter() {
+        let first = mesh.positions.len() as u32;
+        for &corner in face.iter() {
+            mesh.positions.push(box_corners[corner]);
+            mesh.attributes.push(VertexAttributes {
+                normal: *normal,
+                tangent: Vec4::new(1.0, 0.0, 0.0, 1.0),
+                tex_coords: Vec4::ZERO,
+                color,
+            });
+        }
+        mesh.indices
+            .extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+}
@@ -0,0 +1,284 @@
+use crate::scene::reduced_resolution::{ReducedResolution, UpsampleComposite};
+use crate::scene::scene_renderer::{slice_to_bytes_unsafe, SceneCamera};
+use anyhow::Context;
+use glam::{Vec3, Vec4};
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph::QueueType;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, ComputePassBuilder, RasterDrawCommandBuilder,
+    RasterPassBuilder, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{
+    vk, AddressMode, BufferHandle, BufferUsage, ComputePipelineHandle, Device, FilterMode,
+    ImageDescription3D, ImageHandle, RasterPipelineHandle, SamplerDescription, SamplerHandle,
+};
+
+#[derive(Debug, Clone)]
+pub struct VolumetricFogSettings {
+    pub enabled: bool,
+    pub density: f32,
+    pub scattering_color: Vec3,
+    pub anisotropy: f32,
+    pub sun_direction: Vec3,
+    pub sun_color: Vec3,
+    pub ambient_color: Vec3,
+    pub start_distance: f32,
+    pub max_distance: f32,
+    /// How much of the last frame's volume is kept, 0 disables temporal reprojection
+    pub history_weight: f32,
+}
+
+impl Default for VolumetricFogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            density: 0.02,
+            scattering_color: Vec3::ONE,
+            anisotropy: 0.6,
+            sun_direction: Vec3::new(0.3, 1.0, 0.2).normalize(),
+            sun_color: Vec3::splat(4.0),
+            ambient_color: Vec3::splat(0.1),
+            start_distance: 0.1,
+            max_distance: 64.0,
+            history_weight: 0.9,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct VolumetricFogData {
+    scattering: Vec4,
+    sun_direction: Vec4,
+    sun_color: Vec4,
+    ambient_color: Vec4,
+    volume_params: Vec4,
+}
+
+/// Froxel based light scattering, the volume is aligned to the camera frustum.
+/// The volume is resolved per pixel at the reduced effect resolution and upsampled onto the color image
+pub struct VolumetricFog {
+    pub settings: VolumetricFogSettings,
+    /// Set each frame from the post process volumes around the camera, replaces `settings.density`
+    pub(crate) density_override: Option<f32>,
+
+    scatter_pipeline: ComputePipelineHandle,
+    integrate_pipeline: ComputePipelineHandle,
+    resolve_pipeline: RasterPipelineHandle,
+
+    fog_buffer: BufferHandle,
+    scattering_volumes: [ImageHandle; 2],
+    integrated_volume: ImageHandle,
+    volume_sampler: SamplerHandle,
+    frame_index: u32,
+}
+
+impl VolumetricFog {
+    const VOLUME_SIZE: [u32; 3] = [160, 90, 64];
+    const VOLUME_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+    /// In-scattering in rgb and transmittance in alpha
+    const FOG_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+    pub fn new(device: &mut Device, settings: VolumetricFogSettings) -> anyhow::Result<Self> {
+        let scatter_pipeline = device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+            code: crate::shader::VOLUMETRIC_FOG_SCATTER_COMP,
+            entry: "main",
+        })?;
+        let integrate_pipeline = device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+            code: crate::shader::VOLUMETRIC_FOG_INTEGRATE_COMP,
+            entry: "main",
+        })?;
+
+        let resolve_pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::VOLUMETRIC_FOG_RESOLVE_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format: Self::FOG_FORMAT,
+                        blend: None,
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })?;
+
+        let fog_buffer = device
+            .create_buffer_init(
+                "VolumetricFogBuffer",
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                unsafe { slice_to_bytes_unsafe(&[VolumetricFogData::default()]) },
+            )
+            .context("Failed to create volumetric fog buffer")?;
+
+        let volume_description = ImageDescription3D {
+            size: Self::VOLUME_SIZE,
+            format: Self::VOLUME_FORMAT,
+            usage: vk::ImageUsageFlags::STORAGE,
+            mip_levels: 1,
+            location: MemoryLocation::GpuOnly,
+        };
+        let scattering_volumes = [
+            device.create_image_3d("Fog Scattering Volume 0", &volume_description)?,
+            device.create_image_3d("Fog Scattering Volume 1", &volume_description)?,
+        ];
+        let integrated_volume = device.create_image_3d(
+            "Fog Integrated Volume",
+            &ImageDescription3D {
+                usage: vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+                ..volume_description
+            },
+        )?;
+
+        let volume_sampler = device.create_sampler(
+            "Fog Volume Sampler",
+            &SamplerDescription {
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                address_mode_w: AddressMode::ClampToEdge,
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+        )?;
+
+        Ok(Self {
+            settings,
+            density_override: None,
+            scatter_pipeline,
+            integrate_pipeline,
+            resolve_pipeline,
+            fog_buffer,
+            scattering_volumes,
+            integrated_volume,
+            volume_sampler,
+            frame_index: 0,
+        })
+    }
+
+    /// Returns the fogged color image, or the input image if fog is disabled.
+    /// `effect_depth_image` is `depth_image` downsampled by `reduced_resolution`
+    #[allow(clippy::too_many_arguments)]
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        camera: &SceneCamera,
+        color_image: ImageHandle,
+        depth_image: ImageHandle,
+        reduced_resolution: &ReducedResolution,
+        effect_depth_image: ImageHandle,
+        render_graph_builder: &mut T,
+    ) -> ImageHandle {
+        if !self.settings.enabled {
+            //History is stale once the fog is re-enabled
+            self.frame_index = 0;
+            return color_image;
+        }
+
+        //No valid history on the first frame
+        let history_weight = if self.frame_index == 0 {
+            0.0
+        } else {
+            self.settings.history_weight
+        };
+
+        let fog_data = VolumetricFogData {
+            scattering: self
+                .settings
+                .scattering_color
+                .extend(self.density_override.unwrap_or(self.settings.density)),
+            sun_direction: self
+                .settings
+                .sun_direction
+                .normalize_or_zero()
+                .extend(self.settings.anisotropy),
+            sun_color: self.settings.sun_color.extend(0.0),
+            ambient_color: self.settings.ambient_color.extend(0.0),
+            volume_params: Vec4::new(
+                self.settings.start_distance,
+                self.settings.max_distance,
+                history_weight,
+                (self.frame_index % 1024) as f32,
+            ),
+        };
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: self.fog_buffer,
+                offset: 0,
+            },
+            std::mem::size_of::<VolumetricFogData>(),
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&[fog_data]) });
+            }),
+        );
+
+        let history_volume = self.scattering_volumes[(self.frame_index as usize + 1) % 2];
+        let scattering_volume = self.scattering_volumes[self.frame_index as usize % 2];
+        self.frame_index = self.frame_index.wrapping_add(1).max(1);
+
+        let mut scatter_pass_builder = ComputePassBuilder::new(
+            "Fog Scatter Pass",
+            QueueType::Graphics,
+            self.scatter_pipeline,
+        );
+        scatter_pass_builder.read_buffer(camera.buffer());
+        scatter_pass_builder.read_buffer(self.fog_buffer);
+        scatter_pass_builder.read_storage_image(history_volume);
+        scatter_pass_builder.write_storage_image(scattering_volume);
+        scatter_pass_builder.dispatch_threads(Self::VOLUME_SIZE);
+        scatter_pass_builder.build(render_graph_builder);
+
+        let mut integrate_pass_builder = ComputePassBuilder::new(
+            "Fog Integrate Pass",
+            QueueType::Graphics,
+            self.integrate_pipeline,
+        );
+        integrate_pass_builder.read_buffer(self.fog_buffer);
+        integrate_pass_builder.read_storage_image(scattering_volume);
+        integrate_pass_builder.write_storage_image(self.integrated_volume);
+        integrate_pass_builder.dispatch_threads([Self::VOLUME_SIZE[0], Self::VOLUME_SIZE[1], 1]);
+        integrate_pass_builder.build(render_graph_builder);
+
+        let fog_image = reduced_resolution.create_target(
+            color_image,
+            Self::FOG_FORMAT,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            render_graph_builder,
+        );
+
+        let mut resolve_pass_builder = RasterPassBuilder::new("Fog Resolve Pass");
+        resolve_pass_builder.add_color_attachment(fog_image, None);
+        let mut draw_command_builder = RasterDrawCommandBuilder::new(self.resolve_pipeline);
+        draw_command_builder.read_buffer(camera.buffer());
+        draw_command_builder.read_buffer(self.fog_buffer);
+        draw_command_builder.read_sampled_image(effect_depth_image);
+        draw_command_builder.read_sampled_image(self.integrated_volume);
+        draw_command_builder.read_sampler(self.volume_sampler);
+        draw_command_builder.draw(0..3, 0..1);
+        draw_command_builder.build(&mut resolve_pass_builder);
+        resolve_pass_builder.build(render_graph_builder);
+
+        reduced_resolution.upsample(
+            camera,
+            color_image,
+            depth_image,
+            fog_image,
+            effect_depth_image,
+            UpsampleComposite::Transmittance,
+            render_graph_builder,
+        )
+    }
+}
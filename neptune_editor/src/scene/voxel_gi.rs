@@ -0,0 +1,401 @@
+use crate::scene::scene_renderer::{slice_to_bytes_unsafe, Scene, SceneCamera};
+use anyhow::Context;
+use glam::{UVec4, Vec3, Vec4};
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph::QueueType;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, ComputePassBuilder, RasterDrawCommandBuilder,
+    RasterPassBuilder, RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{
+    vk, AddressMode, BufferHandle, BufferUsage, ComputePipelineHandle, Device, FilterMode,
+    ImageDescription3D, ImageHandle, RasterPipelineHandle, SamplerDescription, SamplerHandle,
+    TransientImageDesc, TransientImageSize,
+};
+
+#[derive(Debug, Clone)]
+pub struct VoxelGiSettings {
+    pub enabled: bool,
+    /// World size of the voxel volume, it follows the camera
+    pub extent: f32,
+    /// Direction towards the sun
+    pub sun_direction: Vec3,
+    pub sun_color: Vec3,
+    /// Added by cones that leave the volume without hitting anything
+    pub sky_color: Vec3,
+    pub diffuse_strength: f32,
+    pub specular_strength: f32,
+    /// Tangent of the specular cone's half angle, smaller is sharper
+    pub specular_aperture: f32,
+}
+
+impl Default for VoxelGiSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            extent: 48.0,
+            sun_direction: Vec3::new(0.3, 1.0, 0.2).normalize(),
+            sun_color: Vec3::splat(1.0),
+            sky_color: Vec3::new(0.3, 0.4, 0.5),
+            diffuse_strength: 1.0,
+            specular_strength: 0.25,
+            specular_aperture: 0.15,
+        }
+    }
+}
+
+/// Matches VoxelGiBuffer in the voxel_gi shaders
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct VoxelGiData {
+    /// xyz: min corner of the volume, w: size of a level 0 voxel
+    volume: Vec4,
+    /// w: diffuse strength
+    sun_direction: Vec4,
+    /// w: specular strength
+    sun_color: Vec4,
+    /// w: specular aperture
+    sky_color: Vec4,
+    /// x: triangle count, y: level 0 resolution, z: level count
+    params: UVec4,
+}
+
+/// Matches Triangle in voxel_gi_voxelize.comp
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct VoxelGiTriangle {
+    position0_albedo_r: Vec4,
+    position1_albedo_g: Vec4,
+    position2_albedo_b: Vec4,
+}
+
+struct VoxelGiScene {
+    triangle_buffer: BufferHandle,
+    triangle_count: u32,
+    version: u64,
+}
+
+/// Voxel cone traced global illumination.
+/// Every frame the scene is voxelized around the camera in compute, lit by the sun with voxel traced shadows,
+/// then downsampled into a few coarser volumes which diffuse and specular cones are traced through
+pub struct VoxelGi {
+    pub settings: VoxelGiSettings,
+
+    clear_pipeline: ComputePipelineHandle,
+    voxelize_pipeline: ComputePipelineHandle,
+    inject_pipeline: ComputePipelineHandle,
+    downsample_pipeline: ComputePipelineHandle,
+    apply_pipeline: RasterPipelineHandle,
+
+    gi_buffer: BufferHandle,
+    /// rgb: albedo, a: opacity
+    albedo_volume: ImageHandle,
+    /// xyz: normal
+    normal_volume: ImageHandle,
+    /// One volume per level, each half the resolution of the last. rgb: radiance, a: opacity
+    radiance_volumes: [ImageHandle; VoxelGi::LEVEL_COUNT],
+    volume_sampler: SamplerHandle,
+    color_format: vk::Format,
+
+    scene: Option<VoxelGiScene>,
+}
+
+impl VoxelGi {
+    const RESOLUTION: u32 = 64;
+    const LEVEL_COUNT: usize = 4;
+    const VOLUME_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+    pub fn new(
+        device: &mut Device,
+        color_format: vk::Format,
+        settings: VoxelGiSettings,
+    ) -> anyhow::Result<Self> {
+        let clear_pipeline = device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+            code: crate::shader::VOXEL_GI_CLEAR_COMP,
+            entry: "main",
+        })?;
+        let voxelize_pipeline = device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+            code: crate::shader::VOXEL_GI_VOXELIZE_COMP,
+            entry: "main",
+        })?;
+        let inject_pipeline = device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+            code: crate::shader::VOXEL_GI_INJECT_COMP,
+            entry: "main",
+        })?;
+        let downsample_pipeline = device.create_compute_pipeline(&neptune_vulkan::ShaderStage {
+            code: crate::shader::VOXEL_GI_DOWNSAMPLE_COMP,
+            entry: "main",
+        })?;
+
+        let apply_pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::VOXEL_GI_APPLY_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format: color_format,
+                        blend: None,
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })?;
+
+        let gi_buffer = device
+            .create_buffer_init(
+                "VoxelGiBuffer",
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                unsafe { slice_to_bytes_unsafe(&[VoxelGiData::default()]) },
+            )
+            .context("Failed to create voxel gi buffer")?;
+
+        let volume_description = ImageDescription3D {
+            size: [Self::RESOLUTION; 3],
+            format: Self::VOLUME_FORMAT,
+            usage: vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+            mip_levels: 1,
+            location: MemoryLocation::GpuOnly,
+        };
+        let albedo_volume = device.create_image_3d("Voxel Gi Albedo", &volume_description)?;
+        let normal_volume = device.create_image_3d(
+            "Voxel Gi Normal",
+            &ImageDescription3D {
+                usage: vk::ImageUsageFlags::STORAGE,
+                ..volume_description
+            },
+        )?;
+
+        let mut radiance_volumes = Vec::with_capacity(Self::LEVEL_COUNT);
+        for level in 0..Self::LEVEL_COUNT {
+            radiance_volumes.push(device.create_image_3d(
+                &format!("Voxel Gi Radiance {}", level),
+                &ImageDescription3D {
+                    size: [Self::RESOLUTION >> level; 3],
+                    ..volume_description
+                },
+            )?);
+        }
+
+        let volume_sampler = device.create_sampler(
+            "Voxel Gi Sampler",
+            &SamplerDescription {
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                address_mode_w: AddressMode::ClampToEdge,
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+        )?;
+
+        Ok(Self {
+            settings,
+            clear_pipeline,
+            voxelize_pipeline,
+            inject_pipeline,
+            downsample_pipeline,
+            apply_pipeline,
+            gi_buffer,
+            albedo_volume,
+            normal_volume,
+            radiance_volumes: radiance_volumes.try_into().unwrap(),
+            volume_sampler,
+            color_format,
+            scene: None,
+        })
+    }
+
+    /// Re-uploads the scene's triangles whenever the scene changes
+    pub fn update(&mut self, device: &mut Device, scene: &Scene) -> anyhow::Result<()> {
+        if !self.settings.enabled
+            || self.scene.as_ref().map(|gpu_scene| gpu_scene.version) == Some(scene.version())
+        {
+            return Ok(());
+        }
+
+        if let Some(old_scene) = self.scene.take() {
+            device.destroy_buffer(old_scene.triangle_buffer);
+        }
+
+        let mut triangles = Vec::new();
+        for (transform, model) in scene.instances() {
+            let model_matrix = transform.model_matrix();
+            for model_primitive in model.primitives.iter() {
+                let albedo = model_primitive
+                    .material
+                    .as_ref()
+                    .map(|material| material.base_color.truncate())
+                    .unwrap_or(Vec3::splat(0.8));
+
+                let geometry = &model_primitive.primitive.geometry;
+                for indices in geometry.indices.chunks_exact(3) {
+                    let [a, b, c] = [0, 1, 2].map(|corner| {
+                        model_matrix.transform_point3(geometry.positions[indices[corner] as usize])
+                    });
+                    triangles.push(VoxelGiTriangle {
+                        position0_albedo_r: a.extend(albedo.x),
+                        position1_albedo_g: b.extend(albedo.y),
+                        position2_albedo_b: c.extend(albedo.z),
+                    });
+                }
+            }
+        }
+        let triangle_count = triangles.len() as u32;
+
+        //Empty buffers can't be created
+        if triangles.is_empty() {
+            triangles.push(VoxelGiTriangle::default());
+        }
+
+        self.scene = Some(VoxelGiScene {
+            triangle_buffer: device
+                .create_buffer_init(
+                    "VoxelGiTriangles",
+                    BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                    MemoryLocation::GpuOnly,
+                    unsafe { slice_to_bytes_unsafe(&triangles) },
+                )
+                .context("Failed to create voxel gi triangle buffer")?,
+            triangle_count,
+            version: scene.version(),
+        });
+        Ok(())
+    }
+
+    /// Returns the color image with indirect light added, or the input image if voxel gi is disabled.
+    /// The surface normal is rebuilt from `depth_image` and the albedo is taken from the voxels
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &self,
+        camera: &SceneCamera,
+        color_image: ImageHandle,
+        depth_image: ImageHandle,
+        render_graph_builder: &mut T,
+    ) -> ImageHandle {
+        if !self.settings.enabled {
+            return color_image;
+        }
+
+        let Some(gpu_scene) = &self.scene else {
+            warn!("VoxelGi::update must be called before rendering");
+            return color_image;
+        };
+
+        //Snapped to the coarsest voxels so the volume doesn't shimmer as the camera moves
+        let voxel_size = self.settings.extent / Self::RESOLUTION as f32;
+        let snap = voxel_size * (1 << (Self::LEVEL_COUNT - 1)) as f32;
+        let center = (camera.position() / snap).round() * snap;
+        let settings = &self.settings;
+        let gi_data = VoxelGiData {
+            volume: (center - Vec3::splat(settings.extent * 0.5)).extend(voxel_size),
+            sun_direction: settings
+                .sun_direction
+                .normalize_or_zero()
+                .extend(settings.diffuse_strength),
+            sun_color: settings.sun_color.extend(settings.specular_strength),
+            sky_color: settings.sky_color.extend(settings.specular_aperture),
+            params: UVec4::new(
+                gpu_scene.triangle_count,
+                Self::RESOLUTION,
+                Self::LEVEL_COUNT as u32,
+                0,
+            ),
+        };
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: self.gi_buffer,
+                offset: 0,
+            },
+            std::mem::size_of::<VoxelGiData>(),
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&[gi_data]) });
+            }),
+        );
+
+        let mut clear_pass_builder = ComputePassBuilder::new(
+            "Voxel Gi Clear Pass",
+            QueueType::Graphics,
+            self.clear_pipeline,
+        );
+        clear_pass_builder.write_storage_image(self.albedo_volume);
+        clear_pass_builder.write_storage_image(self.normal_volume);
+        clear_pass_builder.dispatch_threads([Self::RESOLUTION; 3]);
+        clear_pass_builder.build(render_graph_builder);
+
+        //One thread per triangle, each writes every voxel it touches
+        let mut voxelize_pass_builder = ComputePassBuilder::new(
+            "Voxel Gi Voxelize Pass",
+            QueueType::Graphics,
+            self.voxelize_pipeline,
+        );
+        voxelize_pass_builder.read_buffer(self.gi_buffer);
+        voxelize_pass_builder.read_buffer(gpu_scene.triangle_buffer);
+        voxelize_pass_builder.write_storage_image(self.albedo_volume);
+        voxelize_pass_builder.write_storage_image(self.normal_volume);
+        voxelize_pass_builder.dispatch_threads([gpu_scene.triangle_count.max(1), 1, 1]);
+        voxelize_pass_builder.build(render_graph_builder);
+
+        let mut inject_pass_builder = ComputePassBuilder::new(
+            "Voxel Gi Inject Pass",
+            QueueType::Graphics,
+            self.inject_pipeline,
+        );
+        inject_pass_builder.read_buffer(self.gi_buffer);
+        inject_pass_builder.read_storage_image(self.albedo_volume);
+        inject_pass_builder.read_storage_image(self.normal_volume);
+        inject_pass_builder.write_storage_image(self.radiance_volumes[0]);
+        inject_pass_builder.dispatch_threads([Self::RESOLUTION; 3]);
+        inject_pass_builder.build(render_graph_builder);
+
+        for level in 1..Self::LEVEL_COUNT {
+            let mut downsample_pass_builder = ComputePassBuilder::new(
+                "Voxel Gi Downsample Pass",
+                QueueType::Graphics,
+                self.downsample_pipeline,
+            );
+            downsample_pass_builder.read_storage_image(self.radiance_volumes[level - 1]);
+            downsample_pass_builder.write_storage_image(self.radiance_volumes[level]);
+            downsample_pass_builder.dispatch_threads([Self::RESOLUTION >> level; 3]);
+            downsample_pass_builder.build(render_graph_builder);
+        }
+
+        let output_image = render_graph_builder.create_transient_image(TransientImageDesc {
+            size: TransientImageSize::Relative([1.0; 2], color_image),
+            format: self.color_format,
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::STORAGE,
+            mip_levels: 1,
+            memory_location: MemoryLocation::GpuOnly,
+        });
+
+        let mut apply_pass_builder = RasterPassBuilder::new("Voxel Gi Apply Pass");
+        apply_pass_builder.add_color_attachment(output_image, None);
+        let mut draw_command_builder = RasterDrawCommandBuilder::new(self.apply_pipeline);
+        draw_command_builder.read_buffer(camera.buffer());
+        draw_command_builder.read_buffer(self.gi_buffer);
+        draw_command_builder.read_storage_image(color_image);
+        draw_command_builder.read_sampled_image(depth_image);
+        draw_command_builder.read_sampled_image(self.albedo_volume);
+        for radiance_volume in self.radiance_volumes {
+            draw_command_builder.read_sampled_image(radiance_volume);
+        }
+        draw_command_builder.read_sampler(self.volume_sampler);
+        draw_command_builder.draw(0..3, 0..1);
+        draw_command_builder.build(&mut apply_pass_builder);
+        apply_pass_builder.build(render_graph_builder);
+
+        output_image
+    }
+}
@@ -0,0 +1,313 @@
+use crate::scene::scene_renderer::{slice_to_bytes_unsafe, SceneCamera};
+use anyhow::Context;
+use glam::{Vec2, Vec3, Vec4};
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, RasterDrawCommandBuilder, RasterPassBuilder,
+    RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{
+    vk, BufferHandle, BufferUsage, Device, ImageHandle, RasterPipelineHandle, SamplerDescription,
+    SamplerHandle, TransientImageDesc, TransientImageSize,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct GerstnerWave {
+    /// Direction the wave travels in on the xz plane
+    pub direction: Vec2,
+    pub wavelength: f32,
+    pub amplitude: f32,
+}
+
+impl GerstnerWave {
+    pub const fn new(direction: Vec2, wavelength: f32, amplitude: f32) -> Self {
+        Self {
+            direction,
+            wavelength,
+            amplitude,
+        }
+    }
+}
+
+/// Flat body of water added to a `Scene`, displaced by a few Gerstner waves
+#[derive(Debug, Clone)]
+pub struct WaterSurface {
+    /// Center of the surface on the xz plane
+    pub center: Vec2,
+    pub height: f32,
+    pub size: Vec2,
+    pub waves: [GerstnerWave; WaterSurface::WAVE_COUNT],
+    /// 0 to 1, how sharp the wave crests are
+    pub steepness: f32,
+    /// Tints the refracted scene where the water is shallow
+    pub shallow_color: Vec3,
+    /// Color of water too deep to see through
+    pub deep_color: Vec3,
+    /// How quickly light is absorbed, per meter of water
+    pub absorption: f32,
+    /// How far the scene under the water is bent by the waves, in screen uv
+    pub refraction_strength: f32,
+    /// Reflected where screen space reflections miss
+    pub sky_color: Vec3,
+    pub reflection_strength: f32,
+    /// How far the reflection rays march before giving up
+    pub reflection_distance: f32,
+}
+
+impl WaterSurface {
+    pub const WAVE_COUNT: usize = 4;
+}
+
+impl Default for WaterSurface {
+    fn default() -> Self {
+        Self {
+            center: Vec2::ZERO,
+            height: 0.0,
+            size: Vec2::splat(32.0),
+            waves: [
+                GerstnerWave::new(Vec2::new(1.0, 0.3), 8.0, 0.12),
+                GerstnerWave::new(Vec2::new(0.6, 1.0), 5.0, 0.08),
+                GerstnerWave::new(Vec2::new(-0.4, 1.0), 3.0, 0.04),
+                GerstnerWave::new(Vec2::new(1.0, -0.7), 1.5, 0.02),
+            ],
+            steepness: 0.6,
+            shallow_color: Vec3::new(0.8, 0.95, 0.95),
+            deep_color: Vec3::new(0.02, 0.1, 0.15),
+            absorption: 0.6,
+            refraction_strength: 0.03,
+            sky_color: Vec3::new(0.5, 0.7, 0.9),
+            reflection_strength: 1.0,
+            reflection_distance: 16.0,
+        }
+    }
+}
+
+/// Matches WaterSurface in water.vert and water.frag
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct WaterSurfaceData {
+    /// xy: center on the xz plane, z: height, w: time
+    center: Vec4,
+    /// xy: size, z: quads per side, w: steepness
+    grid: Vec4,
+    /// xy: direction, z: wavelength, w: amplitude
+    waves: [Vec4; WaterSurface::WAVE_COUNT],
+    /// w: refraction strength
+    shallow_color: Vec4,
+    /// w: absorption
+    deep_color: Vec4,
+    /// w: reflection strength
+    sky_color: Vec4,
+    /// x: reflection distance
+    reflection: Vec4,
+}
+
+impl WaterSurfaceData {
+    fn new(surface: &WaterSurface, time: f32, grid_resolution: u32) -> Self {
+        let mut waves = [Vec4::ZERO; WaterSurface::WAVE_COUNT];
+        for (data, wave) in waves.iter_mut().zip(surface.waves.iter()) {
+            *data = Vec4::new(
+                wave.direction.normalize_or_zero().x,
+                wave.direction.normalize_or_zero().y,
+                wave.wavelength.max(0.0),
+                wave.amplitude.max(0.0),
+            );
+        }
+
+        Self {
+            center: Vec4::new(surface.center.x, surface.center.y, surface.height, time),
+            grid: Vec4::new(
+                surface.size.x,
+                surface.size.y,
+                grid_resolution as f32,
+                surface.steepness.clamp(0.0, 1.0),
+            ),
+            waves,
+            shallow_color: surface
+                .shallow_color
+                .extend(surface.refraction_strength.max(0.0)),
+            deep_color: surface.deep_color.extend(surface.absorption.max(0.0)),
+            sky_color: surface
+                .sky_color
+                .extend(surface.reflection_strength.clamp(0.0, 1.0)),
+            reflection: Vec4::new(surface.reflection_distance.max(0.0), 0.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// Draws a scene's water surfaces over the opaque scene.
+/// The scene color is copied into a new image first so the water can refract and reflect the original one,
+/// the depth test is done by hand since the depth image is also sampled
+pub struct WaterRenderer {
+    color_format: vk::Format,
+    background_pipeline: RasterPipelineHandle,
+    water_pipeline: RasterPipelineHandle,
+    water_buffer: BufferHandle,
+    depth_sampler: SamplerHandle,
+    time: f32,
+}
+
+impl WaterRenderer {
+    pub const MAX_SURFACES: usize = 16;
+
+    /// Quads along each side of a surface, the waves are displaced per vertex
+    const GRID_RESOLUTION: u32 = 128;
+
+    pub fn new(device: &mut Device, color_format: vk::Format) -> anyhow::Result<Self> {
+        let background_pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::WATER_BACKGROUND_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format: color_format,
+                        blend: None,
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })?;
+
+        //Seen from above and below, so nothing is culled
+        let water_pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::WATER_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::WATER_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format: color_format,
+                        blend: None,
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })?;
+
+        let water_buffer = device
+            .create_buffer_init(
+                "WaterBuffer",
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                unsafe {
+                    slice_to_bytes_unsafe(&[WaterSurfaceData::default(); Self::MAX_SURFACES])
+                },
+            )
+            .context("Failed to create water buffer")?;
+
+        //Only used with texelFetch, so the filtering doesn't matter
+        let depth_sampler =
+            device.create_sampler("Water Depth Sampler", &SamplerDescription::default())?;
+
+        Ok(Self {
+            color_format,
+            background_pipeline,
+            water_pipeline,
+            water_buffer,
+            depth_sampler,
+            time: 0.0,
+        })
+    }
+
+    /// Called once per frame with the game time step, so pausing the game freezes the waves
+    pub fn update(&mut self, delta_time: f32) {
+        self.time += delta_time;
+    }
+
+    /// Returns the color image with the water drawn into it, or the input image if there is no water
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &self,
+        camera: &SceneCamera,
+        surfaces: &[WaterSurface],
+        color_image: ImageHandle,
+        depth_image: ImageHandle,
+        render_graph_builder: &mut T,
+    ) -> ImageHandle {
+        if surfaces.is_empty() {
+            return color_image;
+        }
+
+        if surfaces.len() > Self::MAX_SURFACES {
+            warn!(
+                "Scene has {} water surfaces, only the first {} are drawn",
+                surfaces.len(),
+                Self::MAX_SURFACES
+            );
+        }
+
+        let surface_data: Vec<WaterSurfaceData> = surfaces
+            .iter()
+            .take(Self::MAX_SURFACES)
+            .map(|surface| WaterSurfaceData::new(surface, self.time, Self::GRID_RESOLUTION))
+            .collect();
+        let surface_count = surface_data.len() as u32;
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: self.water_buffer,
+                offset: 0,
+            },
+            std::mem::size_of_val(surface_data.as_slice()),
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&surface_data) });
+            }),
+        );
+
+        let output_image = render_graph_builder.create_transient_image(TransientImageDesc {
+            size: TransientImageSize::Relative([1.0; 2], color_image),
+            format: self.color_format,
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::STORAGE,
+            mip_levels: 1,
+            memory_location: MemoryLocation::GpuOnly,
+        });
+
+        let mut water_pass_builder = RasterPassBuilder::new("Water Pass");
+        water_pass_builder.add_color_attachment(output_image, None);
+
+        let mut background_command_builder =
+            RasterDrawCommandBuilder::new(self.background_pipeline);
+        background_command_builder.read_storage_image(color_image);
+        background_command_builder.draw(0..3, 0..1);
+        background_command_builder.build(&mut water_pass_builder);
+
+        //One instance per surface
+        let mut water_command_builder = RasterDrawCommandBuilder::new(self.water_pipeline);
+        water_command_builder.read_buffer(camera.buffer());
+        water_command_builder.read_buffer(self.water_buffer);
+        water_command_builder.read_storage_image(color_image);
+        water_command_builder.read_sampled_image(depth_image);
+        water_command_builder.read_sampler(self.depth_sampler);
+        water_command_builder.draw(
+            0..(Self::GRID_RESOLUTION * Self::GRID_RESOLUTION * 6),
+            0..surface_count,
+        );
+        water_command_builder.build(&mut water_pass_builder);
+
+        water_pass_builder.build(render_graph_builder);
+        output_image
+    }
+}
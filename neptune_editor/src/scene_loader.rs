@@ -0,0 +1,358 @@
+use crate::derived_data::DerivedDataCache;
+use crate::gltf_loader::{
+    finish_gltf_scene, import_image, material_images, read_primitive, scene_mesh_nodes, GltfScene,
+    GltfSource, PrimitiveData,
+};
+use crate::material::MaterialPalette;
+use crate::mesh::batching::StaticBatching;
+use crate::mesh::primitive_cache::PrimitiveCache;
+use crate::mesh::{BoundingBox, Mesh};
+use crate::texture_cache::{ImportedTexture, TextureCache};
+use crate::upload_scheduler::UploadScheduler;
+use anyhow::Context;
+use glam::{Mat4, Vec3};
+use neptune_vulkan::{Device, ImageHandle};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::time::Instant;
+
+enum LoadMessage {
+    /// Sent once the file is parsed so progress has totals
+    Started {
+        mesh_count: usize,
+        image_count: usize,
+    },
+    /// Bounds are in world space, None if no node uses it
+    Mesh {
+        index: usize,
+        name: String,
+        primitives: Vec<PrimitiveData>,
+        bounds: Option<BoundingBox>,
+    },
+    /// Bounds cover every mesh using the image
+    Image {
+        index: usize,
+        name: String,
+        texture: ImportedTexture,
+        bounds: Option<BoundingBox>,
+    },
+    /// Last message, the rest of the scene is built from the document on the main thread
+    Parsed(Box<GltfSource>),
+    Failed(anyhow::Error),
+}
+
+/// Finished work from the loader thread waiting for its turn to upload
+enum PendingUpload {
+    Mesh {
+        index: usize,
+        name: String,
+        primitives: Vec<PrimitiveData>,
+    },
+    Image {
+        index: usize,
+        name: String,
+        texture: ImportedTexture,
+    },
+}
+
+/// Finished and total counts for each part of a load
+#[derive(Debug, Default, Copy, Clone)]
+pub struct LoadProgress {
+    pub meshes: (usize, usize),
+    pub textures: (usize, usize),
+    pub pipelines: (usize, usize),
+}
+
+impl LoadProgress {
+    /// Fraction of the load that's done, each part counts equally
+    pub fn fraction(&self) -> f32 {
+        let part = |(done, total): (usize, usize)| {
+            if total == 0 {
+                1.0
+            } else {
+                done as f32 / total as f32
+            }
+        };
+        (part(self.meshes) + part(self.textures) + part(self.pipelines)) / 3.0
+    }
+}
+
+/// Loads a gltf scene without blocking the frame. The file is parsed, its primitives read and its textures
+/// decoded and compressed on a worker thread, then the main thread uploads a budgeted amount each `poll`, the parts
+/// nearest the camera first. Pipelines aren't tracked here, they're precompiled once the scene has been added to the world
+pub struct SceneLoader {
+    path: PathBuf,
+    static_batching: StaticBatching,
+    receiver: Receiver<LoadMessage>,
+    scheduler: UploadScheduler<PendingUpload>,
+    meshes: Vec<Option<Mesh>>,
+    images: Vec<Option<ImageHandle>>,
+    source: Option<GltfSource>,
+    started: bool,
+}
+
+impl SceneLoader {
+    /// `upload_budget` is the bytes of mesh and texture data uploaded per `poll`
+    pub fn new(
+        path: &Path,
+        static_batching: StaticBatching,
+        upload_budget: usize,
+    ) -> anyhow::Result<Self> {
+        let (sender, receiver) = channel();
+        let worker_path = path.to_path_buf();
+        std::thread::Builder::new()
+            .name("Scene Loader".to_string())
+            .spawn(move || {
+                if let Err(err) = parse_scene(&worker_path, &sender) {
+                    let _ = sender.send(LoadMessage::Failed(err));
+                }
+            })
+            .context("Failed to start scene loader thread")?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            static_batching,
+            receiver,
+            scheduler: UploadScheduler::new(upload_budget),
+            meshes: Vec::new(),
+            images: Vec::new(),
+            source: None,
+            started: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Uploads what the worker has finished, up to the budget, returns the scene once everything is loaded.
+    /// The camera decides what goes first
+    pub fn poll(
+        &mut self,
+        device: &mut Device,
+        primitive_cache: &mut PrimitiveCache,
+        material_palette: &MaterialPalette,
+        camera_position: Vec3,
+        view_projection: &Mat4,
+    ) -> anyhow::Result<Option<GltfScene>> {
+        loop {
+            let message = match self.receiver.try_recv() {
+                Ok(message) => message,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if self.source.is_some() {
+                        break;
+                    }
+                    anyhow::bail!("Scene loader thread stopped before it finished");
+                }
+            };
+
+            match message {
+                LoadMessage::Started {
+                    mesh_count,
+                    image_count,
+                } => {
+                    self.meshes.resize_with(mesh_count, || None);
+                    self.images.resize_with(image_count, || None);
+                    self.started = true;
+                }
+                LoadMessage::Mesh {
+                    index,
+                    name,
+                    primitives,
+                    bounds,
+                } => {
+                    let size = primitives.iter().map(PrimitiveData::byte_size).sum();
+                    self.scheduler.push(
+                        PendingUpload::Mesh {
+                            index,
+                            name,
+                            primitives,
+                        },
+                        size,
+                        bounds,
+                    );
+                }
+                LoadMessage::Image {
+                    index,
+                    name,
+                    texture,
+                    bounds,
+                } => {
+                    let size = texture.mips.iter().map(Vec::len).sum();
+                    self.scheduler.push(
+                        PendingUpload::Image {
+                            index,
+                            name,
+                            texture,
+                        },
+                        size,
+                        bounds,
+                    );
+                }
+                LoadMessage::Parsed(source) => self.source = Some(*source),
+                LoadMessage::Failed(err) => return Err(err),
+            }
+        }
+
+        for upload in self.scheduler.next_frame(camera_position, view_projection) {
+            match upload {
+                PendingUpload::Mesh {
+                    index,
+                    name,
+                    primitives,
+                } => {
+                    let primitives = primitives
+                        .iter()
+                        .map(|data| primitive_cache.get_or_create(device, data))
+                        .collect::<anyhow::Result<Vec<_>>>()?;
+                    self.meshes[index] = Some(Mesh::new(name, primitives));
+                }
+                PendingUpload::Image {
+                    index,
+                    name,
+                    texture,
+                } => {
+                    self.images[index] = Some(texture.create_image(device, &name)?);
+                }
+            }
+        }
+
+        let uploaded = self.scheduler.is_empty()
+            && self.meshes.iter().all(Option::is_some)
+            && self.images.iter().all(Option::is_some);
+        let Some(source) = self.source.as_ref().filter(|_| uploaded) else {
+            return Ok(None);
+        };
+
+        let now = Instant::now();
+        let scene = finish_gltf_scene(
+            device,
+            material_palette,
+            source,
+            self.meshes.drain(..).flatten().collect(),
+            self.images.drain(..).flatten().collect(),
+            self.static_batching,
+        )?;
+        info!("Scene Finish: {}", now.elapsed().as_secs_f32());
+        Ok(Some(scene))
+    }
+
+    /// Pipelines are left at zero, they're the editor's to fill in
+    pub fn progress(&self) -> LoadProgress {
+        //Nothing is known until the file is parsed, so show it as not started rather than done
+        if !self.started {
+            return LoadProgress {
+                meshes: (0, 1),
+                textures: (0, 1),
+                pipelines: (0, 0),
+            };
+        }
+        LoadProgress {
+            meshes: (
+                self.meshes.iter().filter(|mesh| mesh.is_some()).count(),
+                self.meshes.len(),
+            ),
+            textures: (
+                self.images.iter().filter(|image| image.is_some()).count(),
+                self.images.len(),
+            ),
+            pipelines: (0, 0),
+        }
+    }
+}
+
+/// Everything that doesn't need the device, run on the loader thread
+fn parse_scene(path: &Path, sender: &Sender<LoadMessage>) -> anyhow::Result<()> {
+    let now = Instant::now();
+    let file_data =
+        std::fs::read(path).with_context(|| format!("Failed to read scene {}", path.display()))?;
+    let source = GltfSource::parse(&file_data, path.parent().unwrap_or_else(|| Path::new("./")))?;
+    info!("File Loading: {}", now.elapsed().as_secs_f32());
+
+    let document = &source.document;
+    let send = |message| {
+        sender
+            .send(message)
+            .ok()
+            .context("Scene loader was dropped")
+    };
+    send(LoadMessage::Started {
+        mesh_count: document.meshes().len(),
+        image_count: document.images().len(),
+    })?;
+
+    //World bounds of each mesh and image, so the main thread can upload what's in front of the camera first
+    let mesh_nodes = scene_mesh_nodes(document);
+    let mut mesh_bounds: Vec<Option<BoundingBox>> = vec![None; document.meshes().len()];
+    let mut image_bounds: Vec<Option<BoundingBox>> = vec![None; document.images().len()];
+    let material_image_indices: Vec<Vec<usize>> = document
+        .materials()
+        .map(|material| material_images(&material))
+        .collect();
+
+    let now = Instant::now();
+    for gltf_mesh in document.meshes() {
+        let name = gltf_mesh
+            .name()
+            .map(|str| str.to_string())
+            .unwrap_or_else(|| format!("Unnamed Mesh {}", gltf_mesh.index()));
+        let primitives = gltf_mesh
+            .primitives()
+            .map(|gltf_primitive| read_primitive(&source.buffer_data, &gltf_primitive))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let local_bounds =
+            BoundingBox::union_all(primitives.iter().map(|primitive| primitive.bounding_box));
+        if let Some(local_bounds) = local_bounds {
+            for node in mesh_nodes
+                .iter()
+                .filter(|node| node.mesh_index == gltf_mesh.index())
+            {
+                let node_bounds = local_bounds.transform(&node.transform);
+                union_bounds(&mut mesh_bounds[gltf_mesh.index()], &node_bounds);
+                for &material in node.primitive_materials.iter() {
+                    for &image in material_image_indices.get(material).into_iter().flatten() {
+                        union_bounds(&mut image_bounds[image], &node_bounds);
+                    }
+                }
+            }
+        }
+
+        send(LoadMessage::Mesh {
+            index: gltf_mesh.index(),
+            name,
+            primitives,
+            bounds: mesh_bounds[gltf_mesh.index()],
+        })?;
+    }
+    info!("Mesh Convert: {}", now.elapsed().as_secs_f32());
+
+    let now = Instant::now();
+    let texture_cache =
+        TextureCache::new(DerivedDataCache::new(DerivedDataCache::DEFAULT_DIRECTORY));
+    for gltf_image in document.images() {
+        let (name, texture) = import_image(
+            &texture_cache,
+            &gltf_image,
+            &source.base_path,
+            &source.buffer_data,
+        )?;
+        send(LoadMessage::Image {
+            index: gltf_image.index(),
+            name,
+            texture,
+            bounds: image_bounds[gltf_image.index()],
+        })?;
+    }
+    info!("Image Convert: {}", now.elapsed().as_secs_f32());
+
+    send(LoadMessage::Parsed(Box::new(source)))
+}
+
+fn union_bounds(bounds: &mut Option<BoundingBox>, other: &BoundingBox) {
+    *bounds = Some(match bounds {
+        Some(bounds) => bounds.union(other),
+        None => *other,
+    });
+}
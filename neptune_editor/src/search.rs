@@ -0,0 +1,253 @@
+use crate::asset_database::{AssetDatabase, AssetGuid};
+use crate::game::world::World;
+use crate::scene::scene_renderer::SceneInstanceHandle;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchTarget {
+    Entity(SceneInstanceHandle),
+    Asset(AssetGuid, PathBuf),
+}
+
+/// Something that can show up in the search results
+#[derive(Debug, Clone)]
+pub struct SearchItem {
+    pub name: String,
+    pub target: SearchTarget,
+    /// What `type:` filters against, the entity kind or asset type name
+    pub kind: &'static str,
+    /// What `has:` filters against, the components an entity has
+    pub components: Vec<&'static str>,
+}
+
+impl SearchItem {
+    /// Every static entity and ship module in the world, named after their models
+    pub fn from_world(world: &World) -> Vec<Self> {
+        let mut items: Vec<Self> = world
+            .static_entities()
+            .iter()
+            .filter_map(|entity| {
+                let mut components = vec!["model"];
+                if entity.has_collider() {
+                    components.push("collider");
+                }
+                Some(Self {
+                    name: entity.model().name.clone(),
+                    target: SearchTarget::Entity(entity.scene_instance()?),
+                    kind: "static_entity",
+                    components,
+                })
+            })
+            .collect();
+
+        for ship in world.ships() {
+            for handle in ship.module_instances() {
+                let Some(model) = world.data.scene.get_model(handle) else {
+                    continue;
+                };
+                items.push(Self {
+                    name: model.name.clone(),
+                    target: SearchTarget::Entity(handle),
+                    kind: "ship_module",
+                    components: vec!["model", "collider", "rigid_body"],
+                });
+            }
+        }
+        items
+    }
+
+    /// Every asset in the database, by file name
+    pub fn from_asset_database(asset_database: &AssetDatabase) -> Vec<Self> {
+        let mut items: Vec<Self> = asset_database
+            .iter()
+            .map(|(guid, record)| Self {
+                name: record.name().to_string(),
+                target: SearchTarget::Asset(guid, record.path.clone()),
+                kind: record.asset_type.name(),
+                components: Vec::new(),
+            })
+            .collect();
+        items.sort_by(|a, b| a.name.cmp(&b.name));
+        items
+    }
+}
+
+/// A parsed search query, `type:<kind>` and `has:<component>` words filter and everything else is fuzzy matched
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct SearchQuery {
+    pub text: String,
+    pub kinds: Vec<String>,
+    pub components: Vec<String>,
+    /// From `rename:<name>`, what to rename the best matching asset to once it's picked
+    pub rename: Option<String>,
+}
+
+impl SearchQuery {
+    pub fn parse(query: &str) -> Self {
+        let mut search_query = Self::default();
+        let mut words = Vec::new();
+        for word in query.split_whitespace() {
+            if let Some(kind) = word.strip_prefix("type:") {
+                search_query.kinds.push(kind.to_lowercase());
+            } else if let Some(component) = word.strip_prefix("has:") {
+                search_query.components.push(component.to_lowercase());
+            } else if let Some(name) = word.strip_prefix("rename:") {
+                search_query.rename = Some(name.to_string());
+            } else {
+                words.push(word);
+            }
+        }
+        search_query.text = words.join(" ");
+        search_query
+    }
+
+    /// None if the item is filtered out or doesn't match, higher scores are better matches
+    pub fn score(&self, item: &SearchItem) -> Option<i32> {
+        if !self.kinds.is_empty() && !self.kinds.iter().any(|kind| item.kind.starts_with(kind)) {
+            return None;
+        }
+        if !self
+            .components
+            .iter()
+            .all(|component| item.components.contains(&component.as_str()))
+        {
+            return None;
+        }
+        fuzzy_score(&self.text, &item.name)
+    }
+
+    /// Indices of the matching items, best match first
+    pub fn results(&self, items: &[SearchItem]) -> Vec<usize> {
+        let mut results: Vec<(i32, usize)> = items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| self.score(item).map(|score| (score, index)))
+            .collect();
+        //Stable so equal scores keep their original order
+        results.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        results.into_iter().map(|(_, index)| index).collect()
+    }
+}
+
+/// Case insensitive subsequence match, consecutive letters and letters starting a word score higher.
+/// An empty pattern matches everything with a score of 0
+pub fn fuzzy_score(pattern: &str, candidate: &str) -> Option<i32> {
+    const MATCH_SCORE: i32 = 1;
+    const CONSECUTIVE_BONUS: i32 = 4;
+    const WORD_START_BONUS: i32 = 6;
+    const GAP_PENALTY: i32 = 1;
+
+    let mut pattern_chars = pattern
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .peekable();
+
+    let mut score = 0;
+    let mut previous_matched = false;
+    let mut previous_char = None;
+    for c in candidate.chars() {
+        let Some(pattern_char) = pattern_chars.peek() else {
+            break;
+        };
+
+        let word_start = match previous_char {
+            None => true,
+            Some(previous) => {
+                !char::is_alphanumeric(previous)
+                    || (char::is_lowercase(previous) && c.is_uppercase())
+            }
+        };
+        previous_char = Some(c);
+
+        if c.to_lowercase().eq(std::iter::once(*pattern_char)) {
+            pattern_chars.next();
+            score += MATCH_SCORE;
+            if previous_matched {
+                score += CONSECUTIVE_BONUS;
+            }
+            if word_start {
+                score += WORD_START_BONUS;
+            }
+            previous_matched = true;
+        } else {
+            if previous_matched {
+                score -= GAP_PENALTY;
+            }
+            previous_matched = false;
+        }
+    }
+
+    pattern_chars.peek().is_none().then_some(score)
+}
+
+/// Type to search the world's entities and the asset directories.
+/// There's no text rendering yet, so the results are logged and entity matches can be selected
+#[derive(Default)]
+pub struct SearchBox {
+    active: bool,
+    query: String,
+    items: Vec<SearchItem>,
+}
+
+impl SearchBox {
+    const LOGGED_RESULTS: usize = 10;
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Starts a new search over `items`, taken when it's opened so the results match what was there
+    pub fn open(&mut self, items: Vec<SearchItem>) {
+        self.active = true;
+        self.query.clear();
+        self.items = items;
+        info!(
+            "Search: {} items, filter with type:<kind> or has:<component>, rename an asset with rename:<name>",
+            self.items.len()
+        );
+    }
+
+    pub fn close(&mut self) {
+        self.active = false;
+        self.items.clear();
+    }
+
+    pub fn push_text(&mut self, text: &str) {
+        self.query.push_str(text);
+        self.log_results();
+    }
+
+    pub fn backspace(&mut self) {
+        self.query.pop();
+        self.log_results();
+    }
+
+    /// Matching items, best match first
+    pub fn results(&self) -> Vec<&SearchItem> {
+        SearchQuery::parse(&self.query)
+            .results(&self.items)
+            .into_iter()
+            .map(|index| &self.items[index])
+            .collect()
+    }
+
+    pub fn rename(&self) -> Option<String> {
+        SearchQuery::parse(&self.query).rename
+    }
+
+    fn log_results(&self) {
+        let results = self.results();
+        info!("Search \"{}\": {} results", self.query, results.len());
+        for item in results.iter().take(Self::LOGGED_RESULTS) {
+            match &item.target {
+                SearchTarget::Entity(handle) => {
+                    info!("  [{}] {} {:?}", item.kind, item.name, handle)
+                }
+                SearchTarget::Asset(_, path) => {
+                    info!("  [{}] {} ({})", item.kind, item.name, path.display())
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use crate::derived_data::{DerivedDataCache, DerivedDataKey};
+use crate::scene::scene_renderer::SceneRenderer;
+use crate::shader_graph::graph::ShaderGraph;
+use crate::shader_library;
+use anyhow::Context;
+use neptune_vulkan::{Device, RasterPipelineHandle};
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+struct PrecompileJob {
+    key: DerivedDataKey,
+    name: String,
+    source: String,
+}
+
+struct PrecompileResult {
+    key: DerivedDataKey,
+    name: String,
+    code: anyhow::Result<Vec<u32>>,
+}
+
+/// Compiles graphs on a worker thread so they're ready before they are first used.
+/// Only the spirv is built off the main thread, pipelines still need the device
+struct Precompiler {
+    job_sender: Sender<PrecompileJob>,
+    result_receiver: Receiver<PrecompileResult>,
+    pending: HashSet<DerivedDataKey>,
+    /// Jobs queued since the queue was last empty, for progress
+    queued: usize,
+}
+
+impl Precompiler {
+    fn new(derived_data: Option<DerivedDataCache>) -> anyhow::Result<Self> {
+        let (job_sender, job_receiver) = channel::<PrecompileJob>();
+        let (result_sender, result_receiver) = channel();
+        std::thread::Builder::new()
+            .name("Shader Precompile".to_string())
+            .spawn(move || {
+                let Some(compiler) = shaderc::Compiler::new() else {
+                    error!("Failed to create shader precompile compiler");
+                    return;
+                };
+                //Ends once the sender is dropped along with the ShaderGraphCompiler
+                for job in job_receiver {
+                    let code = compile_cached(
+                        &compiler,
+                        derived_data.as_ref(),
+                        &job.key,
+                        &job.name,
+                        &job.source,
+                    );
+                    let result = PrecompileResult {
+                        key: job.key,
+                        name: job.name,
+                        code,
+                    };
+                    if result_sender.send(result).is_err() {
+                        break;
+                    }
+                }
+            })
+            .context("Failed to start shader precompile thread")?;
+
+        Ok(Self {
+            job_sender,
+            result_receiver,
+            pending: HashSet::new(),
+            queued: 0,
+        })
+    }
+}
+
+/// Compiles shader graphs at runtime, both the spirv and the pipelines are cached by the generated source
+pub struct ShaderGraphCompiler {
+    compiler: shaderc::Compiler,
+    derived_data: Option<DerivedDataCache>,
+    pipelines: HashMap<DerivedDataKey, RasterPipelineHandle>,
+    precompiler: Option<Precompiler>,
+}
+
+impl ShaderGraphCompiler {
+    const IMPORTER: &'static str = "shader_graphs";
+    /// Bump when the compile options change
+    const VERSION: u32 = 2;
+
+    /// Spirv is also cached on disk in `derived_data` if there is one
+    pub fn new(derived_data: Option<DerivedDataCache>) -> anyhow::Result<Self> {
+        Ok(Self {
+            compiler: shaderc::Compiler::new().context("Failed to create shader compiler")?,
+            derived_data,
+            pipelines: HashMap::new(),
+            precompiler: None,
+        })
+    }
+
+    /// Queues the graph to be compiled in the background, `poll_precompiled` creates its pipeline once it's done
+    pub fn precompile(&mut self, name: &str, graph: &ShaderGraph) -> anyhow::Result<()> {
+        let source = graph.generate_glsl()?;
+        let key = source_key(&source);
+        if self.pipelines.contains_key(&key) {
+            return Ok(());
+        }
+
+        let precompiler = match &mut self.precompiler {
+            Some(precompiler) => precompiler,
+            None => self
+                .precompiler
+                .insert(Precompiler::new(self.derived_data.clone())?),
+        };
+        if !precompiler.pending.insert(key) {
+            return Ok(());
+        }
+        if precompiler.pending.len() == 1 {
+            precompiler.queued = 0;
+        }
+        precompiler.queued += 1;
+        precompiler
+            .job_sender
+            .send(PrecompileJob {
+                key,
+                name: name.to_string(),
+                source,
+            })
+            .ok()
+            .context("Shader precompile thread has stopped")
+    }
+
+    /// Creates pipelines for every graph that finished compiling since the last call
+    pub fn poll_precompiled(&mut self, device: &mut Device, scene_renderer: &SceneRenderer) {
+        let Some(precompiler) = &mut self.precompiler else {
+            return;
+        };
+
+        let mut finished = false;
+        for result in precompiler.result_receiver.try_iter() {
+            precompiler.pending.remove(&result.key);
+            finished = precompiler.pending.is_empty();
+            //Already built on the main thread if it was needed before it finished
+            if self.pipelines.contains_key(&result.key) {
+                continue;
+            }
+
+            match result
+                .code
+                .and_then(|code| scene_renderer.create_material_pipeline(device, &code))
+            {
+                Ok(pipeline) => {
+                    self.pipelines.insert(result.key, pipeline);
+                }
+                Err(err) => warn!(
+                    "Failed to precompile shader graph {}: {:#}",
+                    result.name, err
+                ),
+            }
+        }
+
+        if finished {
+            info!("Precompiled {} shader graphs", precompiler.queued);
+        }
+    }
+
+    /// Finished and total count of the current batch of background compiles, None once they're all done
+    pub fn precompile_progress(&self) -> Option<(usize, usize)> {
+        self.precompiler
+            .as_ref()
+            .filter(|precompiler| !precompiler.pending.is_empty())
+            .map(|precompiler| {
+                (
+                    precompiler.queued - precompiler.pending.len(),
+                    precompiler.queued,
+                )
+            })
+    }
+
+    pub fn get_pipeline(
+        &mut self,
+        device: &mut Device,
+        scene_renderer: &SceneRenderer,
+        name: &str,
+        graph: &ShaderGraph,
+    ) -> anyhow::Result<RasterPipelineHandle> {
+        let source = graph.generate_glsl()?;
+
+        let key = source_key(&source);
+        if let Some(pipeline) = self.pipelines.get(&key) {
+            return Ok(*pipeline);
+        }
+
+        let code = compile_cached(
+            &self.compiler,
+            self.derived_data.as_ref(),
+            &key,
+            name,
+            &source,
+        )?;
+
+        let pipeline = scene_renderer.create_material_pipeline(device, &code)?;
+        self.pipelines.insert(key, pipeline);
+        Ok(pipeline)
+    }
+}
+
+/// Covers the shader library too, the generated source includes it
+fn source_key(source: &str) -> DerivedDataKey {
+    let mut parts = vec![source.as_bytes()];
+    for library_source in shader_library::library_sources() {
+        parts.push(library_source);
+    }
+    DerivedDataKey::new(
+        ShaderGraphCompiler::IMPORTER,
+        ShaderGraphCompiler::VERSION,
+        &parts,
+    )
+}
+
+fn compile_cached(
+    compiler: &shaderc::Compiler,
+    derived_data: Option<&DerivedDataCache>,
+    key: &DerivedDataKey,
+    name: &str,
+    source: &str,
+) -> anyhow::Result<Vec<u32>> {
+    let Some(derived_data) = derived_data else {
+        return compile(compiler, name, source);
+    };
+
+    let bytes = derived_data.get_or_build(key, "spv", || {
+        Ok(compile(compiler, name, source)?
+            .iter()
+            .flat_map(|word| word.to_ne_bytes())
+            .collect())
+    })?;
+    code_from_bytes(&bytes).with_context(|| format!("Cached spirv for {} is corrupt", name))
+}
+
+fn compile(compiler: &shaderc::Compiler, name: &str, source: &str) -> anyhow::Result<Vec<u32>> {
+    let mut options =
+        shaderc::CompileOptions::new().context("Failed to create shader compiler options")?;
+    options.set_source_language(shaderc::SourceLanguage::GLSL);
+    options.set_target_env(
+        shaderc::TargetEnv::Vulkan,
+        shaderc::EnvVersion::Vulkan1_2 as u32,
+    );
+    options.set_optimization_level(shaderc::OptimizationLevel::Performance);
+    shader_library::set_include_callback(&mut options);
+
+    let compilation_artifact = compiler
+        .compile_into_spirv(
+            source,
+            shaderc::ShaderKind::Fragment,
+            &format!("{}.frag", name),
+            "main",
+            Some(&options),
+        )
+        .with_context(|| format!("Failed to compile shader graph {}", name))?;
+    Ok(compilation_artifact.as_binary().to_vec())
+}
+
+fn code_from_bytes(bytes: &[u8]) -> Option<Vec<u32>> {
+    if !bytes.len().is_multiple_of(4) {
+        return None;
+    }
+
+    Some(
+        bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_ne_bytes([word[0], word[1], word[2], word[3]]))
+            .collect(),
+    )
+}
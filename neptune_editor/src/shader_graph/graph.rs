@@ -0,0 +1,239 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::path::Path;
+
+const MESH_GRAPH_TEMPLATE: &str = include_str!("../../resource/shader_graph/mesh_graph.glsl");
+const GRAPH_BODY_MARKER: &str = "// GRAPH_BODY";
+
+/// Index into `ShaderGraph::nodes`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct NodeId(pub usize);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MaterialParameterInput {
+    BaseColor,
+    EmissiveColor,
+    /// x: metallic, y: roughness
+    MetallicRoughness,
+}
+
+/// Same order as `material_textures` in the graph template
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum TextureSlot {
+    BaseColor,
+    MetallicRoughness,
+    Normal,
+    Occlusion,
+    Emissive,
+}
+
+impl TextureSlot {
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MathOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Min,
+    Max,
+    Power,
+}
+
+/// Every node outputs a vec4, narrower values are padded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ShaderNode {
+    Constant {
+        value: [f32; 4],
+    },
+    MaterialParameter {
+        parameter: MaterialParameterInput,
+    },
+    VertexColor,
+    TexCoord {
+        channel: u32,
+    },
+    TextureSample {
+        slot: TextureSlot,
+        /// Uses uv channel 0 when unset
+        uv: Option<NodeId>,
+    },
+    Math {
+        op: MathOp,
+        a: NodeId,
+        b: NodeId,
+    },
+    Mix {
+        a: NodeId,
+        b: NodeId,
+        factor: NodeId,
+    },
+    Saturate {
+        input: NodeId,
+    },
+}
+
+impl ShaderNode {
+    fn inputs(&self) -> Vec<NodeId> {
+        match self {
+            ShaderNode::Constant { .. }
+            | ShaderNode::MaterialParameter { .. }
+            | ShaderNode::VertexColor
+            | ShaderNode::TexCoord { .. } => vec![],
+            ShaderNode::TextureSample { uv, .. } => uv.iter().copied().collect(),
+            ShaderNode::Math { a, b, .. } => vec![*a, *b],
+            ShaderNode::Mix { a, b, factor } => vec![*a, *b, *factor],
+            ShaderNode::Saturate { input } => vec![*input],
+        }
+    }
+
+    fn glsl_expression(&self) -> anyhow::Result<String> {
+        Ok(match self {
+            ShaderNode::Constant { value } => format!(
+                "vec4({:?}, {:?}, {:?}, {:?})",
+                value[0], value[1], value[2], value[3]
+            ),
+            ShaderNode::MaterialParameter { parameter } => {
+                let field = match parameter {
+                    MaterialParameterInput::BaseColor => "base_color",
+                    MaterialParameterInput::EmissiveColor => "emissive_color",
+                    MaterialParameterInput::MetallicRoughness => "metallic_roughness",
+                };
+                format!("get_material().{}", field)
+            }
+            ShaderNode::VertexColor => "frag_color".to_string(),
+            ShaderNode::TexCoord { channel } => match channel {
+                0 => "vec4(frag_uv1, 0.0, 0.0)".to_string(),
+                1 => "vec4(frag_uv2, 0.0, 0.0)".to_string(),
+                _ => anyhow::bail!("TexCoord channel {} doesn't exist", channel),
+            },
+            ShaderNode::TextureSample { slot, uv } => format!(
+                "sample_image(push_constants.material_textures[{}], push_constants.material_sampler, {})",
+                slot.index(),
+                uv.map(|uv| format!("{}.xy", node_variable(uv)))
+                    .unwrap_or_else(|| "frag_uv1".to_string())
+            ),
+            ShaderNode::Math { op, a, b } => {
+                let (a, b) = (node_variable(*a), node_variable(*b));
+                match op {
+                    MathOp::Add => format!("{} + {}", a, b),
+                    MathOp::Subtract => format!("{} - {}", a, b),
+                    MathOp::Multiply => format!("{} * {}", a, b),
+                    MathOp::Divide => format!("{} / {}", a, b),
+                    MathOp::Min => format!("min({}, {})", a, b),
+                    MathOp::Max => format!("max({}, {})", a, b),
+                    MathOp::Power => format!("pow({}, {})", a, b),
+                }
+            }
+            ShaderNode::Mix { a, b, factor } => format!(
+                "mix({}, {}, {})",
+                node_variable(*a),
+                node_variable(*b),
+                node_variable(*factor)
+            ),
+            ShaderNode::Saturate { input } => {
+                format!("clamp({}, vec4(0.0), vec4(1.0))", node_variable(*input))
+            }
+        })
+    }
+}
+
+/// Inputs to the BSDF output node, unset inputs keep the template defaults
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BsdfOutputNode {
+    pub base_color: Option<NodeId>,
+    pub emissive: Option<NodeId>,
+    pub metallic_roughness: Option<NodeId>,
+}
+
+/// Node based material description, generated into a fragment shader for the mesh pipeline
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShaderGraph {
+    /// Nodes may only reference nodes before them, so the list is always in evaluation order
+    pub nodes: Vec<ShaderNode>,
+    pub output: BsdfOutputNode,
+}
+
+impl ShaderGraph {
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file_content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read shader graph {}", path.display()))?;
+        toml::from_str(&file_content)
+            .with_context(|| format!("Failed to parse shader graph {}", path.display()))
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        for (index, node) in self.nodes.iter().enumerate() {
+            for input in node.inputs() {
+                if input.0 >= index {
+                    anyhow::bail!(
+                        "Node {} ({:?}) references node {} which isn't before it",
+                        index,
+                        node,
+                        input.0
+                    );
+                }
+            }
+        }
+
+        for output in [
+            self.output.base_color,
+            self.output.emissive,
+            self.output.metallic_roughness,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if output.0 >= self.nodes.len() {
+                anyhow::bail!("Output references missing node {}", output.0);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Generates the full glsl fragment shader source for this graph
+    pub fn generate_glsl(&self) -> anyhow::Result<String> {
+        self.validate()?;
+
+        let mut body = String::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            writeln!(
+                body,
+                "    vec4 {} = {};",
+                node_variable(NodeId(index)),
+                node.glsl_expression()?
+            )?;
+        }
+
+        if let Some(base_color) = self.output.base_color {
+            writeln!(body, "    bsdf.base_color = {};", node_variable(base_color))?;
+        }
+        if let Some(emissive) = self.output.emissive {
+            writeln!(body, "    bsdf.emissive = {}.rgb;", node_variable(emissive))?;
+        }
+        if let Some(metallic_roughness) = self.output.metallic_roughness {
+            writeln!(
+                body,
+                "    bsdf.metallic_roughness = {}.xy;",
+                node_variable(metallic_roughness)
+            )?;
+        }
+
+        Ok(MESH_GRAPH_TEMPLATE.replace(GRAPH_BODY_MARKER, &body))
+    }
+}
+
+fn node_variable(node: NodeId) -> String {
+    format!("node_{}", node.0)
+}
@@ -0,0 +1,74 @@
+use std::path::Path;
+
+/// Shared glsl shipped with the editor, included with `#include <neptune/view.glsl>`.
+/// Lives next to the built in shaders so build.rs resolves the same includes
+const LIBRARY: &[(&str, &str)] = &[
+    (
+        "neptune/bindless.glsl",
+        include_str!("../resource/shader/neptune/bindless.glsl"),
+    ),
+    (
+        "neptune/brdf.glsl",
+        include_str!("../resource/shader/neptune/brdf.glsl"),
+    ),
+    (
+        "neptune/draw.glsl",
+        include_str!("../resource/shader/neptune/draw.glsl"),
+    ),
+    (
+        "neptune/tonemap.glsl",
+        include_str!("../resource/shader/neptune/tonemap.glsl"),
+    ),
+    (
+        "neptune/view.glsl",
+        include_str!("../resource/shader/neptune/view.glsl"),
+    ),
+];
+
+pub fn library_source(name: &str) -> Option<&'static str> {
+    LIBRARY
+        .iter()
+        .find(|(library_name, _)| *library_name == name)
+        .map(|(_, source)| *source)
+}
+
+/// Every library file, for cache keys of shaders that may include them
+pub fn library_sources() -> impl Iterator<Item = &'static [u8]> {
+    LIBRARY.iter().map(|(_, source)| source.as_bytes())
+}
+
+/// Resolves `#include <...>` from the library and `#include "..."` relative to the including file,
+/// which is either another library file or a shader on disk
+pub fn set_include_callback(options: &mut shaderc::CompileOptions) {
+    options.set_include_callback(|include_path, include_type, source_name, _depth| {
+        let relative_path = match include_type {
+            shaderc::IncludeType::Relative => Path::new(source_name)
+                .parent()
+                .map(|directory| directory.join(include_path)),
+            shaderc::IncludeType::Standard => None,
+        };
+
+        if let Some(relative_path) = relative_path {
+            let relative_name = relative_path.to_string_lossy().replace('\\', "/");
+            if let Some(content) = library_source(&relative_name) {
+                return Ok(shaderc::ResolvedInclude {
+                    resolved_name: relative_name,
+                    content: content.to_string(),
+                });
+            }
+            if let Ok(content) = std::fs::read_to_string(&relative_path) {
+                return Ok(shaderc::ResolvedInclude {
+                    resolved_name: relative_name,
+                    content,
+                });
+            }
+        }
+
+        library_source(include_path)
+            .map(|content| shaderc::ResolvedInclude {
+                resolved_name: include_path.to_string(),
+                content: content.to_string(),
+            })
+            .ok_or_else(|| format!("Unknown shader include {}", include_path))
+    });
+}
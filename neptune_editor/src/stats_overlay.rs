@@ -0,0 +1,401 @@
+use crate::scene::sprite_renderer::{Sprite, SpriteRenderer};
+use crate::scene_loader::LoadProgress;
+use glam::{Vec2, Vec3, Vec4};
+use neptune_vulkan::{DefragmentStats, FrameProfile, FrameStats};
+
+/// Per-pass bar graph of the last completed frame, drawn with sprites since there is no text rendering yet.
+/// The exact numbers and pass names are logged once a second while it's visible
+#[derive(Default)]
+pub struct StatsOverlay {
+    pub visible: bool,
+    log_timer: f32,
+}
+
+impl StatsOverlay {
+    const LOG_INTERVAL: f32 = 1.0;
+    /// Bars are scaled so a full bar is a 60hz frame
+    const FRAME_BUDGET_MS: f32 = 1000.0 / 60.0;
+    const LAYER: i32 = 1000;
+
+    const MARGIN: f32 = 8.0;
+    const BAR_WIDTH: f32 = 256.0;
+    const BAR_HEIGHT: f32 = 10.0;
+    const ROW_HEIGHT: f32 = 16.0;
+    const MAX_WARNING_TICKS: u64 = 32;
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.log_timer = 0.0;
+    }
+
+    pub fn update(
+        &mut self,
+        delta_time: f32,
+        profile: Option<&FrameProfile>,
+        stats: &FrameStats,
+        defragment_stats: &DefragmentStats,
+    ) {
+        if !self.visible {
+            return;
+        }
+
+        self.log_timer -= delta_time;
+        if self.log_timer <= 0.0 {
+            self.log_timer = Self::LOG_INTERVAL;
+            if let Some(profile) = profile {
+                log_profile(profile);
+            }
+            log_stats(stats);
+            log_defragment_stats(defragment_stats);
+        }
+    }
+
+    /// Rows from the top: one per pass, swapchain latency, transient memory against its watermark, validation warnings, then sync warnings
+    pub fn draw(
+        &self,
+        profile: Option<&FrameProfile>,
+        stats: &FrameStats,
+        sprite_renderer: &mut SpriteRenderer,
+    ) {
+        let Some(profile) = profile.filter(|_| self.visible) else {
+            return;
+        };
+
+        let row_count = profile.passes.len() + 4;
+        let panel_size = Vec2::new(
+            Self::BAR_WIDTH + Self::MARGIN * 2.0,
+            row_count as f32 * Self::ROW_HEIGHT + Self::MARGIN * 2.0,
+        );
+        draw_rect(
+            sprite_renderer,
+            Vec2::ZERO,
+            panel_size,
+            Vec4::new(0.0, 0.0, 0.0, 0.6),
+            0,
+        );
+
+        let total_triangles = profile.triangle_count().max(1) as f32;
+        let mut row_position = Vec2::splat(Self::MARGIN);
+        for pass in profile.passes.iter() {
+            let time_ms = pass
+                .gpu_time
+                .map(|time| time.as_secs_f32() * 1000.0)
+                .unwrap_or_default();
+            let fraction = time_ms / Self::FRAME_BUDGET_MS;
+
+            //Green to red as the pass takes up more of the frame
+            let color = Vec4::new(fraction.min(1.0), 1.0 - fraction.min(1.0), 0.2, 1.0);
+            self.draw_bar(sprite_renderer, row_position, fraction, color);
+
+            //Thin bar under each pass for its share of the frame's triangles
+            draw_rect(
+                sprite_renderer,
+                row_position + Vec2::new(0.0, Self::BAR_HEIGHT),
+                Vec2::new(
+                    Self::BAR_WIDTH * pass.triangle_count as f32 / total_triangles,
+                    2.0,
+                ),
+                Vec4::new(0.8, 0.8, 0.8, 1.0),
+                1,
+            );
+            row_position.y += Self::ROW_HEIGHT;
+        }
+
+        let latency_ms = profile.swapchain_latency.as_secs_f32() * 1000.0;
+        self.draw_bar(
+            sprite_renderer,
+            row_position,
+            latency_ms / Self::FRAME_BUDGET_MS,
+            Vec4::new(0.2, 0.5, 1.0, 1.0),
+        );
+        row_position.y += Self::ROW_HEIGHT;
+
+        let watermark = profile.transient_memory_watermark.max(1) as f32;
+        self.draw_bar(
+            sprite_renderer,
+            row_position,
+            1.0,
+            Vec4::new(0.4, 0.2, 0.5, 1.0),
+        );
+        draw_rect(
+            sprite_renderer,
+            row_position,
+            Vec2::new(
+                Self::BAR_WIDTH * profile.transient_memory as f32 / watermark,
+                Self::BAR_HEIGHT,
+            ),
+            Vec4::new(0.8, 0.4, 1.0, 1.0),
+            2,
+        );
+        row_position.y += Self::ROW_HEIGHT;
+
+        //One tick per warning, the exact count is in the logged stats
+        self.draw_ticks(
+            sprite_renderer,
+            row_position,
+            stats.validation_warnings,
+            Vec4::new(1.0, 0.15, 0.1, 1.0),
+        );
+        row_position.y += Self::ROW_HEIGHT;
+
+        self.draw_ticks(
+            sprite_renderer,
+            row_position,
+            stats.sync_warnings.len() as u64,
+            Vec4::new(1.0, 0.6, 0.1, 1.0),
+        );
+    }
+
+    fn draw_ticks(
+        &self,
+        sprite_renderer: &mut SpriteRenderer,
+        position: Vec2,
+        count: u64,
+        color: Vec4,
+    ) {
+        let tick_width = Self::BAR_WIDTH / Self::MAX_WARNING_TICKS as f32;
+        for i in 0..count.min(Self::MAX_WARNING_TICKS) {
+            draw_rect(
+                sprite_renderer,
+                position + Vec2::new(i as f32 * tick_width, 0.0),
+                Vec2::new(tick_width - 2.0, Self::BAR_HEIGHT),
+                color,
+                1,
+            );
+        }
+    }
+
+    fn draw_bar(
+        &self,
+        sprite_renderer: &mut SpriteRenderer,
+        position: Vec2,
+        fraction: f32,
+        color: Vec4,
+    ) {
+        //Bars past the budget are clamped and get a white marker at the end
+        draw_rect(
+            sprite_renderer,
+            position,
+            Vec2::new(Self::BAR_WIDTH * fraction.clamp(0.0, 1.0), Self::BAR_HEIGHT),
+            color,
+            1,
+        );
+        if fraction > 1.0 {
+            draw_rect(
+                sprite_renderer,
+                position + Vec2::new(Self::BAR_WIDTH - 2.0, 0.0),
+                Vec2::new(2.0, Self::BAR_HEIGHT),
+                Vec4::ONE,
+                2,
+            );
+        }
+    }
+}
+
+/// `position` is the top left corner in pixels
+/// Thin bar along the top of the screen for background work like shader precompiles
+pub fn draw_progress_bar(
+    sprite_renderer: &mut SpriteRenderer,
+    surface_size: [u32; 2],
+    fraction: f32,
+) {
+    const HEIGHT: f32 = 4.0;
+    let width = surface_size[0] as f32;
+    draw_rect(
+        sprite_renderer,
+        Vec2::ZERO,
+        Vec2::new(width, HEIGHT),
+        Vec4::new(0.0, 0.0, 0.0, 0.6),
+        0,
+    );
+    draw_rect(
+        sprite_renderer,
+        Vec2::ZERO,
+        Vec2::new(width * fraction.clamp(0.0, 1.0), HEIGHT),
+        Vec4::new(0.2, 0.6, 1.0, 1.0),
+        1,
+    );
+}
+
+/// Dims the view while a scene loads, with a bar each for meshes, textures and pipelines above one for the whole load
+pub fn draw_loading_screen(
+    sprite_renderer: &mut SpriteRenderer,
+    surface_size: [u32; 2],
+    progress: &LoadProgress,
+) {
+    const BAR_WIDTH: f32 = 320.0;
+    const BAR_HEIGHT: f32 = 8.0;
+    const ROW_HEIGHT: f32 = 20.0;
+
+    let screen_size = Vec2::new(surface_size[0] as f32, surface_size[1] as f32);
+    draw_rect(
+        sprite_renderer,
+        Vec2::ZERO,
+        screen_size,
+        Vec4::new(0.0, 0.0, 0.0, 0.85),
+        0,
+    );
+
+    let fraction = |(done, total): (usize, usize)| {
+        if total == 0 {
+            1.0
+        } else {
+            done as f32 / total as f32
+        }
+    };
+    let rows = [
+        (fraction(progress.meshes), Vec4::new(0.3, 0.8, 0.4, 1.0)),
+        (fraction(progress.textures), Vec4::new(0.9, 0.6, 0.2, 1.0)),
+        (fraction(progress.pipelines), Vec4::new(0.7, 0.4, 0.9, 1.0)),
+        (progress.fraction(), Vec4::new(0.2, 0.6, 1.0, 1.0)),
+    ];
+    let top_left = (screen_size - Vec2::new(BAR_WIDTH, rows.len() as f32 * ROW_HEIGHT)) * 0.5;
+    for (row, (fraction, color)) in rows.iter().enumerate() {
+        let position = top_left + Vec2::new(0.0, row as f32 * ROW_HEIGHT);
+        draw_rect(
+            sprite_renderer,
+            position,
+            Vec2::new(BAR_WIDTH, BAR_HEIGHT),
+            Vec4::new(0.2, 0.2, 0.2, 1.0),
+            1,
+        );
+        draw_rect(
+            sprite_renderer,
+            position,
+            Vec2::new(BAR_WIDTH * fraction.clamp(0.0, 1.0), BAR_HEIGHT),
+            *color,
+            2,
+        );
+    }
+}
+
+fn draw_rect(
+    sprite_renderer: &mut SpriteRenderer,
+    position: Vec2,
+    size: Vec2,
+    color: Vec4,
+    layer: i32,
+) {
+    if size.x <= 0.0 || size.y <= 0.0 {
+        return;
+    }
+
+    sprite_renderer.draw(Sprite {
+        layer: StatsOverlay::LAYER + layer,
+        position: Vec3::new(position.x + size.x * 0.5, position.y + size.y * 0.5, 0.0),
+        size,
+        color,
+        ..Default::default()
+    });
+}
+
+fn log_profile(profile: &FrameProfile) {
+    const BYTES_TO_MEGABYTES: f32 = 1.0 / (1024.0 * 1024.0);
+
+    let gpu_time = match profile.gpu_time() {
+        Some(time) => format!("{:.2}ms", time.as_secs_f32() * 1000.0),
+        None => "unsupported".to_string(),
+    };
+    info!(
+        "Frame Stats: gpu {}, {} draws, {} triangles, swapchain latency {:.2}ms, transient memory {:.1}MB (peak {:.1}MB)",
+        gpu_time,
+        profile.draw_count(),
+        profile.triangle_count(),
+        profile.swapchain_latency.as_secs_f32() * 1000.0,
+        profile.transient_memory as f32 * BYTES_TO_MEGABYTES,
+        profile.transient_memory_watermark as f32 * BYTES_TO_MEGABYTES,
+    );
+    for pass in profile.passes.iter() {
+        info!(
+            "    {}: {:.3}ms, {} draws, {} triangles",
+            pass.name,
+            pass.gpu_time
+                .map(|time| time.as_secs_f32() * 1000.0)
+                .unwrap_or_default(),
+            pass.draw_count,
+            pass.triangle_count,
+        );
+    }
+}
+
+fn log_stats(stats: &FrameStats) {
+    const BYTES_TO_KILOBYTES: f32 = 1.0 / 1024.0;
+
+    info!(
+        "Frame Counts: {} passes, {} draws, {} instances, {} triangles, {} dispatches, {} barriers ({} layout transitions)",
+        stats.passes,
+        stats.draw_calls,
+        stats.instances,
+        stats.triangles,
+        stats.compute_dispatches,
+        stats.barriers,
+        stats.layout_transitions,
+    );
+    info!(
+        "    {} uploads ({:.1}KB{}), {} reads, {} transient buffers, {} transient images",
+        stats.buffer_uploads,
+        stats.buffer_upload_bytes as f32 * BYTES_TO_KILOBYTES,
+        if stats.transfer_queue_upload {
+            ", transfer queue"
+        } else {
+            ""
+        },
+        stats.buffer_reads,
+        stats.transient_buffers,
+        stats.transient_images,
+    );
+    info!(
+        "    graph arena: {} reused, {} allocated",
+        stats.arena_reused, stats.arena_allocations,
+    );
+    if stats.validation_warnings > 0 {
+        info!(
+            "    {} validation warnings since startup",
+            stats.validation_warnings
+        );
+    }
+    for warning in stats.sync_warnings.iter() {
+        warn!("    sync: {}", warning);
+    }
+
+    //Full barrier listing is only useful when tuning a graph, so it's behind debug logging
+    for pass_barriers in stats.pass_barriers.iter() {
+        debug!(
+            "    {}: {} memory, {} buffer, {} image barriers",
+            pass_barriers.pass,
+            pass_barriers.memory_barriers,
+            pass_barriers.buffer_barriers,
+            pass_barriers.image_barriers.len(),
+        );
+        for image_barrier in pass_barriers.image_barriers.iter() {
+            debug!(
+                "        image {}: {:?} -> {:?}{}",
+                image_barrier.image,
+                image_barrier.src,
+                image_barrier.dst,
+                if image_barrier.layout_transition {
+                    ", layout transition"
+                } else {
+                    ""
+                },
+            );
+        }
+    }
+}
+
+fn log_defragment_stats(stats: &DefragmentStats) {
+    const BYTES_TO_MEGABYTES: f32 = 1.0 / (1024.0 * 1024.0);
+
+    if stats.blocks == 0 {
+        return;
+    }
+    info!(
+        "    device memory: {:.1}MB used of {:.1}MB spanned in {} blocks ({:.0}% holes), {} buffers ({:.1}MB) defragmented",
+        stats.used_bytes as f32 * BYTES_TO_MEGABYTES,
+        stats.span_bytes as f32 * BYTES_TO_MEGABYTES,
+        stats.blocks,
+        stats.fragmentation() * 100.0,
+        stats.moved_buffers,
+        stats.moved_bytes as f32 * BYTES_TO_MEGABYTES,
+    );
+}
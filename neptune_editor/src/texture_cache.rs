@@ -0,0 +1,639 @@
+use crate::derived_data::{DerivedDataCache, DerivedDataKey};
+use anyhow::Context;
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::{vk, Device, ImageDescription2D, ImageHandle};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct TextureImportSettings {
+    pub srgb: bool,
+    /// BC1 for opaque textures, BC3 if any pixel has alpha
+    pub compress: bool,
+    pub generate_mips: bool,
+}
+
+impl Default for TextureImportSettings {
+    fn default() -> Self {
+        Self {
+            srgb: false,
+            compress: true,
+            generate_mips: true,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum HdrTextureFormat {
+    #[default]
+    Rgba16Float,
+    Rgba32Float,
+}
+
+impl HdrTextureFormat {
+    fn vk_format(&self) -> vk::Format {
+        match self {
+            HdrTextureFormat::Rgba16Float => vk::Format::R16G16B16A16_SFLOAT,
+            HdrTextureFormat::Rgba32Float => vk::Format::R32G32B32A32_SFLOAT,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct HdrImportSettings {
+    pub format: HdrTextureFormat,
+    pub generate_mips: bool,
+}
+
+impl HdrImportSettings {
+    /// Radiance rgbe only has 8 bits of mantissa so half floats lose nothing, exr keeps full precision
+    pub fn for_source(format: image::ImageFormat) -> Self {
+        Self {
+            format: match format {
+                image::ImageFormat::OpenExr => HdrTextureFormat::Rgba32Float,
+                _ => HdrTextureFormat::Rgba16Float,
+            },
+            generate_mips: true,
+        }
+    }
+}
+
+/// Gpu ready texture data, mip 0 first
+pub struct ImportedTexture {
+    pub format: vk::Format,
+    pub size: [u32; 2],
+    pub mips: Vec<Vec<u8>>,
+}
+
+impl ImportedTexture {
+    pub fn create_image(&self, device: &mut Device, name: &str) -> anyhow::Result<ImageHandle> {
+        let mip_data: Vec<&[u8]> = self.mips.iter().map(|mip| mip.as_slice()).collect();
+        Ok(device.create_image_init_mips(
+            name,
+            &ImageDescription2D {
+                size: self.size,
+                format: self.format,
+                usage: vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+                mip_levels: self.mips.len() as u32,
+                location: MemoryLocation::GpuOnly,
+            },
+            &mip_data,
+        )?)
+    }
+}
+
+/// Imported textures in the derived data cache, entries are keyed by a hash of the source bytes and import settings
+pub struct TextureCache {
+    derived_data: DerivedDataCache,
+}
+
+impl TextureCache {
+    const IMPORTER: &'static str = "textures";
+    /// Bump whenever the import output changes so stale entries are ignored
+    const VERSION: u32 = 1;
+
+    pub fn new(derived_data: DerivedDataCache) -> Self {
+        Self { derived_data }
+    }
+
+    /// Returns the cached texture for `source_bytes`, `decode` is only called on a cache miss
+    pub fn load(
+        &self,
+        name: &str,
+        source_bytes: &[u8],
+        settings: TextureImportSettings,
+        decode: impl FnOnce() -> anyhow::Result<image::RgbaImage>,
+    ) -> anyhow::Result<ImportedTexture> {
+        let settings_bytes = [
+            settings.srgb as u8,
+            settings.compress as u8,
+            settings.generate_mips as u8,
+        ];
+        self.load_cached(name, &[b"ldr", &settings_bytes, source_bytes], || {
+            Ok(import_texture(&decode()?, settings))
+        })
+    }
+
+    /// Floating point version of `load` for sources `hdr_format` recognizes
+    pub fn load_hdr(
+        &self,
+        name: &str,
+        source_bytes: &[u8],
+        settings: HdrImportSettings,
+    ) -> anyhow::Result<ImportedTexture> {
+        let settings_bytes = [settings.format as u8, settings.generate_mips as u8];
+        self.load_cached(name, &[b"hdr", &settings_bytes, source_bytes], || {
+            Ok(import_hdr_texture(
+                &decode_hdr(name, source_bytes)?,
+                settings,
+            ))
+        })
+    }
+
+    /// Tone mapped srgb version of a hdr source, for displaying hdr images in the editor
+    pub fn load_hdr_preview(
+        &self,
+        name: &str,
+        source_bytes: &[u8],
+        exposure: f32,
+    ) -> anyhow::Result<ImportedTexture> {
+        self.load_cached(
+            name,
+            &[b"hdr_preview", &exposure.to_le_bytes(), source_bytes],
+            || {
+                Ok(import_texture(
+                    &tonemap_preview(&decode_hdr(name, source_bytes)?, exposure),
+                    TextureImportSettings {
+                        srgb: true,
+                        compress: false,
+                        generate_mips: true,
+                    },
+                ))
+            },
+        )
+    }
+
+    fn load_cached(
+        &self,
+        name: &str,
+        key: &[&[u8]],
+        import: impl FnOnce() -> anyhow::Result<ImportedTexture>,
+    ) -> anyhow::Result<ImportedTexture> {
+        let cache_path = self.cache_path(key);
+        match read_dds(&cache_path) {
+            Ok(texture) => return Ok(texture),
+            Err(err) if cache_path.exists() => {
+                warn!("Ignoring bad texture cache entry for {}: {}", name, err)
+            }
+            Err(_) => {}
+        }
+
+        let now = std::time::Instant::now();
+        let texture = import()?;
+        info!(
+            "Imported texture {} in {}s",
+            name,
+            now.elapsed().as_secs_f32()
+        );
+
+        //Failing to write the cache isn't fatal, the texture just gets imported again next time
+        if let Err(err) = self
+            .derived_data
+            .write(&cache_path, |temp_path| write_dds(temp_path, &texture))
+        {
+            warn!("Failed to write texture cache for {}: {}", name, err);
+        }
+
+        Ok(texture)
+    }
+
+    /// Hdr sources are imported as float textures with `HdrImportSettings::for_source` instead of `settings`
+    pub fn load_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        settings: TextureImportSettings,
+    ) -> anyhow::Result<ImportedTexture> {
+        let path = path.as_ref();
+        let source_bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read texture {}", path.display()))?;
+        let name = path.display().to_string();
+        if let Some(format) = hdr_format(&source_bytes) {
+            return self.load_hdr(&name, &source_bytes, HdrImportSettings::for_source(format));
+        }
+        self.load(&name, &source_bytes, settings, || {
+            Ok(image::load_from_memory(&source_bytes)
+                .with_context(|| format!("Failed to decode texture {}", path.display()))?
+                .to_rgba8())
+        })
+    }
+
+    fn cache_path(&self, key: &[&[u8]]) -> PathBuf {
+        self.derived_data.path(
+            &DerivedDataKey::new(Self::IMPORTER, Self::VERSION, key),
+            "dds",
+        )
+    }
+}
+
+fn import_texture(image: &image::RgbaImage, settings: TextureImportSettings) -> ImportedTexture {
+    let size = [image.width().max(1), image.height().max(1)];
+    let mut mips = vec![(size, image.as_raw().clone())];
+    if settings.generate_mips {
+        while mips.last().map(|(size, _)| size[0] > 1 || size[1] > 1) == Some(true) {
+            let (mip_size, mip_pixels) = mips.last().unwrap();
+            mips.push(downsample(*mip_size, mip_pixels));
+        }
+    }
+
+    let has_alpha = image.pixels().any(|pixel| pixel[3] != 255);
+    let format = match (settings.compress, has_alpha, settings.srgb) {
+        (false, _, false) => vk::Format::R8G8B8A8_UNORM,
+        (false, _, true) => vk::Format::R8G8B8A8_SRGB,
+        (true, false, false) => vk::Format::BC1_RGBA_UNORM_BLOCK,
+        (true, false, true) => vk::Format::BC1_RGBA_SRGB_BLOCK,
+        (true, true, false) => vk::Format::BC3_UNORM_BLOCK,
+        (true, true, true) => vk::Format::BC3_SRGB_BLOCK,
+    };
+
+    ImportedTexture {
+        format,
+        size,
+        mips: mips
+            .into_iter()
+            .map(|(mip_size, mip_pixels)| match settings.compress {
+                false => mip_pixels,
+                true => compress_bc(mip_size, &mip_pixels, has_alpha),
+            })
+            .collect(),
+    }
+}
+
+/// Format of a .hdr or .exr source, None for the formats `load` handles
+pub fn hdr_format(source_bytes: &[u8]) -> Option<image::ImageFormat> {
+    image::guess_format(source_bytes).ok().filter(|format| {
+        matches!(
+            format,
+            image::ImageFormat::Hdr | image::ImageFormat::OpenExr
+        )
+    })
+}
+
+fn decode_hdr(name: &str, source_bytes: &[u8]) -> anyhow::Result<image::Rgba32FImage> {
+    let format = hdr_format(source_bytes).with_context(|| format!("{} isn't a hdr image", name))?;
+    Ok(image::load_from_memory_with_format(source_bytes, format)
+        .with_context(|| format!("Failed to decode texture {}", name))?
+        .to_rgba32f())
+}
+
+fn import_hdr_texture(image: &image::Rgba32FImage, settings: HdrImportSettings) -> ImportedTexture {
+    let size = [image.width().max(1), image.height().max(1)];
+    let mut mips = vec![(size, image.as_raw().clone())];
+    if settings.generate_mips {
+        while mips.last().map(|(size, _)| size[0] > 1 || size[1] > 1) == Some(true) {
+            let (mip_size, mip_pixels) = mips.last().unwrap();
+            mips.push(downsample_float(*mip_size, mip_pixels));
+        }
+    }
+
+    ImportedTexture {
+        format: settings.format.vk_format(),
+        size,
+        mips: mips
+            .into_iter()
+            .map(|(_, mip_pixels)| match settings.format {
+                HdrTextureFormat::Rgba16Float => mip_pixels
+                    .iter()
+                    .flat_map(|value| f32_to_f16(*value).to_le_bytes())
+                    .collect(),
+                HdrTextureFormat::Rgba32Float => mip_pixels
+                    .iter()
+                    .flat_map(|value| value.to_le_bytes())
+                    .collect(),
+            })
+            .collect(),
+    }
+}
+
+/// Reinhard tone mapping followed by the srgb transfer function
+fn tonemap_preview(image: &image::Rgba32FImage, exposure: f32) -> image::RgbaImage {
+    image::RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let pixel = image.get_pixel(x, y);
+        let mut output = [0u8; 4];
+        for channel in 0..3 {
+            let value = (pixel[channel] * exposure).max(0.0);
+            let mapped = value / (1.0 + value);
+            let encoded = if mapped <= 0.0031308 {
+                mapped * 12.92
+            } else {
+                1.055 * mapped.powf(1.0 / 2.4) - 0.055
+            };
+            output[channel] = (encoded.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+        output[3] = (pixel[3].clamp(0.0, 1.0) * 255.0).round() as u8;
+        image::Rgba(output)
+    })
+}
+
+/// Round to nearest even, values too large for a half become infinity
+pub(crate) fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x7FFFFF;
+
+    if exponent == 0xFF {
+        //Inf or NaN, keep NaN as a quiet NaN
+        return sign | 0x7C00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1F {
+        return sign | 0x7C00;
+    }
+
+    if half_exponent <= 0 {
+        //Subnormal half or zero
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x800000;
+        let shift = (14 - half_exponent) as u32;
+        let half_mantissa = mantissa >> shift;
+        let remainder = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round_up = remainder > halfway || (remainder == halfway && (half_mantissa & 1) != 0);
+        return sign | (half_mantissa + round_up as u32) as u16;
+    }
+
+    let half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    let remainder = mantissa & 0x1FFF;
+    let round_up = remainder > 0x1000 || (remainder == 0x1000 && (half & 1) != 0);
+    //Rounding can carry into the exponent, which correctly rounds up to the next power of two or infinity
+    sign | (half + round_up as u32) as u16
+}
+
+fn downsample_float(size: [u32; 2], pixels: &[f32]) -> ([u32; 2], Vec<f32>) {
+    let new_size = size.map(|size| (size / 2).max(1));
+    let mut new_pixels = Vec::with_capacity((new_size[0] * new_size[1] * 4) as usize);
+    for y in 0..new_size[1] {
+        for x in 0..new_size[0] {
+            let mut sum = [0.0f32; 4];
+            for (sample_x, sample_y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let source_x = (x * 2 + sample_x).min(size[0] - 1);
+                let source_y = (y * 2 + sample_y).min(size[1] - 1);
+                let index = ((source_y * size[0] + source_x) * 4) as usize;
+                for (channel, sum) in sum.iter_mut().enumerate() {
+                    *sum += pixels[index + channel];
+                }
+            }
+            new_pixels.extend(sum.map(|sum| sum * 0.25));
+        }
+    }
+    (new_size, new_pixels)
+}
+
+//TODO: srgb textures should be filtered in linear space
+fn downsample(size: [u32; 2], pixels: &[u8]) -> ([u32; 2], Vec<u8>) {
+    let new_size = size.map(|size| (size / 2).max(1));
+    let mut new_pixels = Vec::with_capacity((new_size[0] * new_size[1] * 4) as usize);
+    for y in 0..new_size[1] {
+        for x in 0..new_size[0] {
+            let mut sum = [0u32; 4];
+            for (sample_x, sample_y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let source_x = (x * 2 + sample_x).min(size[0] - 1);
+                let source_y = (y * 2 + sample_y).min(size[1] - 1);
+                let index = ((source_y * size[0] + source_x) * 4) as usize;
+                for (channel, sum) in sum.iter_mut().enumerate() {
+                    *sum += pixels[index + channel] as u32;
+                }
+            }
+            new_pixels.extend(sum.map(|sum| ((sum + 2) / 4) as u8));
+        }
+    }
+    (new_size, new_pixels)
+}
+
+fn compress_bc(size: [u32; 2], pixels: &[u8], alpha: bool) -> Vec<u8> {
+    let blocks = size.map(|size| size.div_ceil(4));
+    let mut output = Vec::with_capacity((blocks[0] * blocks[1] * 16) as usize);
+    for block_y in 0..blocks[1] {
+        for block_x in 0..blocks[0] {
+            //Edge blocks repeat the last row/column
+            let mut block = [[0u8; 4]; 16];
+            for (i, texel) in block.iter_mut().enumerate() {
+                let x = (block_x * 4 + (i as u32 % 4)).min(size[0] - 1);
+                let y = (block_y * 4 + (i as u32 / 4)).min(size[1] - 1);
+                let index = ((y * size[0] + x) * 4) as usize;
+                texel.copy_from_slice(&pixels[index..index + 4]);
+            }
+
+            if alpha {
+                encode_alpha_block(&block, &mut output);
+            }
+            encode_color_block(&block, &mut output);
+        }
+    }
+    output
+}
+
+/// BC1 color block using the bounding box of the block's colors as endpoints
+fn encode_color_block(block: &[[u8; 4]; 16], output: &mut Vec<u8>) {
+    let mut min = [255u8; 3];
+    let mut max = [0u8; 3];
+    for texel in block {
+        for channel in 0..3 {
+            min[channel] = min[channel].min(texel[channel]);
+            max[channel] = max[channel].max(texel[channel]);
+        }
+    }
+
+    //Inset the box slightly to reduce the error from outliers
+    for channel in 0..3 {
+        let inset = (max[channel] - min[channel]) / 16;
+        min[channel] += inset;
+        max[channel] -= inset;
+    }
+
+    let mut color0 = to_rgb565(max);
+    let mut color1 = to_rgb565(min);
+    if color0 < color1 {
+        std::mem::swap(&mut color0, &mut color1);
+    }
+
+    let mut indices = 0u32;
+    if color0 != color1 {
+        let endpoint0 = from_rgb565(color0);
+        let endpoint1 = from_rgb565(color1);
+        let palette = [
+            endpoint0,
+            endpoint1,
+            [0, 1, 2].map(|i| (2 * endpoint0[i] + endpoint1[i]) / 3),
+            [0, 1, 2].map(|i| (endpoint0[i] + 2 * endpoint1[i]) / 3),
+        ];
+        for (i, texel) in block.iter().enumerate() {
+            let index = nearest_index(&palette, |color| {
+                (0..3)
+                    .map(|channel| (color[channel] as i32 - texel[channel] as i32).pow(2))
+                    .sum()
+            });
+            indices |= (index as u32) << (i * 2);
+        }
+    }
+
+    output.extend(color0.to_le_bytes());
+    output.extend(color1.to_le_bytes());
+    output.extend(indices.to_le_bytes());
+}
+
+/// BC3 alpha block in 8 value interpolation mode
+fn encode_alpha_block(block: &[[u8; 4]; 16], output: &mut Vec<u8>) {
+    let alpha0 = block.iter().map(|texel| texel[3]).max().unwrap_or(255);
+    let alpha1 = block.iter().map(|texel| texel[3]).min().unwrap_or(255);
+
+    let mut indices = 0u64;
+    if alpha0 != alpha1 {
+        let (alpha0_wide, alpha1_wide) = (alpha0 as u32, alpha1 as u32);
+        let mut palette = [alpha0_wide, alpha1_wide, 0, 0, 0, 0, 0, 0];
+        for (i, value) in palette.iter_mut().enumerate().skip(2) {
+            *value = ((8 - i as u32) * alpha0_wide + (i as u32 - 1) * alpha1_wide) / 7;
+        }
+        for (i, texel) in block.iter().enumerate() {
+            let index = nearest_index(&palette, |value| (*value as i32 - texel[3] as i32).abs());
+            indices |= (index as u64) << (i * 3);
+        }
+    }
+
+    output.push(alpha0);
+    output.push(alpha1);
+    output.extend(&indices.to_le_bytes()[0..6]);
+}
+
+fn nearest_index<T>(palette: &[T], error: impl Fn(&T) -> i32) -> usize {
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, value)| error(value))
+        .map(|(index, _)| index)
+        .unwrap_or_default()
+}
+
+fn to_rgb565(color: [u8; 3]) -> u16 {
+    ((color[0] as u16 >> 3) << 11) | ((color[1] as u16 >> 2) << 5) | (color[2] as u16 >> 3)
+}
+
+fn from_rgb565(color: u16) -> [u32; 3] {
+    let r = ((color >> 11) & 0x1F) as u32;
+    let g = ((color >> 5) & 0x3F) as u32;
+    let b = (color & 0x1F) as u32;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+    ]
+}
+
+const DDS_MAGIC: u32 = 0x20534444;
+const DDS_FOURCC_DX10: u32 = 0x30315844;
+const DDS_HEADER_SIZE: usize = 4 + 124 + 20;
+
+const DDS_FORMATS: [vk::Format; 8] = [
+    vk::Format::R8G8B8A8_UNORM,
+    vk::Format::R8G8B8A8_SRGB,
+    vk::Format::BC1_RGBA_UNORM_BLOCK,
+    vk::Format::BC1_RGBA_SRGB_BLOCK,
+    vk::Format::BC3_UNORM_BLOCK,
+    vk::Format::BC3_SRGB_BLOCK,
+    vk::Format::R16G16B16A16_SFLOAT,
+    vk::Format::R32G32B32A32_SFLOAT,
+];
+
+/// Returns the dxgi format and the size in bytes of a 4x4 block, or of a single pixel for uncompressed formats
+fn dds_format_info(format: vk::Format) -> Option<(u32, usize, bool)> {
+    match format {
+        vk::Format::R32G32B32A32_SFLOAT => Some((2, 16, false)),
+        vk::Format::R16G16B16A16_SFLOAT => Some((10, 8, false)),
+        vk::Format::R8G8B8A8_UNORM => Some((28, 4, false)),
+        vk::Format::R8G8B8A8_SRGB => Some((29, 4, false)),
+        vk::Format::BC1_RGBA_UNORM_BLOCK => Some((71, 8, true)),
+        vk::Format::BC1_RGBA_SRGB_BLOCK => Some((72, 8, true)),
+        vk::Format::BC3_UNORM_BLOCK => Some((77, 16, true)),
+        vk::Format::BC3_SRGB_BLOCK => Some((78, 16, true)),
+        _ => None,
+    }
+}
+
+fn mip_byte_size(format: vk::Format, size: [u32; 2]) -> Option<usize> {
+    let (_, element_size, block_compressed) = dds_format_info(format)?;
+    let elements = match block_compressed {
+        true => size.map(|size| size.div_ceil(4)),
+        false => size,
+    };
+    Some(elements[0] as usize * elements[1] as usize * element_size)
+}
+
+fn write_dds(path: &Path, texture: &ImportedTexture) -> anyhow::Result<()> {
+    let (dxgi_format, _, _) = dds_format_info(texture.format)
+        .with_context(|| format!("Format {:?} can't be written to dds", texture.format))?;
+
+    const DDSD_REQUIRED: u32 = 0x1 | 0x2 | 0x4 | 0x1000;
+    const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+    const DDSD_LINEARSIZE: u32 = 0x80000;
+    const DDPF_FOURCC: u32 = 0x4;
+    const DDSCAPS_COMPLEX: u32 = 0x8;
+    const DDSCAPS_TEXTURE: u32 = 0x1000;
+    const DDSCAPS_MIPMAP: u32 = 0x400000;
+    const D3D10_RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+
+    let mip_count = texture.mips.len() as u32;
+    let mut caps = DDSCAPS_TEXTURE;
+    if mip_count > 1 {
+        caps |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP;
+    }
+
+    let mut header: Vec<u32> = vec![
+        DDS_MAGIC,
+        124,
+        DDSD_REQUIRED | DDSD_MIPMAPCOUNT | DDSD_LINEARSIZE,
+        texture.size[1],
+        texture.size[0],
+        texture
+            .mips
+            .first()
+            .map(|mip| mip.len())
+            .unwrap_or_default() as u32,
+        0,
+        mip_count,
+    ];
+    header.extend([0; 11]);
+    header.extend([32, DDPF_FOURCC, DDS_FOURCC_DX10, 0, 0, 0, 0, 0]);
+    header.extend([caps, 0, 0, 0, 0]);
+    header.extend([dxgi_format, D3D10_RESOURCE_DIMENSION_TEXTURE2D, 0, 1, 0]);
+
+    let mut bytes: Vec<u8> = header
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+    for mip in texture.mips.iter() {
+        bytes.extend_from_slice(mip);
+    }
+    std::fs::write(path, bytes)?;
+    Ok(())
+}
+
+/// Only reads the subset of dds that `write_dds` produces
+fn read_dds(path: &Path) -> anyhow::Result<ImportedTexture> {
+    let bytes = std::fs::read(path)?;
+    anyhow::ensure!(bytes.len() >= DDS_HEADER_SIZE, "File too small");
+
+    let read_u32 = |index: usize| {
+        u32::from_le_bytes([
+            bytes[index * 4],
+            bytes[index * 4 + 1],
+            bytes[index * 4 + 2],
+            bytes[index * 4 + 3],
+        ])
+    };
+    anyhow::ensure!(read_u32(0) == DDS_MAGIC, "Not a dds file");
+    anyhow::ensure!(read_u32(21) == DDS_FOURCC_DX10, "Missing DX10 header");
+
+    let size = [read_u32(4), read_u32(3)];
+    let mip_count = read_u32(7).max(1);
+    let dxgi_format = read_u32(32);
+    let format = DDS_FORMATS
+        .into_iter()
+        .find(|format| dds_format_info(*format).map(|(dxgi, _, _)| dxgi) == Some(dxgi_format))
+        .with_context(|| format!("Unsupported dxgi format {}", dxgi_format))?;
+
+    let mut offset = DDS_HEADER_SIZE;
+    let mut mips = Vec::with_capacity(mip_count as usize);
+    for mip_level in 0..mip_count {
+        let mip_size = size.map(|size| (size >> mip_level).max(1));
+        let mip_bytes = mip_byte_size(format, mip_size).unwrap_or_default();
+        let mip_data = bytes
+            .get(offset..(offset + mip_bytes))
+            .context("File truncated")?;
+        mips.push(mip_data.to_vec());
+        offset += mip_bytes;
+    }
+
+    Ok(ImportedTexture { format, size, mips })
+}
@@ -0,0 +1,168 @@
+use crate::derived_data::DerivedDataCache;
+use crate::scene::sprite_renderer::{Sprite, SpriteRenderer, TextureAtlas};
+use crate::texture_cache::{hdr_format, ImportedTexture, TextureCache, TextureImportSettings};
+use anyhow::Context;
+use glam::{Vec2, Vec4};
+use neptune_vulkan::Device;
+use std::path::{Path, PathBuf};
+
+/// Shows a texture asset picked from the search in the bottom right corner.
+/// Hdr sources are tone mapped for display, with an adjustable exposure.
+/// Sprite sheets can be played back as a flipbook by splitting the texture into a grid
+pub struct TexturePreview {
+    texture_cache: TextureCache,
+    path: Option<PathBuf>,
+    exposure: f32,
+    /// Cells per side, 1 shows the whole texture
+    grid: u32,
+    atlas: Option<TextureAtlas>,
+    /// The image is only recreated in `update`
+    dirty: bool,
+}
+
+impl TexturePreview {
+    const LAYER: i32 = 1040;
+    const PANEL_SIZE: f32 = 256.0;
+    const MARGIN: f32 = 8.0;
+    const MAX_GRID: u32 = 8;
+    const FLIPBOOK_FPS: f64 = 12.0;
+
+    pub fn new() -> Self {
+        Self {
+            texture_cache: TextureCache::new(DerivedDataCache::new(
+                DerivedDataCache::DEFAULT_DIRECTORY,
+            )),
+            path: None,
+            exposure: 1.0,
+            grid: 1,
+            atlas: None,
+            dirty: false,
+        }
+    }
+
+    pub fn open(&mut self, path: PathBuf) {
+        info!("Previewing texture {}", path.display());
+        self.path = Some(path);
+        self.exposure = 1.0;
+        self.grid = 1;
+        self.dirty = true;
+    }
+
+    pub fn close(&mut self) {
+        self.path = None;
+        self.dirty = true;
+    }
+
+    /// Doubles the exposure per step, negative steps darken
+    pub fn step_exposure(&mut self, steps: i32) {
+        if self.path.is_none() {
+            return;
+        }
+        self.exposure *= 2f32.powi(steps);
+        self.dirty = true;
+        info!("Texture preview exposure {}", self.exposure);
+    }
+
+    /// Steps through 1x1, 2x2, 4x4 and 8x8 flipbook grids
+    pub fn cycle_grid(&mut self) {
+        let Some(atlas) = &mut self.atlas else {
+            return;
+        };
+        self.grid = if self.grid >= Self::MAX_GRID {
+            1
+        } else {
+            self.grid * 2
+        };
+        *atlas = TextureAtlas::grid(atlas.image, atlas.size, self.grid, self.grid);
+        info!("Texture preview grid {0}x{0}", self.grid);
+    }
+
+    pub fn update(&mut self, device: &mut Device) -> anyhow::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        self.dirty = false;
+
+        if let Some(atlas) = self.atlas.take() {
+            device.destroy_image(atlas.image);
+        }
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        //A texture that can't be loaded just closes the preview
+        let texture = match self.load(path) {
+            Ok(texture) => texture,
+            Err(err) => {
+                warn!("Failed to preview {}: {:#}", path.display(), err);
+                self.path = None;
+                return Ok(());
+            }
+        };
+        let image = texture.create_image(device, "Texture Preview")?;
+        self.atlas = Some(TextureAtlas::grid(
+            image,
+            texture.size,
+            self.grid,
+            self.grid,
+        ));
+        Ok(())
+    }
+
+    fn load(&self, path: &Path) -> anyhow::Result<ImportedTexture> {
+        let source_bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read texture {}", path.display()))?;
+        let name = path.display().to_string();
+        if hdr_format(&source_bytes).is_some() {
+            return self
+                .texture_cache
+                .load_hdr_preview(&name, &source_bytes, self.exposure);
+        }
+        self.texture_cache.load(
+            &name,
+            &source_bytes,
+            TextureImportSettings {
+                srgb: true,
+                compress: false,
+                generate_mips: true,
+            },
+            || {
+                Ok(image::load_from_memory(&source_bytes)
+                    .with_context(|| format!("Failed to decode texture {}", name))?
+                    .to_rgba8())
+            },
+        )
+    }
+
+    /// Fits the texture, or the current flipbook cell, in the panel keeping its aspect ratio
+    pub fn draw(&self, time: f64, surface_size: [u32; 2], sprite_renderer: &mut SpriteRenderer) {
+        let Some(atlas) = &self.atlas else {
+            return;
+        };
+        let cell_count = (self.grid * self.grid) as u64;
+        let cell = (time * Self::FLIPBOOK_FPS) as u64 % cell_count;
+        let Some(sprite) = atlas.sprite(&cell.to_string()) else {
+            return;
+        };
+
+        let size = Vec2::new(atlas.size[0] as f32, atlas.size[1] as f32) / self.grid as f32;
+        let image_size = size * (Self::PANEL_SIZE / size.max_element());
+        let center = Vec2::new(surface_size[0] as f32, surface_size[1] as f32)
+            - Vec2::splat(Self::MARGIN + Self::PANEL_SIZE * 0.5);
+
+        sprite_renderer.draw(Sprite {
+            layer: Self::LAYER,
+            position: center.extend(0.0),
+            size: Vec2::splat(Self::PANEL_SIZE + Self::MARGIN),
+            color: Vec4::new(0.0, 0.0, 0.0, 0.6),
+            ..Default::default()
+        });
+        sprite_renderer.draw(Sprite {
+            layer: Self::LAYER + 1,
+            position: center.extend(0.0),
+            size: image_size,
+            ..sprite
+        });
+    }
+}
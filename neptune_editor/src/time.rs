@@ -0,0 +1,91 @@
+/// Frame timing shared by every simulation system, so pausing or slowing down the game affects
+/// animation, physics and gameplay the same way. Editor cameras and UI should use the unscaled values
+#[derive(Debug, Clone)]
+pub struct Time {
+    /// Multiplier applied to the scaled delta, 0.5 is half speed slow motion
+    pub time_scale: f32,
+    /// Frame times are clamped to this so a hitch doesn't teleport physics objects through walls
+    pub max_delta: f32,
+
+    paused: bool,
+    step_requested: bool,
+
+    delta: f32,
+    unscaled_delta: f32,
+    elapsed: f64,
+    unscaled_elapsed: f64,
+    frame_count: u64,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self {
+            time_scale: 1.0,
+            max_delta: 0.1,
+            paused: false,
+            step_requested: false,
+            delta: 0.0,
+            unscaled_delta: 0.0,
+            elapsed: 0.0,
+            unscaled_elapsed: 0.0,
+            frame_count: 0,
+        }
+    }
+}
+
+impl Time {
+    /// Starts a new frame with the measured wall clock time of the last one
+    pub fn begin_frame(&mut self, frame_time: f32) {
+        self.unscaled_delta = frame_time.clamp(0.0, self.max_delta);
+        self.delta = if !self.paused {
+            self.unscaled_delta * self.time_scale.max(0.0)
+        } else if std::mem::take(&mut self.step_requested) {
+            //Single steps ignore the time scale so they always advance a full frame
+            self.unscaled_delta
+        } else {
+            0.0
+        };
+
+        self.elapsed += self.delta as f64;
+        self.unscaled_elapsed += self.unscaled_delta as f64;
+        self.frame_count += 1;
+    }
+
+    /// Scaled frame time in seconds, 0 while paused
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+
+    pub fn unscaled_delta(&self) -> f32 {
+        self.unscaled_delta
+    }
+
+    /// Scaled seconds since startup
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    pub fn unscaled_elapsed(&self) -> f64 {
+        self.unscaled_elapsed
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.step_requested = false;
+    }
+
+    /// Advances a single frame on the next `begin_frame` while paused
+    pub fn step(&mut self) {
+        if self.paused {
+            self.step_requested = true;
+        }
+    }
+}
@@ -1,6 +1,7 @@
-use glam::{Mat4, Quat, Vec3};
+use glam::{EulerRot, Mat4, Quat, Vec3};
+use slotmap::SlotMap;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Transform {
     pub position: Vec3,
     pub rotation: Quat,
@@ -63,6 +64,44 @@ impl Transform {
         Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.position)
     }
 
+    /// Decomposes a matrix into a transform, any shear in the matrix is lost
+    pub fn decompose(matrix: &Mat4) -> Self {
+        let (scale, rotation, position) = matrix.to_scale_rotation_translation();
+        Self {
+            position,
+            rotation: rotation.normalize(),
+            scale,
+        }
+    }
+
+    pub fn compose(&self) -> Mat4 {
+        self.model_matrix()
+    }
+
+    /// Euler angles in degrees (yaw, pitch, roll), intended for editor gizmos and panels
+    pub fn euler_angles(&self) -> Vec3 {
+        let (yaw, pitch, roll) = self.rotation.to_euler(EulerRot::YXZ);
+        Vec3::new(yaw.to_degrees(), pitch.to_degrees(), roll.to_degrees())
+    }
+
+    pub fn set_euler_angles(&mut self, angles: Vec3) {
+        self.rotation = Quat::from_euler(
+            EulerRot::YXZ,
+            angles.x.to_radians(),
+            angles.y.to_radians(),
+            angles.z.to_radians(),
+        );
+    }
+
+    /// Component wise blend, the rotation takes the shortest path
+    pub fn lerp(&self, other: &Transform, t: f32) -> Transform {
+        Self {
+            position: self.position.lerp(other.position, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+
     pub fn view_matrix(&self) -> Mat4 {
         Mat4::look_to_rh(
             self.position,
@@ -74,11 +113,202 @@ impl Transform {
 
 impl From<Mat4> for Transform {
     fn from(value: Mat4) -> Self {
-        let (scale, rotation, position) = value.to_scale_rotation_translation();
+        Self::decompose(&value)
+    }
+}
+
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct TransformNodeHandle(slotmap::DefaultKey);
+
+struct TransformNode<T> {
+    local_transform: Transform,
+    parent: Option<TransformNodeHandle>,
+    children: Vec<TransformNodeHandle>,
+    /// As of the last `update_transforms`
+    world_matrix: Mat4,
+    /// A dirty node always has a dirty subtree
+    dirty: bool,
+    data: T,
+}
+
+/// Parent/child transforms with cached world matrices, only changed nodes and their children are recomputed
+pub struct TransformHierarchy<T> {
+    nodes: SlotMap<slotmap::DefaultKey, TransformNode<T>>,
+    dirty_nodes: Vec<TransformNodeHandle>,
+}
+
+impl<T> Default for TransformHierarchy<T> {
+    fn default() -> Self {
         Self {
-            position,
-            rotation,
-            scale,
+            nodes: SlotMap::default(),
+            dirty_nodes: Vec::new(),
+        }
+    }
+}
+
+impl<T> TransformHierarchy<T> {
+    /// A missing parent makes the node a root
+    pub fn insert(
+        &mut self,
+        parent: Option<TransformNodeHandle>,
+        local_transform: Transform,
+        data: T,
+    ) -> TransformNodeHandle {
+        let parent = parent.filter(|parent| self.nodes.contains_key(parent.0));
+        let handle = TransformNodeHandle(self.nodes.insert(TransformNode {
+            local_transform,
+            parent,
+            children: Vec::new(),
+            world_matrix: Mat4::IDENTITY,
+            dirty: false,
+            data,
+        }));
+        if let Some(parent) = parent {
+            self.nodes[parent.0].children.push(handle);
+        }
+        self.mark_dirty(handle);
+        handle
+    }
+
+    /// The children become roots, left where they are in world space
+    pub fn remove(&mut self, handle: TransformNodeHandle) -> Option<T> {
+        let node = self.nodes.remove(handle.0)?;
+        if let Some(parent_node) = node.parent.and_then(|parent| self.nodes.get_mut(parent.0)) {
+            parent_node.children.retain(|child| *child != handle);
+        }
+
+        for child in node.children {
+            if let Some(child_node) = self.nodes.get_mut(child.0) {
+                child_node.parent = None;
+                child_node.local_transform = Transform::decompose(
+                    &(node.world_matrix * child_node.local_transform.compose()),
+                );
+            }
+            self.mark_dirty(child);
+        }
+        Some(node.data)
+    }
+
+    /// Only dirties the node if the transform actually changed
+    pub fn set_local_transform(&mut self, handle: TransformNodeHandle, transform: Transform) {
+        if let Some(node) = self.nodes.get_mut(handle.0) {
+            if node.local_transform != transform {
+                node.local_transform = transform;
+                self.mark_dirty(handle);
+            }
+        }
+    }
+
+    /// As of the last `update_transforms`
+    pub fn world_matrix(&self, handle: TransformNodeHandle) -> Option<Mat4> {
+        self.nodes.get(handle.0).map(|node| node.world_matrix)
+    }
+
+    /// Moves the node under `parent` with a new local transform.
+    /// Returns false if either node is missing or `parent` is in the node's own subtree
+    pub fn set_parent(
+        &mut self,
+        handle: TransformNodeHandle,
+        parent: Option<TransformNodeHandle>,
+        local_transform: Transform,
+    ) -> bool {
+        if !self.nodes.contains_key(handle.0) {
+            return false;
+        }
+        if let Some(parent) = parent {
+            if self
+                .find(handle, |_, node_handle| node_handle == parent)
+                .is_some()
+            {
+                return false;
+            }
+        }
+
+        let node = &mut self.nodes[handle.0];
+        let old_parent = std::mem::replace(&mut node.parent, parent);
+        node.local_transform = local_transform;
+        if let Some(old_parent_node) = old_parent.and_then(|old| self.nodes.get_mut(old.0)) {
+            old_parent_node.children.retain(|child| *child != handle);
+        }
+        if let Some(parent) = parent {
+            self.nodes[parent.0].children.push(handle);
+        }
+
+        //Forced since the node may already be dirty under its old parent
+        self.nodes[handle.0].dirty = false;
+        self.mark_dirty(handle);
+        true
+    }
+
+    /// Depth first search of the subtree starting at `root`, including `root` itself
+    pub fn find(
+        &self,
+        root: TransformNodeHandle,
+        predicate: impl Fn(&T, TransformNodeHandle) -> bool,
+    ) -> Option<TransformNodeHandle> {
+        let mut stack = vec![root];
+        while let Some(handle) = stack.pop() {
+            let node = self.nodes.get(handle.0)?;
+            if predicate(&node.data, handle) {
+                return Some(handle);
+            }
+            stack.extend(node.children.iter().rev());
+        }
+        None
+    }
+
+    fn mark_dirty(&mut self, handle: TransformNodeHandle) {
+        match self.nodes.get(handle.0) {
+            Some(node) if !node.dirty => self.dirty_nodes.push(handle),
+            _ => return,
+        }
+
+        let mut stack = vec![handle];
+        while let Some(node_handle) = stack.pop() {
+            if let Some(node) = self.nodes.get_mut(node_handle.0) {
+                if !std::mem::replace(&mut node.dirty, true) {
+                    stack.extend_from_slice(&node.children);
+                }
+            }
+        }
+    }
+
+    /// Recomputes the world matrices of changed nodes and their children only, `changed` is called with each one
+    pub fn update_transforms(&mut self, mut changed: impl FnMut(&T, &Mat4)) {
+        let mut stack = Vec::new();
+        for dirty_handle in std::mem::take(&mut self.dirty_nodes) {
+            let still_dirty = self
+                .nodes
+                .get(dirty_handle.0)
+                .map(|node| node.dirty)
+                .unwrap_or(false);
+            if !still_dirty {
+                continue;
+            }
+
+            //Start from the top most dirty ancestor so parents are always resolved first
+            let mut top_handle = dirty_handle;
+            while let Some(parent) = self.nodes[top_handle.0].parent {
+                if !self.nodes[parent.0].dirty {
+                    break;
+                }
+                top_handle = parent;
+            }
+
+            let parent_matrix = self.nodes[top_handle.0]
+                .parent
+                .map(|parent| self.nodes[parent.0].world_matrix)
+                .unwrap_or(Mat4::IDENTITY);
+
+            stack.push((top_handle, parent_matrix));
+            while let Some((node_handle, parent_matrix)) = stack.pop() {
+                let node = &mut self.nodes[node_handle.0];
+                node.world_matrix = parent_matrix * node.local_transform.model_matrix();
+                node.dirty = false;
+                changed(&node.data, &node.world_matrix);
+                let world_matrix = node.world_matrix;
+                stack.extend(node.children.iter().map(|child| (*child, world_matrix)));
+            }
         }
     }
 }
@@ -0,0 +1,101 @@
+use crate::mesh::BoundingBox;
+use glam::{BVec3, Mat4, Vec3};
+
+struct PendingUpload<T> {
+    item: T,
+    size: usize,
+    bounds: Option<BoundingBox>,
+}
+
+/// Queues streamed in assets and hands back a frame's worth at a time, so a big scene spreads its uploads over
+/// several frames instead of hitching. Assets the camera can see go first, then the nearest ones
+pub struct UploadScheduler<T> {
+    /// Bytes handed out per frame
+    budget: usize,
+    pending: Vec<PendingUpload<T>>,
+}
+
+impl<T> UploadScheduler<T> {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            pending: Vec::new(),
+        }
+    }
+
+    /// `bounds` is where the asset ends up in the world, None for assets nothing places (they go last)
+    pub fn push(&mut self, item: T, size: usize, bounds: Option<BoundingBox>) {
+        self.pending.push(PendingUpload { item, size, bounds });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Highest priority uploads that fit in the budget. Always at least one, so an upload bigger than the budget
+    /// still goes through on its own
+    pub fn next_frame(&mut self, camera_position: Vec3, view_projection: &Mat4) -> Vec<T> {
+        //Reversed so the highest priority is popped off the end
+        self.pending.sort_by(|a, b| {
+            priority(b.bounds.as_ref(), camera_position, view_projection).total_cmp(&priority(
+                a.bounds.as_ref(),
+                camera_position,
+                view_projection,
+            ))
+        });
+
+        let mut uploads = Vec::new();
+        let mut used = 0;
+        while let Some(upload) = self.pending.last() {
+            if used != 0 && used + upload.size > self.budget {
+                break;
+            }
+            used += upload.size;
+            uploads.push(self.pending.pop().unwrap().item);
+        }
+        uploads
+    }
+}
+
+/// Lower is sooner, visible assets are ordered by distance ahead of everything off screen
+fn priority(bounds: Option<&BoundingBox>, camera_position: Vec3, view_projection: &Mat4) -> f32 {
+    //Keeps every off screen asset behind every visible one
+    const OFF_SCREEN_PENALTY: f32 = 1.0e12;
+
+    let Some(bounds) = bounds else {
+        return f32::MAX;
+    };
+    let distance = bounds.distance_squared(camera_position);
+    if in_view(bounds, view_projection) {
+        distance
+    } else {
+        distance + OFF_SCREEN_PENALTY
+    }
+}
+
+/// Conservative, the box is only off screen if all its corners are outside the same side of the view
+fn in_view(bounds: &BoundingBox, view_projection: &Mat4) -> bool {
+    let corners = (0..8).map(|i| {
+        let corner = Vec3::select(
+            BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0),
+            bounds.max,
+            bounds.min,
+        );
+        *view_projection * corner.extend(1.0)
+    });
+
+    let mut outside = [true; 5];
+    for clip in corners {
+        let sides = [
+            clip.x < -clip.w,
+            clip.x > clip.w,
+            clip.y < -clip.w,
+            clip.y > clip.w,
+            clip.w <= 0.0,
+        ];
+        for (outside, side) in outside.iter_mut().zip(sides) {
+            *outside &= side;
+        }
+    }
+    !outside.iter().any(|outside| *outside)
+}
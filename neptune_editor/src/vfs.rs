@@ -0,0 +1,57 @@
+use crate::asset_archive::{archive_path, AssetArchive};
+use anyhow::Context;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+/// Where assets are read from, loose files while editing or a packed archive in a shipping build.
+/// Paths are the same either way, e.g. `neptune_editor/resource/NeptuneResources.glb`
+pub enum Vfs {
+    Loose,
+    Archive {
+        /// The directory that was packed, stripped from paths before looking them up in the archive
+        root: PathBuf,
+        archive: AssetArchive,
+    },
+}
+
+impl Vfs {
+    pub const RESOURCE_ROOT: &'static str = "neptune_editor/resource";
+
+    /// Reads from the archive if one is given, otherwise from loose files
+    pub fn new(archive_path: Option<&Path>) -> anyhow::Result<Self> {
+        Ok(match archive_path {
+            Some(archive_path) => {
+                let archive = AssetArchive::open(archive_path)?;
+                info!(
+                    target: "assets",
+                    "Loading assets from {}",
+                    archive_path.display()
+                );
+                Vfs::Archive {
+                    root: PathBuf::from(Self::RESOURCE_ROOT),
+                    archive,
+                }
+            }
+            None => Vfs::Loose,
+        })
+    }
+
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<Cow<'_, [u8]>> {
+        let path = path.as_ref();
+        match self {
+            Vfs::Loose => {
+                Ok(Cow::Owned(std::fs::read(path).with_context(|| {
+                    format!("Failed to read {}", path.display())
+                })?))
+            }
+            Vfs::Archive { root, archive } => archive.read(&Self::archive_path(root, path)?),
+        }
+    }
+
+    fn archive_path(root: &Path, path: &Path) -> anyhow::Result<String> {
+        let relative_path = path
+            .strip_prefix(root)
+            .with_context(|| format!("{} is outside the archive", path.display()))?;
+        archive_path(relative_path).with_context(|| format!("{} isn't valid", path.display()))
+    }
+}
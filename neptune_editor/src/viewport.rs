@@ -0,0 +1,510 @@
+use crate::camera::Camera;
+use crate::scene::scene_renderer::{slice_to_bytes_unsafe, Scene, SceneCamera, SceneRenderer};
+use crate::transform::Transform;
+use anyhow::Context;
+use glam::{Quat, UVec4, Vec3, Vec4};
+use neptune_vulkan::gpu_allocator::MemoryLocation;
+use neptune_vulkan::render_graph::RenderArea;
+use neptune_vulkan::render_graph_builder::{
+    BufferOffset, BufferWriteCallback, RasterDrawCommandBuilder, RasterPassBuilder,
+    RenderGraphBuilderTrait,
+};
+use neptune_vulkan::{
+    vk, BufferHandle, BufferUsage, DepthMode, Device, ImageDescription2D, ImageHandle,
+    RasterPipelineHandle, SamplerDescription, SamplerHandle,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum ViewportLayout {
+    #[default]
+    Single,
+    /// Perspective view in the top left with top, front and side orthographic views in the other corners
+    Quad,
+    /// The main view next to each split view, all drawn straight into their own area of the swapchain
+    Split,
+}
+
+impl ViewportLayout {
+    pub fn next(&self) -> Self {
+        match self {
+            ViewportLayout::Single => ViewportLayout::Quad,
+            ViewportLayout::Quad => ViewportLayout::Split,
+            ViewportLayout::Split => ViewportLayout::Single,
+        }
+    }
+}
+
+/// Locks the main view to a fixed width / height, the rest of its space is filled with letterbox or pillarbox bars
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AspectRatioLock {
+    #[default]
+    Off,
+    /// 16:9
+    Widescreen,
+    /// 4:3
+    Standard,
+    Custom(f32),
+}
+
+impl AspectRatioLock {
+    pub fn ratio(&self) -> Option<f32> {
+        match self {
+            AspectRatioLock::Off => None,
+            AspectRatioLock::Widescreen => Some(16.0 / 9.0),
+            AspectRatioLock::Standard => Some(4.0 / 3.0),
+            AspectRatioLock::Custom(ratio) => Some(*ratio).filter(|ratio| *ratio > 0.0),
+        }
+    }
+
+    /// Cycles through the presets, a custom ratio goes back to off
+    pub fn next(&self) -> Self {
+        match self {
+            AspectRatioLock::Off => AspectRatioLock::Widescreen,
+            AspectRatioLock::Widescreen => AspectRatioLock::Standard,
+            AspectRatioLock::Standard | AspectRatioLock::Custom(_) => AspectRatioLock::Off,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OrthographicView {
+    /// Looking down -Y
+    Top,
+    /// Looking down -Z
+    Front,
+    /// Looking down -X
+    Side,
+}
+
+impl OrthographicView {
+    fn rotation(&self) -> Quat {
+        match self {
+            OrthographicView::Top => Quat::from_rotation_x(90.0f32.to_radians()),
+            OrthographicView::Front => Quat::from_rotation_y(180.0f32.to_radians()),
+            OrthographicView::Side => Quat::from_rotation_y(-90.0f32.to_radians()),
+        }
+    }
+}
+
+pub struct OrthographicViewport {
+    pub view: OrthographicView,
+    pub camera: Camera,
+    pub transform: Transform,
+    scene_camera: SceneCamera,
+    image: Option<(ImageHandle, [u32; 2])>,
+}
+
+impl OrthographicViewport {
+    /// How far back from the focus point the camera sits, must be less than the far clip
+    const VIEW_DISTANCE: f32 = 500.0;
+
+    fn new(
+        device: &mut Device,
+        view: OrthographicView,
+        depth_mode: DepthMode,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            view,
+            camera: Camera::orthographic(20.0, 0.1, Self::VIEW_DISTANCE * 2.0),
+            transform: Transform::with_rotation(view.rotation()),
+            scene_camera: SceneCamera::new(device, depth_mode)?,
+            image: None,
+        })
+    }
+
+    fn update(&mut self, focus: Vec3, size: [u32; 2]) {
+        self.transform.rotation = self.view.rotation();
+        self.transform.position = focus - (self.transform.rotation * Vec3::Z * Self::VIEW_DISTANCE);
+        self.scene_camera
+            .update(&self.camera, &self.transform, size);
+    }
+}
+
+/// Extra perspective camera for split screen, e.g. a second player or a fixed view to compare against
+pub struct SplitView {
+    pub camera: Camera,
+    pub transform: Transform,
+    scene_camera: SceneCamera,
+}
+
+impl SplitView {
+    pub fn new(
+        device: &mut Device,
+        camera: Camera,
+        transform: Transform,
+        depth_mode: DepthMode,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            camera,
+            transform,
+            scene_camera: SceneCamera::new(device, depth_mode)?,
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+struct ViewportLayoutData {
+    /// Pixel offset in xy and size in zw of each viewport
+    rects: [UVec4; 4],
+    border_color: Vec4,
+}
+
+/// Splits the window into multiple scene views, each rendered into its own image and composited into the swapchain
+pub struct Viewports {
+    pub layout: ViewportLayout,
+    pub aspect_lock: AspectRatioLock,
+    pub orthographic_viewports: [OrthographicViewport; 3],
+    pub split_views: Vec<SplitView>,
+
+    output_format: vk::Format,
+    main_image: Option<(ImageHandle, [u32; 2])>,
+    composite_pipeline: RasterPipelineHandle,
+    layout_buffer: BufferHandle,
+    sampler: SamplerHandle,
+}
+
+impl Viewports {
+    const BORDER_SIZE: u32 = 2;
+    const BORDER_COLOR: Vec4 = Vec4::new(0.1, 0.1, 0.1, 1.0);
+
+    pub fn new(
+        device: &mut Device,
+        output_format: vk::Format,
+        depth_mode: DepthMode,
+    ) -> anyhow::Result<Self> {
+        let composite_pipeline =
+            device.create_raster_pipeline(&neptune_vulkan::RasterPipelineDescription {
+                vertex: neptune_vulkan::VertexState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::FULLSCREEN_QUAD_VERT,
+                        entry: "main",
+                    },
+                    layouts: &[],
+                },
+                primitive: neptune_vulkan::PrimitiveState {
+                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+                    cull_mode: vk::CullModeFlags::NONE,
+                },
+                depth_state: None,
+                fragment: Some(neptune_vulkan::FragmentState {
+                    shader: neptune_vulkan::ShaderStage {
+                        code: crate::shader::VIEWPORT_COMPOSITE_FRAG,
+                        entry: "main",
+                    },
+                    targets: &[neptune_vulkan::ColorTargetState {
+                        format: output_format,
+                        blend: None,
+                        write_mask: vk::ColorComponentFlags::RGBA,
+                    }],
+                }),
+            })?;
+
+        let layout_buffer = device
+            .create_buffer_init(
+                "ViewportLayoutBuffer",
+                BufferUsage::STORAGE | BufferUsage::TRANSFER,
+                MemoryLocation::GpuOnly,
+                unsafe { slice_to_bytes_unsafe(&[ViewportLayoutData::default()]) },
+            )
+            .context("Failed to create viewport layout buffer")?;
+
+        Ok(Self {
+            layout: ViewportLayout::default(),
+            aspect_lock: AspectRatioLock::default(),
+            orthographic_viewports: [
+                OrthographicViewport::new(device, OrthographicView::Top, depth_mode)?,
+                OrthographicViewport::new(device, OrthographicView::Front, depth_mode)?,
+                OrthographicViewport::new(device, OrthographicView::Side, depth_mode)?,
+            ],
+            split_views: Vec::new(),
+            output_format,
+            main_image: None,
+            composite_pipeline,
+            layout_buffer,
+            sampler: device.create_sampler("Viewport Sampler", &SamplerDescription::default())?,
+        })
+    }
+
+    /// Pixel offset and size of each viewport, the main view is always first
+    fn rects(&self, surface_size: [u32; 2]) -> Vec<([u32; 2], [u32; 2])> {
+        let mut rects = self.layout_rects(surface_size);
+        if let Some(ratio) = self.aspect_lock.ratio() {
+            rects[0] = fit_aspect_ratio(rects[0], ratio);
+        }
+        rects
+    }
+
+    fn layout_rects(&self, surface_size: [u32; 2]) -> Vec<([u32; 2], [u32; 2])> {
+        match self.layout {
+            ViewportLayout::Single => vec![([0; 2], surface_size)],
+            ViewportLayout::Quad => {
+                let cell_size = [
+                    (surface_size[0] / 2)
+                        .saturating_sub(Self::BORDER_SIZE)
+                        .max(1),
+                    (surface_size[1] / 2)
+                        .saturating_sub(Self::BORDER_SIZE)
+                        .max(1),
+                ];
+                let far_offset = [
+                    surface_size[0] - cell_size[0],
+                    surface_size[1] - cell_size[1],
+                ];
+                vec![
+                    ([0, 0], cell_size),
+                    ([far_offset[0], 0], cell_size),
+                    ([0, far_offset[1]], cell_size),
+                    (far_offset, cell_size),
+                ]
+            }
+            ViewportLayout::Split => {
+                //Side by side for two views, then a grid filled row by row
+                let count = 1 + self.split_views.len() as u32;
+                let columns = (count as f32).sqrt().ceil() as u32;
+                let rows = count.div_ceil(columns);
+                let cell_size = [
+                    (surface_size[0] / columns)
+                        .saturating_sub(Self::BORDER_SIZE)
+                        .max(1),
+                    (surface_size[1] / rows)
+                        .saturating_sub(Self::BORDER_SIZE)
+                        .max(1),
+                ];
+                (0..count)
+                    .map(|index| {
+                        let cell = [index % columns, index / columns];
+                        (
+                            [
+                                cell[0] * (surface_size[0] / columns),
+                                cell[1] * (surface_size[1] / rows),
+                            ],
+                            cell_size,
+                        )
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Size the main camera should use for its aspect ratio
+    pub fn main_view_size(&self, surface_size: [u32; 2]) -> [u32; 2] {
+        self.rects(surface_size)[0].1
+    }
+
+    /// A single unlocked view and split views render straight into the swapchain, anything else is composited
+    fn needs_composite(&self) -> bool {
+        match self.layout {
+            ViewportLayout::Single => self.aspect_lock.ratio().is_some(),
+            ViewportLayout::Quad => true,
+            ViewportLayout::Split => false,
+        }
+    }
+
+    /// Resizes the viewport images and moves the orthographic views to keep `focus` centered
+    pub fn update(
+        &mut self,
+        device: &mut Device,
+        surface_size: [u32; 2],
+        focus: Vec3,
+    ) -> anyhow::Result<()> {
+        if self.layout == ViewportLayout::Split {
+            let rects = self.rects(surface_size);
+            for (view, (_, size)) in self.split_views.iter_mut().zip(&rects[1..]) {
+                view.scene_camera
+                    .update(&view.camera, &view.transform, *size);
+            }
+            return Ok(());
+        }
+
+        if !self.needs_composite() {
+            return Ok(());
+        }
+
+        let rects = self.rects(surface_size);
+        resize_image(device, &mut self.main_image, rects[0].1, self.output_format)?;
+        if self.layout == ViewportLayout::Single {
+            return Ok(());
+        }
+        for (viewport, (_, size)) in self.orthographic_viewports.iter_mut().zip(&rects[1..]) {
+            resize_image(device, &mut viewport.image, *size, self.output_format)?;
+            viewport.update(focus, *size);
+        }
+
+        Ok(())
+    }
+
+    pub fn write_render_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        target_image: ImageHandle,
+        surface_size: [u32; 2],
+        main_camera: &SceneCamera,
+        scene: &Scene,
+        scene_renderer: &mut SceneRenderer,
+        render_graph_builder: &mut T,
+    ) {
+        if self.layout == ViewportLayout::Split {
+            self.write_split_render_passes(
+                target_image,
+                surface_size,
+                main_camera,
+                scene,
+                scene_renderer,
+                render_graph_builder,
+            );
+            return;
+        }
+
+        let (true, Some((main_image, _))) = (self.needs_composite(), self.main_image) else {
+            scene_renderer.write_render_passes(
+                target_image,
+                main_camera,
+                scene,
+                render_graph_builder,
+            );
+            return;
+        };
+
+        scene_renderer.write_render_passes(main_image, main_camera, scene, render_graph_builder);
+
+        let mut viewport_images = vec![main_image];
+        let orthographic_viewports = match self.layout {
+            ViewportLayout::Single | ViewportLayout::Split => &mut self.orthographic_viewports[..0],
+            ViewportLayout::Quad => &mut self.orthographic_viewports[..],
+        };
+        for viewport in orthographic_viewports.iter_mut() {
+            let Some((image, _)) = viewport.image else {
+                continue;
+            };
+            viewport
+                .scene_camera
+                .write_render_passes(render_graph_builder);
+            scene_renderer.write_secondary_view_passes(
+                image,
+                &viewport.scene_camera,
+                scene,
+                render_graph_builder,
+            );
+            viewport_images.push(image);
+        }
+
+        //Letterbox bars around a single view are black, borders between views are grey
+        let border_color = match self.layout {
+            ViewportLayout::Single => Vec4::new(0.0, 0.0, 0.0, 1.0),
+            ViewportLayout::Quad | ViewportLayout::Split => Self::BORDER_COLOR,
+        };
+        let mut layout_data = ViewportLayoutData {
+            border_color,
+            ..Default::default()
+        };
+        for (rect, (offset, size)) in layout_data
+            .rects
+            .iter_mut()
+            .zip(self.rects(surface_size).iter().take(viewport_images.len()))
+        {
+            *rect = UVec4::new(offset[0], offset[1], size[0], size[1]);
+        }
+        render_graph_builder.add_buffer_write(
+            BufferOffset {
+                buffer: self.layout_buffer,
+                offset: 0,
+            },
+            std::mem::size_of::<ViewportLayoutData>(),
+            BufferWriteCallback::new(move |slice| {
+                slice.copy_from_slice(unsafe { slice_to_bytes_unsafe(&[layout_data]) });
+            }),
+        );
+
+        let mut composite_pass_builder = RasterPassBuilder::new("Viewport Composite Pass");
+        composite_pass_builder.add_color_attachment(target_image, None);
+        let mut draw_command_builder = RasterDrawCommandBuilder::new(self.composite_pipeline);
+        draw_command_builder.read_buffer(self.layout_buffer);
+        draw_command_builder.read_sampler(self.sampler);
+        //Shader always expects 4 images, missing views have an empty rect so they are never read
+        for index in 0..4 {
+            draw_command_builder
+                .read_sampled_image(*viewport_images.get(index).unwrap_or(&main_image));
+        }
+        draw_command_builder.draw(0..3, 0..1);
+        draw_command_builder.build(&mut composite_pass_builder);
+        composite_pass_builder.build(render_graph_builder);
+    }
+
+    /// No composite, each view's last pass only draws its own area of the target
+    fn write_split_render_passes<T: RenderGraphBuilderTrait>(
+        &mut self,
+        target_image: ImageHandle,
+        surface_size: [u32; 2],
+        main_camera: &SceneCamera,
+        scene: &Scene,
+        scene_renderer: &mut SceneRenderer,
+        render_graph_builder: &mut T,
+    ) {
+        //Clears the borders, the views are drawn over the rest
+        let mut clear_pass_builder = RasterPassBuilder::new("Split View Clear Pass");
+        clear_pass_builder.add_color_attachment(target_image, Some(Self::BORDER_COLOR.to_array()));
+        clear_pass_builder.build(render_graph_builder);
+
+        for view in self.split_views.iter_mut() {
+            view.scene_camera.write_render_passes(render_graph_builder);
+        }
+
+        let rects = self.rects(surface_size);
+        let views: Vec<(RenderArea, &SceneCamera)> = std::iter::once(main_camera)
+            .chain(self.split_views.iter().map(|view| &view.scene_camera))
+            .zip(rects)
+            .map(|(camera, (offset, size))| (RenderArea { offset, size }, camera))
+            .collect();
+        scene_renderer.write_split_view_passes(target_image, &views, scene, render_graph_builder);
+    }
+}
+
+/// Shrinks the rect to the ratio and centers it, leaving bars on the top and bottom or left and right
+fn fit_aspect_ratio((offset, size): ([u32; 2], [u32; 2]), ratio: f32) -> ([u32; 2], [u32; 2]) {
+    let width = size[0] as f32;
+    let height = size[1] as f32;
+    let fitted_size = if width / height > ratio {
+        [((height * ratio).round() as u32).clamp(1, size[0]), size[1]]
+    } else {
+        [size[0], ((width / ratio).round() as u32).clamp(1, size[1])]
+    };
+    (
+        [
+            offset[0] + (size[0] - fitted_size[0]) / 2,
+            offset[1] + (size[1] - fitted_size[1]) / 2,
+        ],
+        fitted_size,
+    )
+}
+
+fn resize_image(
+    device: &mut Device,
+    image: &mut Option<(ImageHandle, [u32; 2])>,
+    size: [u32; 2],
+    format: vk::Format,
+) -> anyhow::Result<()> {
+    if image.map(|(_, image_size)| image_size) == Some(size) {
+        return Ok(());
+    }
+
+    if let Some((old_image, _)) = image.take() {
+        device.destroy_image(old_image);
+    }
+
+    *image = Some((
+        device.create_image(
+            "Viewport Image",
+            &ImageDescription2D {
+                size,
+                format,
+                usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                mip_levels: 1,
+                location: MemoryLocation::GpuOnly,
+            },
+        )?,
+        size,
+    ));
+    Ok(())
+}
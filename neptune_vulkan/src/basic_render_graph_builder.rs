@@ -1,40 +1,64 @@
+use crate::blackboard::Blackboard;
+use crate::frame_arena::{BufferUsages, ImageUsages};
+use crate::name::NameId;
 use crate::render_graph::{
     BufferBarrier, BufferGraphResource, BufferIndex, BufferRead, BufferResourceDescription,
     BufferWrite, CommandBuffer, CommandBufferDependency, CompiledRenderGraph, Framebuffer,
     ImageBarrier, ImageBarrierSource, ImageGraphResource, ImageIndex, ImageResourceDescription,
-    QueueType, RenderPassCommand,
+    QueueType, RenderArea, RenderPassCommand,
 };
 use crate::render_graph_builder::{
     BufferOffset, ColorAttachment, ComputeDispatch, DepthStencilAttachment, DrawCommandDispatch,
     ImageCopyBuffer, ImageCopyImage, RasterDrawCommand, RenderGraphBuilderTrait,
+    TransferPassBuilder,
 };
 use crate::render_graph_builder::{BufferReadCallback, BufferWriteCallback, ShaderResourceUsage};
 use crate::resource_managers::{BufferResourceAccess, ImageResourceAccess};
 use crate::{
     BufferHandle, BufferUsage, ComputePipelineHandle, ImageHandle, SurfaceHandle,
-    TransientImageDesc,
+    TransientBufferSize, TransientImageDesc,
 };
 use ash::vk;
 use std::collections::HashMap;
 
+/// A readback requested with `Device::read_buffer` or `Device::read_image`, added to the end of the next graph
+pub(crate) enum DeviceRead {
+    Buffer {
+        buffer_offset: BufferOffset,
+        size: usize,
+        callback: BufferReadCallback,
+    },
+    Image {
+        image: ImageHandle,
+        size: [u32; 2],
+        texel_size: usize,
+        callback: BufferReadCallback,
+    },
+}
+
+impl std::fmt::Debug for DeviceRead {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Buffer { buffer_offset, .. } => {
+                write!(f, "DeviceRead::Buffer({:?})", buffer_offset)
+            }
+            Self::Image { image, .. } => write!(f, "DeviceRead::Image({:?})", image),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct BasicRenderGraphBuilder {
     render_graph: CompiledRenderGraph,
     buffer_index_map: HashMap<BufferHandle, BufferIndex>,
     image_index_map: HashMap<ImageHandle, ImageIndex>,
+    blackboard: Blackboard,
+    device_reads: Vec<DeviceRead>,
 }
 
 impl Default for BasicRenderGraphBuilder {
     fn default() -> Self {
-        let render_graph = CompiledRenderGraph {
-            command_buffers: vec![CommandBuffer::default()],
-            ..Default::default()
-        };
-        Self {
-            render_graph,
-            buffer_index_map: Default::default(),
-            image_index_map: Default::default(),
-        }
+        Self::from_recycled(CompiledRenderGraph::default())
     }
 }
 
@@ -67,9 +91,9 @@ impl RenderGraphBuilderTrait for BasicRenderGraphBuilder {
         });
     }
 
-    fn create_transient_buffer(
+    fn create_sized_transient_buffer(
         &mut self,
-        size: usize,
+        size: TransientBufferSize,
         usage: BufferUsage,
         location: gpu_allocator::MemoryLocation,
     ) -> BufferHandle {
@@ -119,7 +143,7 @@ impl RenderGraphBuilderTrait for BasicRenderGraphBuilder {
 
     fn add_transfer_pass(
         &mut self,
-        name: String,
+        name: NameId,
         color: [f32; 4],
         queue: QueueType,
         transfers: &[crate::render_graph_builder::Transfer],
@@ -127,8 +151,7 @@ impl RenderGraphBuilderTrait for BasicRenderGraphBuilder {
         //TODO: queue
         let _ = queue;
 
-        let mut buffer_usages = Vec::new();
-        let mut image_usages = Vec::new();
+        let (mut buffer_usages, mut image_usages) = self.take_usages();
 
         let transfers: Vec<crate::render_graph::Transfer> = transfers
             .iter()
@@ -160,7 +183,7 @@ impl RenderGraphBuilderTrait for BasicRenderGraphBuilder {
                     crate::render_graph::Transfer::BufferToImage {
                         src,
                         dst,
-                        copy_size: *copy_size,
+                        copy_size: [copy_size[0], copy_size[1], 1],
                     }
                 }
                 crate::render_graph_builder::Transfer::CopyImageToBuffer {
@@ -193,6 +216,11 @@ impl RenderGraphBuilderTrait for BasicRenderGraphBuilder {
                         copy_size: *copy_size,
                     }
                 }
+                crate::render_graph_builder::Transfer::GenerateMips { image } => {
+                    let image = self.get_image_index(*image);
+                    image_usages.push((image, ImageResourceAccess::TransferWrite));
+                    crate::render_graph::Transfer::GenerateMips { image }
+                }
             })
             .collect();
 
@@ -203,11 +231,12 @@ impl RenderGraphBuilderTrait for BasicRenderGraphBuilder {
             &image_usages,
             Some(RenderPassCommand::Transfer { transfers }),
         );
+        self.return_usages(buffer_usages, image_usages);
     }
 
     fn add_compute_pass(
         &mut self,
-        name: String,
+        name: NameId,
         color: [f32; 4],
         queue: QueueType,
         pipeline: ComputePipelineHandle,
@@ -217,8 +246,7 @@ impl RenderGraphBuilderTrait for BasicRenderGraphBuilder {
         //TODO: queue
         let _ = queue;
 
-        let mut buffer_usages = Vec::new();
-        let mut image_usages = Vec::new();
+        let (mut buffer_usages, mut image_usages) = self.take_usages();
 
         let resources =
             self.get_shader_resource_access(&mut buffer_usages, &mut image_usages, resources);
@@ -243,18 +271,19 @@ impl RenderGraphBuilderTrait for BasicRenderGraphBuilder {
                 dispatch,
             }),
         );
+        self.return_usages(buffer_usages, image_usages);
     }
 
     fn add_raster_pass(
         &mut self,
-        name: String,
+        name: NameId,
         color: [f32; 4],
         color_attachments: &[ColorAttachment],
         depth_stencil_attachment: Option<DepthStencilAttachment>,
+        render_area: Option<RenderArea>,
         raster_draw_commands: &[RasterDrawCommand],
     ) {
-        let mut buffer_usages = Vec::new();
-        let mut image_usages = Vec::new();
+        let (mut buffer_usages, mut image_usages) = self.take_usages();
 
         let raster_command = RenderPassCommand::Raster {
             framebuffer: Framebuffer {
@@ -277,6 +306,7 @@ impl RenderGraphBuilderTrait for BasicRenderGraphBuilder {
                         clear: attachment.clear,
                     }
                 }),
+                render_area,
             },
             draw_commands: self.get_raster_draw_commands(
                 &mut buffer_usages,
@@ -286,9 +316,23 @@ impl RenderGraphBuilderTrait for BasicRenderGraphBuilder {
         };
 
         self.add_render_pass(name, color, &[], &image_usages, Some(raster_command));
+        self.return_usages(buffer_usages, image_usages);
+    }
+
+    fn blackboard(&self) -> &Blackboard {
+        &self.blackboard
+    }
+
+    fn blackboard_mut(&mut self) -> &mut Blackboard {
+        &mut self.blackboard
     }
 
     fn build(mut self) -> CompiledRenderGraph {
+        //Readbacks go last so they see everything else the graph wrote
+        for device_read in std::mem::take(&mut self.device_reads) {
+            self.add_device_read(device_read);
+        }
+
         if let Some(command_buffer) = self.render_graph.command_buffers.get_mut(0) {
             for (swapchain_index, (_, image_index)) in
                 self.render_graph.swapchain_images.iter().enumerate()
@@ -312,74 +356,160 @@ impl RenderGraphBuilderTrait for BasicRenderGraphBuilder {
             }
         }
 
+        //Hand the maps back so the arena can reuse them next frame
+        self.render_graph.storage.buffer_index_map = self.buffer_index_map;
+        self.render_graph.storage.image_index_map = self.image_index_map;
+
         self.render_graph
     }
 }
 
 impl BasicRenderGraphBuilder {
+    /// Builds into a graph from `FrameArena::recycle`, reusing its allocations
+    pub(crate) fn from_recycled(mut render_graph: CompiledRenderGraph) -> Self {
+        if render_graph.command_buffers.is_empty() {
+            render_graph.command_buffers.push(CommandBuffer::default());
+        }
+        let buffer_index_map = std::mem::take(&mut render_graph.storage.buffer_index_map);
+        let image_index_map = std::mem::take(&mut render_graph.storage.image_index_map);
+        Self {
+            render_graph,
+            buffer_index_map,
+            image_index_map,
+            blackboard: Blackboard::default(),
+            device_reads: Vec::new(),
+        }
+    }
+
+    pub(crate) fn add_device_reads(&mut self, device_reads: Vec<DeviceRead>) {
+        self.device_reads.extend(device_reads);
+    }
+
+    fn add_device_read(&mut self, device_read: DeviceRead) {
+        match device_read {
+            DeviceRead::Buffer {
+                buffer_offset,
+                size,
+                callback,
+            } => self.add_buffer_read(buffer_offset, size, callback),
+            DeviceRead::Image {
+                image,
+                size,
+                texel_size,
+                callback,
+            } => {
+                let read_size = size[0] as usize * size[1] as usize * texel_size;
+                let readback_buffer = self.create_transient_buffer(
+                    read_size,
+                    BufferUsage::TRANSFER,
+                    gpu_allocator::MemoryLocation::GpuOnly,
+                );
+                let mut transfer_pass_builder =
+                    TransferPassBuilder::new("Image Readback Pass", QueueType::Graphics);
+                transfer_pass_builder.copy_image_to_buffer(
+                    ImageCopyImage {
+                        image,
+                        offset: [0; 2],
+                        mip_level: 0,
+                    },
+                    ImageCopyBuffer {
+                        buffer: readback_buffer,
+                        offset: 0,
+                        row_length: None,
+                        row_height: None,
+                    },
+                    size,
+                );
+                transfer_pass_builder.build(self);
+                self.add_buffer_read(
+                    BufferOffset {
+                        buffer: readback_buffer,
+                        offset: 0,
+                    },
+                    read_size,
+                    callback,
+                );
+            }
+        }
+    }
+
+    fn take_usages(&mut self) -> (BufferUsages, ImageUsages) {
+        (
+            std::mem::take(&mut self.render_graph.storage.buffer_usages),
+            std::mem::take(&mut self.render_graph.storage.image_usages),
+        )
+    }
+
+    fn return_usages(&mut self, mut buffer_usages: BufferUsages, mut image_usages: ImageUsages) {
+        buffer_usages.clear();
+        image_usages.clear();
+        self.render_graph.storage.buffer_usages = buffer_usages;
+        self.render_graph.storage.image_usages = image_usages;
+    }
+
     fn add_render_pass(
         &mut self,
-        label_name: String,
+        label_name: NameId,
         label_color: [f32; 4],
         buffer_usages: &[(BufferIndex, BufferResourceAccess)],
         image_usages: &[(ImageIndex, ImageResourceAccess)],
         command: Option<RenderPassCommand>,
     ) {
-        let buffer_barriers = self.create_buffer_barriers(buffer_usages);
-        let image_barriers = self.create_image_barriers(image_usages);
-        self.render_graph.command_buffers[0].render_pass_sets.push(
-            crate::render_graph::RenderPassSet {
-                memory_barriers: vec![vk::MemoryBarrier2::builder()
-                    .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
-                    .src_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
-                    .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
-                    .dst_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
-                    .build()],
-                buffer_barriers,
-                image_barriers,
-                render_passes: vec![crate::render_graph::RenderPass {
-                    label_name,
-                    label_color,
-                    command,
-                }],
-            },
+        let mut render_pass_set = self.render_graph.storage.render_pass_set();
+        render_pass_set.memory_barriers.push(
+            vk::MemoryBarrier2::builder()
+                .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .src_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .dst_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
+                .build(),
         );
+        self.create_buffer_barriers(&mut render_pass_set.buffer_barriers, buffer_usages);
+        self.create_image_barriers(&mut render_pass_set.image_barriers, image_usages);
+        render_pass_set
+            .render_passes
+            .push(crate::render_graph::RenderPass {
+                label_name,
+                label_color,
+                command,
+            });
+        self.render_graph.command_buffers[0]
+            .render_pass_sets
+            .push(render_pass_set);
     }
 
     fn create_buffer_barriers(
         &mut self,
+        buffer_barriers: &mut Vec<BufferBarrier>,
         buffer_usages: &[(BufferIndex, BufferResourceAccess)],
-    ) -> Vec<BufferBarrier> {
+    ) {
         //Unused since this uses global barriers
-        let _ = buffer_usages;
-        Vec::new()
+        let _ = (buffer_barriers, buffer_usages);
     }
 
     fn create_image_barriers(
         &mut self,
+        image_barriers: &mut Vec<ImageBarrier>,
         image_usages: &[(ImageIndex, ImageResourceAccess)],
-    ) -> Vec<ImageBarrier> {
-        image_usages
-            .iter()
-            .map(|(image_index, dst_access)| {
-                //Update first access if it doesn't exist
-                let _ = self.render_graph.image_resources[*image_index]
-                    .first_access
-                    .get_or_insert(*dst_access);
-                let src = match self.render_graph.image_resources[*image_index]
-                    .last_access
-                    .replace(*dst_access)
-                {
-                    None => ImageBarrierSource::FirstUsage,
-                    Some(access) => ImageBarrierSource::Precalculated(access),
-                };
-                ImageBarrier {
-                    index: *image_index,
-                    src,
-                    dst: *dst_access,
-                }
-            })
-            .collect()
+    ) {
+        image_barriers.extend(image_usages.iter().map(|(image_index, dst_access)| {
+            //Update first access if it doesn't exist
+            let _ = self.render_graph.image_resources[*image_index]
+                .first_access
+                .get_or_insert(*dst_access);
+            let src = match self.render_graph.image_resources[*image_index]
+                .last_access
+                .replace(*dst_access)
+            {
+                None => ImageBarrierSource::FirstUsage,
+                Some(access) => ImageBarrierSource::Precalculated(access),
+            };
+            ImageBarrier {
+                index: *image_index,
+                src,
+                dst: *dst_access,
+            }
+        }));
     }
 
     fn get_raster_draw_commands(
@@ -522,6 +652,9 @@ impl BasicRenderGraphBuilder {
                 ShaderResourceUsage::Sampler(sampler) => {
                     crate::render_graph::ShaderResourceUsage::Sampler(*sampler)
                 }
+                ShaderResourceUsage::Constant(value) => {
+                    crate::render_graph::ShaderResourceUsage::Constant(*value)
+                }
             })
             .collect()
     }
@@ -596,6 +729,7 @@ impl BasicRenderGraphBuilder {
         crate::render_graph::ImageCopyImage {
             image: self.get_image_index(image.image),
             offset: image.offset,
+            mip_level: image.mip_level,
         }
     }
 }
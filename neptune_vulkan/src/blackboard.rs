@@ -0,0 +1,40 @@
+use crate::name::NameId;
+use std::any::Any;
+use std::collections::HashMap;
+
+/// Values published while a frame's graph is being built, looked up by name by the passes written after them.
+/// Lets one render module find another's resources (a depth pyramid, a shadow atlas, view data) without the two being wired together.
+/// Lives in the graph builder, so every frame starts with an empty one. Values must be Send so the builder can be handed to a render thread
+#[derive(Default)]
+pub struct Blackboard {
+    values: HashMap<NameId, Box<dyn Any + Send>>,
+}
+
+impl Blackboard {
+    /// Replaces whatever was published under `key` before
+    pub fn insert<T: Any + Send>(&mut self, key: &str, value: T) {
+        self.values.insert(NameId::new(key), Box::new(value));
+    }
+
+    /// None if nothing was published under `key` or it was published as a different type
+    pub fn get<T: Any>(&self, key: &str) -> Option<&T> {
+        self.values
+            .get(&NameId::new(key))
+            .and_then(|value| value.downcast_ref())
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.values.contains_key(&NameId::new(key))
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.values.remove(&NameId::new(key));
+    }
+}
+
+impl std::fmt::Debug for Blackboard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        //Values are type erased, so only the keys can be shown
+        f.debug_set().entries(self.values.keys()).finish()
+    }
+}
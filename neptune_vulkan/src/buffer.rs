@@ -1,6 +1,6 @@
 use crate::descriptor_set::{DescriptorBinding, GpuBindingIndex};
 use crate::device::AshDevice;
-use crate::{BufferWriteError, VulkanError};
+use crate::{BufferWriteError, ImageHandle, VulkanError};
 use ash::vk;
 use bitflags::bitflags;
 use std::sync::Arc;
@@ -19,6 +19,42 @@ bitflags! {
     }
 }
 
+/// Sizes are resolved when the graph executes, so buffers sized off a screen dependent image resize along with it
+#[derive(Debug, Clone)]
+pub enum TransientBufferSize {
+    Exact(usize),
+    /// Bytes for every pixel of an image, e.g. per pixel fragment lists
+    PerPixel(usize, ImageHandle),
+    /// Bytes for every tile covering an image, partial tiles at the edges count as whole tiles
+    PerTile {
+        bytes_per_tile: usize,
+        tile_size: [u32; 2],
+        target: ImageHandle,
+    },
+}
+
+impl TransientBufferSize {
+    pub(crate) fn resolve(&self, get_image_size: impl Fn(ImageHandle) -> vk::Extent2D) -> usize {
+        match self {
+            TransientBufferSize::Exact(size) => *size,
+            TransientBufferSize::PerPixel(bytes_per_pixel, target) => {
+                let extent = get_image_size(*target);
+                bytes_per_pixel * extent.width as usize * extent.height as usize
+            }
+            TransientBufferSize::PerTile {
+                bytes_per_tile,
+                tile_size,
+                target,
+            } => {
+                let extent = get_image_size(*target);
+                let tiles_x = extent.width.div_ceil(tile_size[0].max(1)) as usize;
+                let tiles_y = extent.height.div_ceil(tile_size[1].max(1)) as usize;
+                bytes_per_tile * tiles_x * tiles_y
+            }
+        }
+    }
+}
+
 impl BufferUsage {
     pub(crate) fn to_vk(&self) -> vk::BufferUsageFlags {
         //Needed to keep clippy from complaining about contains function
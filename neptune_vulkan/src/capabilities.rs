@@ -0,0 +1,183 @@
+use crate::physical_device::{
+    PhysicalDevice, PhysicalDeviceDriverInfo, PhysicalDeviceExtensionInfo, PhysicalDeviceInfo,
+};
+use ash::vk;
+use std::ffi::CStr;
+use std::fmt::{Display, Formatter};
+
+/// The subset of `VkPhysicalDeviceLimits` the renderer cares about
+#[derive(Clone, Debug)]
+pub struct DeviceLimits {
+    pub max_image_dimension_2d: u32,
+    pub max_image_dimension_3d: u32,
+    pub max_image_array_layers: u32,
+    pub max_push_constants_size: u32,
+    pub max_memory_allocation_count: u32,
+    pub max_bound_descriptor_sets: u32,
+    pub max_per_stage_descriptor_sampled_images: u32,
+    pub max_per_stage_descriptor_storage_images: u32,
+    pub max_per_stage_descriptor_storage_buffers: u32,
+    pub max_color_attachments: u32,
+    pub max_viewport_dimensions: [u32; 2],
+    pub max_compute_work_group_count: [u32; 3],
+    pub max_compute_work_group_size: [u32; 3],
+    pub max_compute_work_group_invocations: u32,
+    pub max_sampler_anisotropy: f32,
+    pub min_uniform_buffer_offset_alignment: u64,
+    pub min_storage_buffer_offset_alignment: u64,
+    /// Nanoseconds per timestamp tick
+    pub timestamp_period: f32,
+}
+
+impl DeviceLimits {
+    fn from_vulkan(limits: &vk::PhysicalDeviceLimits) -> Self {
+        Self {
+            max_image_dimension_2d: limits.max_image_dimension2_d,
+            max_image_dimension_3d: limits.max_image_dimension3_d,
+            max_image_array_layers: limits.max_image_array_layers,
+            max_push_constants_size: limits.max_push_constants_size,
+            max_memory_allocation_count: limits.max_memory_allocation_count,
+            max_bound_descriptor_sets: limits.max_bound_descriptor_sets,
+            max_per_stage_descriptor_sampled_images: limits.max_per_stage_descriptor_sampled_images,
+            max_per_stage_descriptor_storage_images: limits.max_per_stage_descriptor_storage_images,
+            max_per_stage_descriptor_storage_buffers: limits
+                .max_per_stage_descriptor_storage_buffers,
+            max_color_attachments: limits.max_color_attachments,
+            max_viewport_dimensions: limits.max_viewport_dimensions,
+            max_compute_work_group_count: limits.max_compute_work_group_count,
+            max_compute_work_group_size: limits.max_compute_work_group_size,
+            max_compute_work_group_invocations: limits.max_compute_work_group_invocations,
+            max_sampler_anisotropy: limits.max_sampler_anisotropy,
+            min_uniform_buffer_offset_alignment: limits.min_uniform_buffer_offset_alignment,
+            min_storage_buffer_offset_alignment: limits.min_storage_buffer_offset_alignment,
+            timestamp_period: limits.timestamp_period,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MemoryHeapInfo {
+    pub size_bytes: u64,
+    pub device_local: bool,
+}
+
+/// Everything worth knowing about a gpu when branching on features at runtime or filing a bug report.
+/// Display prints it as a plain text report
+#[derive(Clone, Debug)]
+pub struct DeviceCapabilities {
+    pub info: PhysicalDeviceInfo,
+    pub driver: PhysicalDeviceDriverInfo,
+    pub async_compute_support: bool,
+    pub async_transfer_support: bool,
+    pub extension: PhysicalDeviceExtensionInfo,
+    pub limits: DeviceLimits,
+    pub memory_heaps: Vec<MemoryHeapInfo>,
+    /// Every device extension the driver exposes, sorted by name
+    pub extensions: Vec<String>,
+}
+
+impl DeviceCapabilities {
+    pub(crate) fn new(physical_device: &PhysicalDevice) -> Self {
+        let core = &physical_device.instance.core;
+        let properties = unsafe { core.get_physical_device_properties(physical_device.handle) };
+        let memory_properties =
+            unsafe { core.get_physical_device_memory_properties(physical_device.handle) };
+
+        let memory_heaps = memory_properties.memory_heaps
+            [0..memory_properties.memory_heap_count as usize]
+            .iter()
+            .map(|memory_heap| MemoryHeapInfo {
+                size_bytes: memory_heap.size,
+                device_local: memory_heap
+                    .flags
+                    .contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+            })
+            .collect();
+
+        let mut extensions: Vec<String> =
+            unsafe { core.enumerate_device_extension_properties(physical_device.handle) }
+                .unwrap_or_default()
+                .iter()
+                .map(|extension_properties| unsafe {
+                    CStr::from_ptr(extension_properties.extension_name.as_ptr())
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect();
+        extensions.sort();
+
+        Self {
+            info: physical_device.info.clone(),
+            driver: physical_device.driver.clone(),
+            async_compute_support: physical_device.supports_async_compute(),
+            async_transfer_support: physical_device.supports_async_transfer(),
+            extension: physical_device.extension.clone(),
+            limits: DeviceLimits::from_vulkan(&properties.limits),
+            memory_heaps,
+            extensions,
+        }
+    }
+
+    pub fn supports_extension(&self, name: &str) -> bool {
+        self.extensions.iter().any(|extension| extension == name)
+    }
+}
+
+impl Display for DeviceCapabilities {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        const BYTES_TO_MEGABYTES: f64 = 1.0 / (1024.0 * 1024.0);
+
+        let info = &self.info;
+        writeln!(f, "Device: {}", info.name)?;
+        writeln!(f, "  Vendor: {:?}", info.vendor)?;
+        writeln!(f, "  Type: {:?}", info.device_type)?;
+        writeln!(f, "  Device Id: {:#x}", info.device_id)?;
+        writeln!(
+            f,
+            "  Vulkan: {}.{}.{}",
+            info.api_version[1], info.api_version[2], info.api_version[3]
+        )?;
+
+        let driver = &self.driver;
+        writeln!(f, "Driver: {} {}", driver.name, driver.version)?;
+        writeln!(f, "  Id: {}", driver.id)?;
+        writeln!(f, "  Info: {}", driver.info)?;
+
+        writeln!(f, "Features:")?;
+        let features = [
+            ("Async Compute", self.async_compute_support),
+            ("Async Transfer", self.async_transfer_support),
+            ("Raytracing", self.extension.raytracing_support),
+            ("Mesh Shading", self.extension.mesh_shader_support),
+            ("Robustness2", self.extension.robustness2_support),
+            ("Null Descriptor", self.extension.null_descriptor_support),
+        ];
+        for (name, supported) in features {
+            writeln!(f, "  {}: {}", name, supported)?;
+        }
+
+        writeln!(f, "Limits:")?;
+        writeln!(f, "{:#?}", self.limits)?;
+
+        writeln!(f, "Memory Heaps:")?;
+        for (index, memory_heap) in self.memory_heaps.iter().enumerate() {
+            writeln!(
+                f,
+                "  {}: {:.0} MiB{}",
+                index,
+                memory_heap.size_bytes as f64 * BYTES_TO_MEGABYTES,
+                if memory_heap.device_local {
+                    " (Device Local)"
+                } else {
+                    ""
+                }
+            )?;
+        }
+
+        writeln!(f, "Extensions ({}):", self.extensions.len())?;
+        for extension in self.extensions.iter() {
+            writeln!(f, "  {}", extension)?;
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
+use crate::name::NameId;
 use ash::vk;
 use ash::vk::DebugUtilsObjectNameInfoEXT;
 use std::ffi::{CStr, CString};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use log::{error, info, trace, warn};
 
@@ -8,6 +10,8 @@ use log::{error, info, trace, warn};
 pub struct DebugUtils {
     debug_utils: ash::extensions::ext::DebugUtils,
     debug_call_back: vk::DebugUtilsMessengerEXT,
+    /// Boxed so the callback's user data pointer stays valid
+    warning_count: Box<AtomicU64>,
 }
 
 impl DebugUtils {
@@ -16,6 +20,7 @@ impl DebugUtils {
         instance: &ash::Instance,
     ) -> ash::prelude::VkResult<Self> {
         let debug_utils_loader = ash::extensions::ext::DebugUtils::new(entry, instance);
+        let warning_count = Box::new(AtomicU64::new(0));
         let debug_call_back = unsafe {
             debug_utils_loader.create_debug_utils_messenger(
                 &vk::DebugUtilsMessengerCreateInfoEXT::builder()
@@ -30,7 +35,8 @@ impl DebugUtils {
                             | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
                             | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
                     )
-                    .pfn_user_callback(Some(vulkan_debug_callback)),
+                    .pfn_user_callback(Some(vulkan_debug_callback))
+                    .user_data(warning_count.as_ref() as *const AtomicU64 as *mut _),
                 None,
             )?
         };
@@ -38,9 +44,15 @@ impl DebugUtils {
         Ok(Self {
             debug_utils: debug_utils_loader,
             debug_call_back,
+            warning_count,
         })
     }
 
+    /// Warnings and errors reported since the instance was created
+    pub(crate) fn warning_count(&self) -> u64 {
+        self.warning_count.load(Ordering::Relaxed)
+    }
+
     pub(crate) fn set_object_name<T: vk::Handle>(
         &self,
         device: vk::Device,
@@ -65,11 +77,9 @@ impl DebugUtils {
     pub(crate) fn cmd_begin_label(
         &self,
         command_buffer: vk::CommandBuffer,
-        label_name: &str,
+        label_name: NameId,
         label_color: [f32; 4],
     ) {
-        let label_name = CString::new(label_name).expect("Failed to create CString");
-
         unsafe {
             self.debug_utils.cmd_begin_debug_utils_label(
                 command_buffer,
@@ -100,7 +110,7 @@ unsafe extern "system" fn vulkan_debug_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     _message_type: vk::DebugUtilsMessageTypeFlagsEXT,
     p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _user_data: *mut std::os::raw::c_void,
+    user_data: *mut std::os::raw::c_void,
 ) -> vk::Bool32 {
     use std::borrow::Cow;
     let callback_data = *p_callback_data;
@@ -110,6 +120,28 @@ unsafe extern "system" fn vulkan_debug_callback(
         CStr::from_ptr(callback_data.p_message).to_string_lossy()
     };
 
+    //Shader printf output comes through as info from the validation layer
+    let message_id_name = if callback_data.p_message_id_name.is_null() {
+        Cow::from("")
+    } else {
+        CStr::from_ptr(callback_data.p_message_id_name).to_string_lossy()
+    };
+    if message_id_name.contains("DEBUG-PRINTF") {
+        //Everything before the last separator is the layer's own prefix
+        let output = message.rsplit("| ").next().unwrap_or_default();
+        info!(target: "shader", "{}", output.trim_end());
+        return vk::FALSE;
+    }
+
+    if message_severity.intersects(
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+            | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+    ) {
+        if let Some(warning_count) = (user_data as *const AtomicU64).as_ref() {
+            warning_count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
     match message_severity {
         vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE => trace!("{:?}", message),
         vk::DebugUtilsMessageSeverityFlagsEXT::INFO => info!("{:?}", message),
@@ -0,0 +1,197 @@
+use crate::render_graph_builder::BufferOffset;
+use crate::resource_managers::ResourceManager;
+use crate::upload_queue::UploadQueue;
+use crate::{BufferHandle, BufferKey, VulkanError};
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use std::collections::{HashMap, HashSet};
+
+/// Device local memory use as of the last frame, and how much the defragmenter has moved so far
+#[derive(Debug, Clone, Default)]
+pub struct DefragmentStats {
+    /// Device local memory blocks holding at least one persistent buffer or image
+    pub blocks: u32,
+    /// Bytes used by persistent buffers and images in those blocks
+    pub used_bytes: u64,
+    /// Bytes from the start of each block to the end of its last allocation, anything not used inside it is a hole
+    pub span_bytes: u64,
+    /// Buffers moved since the device was created
+    pub moved_buffers: u64,
+    pub moved_bytes: u64,
+}
+
+impl DefragmentStats {
+    /// Fraction of the used span that is holes, 0 when everything is packed
+    pub fn fragmentation(&self) -> f32 {
+        if self.span_bytes == 0 {
+            0.0
+        } else {
+            1.0 - self.used_bytes as f32 / self.span_bytes as f32
+        }
+    }
+}
+
+#[derive(Default)]
+struct MemoryBlock {
+    used: u64,
+    span: u64,
+    /// Buffers that can be moved out of the block, as (offset, key, size)
+    movable: Vec<(u64, BufferKey, u64)>,
+}
+
+impl MemoryBlock {
+    fn fragmentation(&self) -> f32 {
+        1.0 - self.used as f32 / self.span.max(1) as f32
+    }
+}
+
+/// Moves long lived gpu only buffers out of the most fragmented memory blocks, a few each frame.
+/// The new buffer takes over the old one's key so handles stay valid, the copy goes through the upload pass
+/// and the old buffer is freed once the frames still using it are done.
+/// Buffers with a storage binding are left alone, their descriptor can't be rewritten while frames are in flight
+#[derive(Default)]
+pub(crate) struct Defragmenter {
+    stats: DefragmentStats,
+    /// Buffers that didn't find a better spot, skipped until another buffer is freed
+    stuck: HashSet<BufferKey>,
+    stuck_buffer_count: usize,
+}
+
+impl Defragmenter {
+    /// Buffers younger than this are likely still part of a streaming burst and may be freed soon anyway
+    const MIN_AGE_FRAMES: u64 = 300;
+    /// Blocks with fewer holes than this aren't worth the copies
+    const MIN_FRAGMENTATION: f32 = 0.25;
+    /// Limits the extra memory and transfer time used per frame
+    const MAX_BYTES_PER_FRAME: u64 = 8 * 1024 * 1024;
+
+    pub(crate) fn stats(&self) -> &DefragmentStats {
+        &self.stats
+    }
+
+    /// Updates the stats and, when nothing else is uploading this frame, queues moves out of the worst block
+    pub(crate) fn step(
+        &mut self,
+        resource_manager: &mut ResourceManager,
+        upload_queue: &mut UploadQueue,
+    ) -> Result<(), VulkanError> {
+        //Freeing buffers opens new holes, so stuck buffers get another chance
+        if resource_manager.buffers.len() < self.stuck_buffer_count {
+            self.stuck.clear();
+        }
+        self.stuck_buffer_count = resource_manager.buffers.len();
+
+        let blocks = self.collect_blocks(resource_manager);
+        self.stats.blocks = blocks.len() as u32;
+        self.stats.used_bytes = blocks.values().map(|block| block.used).sum();
+        self.stats.span_bytes = blocks.values().map(|block| block.span).sum();
+
+        //Only use transfer time that streaming isn't
+        if !upload_queue.is_empty() {
+            return Ok(());
+        }
+
+        let Some(mut block) = blocks
+            .into_values()
+            .filter(|block| {
+                block.fragmentation() >= Self::MIN_FRAGMENTATION && !block.movable.is_empty()
+            })
+            .max_by(|a, b| a.fragmentation().total_cmp(&b.fragmentation()))
+        else {
+            return Ok(());
+        };
+
+        //Emptying the end of the block first shrinks its span the most
+        block
+            .movable
+            .sort_by_key(|&(offset, _, _)| std::cmp::Reverse(offset));
+
+        let mut moved_bytes = 0;
+        for (_, key, size) in block.movable {
+            if moved_bytes + size > Self::MAX_BYTES_PER_FRAME && moved_bytes != 0 {
+                break;
+            }
+
+            match resource_manager.relocate_buffer(key)? {
+                Some(old_key) => {
+                    upload_queue.add_buffer_upload(
+                        BufferOffset {
+                            buffer: BufferHandle::Persistent(old_key),
+                            offset: 0,
+                        },
+                        BufferOffset {
+                            buffer: BufferHandle::Persistent(key),
+                            offset: 0,
+                        },
+                        size as usize,
+                    );
+                    moved_bytes += size;
+                    self.stats.moved_buffers += 1;
+                    self.stats.moved_bytes += size;
+                }
+                None => {
+                    self.stuck.insert(key);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn collect_blocks(
+        &self,
+        resource_manager: &ResourceManager,
+    ) -> HashMap<vk::DeviceMemory, MemoryBlock> {
+        let mut blocks: HashMap<vk::DeviceMemory, MemoryBlock> = HashMap::new();
+        let min_frame = resource_manager
+            .frame_number()
+            .saturating_sub(Self::MIN_AGE_FRAMES);
+        let pending_frees = resource_manager.pending_buffer_frees();
+
+        for (key, resource) in resource_manager.buffers.iter() {
+            let buffer = &resource.buffer;
+            if buffer.location != MemoryLocation::GpuOnly {
+                continue;
+            }
+            let Some(block) = add_allocation(&mut blocks, &buffer.allocation) else {
+                continue;
+            };
+
+            let can_copy = buffer
+                .usage
+                .contains(vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST);
+            if can_copy
+                && buffer.storage_binding.is_none()
+                && resource.created_frame <= min_frame
+                && !self.stuck.contains(&key)
+                && !pending_frees.contains(&key)
+            {
+                block
+                    .movable
+                    .push((buffer.allocation.offset(), key, buffer.size));
+            }
+        }
+
+        for resource in resource_manager.images.values() {
+            if resource.image.location == MemoryLocation::GpuOnly {
+                add_allocation(&mut blocks, &resource.image.allocation);
+            }
+        }
+
+        blocks
+    }
+}
+
+/// Counts the allocation against its memory block, dedicated allocations have a block of their own and are skipped
+fn add_allocation<'a>(
+    blocks: &'a mut HashMap<vk::DeviceMemory, MemoryBlock>,
+    allocation: &gpu_allocator::vulkan::Allocation,
+) -> Option<&'a mut MemoryBlock> {
+    if allocation.is_null() || allocation.is_dedicated() {
+        return None;
+    }
+    let block = blocks.entry(unsafe { allocation.memory() }).or_default();
+    block.used += allocation.size();
+    block.span = block.span.max(allocation.offset() + allocation.size());
+    Some(block)
+}
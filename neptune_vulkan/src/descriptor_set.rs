@@ -1,8 +1,9 @@
 use crate::buffer::Buffer;
 use crate::device::AshDevice;
 use crate::image::Image;
-use crate::{Sampler, VulkanError};
+use crate::{ImageDescription2D, Sampler, VulkanError};
 use ash::vk;
+use gpu_allocator::MemoryLocation;
 use std::sync::{Arc, Mutex};
 
 #[derive(Default, Debug, Clone)]
@@ -27,16 +28,27 @@ impl GpuBindingIndex {
 pub struct DescriptorBinding {
     binding: u16,
     index: u16,
+    /// Stamped into the high 16 bits of the index so shaders can tell a stale binding, zero unless bindings are validated
+    generation: u16,
     set: Arc<Mutex<DescriptorSetInner>>,
 }
 
 impl DescriptorBinding {
     pub(crate) fn index(&self) -> GpuBindingIndex {
-        //TODO: Use last 16(?) bits of index to encode the binding for GPU error checking
-        GpuBindingIndex(self.index as u32)
+        GpuBindingIndex(self.index as u32 | (self.generation as u32) << 16)
     }
 }
 
+/// Shader reads of sampled image bindings that were freed or reused, counted on the gpu since the last check
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StaleBindingReads {
+    pub count: u32,
+    pub last_index: u16,
+    pub last_generation: u16,
+    /// Generation of the slot now, zero if it's still free
+    pub current_generation: u16,
+}
+
 impl Drop for DescriptorBinding {
     fn drop(&mut self) {
         self.set.lock().unwrap().unbind(self.binding, self.index);
@@ -50,8 +62,12 @@ pub struct DescriptorSet {
 }
 
 impl DescriptorSet {
-    pub fn new(device: Arc<AshDevice>, count: DescriptorCount) -> Result<Self, VulkanError> {
-        let inner = DescriptorSetInner::new(device, count)?;
+    pub fn new(
+        device: Arc<AshDevice>,
+        count: DescriptorCount,
+        validate_bindings: bool,
+    ) -> Result<Self, VulkanError> {
+        let inner = DescriptorSetInner::new(device, count, validate_bindings)?;
         let layout = inner.layout;
         let set = inner.set;
         let inner = Arc::new(Mutex::new(inner));
@@ -71,6 +87,7 @@ impl DescriptorSet {
         DescriptorBinding {
             binding: DescriptorSetInner::STORAGE_BUFFER_BINDING,
             index: self.inner.lock().unwrap().bind_storage_buffer(buffer),
+            generation: 0,
             set: self.inner.clone(),
         }
     }
@@ -79,14 +96,17 @@ impl DescriptorSet {
         DescriptorBinding {
             binding: DescriptorSetInner::STORAGE_IMAGE_BINDING,
             index: self.inner.lock().unwrap().bind_storage_image(image),
+            generation: 0,
             set: self.inner.clone(),
         }
     }
 
     pub fn bind_sampled_image(&self, image: &Image) -> DescriptorBinding {
+        let (index, generation) = self.inner.lock().unwrap().bind_sampled_image(image);
         DescriptorBinding {
             binding: DescriptorSetInner::SAMPLED_IMAGE_BINDING,
-            index: self.inner.lock().unwrap().bind_sampled_image(image),
+            index,
+            generation,
             set: self.inner.clone(),
         }
     }
@@ -95,9 +115,15 @@ impl DescriptorSet {
         DescriptorBinding {
             binding: DescriptorSetInner::SAMPLER_BINDING,
             index: self.inner.lock().unwrap().bind_sampler(sampler),
+            generation: 0,
             set: self.inner.clone(),
         }
     }
+
+    /// Takes the stale reads the shaders have reported since the last call, None if there were none or validation is off
+    pub(crate) fn take_stale_reads(&self) -> Option<StaleBindingReads> {
+        self.inner.lock().unwrap().take_stale_reads()
+    }
 }
 
 const EMPTY_BUFFER_INFO: vk::DescriptorBufferInfo = vk::DescriptorBufferInfo {
@@ -112,6 +138,17 @@ const EMPTY_IMAGE_INFO: vk::DescriptorImageInfo = vk::DescriptorImageInfo {
     image_layout: vk::ImageLayout::UNDEFINED,
 };
 
+/// Error resources written into freed slots, so a stale index reads something obviously wrong instead of whatever
+/// was bound there next
+struct ErrorResources {
+    /// Magenta, kept in the general layout for both sampled and storage slots
+    image: Image,
+    /// Filled with 0xFF bytes, NaN as floats and out of range as indices
+    buffer: Buffer,
+    /// Generation of every sampled image slot, zero while free
+    sampled_image_generations: Vec<u16>,
+}
+
 pub struct DescriptorSetInner {
     device: Arc<AshDevice>,
     layout: vk::DescriptorSetLayout,
@@ -119,6 +156,10 @@ pub struct DescriptorSetInner {
     set: vk::DescriptorSet,
     empty_sampler: vk::Sampler,
 
+    /// Always bound to storage buffer 0, laid out like `BindingValidationBuffer` in bindless.glsl
+    validation_buffer: Buffer,
+    error_resources: Option<ErrorResources>,
+
     storage_buffer_pool: IndexPool,
     storage_image_pool: IndexPool,
     sampled_image_pool: IndexPool,
@@ -135,7 +176,20 @@ impl DescriptorSetInner {
     #[allow(unused)]
     const ACCELERATION_STRUCTURE_BINDING: u16 = 4;
 
-    fn new(device: Arc<AshDevice>, count: DescriptorCount) -> Result<Self, VulkanError> {
+    //Validation buffer header, in u32s
+    const VALIDATION_ENABLED: usize = 0;
+    const VALIDATION_STALE_READ_COUNT: usize = 1;
+    const VALIDATION_LAST_STALE_BINDING: usize = 2;
+    const VALIDATION_GENERATIONS: usize = 4;
+
+    const ERROR_COLOR: [f32; 4] = [1.0, 0.0, 1.0, 1.0];
+    const POISON_BUFFER_SIZE: vk::DeviceSize = 64 * 1024;
+
+    fn new(
+        device: Arc<AshDevice>,
+        count: DescriptorCount,
+        validate_bindings: bool,
+    ) -> Result<Self, VulkanError> {
         let mut bindings = Vec::new();
         let mut pool_sizes = Vec::new();
 
@@ -254,19 +308,79 @@ impl DescriptorSetInner {
                 .create_sampler(&vk::SamplerCreateInfo::default(), None)?
         };
 
+        let mut validation_buffer = Buffer::new(
+            device.clone(),
+            "Binding Validation Buffer",
+            ((Self::VALIDATION_GENERATIONS + count.sampled_images as usize)
+                * std::mem::size_of::<u32>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::GpuToCpu,
+        )?;
+        if let Some(slice) = validation_buffer.allocation.mapped_slice_mut() {
+            slice.fill(0);
+            write_u32(slice, Self::VALIDATION_ENABLED, validate_bindings as u32);
+        }
+
+        let error_resources = if validate_bindings {
+            Some(ErrorResources::new(&device, count.sampled_images)?)
+        } else {
+            None
+        };
+
+        //Freed slots are left poisoned for as long as possible before being handed out again
+        let prefer_unused = validate_bindings;
         let new_self = Self {
             device,
             layout,
             pool,
             set,
             empty_sampler,
-            storage_buffer_pool: IndexPool::new(0..count.storage_buffers),
-            storage_image_pool: IndexPool::new(0..count.storage_images),
-            sampled_image_pool: IndexPool::new(0..count.sampled_images),
-            sampler_pool: IndexPool::new(0..count.samplers),
+            validation_buffer,
+            error_resources,
+            storage_buffer_pool: IndexPool::new(1..count.storage_buffers, prefer_unused),
+            storage_image_pool: IndexPool::new(0..count.storage_images, prefer_unused),
+            sampled_image_pool: IndexPool::new(0..count.sampled_images, prefer_unused),
+            sampler_pool: IndexPool::new(0..count.samplers, prefer_unused),
             //acceleration_structure_pool: IndexPool::new(0..count.acceleration_structures),
         };
 
+        new_self.write_buffer_descriptor(
+            vk::DescriptorType::STORAGE_BUFFER,
+            Self::STORAGE_BUFFER_BINDING,
+            0,
+            &[vk::DescriptorBufferInfo {
+                buffer: new_self.validation_buffer.handle,
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            }],
+        );
+
+        //Every slot starts out freed, so in debug mode never bound slots read the error resources too
+        if let Some(error_resources) = &new_self.error_resources {
+            let buffer_infos =
+                vec![error_resources.buffer_info(); count.storage_buffers as usize - 1];
+            new_self.write_buffer_descriptor(
+                vk::DescriptorType::STORAGE_BUFFER,
+                Self::STORAGE_BUFFER_BINDING,
+                1,
+                &buffer_infos,
+            );
+            let image_infos = vec![error_resources.image_info(); count.storage_images as usize];
+            new_self.write_image_descriptor(
+                vk::DescriptorType::STORAGE_IMAGE,
+                Self::STORAGE_IMAGE_BINDING,
+                0,
+                &image_infos,
+            );
+            let image_infos = vec![error_resources.image_info(); count.sampled_images as usize];
+            new_self.write_image_descriptor(
+                vk::DescriptorType::SAMPLED_IMAGE,
+                Self::SAMPLED_IMAGE_BINDING,
+                0,
+                &image_infos,
+            );
+        }
+
         //Write empty sampler
         {
             let sampler_info = vk::DescriptorImageInfo {
@@ -315,12 +429,14 @@ impl DescriptorSetInner {
     }
     fn unbind_storage_buffer(&mut self, index: u16) {
         self.storage_buffer_pool.free(index);
-        self.write_buffer_descriptor(
-            vk::DescriptorType::STORAGE_BUFFER,
-            Self::STORAGE_BUFFER_BINDING,
-            index,
-            &[EMPTY_BUFFER_INFO],
-        );
+        if let Some(buffer_info) = self.freed_buffer_info() {
+            self.write_buffer_descriptor(
+                vk::DescriptorType::STORAGE_BUFFER,
+                Self::STORAGE_BUFFER_BINDING,
+                index,
+                &[buffer_info],
+            );
+        }
     }
 
     fn bind_storage_image(&mut self, image: &Image) -> u16 {
@@ -342,15 +458,18 @@ impl DescriptorSetInner {
     }
     fn unbind_storage_image(&mut self, index: u16) {
         self.storage_image_pool.free(index);
-        self.write_image_descriptor(
-            vk::DescriptorType::STORAGE_IMAGE,
-            Self::STORAGE_IMAGE_BINDING,
-            index,
-            &[EMPTY_IMAGE_INFO],
-        );
+        if let Some(image_info) = self.freed_image_info() {
+            self.write_image_descriptor(
+                vk::DescriptorType::STORAGE_IMAGE,
+                Self::STORAGE_IMAGE_BINDING,
+                index,
+                &[image_info],
+            );
+        }
     }
 
-    fn bind_sampled_image(&mut self, image: &Image) -> u16 {
+    /// Returns the slot's index and generation
+    fn bind_sampled_image(&mut self, image: &Image) -> (u16, u16) {
         let index = self
             .sampled_image_pool
             .get()
@@ -366,16 +485,81 @@ impl DescriptorSetInner {
                 image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
             }],
         );
-        index
+
+        let mut generation = 0;
+        if let Some(error_resources) = &mut self.error_resources {
+            //Zero marks a free slot, so skip it when wrapping
+            let slot_generation = &mut error_resources.sampled_image_generations[index as usize];
+            *slot_generation = slot_generation.wrapping_add(1).max(1);
+            generation = *slot_generation;
+            self.write_sampled_image_generation(index, generation);
+        }
+        (index, generation)
     }
     fn unbind_sampled_image(&mut self, index: u16) {
         self.sampled_image_pool.free(index);
-        self.write_image_descriptor(
-            vk::DescriptorType::SAMPLED_IMAGE,
-            Self::SAMPLED_IMAGE_BINDING,
-            index,
-            &[EMPTY_IMAGE_INFO],
-        );
+        if let Some(image_info) = self.freed_image_info() {
+            self.write_image_descriptor(
+                vk::DescriptorType::SAMPLED_IMAGE,
+                Self::SAMPLED_IMAGE_BINDING,
+                index,
+                &[image_info],
+            );
+        }
+        if self.error_resources.is_some() {
+            self.write_sampled_image_generation(index, 0);
+        }
+    }
+
+    /// What a freed slot is written with, without the error resources or null descriptors the old descriptor is left
+    /// in place since partially bound slots are only invalid once a shader reads them
+    fn freed_buffer_info(&self) -> Option<vk::DescriptorBufferInfo> {
+        match &self.error_resources {
+            Some(error_resources) => Some(error_resources.buffer_info()),
+            None => self.device.null_descriptor.then_some(EMPTY_BUFFER_INFO),
+        }
+    }
+
+    fn freed_image_info(&self) -> Option<vk::DescriptorImageInfo> {
+        match &self.error_resources {
+            Some(error_resources) => Some(error_resources.image_info()),
+            None => self.device.null_descriptor.then_some(EMPTY_IMAGE_INFO),
+        }
+    }
+
+    fn write_sampled_image_generation(&mut self, index: u16, generation: u16) {
+        if let Some(slice) = self.validation_buffer.allocation.mapped_slice_mut() {
+            write_u32(
+                slice,
+                Self::VALIDATION_GENERATIONS + index as usize,
+                generation as u32,
+            );
+        }
+    }
+
+    fn take_stale_reads(&mut self) -> Option<StaleBindingReads> {
+        let error_resources = self.error_resources.as_ref()?;
+        let slice = self.validation_buffer.allocation.mapped_slice_mut()?;
+
+        let count = read_u32(slice, Self::VALIDATION_STALE_READ_COUNT);
+        if count == 0 {
+            return None;
+        }
+        //Frames still in flight can race this reset, a few lost counts don't matter for a debug report
+        write_u32(slice, Self::VALIDATION_STALE_READ_COUNT, 0);
+
+        let last_binding = read_u32(slice, Self::VALIDATION_LAST_STALE_BINDING);
+        let last_index = (last_binding & 0xFFFF) as u16;
+        Some(StaleBindingReads {
+            count,
+            last_index,
+            last_generation: (last_binding >> 16) as u16,
+            current_generation: error_resources
+                .sampled_image_generations
+                .get(last_index as usize)
+                .copied()
+                .unwrap_or_default(),
+        })
     }
 
     fn bind_sampler(&mut self, sampler: &Sampler) -> u16 {
@@ -485,17 +669,26 @@ impl Drop for DescriptorSetInner {
 struct IndexPool {
     range: std::ops::Range<u16>,
     freed_indices: Vec<u16>,
+    /// Hand out never used indices before any freed ones
+    prefer_unused: bool,
 }
 
 impl IndexPool {
-    fn new(range: std::ops::Range<u16>) -> Self {
+    fn new(range: std::ops::Range<u16>, prefer_unused: bool) -> Self {
         Self {
             range,
             freed_indices: Vec::new(),
+            prefer_unused,
         }
     }
 
     fn get(&mut self) -> Option<u16> {
+        if self.prefer_unused {
+            if let Some(new_value) = self.range.next() {
+                return Some(new_value);
+            }
+        }
+
         let mut new_value = self.freed_indices.pop();
         if new_value.is_none() {
             new_value = self.range.next();
@@ -507,3 +700,158 @@ impl IndexPool {
         self.freed_indices.push(index);
     }
 }
+
+impl ErrorResources {
+    fn new(device: &Arc<AshDevice>, sampled_image_count: u16) -> Result<Self, VulkanError> {
+        let image = Image::new_2d(
+            device.clone(),
+            "Error Image",
+            &ImageDescription2D {
+                size: [1, 1],
+                format: vk::Format::R8G8B8A8_UNORM,
+                usage: vk::ImageUsageFlags::SAMPLED
+                    | vk::ImageUsageFlags::STORAGE
+                    | vk::ImageUsageFlags::TRANSFER_DST,
+                mip_levels: 1,
+                location: MemoryLocation::GpuOnly,
+            },
+        )?;
+        clear_error_image(device, &image)?;
+
+        let mut buffer = Buffer::new(
+            device.clone(),
+            "Poison Buffer",
+            DescriptorSetInner::POISON_BUFFER_SIZE,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::CpuToGpu,
+        )?;
+        if let Some(slice) = buffer.allocation.mapped_slice_mut() {
+            slice.fill(0xFF);
+        }
+
+        Ok(Self {
+            image,
+            buffer,
+            sampled_image_generations: vec![0; sampled_image_count as usize],
+        })
+    }
+
+    fn buffer_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo {
+            buffer: self.buffer.handle,
+            offset: 0,
+            range: vk::WHOLE_SIZE,
+        }
+    }
+
+    fn image_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: self.image.view,
+            image_layout: vk::ImageLayout::GENERAL,
+        }
+    }
+}
+
+/// Fills the error image and leaves it in the general layout, outside of any graph since no pass ever declares it
+fn clear_error_image(device: &AshDevice, image: &Image) -> Result<(), VulkanError> {
+    let queue = device
+        .graphics_queue
+        .expect("Binding validation needs a graphics queue");
+
+    let command_pool = unsafe {
+        device.core.create_command_pool(
+            &vk::CommandPoolCreateInfo::builder()
+                .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+                .queue_family_index(queue.family_index),
+            None,
+        )
+    }?;
+
+    let subresource_range = vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    };
+
+    let result = unsafe {
+        (|| {
+            let command_buffer = device.core.allocate_command_buffers(
+                &vk::CommandBufferAllocateInfo::builder()
+                    .command_pool(command_pool)
+                    .level(vk::CommandBufferLevel::PRIMARY)
+                    .command_buffer_count(1),
+            )?[0];
+
+            device.core.begin_command_buffer(
+                command_buffer,
+                &vk::CommandBufferBeginInfo::builder()
+                    .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+            )?;
+            device.core.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&[
+                    vk::ImageMemoryBarrier2::builder()
+                        .image(image.handle)
+                        .subresource_range(subresource_range)
+                        .old_layout(vk::ImageLayout::UNDEFINED)
+                        .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                        .dst_stage_mask(vk::PipelineStageFlags2::CLEAR)
+                        .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                        .build(),
+                ]),
+            );
+            device.core.cmd_clear_color_image(
+                command_buffer,
+                image.handle,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &vk::ClearColorValue {
+                    float32: DescriptorSetInner::ERROR_COLOR,
+                },
+                &[subresource_range],
+            );
+            device.core.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&[
+                    vk::ImageMemoryBarrier2::builder()
+                        .image(image.handle)
+                        .subresource_range(subresource_range)
+                        .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                        .new_layout(vk::ImageLayout::GENERAL)
+                        .src_stage_mask(vk::PipelineStageFlags2::CLEAR)
+                        .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                        .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                        .dst_access_mask(
+                            vk::AccessFlags2::SHADER_READ | vk::AccessFlags2::SHADER_WRITE,
+                        )
+                        .build(),
+                ]),
+            );
+            device.core.end_command_buffer(command_buffer)?;
+
+            device.core.queue_submit(
+                queue.handle,
+                &[vk::SubmitInfo::builder()
+                    .command_buffers(&[command_buffer])
+                    .build()],
+                vk::Fence::null(),
+            )?;
+            device.core.queue_wait_idle(queue.handle)
+        })()
+    };
+
+    unsafe { device.core.destroy_command_pool(command_pool, None) };
+    Ok(result?)
+}
+
+fn read_u32(slice: &[u8], index: usize) -> u32 {
+    let offset = index * std::mem::size_of::<u32>();
+    u32::from_ne_bytes(slice[offset..offset + 4].try_into().unwrap())
+}
+
+fn write_u32(slice: &mut [u8], index: usize, value: u32) {
+    let offset = index * std::mem::size_of::<u32>();
+    slice[offset..offset + 4].copy_from_slice(&value.to_ne_bytes());
+}
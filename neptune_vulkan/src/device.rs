@@ -1,9 +1,19 @@
+use crate::basic_render_graph_builder::{BasicRenderGraphBuilder, DeviceRead};
 use crate::buffer::{Buffer, BufferDescription, BufferUsage};
-use crate::image::{Image, ImageDescription2D};
+use crate::capabilities::DeviceCapabilities;
+use crate::defragment::{DefragmentStats, Defragmenter};
+use crate::frame_arena::FrameArena;
+use crate::image::{
+    vk_format_get_aspect_flags, vk_format_get_texel_size, Image, ImageDescription2D,
+    ImageDescription3D,
+};
 use crate::instance::AshInstance;
 use crate::pipeline::{ComputePipeline, Pipelines, RasterPipeline, RasterPipelineDescription};
+use crate::profiler::{FrameBreadcrumbs, FrameProfile, FrameStats};
 use crate::render_graph::CompiledRenderGraph;
-use crate::render_graph_builder::{BufferOffset, ImageCopyBuffer, ImageCopyImage};
+use crate::render_graph_builder::{
+    BufferOffset, BufferReadCallback, ImageCopyBuffer, ImageCopyImage,
+};
 use crate::render_graph_executor::RenderGraphExecutor;
 use crate::resource_managers::ResourceManager;
 use crate::sampler::{Sampler, SamplerDescription};
@@ -11,10 +21,10 @@ use crate::swapchain::{SurfaceSettings, Swapchain, SwapchainManager};
 use crate::upload_queue::UploadQueue;
 use crate::{
     BufferHandle, ComputePipelineHandle, ImageHandle, PhysicalDevice, RasterPipelineHandle,
-    SamplerHandle, ShaderStage, SurfaceHandle, VulkanError,
+    SamplerHandle, ShaderStage, SurfaceHandle, VulkanError, VulkanFuture,
 };
 use ash::vk;
-use log::error;
+use log::{error, warn};
 use std::mem::ManuallyDrop;
 use std::sync::{Arc, Mutex};
 
@@ -40,6 +50,8 @@ pub struct AshDevice {
     pub swapchain: ash::extensions::khr::Swapchain,
     pub mesh_shader: Option<ash::extensions::ext::MeshShader>,
     pub raytracing: Option<AshRaytracing>,
+    /// Descriptors may be written with null handles
+    pub null_descriptor: bool,
     pub allocator: ManuallyDrop<Mutex<gpu_allocator::vulkan::Allocator>>,
 }
 
@@ -47,6 +59,7 @@ impl AshDevice {
     pub fn new(
         instance: Arc<AshInstance>,
         physical_device: &PhysicalDevice,
+        settings: &DeviceSettings,
     ) -> Result<Self, VulkanError> {
         let mut queue_create_infos: Vec<vk::DeviceQueueCreateInfo> = Vec::with_capacity(3);
 
@@ -95,6 +108,20 @@ impl AshDevice {
             device_extension_names_raw.push(vk::KhrShaderNonSemanticInfoFn::name().as_ptr());
         }
 
+        let robust_access = settings.robust_access && physical_device.extension.robustness2_support;
+        if settings.robust_access && !robust_access {
+            warn!("Robust access was requested but VK_EXT_robustness2 isn't supported");
+        }
+
+        //Freed bindless slots are written with null descriptors unless they're pointed at the error resources
+        let null_descriptor =
+            !settings.validate_bindings && physical_device.extension.null_descriptor_support;
+
+        let robustness2 = robust_access || null_descriptor;
+        if robustness2 {
+            device_extension_names_raw.push(vk::ExtRobustness2Fn::name().as_ptr());
+        }
+
         let mut vulkan_1_2_features = vk::PhysicalDeviceVulkan12Features::builder()
             .buffer_device_address(true)
             .descriptor_indexing(true)
@@ -103,27 +130,40 @@ impl AshDevice {
             .descriptor_binding_storage_image_update_after_bind(true)
             .descriptor_binding_sampled_image_update_after_bind(true)
             .descriptor_binding_update_unused_while_pending(true)
-            .runtime_descriptor_array(true);
+            .runtime_descriptor_array(true)
+            .timeline_semaphore(true);
 
         let mut vulkan_1_3_features = vk::PhysicalDeviceVulkan13Features::builder()
             .synchronization2(true)
             .dynamic_rendering(true);
 
         let mut physical_device_robustness2_features =
-            vk::PhysicalDeviceRobustness2FeaturesEXT::builder().null_descriptor(true);
+            vk::PhysicalDeviceRobustness2FeaturesEXT::builder()
+                .robust_buffer_access2(robust_access)
+                .robust_image_access2(robust_access)
+                .null_descriptor(null_descriptor);
+
+        //Instrumented shaders write their output from every stage, and any shader sampling through bindless.glsl
+        //may write a stale binding report
+        let features = vk::PhysicalDeviceFeatures::builder()
+            .robust_buffer_access(robust_access)
+            .vertex_pipeline_stores_and_atomics(true)
+            .fragment_stores_and_atomics(true);
+
+        let mut create_info = vk::DeviceCreateInfo::builder()
+            .queue_create_infos(&queue_create_infos)
+            .enabled_features(&features)
+            .enabled_extension_names(&device_extension_names_raw)
+            .push_next(&mut vulkan_1_2_features)
+            .push_next(&mut vulkan_1_3_features);
+        if robustness2 {
+            create_info = create_info.push_next(&mut physical_device_robustness2_features);
+        }
 
         let core = unsafe {
-            instance.core.create_device(
-                physical_device.handle,
-                &vk::DeviceCreateInfo::builder()
-                    .queue_create_infos(&queue_create_infos)
-                    .enabled_extension_names(&device_extension_names_raw)
-                    .push_next(&mut vulkan_1_2_features)
-                    .push_next(&mut vulkan_1_3_features)
-                    .push_next(&mut physical_device_robustness2_features)
-                    .build(),
-                None,
-            )
+            instance
+                .core
+                .create_device(physical_device.handle, &create_info, None)
         }?;
 
         let swapchain = ash::extensions::khr::Swapchain::new(&instance.core, &core);
@@ -203,6 +243,7 @@ impl AshDevice {
             swapchain,
             mesh_shader,
             raytracing,
+            null_descriptor,
             allocator,
         })
     }
@@ -218,11 +259,21 @@ impl Drop for AshDevice {
 }
 
 pub struct DeviceSettings {
+    /// Frames the cpu can record ahead of the gpu, each gets its own command buffers, sync objects and deletion queue
     pub frames_in_flight: u32,
+    /// Bounds checks every buffer and image access in shaders, out of range reads return zero and writes are dropped.
+    /// Costs some gpu time, meant for catching bad bindless indices without losing the device
+    pub robust_access: bool,
+    /// Moves long lived gpu only buffers out of fragmented memory blocks during frames with no uploads
+    pub defragment: bool,
+    /// Freed bindless slots point at a magenta image and a poison buffer instead of nothing, and shaders going through
+    /// bindless.glsl report sampled image bindings that outlived their image. Debug only, slots are reused less eagerly
+    pub validate_bindings: bool,
 }
 
 pub struct Device {
     settings: DeviceSettings,
+    capabilities: DeviceCapabilities,
     device: Arc<AshDevice>,
     pipelines: Pipelines,
     resource_manager: ResourceManager,
@@ -230,24 +281,30 @@ pub struct Device {
 
     upload_queue: UploadQueue,
     graph_executor: RenderGraphExecutor,
+    frame_arena: FrameArena,
+    defragmenter: Defragmenter,
+    /// Readbacks waiting for the next `render_graph_builder`
+    pending_reads: Vec<DeviceRead>,
 }
 
 impl Device {
     pub fn new(
         instance: Arc<AshInstance>,
         physical_device: PhysicalDevice,
-        settings: DeviceSettings,
+        mut settings: DeviceSettings,
     ) -> Result<Device, VulkanError> {
-        let push_constant_size = unsafe {
-            instance
-                .core
-                .get_physical_device_properties(physical_device.handle)
-        }
-        .limits
-        .max_push_constants_size;
+        //Zero frames in flight would leave nowhere to record into
+        settings.frames_in_flight = settings.frames_in_flight.max(1);
 
-        let device = AshDevice::new(instance, &physical_device).map(Arc::new)?;
-        let resource_manager = ResourceManager::new(device.clone(), settings.frames_in_flight);
+        let capabilities = physical_device.capabilities();
+        let push_constant_size = capabilities.limits.max_push_constants_size;
+
+        let device = AshDevice::new(instance, &physical_device, &settings).map(Arc::new)?;
+        let resource_manager = ResourceManager::new(
+            device.clone(),
+            settings.frames_in_flight,
+            settings.validate_bindings,
+        );
         let swapchain_manager = SwapchainManager::new(device.instance.clone());
 
         let pipelines = Pipelines::new(device.clone(), unsafe {
@@ -268,15 +325,23 @@ impl Device {
 
         Ok(Device {
             settings,
+            capabilities,
             device,
             pipelines,
             resource_manager,
             swapchain_manager,
             upload_queue,
             graph_executor,
+            frame_arena: FrameArena::default(),
+            defragmenter: Defragmenter::default(),
+            pending_reads: Vec::new(),
         })
     }
 
+    pub fn capabilities(&self) -> &DeviceCapabilities {
+        &self.capabilities
+    }
+
     pub fn create_buffer(
         &mut self,
         name: &str,
@@ -365,6 +430,17 @@ impl Device {
             self.resource_manager.add_image(image),
         ))
     }
+    pub fn create_image_3d(
+        &mut self,
+        name: &str,
+        description: &ImageDescription3D,
+    ) -> Result<ImageHandle, VulkanError> {
+        let image = Image::new_3d(self.device.clone(), name, description)?;
+
+        Ok(ImageHandle::Persistent(
+            self.resource_manager.add_image(image),
+        ))
+    }
     pub fn destroy_image(&mut self, image_handle: ImageHandle) {
         match image_handle {
             ImageHandle::Persistent(key) => self.resource_manager.remove_image(key),
@@ -378,6 +454,28 @@ impl Device {
         image_handle: ImageHandle,
         image_size: [u32; 2],
         data: &[u8],
+    ) -> Result<(), VulkanError> {
+        self.update_data_to_image_mip(image_handle, 0, image_size, data)
+    }
+
+    /// `mip_size` is the size of the mip level in pixels, not the base image size
+    pub fn update_data_to_image_mip(
+        &mut self,
+        image_handle: ImageHandle,
+        mip_level: u32,
+        mip_size: [u32; 2],
+        data: &[u8],
+    ) -> Result<(), VulkanError> {
+        self.upload_image_data(image_handle, mip_level, [mip_size[0], mip_size[1], 1], data)
+    }
+
+    /// Copies `data` into a mip level through a staging buffer, the copy happens at the start of the next frame
+    fn upload_image_data(
+        &mut self,
+        image_handle: ImageHandle,
+        mip_level: u32,
+        copy_size: [u32; 3],
+        data: &[u8],
     ) -> Result<(), VulkanError> {
         let mut staging_buffer = Buffer::new(
             self.device.clone(),
@@ -406,8 +504,9 @@ impl Device {
             ImageCopyImage {
                 image: image_handle,
                 offset: [0, 0],
+                mip_level,
             },
-            image_size,
+            copy_size,
         );
 
         //Destroy stating buffer once frame is done
@@ -415,6 +514,18 @@ impl Device {
 
         Ok(())
     }
+    /// Fills every slice of the first mip level, `data` is tightly packed slices one after another
+    pub fn create_image_3d_init(
+        &mut self,
+        name: &str,
+        description: &ImageDescription3D,
+        data: &[u8],
+    ) -> Result<ImageHandle, VulkanError> {
+        let image = self.create_image_3d(name, description)?;
+        self.upload_image_data(image, 0, description.size, data)?;
+        Ok(image)
+    }
+
     pub fn create_image_init(
         &mut self,
         name: &str,
@@ -426,6 +537,71 @@ impl Device {
         Ok(image)
     }
 
+    /// Creates an image with every mip level filled, `mip_data` must have one entry per mip level
+    pub fn create_image_init_mips(
+        &mut self,
+        name: &str,
+        description: &ImageDescription2D,
+        mip_data: &[&[u8]],
+    ) -> Result<ImageHandle, VulkanError> {
+        let image = self.create_image(name, description)?;
+        for (mip_level, data) in mip_data
+            .iter()
+            .enumerate()
+            .take(description.mip_levels as usize)
+        {
+            let mip_size = description.size.map(|size| (size >> mip_level).max(1));
+            self.update_data_to_image_mip(image, mip_level as u32, mip_size, data)?;
+        }
+        Ok(image)
+    }
+
+    /// Fills mips 1 and up by blitting each level down from the one above, queued after any uploads so mip 0 can be uploaded first.
+    /// The image needs TRANSFER_SRC and TRANSFER_DST usage and a format that can be blitted, so compressed textures still need their mips made offline
+    pub fn generate_mipmaps(&mut self, image_handle: ImageHandle) -> Result<(), VulkanError> {
+        let image = match image_handle {
+            ImageHandle::Persistent(key) => self.resource_manager.get_image(key),
+            ImageHandle::Transient(_) => None,
+        }
+        .ok_or_else(|| {
+            VulkanError::MipGeneration(format!("{:?} isn't a valid image", image_handle))
+        })?;
+
+        let required_usage = vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST;
+        if !image.usage.contains(required_usage) {
+            return Err(VulkanError::MipGeneration(format!(
+                "{:?} wasn't created with TRANSFER_SRC and TRANSFER_DST usage",
+                image_handle
+            )));
+        }
+
+        let mut required_features =
+            vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::BLIT_DST;
+        if vk_format_get_aspect_flags(image.format) == vk::ImageAspectFlags::COLOR {
+            required_features |= vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+        }
+        let format_properties = unsafe {
+            self.device
+                .instance
+                .core
+                .get_physical_device_format_properties(self.device.physical, image.format)
+        };
+        if !format_properties
+            .optimal_tiling_features
+            .contains(required_features)
+        {
+            return Err(VulkanError::MipGeneration(format!(
+                "{:?} images can't be blitted",
+                image.format
+            )));
+        }
+
+        if image.mip_levels > 1 {
+            self.upload_queue.add_generate_mips(image_handle);
+        }
+        Ok(())
+    }
+
     pub fn create_sampler(
         &mut self,
         name: &str,
@@ -480,12 +656,20 @@ impl Device {
         &mut self,
         shader: &ShaderStage,
     ) -> Result<ComputePipelineHandle, VulkanError> {
-        Ok(ComputePipelineHandle(self.pipelines.compute.insert(
-            ComputePipeline::new(self.device.clone(), self.pipelines.layout, shader)?,
-        )))
+        let workgroup_size = crate::spirv::compute_workgroup_size(shader.code, shader.entry)?;
+        Ok(ComputePipelineHandle {
+            key: self.pipelines.compute.insert(ComputePipeline::new(
+                self.device.clone(),
+                self.pipelines.layout,
+                shader,
+            )?),
+            workgroup_size,
+        })
     }
     pub fn destroy_compute_pipeline(&mut self, compute_pipeline_handle: ComputePipelineHandle) {
-        drop(self.pipelines.compute.remove(compute_pipeline_handle.0))
+        if let Some(pipeline) = self.pipelines.compute.remove(compute_pipeline_handle.key) {
+            self.resource_manager.destroy_later(Box::new(pipeline));
+        }
     }
 
     //TODO: allow multiple creation of multiple pipelines at once?
@@ -499,7 +683,9 @@ impl Device {
         )))
     }
     pub fn destroy_raster_pipeline(&mut self, raster_pipeline_handle: RasterPipelineHandle) {
-        drop(self.pipelines.raster.remove(raster_pipeline_handle.0))
+        if let Some(pipeline) = self.pipelines.raster.remove(raster_pipeline_handle.0) {
+            self.resource_manager.destroy_later(Box::new(pipeline));
+        }
     }
 
     pub fn configure_surface(
@@ -519,20 +705,172 @@ impl Device {
 
         Ok(())
     }
+    /// Formats the surface can present, used to pick a swapchain format before `configure_surface`
+    pub fn get_surface_formats(
+        &self,
+        surface_handle: SurfaceHandle,
+    ) -> Result<Vec<vk::SurfaceFormatKHR>, VulkanError> {
+        let surface = match self.device.instance.surface_list.get(surface_handle.0) {
+            None => return Err(vk::Result::ERROR_SURFACE_LOST_KHR.into()),
+            Some(surface) => surface,
+        };
+        Ok(unsafe {
+            self.device
+                .instance
+                .surface
+                .get_physical_device_surface_formats(self.device.physical, surface)
+        }?)
+    }
+
+    /// Present modes the surface supports, FIFO is always one of them
+    pub fn get_surface_present_modes(
+        &self,
+        surface_handle: SurfaceHandle,
+    ) -> Result<Vec<vk::PresentModeKHR>, VulkanError> {
+        let surface = match self.device.instance.surface_list.get(surface_handle.0) {
+            None => return Err(vk::Result::ERROR_SURFACE_LOST_KHR.into()),
+            Some(surface) => surface,
+        };
+        Ok(unsafe {
+            self.device
+                .instance
+                .surface
+                .get_physical_device_surface_present_modes(self.device.physical, surface)
+        }?)
+    }
+
     pub fn release_surface(&mut self, surface_handle: SurfaceHandle) {
         self.swapchain_manager.remove(surface_handle);
     }
 
-    pub fn submit_graph(&mut self, render_graph: &CompiledRenderGraph) -> Result<(), VulkanError> {
-        self.graph_executor.submit_frame(
+    /// A builder that reuses the allocations of the last submitted graph, any pending readbacks are added to the end of it
+    pub fn render_graph_builder(&mut self) -> BasicRenderGraphBuilder {
+        let mut builder = self.frame_arena.builder();
+        builder.add_device_reads(std::mem::take(&mut self.pending_reads));
+        builder
+    }
+
+    /// Copies `size` bytes of a persistent buffer back after everything in the next graph from `render_graph_builder`,
+    /// the future is ready once that frame's fence is signaled. The buffer needs `TRANSFER_SRC` usage
+    pub fn read_buffer(
+        &mut self,
+        buffer: BufferHandle,
+        offset: usize,
+        size: usize,
+    ) -> Result<VulkanFuture<Vec<u8>>, VulkanError> {
+        let buffer_resource = match buffer {
+            BufferHandle::Persistent(key) => self.resource_manager.get_buffer(key),
+            BufferHandle::Transient(_) => None,
+        }
+        .ok_or_else(|| VulkanError::Readback(format!("{:?} isn't a valid buffer", buffer)))?;
+
+        if !buffer_resource
+            .usage
+            .contains(vk::BufferUsageFlags::TRANSFER_SRC)
+        {
+            return Err(VulkanError::Readback(format!(
+                "{:?} wasn't created with TRANSFER_SRC usage",
+                buffer
+            )));
+        }
+        if offset
+            .checked_add(size)
+            .is_none_or(|end| end as vk::DeviceSize > buffer_resource.size)
+        {
+            return Err(VulkanError::Readback(format!(
+                "Reading {} bytes at offset {} is past the end of {:?} ({} bytes)",
+                size, offset, buffer, buffer_resource.size
+            )));
+        }
+
+        let future = VulkanFuture::new();
+        self.pending_reads.push(DeviceRead::Buffer {
+            buffer_offset: BufferOffset { buffer, offset },
+            size,
+            callback: read_callback(&future),
+        });
+        Ok(future)
+    }
+
+    /// Reads back mip 0 of a persistent image tightly packed, like `read_buffer` it happens at the end of the next graph.
+    /// The image needs `TRANSFER_SRC` usage and an uncompressed format
+    pub fn read_image(
+        &mut self,
+        image_handle: ImageHandle,
+    ) -> Result<VulkanFuture<Vec<u8>>, VulkanError> {
+        let image = match image_handle {
+            ImageHandle::Persistent(key) => self.resource_manager.get_image(key),
+            ImageHandle::Transient(_) => None,
+        }
+        .ok_or_else(|| VulkanError::Readback(format!("{:?} isn't a valid image", image_handle)))?;
+
+        if !image.usage.contains(vk::ImageUsageFlags::TRANSFER_SRC) {
+            return Err(VulkanError::Readback(format!(
+                "{:?} wasn't created with TRANSFER_SRC usage",
+                image_handle
+            )));
+        }
+        let texel_size = vk_format_get_texel_size(image.format).ok_or_else(|| {
+            VulkanError::Readback(format!("Can't read back {:?} images", image.format))
+        })?;
+
+        let future = VulkanFuture::new();
+        self.pending_reads.push(DeviceRead::Image {
+            image: image_handle,
+            size: [image.size.width, image.size.height],
+            texel_size,
+            callback: read_callback(&future),
+        });
+        Ok(future)
+    }
+
+    pub fn submit_graph(&mut self, render_graph: CompiledRenderGraph) -> Result<(), VulkanError> {
+        if self.settings.defragment {
+            self.defragmenter
+                .step(&mut self.resource_manager, &mut self.upload_queue)?;
+        }
+
+        let upload_passes = self.upload_queue.get_passes(
+            &self.resource_manager,
+            self.graph_executor.uploads_on_transfer_queue(&render_graph),
+        );
+        let result = self.graph_executor.submit_frame(
             &mut self.resource_manager,
             &mut self.swapchain_manager,
             &self.pipelines,
-            self.upload_queue.get_pass(),
-            render_graph,
-        )?;
-        Ok(())
+            upload_passes,
+            &render_graph,
+        );
+        self.frame_arena.recycle(render_graph);
+        result
+    }
+
+    /// Per-pass timings and memory stats of the most recently completed frame
+    pub fn frame_profile(&self) -> Option<&FrameProfile> {
+        self.graph_executor.last_profile()
+    }
+
+    /// Draw, upload, transient and barrier counts of the most recently submitted frame
+    pub fn frame_stats(&self) -> &FrameStats {
+        self.graph_executor.last_stats()
+    }
+
+    /// Device local memory fragmentation and how much the defragmenter has moved, zeroed if it's disabled
+    pub fn defragment_stats(&self) -> &DefragmentStats {
+        self.defragmenter.stats()
     }
+
+    /// How far the gpu got through each frame still in flight, for crash reports after a submit fails
+    pub fn breadcrumbs(&self) -> Vec<FrameBreadcrumbs> {
+        self.graph_executor.breadcrumbs()
+    }
+}
+
+fn read_callback(future: &VulkanFuture<Vec<u8>>) -> BufferReadCallback {
+    let slot = future.slot();
+    BufferReadCallback::new(move |slice| {
+        *slot.lock().unwrap() = Some(slice.to_vec());
+    })
 }
 
 impl Drop for Device {
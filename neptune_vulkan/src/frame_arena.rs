@@ -0,0 +1,90 @@
+use crate::basic_render_graph_builder::BasicRenderGraphBuilder;
+use crate::render_graph::{BufferIndex, CompiledRenderGraph, ImageIndex, RenderPassSet};
+use crate::resource_managers::{BufferResourceAccess, ImageResourceAccess};
+use crate::{BufferHandle, ImageHandle};
+use std::collections::HashMap;
+
+pub(crate) type BufferUsages = Vec<(BufferIndex, BufferResourceAccess)>;
+pub(crate) type ImageUsages = Vec<(ImageIndex, ImageResourceAccess)>;
+
+/// How many pooled objects the last graph reused versus had to allocate
+#[derive(Debug, Default, Copy, Clone)]
+pub struct FrameArenaStats {
+    pub reused: u32,
+    pub allocated: u32,
+}
+
+/// Cleared allocations from previously submitted graphs, it travels inside the graph from the builder to the device and back to the arena
+#[derive(Debug, Default)]
+pub(crate) struct GraphStorage {
+    render_pass_sets: Vec<RenderPassSet>,
+    pub(crate) buffer_index_map: HashMap<BufferHandle, BufferIndex>,
+    pub(crate) image_index_map: HashMap<ImageHandle, ImageIndex>,
+    pub(crate) buffer_usages: BufferUsages,
+    pub(crate) image_usages: ImageUsages,
+    pub(crate) stats: FrameArenaStats,
+}
+
+impl GraphStorage {
+    /// An empty pass set, reusing a recycled one's vecs if there are any
+    pub(crate) fn render_pass_set(&mut self) -> RenderPassSet {
+        match self.render_pass_sets.pop() {
+            Some(render_pass_set) => {
+                self.stats.reused += 1;
+                render_pass_set
+            }
+            None => {
+                self.stats.allocated += 1;
+                RenderPassSet::default()
+            }
+        }
+    }
+}
+
+/// Per-frame storage for building render graphs, so the same vecs and maps aren't reallocated every frame.
+/// Owned by the `Device`, graphs built with `Device::render_graph_builder` are recycled once `Device::submit_graph` is done with them
+#[derive(Debug, Default)]
+pub(crate) struct FrameArena {
+    graph: Option<CompiledRenderGraph>,
+}
+
+impl FrameArena {
+    pub(crate) fn builder(&mut self) -> BasicRenderGraphBuilder {
+        match self.graph.take() {
+            Some(graph) => BasicRenderGraphBuilder::from_recycled(graph),
+            None => BasicRenderGraphBuilder::default(),
+        }
+    }
+
+    /// Clears the graph while keeping its allocations for the next builder, callbacks are dropped here
+    pub(crate) fn recycle(&mut self, mut graph: CompiledRenderGraph) {
+        graph.buffer_writes.clear();
+        graph.buffer_reads.clear();
+        graph.buffer_resources.clear();
+        graph.image_resources.clear();
+        graph.swapchain_images.clear();
+
+        //The basic builder only ever records to the first command buffer
+        graph.command_buffers.truncate(1);
+        let storage = &mut graph.storage;
+        for command_buffer in graph.command_buffers.iter_mut() {
+            command_buffer.command_buffer_wait_dependencies.clear();
+            command_buffer.command_buffer_signal_dependencies.clear();
+            for mut render_pass_set in command_buffer.render_pass_sets.drain(..) {
+                render_pass_set.memory_barriers.clear();
+                render_pass_set.buffer_barriers.clear();
+                render_pass_set.image_barriers.clear();
+                render_pass_set.render_passes.clear();
+                storage.render_pass_sets.push(render_pass_set);
+            }
+        }
+
+        storage.buffer_index_map.clear();
+        storage.image_index_map.clear();
+        storage.buffer_usages.clear();
+        storage.image_usages.clear();
+        storage.stats = FrameArenaStats::default();
+
+        self.graph = Some(graph);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::descriptor_set::{DescriptorBinding, GpuBindingIndex};
 use crate::device::AshDevice;
-use crate::{ImageHandle, VulkanError};
+use crate::{ImageHandle, SurfaceHandle, VulkanError};
 use ash::vk;
 use std::sync::Arc;
 
@@ -17,10 +17,70 @@ pub fn vk_format_get_aspect_flags(format: vk::Format) -> vk::ImageAspectFlags {
     }
 }
 
+/// Bytes per pixel of uncompressed formats, None for anything else
+pub fn vk_format_get_texel_size(format: vk::Format) -> Option<usize> {
+    Some(match format {
+        vk::Format::R8_UNORM | vk::Format::R8_UINT | vk::Format::S8_UINT => 1,
+        vk::Format::R8G8_UNORM
+        | vk::Format::R16_UNORM
+        | vk::Format::R16_UINT
+        | vk::Format::R16_SFLOAT
+        | vk::Format::D16_UNORM => 2,
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::A2B10G10R10_UNORM_PACK32
+        | vk::Format::A2R10G10B10_UNORM_PACK32
+        | vk::Format::B10G11R11_UFLOAT_PACK32
+        | vk::Format::R16G16_SFLOAT
+        | vk::Format::R32_UINT
+        | vk::Format::R32_SFLOAT
+        | vk::Format::D32_SFLOAT => 4,
+        vk::Format::R16G16B16A16_UNORM
+        | vk::Format::R16G16B16A16_SFLOAT
+        | vk::Format::R32G32_SFLOAT => 8,
+        vk::Format::R32G32B32A32_UINT | vk::Format::R32G32B32A32_SFLOAT => 16,
+        _ => return None,
+    })
+}
+
+/// Sizes are resolved when the graph executes, so images that follow a surface resize along with it.
+/// Every size is at least 1 pixel
 #[derive(Debug, Clone)]
 pub enum TransientImageSize {
     Exact(vk::Extent2D),
+    /// Size of another image scaled, rounded down
     Relative([f32; 2], ImageHandle),
+    /// Size of a surface's swapchain images scaled, rounded down
+    RelativeSurface([f32; 2], SurfaceHandle),
+    /// Size of another image divided, rounded down like mip levels so [4, 4] matches mip 2 of the target
+    Divided([u32; 2], ImageHandle),
+    /// Size of a surface's swapchain images divided, rounded down like mip levels
+    DividedSurface([u32; 2], SurfaceHandle),
+}
+
+impl TransientImageSize {
+    /// The same size as `target`'s mip `level`, e.g. for a chain of bloom images
+    pub fn mip_of(target: ImageHandle, level: u32) -> Self {
+        //Any level past 31 is already 1x1, clamped so the shift can't overflow
+        let divisor = 1u32 << level.min(u32::BITS - 1);
+        Self::Divided([divisor; 2], target)
+    }
+
+    pub(crate) fn scale_extent(extent: vk::Extent2D, scale: [f32; 2]) -> vk::Extent2D {
+        vk::Extent2D {
+            width: ((extent.width as f32 * scale[0]) as u32).max(1),
+            height: ((extent.height as f32 * scale[1]) as u32).max(1),
+        }
+    }
+
+    pub(crate) fn divide_extent(extent: vk::Extent2D, divisor: [u32; 2]) -> vk::Extent2D {
+        vk::Extent2D {
+            width: (extent.width / divisor[0].max(1)).max(1),
+            height: (extent.height / divisor[1].max(1)).max(1),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -53,6 +113,22 @@ pub struct ImageDescription2D {
     pub location: gpu_allocator::MemoryLocation,
 }
 
+impl ImageDescription2D {
+    /// Mip count of a full pyramid down to 1x1 for an image of this size
+    pub fn full_mip_levels(size: [u32; 2]) -> u32 {
+        u32::BITS - size[0].max(size[1]).max(1).leading_zeros()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageDescription3D {
+    pub size: [u32; 3],
+    pub format: vk::Format,
+    pub usage: vk::ImageUsageFlags,
+    pub mip_levels: u32,
+    pub location: gpu_allocator::MemoryLocation,
+}
+
 pub struct Image {
     pub device: Arc<AshDevice>,
     pub handle: vk::Image,
@@ -61,6 +137,7 @@ pub struct Image {
     pub size: vk::Extent2D,
     pub format: vk::Format,
     pub usage: vk::ImageUsageFlags,
+    pub mip_levels: u32,
     pub location: gpu_allocator::MemoryLocation,
     pub storage_binding: Option<DescriptorBinding>,
     pub sampled_binding: Option<DescriptorBinding>,
@@ -71,6 +148,42 @@ impl Image {
         device: Arc<AshDevice>,
         name: &str,
         description: &ImageDescription2D,
+    ) -> Result<Self, VulkanError> {
+        Self::new(
+            device,
+            name,
+            vk::ImageType::TYPE_2D,
+            vk::ImageViewType::TYPE_2D,
+            &ImageDescription3D {
+                size: [description.size[0], description.size[1], 1],
+                format: description.format,
+                usage: description.usage,
+                mip_levels: description.mip_levels,
+                location: description.location,
+            },
+        )
+    }
+
+    pub fn new_3d(
+        device: Arc<AshDevice>,
+        name: &str,
+        description: &ImageDescription3D,
+    ) -> Result<Self, VulkanError> {
+        Self::new(
+            device,
+            name,
+            vk::ImageType::TYPE_3D,
+            vk::ImageViewType::TYPE_3D,
+            description,
+        )
+    }
+
+    fn new(
+        device: Arc<AshDevice>,
+        name: &str,
+        image_type: vk::ImageType,
+        view_type: vk::ImageViewType,
+        description: &ImageDescription3D,
     ) -> Result<Self, VulkanError> {
         let handle = unsafe {
             device.core.create_image(
@@ -79,13 +192,13 @@ impl Image {
                     .extent(vk::Extent3D {
                         width: description.size[0],
                         height: description.size[1],
-                        depth: 1,
+                        depth: description.size[2],
                     })
                     .usage(description.usage)
                     .array_layers(1)
                     .mip_levels(description.mip_levels)
                     .samples(vk::SampleCountFlags::TYPE_1)
-                    .image_type(vk::ImageType::TYPE_2D),
+                    .image_type(image_type),
                 None,
             )
         }?;
@@ -134,7 +247,7 @@ impl Image {
                 base_array_layer: 0,
                 layer_count: 1,
             })
-            .view_type(vk::ImageViewType::TYPE_2D);
+            .view_type(view_type);
 
         let view = match unsafe { device.core.create_image_view(&view_create_info, None) } {
             Ok(view) => view,
@@ -158,6 +271,7 @@ impl Image {
             },
             format: description.format,
             usage: description.usage,
+            mip_levels: description.mip_levels,
             location: description.location,
             storage_binding: None,
             sampled_binding: None,
@@ -171,6 +285,7 @@ impl Image {
             size: self.size,
             format: self.format,
             usage: self.usage,
+            mip_levels: self.mip_levels,
             location: self.location,
             storage_binding: self.storage_binding.as_ref().map(|binding| binding.index()),
             sampled_binding: self.sampled_binding.as_ref().map(|binding| binding.index()),
@@ -201,6 +316,7 @@ pub struct AshImage {
     pub size: vk::Extent2D,
     pub format: vk::Format,
     pub usage: vk::ImageUsageFlags,
+    pub mip_levels: u32,
     pub location: gpu_allocator::MemoryLocation,
     pub storage_binding: Option<GpuBindingIndex>,
     pub sampled_binding: Option<GpuBindingIndex>,
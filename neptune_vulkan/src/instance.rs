@@ -3,7 +3,7 @@ use crate::physical_device::PhysicalDevice;
 use crate::{SurfaceHandle, SurfaceKey, VulkanError};
 use ash::prelude::VkResult;
 use ash::vk;
-use log::trace;
+use log::{trace, warn};
 use slotmap::SlotMap;
 use std::ffi::CString;
 use std::sync::{Arc, Mutex};
@@ -29,6 +29,19 @@ impl<'a> AppInfo<'a> {
     }
 }
 
+#[derive(Debug, Default, Clone)]
+pub struct InstanceSettings {
+    /// Lets shaders use `debugPrintfEXT` (with `#extension GL_EXT_debug_printf : enable`), output is logged with the `shader` target.
+    /// Done by the validation layer, so it slows down every draw and dispatch
+    pub debug_printf: bool,
+    /// Checks for out of bounds and unwritten descriptor access inside shaders, can't be used along with `debug_printf`
+    pub gpu_assisted: bool,
+    /// Reports missing or wrong barriers between commands and queues
+    pub sync_validation: bool,
+    /// Warns about valid but slow usage of the api
+    pub best_practices: bool,
+}
+
 pub struct SurfaceList(Mutex<SlotMap<SurfaceKey, vk::SurfaceKHR>>);
 
 impl SurfaceList {
@@ -63,6 +76,7 @@ impl AshInstance {
         engine_info: &AppInfo,
         app_info: &AppInfo,
         enable_debug: bool,
+        settings: &InstanceSettings,
         display_handle: Option<raw_window_handle::RawDisplayHandle>,
     ) -> VkResult<Self> {
         trace!(
@@ -118,10 +132,44 @@ impl AshInstance {
             .engine_name(engine_name.as_c_str())
             .engine_version(engine_version);
 
-        let create_info = vk::InstanceCreateInfo::builder()
+        let mut enabled_validation_features = Vec::new();
+        if enable_debug {
+            //Both are done by instrumenting shaders, the layer only allows one at a time
+            if settings.debug_printf {
+                if settings.gpu_assisted {
+                    warn!("Gpu assisted validation can't be used with shader printf, it will be disabled");
+                }
+                enabled_validation_features.push(vk::ValidationFeatureEnableEXT::DEBUG_PRINTF);
+            } else if settings.gpu_assisted {
+                enabled_validation_features.extend([
+                    vk::ValidationFeatureEnableEXT::GPU_ASSISTED,
+                    vk::ValidationFeatureEnableEXT::GPU_ASSISTED_RESERVE_BINDING_SLOT,
+                ]);
+            }
+            if settings.sync_validation {
+                enabled_validation_features
+                    .push(vk::ValidationFeatureEnableEXT::SYNCHRONIZATION_VALIDATION);
+            }
+            if settings.best_practices {
+                enabled_validation_features.push(vk::ValidationFeatureEnableEXT::BEST_PRACTICES);
+            }
+        } else if settings.debug_printf
+            || settings.gpu_assisted
+            || settings.sync_validation
+            || settings.best_practices
+        {
+            warn!("Validation features were requested without the validation layer, they will be ignored");
+        }
+        let mut validation_features = vk::ValidationFeaturesEXT::builder()
+            .enabled_validation_features(&enabled_validation_features);
+
+        let mut create_info = vk::InstanceCreateInfo::builder()
             .application_info(&app_info)
             .enabled_layer_names(&layer_names_raw)
             .enabled_extension_names(&extension_names_raw);
+        if !enabled_validation_features.is_empty() {
+            create_info = create_info.push_next(&mut validation_features);
+        }
 
         let instance: ash::Instance = unsafe { entry.create_instance(&create_info, None)? };
 
@@ -182,10 +230,11 @@ impl Instance {
     pub fn new(
         engine_info: &AppInfo,
         app_info: &AppInfo,
+        settings: InstanceSettings,
         display_handle: Option<raw_window_handle::RawDisplayHandle>,
     ) -> Result<Self, VulkanError> {
-        let instance =
-            AshInstance::new(engine_info, app_info, true, display_handle).map(Arc::new)?;
+        let instance = AshInstance::new(engine_info, app_info, true, &settings, display_handle)
+            .map(Arc::new)?;
 
         let physical_devices = unsafe { instance.core.enumerate_physical_devices() }
             .expect("Failed to enumerate physical devices")
@@ -214,6 +263,10 @@ impl Instance {
         self.instance.destroy_surface(surface_handle.0)
     }
 
+    pub fn physical_devices(&self) -> &[PhysicalDevice] {
+        &self.physical_devices
+    }
+
     pub fn get_physical_device(&self, index: usize) -> Option<PhysicalDevice> {
         self.physical_devices.get(index).cloned()
     }
@@ -1,16 +1,23 @@
 mod buffer;
+mod capabilities;
 mod debug_utils;
+mod defragment;
 mod descriptor_set;
 mod device;
+mod frame_arena;
 mod image;
 mod instance;
+mod name;
 mod physical_device;
 mod pipeline;
+mod profiler;
 mod resource_managers;
 mod sampler;
+mod spirv;
 mod swapchain;
 
 pub mod basic_render_graph_builder;
+pub mod blackboard;
 pub mod render_graph;
 pub mod render_graph_builder;
 mod render_graph_executor;
@@ -23,15 +30,25 @@ pub use gpu_allocator;
 
 use crate::render_graph::BufferIndex;
 
-pub use buffer::BufferUsage;
+pub use buffer::{BufferUsage, TransientBufferSize};
+pub use capabilities::{DeviceCapabilities, DeviceLimits, MemoryHeapInfo};
+pub use defragment::DefragmentStats;
 pub use device::{Device, DeviceSettings};
-pub use image::{ImageDescription2D, TransientImageDesc, TransientImageSize};
-pub use instance::{AppInfo, Instance};
+pub use frame_arena::FrameArenaStats;
+pub use image::{ImageDescription2D, ImageDescription3D, TransientImageDesc, TransientImageSize};
+pub use instance::{AppInfo, Instance, InstanceSettings};
+pub use name::NameId;
 pub use physical_device::*;
 pub use pipeline::{
-    ColorTargetState, DepthState, FragmentState, FramebufferDesc, PrimitiveState,
-    RasterPipelineDescription, ShaderStage, VertexAttribute, VertexBufferLayout, VertexState,
+    BlendState, ColorTargetState, DepthMode, DepthState, FragmentState, FramebufferDesc,
+    PrimitiveState, RasterPipelineDescription, ShaderStage, VertexAttribute, VertexBufferLayout,
+    VertexState,
 };
+pub use profiler::{
+    FrameBreadcrumbs, FrameProfile, FrameStats, ImageBarrierStats, PassBarrierStats, PassStats,
+    SyncWarning,
+};
+pub use resource_managers::ImageResourceAccess;
 pub use sampler::*;
 pub use swapchain::SurfaceSettings;
 
@@ -99,12 +116,26 @@ pub struct ImageSetHandle(ImageSetKey);
 #[derive(Copy, Clone, Debug)]
 pub struct SamplerSetHandle(SamplerSetKey);
 
-#[repr(transparent)]
 #[derive(Copy, Clone, Debug)]
-pub struct ComputePipelineHandle(ComputePipelineKey);
+pub struct ComputePipelineHandle {
+    key: ComputePipelineKey,
+    workgroup_size: [u32; 3],
+}
+
+impl ComputePipelineHandle {
+    /// The local size reflected from the shader when the pipeline was created
+    pub fn workgroup_size(&self) -> [u32; 3] {
+        self.workgroup_size
+    }
+
+    /// Number of workgroups needed to cover `thread_count` invocations in each dimension
+    pub fn group_count(&self, thread_count: [u32; 3]) -> [u32; 3] {
+        [0, 1, 2].map(|i| thread_count[i].div_ceil(self.workgroup_size[i]))
+    }
+}
 
 #[repr(transparent)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct RasterPipelineHandle(RasterPipleineKey);
 
 #[derive(thiserror::Error, Debug)]
@@ -123,10 +154,38 @@ pub enum VulkanError {
     GpuAllocator(#[from] gpu_allocator::AllocationError),
     #[error("BufferWriteError: {0}")]
     BufferWriteError(#[from] BufferWriteError),
+    #[error("Shader Reflection Error: {0}")]
+    ShaderReflection(String),
+    #[error("Readback Error: {0}")]
+    Readback(String),
+    #[error("Mip Generation Error: {0}")]
+    MipGeneration(String),
 }
 
 /// Similar to promise/future in c++ and rust async. The contained type will be available sometime later
 /// User should check it once a frame to see if it's ready.
 pub struct VulkanFuture<T> {
-    _phantom: std::marker::PhantomData<T>,
+    value: std::sync::Arc<std::sync::Mutex<Option<T>>>,
+}
+
+impl<T> VulkanFuture<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            value: Default::default(),
+        }
+    }
+
+    /// Shared slot the value is written into once it's ready
+    pub(crate) fn slot(&self) -> std::sync::Arc<std::sync::Mutex<Option<T>>> {
+        self.value.clone()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.value.lock().unwrap().is_some()
+    }
+
+    /// Takes the value if it's ready, only the first call after that gets it
+    pub fn try_take(&mut self) -> Option<T> {
+        self.value.lock().unwrap().take()
+    }
 }
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::{LazyLock, RwLock};
+
+/// Lookup table for every name interned so far, names are leaked since there's only ever a handful of distinct ones
+#[derive(Default)]
+struct NameTable {
+    names: Vec<&'static str>,
+    /// Nul terminated copies for vulkan debug labels
+    c_names: Vec<&'static CStr>,
+    ids: HashMap<&'static str, NameId>,
+}
+
+static NAME_TABLE: LazyLock<RwLock<NameTable>> = LazyLock::new(Default::default);
+
+/// An interned pass or resource name, copying and comparing one is free.
+/// Only the first use of a name allocates, after that it's a table lookup, and the string is still there for profiling and debug labels
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+pub struct NameId(u32);
+
+impl NameId {
+    pub fn new(name: &str) -> Self {
+        if let Some(id) = NAME_TABLE
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .ids
+            .get(name)
+        {
+            return *id;
+        }
+
+        let mut table = NAME_TABLE.write().unwrap_or_else(|err| err.into_inner());
+        //Another thread may have added it between the locks
+        if let Some(id) = table.ids.get(name) {
+            return *id;
+        }
+        let id = NameId(table.names.len() as u32);
+        let c_name: &'static CStr = Box::leak(
+            CString::new(name)
+                .expect("Name contains a nul")
+                .into_boxed_c_str(),
+        );
+        let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+        table.names.push(name);
+        table.c_names.push(c_name);
+        table.ids.insert(name, id);
+        id
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        NAME_TABLE
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .names[self.0 as usize]
+    }
+
+    pub fn as_c_str(&self) -> &'static CStr {
+        NAME_TABLE
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .c_names[self.0 as usize]
+    }
+}
+
+impl From<&str> for NameId {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+impl Display for NameId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for NameId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NameId({}: {:?})", self.0, self.as_str())
+    }
+}
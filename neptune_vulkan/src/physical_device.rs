@@ -1,3 +1,4 @@
+use crate::capabilities::DeviceCapabilities;
 use crate::device::DeviceSettings;
 use crate::instance::AshInstance;
 use crate::{Device, SurfaceHandle, VulkanError};
@@ -199,6 +200,10 @@ impl Debug for PhysicalDeviceQueueInfo {
 pub struct PhysicalDeviceExtensionInfo {
     pub raytracing_support: bool,
     pub mesh_shader_support: bool,
+    /// Both robustBufferAccess2 and robustImageAccess2 are supported
+    pub robustness2_support: bool,
+    /// Descriptors can be written with null handles, needs VK_EXT_robustness2 too
+    pub null_descriptor_support: bool,
 }
 
 #[derive(Clone)]
@@ -302,6 +307,18 @@ impl PhysicalDevice {
         }
         .unwrap_or_default();
 
+        //The extension being listed doesn't mean every feature in it is, so they're queried separately
+        let mut robustness2_features = vk::PhysicalDeviceRobustness2FeaturesEXT::default();
+        if supports_extension(&extension_list, vk::ExtRobustness2Fn::name()) {
+            let mut features2 =
+                vk::PhysicalDeviceFeatures2::builder().push_next(&mut robustness2_features);
+            unsafe {
+                instance
+                    .core
+                    .get_physical_device_features2(physical_device, &mut features2);
+            }
+        }
+
         let extension = PhysicalDeviceExtensionInfo {
             raytracing_support: supports_extension(
                 &extension_list,
@@ -318,6 +335,9 @@ impl PhysicalDevice {
                 &extension_list,
                 ash::extensions::ext::MeshShader::name(),
             ),
+            robustness2_support: robustness2_features.robust_buffer_access2 == vk::TRUE
+                && robustness2_features.robust_image_access2 == vk::TRUE,
+            null_descriptor_support: robustness2_features.null_descriptor == vk::TRUE,
         };
 
         Self {
@@ -331,6 +351,11 @@ impl PhysicalDevice {
         }
     }
 
+    /// Queries the full limits and extension list, which aren't kept on the physical device
+    pub fn capabilities(&self) -> DeviceCapabilities {
+        DeviceCapabilities::new(self)
+    }
+
     pub fn supports_graphics(&self) -> bool {
         self.queue.graphics_queue_family_index.is_some()
     }
@@ -112,10 +112,97 @@ pub struct DepthState {
     pub depth_op: vk::CompareOp,
 }
 
+/// Which end of the depth range the near plane maps to. Reversed depth puts the near plane at 1 and the far plane at 0,
+/// which pairs with float precision to avoid z-fighting far from the camera
+#[derive(PartialEq, Eq, Hash, Debug, Default, Copy, Clone)]
+pub enum DepthMode {
+    #[default]
+    Standard,
+    Reversed,
+}
+
+impl DepthMode {
+    pub fn near_depth(self) -> f32 {
+        match self {
+            DepthMode::Standard => 0.0,
+            DepthMode::Reversed => 1.0,
+        }
+    }
+
+    /// Also the value depth attachments should be cleared to
+    pub fn far_depth(self) -> f32 {
+        match self {
+            DepthMode::Standard => 1.0,
+            DepthMode::Reversed => 0.0,
+        }
+    }
+
+    /// Depth stencil clear value that leaves every pixel at the far plane
+    pub fn clear_value(self) -> (f32, u32) {
+        (self.far_depth(), 0)
+    }
+
+    /// Converts a compare op written for standard depth into the equivalent for this mode
+    pub fn compare_op(self, compare_op: vk::CompareOp) -> vk::CompareOp {
+        if self == DepthMode::Standard {
+            return compare_op;
+        }
+
+        match compare_op {
+            vk::CompareOp::LESS => vk::CompareOp::GREATER,
+            vk::CompareOp::LESS_OR_EQUAL => vk::CompareOp::GREATER_OR_EQUAL,
+            vk::CompareOp::GREATER => vk::CompareOp::LESS,
+            vk::CompareOp::GREATER_OR_EQUAL => vk::CompareOp::LESS_OR_EQUAL,
+            compare_op => compare_op,
+        }
+    }
+
+    /// Converts a standard depth value into this mode
+    pub fn map_depth(self, depth: f32) -> f32 {
+        match self {
+            DepthMode::Standard => depth,
+            DepthMode::Reversed => 1.0 - depth,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+pub enum BlendState {
+    /// src * src_alpha + dst * (1 - src_alpha)
+    AlphaBlend,
+    /// src + dst * (1 - src_alpha)
+    PremultipliedAlpha,
+    /// src * src_alpha + dst
+    Additive,
+}
+
+impl BlendState {
+    fn to_vk(self) -> vk::PipelineColorBlendAttachmentStateBuilder<'static> {
+        let (src_color, dst_color) = match self {
+            BlendState::AlphaBlend => (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+            BlendState::PremultipliedAlpha => {
+                (vk::BlendFactor::ONE, vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            }
+            BlendState::Additive => (vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE),
+        };
+        vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(true)
+            .src_color_blend_factor(src_color)
+            .dst_color_blend_factor(dst_color)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .alpha_blend_op(vk::BlendOp::ADD)
+    }
+}
+
 #[derive(PartialEq, Eq, Hash, Debug, Clone)]
 pub struct ColorTargetState {
     pub format: vk::Format,
-    pub blend: Option<()>, //TODO: blend states
+    pub blend: Option<BlendState>,
     pub write_mask: vk::ColorComponentFlags,
 }
 
@@ -286,10 +373,13 @@ impl RasterPipeline {
         if let Some(fragment_state) = &pipeline_description.fragment {
             for color_target in fragment_state.targets {
                 color_attachments_formats.push(color_target.format);
+                let blend_state = match color_target.blend {
+                    Some(blend) => blend.to_vk(),
+                    None => vk::PipelineColorBlendAttachmentState::builder().blend_enable(false),
+                };
                 color_attachments_blend_states.push(
-                    vk::PipelineColorBlendAttachmentState::builder()
+                    blend_state
                         .color_write_mask(color_target.write_mask)
-                        .blend_enable(false)
                         .build(),
                 );
             }
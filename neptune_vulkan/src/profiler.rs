@@ -0,0 +1,559 @@
+use crate::device::AshDevice;
+use crate::name::NameId;
+use crate::render_graph::{
+    CommandBuffer, CompiledRenderGraph, DrawCommandDispatch, ImageBarrierSource, ImageIndex,
+    ImageResourceDescription, RenderPassCommand,
+};
+use crate::resource_managers::ImageResourceAccess;
+use ash::vk;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Stats for a single render graph pass
+#[derive(Debug, Clone)]
+pub struct PassStats {
+    pub name: NameId,
+    /// None if the device doesn't support timestamp queries
+    pub gpu_time: Option<Duration>,
+    pub draw_count: u32,
+    /// Assumes triangle lists, indirect draws aren't included since their counts live on the gpu
+    pub triangle_count: u64,
+}
+
+/// Stats for a whole frame, only available once the frame's fence is signaled so it lags `frames_in_flight` frames behind
+#[derive(Debug, Clone, Default)]
+pub struct FrameProfile {
+    pub passes: Vec<PassStats>,
+    /// Memory allocated for this frame's transient buffers and images
+    pub transient_memory: u64,
+    /// Highest `transient_memory` of any frame since the device was created
+    pub transient_memory_watermark: u64,
+    /// Time the cpu spent blocked waiting for the frame in flight and acquiring the swapchain images
+    pub swapchain_latency: Duration,
+}
+
+impl FrameProfile {
+    pub fn gpu_time(&self) -> Option<Duration> {
+        self.passes.iter().map(|pass| pass.gpu_time).sum()
+    }
+
+    pub fn draw_count(&self) -> u32 {
+        self.passes.iter().map(|pass| pass.draw_count).sum()
+    }
+
+    pub fn triangle_count(&self) -> u64 {
+        self.passes.iter().map(|pass| pass.triangle_count).sum()
+    }
+}
+
+/// An image barrier emitted before a pass
+#[derive(Debug, Clone)]
+pub struct ImageBarrierStats {
+    /// Index of the image in the graph, in the order the graph first used them
+    pub image: ImageIndex,
+    /// None for the image's first barrier in the graph
+    pub src: Option<ImageResourceAccess>,
+    pub dst: ImageResourceAccess,
+    pub layout_transition: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PassBarrierStats {
+    pub pass: NameId,
+    pub memory_barriers: u32,
+    pub buffer_barriers: u32,
+    pub image_barriers: Vec<ImageBarrierStats>,
+}
+
+#[derive(Debug, Clone)]
+pub enum SyncWarning {
+    /// The image is written by two passes in a row, so the first write is either overwritten or needs its own pass.
+    /// Attachment writes back to back aren't flagged since that is how render passes share targets
+    WriteAfterWrite {
+        image: ImageIndex,
+        first_pass: NameId,
+        second_pass: NameId,
+    },
+    /// A barrier between two identical reads, it neither changes the layout nor guards a write
+    RedundantTransition {
+        image: ImageIndex,
+        pass: NameId,
+        access: ImageResourceAccess,
+    },
+}
+
+impl std::fmt::Display for SyncWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WriteAfterWrite {
+                image,
+                first_pass,
+                second_pass,
+            } => write!(
+                f,
+                "image {} written by {} then again by {} without a read",
+                image, first_pass, second_pass
+            ),
+            Self::RedundantTransition {
+                image,
+                pass,
+                access,
+            } => write!(
+                f,
+                "image {} has a redundant {:?} to {:?} barrier before {}",
+                image, access, access, pass
+            ),
+        }
+    }
+}
+
+/// Cpu side counts for the most recently submitted frame, unlike `FrameProfile` these are available right after `submit_graph`
+#[derive(Debug, Clone, Default)]
+pub struct FrameStats {
+    pub passes: u32,
+    /// Indirect draws count their max draw count
+    pub draw_calls: u32,
+    /// Doesn't include indirect draws, their counts live on the gpu
+    pub instances: u64,
+    /// Assumes triangle lists, also excludes indirect draws
+    pub triangles: u64,
+    pub compute_dispatches: u32,
+    /// Graph buffer writes plus the transfers queued with `update_data_to_*`
+    pub buffer_uploads: u32,
+    pub buffer_upload_bytes: u64,
+    pub buffer_reads: u32,
+    pub transient_buffers: u32,
+    pub transient_images: u32,
+    /// Memory allocated for this frame's transient buffers and images
+    pub transient_memory: u64,
+    /// Memory, buffer and image barriers the graph emits, not counting the staging and swapchain ones
+    pub barriers: u32,
+    /// Image barriers that change the layout, not counting an image's first barrier since its old layout comes from the last frame
+    pub layout_transitions: u32,
+    /// Barriers of each pass in submission order
+    pub pass_barriers: Vec<PassBarrierStats>,
+    /// Barrier patterns that are valid but probably not what the graph meant
+    pub sync_warnings: Vec<SyncWarning>,
+    /// Render pass sets the graph took from the `FrameArena` instead of allocating
+    pub arena_reused: u32,
+    pub arena_allocations: u32,
+    /// Validation warnings and errors since startup, not just this frame's
+    pub validation_warnings: u64,
+    /// The device uploads went through the dedicated transfer queue instead of the graphics queue
+    pub transfer_queue_upload: bool,
+}
+
+impl FrameStats {
+    pub(crate) fn new(render_graph: &CompiledRenderGraph) -> Self {
+        let mut stats = Self {
+            buffer_uploads: render_graph.buffer_writes.buffer_writes.len() as u32,
+            buffer_upload_bytes: render_graph.buffer_writes.total_write_size as u64,
+            buffer_reads: render_graph.buffer_reads.buffer_reads.len() as u32,
+            transient_buffers: render_graph
+                .buffer_resources
+                .iter()
+                .filter(|buffer| !buffer.description.is_persistent())
+                .count() as u32,
+            transient_images: render_graph
+                .image_resources
+                .iter()
+                .filter(|image| matches!(image.description, ImageResourceDescription::Transient(_)))
+                .count() as u32,
+            arena_reused: render_graph.storage.stats.reused,
+            arena_allocations: render_graph.storage.stats.allocated,
+            ..Default::default()
+        };
+        for command_buffer in render_graph.command_buffers.iter() {
+            stats.add_command_buffer(command_buffer);
+        }
+        stats
+    }
+
+    pub(crate) fn add_upload_pass(&mut self, command_buffer: &CommandBuffer) {
+        for render_pass in command_buffer
+            .render_pass_sets
+            .iter()
+            .flat_map(|render_pass_set| render_pass_set.render_passes.iter())
+        {
+            if let Some(RenderPassCommand::Transfer { transfers }) = &render_pass.command {
+                self.buffer_uploads += transfers.len() as u32;
+            }
+        }
+    }
+
+    fn add_command_buffer(&mut self, command_buffer: &CommandBuffer) {
+        //Last pass that wrote each image, cleared once something else touches it
+        let mut last_writes: Vec<(ImageIndex, usize, NameId)> = Vec::new();
+
+        for (render_pass_set_index, render_pass_set) in
+            command_buffer.render_pass_sets.iter().enumerate()
+        {
+            self.barriers += (render_pass_set.memory_barriers.len()
+                + render_pass_set.buffer_barriers.len()
+                + render_pass_set.image_barriers.len()) as u32;
+
+            let pass_name = render_pass_set
+                .render_passes
+                .first()
+                .map(|render_pass| render_pass.label_name)
+                .unwrap_or_else(|| NameId::new("Empty Pass Set"));
+            let mut pass_barriers = PassBarrierStats {
+                pass: pass_name,
+                memory_barriers: render_pass_set.memory_barriers.len() as u32,
+                buffer_barriers: render_pass_set.buffer_barriers.len() as u32,
+                image_barriers: Vec::with_capacity(render_pass_set.image_barriers.len()),
+            };
+
+            for image_barrier in render_pass_set.image_barriers.iter() {
+                let src = match image_barrier.src {
+                    ImageBarrierSource::FirstUsage => None,
+                    ImageBarrierSource::Precalculated(access) => Some(access),
+                };
+                let dst = image_barrier.dst;
+
+                //Only attachment layouts depend on the format and those never match any other access
+                let layout_transition = src.is_some_and(|src| {
+                    src.get_barrier_flags(true).layout != dst.get_barrier_flags(true).layout
+                });
+                if layout_transition {
+                    self.layout_transitions += 1;
+                }
+
+                if src == Some(dst) && !is_write(dst) {
+                    self.sync_warnings.push(SyncWarning::RedundantTransition {
+                        image: image_barrier.index,
+                        pass: pass_name,
+                        access: dst,
+                    });
+                }
+
+                let last_write = last_writes
+                    .iter()
+                    .position(|(image, ..)| *image == image_barrier.index)
+                    .map(|index| last_writes.swap_remove(index));
+                if is_write(dst) {
+                    if let Some((_, last_pass_index, last_pass_name)) = last_write {
+                        let both_attachments = src == Some(ImageResourceAccess::AttachmentWrite)
+                            && dst == ImageResourceAccess::AttachmentWrite;
+                        if last_pass_index + 1 == render_pass_set_index && !both_attachments {
+                            self.sync_warnings.push(SyncWarning::WriteAfterWrite {
+                                image: image_barrier.index,
+                                first_pass: last_pass_name,
+                                second_pass: pass_name,
+                            });
+                        }
+                    }
+                    last_writes.push((image_barrier.index, render_pass_set_index, pass_name));
+                }
+
+                pass_barriers.image_barriers.push(ImageBarrierStats {
+                    image: image_barrier.index,
+                    src,
+                    dst,
+                    layout_transition,
+                });
+            }
+            self.pass_barriers.push(pass_barriers);
+
+            for render_pass in render_pass_set.render_passes.iter() {
+                self.passes += 1;
+                match &render_pass.command {
+                    Some(RenderPassCommand::Compute { .. }) => self.compute_dispatches += 1,
+                    Some(RenderPassCommand::Raster { draw_commands, .. }) => {
+                        for draw_command in draw_commands.iter() {
+                            match &draw_command.dispatch {
+                                DrawCommandDispatch::Draw {
+                                    vertices,
+                                    instances,
+                                } => {
+                                    self.draw_calls += 1;
+                                    self.instances += instances.len() as u64;
+                                    self.triangles += (vertices.len() / 3 * instances.len()) as u64;
+                                }
+                                DrawCommandDispatch::DrawIndexed {
+                                    indices, instances, ..
+                                } => {
+                                    self.draw_calls += 1;
+                                    self.instances += instances.len() as u64;
+                                    self.triangles += (indices.len() / 3 * instances.len()) as u64;
+                                }
+                                DrawCommandDispatch::DrawIndirect { draw_count, .. }
+                                | DrawCommandDispatch::DrawIndirectIndexed { draw_count, .. } => {
+                                    self.draw_calls += draw_count;
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+fn is_write(access: ImageResourceAccess) -> bool {
+    matches!(
+        access,
+        ImageResourceAccess::TransferWrite
+            | ImageResourceAccess::AttachmentWrite
+            | ImageResourceAccess::StorageWrite
+    )
+}
+
+/// How far the gpu got through a submitted frame, read from which of its pass timestamps have been written.
+/// Meant for crash reports after a device loss, when the frame's fence will never be signaled.
+/// It's a best guess, a frame the gpu hasn't started yet can still show the previous frame's timestamps until they are reset
+#[derive(Debug, Clone, Default)]
+pub struct FrameBreadcrumbs {
+    pub passes: Vec<NameId>,
+    /// Passes the gpu finished, None if timestamps aren't supported or couldn't be read back
+    pub completed_passes: Option<usize>,
+}
+
+impl FrameBreadcrumbs {
+    pub fn last_completed_pass(&self) -> Option<&'static str> {
+        let index = self.completed_passes?.checked_sub(1)?;
+        self.passes.get(index).map(NameId::as_str)
+    }
+
+    /// The pass the gpu was most likely working on
+    pub fn current_pass(&self) -> Option<&'static str> {
+        self.passes.get(self.completed_passes?).map(NameId::as_str)
+    }
+}
+
+/// Timestamp queries for one frame in flight, each pass writes a begin and end timestamp
+pub(crate) struct FrameProfiler {
+    device: Arc<AshDevice>,
+    /// Nanoseconds per timestamp tick, None if timestamps aren't supported
+    timestamp_period: Option<f32>,
+    query_pool: vk::QueryPool,
+    query_capacity: u32,
+    next_pass: u32,
+    pending: Option<FrameProfile>,
+}
+
+impl FrameProfiler {
+    pub fn new(device: Arc<AshDevice>) -> Self {
+        let limits = unsafe {
+            device
+                .instance
+                .core
+                .get_physical_device_properties(device.physical)
+        }
+        .limits;
+        let timestamp_period = (limits.timestamp_compute_and_graphics == vk::TRUE
+            && limits.timestamp_period > 0.0)
+            .then_some(limits.timestamp_period);
+
+        Self {
+            device,
+            timestamp_period,
+            query_pool: vk::QueryPool::null(),
+            query_capacity: 0,
+            next_pass: 0,
+            pending: None,
+        }
+    }
+
+    /// Reads back the timestamps of the last frame recorded with this profiler, its fence must already be signaled
+    pub fn resolve(&mut self) -> Option<FrameProfile> {
+        let mut profile = self.pending.take()?;
+
+        let query_count = profile.passes.len() as u32 * 2;
+        if let Some(timestamp_period) = self.timestamp_period.filter(|_| query_count > 0) {
+            let mut timestamps = vec![0u64; query_count as usize];
+            let result = unsafe {
+                self.device.core.get_query_pool_results(
+                    self.query_pool,
+                    0,
+                    query_count,
+                    &mut timestamps,
+                    vk::QueryResultFlags::TYPE_64,
+                )
+            };
+
+            if result.is_ok() {
+                for (pass, times) in profile.passes.iter_mut().zip(timestamps.chunks_exact(2)) {
+                    let ticks = times[1].saturating_sub(times[0]);
+                    pass.gpu_time = Some(Duration::from_nanos(
+                        (ticks as f64 * timestamp_period as f64) as u64,
+                    ));
+                }
+            }
+        }
+
+        Some(profile)
+    }
+
+    /// Breadcrumbs of the last frame recorded with this profiler, doesn't wait on the frame or consume its profile
+    pub fn breadcrumbs(&self) -> Option<FrameBreadcrumbs> {
+        let profile = self.pending.as_ref()?;
+        let passes: Vec<NameId> = profile.passes.iter().map(|pass| pass.name).collect();
+
+        let query_count = passes.len() as u32 * 2;
+        let completed_passes = (self.query_pool != vk::QueryPool::null() && query_count > 0)
+            .then(|| {
+                //Each result is the timestamp then its availability
+                let mut results = vec![[0u64; 2]; query_count as usize];
+                let result = unsafe {
+                    self.device.core.get_query_pool_results(
+                        self.query_pool,
+                        0,
+                        query_count,
+                        &mut results,
+                        vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WITH_AVAILABILITY,
+                    )
+                };
+                match result {
+                    Ok(()) | Err(vk::Result::NOT_READY) => Some(
+                        results
+                            .chunks_exact(2)
+                            .take_while(|times| times[1][1] != 0)
+                            .count(),
+                    ),
+                    Err(_) => None,
+                }
+            })
+            .flatten();
+
+        Some(FrameBreadcrumbs {
+            passes,
+            completed_passes,
+        })
+    }
+
+    /// Collects the pass stats of the graph and makes sure there are enough queries for it
+    pub fn begin_frame(
+        &mut self,
+        render_graph: &CompiledRenderGraph,
+    ) -> ash::prelude::VkResult<()> {
+        let mut passes = Vec::new();
+        for render_pass in render_graph
+            .command_buffers
+            .iter()
+            .flat_map(|command_buffer| command_buffer.render_pass_sets.iter())
+            .flat_map(|render_pass_set| render_pass_set.render_passes.iter())
+        {
+            let mut stats = PassStats {
+                name: render_pass.label_name,
+                gpu_time: None,
+                draw_count: 0,
+                triangle_count: 0,
+            };
+
+            if let Some(RenderPassCommand::Raster { draw_commands, .. }) = &render_pass.command {
+                for draw_command in draw_commands.iter() {
+                    match &draw_command.dispatch {
+                        DrawCommandDispatch::Draw {
+                            vertices,
+                            instances,
+                        } => {
+                            stats.draw_count += 1;
+                            stats.triangle_count += (vertices.len() / 3 * instances.len()) as u64;
+                        }
+                        DrawCommandDispatch::DrawIndexed {
+                            indices, instances, ..
+                        } => {
+                            stats.draw_count += 1;
+                            stats.triangle_count += (indices.len() / 3 * instances.len()) as u64;
+                        }
+                        DrawCommandDispatch::DrawIndirect { draw_count, .. }
+                        | DrawCommandDispatch::DrawIndirectIndexed { draw_count, .. } => {
+                            stats.draw_count += draw_count;
+                        }
+                    }
+                }
+            }
+            passes.push(stats);
+        }
+
+        let query_count = passes.len() as u32 * 2;
+        if self.timestamp_period.is_some() && query_count > self.query_capacity {
+            //The last frame using this pool has finished, so it's safe to replace
+            self.destroy_query_pool();
+            let query_capacity = query_count.next_power_of_two();
+            self.query_pool = unsafe {
+                self.device.core.create_query_pool(
+                    &vk::QueryPoolCreateInfo::builder()
+                        .query_type(vk::QueryType::TIMESTAMP)
+                        .query_count(query_capacity),
+                    None,
+                )
+            }?;
+            self.query_capacity = query_capacity;
+        }
+
+        self.next_pass = 0;
+        self.pending = Some(FrameProfile {
+            passes,
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    /// Must be recorded before any pass of the frame
+    pub fn reset_queries(&self, command_buffer: vk::CommandBuffer) {
+        if self.query_pool != vk::QueryPool::null() {
+            unsafe {
+                self.device.core.cmd_reset_query_pool(
+                    command_buffer,
+                    self.query_pool,
+                    0,
+                    self.query_capacity,
+                );
+            }
+        }
+    }
+
+    pub fn begin_pass(&self, command_buffer: vk::CommandBuffer) {
+        self.write_timestamp(command_buffer, self.next_pass * 2);
+    }
+
+    pub fn end_pass(&mut self, command_buffer: vk::CommandBuffer) {
+        self.write_timestamp(command_buffer, self.next_pass * 2 + 1);
+        self.next_pass += 1;
+    }
+
+    pub fn set_frame_stats(
+        &mut self,
+        transient_memory: u64,
+        transient_memory_watermark: u64,
+        swapchain_latency: Duration,
+    ) {
+        if let Some(profile) = &mut self.pending {
+            profile.transient_memory = transient_memory;
+            profile.transient_memory_watermark = transient_memory_watermark;
+            profile.swapchain_latency = swapchain_latency;
+        }
+    }
+
+    fn write_timestamp(&self, command_buffer: vk::CommandBuffer, query: u32) {
+        if self.query_pool != vk::QueryPool::null() {
+            unsafe {
+                self.device.core.cmd_write_timestamp2(
+                    command_buffer,
+                    vk::PipelineStageFlags2::ALL_COMMANDS,
+                    self.query_pool,
+                    query,
+                );
+            }
+        }
+    }
+
+    fn destroy_query_pool(&mut self) {
+        if self.query_pool != vk::QueryPool::null() {
+            unsafe {
+                self.device.core.destroy_query_pool(self.query_pool, None);
+            }
+            self.query_pool = vk::QueryPool::null();
+            self.query_capacity = 0;
+        }
+    }
+}
+
+impl Drop for FrameProfiler {
+    fn drop(&mut self) {
+        self.destroy_query_pool();
+    }
+}
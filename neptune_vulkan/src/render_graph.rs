@@ -1,8 +1,10 @@
+use crate::frame_arena::GraphStorage;
+use crate::name::NameId;
 use crate::render_graph_builder::{BufferReadCallback, BufferWriteCallback};
 use crate::resource_managers::{BufferResourceAccess, BufferTempResource, ImageResourceAccess};
 use crate::{
     BufferKey, BufferUsage, ComputePipelineHandle, ImageKey, RasterPipelineHandle, SamplerHandle,
-    SurfaceHandle, TransientImageDesc,
+    SurfaceHandle, TransientBufferSize, TransientImageDesc,
 };
 use ash::vk;
 use std::fmt::{Debug, Formatter};
@@ -22,7 +24,7 @@ pub type BufferIndex = usize;
 pub enum BufferResourceDescription {
     Persistent(BufferKey),
     Transient {
-        size: usize,
+        size: TransientBufferSize,
         usage: BufferUsage,
         location: gpu_allocator::MemoryLocation,
     },
@@ -74,10 +76,18 @@ pub struct BufferOffset {
 
 #[derive(Debug)]
 pub enum ShaderResourceUsage {
-    StorageBuffer { buffer: BufferIndex, write: bool },
-    StorageImage { image: ImageIndex, write: bool },
+    StorageBuffer {
+        buffer: BufferIndex,
+        write: bool,
+    },
+    StorageImage {
+        image: ImageIndex,
+        write: bool,
+    },
     SampledImage(ImageIndex),
     Sampler(SamplerHandle),
+    /// Raw value pushed in order with the bindings, e.g. an index into a buffer
+    Constant(u32),
 }
 
 //Transfer
@@ -93,6 +103,7 @@ pub struct ImageCopyBuffer {
 pub struct ImageCopyImage {
     pub image: ImageIndex,
     pub offset: [u32; 2],
+    pub mip_level: u32,
 }
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
@@ -105,7 +116,8 @@ pub enum Transfer {
     BufferToImage {
         src: ImageCopyBuffer,
         dst: ImageCopyImage,
-        copy_size: [u32; 2],
+        /// Depth is 1 for 2D images
+        copy_size: [u32; 3],
     },
     ImageToBuffer {
         src: ImageCopyImage,
@@ -117,6 +129,8 @@ pub enum Transfer {
         dst: ImageCopyImage,
         copy_size: [u32; 2],
     },
+    /// Leaves every mip in TRANSFER_DST_OPTIMAL like any other transfer write
+    GenerateMips { image: ImageIndex },
 }
 
 //Compute
@@ -145,10 +159,19 @@ pub struct DepthStencilAttachment {
     pub clear: Option<(f32, u32)>,
 }
 
+/// Pixel rect of the attachments a raster pass draws to, used for the viewport and scissor too
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct RenderArea {
+    pub offset: [u32; 2],
+    pub size: [u32; 2],
+}
+
 #[derive(Default, Debug)]
 pub struct Framebuffer {
     pub color_attachments: Vec<ColorAttachment>,
     pub depth_stencil_attachment: Option<DepthStencilAttachment>,
+    /// The whole attachment if None
+    pub render_area: Option<RenderArea>,
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -249,7 +272,7 @@ pub enum Queue {
 
 #[derive(Debug)]
 pub struct RenderPass {
-    pub label_name: String,
+    pub label_name: NameId,
     pub label_color: [f32; 4],
     pub command: Option<RenderPassCommand>,
 }
@@ -297,6 +320,9 @@ pub struct RenderPassSet {
     pub render_passes: Vec<RenderPass>,
 }
 
+//The memory barriers are built without p_next chains, so there are no pointers to send
+unsafe impl Send for RenderPassSet {}
+
 #[derive(Debug, Default)]
 pub struct BufferOwnershipTransfer {
     pub index: BufferIndex,
@@ -359,6 +385,11 @@ impl BufferWrites {
         self.buffer_writes.push(write);
     }
 
+    pub fn clear(&mut self) {
+        self.total_write_size = 0;
+        self.buffer_writes.clear();
+    }
+
     pub fn calc_needed_staging_size(&self, buffer_resources: &[BufferTempResource]) -> usize {
         self.buffer_writes
             .iter()
@@ -388,6 +419,11 @@ impl BufferReads {
         self.buffer_reads.push(read);
     }
 
+    pub fn clear(&mut self) {
+        self.total_read_size = 0;
+        self.buffer_reads.clear();
+    }
+
     pub fn calc_needed_staging_size(&self, buffer_resources: &[BufferTempResource]) -> usize {
         self.buffer_reads
             .iter()
@@ -422,4 +458,7 @@ pub struct CompiledRenderGraph {
     pub swapchain_images: Vec<(SurfaceHandle, ImageIndex)>,
 
     pub command_buffers: Vec<CommandBuffer>,
+
+    /// Spare allocations from earlier frames, see `FrameArena`
+    pub(crate) storage: GraphStorage,
 }
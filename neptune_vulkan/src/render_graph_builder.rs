@@ -1,16 +1,18 @@
-use crate::render_graph::{CompiledRenderGraph, IndexType, QueueType};
+use crate::blackboard::Blackboard;
+use crate::name::NameId;
+use crate::render_graph::{CompiledRenderGraph, IndexType, QueueType, RenderArea};
 use crate::{
     BufferHandle, BufferUsage, ComputePipelineHandle, ImageHandle, RasterPipelineHandle,
-    SamplerHandle, SurfaceHandle, TransientImageDesc,
+    SamplerHandle, SurfaceHandle, TransientBufferSize, TransientImageDesc,
 };
 use ash::vk;
 use std::ops::Range;
 use std::sync::Arc;
 
-type BufferWriteCallbackType = Box<dyn Fn(&mut [u8])>;
+type BufferWriteCallbackType = Box<dyn Fn(&mut [u8]) + Send>;
 pub struct BufferWriteCallback(BufferWriteCallbackType);
 impl BufferWriteCallback {
-    pub fn new(function: impl Fn(&mut [u8]) + 'static) -> Self {
+    pub fn new(function: impl Fn(&mut [u8]) + Send + 'static) -> Self {
         Self(Box::new(function))
     }
 
@@ -19,12 +21,12 @@ impl BufferWriteCallback {
     }
 }
 
-type BufferReadCallbackType = Arc<dyn Fn(&[u8])>;
+type BufferReadCallbackType = Arc<dyn Fn(&[u8]) + Send + Sync>;
 
 #[derive(Clone)]
 pub struct BufferReadCallback(BufferReadCallbackType);
 impl BufferReadCallback {
-    pub fn new(function: impl Fn(&[u8]) + 'static) -> Self {
+    pub fn new(function: impl Fn(&[u8]) + Send + Sync + 'static) -> Self {
         Self(Arc::new(function))
     }
 
@@ -51,6 +53,7 @@ pub struct ImageCopyBuffer {
 pub struct ImageCopyImage {
     pub image: ImageHandle,
     pub offset: [u32; 2],
+    pub mip_level: u32,
 }
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
@@ -75,14 +78,24 @@ pub enum Transfer {
         dst: ImageCopyImage,
         copy_size: [u32; 2],
     },
+    /// Blits every mip level down from the one above it, starting from mip 0
+    GenerateMips { image: ImageHandle },
 }
 
 #[derive(Debug, Clone)]
 pub enum ShaderResourceUsage {
-    StorageBuffer { buffer: BufferHandle, write: bool },
-    StorageImage { image: ImageHandle, write: bool },
+    StorageBuffer {
+        buffer: BufferHandle,
+        write: bool,
+    },
+    StorageImage {
+        image: ImageHandle,
+        write: bool,
+    },
     SampledImage(ImageHandle),
     Sampler(SamplerHandle),
+    /// Raw value pushed in order with the bindings, e.g. an index into a buffer
+    Constant(u32),
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -107,6 +120,7 @@ pub struct DepthStencilAttachment {
 pub struct Framebuffer {
     pub color_attachments: Vec<ColorAttachment>,
     pub depth_stencil_attachment: Option<DepthStencilAttachment>,
+    pub render_area: Option<RenderArea>,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -171,6 +185,15 @@ pub trait RenderGraphBuilderTrait {
         size: usize,
         usage: BufferUsage,
         location: gpu_allocator::MemoryLocation,
+    ) -> BufferHandle {
+        self.create_sized_transient_buffer(TransientBufferSize::Exact(size), usage, location)
+    }
+    /// Transient buffer whose size depends on another resource, resolved when the graph executes
+    fn create_sized_transient_buffer(
+        &mut self,
+        size: TransientBufferSize,
+        usage: BufferUsage,
+        location: gpu_allocator::MemoryLocation,
     ) -> BufferHandle;
     fn create_transient_image(&mut self, desc: TransientImageDesc) -> ImageHandle;
     fn acquire_swapchain_image(&mut self, surface_handle: SurfaceHandle) -> ImageHandle;
@@ -180,14 +203,14 @@ pub trait RenderGraphBuilderTrait {
 
     fn add_transfer_pass(
         &mut self,
-        name: String,
+        name: NameId,
         color: [f32; 4],
         queue: QueueType,
         transfers: &[Transfer],
     );
     fn add_compute_pass(
         &mut self,
-        name: String,
+        name: NameId,
         color: [f32; 4],
         queue: QueueType,
         pipeline: ComputePipelineHandle,
@@ -196,19 +219,24 @@ pub trait RenderGraphBuilderTrait {
     );
     fn add_raster_pass(
         &mut self,
-        name: String,
+        name: NameId,
         color: [f32; 4],
         color_attachments: &[ColorAttachment],
         depth_stencil_attachment: Option<DepthStencilAttachment>,
+        render_area: Option<RenderArea>,
         raster_draw_commands: &[RasterDrawCommand],
     );
 
+    /// Values shared between the systems writing this frame's passes, see `Blackboard`
+    fn blackboard(&self) -> &Blackboard;
+    fn blackboard_mut(&mut self) -> &mut Blackboard;
+
     fn build(self) -> CompiledRenderGraph;
 }
 
 //Helper Structs
 pub struct TransferPassBuilder {
-    name: String,
+    name: NameId,
     color: [f32; 4],
     queue: QueueType,
     transfers: Vec<Transfer>,
@@ -217,7 +245,7 @@ pub struct TransferPassBuilder {
 impl TransferPassBuilder {
     pub fn new(name: &str, queue: QueueType) -> Self {
         Self {
-            name: name.to_string(),
+            name: NameId::new(name),
             color: [1.0, 0.0, 0.0, 1.0],
             queue,
             transfers: Vec::new(),
@@ -280,13 +308,18 @@ impl TransferPassBuilder {
         });
     }
 
+    /// Should come after any transfers that write mip 0 of the image
+    pub fn generate_mips(&mut self, image: ImageHandle) {
+        self.transfers.push(Transfer::GenerateMips { image });
+    }
+
     pub fn build<T: RenderGraphBuilderTrait>(self, render_graph_builder: &mut T) {
         render_graph_builder.add_transfer_pass(self.name, self.color, self.queue, &self.transfers);
     }
 }
 
 pub struct ComputePassBuilder {
-    name: String,
+    name: NameId,
     color: [f32; 4],
     queue: QueueType,
     pipeline: ComputePipelineHandle,
@@ -297,7 +330,7 @@ pub struct ComputePassBuilder {
 impl ComputePassBuilder {
     pub fn new(name: &str, queue: QueueType, pipeline: ComputePipelineHandle) -> Self {
         Self {
-            name: name.to_string(),
+            name: NameId::new(name),
             color: [0.0, 1.0, 0.0, 1.0],
             queue,
             pipeline,
@@ -314,6 +347,11 @@ impl ComputePassBuilder {
         self.dispatch = ComputeDispatch::Size(size);
     }
 
+    /// Dispatches enough workgroups of the pipeline's reflected local size to cover `thread_count`
+    pub fn dispatch_threads(&mut self, thread_count: [u32; 3]) {
+        self.dispatch = ComputeDispatch::Size(self.pipeline.group_count(thread_count));
+    }
+
     pub fn dispatch_indirect(&mut self, buffer: BufferHandle, offset: usize) {
         self.dispatch = ComputeDispatch::Indirect(BufferOffset { buffer, offset });
     }
@@ -353,6 +391,10 @@ impl ComputePassBuilder {
         self.resources.push(ShaderResourceUsage::Sampler(sampler));
     }
 
+    pub fn push_constant(&mut self, value: u32) {
+        self.resources.push(ShaderResourceUsage::Constant(value));
+    }
+
     pub fn build<T: RenderGraphBuilderTrait>(self, render_graph_builder: &mut T) {
         render_graph_builder.add_compute_pass(
             self.name,
@@ -366,7 +408,7 @@ impl ComputePassBuilder {
 }
 
 pub struct RasterPassBuilder {
-    name: String,
+    name: NameId,
     color: [f32; 4],
     framebuffer: Framebuffer,
     draw_commands: Vec<RasterDrawCommand>,
@@ -375,7 +417,7 @@ pub struct RasterPassBuilder {
 impl RasterPassBuilder {
     pub fn new(name: &str) -> Self {
         Self {
-            name: name.to_string(),
+            name: NameId::new(name),
             color: [0.0, 1.0, 0.0, 1.0],
             framebuffer: Framebuffer::default(),
             draw_commands: Vec::new(),
@@ -396,6 +438,11 @@ impl RasterPassBuilder {
         self.framebuffer.depth_stencil_attachment = Some(DepthStencilAttachment { image, clear });
     }
 
+    /// Limits drawing (and clears) to part of the attachments, e.g. one player's half of a split screen
+    pub fn set_render_area(&mut self, offset: [u32; 2], size: [u32; 2]) {
+        self.framebuffer.render_area = Some(RenderArea { offset, size });
+    }
+
     pub fn add_draw_command(&mut self, draw_command: RasterDrawCommand) {
         self.draw_commands.push(draw_command);
     }
@@ -406,6 +453,7 @@ impl RasterPassBuilder {
             self.color,
             &self.framebuffer.color_attachments,
             self.framebuffer.depth_stencil_attachment,
+            self.framebuffer.render_area,
             &self.draw_commands,
         );
     }
@@ -467,6 +515,10 @@ impl RasterDrawCommandBuilder {
         self.resources.push(ShaderResourceUsage::Sampler(sampler));
     }
 
+    pub fn push_constant(&mut self, value: u32) {
+        self.resources.push(ShaderResourceUsage::Constant(value));
+    }
+
     pub fn draw(&mut self, vertices: Range<u32>, instances: Range<u32>) {
         self.dispatch = Some(DrawCommandDispatch::Draw {
             vertices,
@@ -1,7 +1,8 @@
-use crate::descriptor_set::GpuBindingIndex;
 use crate::device::{AshDevice, AshQueue};
-use crate::image::vk_format_get_aspect_flags;
+use crate::image::{vk_format_get_aspect_flags, AshImage};
+use crate::name::NameId;
 use crate::pipeline::Pipelines;
+use crate::profiler::{FrameBreadcrumbs, FrameProfile, FrameProfiler, FrameStats};
 use crate::render_graph::{
     BufferBarrierSource, BufferOffset, CommandBuffer, CommandBufferDependency, CompiledRenderGraph,
     ComputeDispatch, DrawCommandDispatch, Framebuffer, ImageBarrierSource, ImageIndex, IndexType,
@@ -11,13 +12,14 @@ use crate::resource_managers::{
     BufferResourceAccess, BufferTempResource, ImageTempResource, ResourceManager,
 };
 use crate::swapchain::{AcquiredSwapchainImage, SwapchainManager};
-use crate::upload_queue::UploadPass;
+use crate::upload_queue::UploadPasses;
 use crate::{
     ComputePipelineHandle, RasterPipelineHandle, Sampler, SamplerHandle, SurfaceHandle, VulkanError,
 };
 use ash::vk;
 use log::info;
 use std::sync::Arc;
+use std::time::Instant;
 
 // Render Graph Executor Evolution
 // 0. Whole pipeline barriers between passes, no image layout changes (only general layout), no pass order changes, no dead-code culling (DONE!)
@@ -228,6 +230,7 @@ struct FrameContext {
     async_transfer_command_pool: Option<AshCommandPool>,
     semaphore_pool: AshSemaphorePool,
     fence_pool: AshFencePool,
+    profiler: FrameProfiler,
 }
 
 impl FrameContext {
@@ -247,7 +250,8 @@ impl FrameContext {
                 Some(queue) => Some(AshCommandPool::new(device.clone(), queue, 4)?),
             },
             semaphore_pool: AshSemaphorePool::new(device.clone()),
-            fence_pool: AshFencePool::new(device),
+            fence_pool: AshFencePool::new(device.clone()),
+            profiler: FrameProfiler::new(device),
         })
     }
 
@@ -273,6 +277,13 @@ pub struct RenderGraphExecutor {
     device: Arc<AshDevice>,
     frame_contexts: Vec<FrameContext>,
     frame_index: usize,
+
+    last_profile: Option<FrameProfile>,
+    last_stats: FrameStats,
+    transient_memory_watermark: u64,
+
+    /// Signaled by uploads on the transfer queue, None if there's no dedicated transfer queue
+    transfer_timeline: Option<TimelineSemaphore>,
 }
 
 impl RenderGraphExecutor {
@@ -281,19 +292,58 @@ impl RenderGraphExecutor {
         for _ in 0..frame_contexts.capacity() {
             frame_contexts.push(FrameContext::new(device.clone())?)
         }
+        let transfer_timeline = match device.transfer_queue {
+            Some(_) => Some(TimelineSemaphore::new(device.clone())?),
+            None => None,
+        };
         Ok(Self {
             device,
             frame_contexts,
             frame_index: 0,
+            last_profile: None,
+            last_stats: FrameStats::default(),
+            transient_memory_watermark: 0,
+            transfer_timeline,
         })
     }
 
+    /// Stats of the most recently completed frame
+    pub(crate) fn last_profile(&self) -> Option<&FrameProfile> {
+        self.last_profile.as_ref()
+    }
+
+    /// Counts of the most recently submitted frame
+    pub(crate) fn last_stats(&self) -> &FrameStats {
+        &self.last_stats
+    }
+
+    /// Breadcrumbs of every frame still in flight, oldest first
+    pub(crate) fn breadcrumbs(&self) -> Vec<FrameBreadcrumbs> {
+        let frame_count = self.frame_contexts.len();
+        (1..=frame_count)
+            .filter_map(|offset| {
+                self.frame_contexts[(self.frame_index + offset) % frame_count]
+                    .profiler
+                    .breadcrumbs()
+            })
+            .collect()
+    }
+
+    /// Whether the next frame's new resource uploads can go to the transfer queue.
+    /// Resources used by the graphics queue would need their ownership released first, so only new ones are moved
+    pub(crate) fn uploads_on_transfer_queue(&self, render_graph: &CompiledRenderGraph) -> bool {
+        //Nothing waits on a frame without command buffers, so its uploads couldn't be fenced
+        self.transfer_timeline.is_some()
+            && self.device.transfer_queue.is_some()
+            && !render_graph.command_buffers.is_empty()
+    }
+
     pub(crate) fn submit_frame(
         &mut self,
         resource_manager: &mut ResourceManager,
         swapchain_manager: &mut SwapchainManager,
         pipelines: &Pipelines,
-        upload_pass: Option<UploadPass>,
+        upload_passes: UploadPasses,
         render_graph: &CompiledRenderGraph,
     ) -> Result<(), VulkanError> {
         const TIMEOUT_NS: u64 = std::time::Duration::from_secs(2).as_nanos() as u64;
@@ -301,12 +351,58 @@ impl RenderGraphExecutor {
 
         let frame_context = &mut self.frame_contexts[self.frame_index];
 
+        let wait_start = Instant::now();
         frame_context.wait_and_reset(TIMEOUT_NS)?;
-        resource_manager.flush_frame();
+        let frame_wait_time = wait_start.elapsed();
+        if let Some(profile) = frame_context.profiler.resolve() {
+            self.last_profile = Some(profile);
+        }
+        resource_manager.flush_frame(self.frame_index);
+
+        let mut stats = FrameStats::new(render_graph);
+        if let Some(debug_util) = &self.device.instance.debug_utils {
+            stats.validation_warnings = debug_util.warning_count();
+        }
+
+        //Upload Passes
+        let mut upload_ownership: Option<QueueOwnershipTransfer> = None;
+        let mut upload_wait: Option<vk::SemaphoreSubmitInfo> = None;
+        if let Some(upload_pass) = upload_passes.new_resources {
+            stats.add_upload_pass(&upload_pass.command_buffer);
+
+            let mut buffers = resource_manager.get_buffer_resources(
+                swapchain_manager,
+                &[],
+                &upload_pass.buffer_resources,
+                &upload_pass.image_resources,
+            )?;
+            let mut images = resource_manager.get_image_resources(
+                swapchain_manager,
+                &[],
+                &upload_pass.image_resources,
+            )?;
+
+            //Only split off when uploads_on_transfer_queue allowed it, graphics is the fallback if the pool is missing
+            let (upload_command_buffer, submit_queue) = match (
+                self.device.transfer_queue,
+                frame_context.async_transfer_command_pool.as_mut(),
+            ) {
+                (Some(transfer_queue), Some(command_pool)) => {
+                    stats.transfer_queue_upload = true;
+                    upload_ownership = Some(QueueOwnershipTransfer::new(
+                        transfer_queue.family_index,
+                        self.device.graphics_queue.unwrap().family_index,
+                        &buffers,
+                        &images,
+                    ));
+                    (command_pool.get()?, transfer_queue.handle)
+                }
+                _ => (
+                    frame_context.graphics_command_pool.get()?,
+                    self.device.graphics_queue.unwrap().handle,
+                ),
+            };
 
-        //Upload Pass
-        if let Some(upload_pass) = upload_pass {
-            let upload_command_buffer = frame_context.graphics_command_pool.get()?;
             unsafe {
                 self.device.core.begin_command_buffer(
                     upload_command_buffer,
@@ -314,11 +410,6 @@ impl RenderGraphExecutor {
                 )?
             };
 
-            let mut buffers =
-                resource_manager.get_buffer_resources(&upload_pass.buffer_resources)?;
-            let mut images =
-                resource_manager.get_image_resources(&[], &upload_pass.image_resources)?;
-
             let mut resources = RenderGraphResources {
                 buffers: &mut buffers,
                 images: &mut images,
@@ -331,18 +422,103 @@ impl RenderGraphExecutor {
                 upload_command_buffer,
                 &upload_pass.command_buffer,
                 &mut resources,
+                None,
             );
 
+            if let Some(ownership) = &upload_ownership {
+                ownership.record_release(&self.device, upload_command_buffer);
+            }
+
             unsafe {
                 self.device.core.end_command_buffer(upload_command_buffer)?;
 
                 let command_buffer_info = vk::CommandBufferSubmitInfo::builder()
                     .command_buffer(upload_command_buffer)
                     .build();
+
+                //Graphics waits for the uploads with a timeline value instead of a binary semaphore per frame
+                let signal_semaphore_infos: Vec<vk::SemaphoreSubmitInfo> = match self
+                    .transfer_timeline
+                    .as_mut()
+                    .filter(|_| upload_ownership.is_some())
+                {
+                    Some(timeline) => {
+                        let signal_info = timeline.next_submit_info();
+                        upload_wait = Some(signal_info);
+                        vec![signal_info]
+                    }
+                    None => Vec::new(),
+                };
+
+                self.device.core.queue_submit2(
+                    submit_queue,
+                    &[vk::SubmitInfo2::builder()
+                        .command_buffer_infos(&[command_buffer_info])
+                        .signal_semaphore_infos(&signal_semaphore_infos)
+                        .build()],
+                    vk::Fence::null(),
+                )?;
+            }
+        }
+
+        //Updates to resources already in use stay on graphics, after the new resources' uploads have been handed over
+        if let Some(upload_pass) = upload_passes.graphics {
+            stats.add_upload_pass(&upload_pass.command_buffer);
+
+            let mut buffers = resource_manager.get_buffer_resources(
+                swapchain_manager,
+                &[],
+                &upload_pass.buffer_resources,
+                &upload_pass.image_resources,
+            )?;
+            let mut images = resource_manager.get_image_resources(
+                swapchain_manager,
+                &[],
+                &upload_pass.image_resources,
+            )?;
+
+            let update_command_buffer = frame_context.graphics_command_pool.get()?;
+            unsafe {
+                self.device.core.begin_command_buffer(
+                    update_command_buffer,
+                    &vk::CommandBufferBeginInfo::builder(),
+                )?
+            };
+
+            //Acquired here since this pass may touch the new resources, the graph only waits on the timeline after this
+            if let Some(ownership) = upload_ownership.take() {
+                ownership.record_acquire(&self.device, update_command_buffer);
+            }
+
+            let mut resources = RenderGraphResources {
+                buffers: &mut buffers,
+                images: &mut images,
+                persistent: resource_manager,
+                pipelines,
+            };
+
+            record_command_buffer(
+                &self.device,
+                update_command_buffer,
+                &upload_pass.command_buffer,
+                &mut resources,
+                None,
+            );
+
+            unsafe {
+                self.device.core.end_command_buffer(update_command_buffer)?;
+
+                let command_buffer_info = vk::CommandBufferSubmitInfo::builder()
+                    .command_buffer(update_command_buffer)
+                    .build();
+                let wait_semaphore_infos: Vec<vk::SemaphoreSubmitInfo> =
+                    upload_wait.into_iter().collect();
+
                 self.device.core.queue_submit2(
                     self.device.graphics_queue.unwrap().handle,
                     &[vk::SubmitInfo2::builder()
                         .command_buffer_infos(&[command_buffer_info])
+                        .wait_semaphore_infos(&wait_semaphore_infos)
                         .build()],
                     vk::Fence::null(),
                 )?;
@@ -354,18 +530,28 @@ impl RenderGraphExecutor {
             &render_graph.command_buffers,
         )?;
 
+        let acquire_start = Instant::now();
         let acquired_swapchains = acquire_swapchain_images(
             &mut frame_context.semaphore_pool,
             swapchain_manager,
             &render_graph.swapchain_images,
         )?;
+        let swapchain_latency = frame_wait_time + acquire_start.elapsed();
         let acquired_swapchain_images: Vec<AcquiredSwapchainImage> = acquired_swapchains
             .iter()
             .map(|swapchain| swapchain.image.clone())
             .collect();
-        let mut buffers = resource_manager.get_buffer_resources(&render_graph.buffer_resources)?;
-        let mut images = resource_manager
-            .get_image_resources(&acquired_swapchain_images, &render_graph.image_resources)?;
+        let mut buffers = resource_manager.get_buffer_resources(
+            swapchain_manager,
+            &acquired_swapchain_images,
+            &render_graph.buffer_resources,
+            &render_graph.image_resources,
+        )?;
+        let mut images = resource_manager.get_image_resources(
+            swapchain_manager,
+            &acquired_swapchain_images,
+            &render_graph.image_resources,
+        )?;
 
         let mut staging_buffer_offset = 0;
         let mut staging_buffer = resource_manager.get_write_staging_buffer(
@@ -409,6 +595,17 @@ impl RenderGraphExecutor {
         let read_staging_buffer =
             resource_manager.get_read_staging_buffer(&render_graph.buffer_reads, &buffers)?;
 
+        let transient_memory = resource_manager.transient_memory_size();
+        self.transient_memory_watermark = self.transient_memory_watermark.max(transient_memory);
+        stats.transient_memory = transient_memory;
+        self.last_stats = stats;
+        frame_context.profiler.begin_frame(render_graph)?;
+        frame_context.profiler.set_frame_stats(
+            transient_memory,
+            self.transient_memory_watermark,
+            swapchain_latency,
+        );
+
         //Buffer Writes/Reads
 
         let submit_queue = self.device.graphics_queue.unwrap().handle;
@@ -433,10 +630,16 @@ impl RenderGraphExecutor {
                 //TODO: Properly schedule and barrier staging uploads
                 //TODO: Make a separate command_buffer?
                 if is_first_command_buffer {
+                    frame_context.profiler.reset_queries(vulkan_command_buffer);
+
+                    if let Some(ownership) = &upload_ownership {
+                        ownership.record_acquire(&self.device, vulkan_command_buffer);
+                    }
+
                     if let Some(debug_util) = &self.device.instance.debug_utils {
                         debug_util.cmd_begin_label(
                             vulkan_command_buffer,
-                            "Staging Buffer Upload",
+                            NameId::new("Staging Buffer Upload"),
                             [0.0, 1.0, 1.0, 1.0],
                         );
                     }
@@ -501,7 +704,7 @@ impl RenderGraphExecutor {
                 if let Some(debug_util) = &self.device.instance.debug_utils {
                     debug_util.cmd_begin_label(
                         vulkan_command_buffer,
-                        &format!("Command Buffer {}", command_buffer_index),
+                        NameId::new(&format!("Command Buffer {}", command_buffer_index)),
                         [1.0, 1.0, 0.0, 1.0],
                     );
                 }
@@ -519,6 +722,7 @@ impl RenderGraphExecutor {
                     vulkan_command_buffer,
                     graph_command_buffer,
                     &mut resources,
+                    Some(&mut frame_context.profiler),
                 );
 
                 //TODO: release resource ownership
@@ -576,7 +780,7 @@ impl RenderGraphExecutor {
                     if let Some(debug_util) = &self.device.instance.debug_utils {
                         debug_util.cmd_begin_label(
                             vulkan_command_buffer,
-                            "Staging Buffer Download",
+                            NameId::new("Staging Buffer Download"),
                             [1.0, 0.0, 1.0, 1.0],
                         );
                     }
@@ -633,7 +837,7 @@ impl RenderGraphExecutor {
                     .command_buffer(vulkan_command_buffer)
                     .build()];
 
-                let wait_semaphore_infos: Vec<vk::SemaphoreSubmitInfo> = graph_command_buffer
+                let mut wait_semaphore_infos: Vec<vk::SemaphoreSubmitInfo> = graph_command_buffer
                     .command_buffer_wait_dependencies
                     .iter()
                     .map(|dependency| match dependency {
@@ -657,6 +861,8 @@ impl RenderGraphExecutor {
                         }
                     })
                     .collect();
+                //Every command buffer waits, the graph doesn't know which of them use the uploads
+                wait_semaphore_infos.extend(upload_wait);
 
                 let mut command_buffer_dependency: u32 = 0;
                 let signal_semaphore_infos: Vec<vk::SemaphoreSubmitInfo> = graph_command_buffer
@@ -731,6 +937,188 @@ impl RenderGraphExecutor {
     }
 }
 
+/// Moves the upload destinations from the transfer queue's family to the graphics queue's
+struct QueueOwnershipTransfer {
+    src_family: u32,
+    dst_family: u32,
+    buffers: Vec<vk::Buffer>,
+    images: Vec<(vk::Image, vk::ImageAspectFlags)>,
+}
+
+impl QueueOwnershipTransfer {
+    fn new(
+        src_family: u32,
+        dst_family: u32,
+        buffers: &[BufferTempResource],
+        images: &[ImageTempResource],
+    ) -> Self {
+        //Only the copy destinations carry data over, the staging buffers are freed after this frame
+        let mut buffer_handles: Vec<vk::Buffer> = buffers
+            .iter()
+            .filter(|buffer| {
+                buffer
+                    .buffer
+                    .usage
+                    .contains(vk::BufferUsageFlags::TRANSFER_DST)
+            })
+            .filter(|buffer| buffer.buffer.location == gpu_allocator::MemoryLocation::GpuOnly)
+            .map(|buffer| buffer.buffer.handle)
+            .collect();
+        buffer_handles.sort();
+        buffer_handles.dedup();
+
+        let mut image_handles: Vec<(vk::Image, vk::ImageAspectFlags)> = images
+            .iter()
+            .map(|image| {
+                (
+                    image.image.handle,
+                    vk_format_get_aspect_flags(image.image.format),
+                )
+            })
+            .collect();
+        image_handles.sort();
+        image_handles.dedup();
+
+        Self {
+            src_family,
+            dst_family,
+            buffers: buffer_handles,
+            images: image_handles,
+        }
+    }
+
+    fn record_release(&self, device: &AshDevice, command_buffer: vk::CommandBuffer) {
+        self.record(
+            device,
+            command_buffer,
+            (
+                vk::PipelineStageFlags2::TRANSFER,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+            (vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE),
+        );
+    }
+
+    fn record_acquire(&self, device: &AshDevice, command_buffer: vk::CommandBuffer) {
+        self.record(
+            device,
+            command_buffer,
+            (vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE),
+            (
+                vk::PipelineStageFlags2::ALL_COMMANDS,
+                vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE,
+            ),
+        );
+    }
+
+    /// Release and acquire need identical barriers apart from the stages each side fills in
+    fn record(
+        &self,
+        device: &AshDevice,
+        command_buffer: vk::CommandBuffer,
+        (src_stage, src_access): (vk::PipelineStageFlags2, vk::AccessFlags2),
+        (dst_stage, dst_access): (vk::PipelineStageFlags2, vk::AccessFlags2),
+    ) {
+        let buffer_barriers: Vec<vk::BufferMemoryBarrier2> = self
+            .buffers
+            .iter()
+            .map(|&buffer| {
+                vk::BufferMemoryBarrier2::builder()
+                    .buffer(buffer)
+                    .offset(0)
+                    .size(vk::WHOLE_SIZE)
+                    .src_queue_family_index(self.src_family)
+                    .src_stage_mask(src_stage)
+                    .src_access_mask(src_access)
+                    .dst_queue_family_index(self.dst_family)
+                    .dst_stage_mask(dst_stage)
+                    .dst_access_mask(dst_access)
+                    .build()
+            })
+            .collect();
+
+        //Uploaded images are left in the transfer layout, the graph moves them on from there
+        let image_barriers: Vec<vk::ImageMemoryBarrier2> = self
+            .images
+            .iter()
+            .map(|&(image, aspect_mask)| {
+                vk::ImageMemoryBarrier2::builder()
+                    .image(image)
+                    .subresource_range(vk::ImageSubresourceRange {
+                        aspect_mask,
+                        base_mip_level: 0,
+                        level_count: vk::REMAINING_MIP_LEVELS,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })
+                    .src_queue_family_index(self.src_family)
+                    .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .src_stage_mask(src_stage)
+                    .src_access_mask(src_access)
+                    .dst_queue_family_index(self.dst_family)
+                    .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .dst_stage_mask(dst_stage)
+                    .dst_access_mask(dst_access)
+                    .build()
+            })
+            .collect();
+
+        unsafe {
+            device.core.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder()
+                    .buffer_memory_barriers(&buffer_barriers)
+                    .image_memory_barriers(&image_barriers)
+                    .build(),
+            );
+        }
+    }
+}
+
+/// Counts up once per signal, so waiting on an old value never blocks
+struct TimelineSemaphore {
+    device: Arc<AshDevice>,
+    handle: vk::Semaphore,
+    value: u64,
+}
+
+impl TimelineSemaphore {
+    fn new(device: Arc<AshDevice>) -> ash::prelude::VkResult<Self> {
+        let mut type_info = vk::SemaphoreTypeCreateInfo::builder()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(0);
+        let handle = unsafe {
+            device.core.create_semaphore(
+                &vk::SemaphoreCreateInfo::builder().push_next(&mut type_info),
+                None,
+            )
+        }?;
+        Ok(Self {
+            device,
+            handle,
+            value: 0,
+        })
+    }
+
+    /// Bumps the value, the same info is used to signal it and to wait on it
+    fn next_submit_info(&mut self) -> vk::SemaphoreSubmitInfo {
+        self.value += 1;
+        vk::SemaphoreSubmitInfo::builder()
+            .semaphore(self.handle)
+            .value(self.value)
+            .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+            .build()
+    }
+}
+
+impl Drop for TimelineSemaphore {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.core.destroy_semaphore(self.handle, None);
+        }
+    }
+}
+
 fn allocate_command_buffer_semaphores(
     semaphore_pool: &mut AshSemaphorePool,
     command_buffers: &[CommandBuffer],
@@ -787,6 +1175,7 @@ fn record_command_buffer(
     vulkan_command_buffer: vk::CommandBuffer,
     graph_command_buffer: &CommandBuffer,
     graph_resources: &mut RenderGraphResources,
+    mut profiler: Option<&mut FrameProfiler>,
 ) {
     for (render_pass_set_index, render_pass_set) in
         graph_command_buffer.render_pass_sets.iter().enumerate()
@@ -794,7 +1183,7 @@ fn record_command_buffer(
         if let Some(debug_util) = &device.instance.debug_utils {
             debug_util.cmd_begin_label(
                 vulkan_command_buffer,
-                &format!("RenderPass Set {}", render_pass_set_index),
+                NameId::new(&format!("RenderPass Set {}", render_pass_set_index)),
                 [1.0; 4],
             );
         }
@@ -819,7 +1208,7 @@ fn record_command_buffer(
                     .src_access_mask(src.access_mask)
                     .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
                     .dst_stage_mask(dst.stage_mask)
-                    .src_access_mask(dst.access_mask)
+                    .dst_access_mask(dst.access_mask)
                     .build()
             })
             .collect();
@@ -840,7 +1229,7 @@ fn record_command_buffer(
                     .subresource_range(vk::ImageSubresourceRange {
                         aspect_mask: vk_format_get_aspect_flags(image.image.format),
                         base_mip_level: 0,
-                        level_count: 1,
+                        level_count: vk::REMAINING_MIP_LEVELS,
                         base_array_layer: 0,
                         layer_count: 1,
                     })
@@ -871,11 +1260,15 @@ fn record_command_buffer(
             if let Some(debug_util) = &device.instance.debug_utils {
                 debug_util.cmd_begin_label(
                     vulkan_command_buffer,
-                    &render_pass.label_name,
+                    render_pass.label_name,
                     render_pass.label_color,
                 );
             }
 
+            if let Some(profiler) = &profiler {
+                profiler.begin_pass(vulkan_command_buffer);
+            }
+
             if let Some(render_pass_command) = &render_pass.command {
                 match render_pass_command {
                     RenderPassCommand::Transfer { transfers } => {
@@ -911,6 +1304,10 @@ fn record_command_buffer(
                 }
             }
 
+            if let Some(profiler) = &mut profiler {
+                profiler.end_pass(vulkan_command_buffer);
+            }
+
             if let Some(debug_util) = &device.instance.debug_utils {
                 debug_util.cmd_end_label(vulkan_command_buffer);
             }
@@ -977,11 +1374,11 @@ pub fn record_transfer_pass(
                                 .image_extent(vk::Extent3D {
                                     width: copy_size[0],
                                     height: copy_size[1],
-                                    depth: 1,
+                                    depth: copy_size[2],
                                 })
                                 .image_subresource(vk::ImageSubresourceLayers {
                                     aspect_mask: vk_format_get_aspect_flags(dst_image.format),
-                                    mip_level: 0,
+                                    mip_level: dst.mip_level,
                                     base_array_layer: 0,
                                     layer_count: 1,
                                 })
@@ -1019,7 +1416,7 @@ pub fn record_transfer_pass(
                                 })
                                 .image_subresource(vk::ImageSubresourceLayers {
                                     aspect_mask: vk_format_get_aspect_flags(src_image.format),
-                                    mip_level: 0,
+                                    mip_level: src.mip_level,
                                     base_array_layer: 0,
                                     layer_count: 1,
                                 })
@@ -1061,13 +1458,13 @@ pub fn record_transfer_pass(
                                 })
                                 .src_subresource(vk::ImageSubresourceLayers {
                                     aspect_mask: vk_format_get_aspect_flags(src_image.format),
-                                    mip_level: 0,
+                                    mip_level: src.mip_level,
                                     base_array_layer: 0,
                                     layer_count: 1,
                                 })
                                 .dst_subresource(vk::ImageSubresourceLayers {
                                     aspect_mask: vk_format_get_aspect_flags(dst_image.format),
-                                    mip_level: 0,
+                                    mip_level: dst.mip_level,
                                     base_array_layer: 0,
                                     layer_count: 1,
                                 })
@@ -1075,7 +1472,127 @@ pub fn record_transfer_pass(
                     )
                 }
             }
+            Transfer::GenerateMips { image } => {
+                record_generate_mips(
+                    device,
+                    command_buffer,
+                    &graph_resources.images[*image].image,
+                );
+            }
+        }
+    }
+}
+
+/// Each mip is moved to TRANSFER_SRC once it's written, then blitted into the next one
+fn record_generate_mips(device: &AshDevice, command_buffer: vk::CommandBuffer, image: &AshImage) {
+    if image.mip_levels <= 1 {
+        return;
+    }
+
+    let aspect_mask = image.get_aspect_flags();
+    let filter = if image.is_color() {
+        vk::Filter::LINEAR
+    } else {
+        vk::Filter::NEAREST
+    };
+
+    let mip_barrier = |base_mip_level: u32,
+                       level_count: u32,
+                       old_layout: vk::ImageLayout,
+                       new_layout: vk::ImageLayout,
+                       src_access_mask: vk::AccessFlags2,
+                       dst_access_mask: vk::AccessFlags2| {
+        vk::ImageMemoryBarrier2::builder()
+            .image(image.handle)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask,
+                base_mip_level,
+                level_count,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_stage_mask(vk::PipelineStageFlags2::ALL_TRANSFER)
+            .src_access_mask(src_access_mask)
+            .dst_stage_mask(vk::PipelineStageFlags2::ALL_TRANSFER)
+            .dst_access_mask(dst_access_mask)
+            .build()
+    };
+
+    let mip_layers = |mip_level: u32| vk::ImageSubresourceLayers {
+        aspect_mask,
+        mip_level,
+        base_array_layer: 0,
+        layer_count: 1,
+    };
+
+    let mut src_size = [image.size.width as i32, image.size.height as i32];
+    for mip_level in 1..image.mip_levels {
+        let dst_size = src_size.map(|size| (size / 2).max(1));
+
+        unsafe {
+            device.core.cmd_pipeline_barrier2(
+                command_buffer,
+                &vk::DependencyInfo::builder().image_memory_barriers(&[mip_barrier(
+                    mip_level - 1,
+                    1,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::AccessFlags2::TRANSFER_WRITE,
+                    vk::AccessFlags2::TRANSFER_READ,
+                )]),
+            );
+
+            device.core.cmd_blit_image2(
+                command_buffer,
+                &vk::BlitImageInfo2::builder()
+                    .src_image(image.handle)
+                    .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .dst_image(image.handle)
+                    .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .filter(filter)
+                    .regions(&[vk::ImageBlit2::builder()
+                        .src_subresource(mip_layers(mip_level - 1))
+                        .src_offsets([
+                            vk::Offset3D::default(),
+                            vk::Offset3D {
+                                x: src_size[0],
+                                y: src_size[1],
+                                z: 1,
+                            },
+                        ])
+                        .dst_subresource(mip_layers(mip_level))
+                        .dst_offsets([
+                            vk::Offset3D::default(),
+                            vk::Offset3D {
+                                x: dst_size[0],
+                                y: dst_size[1],
+                                z: 1,
+                            },
+                        ])
+                        .build()]),
+            );
         }
+
+        src_size = dst_size;
+    }
+
+    //The graph tracks the whole image as a transfer write, so every mip but the last goes back to TRANSFER_DST
+    unsafe {
+        device.core.cmd_pipeline_barrier2(
+            command_buffer,
+            &vk::DependencyInfo::builder().image_memory_barriers(&[mip_barrier(
+                0,
+                image.mip_levels - 1,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::AccessFlags2::TRANSFER_READ,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            )]),
+        );
     }
 }
 
@@ -1197,9 +1714,26 @@ pub fn record_raster_pass(
 
         let extent = extent.expect("Framebuffer has no attachments");
 
-        let render_area = vk::Rect2D {
-            offset: vk::Offset2D::default(),
-            extent,
+        //Clamped so a stale area from before a resize can't go past the attachments
+        let render_area = match framebuffer.render_area {
+            Some(area) => {
+                let x = area.offset[0].min(extent.width);
+                let y = area.offset[1].min(extent.height);
+                vk::Rect2D {
+                    offset: vk::Offset2D {
+                        x: x as i32,
+                        y: y as i32,
+                    },
+                    extent: vk::Extent2D {
+                        width: area.size[0].min(extent.width - x),
+                        height: area.size[1].min(extent.height - y),
+                    },
+                }
+            }
+            None => vk::Rect2D {
+                offset: vk::Offset2D::default(),
+                extent,
+            },
         };
 
         rendering_info_builder = rendering_info_builder
@@ -1215,10 +1749,10 @@ pub fn record_raster_pass(
                 command_buffer,
                 0,
                 &[vk::Viewport {
-                    x: 0.0,
-                    y: 0.0,
-                    width: extent.width as f32,
-                    height: extent.height as f32,
+                    x: render_area.offset.x as f32,
+                    y: render_area.offset.y as f32,
+                    width: render_area.extent.width as f32,
+                    height: render_area.extent.height as f32,
                     min_depth: 0.0,
                     max_depth: 1.0,
                 }],
@@ -1230,15 +1764,25 @@ pub fn record_raster_pass(
         }
     }
 
+    //State that is already bound is skipped, so draws sorted by pipeline and mesh only pay for what changes
+    let mut bound_pipeline = vk::Pipeline::null();
+    let mut bound_vertex_buffers: (Vec<vk::Buffer>, Vec<vk::DeviceSize>) = (Vec::new(), Vec::new());
+    let mut bound_index_buffer: Option<(vk::Buffer, vk::DeviceSize, IndexType)> = None;
+    let mut pushed_bindings: Option<Vec<u8>> = None;
+
     //Draw calls
     for draw_call in draw_commands {
         //Bind Pipeline
-        unsafe {
-            device.core.cmd_bind_pipeline(
-                command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                graph_resources.get_raster_pipeline(draw_call.pipeline),
-            );
+        let pipeline = graph_resources.get_raster_pipeline(draw_call.pipeline);
+        if pipeline != bound_pipeline {
+            bound_pipeline = pipeline;
+            unsafe {
+                device.core.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline,
+                );
+            }
         }
 
         //Bind Vertex Buffers
@@ -1255,23 +1799,57 @@ pub fn record_raster_pass(
                 .map(|buffer| buffer.offset as vk::DeviceSize)
                 .collect();
 
+            if (&vertex_buffers, &vertex_offset)
+                != (&bound_vertex_buffers.0, &bound_vertex_buffers.1)
+            {
+                unsafe {
+                    device.core.cmd_bind_vertex_buffers(
+                        command_buffer,
+                        0,
+                        &vertex_buffers,
+                        &vertex_offset,
+                    );
+                }
+                bound_vertex_buffers = (vertex_buffers, vertex_offset);
+            }
+        }
+
+        //Push Resource
+        let push_data_bytes = get_push_data_bytes(graph_resources, &draw_call.resources);
+        if pushed_bindings.as_ref() != Some(&push_data_bytes) {
             unsafe {
-                device.core.cmd_bind_vertex_buffers(
+                device.core.cmd_push_constants(
                     command_buffer,
+                    graph_resources.get_pipeline_layout(),
+                    vk::ShaderStageFlags::ALL,
                     0,
-                    &vertex_buffers,
-                    &vertex_offset,
+                    &push_data_bytes,
                 );
             }
+            pushed_bindings = Some(push_data_bytes);
         }
 
-        //Push Resource
-        record_shader_resources(
-            device,
-            command_buffer,
-            graph_resources,
-            &draw_call.resources,
-        );
+        let mut bind_index_buffer = |index_buffer: &BufferOffset, index_type: &IndexType| {
+            let index_buffer = (
+                graph_resources.buffers[index_buffer.buffer].buffer.handle,
+                index_buffer.offset as vk::DeviceSize,
+                *index_type,
+            );
+            if bound_index_buffer != Some(index_buffer) {
+                bound_index_buffer = Some(index_buffer);
+                unsafe {
+                    device.core.cmd_bind_index_buffer(
+                        command_buffer,
+                        index_buffer.0,
+                        index_buffer.1,
+                        match index_buffer.2 {
+                            IndexType::U16 => vk::IndexType::UINT16,
+                            IndexType::U32 => vk::IndexType::UINT32,
+                        },
+                    );
+                }
+            }
+        };
 
         //Dispatch
         unsafe {
@@ -1293,15 +1871,7 @@ pub fn record_raster_pass(
                     index_buffer,
                     index_type,
                 } => {
-                    device.core.cmd_bind_index_buffer(
-                        command_buffer,
-                        graph_resources.buffers[index_buffer.buffer].buffer.handle,
-                        index_buffer.offset as vk::DeviceSize,
-                        match index_type {
-                            IndexType::U16 => vk::IndexType::UINT16,
-                            IndexType::U32 => vk::IndexType::UINT32,
-                        },
-                    );
+                    bind_index_buffer(index_buffer, index_type);
 
                     device.core.cmd_draw_indexed(
                         command_buffer,
@@ -1330,15 +1900,7 @@ pub fn record_raster_pass(
                     index_buffer,
                     index_type,
                 } => {
-                    device.core.cmd_bind_index_buffer(
-                        command_buffer,
-                        graph_resources.buffers[index_buffer.buffer].buffer.handle,
-                        index_buffer.offset as vk::DeviceSize,
-                        match index_type {
-                            IndexType::U16 => vk::IndexType::UINT16,
-                            IndexType::U32 => vk::IndexType::UINT32,
-                        },
-                    );
+                    bind_index_buffer(index_buffer, index_type);
                     device.core.cmd_draw_indexed_indirect(
                         command_buffer,
                         graph_resources.buffers[buffer.buffer].buffer.handle,
@@ -1363,45 +1925,53 @@ fn record_shader_resources(
     graph_resources: &RenderGraphResources,
     resources: &[ShaderResourceUsage],
 ) {
-    let mut push_bindings: Vec<GpuBindingIndex> = Vec::with_capacity(resources.len());
+    let push_data_bytes = get_push_data_bytes(graph_resources, resources);
+    unsafe {
+        device.core.cmd_push_constants(
+            command_buffer,
+            graph_resources.get_pipeline_layout(),
+            vk::ShaderStageFlags::ALL,
+            0,
+            &push_data_bytes,
+        );
+    }
+}
+
+fn get_push_data_bytes(
+    graph_resources: &RenderGraphResources,
+    resources: &[ShaderResourceUsage],
+) -> Vec<u8> {
+    let mut push_data: Vec<u8> = Vec::with_capacity(resources.len() * 4);
 
     for resource in resources.iter() {
-        push_bindings.push(match resource {
+        push_data.extend_from_slice(&match resource {
             ShaderResourceUsage::StorageBuffer { buffer, .. } => graph_resources.buffers[*buffer]
                 .buffer
                 .storage_binding
-                .expect("Buffer not bound as storage buffer"),
+                .expect("Buffer not bound as storage buffer")
+                .to_bytes(),
             ShaderResourceUsage::StorageImage { image, .. } => graph_resources.images[*image]
                 .image
                 .storage_binding
-                .expect("Image not bound as storage image"),
+                .expect("Image not bound as storage image")
+                .to_bytes(),
             ShaderResourceUsage::SampledImage(image) => graph_resources.images[*image]
                 .image
                 .sampled_binding
-                .expect("Image not bound as sampled image"),
+                .expect("Image not bound as sampled image")
+                .to_bytes(),
             ShaderResourceUsage::Sampler(handle) => graph_resources
                 .get_sampler(*handle)
                 .binding
                 .as_ref()
                 .expect("Sampler is not bound")
-                .index(),
+                .index()
+                .to_bytes(),
+            ShaderResourceUsage::Constant(value) => value.to_ne_bytes(),
         });
     }
 
-    let push_data_bytes: Vec<u8> = push_bindings
-        .drain(..)
-        .flat_map(|binding| binding.to_bytes())
-        .collect();
-
-    unsafe {
-        device.core.cmd_push_constants(
-            command_buffer,
-            graph_resources.get_pipeline_layout(),
-            vk::ShaderStageFlags::ALL,
-            0,
-            &push_data_bytes,
-        );
-    }
+    push_data
 }
 
 pub struct RenderGraphResources<'a> {
@@ -1419,7 +1989,7 @@ impl<'a> RenderGraphResources<'a> {
     }
 
     pub(crate) fn get_compute_pipeline(&self, pipeline: ComputePipelineHandle) -> vk::Pipeline {
-        self.pipelines.compute.get(pipeline.0).unwrap().handle
+        self.pipelines.compute.get(pipeline.key).unwrap().handle
     }
 
     pub fn get_raster_pipeline(&self, pipeline: RasterPipelineHandle) -> vk::Pipeline {
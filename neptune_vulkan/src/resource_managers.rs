@@ -8,13 +8,15 @@ use crate::render_graph::{
 };
 use crate::render_graph_builder::BufferReadCallback;
 use crate::sampler::Sampler;
-use crate::swapchain::AcquiredSwapchainImage;
+use crate::swapchain::{AcquiredSwapchainImage, SwapchainManager};
 use crate::{BufferKey, BufferUsage, ImageHandle, ImageKey, SamplerKey, VulkanError};
 use ash::vk;
 use gpu_allocator::vulkan::Allocation;
 use gpu_allocator::MemoryLocation;
 use log::{error, warn};
 use slotmap::SlotMap;
+use std::any::Any;
+use std::collections::HashSet;
 use std::sync::Arc;
 
 #[derive(Default, Debug, Eq, PartialEq, Copy, Clone)]
@@ -180,6 +182,9 @@ pub struct BufferResource {
 
     //TODO: move to frame context
     pub last_access: BufferResourceAccess,
+
+    /// `ResourceManager::frame_number` when the buffer was added
+    pub created_frame: u64,
 }
 
 #[derive(Clone)]
@@ -194,6 +199,9 @@ pub struct MappedSlice {
     size: usize,
 }
 
+//Points into persistently mapped memory, which stays valid on any thread until the allocation is freed
+unsafe impl Send for MappedSlice {}
+
 impl MappedSlice {
     pub fn new(allocation: &Allocation) -> Option<Self> {
         allocation.mapped_ptr().map(|ptr| Self {
@@ -258,6 +266,8 @@ struct TempBufferRead {
 struct ResourceFrame {
     freed_buffers: Vec<BufferKey>,
     freed_images: Vec<ImageKey>,
+    /// Pipelines and samplers, destroyed when dropped
+    freed_objects: Vec<Box<dyn Any + Send>>,
     transient_buffers: Vec<Buffer>,
     transient_images: Vec<Image>,
 
@@ -275,17 +285,24 @@ pub struct ResourceManager {
     pub(crate) buffers: SlotMap<BufferKey, BufferResource>,
     freed_buffers: Vec<BufferKey>,
 
-    images: SlotMap<ImageKey, ImageResource>,
+    pub(crate) images: SlotMap<ImageKey, ImageResource>,
     freed_images: Vec<ImageKey>,
 
     samplers: SlotMap<SamplerKey, Arc<Sampler>>,
 
+    freed_objects: Vec<Box<dyn Any + Send>>,
+
     frames_in_flight: Vec<ResourceFrame>,
     frame_index: usize,
+    frame_number: u64,
 }
 
 impl ResourceManager {
-    pub fn new(device: Arc<AshDevice>, frame_in_flight_count: u32) -> Self {
+    pub fn new(
+        device: Arc<AshDevice>,
+        frame_in_flight_count: u32,
+        validate_bindings: bool,
+    ) -> Self {
         let descriptor_set = DescriptorSet::new(
             device.clone(),
             DescriptorCount {
@@ -295,6 +312,7 @@ impl ResourceManager {
                 samplers: 128,
                 ..Default::default()
             },
+            validate_bindings,
         )
         .unwrap();
 
@@ -313,16 +331,31 @@ impl ResourceManager {
 
             samplers: SlotMap::with_key(),
 
+            freed_objects: Vec::new(),
+
             descriptor_set,
             frames_in_flight,
             frame_index: 0,
+            frame_number: 0,
         }
     }
 
-    pub fn flush_frame(&mut self) {
-        self.frame_index = (self.frame_index + 1) % self.frames_in_flight.len();
+    /// `frame_index` is the executor's frame in flight, its fence must already be signaled so everything it freed can go
+    pub fn flush_frame(&mut self, frame_index: usize) {
+        self.frame_number += 1;
+        self.frame_index = frame_index % self.frames_in_flight.len();
         let frame = &mut self.frames_in_flight[self.frame_index];
 
+        if let Some(stale_reads) = self.descriptor_set.take_stale_reads() {
+            error!(
+                "Shaders read {} stale sampled image bindings, last was slot {} generation {} but the slot is at generation {} (0 is free)",
+                stale_reads.count,
+                stale_reads.last_index,
+                stale_reads.last_generation,
+                stale_reads.current_generation
+            );
+        }
+
         //Read callbacks
         for buffer_read in frame.buffer_reads.drain(..) {
             let slice = match &buffer_read.source {
@@ -351,8 +384,11 @@ impl ResourceManager {
             }
         }
 
+        frame.freed_objects.clear();
+
         frame.freed_buffers = std::mem::take(&mut self.freed_buffers);
         frame.freed_images = std::mem::take(&mut self.freed_images);
+        frame.freed_objects = std::mem::take(&mut self.freed_objects);
         frame.transient_buffers.clear();
         frame.transient_images.clear();
     }
@@ -381,6 +417,7 @@ impl ResourceManager {
             buffer,
             queue_owner: None,
             last_access: Default::default(),
+            created_frame: self.frame_number,
         }))
     }
 
@@ -393,12 +430,76 @@ impl ResourceManager {
             buffer,
             queue_owner: None,
             last_access: Default::default(),
+            created_frame: self.frame_number,
         })
     }
+    pub fn get_buffer(&self, key: BufferKey) -> Option<&Buffer> {
+        self.buffers.get(key).map(|resource| &resource.buffer)
+    }
     pub fn remove_buffer(&mut self, key: BufferKey) {
         self.freed_buffers.push(key);
     }
 
+    /// Frames flushed since the manager was created
+    pub(crate) fn frame_number(&self) -> u64 {
+        self.frame_number
+    }
+
+    /// Buffers that were removed but are still alive for the frames in flight
+    pub(crate) fn pending_buffer_frees(&self) -> HashSet<BufferKey> {
+        self.freed_buffers
+            .iter()
+            .chain(
+                self.frames_in_flight
+                    .iter()
+                    .flat_map(|frame| frame.freed_buffers.iter()),
+            )
+            .copied()
+            .collect()
+    }
+
+    /// Gives the buffer a new allocation under the same key, returning a key for the old buffer so its contents can be
+    /// copied over. The old buffer is already queued for removal. None if the new allocation wouldn't sit any lower in
+    /// memory than the old one, it's freed right away in that case
+    pub(crate) fn relocate_buffer(
+        &mut self,
+        key: BufferKey,
+    ) -> Result<Option<BufferKey>, VulkanError> {
+        let Some(resource) = self.buffers.get_mut(key) else {
+            return Ok(None);
+        };
+
+        let new_buffer = Buffer::new(
+            self.device.clone(),
+            "Relocated Buffer",
+            resource.buffer.size,
+            resource.buffer.usage,
+            resource.buffer.location,
+        )?;
+
+        let (old_memory, new_memory) = unsafe {
+            (
+                resource.buffer.allocation.memory(),
+                new_buffer.allocation.memory(),
+            )
+        };
+        if old_memory == new_memory
+            && new_buffer.allocation.offset() >= resource.buffer.allocation.offset()
+        {
+            return Ok(None);
+        }
+
+        let old_resource = BufferResource {
+            buffer: std::mem::replace(&mut resource.buffer, new_buffer),
+            queue_owner: None,
+            last_access: std::mem::take(&mut resource.last_access),
+            created_frame: resource.created_frame,
+        };
+        let old_key = self.buffers.insert(old_resource);
+        self.freed_buffers.push(old_key);
+        Ok(Some(old_key))
+    }
+
     //Images
     pub fn add_image(&mut self, mut image: Image) -> ImageKey {
         if image.usage.contains(vk::ImageUsageFlags::STORAGE) {
@@ -430,11 +531,17 @@ impl ResourceManager {
         self.samplers.get(key).cloned()
     }
     pub fn remove_sampler(&mut self, key: SamplerKey) {
-        if self.samplers.remove(key).is_none() {
-            warn!("Tried to remove invalid SamplerKey({:?})", key);
+        match self.samplers.remove(key) {
+            Some(sampler) => self.destroy_later(Box::new(sampler)),
+            None => warn!("Tried to remove invalid SamplerKey({:?})", key),
         }
     }
 
+    /// Keeps a vulkan object alive until every frame that could still be using it has finished
+    pub(crate) fn destroy_later(&mut self, object: Box<dyn Any + Send>) {
+        self.freed_objects.push(object);
+    }
+
     fn allocate_or_resize_staging_buffer(
         device: &Arc<AshDevice>,
         buffer: &mut Option<Buffer>,
@@ -543,18 +650,54 @@ impl ResourceManager {
         }))
     }
 
+    /// Memory allocated for the current frame's transient buffers and images
+    pub fn transient_memory_size(&self) -> u64 {
+        let frame = &self.frames_in_flight[self.frame_index];
+        frame
+            .transient_buffers
+            .iter()
+            .map(|buffer| buffer.allocation.size())
+            .chain(
+                frame
+                    .transient_images
+                    .iter()
+                    .map(|image| image.allocation.size()),
+            )
+            .sum()
+    }
+
     //Graph Functions
     //TODO: take in vector to reuse memory?
     /// Get the buffer resources and update the last usages
     pub fn get_buffer_resources(
         &mut self,
+        swapchain_manager: &SwapchainManager,
+        swapchain_images: &[AcquiredSwapchainImage],
         graph_buffers: &[BufferGraphResource],
+        graph_images: &[ImageGraphResource],
     ) -> Result<Vec<BufferTempResource>, VulkanError> {
+        //Resolved before borrowing the frame, image sizes never depend on buffers
+        let buffer_sizes: Vec<usize> = graph_buffers
+            .iter()
+            .map(|graph_buffer| match &graph_buffer.description {
+                BufferResourceDescription::Persistent(_) => 0,
+                BufferResourceDescription::Transient { size, .. } => size.resolve(|target| {
+                    get_target_image_size(
+                        target,
+                        self,
+                        swapchain_manager,
+                        graph_images,
+                        swapchain_images,
+                    )
+                }),
+            })
+            .collect();
+
         let frame_count = self.frames_in_flight.len();
         let frame = &mut self.frames_in_flight[self.frame_index];
 
         let mut buffer_resources = Vec::with_capacity(graph_buffers.len());
-        for graph_buffer in graph_buffers.iter() {
+        for (graph_buffer, size) in graph_buffers.iter().zip(buffer_sizes) {
             buffer_resources.push(match &graph_buffer.description {
                 BufferResourceDescription::Persistent(key) => {
                     let resource = &mut self.buffers[*key];
@@ -578,14 +721,12 @@ impl ResourceManager {
                     }
                 }
                 BufferResourceDescription::Transient {
-                    size,
-                    usage,
-                    location,
+                    usage, location, ..
                 } => {
                     let mut buffer = Buffer::new(
                         self.device.clone(),
                         "Transient Buffer",
-                        *size as vk::DeviceSize,
+                        size as vk::DeviceSize,
                         usage.to_vk(),
                         *location,
                     )?;
@@ -616,6 +757,7 @@ impl ResourceManager {
     /// Get the image resources and update the last usages
     pub fn get_image_resources(
         &mut self,
+        swapchain_manager: &SwapchainManager,
         swapchain_images: &[AcquiredSwapchainImage],
         graph_images: &[ImageGraphResource],
     ) -> Result<Vec<ImageTempResource>, VulkanError> {
@@ -638,6 +780,7 @@ impl ResourceManager {
                     let image_size = get_transient_image_size(
                         transient_image_description.size.clone(),
                         self,
+                        swapchain_manager,
                         graph_images,
                         swapchain_images,
                     );
@@ -681,40 +824,86 @@ impl ResourceManager {
 fn get_transient_image_size(
     size: TransientImageSize,
     resource_manager: &ResourceManager,
+    swapchain_manager: &SwapchainManager,
     images: &[ImageGraphResource],
     swapchain_images: &[AcquiredSwapchainImage],
 ) -> vk::Extent2D {
+    let get_surface_size = |surface_handle| {
+        swapchain_manager
+            .get_size(surface_handle)
+            .unwrap_or_else(|| {
+                error!(
+                    "Failed to find surface {:?} when looking up a transient image size, using 1x1",
+                    surface_handle
+                );
+                vk::Extent2D {
+                    width: 1,
+                    height: 1,
+                }
+            })
+    };
+
     match size {
         TransientImageSize::Exact(extent) => extent,
-        TransientImageSize::Relative(scale, target) => {
-            let mut extent = match target {
-                ImageHandle::Persistent(image_key) => {
-                    resource_manager.get_image(image_key).as_ref().unwrap().size
-                }
-                ImageHandle::Transient(index) => match &images[index].description {
-                    ImageResourceDescription::Persistent(image_key) => {
-                        error!("Found a Persistent image when looking up a transient image size, this shouldn't happened (but I won't crash)");
-                        resource_manager
-                            .get_image(*image_key)
-                            .as_ref()
-                            .unwrap()
-                            .size
-                    }
-                    ImageResourceDescription::Transient(desc) => get_transient_image_size(
-                        desc.size.clone(),
-                        resource_manager,
-                        images,
-                        swapchain_images,
-                    ),
-                    ImageResourceDescription::Swapchain(swapchain_index) => {
-                        swapchain_images[*swapchain_index].image.size
-                    }
-                },
-            };
-            extent.width = ((extent.width as f32) * scale[0]) as u32;
-            extent.height = ((extent.height as f32) * scale[1]) as u32;
+        TransientImageSize::Relative(scale, target) => TransientImageSize::scale_extent(
+            get_target_image_size(
+                target,
+                resource_manager,
+                swapchain_manager,
+                images,
+                swapchain_images,
+            ),
+            scale,
+        ),
+        TransientImageSize::RelativeSurface(scale, surface_handle) => {
+            TransientImageSize::scale_extent(get_surface_size(surface_handle), scale)
+        }
+        TransientImageSize::Divided(divisor, target) => TransientImageSize::divide_extent(
+            get_target_image_size(
+                target,
+                resource_manager,
+                swapchain_manager,
+                images,
+                swapchain_images,
+            ),
+            divisor,
+        ),
+        TransientImageSize::DividedSurface(divisor, surface_handle) => {
+            TransientImageSize::divide_extent(get_surface_size(surface_handle), divisor)
+        }
+    }
+}
 
-            extent
+fn get_target_image_size(
+    target: ImageHandle,
+    resource_manager: &ResourceManager,
+    swapchain_manager: &SwapchainManager,
+    images: &[ImageGraphResource],
+    swapchain_images: &[AcquiredSwapchainImage],
+) -> vk::Extent2D {
+    match target {
+        ImageHandle::Persistent(image_key) => {
+            resource_manager.get_image(image_key).as_ref().unwrap().size
         }
+        ImageHandle::Transient(index) => match &images[index].description {
+            ImageResourceDescription::Persistent(image_key) => {
+                error!("Found a Persistent image when looking up a transient image size, this shouldn't happened (but I won't crash)");
+                resource_manager
+                    .get_image(*image_key)
+                    .as_ref()
+                    .unwrap()
+                    .size
+            }
+            ImageResourceDescription::Transient(desc) => get_transient_image_size(
+                desc.size.clone(),
+                resource_manager,
+                swapchain_manager,
+                images,
+                swapchain_images,
+            ),
+            ImageResourceDescription::Swapchain(swapchain_index) => {
+                swapchain_images[*swapchain_index].image.size
+            }
+        },
     }
 }
@@ -0,0 +1,114 @@
+//! Minimal SPIR-V parsing, just enough to pull pipeline info out of compiled shaders
+
+use crate::VulkanError;
+
+const MAGIC_NUMBER: u32 = 0x07230203;
+const HEADER_WORDS: usize = 5;
+
+const OP_ENTRY_POINT: u32 = 15;
+const OP_EXECUTION_MODE: u32 = 16;
+const OP_CONSTANT: u32 = 43;
+const OP_EXECUTION_MODE_ID: u32 = 331;
+
+const EXECUTION_MODEL_GL_COMPUTE: u32 = 5;
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+const EXECUTION_MODE_LOCAL_SIZE_ID: u32 = 38;
+
+struct Instruction<'a> {
+    opcode: u32,
+    operands: &'a [u32],
+}
+
+fn instructions(code: &[u32]) -> Result<Vec<Instruction<'_>>, VulkanError> {
+    if code.len() < HEADER_WORDS || code[0] != MAGIC_NUMBER {
+        return Err(VulkanError::ShaderReflection(
+            "Not a SPIR-V module".to_string(),
+        ));
+    }
+
+    let mut instructions = Vec::new();
+    let mut words = &code[HEADER_WORDS..];
+    while let Some(&first_word) = words.first() {
+        let word_count = (first_word >> 16) as usize;
+        if word_count == 0 || word_count > words.len() {
+            return Err(VulkanError::ShaderReflection(
+                "Truncated SPIR-V instruction".to_string(),
+            ));
+        }
+        instructions.push(Instruction {
+            opcode: first_word & 0xFFFF,
+            operands: &words[1..word_count],
+        });
+        words = &words[word_count..];
+    }
+    Ok(instructions)
+}
+
+/// Null terminated utf8 packed 4 bytes per word, little endian
+fn literal_string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take_while(|&byte| byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Returns the local size of the compute entry point `entry`,
+/// fails if the module has no such entry point or it doesn't declare a size
+pub(crate) fn compute_workgroup_size(code: &[u32], entry: &str) -> Result<[u32; 3], VulkanError> {
+    let instructions = instructions(code)?;
+
+    let entry_id = instructions
+        .iter()
+        .filter(|instruction| instruction.opcode == OP_ENTRY_POINT)
+        .find(|instruction| {
+            instruction.operands.len() > 2
+                && instruction.operands[0] == EXECUTION_MODEL_GL_COMPUTE
+                && literal_string(&instruction.operands[2..]) == entry
+        })
+        .map(|instruction| instruction.operands[1])
+        .ok_or_else(|| {
+            VulkanError::ShaderReflection(format!("No compute entry point named {}", entry))
+        })?;
+
+    //LocalSizeId refers to constants, which is how specialized sizes are declared
+    let constant_value = |id: u32| {
+        instructions
+            .iter()
+            .find(|instruction| {
+                instruction.opcode == OP_CONSTANT
+                    && instruction.operands.len() > 2
+                    && instruction.operands[1] == id
+            })
+            .map(|instruction| instruction.operands[2])
+    };
+
+    for instruction in instructions.iter() {
+        let operands = instruction.operands;
+        if operands.len() < 5 || operands[0] != entry_id {
+            continue;
+        }
+
+        match (instruction.opcode, operands[1]) {
+            (OP_EXECUTION_MODE, EXECUTION_MODE_LOCAL_SIZE) => {
+                return Ok([operands[2], operands[3], operands[4]]);
+            }
+            (OP_EXECUTION_MODE_ID, EXECUTION_MODE_LOCAL_SIZE_ID) => {
+                if let (Some(x), Some(y), Some(z)) = (
+                    constant_value(operands[2]),
+                    constant_value(operands[3]),
+                    constant_value(operands[4]),
+                ) {
+                    return Ok([x, y, z]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Err(VulkanError::ShaderReflection(format!(
+        "Compute entry point {} has no local size",
+        entry
+    )))
+}
@@ -64,6 +64,7 @@ impl SwapchainInstance {
                 format: create_info.image_format,
                 size: create_info.image_extent,
                 usage: create_info.image_usage,
+                mip_levels: 1,
                 location: gpu_allocator::MemoryLocation::GpuOnly,
                 storage_binding: None,
                 sampled_binding: None,
@@ -271,6 +272,17 @@ impl SwapchainManager {
             .and_then(|surface| self.swapchains.get_mut(&surface))
     }
 
+    /// Size of the surface's current swapchain images
+    pub fn get_size(&self, surface_handle: SurfaceHandle) -> Option<vk::Extent2D> {
+        self.instance
+            .surface_list
+            .get(surface_handle.0)
+            .and_then(|surface| self.swapchains.get(&surface))
+            .and_then(|swapchain| swapchain.current_swapchain.as_ref())
+            .and_then(|swapchain| swapchain.images.first())
+            .map(|image| image.size)
+    }
+
     pub fn remove(&mut self, surface_handle: SurfaceHandle) {
         let _ = self
             .instance
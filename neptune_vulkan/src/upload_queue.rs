@@ -1,11 +1,13 @@
+use crate::name::NameId;
 use crate::render_graph::{
     BufferBarrier, BufferBarrierSource, BufferGraphResource, BufferIndex, BufferOffset,
     BufferResourceDescription, CommandBuffer, ImageBarrier, ImageBarrierSource, ImageCopyBuffer,
     ImageCopyImage, ImageGraphResource, ImageIndex, ImageResourceDescription, Queue, RenderPass,
     RenderPassCommand, RenderPassSet, Transfer,
 };
-use crate::resource_managers::{BufferResourceAccess, ImageResourceAccess};
-use crate::{BufferHandle, ImageHandle};
+use crate::resource_managers::{BufferResourceAccess, ImageResourceAccess, ResourceManager};
+use crate::{BufferHandle, BufferKey, ImageHandle, ImageKey};
+use std::collections::HashSet;
 
 //TODO: switch to new pass system
 pub(crate) struct UploadPass {
@@ -14,24 +16,152 @@ pub(crate) struct UploadPass {
     pub(crate) command_buffer: CommandBuffer,
 }
 
+#[derive(Default)]
+pub(crate) struct UploadPasses {
+    /// Only touches resources no graph has used yet, so it can run on the transfer queue
+    pub(crate) new_resources: Option<UploadPass>,
+    /// Updates to resources the graph already uses, recorded on the graphics queue after `new_resources`
+    pub(crate) graphics: Option<UploadPass>,
+}
+
+/// Transfers keep their handles until the passes are built, since resource indices depend on which pass a transfer ends up in
+enum QueuedTransfer {
+    BufferToBuffer {
+        src: crate::render_graph_builder::BufferOffset,
+        dst: crate::render_graph_builder::BufferOffset,
+        copy_size: u64,
+    },
+    BufferToImage {
+        src: crate::render_graph_builder::ImageCopyBuffer,
+        dst: crate::render_graph_builder::ImageCopyImage,
+        copy_size: [u32; 3],
+    },
+    GenerateMips {
+        image: ImageHandle,
+    },
+}
+
+impl QueuedTransfer {
+    fn resources(&self) -> (Vec<BufferKey>, Vec<ImageKey>) {
+        match self {
+            QueuedTransfer::BufferToBuffer { src, dst, .. } => {
+                (vec![src.buffer.as_key(), dst.buffer.as_key()], vec![])
+            }
+            QueuedTransfer::BufferToImage { src, dst, .. } => {
+                (vec![src.buffer.as_key()], vec![dst.image.as_key()])
+            }
+            QueuedTransfer::GenerateMips { image } => (vec![], vec![image.as_key()]),
+        }
+    }
+}
+
 #[derive(Default)]
 pub(crate) struct UploadQueue {
+    transfers: Vec<QueuedTransfer>,
+}
+
+impl UploadQueue {
+    pub(crate) fn add_buffer_upload(
+        &mut self,
+        src: crate::render_graph_builder::BufferOffset,
+        dst: crate::render_graph_builder::BufferOffset,
+        copy_size: usize,
+    ) {
+        self.transfers.push(QueuedTransfer::BufferToBuffer {
+            src,
+            dst,
+            copy_size: copy_size as u64,
+        });
+    }
+
+    pub(crate) fn add_image_upload(
+        &mut self,
+        src: crate::render_graph_builder::ImageCopyBuffer,
+        dst: crate::render_graph_builder::ImageCopyImage,
+        copy_size: [u32; 3],
+    ) {
+        self.transfers.push(QueuedTransfer::BufferToImage {
+            src,
+            dst,
+            copy_size,
+        });
+    }
+
+    pub(crate) fn add_generate_mips(&mut self, image: ImageHandle) {
+        self.transfers.push(QueuedTransfer::GenerateMips { image });
+    }
+
+    /// No transfers have been queued since the last pass
+    pub(crate) fn is_empty(&self) -> bool {
+        self.transfers.is_empty()
+    }
+
+    /// Splits the queued transfers so one update to a resource in use doesn't pull every new resource's upload onto graphics.
+    /// With `split_new_resources` false everything goes in the graphics pass
+    pub(crate) fn get_passes(
+        &mut self,
+        resource_manager: &ResourceManager,
+        split_new_resources: bool,
+    ) -> UploadPasses {
+        let mut new_resources = UploadPassBuilder::default();
+        let mut graphics = UploadPassBuilder::default();
+
+        //Once a resource has a transfer on graphics every later one touching it has to follow, or they'd run out of order
+        let mut graphics_buffers: HashSet<BufferKey> = HashSet::new();
+        let mut graphics_images: HashSet<ImageKey> = HashSet::new();
+
+        for transfer in self.transfers.drain(..) {
+            let (buffers, images) = transfer.resources();
+            //Blits aren't valid on the transfer queue, so mip generation always runs on graphics
+            let new_resources_only = split_new_resources
+                && !matches!(transfer, QueuedTransfer::GenerateMips { .. })
+                && buffers.iter().all(|key| {
+                    !graphics_buffers.contains(key)
+                        && resource_manager
+                            .buffers
+                            .get(*key)
+                            .map(|buffer| buffer.last_access)
+                            == Some(BufferResourceAccess::None)
+                })
+                && images.iter().all(|key| {
+                    !graphics_images.contains(key)
+                        && resource_manager
+                            .images
+                            .get(*key)
+                            .map(|image| image.last_access)
+                            == Some(ImageResourceAccess::None)
+                });
+
+            if new_resources_only {
+                new_resources.add_transfer(&transfer);
+            } else {
+                graphics_buffers.extend(buffers);
+                graphics_images.extend(images);
+                graphics.add_transfer(&transfer);
+            }
+        }
+
+        UploadPasses {
+            new_resources: new_resources.build("Device Upload Pass"),
+            graphics: graphics.build("Device Update Pass"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct UploadPassBuilder {
     buffer_resources: Vec<BufferGraphResource>,
     image_resources: Vec<ImageGraphResource>,
-
-    buffer_access: Vec<(BufferIndex, BufferResourceAccess)>,
-    image_access: Vec<(ImageIndex, ImageResourceAccess)>,
     transfers: Vec<Transfer>,
 }
 
-impl UploadQueue {
+impl UploadPassBuilder {
     fn add_buffer(&mut self, buffer: BufferHandle, access: BufferResourceAccess) -> BufferIndex {
         let index = self.buffer_resources.len();
         self.buffer_resources.push(BufferGraphResource {
             description: BufferResourceDescription::Persistent(buffer.as_key()),
             last_access: access,
         });
-        self.buffer_access.push((index, access));
         index
     }
 
@@ -42,105 +172,98 @@ impl UploadQueue {
             first_access: None,
             last_access: Some(access),
         });
-        self.image_access.push((index, access));
         index
     }
 
-    pub(crate) fn add_buffer_upload(
-        &mut self,
-        src: crate::render_graph_builder::BufferOffset,
-        dst: crate::render_graph_builder::BufferOffset,
-        copy_size: usize,
-    ) {
-        let src = BufferOffset {
-            buffer: self.add_buffer(src.buffer, BufferResourceAccess::TransferRead),
-            offset: src.offset as u64,
-        };
-
-        let dst = BufferOffset {
-            buffer: self.add_buffer(dst.buffer, BufferResourceAccess::TransferWrite),
-            offset: dst.offset as u64,
+    fn add_transfer(&mut self, transfer: &QueuedTransfer) {
+        let transfer = match transfer {
+            QueuedTransfer::BufferToBuffer {
+                src,
+                dst,
+                copy_size,
+            } => Transfer::BufferToBuffer {
+                src: BufferOffset {
+                    buffer: self.add_buffer(src.buffer, BufferResourceAccess::TransferRead),
+                    offset: src.offset as u64,
+                },
+                dst: BufferOffset {
+                    buffer: self.add_buffer(dst.buffer, BufferResourceAccess::TransferWrite),
+                    offset: dst.offset as u64,
+                },
+                copy_size: *copy_size,
+            },
+            QueuedTransfer::BufferToImage {
+                src,
+                dst,
+                copy_size,
+            } => Transfer::BufferToImage {
+                src: ImageCopyBuffer {
+                    buffer: self.add_buffer(src.buffer, BufferResourceAccess::TransferRead),
+                    offset: src.offset,
+                    row_length: src.row_length,
+                    row_height: src.row_height,
+                },
+                dst: ImageCopyImage {
+                    image: self.add_image(dst.image, ImageResourceAccess::TransferWrite),
+                    offset: dst.offset,
+                    mip_level: dst.mip_level,
+                },
+                copy_size: *copy_size,
+            },
+            QueuedTransfer::GenerateMips { image } => Transfer::GenerateMips {
+                image: self.add_image(*image, ImageResourceAccess::TransferWrite),
+            },
         };
-
-        self.transfers.push(Transfer::BufferToBuffer {
-            src,
-            dst,
-            copy_size: copy_size as u64,
-        })
+        self.transfers.push(transfer);
     }
 
-    pub(crate) fn add_image_upload(
-        &mut self,
-        src: crate::render_graph_builder::ImageCopyBuffer,
-        dst: crate::render_graph_builder::ImageCopyImage,
-        copy_size: [u32; 2],
-    ) {
-        let src = ImageCopyBuffer {
-            buffer: self.add_buffer(src.buffer, BufferResourceAccess::TransferRead),
-            offset: src.offset,
-            row_length: src.row_length,
-            row_height: src.row_height,
-        };
+    fn build(self, name: &str) -> Option<UploadPass> {
+        if self.transfers.is_empty() {
+            return None;
+        }
 
-        let dst = ImageCopyImage {
-            image: self.add_image(dst.image, ImageResourceAccess::TransferWrite),
-            offset: dst.offset,
-        };
+        let buffer_barriers = self
+            .buffer_resources
+            .iter()
+            .enumerate()
+            .map(|(index, _buffer)| BufferBarrier {
+                index,
+                src: BufferBarrierSource::FirstUsage,
+                dst: BufferResourceAccess::TransferWrite,
+            })
+            .collect();
 
-        self.transfers.push(Transfer::BufferToImage {
-            src,
-            dst,
-            copy_size,
-        });
-    }
+        let image_barriers = self
+            .image_resources
+            .iter()
+            .enumerate()
+            .map(|(index, _image)| ImageBarrier {
+                index,
+                src: ImageBarrierSource::FirstUsage,
+                dst: ImageResourceAccess::TransferWrite,
+            })
+            .collect();
 
-    pub(crate) fn get_pass(&mut self) -> Option<UploadPass> {
-        if self.transfers.is_empty() {
-            None
-        } else {
-            let buffer_barriers = self
-                .buffer_resources
-                .iter()
-                .enumerate()
-                .map(|(index, _buffer)| BufferBarrier {
-                    index,
-                    src: BufferBarrierSource::FirstUsage,
-                    dst: BufferResourceAccess::TransferWrite,
-                })
-                .collect();
-
-            let image_barriers = self
-                .image_resources
-                .iter()
-                .enumerate()
-                .map(|(index, _image)| ImageBarrier {
-                    index,
-                    src: ImageBarrierSource::FirstUsage,
-                    dst: ImageResourceAccess::TransferWrite,
-                })
-                .collect();
-
-            Some(UploadPass {
-                buffer_resources: std::mem::take(&mut self.buffer_resources),
-                image_resources: std::mem::take(&mut self.image_resources),
-                command_buffer: CommandBuffer {
-                    queue: Queue::Graphics,
-                    command_buffer_wait_dependencies: Vec::new(),
-                    render_pass_sets: vec![RenderPassSet {
-                        memory_barriers: vec![],
-                        buffer_barriers,
-                        image_barriers,
-                        render_passes: vec![RenderPass {
-                            label_name: "Device Upload Pass".to_string(),
-                            label_color: [0.5, 0.0, 0.5, 1.0],
-                            command: Some(RenderPassCommand::Transfer {
-                                transfers: std::mem::take(&mut self.transfers),
-                            }),
-                        }],
+        Some(UploadPass {
+            buffer_resources: self.buffer_resources,
+            image_resources: self.image_resources,
+            command_buffer: CommandBuffer {
+                queue: Queue::Graphics,
+                command_buffer_wait_dependencies: Vec::new(),
+                render_pass_sets: vec![RenderPassSet {
+                    memory_barriers: vec![],
+                    buffer_barriers,
+                    image_barriers,
+                    render_passes: vec![RenderPass {
+                        label_name: NameId::new(name),
+                        label_color: [0.5, 0.0, 0.5, 1.0],
+                        command: Some(RenderPassCommand::Transfer {
+                            transfers: self.transfers,
+                        }),
                     }],
-                    command_buffer_signal_dependencies: Vec::new(),
-                },
-            })
-        }
+                }],
+                command_buffer_signal_dependencies: Vec::new(),
+            },
+        })
     }
 }